@@ -0,0 +1,100 @@
+//! GET throughput for small objects: buffered streaming vs. memory-mapped
+//! reads (`StorageOptions::mmap_read_threshold`).
+//!
+//! Run with `cargo bench --bench small_reads`.
+
+use axum::{
+    Router,
+    body::{Body, to_bytes},
+    http::{Method, Request, StatusCode},
+};
+use criterion::{BenchmarkId, Criterion, Throughput, criterion_group, criterion_main};
+use object_store::{
+    routes::routes::routes,
+    services::storage_service::{StorageOptions, StorageService},
+};
+use sqlx::sqlite::SqlitePoolOptions;
+use std::sync::Arc;
+use tempfile::TempDir;
+use tokio::runtime::Runtime;
+use tower::ServiceExt;
+
+const OBJECTS: usize = 64;
+const SIZES: [usize; 3] = [1024, 16 * 1024, 128 * 1024];
+
+/// A migrated instance holding `OBJECTS` objects of `size` bytes.
+async fn setup(size: usize, mmap: bool) -> (Router, TempDir) {
+    let dir = TempDir::new().expect("temp dir");
+    let db = SqlitePoolOptions::new()
+        .max_connections(1)
+        .connect("sqlite::memory:")
+        .await
+        .expect("sqlite");
+    sqlx::migrate!("./migrations")
+        .run(&db)
+        .await
+        .expect("migrations");
+    let service = StorageService::new(Arc::new(db), dir.path().join("objects")).with_options(
+        StorageOptions {
+            mmap_read_threshold: mmap.then_some(1024 * 1024),
+            ..Default::default()
+        },
+    );
+    let router = routes().with_state(service);
+
+    let call = |method: Method, uri: String, body: Body| {
+        let request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("content-type", "application/json")
+            .body(body)
+            .expect("request");
+        router.clone().oneshot(request)
+    };
+    let resp = call(Method::PUT, "/bench".into(), Body::from("null"))
+        .await
+        .unwrap();
+    assert_eq!(resp.status(), StatusCode::OK);
+    let payload = vec![7u8; size];
+    for i in 0..OBJECTS {
+        let resp = call(
+            Method::PUT,
+            format!("/bench/obj-{}", i),
+            Body::from(payload.clone()),
+        )
+        .await
+        .unwrap();
+        assert_eq!(resp.status(), StatusCode::OK);
+    }
+    (router, dir)
+}
+
+fn small_reads(c: &mut Criterion) {
+    let runtime = Runtime::new().expect("runtime");
+    let mut group = c.benchmark_group("get_object");
+    for size in SIZES {
+        group.throughput(Throughput::Bytes(size as u64));
+        for (label, mmap) in [("buffered", false), ("mmap", true)] {
+            let (router, _dir) = runtime.block_on(setup(size, mmap));
+            let mut next = 0usize;
+            group.bench_with_input(BenchmarkId::new(label, size), &size, |b, _| {
+                b.to_async(&runtime).iter(|| {
+                    next = (next + 1) % OBJECTS;
+                    let request = Request::builder()
+                        .uri(format!("/bench/obj-{}", next))
+                        .body(Body::empty())
+                        .expect("request");
+                    let router = router.clone();
+                    async move {
+                        let resp = router.oneshot(request).await.unwrap();
+                        to_bytes(resp.into_body(), usize::MAX).await.unwrap()
+                    }
+                })
+            });
+        }
+    }
+    group.finish();
+}
+
+criterion_group!(benches, small_reads);
+criterion_main!(benches);
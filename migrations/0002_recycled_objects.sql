@@ -0,0 +1,19 @@
+-- 0002_recycled_objects.sql
+-- Payloads displaced by an overwrite are parked in a recycle area for a grace
+-- period so an accidental overwrite can be recovered.
+CREATE TABLE IF NOT EXISTS recycled_objects (
+  id TEXT PRIMARY KEY,
+  bucket_id TEXT NOT NULL REFERENCES buckets(id) ON DELETE CASCADE,
+  key TEXT NOT NULL,
+  content_type TEXT,
+  size_bytes INTEGER NOT NULL,
+  etag TEXT,
+  last_modified TEXT NOT NULL,
+  recycled_at TEXT NOT NULL,
+  -- ISO8601 timestamp after which the payload may be purged
+  expires_at TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_recycled_objects_bucket_key ON recycled_objects(bucket_id, key);
+
+CREATE INDEX IF NOT EXISTS idx_recycled_objects_expires ON recycled_objects(expires_at);
@@ -0,0 +1,6 @@
+-- 0003_content_encoding.sql
+-- Persist the Content-Encoding an object was uploaded with so it can be
+-- replayed on GET/HEAD.
+ALTER TABLE objects ADD COLUMN content_encoding TEXT;
+
+ALTER TABLE recycled_objects ADD COLUMN content_encoding TEXT;
@@ -0,0 +1,3 @@
+-- 0004_bucket_read_only.sql
+-- Buckets flagged read-only reject writes and deletes.
+ALTER TABLE buckets ADD COLUMN read_only INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,5 @@
+-- 0005_object_class_size_index.sql
+-- Serves listing filters on storage class and size without a bucket scan.
+CREATE INDEX IF NOT EXISTS idx_objects_bucket_class_size
+  ON objects(bucket_id, storage_class, size_bytes)
+  WHERE is_deleted = 0;
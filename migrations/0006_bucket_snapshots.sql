@@ -0,0 +1,40 @@
+-- 0006_bucket_snapshots.sql
+-- Point-in-time bucket snapshots. Each snapshot records the live objects of
+-- its bucket; payloads are hard-linked under `.snapshots/{bucket}/{id}/`.
+CREATE TABLE IF NOT EXISTS bucket_snapshots (
+  id TEXT PRIMARY KEY,
+  bucket_id TEXT NOT NULL REFERENCES buckets(id) ON DELETE CASCADE,
+  created_at TEXT NOT NULL,
+  -- 'scheduled', 'manual' or 'pre-restore'; retention only prunes 'scheduled'
+  trigger TEXT NOT NULL,
+  object_count INTEGER NOT NULL,
+  total_bytes INTEGER NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_bucket_snapshots_bucket_created
+  ON bucket_snapshots(bucket_id, created_at);
+
+CREATE TABLE IF NOT EXISTS snapshot_objects (
+  snapshot_id TEXT NOT NULL REFERENCES bucket_snapshots(id) ON DELETE CASCADE,
+  key TEXT NOT NULL,
+  -- file name of the payload inside the snapshot directory
+  payload_id TEXT NOT NULL,
+  content_type TEXT,
+  content_encoding TEXT,
+  size_bytes INTEGER NOT NULL,
+  etag TEXT,
+  storage_class TEXT NOT NULL,
+  last_modified TEXT NOT NULL,
+  PRIMARY KEY (snapshot_id, key)
+);
+
+-- One schedule per bucket.
+CREATE TABLE IF NOT EXISTS snapshot_policies (
+  bucket_id TEXT PRIMARY KEY REFERENCES buckets(id) ON DELETE CASCADE,
+  -- cron expression (5 or 6 fields) or @hourly/@daily/@weekly/...
+  schedule TEXT NOT NULL,
+  keep_daily INTEGER NOT NULL,
+  keep_weekly INTEGER NOT NULL,
+  created_at TEXT NOT NULL,
+  last_run_at TEXT
+);
@@ -0,0 +1,21 @@
+-- 0007_jobs.sql
+-- Background jobs (e.g. deleting every key under a prefix). Jobs are picked
+-- up by the job runner in creation order; `running` jobs found at startup are
+-- requeued, so job steps must be idempotent.
+CREATE TABLE IF NOT EXISTS jobs (
+  id TEXT PRIMARY KEY,
+  -- e.g. 'delete-prefix'
+  kind TEXT NOT NULL,
+  bucket_id TEXT NOT NULL REFERENCES buckets(id) ON DELETE CASCADE,
+  prefix TEXT,
+  -- 'queued', 'running', 'succeeded' or 'failed'
+  status TEXT NOT NULL,
+  -- items handled so far (keys deleted for 'delete-prefix')
+  processed INTEGER NOT NULL DEFAULT 0,
+  error TEXT,
+  created_at TEXT NOT NULL,
+  started_at TEXT,
+  finished_at TEXT
+);
+
+CREATE INDEX IF NOT EXISTS idx_jobs_status_created ON jobs(status, created_at);
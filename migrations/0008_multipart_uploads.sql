@@ -0,0 +1,23 @@
+-- 0008_multipart_uploads.sql
+-- In-progress S3 multipart uploads. Part payloads live under
+-- `.multipart/{bucket}/{upload_id}/{part_number}` until the upload is
+-- completed (concatenated into the object) or aborted.
+CREATE TABLE IF NOT EXISTS multipart_uploads (
+  id TEXT PRIMARY KEY,
+  bucket_id TEXT NOT NULL REFERENCES buckets(id) ON DELETE CASCADE,
+  key TEXT NOT NULL,
+  content_type TEXT,
+  content_encoding TEXT,
+  initiated_at TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS idx_multipart_uploads_bucket_key ON multipart_uploads(bucket_id, key);
+
+CREATE TABLE IF NOT EXISTS multipart_parts (
+  upload_id TEXT NOT NULL REFERENCES multipart_uploads(id) ON DELETE CASCADE,
+  part_number INTEGER NOT NULL,
+  size_bytes INTEGER NOT NULL,
+  etag TEXT NOT NULL,
+  last_modified TEXT NOT NULL,
+  PRIMARY KEY (upload_id, part_number)
+);
@@ -0,0 +1,8 @@
+-- 0009_checksum_sha256.sql
+-- Server-computed SHA-256 of the stored payload (base64, as S3 reports it),
+-- recorded when checksum computation is enabled.
+ALTER TABLE objects ADD COLUMN checksum_sha256 TEXT;
+
+ALTER TABLE recycled_objects ADD COLUMN checksum_sha256 TEXT;
+
+ALTER TABLE snapshot_objects ADD COLUMN checksum_sha256 TEXT;
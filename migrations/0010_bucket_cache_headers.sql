@@ -0,0 +1,6 @@
+-- 0010_bucket_cache_headers.sql
+-- Per-bucket caching defaults emitted on GET/HEAD: a literal Cache-Control
+-- value and an Expires offset (seconds after the response).
+ALTER TABLE buckets ADD COLUMN cache_control TEXT;
+
+ALTER TABLE buckets ADD COLUMN expires_secs INTEGER;
@@ -0,0 +1,23 @@
+-- 0011_object_versions.sql
+-- Noncurrent object versions of versioned buckets. The current version (or
+-- current delete marker) of a key stays in `objects`; older versions and
+-- markers move here, with payloads under `.versions/{bucket}/{id}`.
+CREATE TABLE IF NOT EXISTS object_versions (
+  id TEXT PRIMARY KEY,
+  bucket_id TEXT NOT NULL REFERENCES buckets(id) ON DELETE CASCADE,
+  key TEXT NOT NULL,
+  -- `null` for the version written while versioning was off
+  version_id TEXT NOT NULL,
+  content_type TEXT,
+  content_encoding TEXT,
+  size_bytes INTEGER NOT NULL,
+  etag TEXT,
+  checksum_sha256 TEXT,
+  storage_class TEXT NOT NULL,
+  last_modified TEXT NOT NULL,
+  is_delete_marker INTEGER NOT NULL DEFAULT 0,
+  UNIQUE(bucket_id, key, version_id)
+);
+
+CREATE INDEX IF NOT EXISTS idx_object_versions_bucket_key
+  ON object_versions(bucket_id, key, last_modified);
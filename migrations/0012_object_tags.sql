@@ -0,0 +1,18 @@
+-- 0012_object_tags.sql
+-- Tag set of the current version of each key (S3 object tagging). Written
+-- with the object on upload (`x-amz-tagging`) and replaced by `?tagging`;
+-- every new payload for a key replaces its tags.
+CREATE TABLE IF NOT EXISTS object_tags (
+  bucket_id TEXT NOT NULL REFERENCES buckets(id) ON DELETE CASCADE,
+  key TEXT NOT NULL,
+  tag_key TEXT NOT NULL,
+  tag_value TEXT NOT NULL,
+  PRIMARY KEY (bucket_id, key, tag_key)
+);
+
+CREATE INDEX IF NOT EXISTS idx_object_tags_tag
+  ON object_tags(bucket_id, tag_key, tag_value);
+
+-- `x-amz-tagging` given when a multipart upload is initiated, applied when
+-- it completes.
+ALTER TABLE multipart_uploads ADD COLUMN tagging TEXT;
@@ -0,0 +1,15 @@
+-- 0013_object_metadata.sql
+-- User-defined metadata (`x-amz-meta-*`) of the current version of each
+-- key. Written with the object and replaced by every new payload, like
+-- `object_tags`; CopyObject copies or replaces it per `MetadataDirective`.
+CREATE TABLE IF NOT EXISTS object_metadata (
+  bucket_id TEXT NOT NULL REFERENCES buckets(id) ON DELETE CASCADE,
+  key TEXT NOT NULL,
+  name TEXT NOT NULL,
+  value TEXT NOT NULL,
+  PRIMARY KEY (bucket_id, key, name)
+);
+
+-- `x-amz-meta-*` given when a multipart upload is initiated (JSON array of
+-- `{"name", "value"}`), applied when it completes.
+ALTER TABLE multipart_uploads ADD COLUMN user_metadata TEXT;
@@ -0,0 +1,18 @@
+-- 0014_object_acl.sql
+-- Canned ACL of each object (`x-amz-acl`, or the bucket default), kept with
+-- every copy of the object's attributes. NULL means `private`.
+ALTER TABLE objects ADD COLUMN acl TEXT;
+
+ALTER TABLE recycled_objects ADD COLUMN acl TEXT;
+
+ALTER TABLE snapshot_objects ADD COLUMN acl TEXT;
+
+ALTER TABLE object_versions ADD COLUMN acl TEXT;
+
+ALTER TABLE multipart_uploads ADD COLUMN acl TEXT;
+
+-- Per-bucket upload ACL policy: the canned ACL applied when an upload sends
+-- none, and whether uploads must grant the bucket owner full control.
+ALTER TABLE buckets ADD COLUMN default_acl TEXT;
+
+ALTER TABLE buckets ADD COLUMN enforce_bucket_owner_full_control INTEGER NOT NULL DEFAULT 0;
@@ -0,0 +1,23 @@
+-- 0015_object_content_headers.sql
+-- System headers sent at upload and replayed on GET/HEAD, kept with every
+-- copy of the object's attributes. An object's Cache-Control / Expires take
+-- precedence over the bucket defaults.
+ALTER TABLE objects ADD COLUMN cache_control TEXT;
+ALTER TABLE objects ADD COLUMN content_disposition TEXT;
+ALTER TABLE objects ADD COLUMN expires TEXT;
+
+ALTER TABLE recycled_objects ADD COLUMN cache_control TEXT;
+ALTER TABLE recycled_objects ADD COLUMN content_disposition TEXT;
+ALTER TABLE recycled_objects ADD COLUMN expires TEXT;
+
+ALTER TABLE snapshot_objects ADD COLUMN cache_control TEXT;
+ALTER TABLE snapshot_objects ADD COLUMN content_disposition TEXT;
+ALTER TABLE snapshot_objects ADD COLUMN expires TEXT;
+
+ALTER TABLE object_versions ADD COLUMN cache_control TEXT;
+ALTER TABLE object_versions ADD COLUMN content_disposition TEXT;
+ALTER TABLE object_versions ADD COLUMN expires TEXT;
+
+ALTER TABLE multipart_uploads ADD COLUMN cache_control TEXT;
+ALTER TABLE multipart_uploads ADD COLUMN content_disposition TEXT;
+ALTER TABLE multipart_uploads ADD COLUMN expires TEXT;
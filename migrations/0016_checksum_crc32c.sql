@@ -0,0 +1,11 @@
+-- 0016_checksum_crc32c.sql
+-- CRC32C (base64, as S3 reports it) of the stored payload, recorded when the
+-- client sent `x-amz-checksum-crc32c` and it matched. Kept with every copy of
+-- the object's attributes.
+ALTER TABLE objects ADD COLUMN checksum_crc32c TEXT;
+
+ALTER TABLE recycled_objects ADD COLUMN checksum_crc32c TEXT;
+
+ALTER TABLE snapshot_objects ADD COLUMN checksum_crc32c TEXT;
+
+ALTER TABLE object_versions ADD COLUMN checksum_crc32c TEXT;
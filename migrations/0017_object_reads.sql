@@ -0,0 +1,16 @@
+-- 0017_object_reads.sql
+-- Reads of each key grouped by how old the object was when read, recorded
+-- when access analytics is on and summarised by storage class analysis jobs.
+CREATE TABLE IF NOT EXISTS object_reads (
+  bucket_id TEXT NOT NULL REFERENCES buckets(id) ON DELETE CASCADE,
+  key TEXT NOT NULL,
+  -- age group label, e.g. '30-45' (days) or '365+'
+  age_group TEXT NOT NULL,
+  reads INTEGER NOT NULL DEFAULT 0,
+  bytes_read INTEGER NOT NULL DEFAULT 0,
+  last_read_at TEXT NOT NULL,
+  PRIMARY KEY (bucket_id, key, age_group)
+);
+
+-- Bucket a job writes its output to (e.g. the analysis report).
+ALTER TABLE jobs ADD COLUMN destination TEXT;
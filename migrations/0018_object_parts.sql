@@ -0,0 +1,12 @@
+-- 0018_object_parts.sql
+-- Parts the current version of a key was assembled from by
+-- CompleteMultipartUpload, reported by GetObjectAttributes. Every new payload
+-- for a key replaces them; single-part uploads have none.
+CREATE TABLE IF NOT EXISTS object_parts (
+  bucket_id TEXT NOT NULL REFERENCES buckets(id) ON DELETE CASCADE,
+  key TEXT NOT NULL,
+  part_number INTEGER NOT NULL,
+  size_bytes INTEGER NOT NULL,
+  etag TEXT NOT NULL,
+  PRIMARY KEY (bucket_id, key, part_number)
+);
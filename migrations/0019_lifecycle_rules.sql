@@ -0,0 +1,18 @@
+-- 0019_lifecycle_rules.sql
+-- Bucket lifecycle configuration (S3 `?lifecycle`): one row per rule, in the
+-- order the configuration listed them. A PUT replaces every rule of the
+-- bucket; the lifecycle worker applies enabled rules periodically.
+CREATE TABLE IF NOT EXISTS lifecycle_rules (
+  bucket_id TEXT NOT NULL REFERENCES buckets(id) ON DELETE CASCADE,
+  rule_id TEXT NOT NULL,
+  position INTEGER NOT NULL,
+  enabled INTEGER NOT NULL,
+  prefix TEXT,
+  -- tag filter as a JSON array of {"key", "value"}; every tag must match
+  tags TEXT,
+  -- expire current objects this many days after their last modification
+  expiration_days INTEGER,
+  -- abort multipart uploads this many days after initiation
+  abort_incomplete_days INTEGER,
+  PRIMARY KEY (bucket_id, rule_id)
+);
@@ -0,0 +1,6 @@
+-- 0020_object_deleted_at.sql
+-- When an object row was soft-deleted. Rows deleted outside a versioned
+-- history (`is_deleted = 1` with no `version_id`) are purged for good once
+-- they are older than the configured retention; rows deleted before this
+-- column existed fall back to `last_modified`.
+ALTER TABLE objects ADD COLUMN deleted_at TEXT;
@@ -0,0 +1,23 @@
+-- 0021_object_lock.sql
+-- Object Lock (S3 `?object-lock`). A row in `bucket_object_lock` means the
+-- bucket has Object Lock enabled, which cannot be undone; its default
+-- retention (mode plus days or years) is applied to new versions that do not
+-- name their own.
+CREATE TABLE IF NOT EXISTS bucket_object_lock (
+  bucket_id TEXT PRIMARY KEY REFERENCES buckets(id) ON DELETE CASCADE,
+  -- `GOVERNANCE` or `COMPLIANCE`; NULL when there is no default retention
+  default_mode TEXT,
+  default_days INTEGER,
+  default_years INTEGER
+);
+
+-- Retention of one object version. Versions keep their id when they are
+-- archived or promoted, so the row follows the version wherever it lives.
+CREATE TABLE IF NOT EXISTS object_retention (
+  bucket_id TEXT NOT NULL REFERENCES buckets(id) ON DELETE CASCADE,
+  key TEXT NOT NULL,
+  version_id TEXT NOT NULL,
+  mode TEXT NOT NULL,
+  retain_until TEXT NOT NULL,
+  PRIMARY KEY (bucket_id, key, version_id)
+);
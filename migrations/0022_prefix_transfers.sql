@@ -0,0 +1,12 @@
+-- 0022_prefix_transfers.sql
+-- Bytes uploaded and downloaded per key prefix (the first
+-- OBJECT_STORE_USAGE_PREFIX_DEPTH `/`-separated segments of the key),
+-- recorded when prefix usage tracking is on and reported next to the bytes
+-- stored under each prefix for chargeback.
+CREATE TABLE IF NOT EXISTS prefix_transfers (
+  bucket_id TEXT NOT NULL REFERENCES buckets(id) ON DELETE CASCADE,
+  prefix TEXT NOT NULL,
+  bytes_in INTEGER NOT NULL DEFAULT 0,
+  bytes_out INTEGER NOT NULL DEFAULT 0,
+  PRIMARY KEY (bucket_id, prefix)
+);
@@ -0,0 +1,12 @@
+-- 0023_content_blobs.sql
+-- Payload blobs shared by deduplicated keys, stored once under
+-- `.cas/{aa}/{bb}/{sha256}` and hard-linked to every payload path holding
+-- that content. `refcount` is the number of those links, refreshed on every
+-- upload of the content and by the blob collector, which removes blobs
+-- whose count reached zero.
+CREATE TABLE IF NOT EXISTS content_blobs (
+  sha256 TEXT PRIMARY KEY,
+  size_bytes INTEGER NOT NULL,
+  refcount INTEGER NOT NULL DEFAULT 0,
+  created_at TEXT NOT NULL
+);
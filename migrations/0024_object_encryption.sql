@@ -0,0 +1,14 @@
+-- 0024_object_encryption.sql
+-- Payloads encrypted with a customer-provided key (SSE-C). The key itself is
+-- never stored: `key_md5` (base64) identifies it so reads can be refused
+-- when the caller sends another key, and `iv` (base64) is the AES-CTR
+-- initial counter block. Keyed by version like `object_retention`, so the
+-- row follows a version when it is archived or promoted.
+CREATE TABLE IF NOT EXISTS object_encryption (
+  bucket_id TEXT NOT NULL REFERENCES buckets(id) ON DELETE CASCADE,
+  key TEXT NOT NULL,
+  version_id TEXT NOT NULL,
+  key_md5 TEXT NOT NULL,
+  iv TEXT NOT NULL,
+  PRIMARY KEY (bucket_id, key, version_id)
+);
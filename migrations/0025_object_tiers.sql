@@ -0,0 +1,22 @@
+-- 0025_object_tiers.sql
+-- Lifecycle `Transition` actions: the target storage class and age in days
+-- of each rule.
+ALTER TABLE lifecycle_rules ADD COLUMN transition_days INTEGER;
+ALTER TABLE lifecycle_rules ADD COLUMN transition_storage_class TEXT;
+
+-- Payloads moved off the local volume. Keyed by version like
+-- `object_retention`; `last_modified` pins the row to the payload it was
+-- written for, so a later `null` version of the key is not mistaken for it.
+-- `remote_key` names the blob in the tier's store. Rows whose version is
+-- gone are collected, with their blob, by the lifecycle worker; there is no
+-- foreign key so that deleting a bucket leaves its rows for collection.
+CREATE TABLE IF NOT EXISTS object_tiers (
+  bucket_id TEXT NOT NULL,
+  key TEXT NOT NULL,
+  version_id TEXT NOT NULL,
+  last_modified TEXT NOT NULL,
+  tier TEXT NOT NULL,
+  remote_key TEXT NOT NULL,
+  transitioned_at TEXT NOT NULL,
+  PRIMARY KEY (bucket_id, key, version_id)
+);
@@ -0,0 +1,66 @@
+-- 0026_bucket_quotas.sql
+-- Storage used per bucket, kept current by triggers so quota checks do not
+-- scan `objects`: `objects` counts live current objects, `bytes` their
+-- payloads plus those of noncurrent versions.
+CREATE TABLE IF NOT EXISTS bucket_usage (
+  bucket_id TEXT PRIMARY KEY REFERENCES buckets(id) ON DELETE CASCADE,
+  objects INTEGER NOT NULL DEFAULT 0,
+  bytes INTEGER NOT NULL DEFAULT 0
+);
+
+INSERT OR IGNORE INTO bucket_usage (bucket_id, objects, bytes)
+SELECT b.id,
+       (SELECT COUNT(*) FROM objects o WHERE o.bucket_id = b.id AND o.is_deleted = 0),
+       (SELECT COALESCE(SUM(o.size_bytes), 0) FROM objects o
+         WHERE o.bucket_id = b.id AND o.is_deleted = 0)
+       + (SELECT COALESCE(SUM(v.size_bytes), 0) FROM object_versions v
+           WHERE v.bucket_id = b.id AND v.is_delete_marker = 0)
+FROM buckets b;
+
+CREATE TRIGGER IF NOT EXISTS bucket_usage_bucket_insert AFTER INSERT ON buckets
+BEGIN
+  INSERT OR IGNORE INTO bucket_usage (bucket_id) VALUES (NEW.id);
+END;
+
+CREATE TRIGGER IF NOT EXISTS bucket_usage_object_insert AFTER INSERT ON objects
+WHEN NEW.is_deleted = 0
+BEGIN
+  UPDATE bucket_usage SET objects = objects + 1, bytes = bytes + NEW.size_bytes
+  WHERE bucket_id = NEW.bucket_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS bucket_usage_object_update
+AFTER UPDATE OF size_bytes, is_deleted ON objects
+BEGIN
+  UPDATE bucket_usage SET
+    objects = objects - (OLD.is_deleted = 0) + (NEW.is_deleted = 0),
+    bytes = bytes - IIF(OLD.is_deleted = 0, OLD.size_bytes, 0)
+                  + IIF(NEW.is_deleted = 0, NEW.size_bytes, 0)
+  WHERE bucket_id = NEW.bucket_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS bucket_usage_object_delete AFTER DELETE ON objects
+WHEN OLD.is_deleted = 0
+BEGIN
+  UPDATE bucket_usage SET objects = objects - 1, bytes = bytes - OLD.size_bytes
+  WHERE bucket_id = OLD.bucket_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS bucket_usage_version_insert AFTER INSERT ON object_versions
+WHEN NEW.is_delete_marker = 0
+BEGIN
+  UPDATE bucket_usage SET bytes = bytes + NEW.size_bytes WHERE bucket_id = NEW.bucket_id;
+END;
+
+CREATE TRIGGER IF NOT EXISTS bucket_usage_version_delete AFTER DELETE ON object_versions
+WHEN OLD.is_delete_marker = 0
+BEGIN
+  UPDATE bucket_usage SET bytes = bytes - OLD.size_bytes WHERE bucket_id = OLD.bucket_id;
+END;
+
+-- Per-bucket limits; a missing row or NULL column means unlimited.
+CREATE TABLE IF NOT EXISTS bucket_quotas (
+  bucket_id TEXT PRIMARY KEY REFERENCES buckets(id) ON DELETE CASCADE,
+  max_bytes INTEGER,
+  max_objects INTEGER
+);
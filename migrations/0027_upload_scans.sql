@@ -0,0 +1,27 @@
+-- 0027_upload_scans.sql
+-- Content scanning of uploads (see `services::scanner`). `scan_uploads` is
+-- NULL (off), 'inline' or 'async'.
+ALTER TABLE buckets ADD COLUMN scan_uploads TEXT;
+
+-- Scan state of versions written to 'async' buckets, keyed by version like
+-- `object_encryption`. A row with `scanned_at` NULL is waiting for the scan
+-- worker; `quarantined` = 1 blocks reads of the version. `scan_id` changes
+-- whenever the version's payload is rewritten, so a verdict about an older
+-- payload is never recorded against a newer one.
+CREATE TABLE IF NOT EXISTS object_scans (
+  bucket_id TEXT NOT NULL REFERENCES buckets(id) ON DELETE CASCADE,
+  key TEXT NOT NULL,
+  version_id TEXT NOT NULL,
+  scan_id TEXT NOT NULL,
+  queued_at TEXT NOT NULL,
+  scanned_at TEXT,
+  quarantined INTEGER NOT NULL DEFAULT 0,
+  signature TEXT,
+  attempts INTEGER NOT NULL DEFAULT 0,
+  PRIMARY KEY (bucket_id, key, version_id)
+);
+
+CREATE INDEX IF NOT EXISTS object_scans_pending
+  ON object_scans (queued_at) WHERE scanned_at IS NULL;
+CREATE INDEX IF NOT EXISTS object_scans_quarantined
+  ON object_scans (bucket_id, key) WHERE quarantined = 1;
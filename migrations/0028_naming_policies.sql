@@ -0,0 +1,7 @@
+-- 0028_naming_policies.sql
+-- Key constraints a bucket enforces on writes (see `services::naming_policy`),
+-- stored as the JSON of `NamingPolicy`. A missing row means no constraints.
+CREATE TABLE IF NOT EXISTS bucket_naming_policies (
+  bucket_id TEXT PRIMARY KEY REFERENCES buckets(id) ON DELETE CASCADE,
+  policy TEXT NOT NULL
+);
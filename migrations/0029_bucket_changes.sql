@@ -0,0 +1,26 @@
+-- 0029_bucket_changes.sql
+-- Change feed of object writes and deletes (see `services::changes`). `seq`
+-- is the cursor handed to clients: AUTOINCREMENT keeps it from ever being
+-- reused, even after the newest rows are pruned.
+CREATE TABLE IF NOT EXISTS bucket_changes (
+  seq INTEGER PRIMARY KEY AUTOINCREMENT,
+  bucket_id TEXT NOT NULL REFERENCES buckets(id) ON DELETE CASCADE,
+  key TEXT NOT NULL,
+  event TEXT NOT NULL,
+  version_id TEXT,
+  etag TEXT,
+  size_bytes INTEGER,
+  occurred_at TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS bucket_changes_by_bucket
+  ON bucket_changes (bucket_id, seq);
+CREATE INDEX IF NOT EXISTS bucket_changes_by_age
+  ON bucket_changes (occurred_at);
+
+-- Highest `seq` pruned from each bucket's feed: a cursor below it may have
+-- missed changes.
+CREATE TABLE IF NOT EXISTS bucket_changes_pruned (
+  bucket_id TEXT PRIMARY KEY REFERENCES buckets(id) ON DELETE CASCADE,
+  pruned_through INTEGER NOT NULL
+);
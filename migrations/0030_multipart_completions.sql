@@ -0,0 +1,25 @@
+-- 0030_multipart_completions.sql
+-- Progress of CompleteMultipartUpload assemblies (see
+-- `services::multipart_assembly`). Not tied to `multipart_uploads`: the row
+-- outlives the upload once completed, so late retries still get the object.
+-- `parts` is the requested part list (JSON); `state` is 'assembling',
+-- 'completed' or 'failed'.
+CREATE TABLE IF NOT EXISTS multipart_completions (
+  upload_id TEXT PRIMARY KEY,
+  bucket_id TEXT NOT NULL REFERENCES buckets(id) ON DELETE CASCADE,
+  key TEXT NOT NULL,
+  parts TEXT NOT NULL,
+  state TEXT NOT NULL,
+  parts_total INTEGER NOT NULL,
+  parts_assembled INTEGER NOT NULL DEFAULT 0,
+  bytes_total INTEGER NOT NULL,
+  bytes_assembled INTEGER NOT NULL DEFAULT 0,
+  started_at TEXT NOT NULL,
+  updated_at TEXT NOT NULL,
+  etag TEXT,
+  version_id TEXT,
+  error TEXT
+);
+
+CREATE INDEX IF NOT EXISTS multipart_completions_state
+  ON multipart_completions (state);
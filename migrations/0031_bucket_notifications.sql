@@ -0,0 +1,47 @@
+-- 0031_bucket_notifications.sql
+-- Bucket notification configuration (S3 `?notification`) with webhook
+-- targets, and the queue of event deliveries to them (see
+-- `services::notifications`). Events are taken from the change feed after
+-- `notification_cursor`; every matching target gets a delivery row, retried
+-- with backoff until it succeeds or runs out of attempts.
+CREATE TABLE IF NOT EXISTS notification_targets (
+  bucket_id TEXT NOT NULL REFERENCES buckets(id) ON DELETE CASCADE,
+  target_id TEXT NOT NULL,
+  position INTEGER NOT NULL,
+  endpoint TEXT NOT NULL,
+  -- JSON array of event names, e.g. ["s3:ObjectCreated:*"]
+  events TEXT NOT NULL,
+  prefix TEXT,
+  suffix TEXT,
+  -- only changes after this `bucket_changes.seq` are delivered
+  since_seq INTEGER NOT NULL,
+  PRIMARY KEY (bucket_id, target_id)
+);
+
+-- Last change fanned out to the delivery queue. Starts at the end of the
+-- feed, so history from before the upgrade is never sent.
+CREATE TABLE IF NOT EXISTS notification_cursor (
+  id INTEGER PRIMARY KEY CHECK (id = 1),
+  last_seq INTEGER NOT NULL
+);
+INSERT OR IGNORE INTO notification_cursor (id, last_seq)
+  SELECT 1, COALESCE(MAX(seq), 0) FROM bucket_changes;
+
+CREATE TABLE IF NOT EXISTS notification_deliveries (
+  id INTEGER PRIMARY KEY AUTOINCREMENT,
+  bucket_id TEXT NOT NULL REFERENCES buckets(id) ON DELETE CASCADE,
+  target_id TEXT NOT NULL,
+  endpoint TEXT NOT NULL,
+  event_name TEXT NOT NULL,
+  -- S3 event JSON (`{"Records": [...]}`)
+  payload TEXT NOT NULL,
+  -- pending | failed (out of attempts)
+  state TEXT NOT NULL DEFAULT 'pending',
+  attempts INTEGER NOT NULL DEFAULT 0,
+  next_attempt_at TEXT NOT NULL,
+  last_error TEXT,
+  created_at TEXT NOT NULL
+);
+
+CREATE INDEX IF NOT EXISTS notification_deliveries_due
+  ON notification_deliveries (state, next_attempt_at);
@@ -0,0 +1,11 @@
+-- 0032_placement_policies.sql
+-- Rules placing payloads on volumes by key prefix (see
+-- `services::placement`), stored as the JSON of `PlacementPolicy`. A missing
+-- row places everything on the default volume. `objects.volume` records
+-- where each payload was placed; NULL is the default volume.
+CREATE TABLE IF NOT EXISTS bucket_placement_policies (
+  bucket_id TEXT PRIMARY KEY REFERENCES buckets(id) ON DELETE CASCADE,
+  policy TEXT NOT NULL
+);
+
+ALTER TABLE objects ADD COLUMN volume TEXT;
@@ -0,0 +1,8 @@
+-- 0033_event_publishers.sql
+-- NATS / Kafka publishers of a bucket's object events (see
+-- `services::publishers`), stored as the JSON of `PublisherConfig`. A
+-- missing row means the bucket's events are not published.
+CREATE TABLE IF NOT EXISTS bucket_event_publishers (
+  bucket_id TEXT PRIMARY KEY REFERENCES buckets(id) ON DELETE CASCADE,
+  config TEXT NOT NULL
+);
@@ -0,0 +1,30 @@
+-- 0034_bucket_replication.sql
+-- Asynchronous replication of a bucket to another S3 endpoint (see
+-- `services::replication`). `bucket_replication` holds the JSON of
+-- `ReplicationConfig` and how far the bucket's change feed has been queued;
+-- `object_replication` holds the replication status of each changed key.
+CREATE TABLE IF NOT EXISTS bucket_replication (
+  bucket_id TEXT PRIMARY KEY REFERENCES buckets(id) ON DELETE CASCADE,
+  config TEXT NOT NULL,
+  -- changes up to this `bucket_changes.seq` are queued
+  last_seq INTEGER NOT NULL
+);
+
+CREATE TABLE IF NOT EXISTS object_replication (
+  bucket_id TEXT NOT NULL REFERENCES buckets(id) ON DELETE CASCADE,
+  key TEXT NOT NULL,
+  -- latest change of the key; a newer one requeues it
+  change_seq INTEGER NOT NULL,
+  -- pending | completed | failed (out of attempts)
+  state TEXT NOT NULL,
+  attempts INTEGER NOT NULL DEFAULT 0,
+  next_attempt_at TEXT NOT NULL,
+  last_error TEXT,
+  -- when the oldest change not yet replicated happened
+  queued_at TEXT NOT NULL,
+  replicated_at TEXT,
+  PRIMARY KEY (bucket_id, key)
+);
+
+CREATE INDEX IF NOT EXISTS object_replication_due
+  ON object_replication (state, next_attempt_at);
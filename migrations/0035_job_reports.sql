@@ -0,0 +1,4 @@
+-- 0035_job_reports.sql
+-- Result summary of jobs that produce one (e.g. `replication-reconcile`),
+-- as JSON. NULL for jobs without a report.
+ALTER TABLE jobs ADD COLUMN report TEXT;
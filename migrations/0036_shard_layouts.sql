@@ -0,0 +1,14 @@
+-- 0036_shard_layouts.sql
+-- Directory layouts of live payloads (see `services::shard_layout`): `depth`
+-- levels named after MD5(bucket/key) below the bucket directory. The
+-- `current` layout takes writes; the others still hold payloads until the
+-- `shard-migration` jobs queued since `adopted_at` have moved them.
+CREATE TABLE IF NOT EXISTS shard_layouts (
+  depth INTEGER PRIMARY KEY,
+  current INTEGER NOT NULL,
+  adopted_at TEXT NOT NULL
+);
+
+-- Payloads written so far are two levels deep.
+INSERT OR IGNORE INTO shard_layouts (depth, current, adopted_at)
+VALUES (2, 1, '1970-01-01T00:00:00+00:00');
@@ -0,0 +1,17 @@
+-- 0037_portable_filenames.sql
+-- Payload layouts also differ in whether key segments are escaped into
+-- names every filesystem accepts (see `services::shard_layout`), so a layout
+-- is keyed by both.
+CREATE TABLE shard_layouts_new (
+  depth INTEGER NOT NULL,
+  portable INTEGER NOT NULL,
+  current INTEGER NOT NULL,
+  adopted_at TEXT NOT NULL,
+  PRIMARY KEY (depth, portable)
+);
+
+INSERT INTO shard_layouts_new (depth, portable, current, adopted_at)
+SELECT depth, 0, current, adopted_at FROM shard_layouts;
+
+DROP TABLE shard_layouts;
+ALTER TABLE shard_layouts_new RENAME TO shard_layouts;
@@ -0,0 +1,7 @@
+-- 0038_object_search.sql
+-- Serve cross-bucket object searches (`GET /admin/objects`) by owner and age.
+CREATE INDEX IF NOT EXISTS idx_buckets_owner_name ON buckets(owner_id, name);
+
+CREATE INDEX IF NOT EXISTS idx_objects_bucket_modified
+  ON objects(bucket_id, last_modified)
+  WHERE is_deleted = 0;
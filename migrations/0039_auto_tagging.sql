@@ -0,0 +1,11 @@
+-- 0039_auto_tagging.sql
+-- Rules that tag uploads of a bucket (see `services::auto_tagging`), stored
+-- as the JSON of `AutoTagging`. A missing row means no rules.
+CREATE TABLE IF NOT EXISTS bucket_auto_tagging (
+  bucket_id TEXT PRIMARY KEY REFERENCES buckets(id) ON DELETE CASCADE,
+  rules TEXT NOT NULL
+);
+
+-- Access key that initiated a multipart upload, matched by the rules when
+-- it completes.
+ALTER TABLE multipart_uploads ADD COLUMN uploader TEXT;
@@ -0,0 +1,10 @@
+-- 0040_config_versions.sql
+-- Version of each configuration resource of a bucket, bumped on every
+-- successful update (see `services::config_version`). A missing row is
+-- version 0: the resource was never changed since the bucket was created.
+CREATE TABLE IF NOT EXISTS bucket_config_versions (
+  bucket_id TEXT NOT NULL REFERENCES buckets(id) ON DELETE CASCADE,
+  resource TEXT NOT NULL,
+  version INTEGER NOT NULL,
+  PRIMARY KEY (bucket_id, resource)
+);
@@ -0,0 +1,9 @@
+-- 0041_part_checksums.sql
+-- Base64 SHA-256 and CRC32C of each uploaded part, kept when the part was
+-- sent with a matching `x-amz-checksum-*` (or SHA-256 is computed for every
+-- payload), and carried over to the parts of the completed object for
+-- GetObjectAttributes.
+ALTER TABLE multipart_parts ADD COLUMN checksum_sha256 TEXT;
+ALTER TABLE multipart_parts ADD COLUMN checksum_crc32c TEXT;
+ALTER TABLE object_parts ADD COLUMN checksum_sha256 TEXT;
+ALTER TABLE object_parts ADD COLUMN checksum_crc32c TEXT;
@@ -0,0 +1,13 @@
+-- 0042_admin_tokens.sql
+-- Scoped admin API tokens for automation (see `services::admin_tokens`).
+-- Only the SHA-256 of a token is kept; `scopes` is a JSON array of scope
+-- names and a NULL `expires_at` never expires.
+CREATE TABLE IF NOT EXISTS admin_tokens (
+  id TEXT PRIMARY KEY,
+  name TEXT NOT NULL,
+  token_sha256 TEXT NOT NULL UNIQUE,
+  scopes TEXT NOT NULL,
+  created_at TEXT NOT NULL,
+  expires_at TEXT,
+  last_used_at TEXT
+);
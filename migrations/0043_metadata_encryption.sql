@@ -0,0 +1,12 @@
+-- 0043_metadata_encryption.sql
+-- Encryption at rest of user metadata and tag values (see
+-- `services::metadata_encryption`). A row with `value_hash` set holds its
+-- value sealed; `value_hash` is the keyed hash tag filters match on.
+ALTER TABLE buckets ADD COLUMN encrypt_metadata INTEGER NOT NULL DEFAULT 0;
+
+ALTER TABLE object_tags ADD COLUMN value_hash TEXT;
+ALTER TABLE object_metadata ADD COLUMN value_hash TEXT;
+
+CREATE INDEX IF NOT EXISTS idx_object_tags_hash
+  ON object_tags(bucket_id, tag_key, value_hash)
+  WHERE value_hash IS NOT NULL;
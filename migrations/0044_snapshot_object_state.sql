@@ -0,0 +1,37 @@
+-- 0044_snapshot_object_state.sql
+-- Per-key state captured with each snapshot object, so a restore puts back
+-- everything a read depends on and not just the payload: the SSE-C
+-- encryption of the payload (`object_encryption`), and the key's tags, user
+-- metadata and multipart parts. Tag and metadata values are copied as
+-- stored; sealed rows (`value_hash` set) stay sealed.
+ALTER TABLE snapshot_objects ADD COLUMN sse_key_md5 TEXT;
+ALTER TABLE snapshot_objects ADD COLUMN sse_iv TEXT;
+
+CREATE TABLE IF NOT EXISTS snapshot_object_tags (
+  snapshot_id TEXT NOT NULL REFERENCES bucket_snapshots(id) ON DELETE CASCADE,
+  key TEXT NOT NULL,
+  tag_key TEXT NOT NULL,
+  tag_value TEXT NOT NULL,
+  value_hash TEXT,
+  PRIMARY KEY (snapshot_id, key, tag_key)
+);
+
+CREATE TABLE IF NOT EXISTS snapshot_object_metadata (
+  snapshot_id TEXT NOT NULL REFERENCES bucket_snapshots(id) ON DELETE CASCADE,
+  key TEXT NOT NULL,
+  name TEXT NOT NULL,
+  value TEXT NOT NULL,
+  value_hash TEXT,
+  PRIMARY KEY (snapshot_id, key, name)
+);
+
+CREATE TABLE IF NOT EXISTS snapshot_object_parts (
+  snapshot_id TEXT NOT NULL REFERENCES bucket_snapshots(id) ON DELETE CASCADE,
+  key TEXT NOT NULL,
+  part_number INTEGER NOT NULL,
+  size_bytes INTEGER NOT NULL,
+  etag TEXT NOT NULL,
+  checksum_sha256 TEXT,
+  checksum_crc32c TEXT,
+  PRIMARY KEY (snapshot_id, key, part_number)
+);
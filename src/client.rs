@@ -0,0 +1,597 @@
+//! Typed HTTP client for this store (cargo feature `client`).
+//!
+//! Wraps the S3-style routes with typed requests and responses so Rust
+//! programs talking to an object-store server do not need the full AWS SDK:
+//! streaming uploads, downloads (whole or ranged), paginated listings and
+//! multipart uploads, including a helper that splits a reader into parts.
+//! Requests are unsigned; an optional bearer token is sent for deployments
+//! that put the store behind an authorizer.
+//!
+//! ```no_run
+//! # async fn demo() -> Result<(), object_store::client::ClientError> {
+//! use futures::TryStreamExt;
+//! use object_store::client::Client;
+//!
+//! let client = Client::new("http://127.0.0.1:3000");
+//! client.create_bucket("photos").await?;
+//! client.put_object("photos", "2025/cat.jpg", b"...".to_vec(), Some("image/jpeg")).await?;
+//! let body = client.get_object("photos", "2025/cat.jpg").await?.bytes().await?;
+//! let keys: Vec<_> = client
+//!     .list_all("photos", Some("2025/"))
+//!     .map_ok(|object| object.key)
+//!     .try_collect()
+//!     .await?;
+//! # let _ = (body, keys);
+//! # Ok(())
+//! # }
+//! ```
+
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use futures::{Stream, TryStream, TryStreamExt, stream};
+use percent_encoding::{AsciiSet, NON_ALPHANUMERIC, utf8_percent_encode};
+use reqwest::{Method, RequestBuilder, Response, StatusCode, header};
+use serde::Deserialize;
+use thiserror::Error;
+use tokio::io::{AsyncRead, AsyncReadExt};
+
+/// Characters escaped in bucket names and keys; `/` stays literal so nested
+/// keys map onto the `{*key}` route.
+const PATH_SET: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~')
+    .remove(b'/');
+
+/// A sensible `part_size` for `upload_multipart`.
+pub const DEFAULT_PART_SIZE: usize = 8 * 1024 * 1024;
+
+#[derive(Debug, Error)]
+pub enum ClientError {
+    /// The server answered with a non-success status.
+    #[error("server returned {status}: {message}")]
+    Status { status: StatusCode, message: String },
+    /// The response could not be understood.
+    #[error("unexpected response: {0}")]
+    InvalidResponse(String),
+    #[error(transparent)]
+    Http(#[from] reqwest::Error),
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+}
+
+pub type ClientResult<T> = Result<T, ClientError>;
+
+/// Connection to one object-store server.
+#[derive(Debug, Clone)]
+pub struct Client {
+    http: reqwest::Client,
+    endpoint: String,
+    bearer_token: Option<String>,
+}
+
+/// What the server reported after storing an object.
+#[derive(Debug, Clone, Default)]
+pub struct PutObjectOutput {
+    pub etag: Option<String>,
+    pub version_id: Option<String>,
+    pub checksum_sha256: Option<String>,
+}
+
+/// Object metadata from `HEAD` or the headers of a `GET`.
+#[derive(Debug, Clone, Default)]
+pub struct ObjectInfo {
+    pub content_length: Option<u64>,
+    pub content_type: Option<String>,
+    pub content_encoding: Option<String>,
+    pub etag: Option<String>,
+    pub last_modified: Option<DateTime<Utc>>,
+    pub version_id: Option<String>,
+    pub checksum_sha256: Option<String>,
+}
+
+/// A download in progress; the body has not been read yet.
+#[derive(Debug)]
+pub struct GetObjectOutput {
+    pub info: ObjectInfo,
+    response: Response,
+}
+
+impl GetObjectOutput {
+    /// Read the whole body into memory.
+    pub async fn bytes(self) -> ClientResult<Bytes> {
+        Ok(self.response.bytes().await?)
+    }
+
+    /// Stream the body chunk by chunk.
+    pub fn into_stream(self) -> impl Stream<Item = ClientResult<Bytes>> {
+        self.response.bytes_stream().map_err(ClientError::from)
+    }
+}
+
+/// Parameters of one `ListObjectsV2` call.
+#[derive(Debug, Clone, Default)]
+pub struct ListObjectsRequest {
+    pub prefix: Option<String>,
+    pub delimiter: Option<String>,
+    pub max_keys: Option<usize>,
+    pub continuation_token: Option<String>,
+    pub start_after: Option<String>,
+}
+
+/// One page of a listing.
+#[derive(Debug, Clone, Default)]
+pub struct ListObjectsPage {
+    pub objects: Vec<ListedObject>,
+    pub common_prefixes: Vec<String>,
+    pub is_truncated: bool,
+    pub next_continuation_token: Option<String>,
+}
+
+/// An entry of a listing.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ListedObject {
+    pub key: String,
+    pub last_modified: DateTime<Utc>,
+    #[serde(rename = "ETag", deserialize_with = "unquote")]
+    pub etag: String,
+    pub size: i64,
+    pub storage_class: String,
+}
+
+/// A stored part, as needed to complete a multipart upload.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct CompletedPart {
+    pub part_number: i64,
+    pub etag: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ListBucketResultXml {
+    #[serde(default)]
+    contents: Vec<ListedObject>,
+    #[serde(default)]
+    common_prefixes: Vec<CommonPrefixXml>,
+    is_truncated: bool,
+    next_continuation_token: Option<String>,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct CommonPrefixXml {
+    prefix: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct InitiateMultipartUploadResultXml {
+    upload_id: String,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct CompleteMultipartUploadResultXml {
+    #[serde(rename = "ETag", deserialize_with = "unquote")]
+    etag: String,
+    #[serde(rename = "ChecksumSHA256")]
+    checksum_sha256: Option<String>,
+}
+
+/// JSON error body the server sends with failures.
+#[derive(Debug, Deserialize)]
+struct ErrorBodyJson {
+    error: String,
+}
+
+impl Client {
+    /// Client for the server at `endpoint`, e.g. `http://127.0.0.1:3000`.
+    pub fn new(endpoint: impl Into<String>) -> Self {
+        Self::with_http_client(endpoint, reqwest::Client::new())
+    }
+
+    /// Like `new`, with a preconfigured `reqwest::Client` (timeouts, TLS,
+    /// proxies, ...).
+    pub fn with_http_client(endpoint: impl Into<String>, http: reqwest::Client) -> Self {
+        let endpoint = endpoint.into().trim_end_matches('/').to_string();
+        Self {
+            http,
+            endpoint,
+            bearer_token: None,
+        }
+    }
+
+    /// Send `Authorization: Bearer <token>` with every request.
+    pub fn with_bearer_token(mut self, token: impl Into<String>) -> Self {
+        self.bearer_token = Some(token.into());
+        self
+    }
+
+    /// `PUT /{bucket}`
+    pub async fn create_bucket(&self, bucket: &str) -> ClientResult<()> {
+        let request = self
+            .request(Method::PUT, &self.bucket_url(bucket))
+            .header(header::CONTENT_TYPE, "application/json")
+            .body("null");
+        self.send(request).await.map(drop)
+    }
+
+    /// `DELETE /{bucket}`
+    pub async fn delete_bucket(&self, bucket: &str) -> ClientResult<()> {
+        self.send(self.request(Method::DELETE, &self.bucket_url(bucket)))
+            .await
+            .map(drop)
+    }
+
+    /// Upload a body held in memory.
+    pub async fn put_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        body: impl Into<reqwest::Body>,
+        content_type: Option<&str>,
+    ) -> ClientResult<PutObjectOutput> {
+        let mut request = self.request(Method::PUT, &self.object_url(bucket, key));
+        if let Some(content_type) = content_type {
+            request = request.header(header::CONTENT_TYPE, content_type);
+        }
+        let response = self.send(request.body(body)).await?;
+        Ok(put_output(&response))
+    }
+
+    /// Upload a body streamed from `stream` without buffering it. Passing
+    /// `content_length` lets the server report accurate upload progress.
+    pub async fn put_object_stream<S>(
+        &self,
+        bucket: &str,
+        key: &str,
+        stream: S,
+        content_length: Option<u64>,
+        content_type: Option<&str>,
+    ) -> ClientResult<PutObjectOutput>
+    where
+        S: TryStream + Send + 'static,
+        S::Error: Into<Box<dyn std::error::Error + Send + Sync>>,
+        Bytes: From<S::Ok>,
+    {
+        let mut request = self.request(Method::PUT, &self.object_url(bucket, key));
+        if let Some(length) = content_length {
+            request = request.header(header::CONTENT_LENGTH, length);
+        }
+        if let Some(content_type) = content_type {
+            request = request.header(header::CONTENT_TYPE, content_type);
+        }
+        let response = self
+            .send(request.body(reqwest::Body::wrap_stream(stream)))
+            .await?;
+        Ok(put_output(&response))
+    }
+
+    /// `GET /{bucket}/{key}`
+    pub async fn get_object(&self, bucket: &str, key: &str) -> ClientResult<GetObjectOutput> {
+        let response = self
+            .send(self.request(Method::GET, &self.object_url(bucket, key)))
+            .await?;
+        Ok(GetObjectOutput {
+            info: object_info(&response),
+            response,
+        })
+    }
+
+    /// Download bytes `start..=end` (to the end of the object without `end`).
+    pub async fn get_object_range(
+        &self,
+        bucket: &str,
+        key: &str,
+        start: u64,
+        end: Option<u64>,
+    ) -> ClientResult<GetObjectOutput> {
+        let range = match end {
+            Some(end) => format!("bytes={}-{}", start, end),
+            None => format!("bytes={}-", start),
+        };
+        let request = self
+            .request(Method::GET, &self.object_url(bucket, key))
+            .header(header::RANGE, range);
+        let response = self.send(request).await?;
+        Ok(GetObjectOutput {
+            info: object_info(&response),
+            response,
+        })
+    }
+
+    /// `HEAD /{bucket}/{key}`
+    pub async fn head_object(&self, bucket: &str, key: &str) -> ClientResult<ObjectInfo> {
+        let response = self
+            .send(self.request(Method::HEAD, &self.object_url(bucket, key)))
+            .await?;
+        Ok(object_info(&response))
+    }
+
+    /// `DELETE /{bucket}/{key}`
+    pub async fn delete_object(&self, bucket: &str, key: &str) -> ClientResult<()> {
+        self.send(self.request(Method::DELETE, &self.object_url(bucket, key)))
+            .await
+            .map(drop)
+    }
+
+    /// One page of `ListObjectsV2`.
+    pub async fn list_objects(
+        &self,
+        bucket: &str,
+        params: &ListObjectsRequest,
+    ) -> ClientResult<ListObjectsPage> {
+        let mut query = vec![("list-type", "2".to_string())];
+        let optional = [
+            ("prefix", params.prefix.clone()),
+            ("delimiter", params.delimiter.clone()),
+            ("max-keys", params.max_keys.map(|n| n.to_string())),
+            ("continuation-token", params.continuation_token.clone()),
+            ("start-after", params.start_after.clone()),
+        ];
+        query.extend(
+            optional
+                .into_iter()
+                .filter_map(|(name, value)| value.map(|value| (name, value))),
+        );
+        let response = self
+            .send(
+                self.request(Method::GET, &self.bucket_url(bucket))
+                    .query(&query),
+            )
+            .await?;
+        let result: ListBucketResultXml = parse_xml(response).await?;
+        Ok(ListObjectsPage {
+            objects: result.contents,
+            common_prefixes: result
+                .common_prefixes
+                .into_iter()
+                .map(|p| p.prefix)
+                .collect(),
+            is_truncated: result.is_truncated,
+            next_continuation_token: result.next_continuation_token,
+        })
+    }
+
+    /// Every object under `prefix`, fetching further pages as the stream is
+    /// consumed.
+    pub fn list_all<'a>(
+        &'a self,
+        bucket: &'a str,
+        prefix: Option<&str>,
+    ) -> impl Stream<Item = ClientResult<ListedObject>> + 'a {
+        let first = ListObjectsRequest {
+            prefix: prefix.map(str::to_string),
+            ..Default::default()
+        };
+        stream::try_unfold(Some(first), move |next| async move {
+            let Some(params) = next else {
+                return Ok::<_, ClientError>(None);
+            };
+            let page = self.list_objects(bucket, &params).await?;
+            let following = match (&page.next_continuation_token, page.is_truncated) {
+                (Some(token), true) => Some(ListObjectsRequest {
+                    continuation_token: Some(token.clone()),
+                    ..params
+                }),
+                _ => None,
+            };
+            Ok(Some((page.objects, following)))
+        })
+        .map_ok(|objects| stream::iter(objects.into_iter().map(Ok)))
+        .try_flatten()
+    }
+
+    /// `POST /{bucket}/{key}?uploads`; returns the upload id.
+    pub async fn create_multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        content_type: Option<&str>,
+    ) -> ClientResult<String> {
+        let url = format!("{}?uploads", self.object_url(bucket, key));
+        let mut request = self.request(Method::POST, &url);
+        if let Some(content_type) = content_type {
+            request = request.header(header::CONTENT_TYPE, content_type);
+        }
+        let response = self.send(request).await?;
+        let result: InitiateMultipartUploadResultXml = parse_xml(response).await?;
+        Ok(result.upload_id)
+    }
+
+    /// `PUT /{bucket}/{key}?partNumber=N&uploadId=U`
+    pub async fn upload_part(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+        part_number: i64,
+        body: impl Into<reqwest::Body>,
+    ) -> ClientResult<CompletedPart> {
+        let request = self
+            .request(Method::PUT, &self.object_url(bucket, key))
+            .query(&[
+                ("partNumber", part_number.to_string()),
+                ("uploadId", upload_id.to_string()),
+            ])
+            .body(body);
+        let response = self.send(request).await?;
+        let etag = header_str(&response, header::ETAG.as_str())
+            .map(|etag| etag.trim_matches('"').to_string())
+            .ok_or_else(|| ClientError::InvalidResponse("UploadPart without ETag".into()))?;
+        Ok(CompletedPart { part_number, etag })
+    }
+
+    /// `POST /{bucket}/{key}?uploadId=U`
+    pub async fn complete_multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+        parts: &[CompletedPart],
+    ) -> ClientResult<PutObjectOutput> {
+        let mut body = String::from("<CompleteMultipartUpload>");
+        for part in parts {
+            body.push_str(&format!(
+                "<Part><PartNumber>{}</PartNumber><ETag>\"{}\"</ETag></Part>",
+                part.part_number, part.etag
+            ));
+        }
+        body.push_str("</CompleteMultipartUpload>");
+        let request = self
+            .request(Method::POST, &self.object_url(bucket, key))
+            .query(&[("uploadId", upload_id)])
+            .header(header::CONTENT_TYPE, "application/xml")
+            .body(body);
+        let response = self.send(request).await?;
+        let version_id = header_str(&response, "x-amz-version-id").map(str::to_string);
+        let result: CompleteMultipartUploadResultXml = parse_xml(response).await?;
+        Ok(PutObjectOutput {
+            etag: Some(result.etag),
+            version_id,
+            checksum_sha256: result.checksum_sha256,
+        })
+    }
+
+    /// `DELETE /{bucket}/{key}?uploadId=U`
+    pub async fn abort_multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: &str,
+    ) -> ClientResult<()> {
+        let request = self
+            .request(Method::DELETE, &self.object_url(bucket, key))
+            .query(&[("uploadId", upload_id)]);
+        self.send(request).await.map(drop)
+    }
+
+    /// Upload everything `reader` yields as a multipart upload of
+    /// `part_size`-byte parts (at least the server's 5 MiB minimum, except
+    /// the last). The upload is aborted if any step fails.
+    pub async fn upload_multipart<R>(
+        &self,
+        bucket: &str,
+        key: &str,
+        mut reader: R,
+        part_size: usize,
+        content_type: Option<&str>,
+    ) -> ClientResult<PutObjectOutput>
+    where
+        R: AsyncRead + Unpin,
+    {
+        let upload_id = self
+            .create_multipart_upload(bucket, key, content_type)
+            .await?;
+        let result = async {
+            let mut parts = Vec::new();
+            loop {
+                let mut chunk = Vec::with_capacity(part_size);
+                (&mut reader)
+                    .take(part_size as u64)
+                    .read_to_end(&mut chunk)
+                    .await?;
+                if chunk.is_empty() && !parts.is_empty() {
+                    break;
+                }
+                let last = chunk.len() < part_size;
+                let part_number = parts.len() as i64 + 1;
+                parts.push(
+                    self.upload_part(bucket, key, &upload_id, part_number, chunk)
+                        .await?,
+                );
+                if last {
+                    break;
+                }
+            }
+            self.complete_multipart_upload(bucket, key, &upload_id, &parts)
+                .await
+        }
+        .await;
+        if result.is_err() {
+            // Best effort: the original error is what the caller needs.
+            let _ = self.abort_multipart_upload(bucket, key, &upload_id).await;
+        }
+        result
+    }
+
+    fn bucket_url(&self, bucket: &str) -> String {
+        format!(
+            "{}/{}",
+            self.endpoint,
+            utf8_percent_encode(bucket, PATH_SET)
+        )
+    }
+
+    fn object_url(&self, bucket: &str, key: &str) -> String {
+        format!(
+            "{}/{}",
+            self.bucket_url(bucket),
+            utf8_percent_encode(key, PATH_SET)
+        )
+    }
+
+    fn request(&self, method: Method, url: &str) -> RequestBuilder {
+        let request = self.http.request(method, url);
+        match &self.bearer_token {
+            Some(token) => request.bearer_auth(token),
+            None => request,
+        }
+    }
+
+    async fn send(&self, request: RequestBuilder) -> ClientResult<Response> {
+        let response = request.send().await?;
+        if response.status().is_success() {
+            return Ok(response);
+        }
+        let status = response.status();
+        let text = response.text().await.unwrap_or_default();
+        let message = serde_json::from_str::<ErrorBodyJson>(&text)
+            .map(|body| body.error)
+            .unwrap_or(text);
+        Err(ClientError::Status { status, message })
+    }
+}
+
+async fn parse_xml<T: for<'de> Deserialize<'de>>(response: Response) -> ClientResult<T> {
+    let text = response.text().await?;
+    quick_xml::de::from_str(&text).map_err(|err| ClientError::InvalidResponse(err.to_string()))
+}
+
+fn header_str<'a>(response: &'a Response, name: &str) -> Option<&'a str> {
+    response.headers().get(name).and_then(|v| v.to_str().ok())
+}
+
+fn put_output(response: &Response) -> PutObjectOutput {
+    PutObjectOutput {
+        etag: header_str(response, header::ETAG.as_str()).map(|e| e.trim_matches('"').to_string()),
+        version_id: header_str(response, "x-amz-version-id").map(str::to_string),
+        checksum_sha256: header_str(response, "x-amz-checksum-sha256").map(str::to_string),
+    }
+}
+
+fn object_info(response: &Response) -> ObjectInfo {
+    let owned = |name: &str| header_str(response, name).map(str::to_string);
+    ObjectInfo {
+        content_length: header_str(response, header::CONTENT_LENGTH.as_str())
+            .and_then(|v| v.parse().ok()),
+        content_type: owned(header::CONTENT_TYPE.as_str()),
+        content_encoding: owned(header::CONTENT_ENCODING.as_str()),
+        etag: header_str(response, header::ETAG.as_str()).map(|e| e.trim_matches('"').to_string()),
+        last_modified: header_str(response, header::LAST_MODIFIED.as_str())
+            .and_then(|v| DateTime::parse_from_rfc2822(v).ok())
+            .map(|date| date.with_timezone(&Utc)),
+        version_id: owned("x-amz-version-id"),
+        checksum_sha256: owned("x-amz-checksum-sha256"),
+    }
+}
+
+fn unquote<'de, D>(deserializer: D) -> Result<String, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let value = String::deserialize(deserializer)?;
+    Ok(value.trim_matches('"').to_string())
+}
@@ -1,6 +1,32 @@
-use anyhow::{Context, Result};
+use crate::{
+    middleware::{
+        authorizer::AuthorizerEndpoint,
+        client_info::{ForwardedHeader, IpNetwork},
+        feature_flags::ApiGroup,
+    },
+    models::admin_token::AdminScope,
+    services::{
+        blob_store::S3Credentials,
+        bucket_template::BucketTemplates,
+        manifest::ManifestKey,
+        metadata_encryption::MetadataKey,
+        outbound::ProxyRule,
+        placement::VolumeSpec,
+        prefix_usage::MAX_USAGE_PREFIX_DEPTH,
+        scanner::ScannerEndpoint,
+        session::SessionKey,
+        shard_layout::{DEFAULT_SHARD_DEPTH, MAX_SHARD_DEPTH},
+    },
+};
+use anyhow::{Context, Result, anyhow};
 use clap::Parser;
-use std::env;
+use reqwest::Url;
+use std::{
+    env, fmt,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use uuid::Uuid;
 
 /// Centralized application configuration.
 /// Combines environment variables and CLI arguments.
@@ -8,8 +34,179 @@ use std::env;
 pub struct AppConfig {
     pub host: String,
     pub port: u16,
+    /// PEM certificate chain and private key to serve HTTPS with.
+    pub tls_cert: Option<PathBuf>,
+    pub tls_key: Option<PathBuf>,
+    /// Seconds between checks of the TLS files for changes (0 disables).
+    pub tls_reload_secs: u64,
     pub storage_dir: String,
+    /// Directory for temp files of uploads, on the storage filesystem.
+    pub staging_dir: Option<String>,
+    /// Named volumes besides the storage directory, for placement policies.
+    pub volumes: Vec<VolumeSpec>,
+    /// Directory levels live payloads are sharded in below their bucket.
+    pub shard_depth: usize,
+    /// Escape key segments into file names every filesystem accepts.
+    pub portable_filenames: bool,
     pub database_url: String,
+    /// Seconds an overwritten payload is kept in the recycle area (0 disables).
+    pub overwrite_retention_secs: u64,
+    /// Seconds after initiation an unfinished multipart upload is aborted
+    /// (0 disables).
+    pub multipart_ttl_secs: u64,
+    /// Seconds a soft-deleted object row is kept before it is purged (0
+    /// keeps them).
+    pub deleted_retention_secs: u64,
+    /// Seconds entries of bucket change feeds are kept (0 keeps them).
+    pub changes_retention_secs: u64,
+    /// API groups rejected with 403 (see `middleware::feature_flags`).
+    pub disabled_apis: Vec<ApiGroup>,
+    /// Proxies whose `Forwarded` / `X-Forwarded-*` headers are trusted.
+    pub trusted_proxies: Vec<IpNetwork>,
+    /// The one header trusted proxies identify clients with.
+    pub forwarded_header: ForwardedHeader,
+    /// Per-target proxy overrides for outbound worker traffic.
+    pub outbound_proxy_rules: Vec<ProxyRule>,
+    /// Decode gzip/deflate uploads to identity before storing.
+    pub decode_content_encoding: bool,
+    /// Compute and record a SHA-256 of every stored payload.
+    pub compute_sha256: bool,
+    /// Check every full download against the stored checksums.
+    pub verify_read_checksums: bool,
+    /// Write one JSON access-log line per request.
+    pub access_log: bool,
+    /// Record requests refused by flags, the authorizer or admin auth.
+    pub log_denied_requests: bool,
+    /// Add caching-proxy friendly headers to GET/HEAD responses.
+    pub proxy_cache_headers: bool,
+    /// Largest object (bytes) served via mmap; 0 disables.
+    pub mmap_read_threshold: u64,
+    /// Memory (bytes) for the range-read block cache; 0 disables.
+    pub range_cache_bytes: u64,
+    /// Consecutive write failures that mark the volume read-only; 0 never
+    /// does.
+    pub volume_failure_threshold: u64,
+    /// Alert when a bucket stores more bytes than this; 0 disables.
+    pub alert_bucket_bytes: u64,
+    /// Alert when an owner's buckets store more bytes than this; 0 disables.
+    pub alert_account_bytes: u64,
+    /// Alert when free disk space drops below this many bytes; 0 disables.
+    pub alert_min_disk_free_bytes: u64,
+    /// Alert when this fraction of responses are 5xx; 0 disables.
+    pub alert_error_rate: f64,
+    /// Where alert transitions are POSTed.
+    pub alert_webhook: Option<Url>,
+    /// Count object reads for storage class analysis.
+    pub access_analytics: bool,
+    /// Key segments transfers are recorded under per prefix; 0 disables.
+    pub usage_prefix_depth: usize,
+    /// Store identical payloads once (content-addressed, hard-linked).
+    pub dedup: bool,
+    /// Bytes stored across all buckets before writes are refused; 0 disables.
+    pub quota_bytes: u64,
+    /// Live objects across all buckets before writes are refused; 0
+    /// disables.
+    pub quota_objects: u64,
+    /// Largest object in bytes; 0 disables.
+    pub max_object_size: u64,
+    /// Largest request body in bytes; 0 disables.
+    pub max_request_body: u64,
+    /// Requests per second each client address may send; 0 disables.
+    pub rate_limit_per_ip: u32,
+    /// Requests a client may send at once before the rate applies; 0 means
+    /// one second's worth.
+    pub rate_limit_burst: u32,
+    /// Object uploads handled at once; 0 disables.
+    pub max_concurrent_uploads: usize,
+    /// Smoke-test the server after binding and exit non-zero on failure.
+    pub self_test: bool,
+    /// Named configurations buckets can be created with.
+    pub bucket_templates: BucketTemplates,
+    /// Secret that signs and verifies content manifests.
+    pub manifest_signing_key: Option<ManifestKey>,
+    /// Secret that signs and verifies scoped session tokens.
+    pub session_token_key: Option<SessionKey>,
+    /// Secret the keys of buckets that encrypt their metadata derive from.
+    pub metadata_encryption_key: Option<MetadataKey>,
+    /// Token requests send in `x-debug-timing` to get a `Server-Timing`
+    /// breakdown.
+    pub debug_timing_token: Option<String>,
+    /// Token requests send in `x-debug-signature` to get the canonical
+    /// request and string-to-sign of a signature that does not match.
+    pub debug_signature_token: Option<String>,
+    /// Access keys streaming upload chunk signatures are verified with.
+    pub access_keys: Vec<S3Credentials>,
+    /// Remote S3 bucket lifecycle transitions move cold payloads to.
+    pub tier_url: Option<Url>,
+    /// Signing region of the remote tier.
+    pub tier_region: String,
+    /// Access key pair for the remote tier.
+    pub tier_credentials: Option<S3Credentials>,
+    /// Content scanner uploads to scanning buckets are checked by (see
+    /// `services::scanner`).
+    pub scanner: Option<ScannerEndpoint>,
+    /// Timeout for scanning a single payload, in seconds.
+    pub scanner_timeout_secs: u64,
+    /// External policy endpoint consulted for every request (see
+    /// `middleware::authorizer`).
+    pub authorizer: Option<AuthorizerEndpoint>,
+    /// Timeout for a single authorizer callout, in milliseconds.
+    pub authorizer_timeout_ms: u64,
+    /// Seconds an authorizer decision is cached (0 disables caching).
+    pub authorizer_cache_ttl_secs: u64,
+    /// OIDC issuer for admin API bearer tokens.
+    pub oidc_issuer: Option<Url>,
+    /// Expected `aud` claim of admin API tokens.
+    pub oidc_audience: Option<String>,
+    /// JWKS endpoint override (otherwise discovered from the issuer).
+    pub oidc_jwks_url: Option<Url>,
+    /// Token claim listing the user's groups.
+    pub oidc_groups_claim: String,
+    /// LDAP server for admin API basic-auth logins.
+    pub ldap_url: Option<String>,
+    /// Bind DN template containing `{user}`.
+    pub ldap_user_dn: Option<String>,
+    /// User attribute listing group DNs.
+    pub ldap_group_attribute: String,
+    /// Groups granted the full admin role.
+    pub admin_groups: Vec<String>,
+    /// Groups granted the read-only admin role.
+    pub readonly_groups: Vec<String>,
+    /// Accept scoped admin tokens on the admin API.
+    pub admin_tokens: bool,
+    /// Secondary instance that receives mirrored traffic.
+    pub shadow_url: Option<Url>,
+    /// Percentage of eligible requests mirrored to `shadow_url`.
+    pub shadow_percent: u8,
+    /// Mirror writes as well as reads.
+    pub shadow_writes: bool,
+}
+
+/// What the binary should do once configuration is loaded.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum RunMode {
+    /// Run the HTTP server (default).
+    Serve,
+    /// Apply database migrations and exit.
+    Migrate,
+    /// Dump bucket/object metadata as JSONL to the given path and exit.
+    ExportMetadata(PathBuf),
+    /// Load bucket/object metadata from a JSONL file and exit.
+    ImportMetadata(PathBuf),
+    /// Abort multipart uploads older than the TTL once and exit.
+    GcMultipart,
+    /// Reconcile payload files with metadata once and exit.
+    Fsck,
+    /// Create an admin token, print it and exit.
+    CreateAdminToken {
+        name: String,
+        scopes: Vec<AdminScope>,
+        ttl_secs: Option<u64>,
+    },
+    /// Print the admin tokens and exit.
+    ListAdminTokens,
+    /// Revoke an admin token and exit.
+    RevokeAdminToken(Uuid),
 }
 
 /// Command-line + environment configuration.
@@ -24,51 +221,782 @@ pub struct Args {
     #[arg(long)]
     pub port: Option<u16>,
 
+    /// PEM certificate chain to serve HTTPS with; needs `--tls-key`
+    /// (overrides OBJECT_STORE_TLS_CERT)
+    #[arg(long, value_name = "PATH")]
+    pub tls_cert: Option<PathBuf>,
+
+    /// PEM private key (PKCS#8, PKCS#1 or SEC1) of `--tls-cert` (overrides
+    /// OBJECT_STORE_TLS_KEY)
+    #[arg(long, value_name = "PATH")]
+    pub tls_key: Option<PathBuf>,
+
+    /// Seconds between checks of the TLS certificate and key for changes,
+    /// which are then loaded without a restart; 0 disables (overrides
+    /// OBJECT_STORE_TLS_RELOAD_SECS)
+    #[arg(long)]
+    pub tls_reload_secs: Option<u64>,
+
     /// Directory where objects are stored (overrides OBJECT_STORE_STORAGE_DIR)
     #[arg(long)]
     pub storage_dir: Option<String>,
 
+    /// Directory for temp files of payloads being written, on the same
+    /// filesystem as the storage directory; next to each payload when unset
+    /// (overrides OBJECT_STORE_STAGING_DIR)
+    #[arg(long)]
+    pub staging_dir: Option<String>,
+
+    /// Comma-separated `name=path` volumes besides the storage directory
+    /// that bucket placement policies can put payloads on (overrides
+    /// OBJECT_STORE_VOLUMES)
+    #[arg(long, value_delimiter = ',')]
+    pub volumes: Option<Vec<VolumeSpec>>,
+
+    /// Directory levels live payloads are sharded in below their bucket;
+    /// changing it migrates existing payloads in the background (overrides
+    /// OBJECT_STORE_SHARD_DEPTH)
+    #[arg(long)]
+    pub shard_depth: Option<usize>,
+
+    /// Escape key segments into file names Windows accepts too (`:`, `?`,
+    /// `*`, trailing dots, device names); on by default on Windows, and
+    /// changing it migrates existing payloads in the background (overrides
+    /// OBJECT_STORE_PORTABLE_FILENAMES)
+    #[arg(long)]
+    pub portable_filenames: bool,
+
     /// Database URL (overrides OBJECT_STORE_DATABASE_URL)
     #[arg(long)]
     pub database_url: Option<String>,
 
+    /// Seconds to keep overwritten payloads recoverable; 0 disables
+    /// (overrides OBJECT_STORE_OVERWRITE_RETENTION_SECS)
+    #[arg(long)]
+    pub overwrite_retention_secs: Option<u64>,
+
+    /// Seconds after initiation an unfinished multipart upload is aborted
+    /// and its parts deleted; 0 disables (overrides OBJECT_STORE_MULTIPART_TTL_SECS)
+    #[arg(long)]
+    pub multipart_ttl_secs: Option<u64>,
+
+    /// Seconds soft-deleted object rows are kept before they are purged for
+    /// good; 0 keeps them (overrides OBJECT_STORE_DELETED_RETENTION_SECS)
+    #[arg(long)]
+    pub deleted_retention_secs: Option<u64>,
+
+    /// Seconds entries of bucket change feeds (`GET /{bucket}?changes`) are
+    /// kept; 0 keeps them (overrides OBJECT_STORE_CHANGES_RETENTION_SECS)
+    #[arg(long)]
+    pub changes_retention_secs: Option<u64>,
+
+    /// Comma-separated API groups to disable, e.g. `bucket-delete,admin`
+    /// (overrides OBJECT_STORE_DISABLED_APIS)
+    #[arg(long, value_delimiter = ',')]
+    pub disabled_apis: Option<Vec<ApiGroup>>,
+
+    /// Comma-separated proxy addresses/CIDRs whose forwarding headers are
+    /// trusted (overrides OBJECT_STORE_TRUSTED_PROXIES)
+    #[arg(long, value_delimiter = ',')]
+    pub trusted_proxies: Option<Vec<IpNetwork>>,
+
+    /// Header the trusted proxies set: `x-forwarded-for` or `forwarded`;
+    /// the other is ignored (overrides OBJECT_STORE_FORWARDED_HEADER)
+    #[arg(long)]
+    pub forwarded_header: Option<ForwardedHeader>,
+
+    /// Comma-separated `host=proxy-url|direct` overrides for outbound worker
+    /// traffic (overrides OBJECT_STORE_OUTBOUND_PROXY_RULES)
+    #[arg(long, value_delimiter = ',')]
+    pub outbound_proxy_rules: Option<Vec<ProxyRule>>,
+
+    /// Decode gzip/deflate request bodies before storing instead of keeping
+    /// them encoded (overrides OBJECT_STORE_DECODE_CONTENT_ENCODING)
+    #[arg(long)]
+    pub decode_content_encoding: bool,
+
+    /// Compute a SHA-256 of every upload while it streams in and return it as
+    /// `x-amz-checksum-sha256` (overrides OBJECT_STORE_COMPUTE_SHA256)
+    #[arg(long)]
+    pub compute_sha256: bool,
+
+    /// Check every full download against the object's stored SHA-256 /
+    /// CRC32C, aborting it on a mismatch, instead of only those sent with
+    /// `x-amz-checksum-mode: ENABLED` (overrides
+    /// OBJECT_STORE_VERIFY_READ_CHECKSUMS)
+    #[arg(long)]
+    pub verify_read_checksums: bool,
+
+    /// Write one JSON line per request (request id, method, bucket, key,
+    /// status, bytes, duration, client) to the `access` log target
+    /// (overrides OBJECT_STORE_ACCESS_LOG)
+    #[arg(long)]
+    pub access_log: bool,
+
+    /// Record refused requests (who, what, why) in the `audit` log and at
+    /// `GET /admin/denials` (overrides OBJECT_STORE_LOG_DENIED_REQUESTS)
+    #[arg(long)]
+    pub log_denied_requests: bool,
+
+    /// Set Date/Vary/ETag/Cache-Control on GET/HEAD responses for caching
+    /// reverse proxies (overrides OBJECT_STORE_PROXY_CACHE_HEADERS)
+    #[arg(long)]
+    pub proxy_cache_headers: bool,
+
+    /// Serve objects up to this many bytes from a memory map instead of
+    /// buffered reads; 0 disables (overrides OBJECT_STORE_MMAP_READ_THRESHOLD)
+    #[arg(long)]
+    pub mmap_read_threshold: Option<u64>,
+
+    /// Bytes of memory for caching blocks of range reads of hot objects; 0
+    /// disables (overrides OBJECT_STORE_RANGE_CACHE_BYTES)
+    #[arg(long)]
+    pub range_cache_bytes: Option<u64>,
+
+    /// Consecutive disk write failures after which the volume is marked
+    /// read-only until `POST /admin/volumes/{volume}/reset`; 0 never marks it
+    /// (overrides OBJECT_STORE_VOLUME_FAILURE_THRESHOLD)
+    #[arg(long)]
+    pub volume_failure_threshold: Option<u64>,
+
+    /// Alert when a bucket stores more than this many bytes; 0 disables
+    /// (overrides OBJECT_STORE_ALERT_BUCKET_BYTES)
+    #[arg(long)]
+    pub alert_bucket_bytes: Option<u64>,
+
+    /// Alert when one owner's buckets store more than this many bytes; 0
+    /// disables (overrides OBJECT_STORE_ALERT_ACCOUNT_BYTES)
+    #[arg(long)]
+    pub alert_account_bytes: Option<u64>,
+
+    /// Alert when free space on the storage filesystem drops below this many
+    /// bytes; 0 disables (overrides OBJECT_STORE_ALERT_MIN_DISK_FREE_BYTES)
+    #[arg(long)]
+    pub alert_min_disk_free_bytes: Option<u64>,
+
+    /// Alert when more than this fraction (0..1) of responses are server
+    /// errors; 0 disables (overrides OBJECT_STORE_ALERT_ERROR_RATE)
+    #[arg(long)]
+    pub alert_error_rate: Option<f64>,
+
+    /// URL that alert transitions are POSTed to as JSON (overrides
+    /// OBJECT_STORE_ALERT_WEBHOOK)
+    #[arg(long)]
+    pub alert_webhook: Option<Url>,
+
+    /// Count object reads by object age so storage class analysis jobs can
+    /// recommend transitions (overrides OBJECT_STORE_ACCESS_ANALYTICS)
+    #[arg(long)]
+    pub access_analytics: bool,
+
+    /// Record bytes uploaded/downloaded per key prefix of this many
+    /// `/`-separated segments; 0 disables (overrides OBJECT_STORE_USAGE_PREFIX_DEPTH)
+    #[arg(long)]
+    pub usage_prefix_depth: Option<usize>,
+
+    /// Store identical payloads once and hard-link them from every key
+    /// (overrides OBJECT_STORE_DEDUP)
+    #[arg(long)]
+    pub dedup: bool,
+
+    /// Bytes all buckets together may store (noncurrent versions included)
+    /// before writes are refused with 403; 0 disables (overrides
+    /// OBJECT_STORE_QUOTA_BYTES)
+    #[arg(long)]
+    pub quota_bytes: Option<u64>,
+
+    /// Live objects all buckets together may hold before new keys are refused
+    /// with 403; 0 disables (overrides OBJECT_STORE_QUOTA_OBJECTS)
+    #[arg(long)]
+    pub quota_objects: Option<u64>,
+
+    /// Largest object in bytes a PUT, copy or multipart upload may store;
+    /// bigger uploads are cut off with 413 EntityTooLarge; 0 disables
+    /// (overrides OBJECT_STORE_MAX_OBJECT_SIZE)
+    #[arg(long)]
+    pub max_object_size: Option<u64>,
+
+    /// Largest request body in bytes; bigger requests are refused or cut off
+    /// with 413 EntityTooLarge; 0 disables (overrides
+    /// OBJECT_STORE_MAX_REQUEST_BODY)
+    #[arg(long)]
+    pub max_request_body: Option<u64>,
+
+    /// Requests per second each client address may send; faster clients
+    /// are answered 429 SlowDown; 0 disables (overrides
+    /// OBJECT_STORE_RATE_LIMIT_PER_IP)
+    #[arg(long)]
+    pub rate_limit_per_ip: Option<u32>,
+
+    /// Requests a client may send in a burst before the rate applies; 0
+    /// means one second's worth (overrides OBJECT_STORE_RATE_LIMIT_BURST)
+    #[arg(long)]
+    pub rate_limit_burst: Option<u32>,
+
+    /// Object uploads (PUT, UploadPart, copies, completions) handled at
+    /// once; more are answered 503 SlowDown; 0 disables (overrides
+    /// OBJECT_STORE_MAX_CONCURRENT_UPLOADS)
+    #[arg(long)]
+    pub max_concurrent_uploads: Option<usize>,
+
+    /// JSON file of named bucket templates that `PUT /{bucket}` can apply
+    /// (overrides OBJECT_STORE_BUCKET_TEMPLATES)
+    #[arg(long, value_name = "PATH")]
+    pub bucket_templates: Option<PathBuf>,
+
+    /// Secret (at least 16 bytes) for HMAC-signing content manifests;
+    /// manifests are disabled when unset (overrides
+    /// OBJECT_STORE_MANIFEST_SIGNING_KEY)
+    #[arg(long)]
+    pub manifest_signing_key: Option<ManifestKey>,
+
+    /// Secret (at least 16 bytes) for HMAC-signing session tokens issued by
+    /// `POST /admin/sessions`; tokens are refused when unset (overrides
+    /// OBJECT_STORE_SESSION_TOKEN_KEY)
+    #[arg(long)]
+    pub session_token_key: Option<SessionKey>,
+
+    /// Secret (at least 16 bytes) the per-bucket keys of buckets with
+    /// `encrypt_metadata` derive from; such buckets are refused when unset
+    /// (overrides OBJECT_STORE_METADATA_ENCRYPTION_KEY)
+    #[arg(long)]
+    pub metadata_encryption_key: Option<MetadataKey>,
+
+    /// Token (at least 16 bytes) that requests send in `x-debug-timing` to
+    /// get a `Server-Timing` breakdown of auth, db, disk and hash time;
+    /// disabled when unset (overrides OBJECT_STORE_DEBUG_TIMING_TOKEN)
+    #[arg(long)]
+    pub debug_timing_token: Option<String>,
+
+    /// Token (at least 16 bytes) that requests send in `x-debug-signature`
+    /// to have a SigV4 signature that does not match refused with the
+    /// server's canonical request and string-to-sign; disabled when unset
+    /// (overrides OBJECT_STORE_DEBUG_SIGNATURE_TOKEN)
+    #[arg(long)]
+    pub debug_signature_token: Option<String>,
+
+    /// Comma-separated `ACCESS_KEY:SECRET_KEY` pairs clients sign with; the
+    /// chunk signatures of streaming (aws-chunked) uploads are verified
+    /// against them (overrides OBJECT_STORE_ACCESS_KEYS)
+    #[arg(long, value_delimiter = ',')]
+    pub access_keys: Option<Vec<S3Credentials>>,
+
+    /// S3 endpoint and bucket, e.g. `https://minio:9000/cold`, that lifecycle
+    /// `Transition` rules move cold payloads to (overrides
+    /// OBJECT_STORE_TIER_URL)
+    #[arg(long)]
+    pub tier_url: Option<Url>,
+
+    /// Signing region of the remote tier, default `us-east-1` (overrides
+    /// OBJECT_STORE_TIER_REGION)
+    #[arg(long)]
+    pub tier_region: Option<String>,
+
+    /// `ACCESS_KEY:SECRET_KEY` for the remote tier (overrides
+    /// OBJECT_STORE_TIER_CREDENTIALS)
+    #[arg(long)]
+    pub tier_credentials: Option<S3Credentials>,
+
+    /// Content scanner as `clamd:HOST:PORT` or `http:URL` (overrides
+    /// OBJECT_STORE_SCANNER)
+    #[arg(long)]
+    pub scanner: Option<ScannerEndpoint>,
+
+    /// Seconds a single payload scan may take (overrides
+    /// OBJECT_STORE_SCANNER_TIMEOUT_SECS)
+    #[arg(long)]
+    pub scanner_timeout_secs: Option<u64>,
+
+    /// External authorizer as `http:URL` (webhook) or `opa:URL` (OPA Data
+    /// API) (overrides OBJECT_STORE_AUTHORIZER)
+    #[arg(long)]
+    pub authorizer: Option<AuthorizerEndpoint>,
+
+    /// Authorizer callout timeout in milliseconds (overrides
+    /// OBJECT_STORE_AUTHORIZER_TIMEOUT_MS)
+    #[arg(long)]
+    pub authorizer_timeout_ms: Option<u64>,
+
+    /// Seconds to cache authorizer decisions; 0 disables (overrides
+    /// OBJECT_STORE_AUTHORIZER_CACHE_TTL_SECS)
+    #[arg(long)]
+    pub authorizer_cache_ttl_secs: Option<u64>,
+
+    /// OIDC issuer URL for admin API tokens (overrides OBJECT_STORE_OIDC_ISSUER)
+    #[arg(long)]
+    pub oidc_issuer: Option<Url>,
+
+    /// Required `aud` of admin API tokens (overrides OBJECT_STORE_OIDC_AUDIENCE)
+    #[arg(long)]
+    pub oidc_audience: Option<String>,
+
+    /// JWKS URL; discovered from the issuer when unset (overrides
+    /// OBJECT_STORE_OIDC_JWKS_URL)
+    #[arg(long)]
+    pub oidc_jwks_url: Option<Url>,
+
+    /// Token claim holding group names, default `groups` (overrides
+    /// OBJECT_STORE_OIDC_GROUPS_CLAIM)
+    #[arg(long)]
+    pub oidc_groups_claim: Option<String>,
+
+    /// LDAP server URL for admin logins (overrides OBJECT_STORE_LDAP_URL)
+    #[arg(long)]
+    pub ldap_url: Option<String>,
+
+    /// Bind DN template with a `{user}` placeholder (overrides
+    /// OBJECT_STORE_LDAP_USER_DN)
+    #[arg(long)]
+    pub ldap_user_dn: Option<String>,
+
+    /// User attribute listing groups, default `memberOf` (overrides
+    /// OBJECT_STORE_LDAP_GROUP_ATTRIBUTE)
+    #[arg(long)]
+    pub ldap_group_attribute: Option<String>,
+
+    /// Comma-separated groups mapped to the admin role (overrides
+    /// OBJECT_STORE_ADMIN_GROUPS)
+    #[arg(long, value_delimiter = ',')]
+    pub admin_groups: Option<Vec<String>>,
+
+    /// Comma-separated groups mapped to the read-only admin role (overrides
+    /// OBJECT_STORE_READONLY_GROUPS)
+    #[arg(long, value_delimiter = ',')]
+    pub readonly_groups: Option<Vec<String>>,
+
+    /// Accept scoped admin tokens (see --create-admin-token) as bearer
+    /// tokens on the admin API, which then requires authentication even
+    /// without OIDC or LDAP (overrides OBJECT_STORE_ADMIN_TOKENS)
+    #[arg(long)]
+    pub admin_tokens: bool,
+
+    /// Base URL of a secondary instance to mirror traffic to (overrides
+    /// OBJECT_STORE_SHADOW_URL)
+    #[arg(long)]
+    pub shadow_url: Option<Url>,
+
+    /// Percentage of reads (and writes, if enabled) to mirror, default 100
+    /// (overrides OBJECT_STORE_SHADOW_PERCENT)
+    #[arg(long)]
+    pub shadow_percent: Option<u8>,
+
+    /// Also mirror PUT/POST/DELETE requests (overrides OBJECT_STORE_SHADOW_WRITES)
+    #[arg(long)]
+    pub shadow_writes: bool,
+
     /// Run migrations and exit
     #[arg(long)]
     pub migrate: bool,
+
+    /// Export all bucket/object metadata (no payloads) as JSONL and exit
+    #[arg(long, value_name = "PATH", conflicts_with_all = ["migrate", "import_metadata"])]
+    pub export_metadata: Option<PathBuf>,
+
+    /// Import bucket/object metadata from a JSONL export and exit
+    #[arg(long, value_name = "PATH", conflicts_with = "migrate")]
+    pub import_metadata: Option<PathBuf>,
+
+    /// After binding, create a bucket, write, read and delete an object
+    /// through the server and exit non-zero if that fails (overrides
+    /// OBJECT_STORE_SELF_TEST)
+    #[arg(long)]
+    pub self_test: bool,
+
+    /// Abort multipart uploads older than the multipart TTL and exit
+    #[arg(long, conflicts_with_all = ["migrate", "export_metadata", "import_metadata"])]
+    pub gc_multipart: bool,
+
+    /// Remove stale temp files, report missing and orphaned payloads and exit
+    #[arg(long, conflicts_with_all = ["migrate", "export_metadata", "import_metadata", "gc_multipart"])]
+    pub fsck: bool,
+
+    /// Create an admin API token with this name, print it (with its secret,
+    /// shown only this once) as JSON and exit
+    #[arg(
+        long,
+        value_name = "NAME",
+        conflicts_with_all = ["migrate", "export_metadata", "import_metadata", "gc_multipart", "fsck"]
+    )]
+    pub create_admin_token: Option<String>,
+
+    /// Comma-separated scopes of the created token: `admin`, `read-only`,
+    /// `metrics`, `backup`, `sessions`
+    #[arg(long, value_delimiter = ',', requires = "create_admin_token")]
+    pub admin_token_scopes: Option<Vec<AdminScope>>,
+
+    /// Seconds the created token stays valid; without it the token never
+    /// expires
+    #[arg(long, requires = "create_admin_token")]
+    pub admin_token_ttl_secs: Option<u64>,
+
+    /// Print the admin API tokens (without secrets) as JSON and exit
+    #[arg(
+        long,
+        conflicts_with_all = ["migrate", "export_metadata", "import_metadata", "gc_multipart", "fsck", "create_admin_token"]
+    )]
+    pub list_admin_tokens: bool,
+
+    /// Revoke the admin API token with this id and exit
+    #[arg(
+        long,
+        value_name = "ID",
+        conflicts_with_all = ["migrate", "export_metadata", "import_metadata", "gc_multipart", "fsck", "create_admin_token", "list_admin_tokens"]
+    )]
+    pub revoke_admin_token: Option<Uuid>,
 }
 
 impl AppConfig {
-    /// Parse environment variables + CLI args into AppConfig and migrate flag.
-    pub fn from_env_and_args() -> Result<(Self, bool)> {
+    /// Parse environment variables + CLI args into AppConfig and run mode.
+    pub fn from_env_and_args() -> Result<(Self, RunMode)> {
         // Parse CLI once
         let args = Args::parse();
 
         // --- Environment fallback ---
         let env_host = env::var("OBJECT_STORE_HOST").unwrap_or_else(|_| "0.0.0.0".into());
-        let env_port = match env::var("OBJECT_STORE_PORT") {
-            Ok(value) => value
-                .parse::<u16>()
-                .with_context(|| format!("parsing OBJECT_STORE_PORT value `{}`", value))?,
-            Err(env::VarError::NotPresent) => 3000,
-            Err(err) => return Err(err).context("reading OBJECT_STORE_PORT"),
-        };
+        let env_port = env_parse("OBJECT_STORE_PORT", 3000u16)?;
+        let env_tls_cert = env_opt::<PathBuf>("OBJECT_STORE_TLS_CERT")?;
+        let env_tls_key = env_opt::<PathBuf>("OBJECT_STORE_TLS_KEY")?;
+        let env_tls_reload = env_parse("OBJECT_STORE_TLS_RELOAD_SECS", 0u64)?;
         let env_storage =
             env::var("OBJECT_STORE_STORAGE_DIR").unwrap_or_else(|_| "./data/objects".into());
+        let env_staging = env::var("OBJECT_STORE_STAGING_DIR").ok();
+        let env_volumes = env_list::<VolumeSpec>("OBJECT_STORE_VOLUMES")?;
+        let env_shard_depth = env_parse("OBJECT_STORE_SHARD_DEPTH", DEFAULT_SHARD_DEPTH)?;
+        let env_portable = env_parse("OBJECT_STORE_PORTABLE_FILENAMES", cfg!(windows))?;
         let env_db = env::var("OBJECT_STORE_DATABASE_URL")
             .unwrap_or_else(|_| "sqlite://./data/meta/object_store.db".into());
+        let env_retention = env_parse("OBJECT_STORE_OVERWRITE_RETENTION_SECS", 0u64)?;
+        let env_multipart_ttl = env_parse("OBJECT_STORE_MULTIPART_TTL_SECS", 7 * 24 * 3600u64)?;
+        let env_deleted_retention =
+            env_parse("OBJECT_STORE_DELETED_RETENTION_SECS", 7 * 24 * 3600u64)?;
+        let env_changes_retention =
+            env_parse("OBJECT_STORE_CHANGES_RETENTION_SECS", 7 * 24 * 3600u64)?;
+        let env_disabled = env_list::<ApiGroup>("OBJECT_STORE_DISABLED_APIS")?;
+        let env_proxies = env_list::<IpNetwork>("OBJECT_STORE_TRUSTED_PROXIES")?;
+        let env_forwarded =
+            env_opt::<ForwardedHeader>("OBJECT_STORE_FORWARDED_HEADER")?.unwrap_or_default();
+        let env_proxy_rules = env_list::<ProxyRule>("OBJECT_STORE_OUTBOUND_PROXY_RULES")?;
+        let env_decode = env_parse("OBJECT_STORE_DECODE_CONTENT_ENCODING", false)?;
+        let env_sha256 = env_parse("OBJECT_STORE_COMPUTE_SHA256", false)?;
+        let env_verify_reads = env_parse("OBJECT_STORE_VERIFY_READ_CHECKSUMS", false)?;
+        let env_access_log = env_parse("OBJECT_STORE_ACCESS_LOG", false)?;
+        let env_log_denied = env_parse("OBJECT_STORE_LOG_DENIED_REQUESTS", false)?;
+        let env_proxy_cache = env_parse("OBJECT_STORE_PROXY_CACHE_HEADERS", false)?;
+        let env_mmap = env_parse("OBJECT_STORE_MMAP_READ_THRESHOLD", 0u64)?;
+        let env_range_cache = env_parse("OBJECT_STORE_RANGE_CACHE_BYTES", 0u64)?;
+        let env_volume_failures = env_parse("OBJECT_STORE_VOLUME_FAILURE_THRESHOLD", 5u64)?;
+        let env_alert_bucket = env_parse("OBJECT_STORE_ALERT_BUCKET_BYTES", 0u64)?;
+        let env_alert_account = env_parse("OBJECT_STORE_ALERT_ACCOUNT_BYTES", 0u64)?;
+        let env_alert_disk = env_parse("OBJECT_STORE_ALERT_MIN_DISK_FREE_BYTES", 0u64)?;
+        let env_alert_errors = env_parse("OBJECT_STORE_ALERT_ERROR_RATE", 0f64)?;
+        let env_alert_webhook = env_opt::<Url>("OBJECT_STORE_ALERT_WEBHOOK")?;
+        let env_access_analytics = env_parse("OBJECT_STORE_ACCESS_ANALYTICS", false)?;
+        let env_usage_depth = env_parse("OBJECT_STORE_USAGE_PREFIX_DEPTH", 0usize)?;
+        let env_dedup = env_parse("OBJECT_STORE_DEDUP", false)?;
+        let env_quota_bytes = env_parse("OBJECT_STORE_QUOTA_BYTES", 0u64)?;
+        let env_quota_objects = env_parse("OBJECT_STORE_QUOTA_OBJECTS", 0u64)?;
+        let env_max_object = env_parse("OBJECT_STORE_MAX_OBJECT_SIZE", 0u64)?;
+        let env_max_body = env_parse("OBJECT_STORE_MAX_REQUEST_BODY", 0u64)?;
+        let env_rate_limit = env_parse("OBJECT_STORE_RATE_LIMIT_PER_IP", 0u32)?;
+        let env_rate_burst = env_parse("OBJECT_STORE_RATE_LIMIT_BURST", 0u32)?;
+        let env_max_uploads = env_parse("OBJECT_STORE_MAX_CONCURRENT_UPLOADS", 0usize)?;
+        let env_self_test = env_parse("OBJECT_STORE_SELF_TEST", false)?;
+        let env_templates = env_opt::<PathBuf>("OBJECT_STORE_BUCKET_TEMPLATES")?;
+        let env_manifest_key = env_opt::<ManifestKey>("OBJECT_STORE_MANIFEST_SIGNING_KEY")?;
+        let env_session_key = env_opt::<SessionKey>("OBJECT_STORE_SESSION_TOKEN_KEY")?;
+        let env_metadata_key = env_opt::<MetadataKey>("OBJECT_STORE_METADATA_ENCRYPTION_KEY")?;
+        let env_debug_timing_token = env_opt::<String>("OBJECT_STORE_DEBUG_TIMING_TOKEN")?;
+        let env_debug_signature_token = env_opt::<String>("OBJECT_STORE_DEBUG_SIGNATURE_TOKEN")?;
+        let env_access_keys = env_list::<S3Credentials>("OBJECT_STORE_ACCESS_KEYS")?;
+        let env_tier_url = env_opt::<Url>("OBJECT_STORE_TIER_URL")?;
+        let env_tier_region =
+            env::var("OBJECT_STORE_TIER_REGION").unwrap_or_else(|_| "us-east-1".into());
+        let env_tier_credentials = env_opt::<S3Credentials>("OBJECT_STORE_TIER_CREDENTIALS")?;
+        let env_scanner = env_opt::<ScannerEndpoint>("OBJECT_STORE_SCANNER")?;
+        let env_scanner_timeout = env_parse("OBJECT_STORE_SCANNER_TIMEOUT_SECS", 60u64)?;
+        let env_authorizer = env_opt::<AuthorizerEndpoint>("OBJECT_STORE_AUTHORIZER")?;
+        let env_authz_timeout = env_parse("OBJECT_STORE_AUTHORIZER_TIMEOUT_MS", 2000u64)?;
+        let env_authz_ttl = env_parse("OBJECT_STORE_AUTHORIZER_CACHE_TTL_SECS", 5u64)?;
+        let env_oidc_issuer = env_opt::<Url>("OBJECT_STORE_OIDC_ISSUER")?;
+        let env_oidc_audience = env::var("OBJECT_STORE_OIDC_AUDIENCE").ok();
+        let env_oidc_jwks = env_opt::<Url>("OBJECT_STORE_OIDC_JWKS_URL")?;
+        let env_oidc_groups =
+            env::var("OBJECT_STORE_OIDC_GROUPS_CLAIM").unwrap_or_else(|_| "groups".into());
+        let env_ldap_url = env::var("OBJECT_STORE_LDAP_URL").ok();
+        let env_ldap_dn = env::var("OBJECT_STORE_LDAP_USER_DN").ok();
+        let env_ldap_groups =
+            env::var("OBJECT_STORE_LDAP_GROUP_ATTRIBUTE").unwrap_or_else(|_| "memberOf".into());
+        let env_admin_groups = env_list::<String>("OBJECT_STORE_ADMIN_GROUPS")?;
+        let env_readonly_groups = env_list::<String>("OBJECT_STORE_READONLY_GROUPS")?;
+        let env_admin_tokens = env_parse("OBJECT_STORE_ADMIN_TOKENS", false)?;
+        let env_shadow_url = env_opt::<Url>("OBJECT_STORE_SHADOW_URL")?;
+        let env_shadow_percent = env_parse("OBJECT_STORE_SHADOW_PERCENT", 100u8)?;
+        let env_shadow_writes = env_parse("OBJECT_STORE_SHADOW_WRITES", false)?;
 
         // --- Merge ---
         let cfg = Self {
             host: args.host.unwrap_or(env_host),
             port: args.port.unwrap_or(env_port),
+            tls_cert: args.tls_cert.or(env_tls_cert),
+            tls_key: args.tls_key.or(env_tls_key),
+            tls_reload_secs: args.tls_reload_secs.unwrap_or(env_tls_reload),
             storage_dir: args.storage_dir.unwrap_or(env_storage),
+            staging_dir: args
+                .staging_dir
+                .or(env_staging)
+                .filter(|dir| !dir.is_empty()),
+            volumes: args.volumes.unwrap_or(env_volumes),
+            shard_depth: args.shard_depth.unwrap_or(env_shard_depth),
+            portable_filenames: args.portable_filenames || env_portable,
             database_url: args.database_url.unwrap_or(env_db),
+            overwrite_retention_secs: args.overwrite_retention_secs.unwrap_or(env_retention),
+            multipart_ttl_secs: args.multipart_ttl_secs.unwrap_or(env_multipart_ttl),
+            deleted_retention_secs: args.deleted_retention_secs.unwrap_or(env_deleted_retention),
+            changes_retention_secs: args.changes_retention_secs.unwrap_or(env_changes_retention),
+            disabled_apis: args.disabled_apis.unwrap_or(env_disabled),
+            trusted_proxies: args.trusted_proxies.unwrap_or(env_proxies),
+            forwarded_header: args.forwarded_header.unwrap_or(env_forwarded),
+            outbound_proxy_rules: args.outbound_proxy_rules.unwrap_or(env_proxy_rules),
+            decode_content_encoding: args.decode_content_encoding || env_decode,
+            compute_sha256: args.compute_sha256 || env_sha256,
+            verify_read_checksums: args.verify_read_checksums || env_verify_reads,
+            access_log: args.access_log || env_access_log,
+            log_denied_requests: args.log_denied_requests || env_log_denied,
+            proxy_cache_headers: args.proxy_cache_headers || env_proxy_cache,
+            mmap_read_threshold: args.mmap_read_threshold.unwrap_or(env_mmap),
+            range_cache_bytes: args.range_cache_bytes.unwrap_or(env_range_cache),
+            volume_failure_threshold: args.volume_failure_threshold.unwrap_or(env_volume_failures),
+            alert_bucket_bytes: args.alert_bucket_bytes.unwrap_or(env_alert_bucket),
+            alert_account_bytes: args.alert_account_bytes.unwrap_or(env_alert_account),
+            alert_min_disk_free_bytes: args.alert_min_disk_free_bytes.unwrap_or(env_alert_disk),
+            alert_error_rate: args.alert_error_rate.unwrap_or(env_alert_errors),
+            alert_webhook: args.alert_webhook.or(env_alert_webhook),
+            access_analytics: args.access_analytics || env_access_analytics,
+            usage_prefix_depth: args.usage_prefix_depth.unwrap_or(env_usage_depth),
+            dedup: args.dedup || env_dedup,
+            quota_bytes: args.quota_bytes.unwrap_or(env_quota_bytes),
+            quota_objects: args.quota_objects.unwrap_or(env_quota_objects),
+            max_object_size: args.max_object_size.unwrap_or(env_max_object),
+            max_request_body: args.max_request_body.unwrap_or(env_max_body),
+            rate_limit_per_ip: args.rate_limit_per_ip.unwrap_or(env_rate_limit),
+            rate_limit_burst: args.rate_limit_burst.unwrap_or(env_rate_burst),
+            max_concurrent_uploads: args.max_concurrent_uploads.unwrap_or(env_max_uploads),
+            self_test: args.self_test || env_self_test,
+            bucket_templates: match args.bucket_templates.or(env_templates) {
+                Some(path) => load_bucket_templates(&path)?,
+                None => BucketTemplates::default(),
+            },
+            manifest_signing_key: args.manifest_signing_key.or(env_manifest_key),
+            session_token_key: args.session_token_key.or(env_session_key),
+            metadata_encryption_key: args.metadata_encryption_key.or(env_metadata_key),
+            debug_timing_token: args.debug_timing_token.or(env_debug_timing_token),
+            debug_signature_token: args.debug_signature_token.or(env_debug_signature_token),
+            access_keys: args.access_keys.unwrap_or(env_access_keys),
+            tier_url: args.tier_url.or(env_tier_url),
+            tier_region: args.tier_region.unwrap_or(env_tier_region),
+            tier_credentials: args.tier_credentials.or(env_tier_credentials),
+            scanner: args.scanner.or(env_scanner),
+            scanner_timeout_secs: args.scanner_timeout_secs.unwrap_or(env_scanner_timeout),
+            authorizer: args.authorizer.or(env_authorizer),
+            authorizer_timeout_ms: args.authorizer_timeout_ms.unwrap_or(env_authz_timeout),
+            authorizer_cache_ttl_secs: args.authorizer_cache_ttl_secs.unwrap_or(env_authz_ttl),
+            oidc_issuer: args.oidc_issuer.or(env_oidc_issuer),
+            oidc_audience: args.oidc_audience.or(env_oidc_audience),
+            oidc_jwks_url: args.oidc_jwks_url.or(env_oidc_jwks),
+            oidc_groups_claim: args.oidc_groups_claim.unwrap_or(env_oidc_groups),
+            ldap_url: args.ldap_url.or(env_ldap_url),
+            ldap_user_dn: args.ldap_user_dn.or(env_ldap_dn),
+            ldap_group_attribute: args.ldap_group_attribute.unwrap_or(env_ldap_groups),
+            admin_groups: args.admin_groups.unwrap_or(env_admin_groups),
+            readonly_groups: args.readonly_groups.unwrap_or(env_readonly_groups),
+            admin_tokens: args.admin_tokens || env_admin_tokens,
+            shadow_url: args.shadow_url.or(env_shadow_url),
+            shadow_percent: args.shadow_percent.unwrap_or(env_shadow_percent),
+            shadow_writes: args.shadow_writes || env_shadow_writes,
+        };
+
+        if !(1..=MAX_SHARD_DEPTH).contains(&cfg.shard_depth) {
+            return Err(anyhow!(
+                "shard depth must be between 1 and {}, got {}",
+                MAX_SHARD_DEPTH,
+                cfg.shard_depth
+            ));
+        }
+        if cfg.usage_prefix_depth > MAX_USAGE_PREFIX_DEPTH {
+            return Err(anyhow!(
+                "usage prefix depth must be at most {}, got {}",
+                MAX_USAGE_PREFIX_DEPTH,
+                cfg.usage_prefix_depth
+            ));
+        }
+        if cfg.shadow_percent > 100 {
+            return Err(anyhow!(
+                "shadow percentage must be between 0 and 100, got {}",
+                cfg.shadow_percent
+            ));
+        }
+        if !(0.0..=1.0).contains(&cfg.alert_error_rate) {
+            return Err(anyhow!(
+                "alert error rate must be a fraction between 0 and 1, got {}",
+                cfg.alert_error_rate
+            ));
+        }
+
+        for (idx, volume) in cfg.volumes.iter().enumerate() {
+            if cfg.volumes[..idx]
+                .iter()
+                .any(|other| other.name == volume.name)
+            {
+                return Err(anyhow!("volume `{}` is configured twice", volume.name));
+            }
+        }
+        if cfg.oidc_issuer.is_some() != cfg.oidc_audience.is_some() {
+            return Err(anyhow!(
+                "OIDC needs both an issuer and an audience (OBJECT_STORE_OIDC_ISSUER / OBJECT_STORE_OIDC_AUDIENCE)"
+            ));
+        }
+        if cfg.ldap_url.is_some() != cfg.ldap_user_dn.is_some() {
+            return Err(anyhow!(
+                "LDAP needs both a server URL and a user DN template (OBJECT_STORE_LDAP_URL / OBJECT_STORE_LDAP_USER_DN)"
+            ));
+        }
+        if cfg.tls_cert.is_some() != cfg.tls_key.is_some() {
+            return Err(anyhow!(
+                "TLS needs both a certificate and a private key (OBJECT_STORE_TLS_CERT / OBJECT_STORE_TLS_KEY)"
+            ));
+        }
+        if cfg.tier_url.is_some() != cfg.tier_credentials.is_some() {
+            return Err(anyhow!(
+                "the remote tier needs both a URL and credentials (OBJECT_STORE_TIER_URL / OBJECT_STORE_TIER_CREDENTIALS)"
+            ));
+        }
+        if cfg
+            .debug_timing_token
+            .as_ref()
+            .is_some_and(|token| token.len() < 16)
+        {
+            return Err(anyhow!("the debug timing token must be at least 16 bytes"));
+        }
+        if cfg
+            .debug_signature_token
+            .as_ref()
+            .is_some_and(|token| token.len() < 16)
+        {
+            return Err(anyhow!(
+                "the debug signature token must be at least 16 bytes"
+            ));
+        }
+        if cfg.scanner_timeout_secs == 0 {
+            return Err(anyhow!("the scanner timeout must be at least one second"));
+        }
+        if let Some(template) = &cfg.ldap_user_dn
+            && !template.contains("{user}")
+        {
+            return Err(anyhow!(
+                "LDAP user DN template `{}` must contain `{{user}}`",
+                template
+            ));
+        }
+
+        let mode = if args.migrate {
+            RunMode::Migrate
+        } else if let Some(path) = args.export_metadata {
+            RunMode::ExportMetadata(path)
+        } else if let Some(path) = args.import_metadata {
+            RunMode::ImportMetadata(path)
+        } else if args.gc_multipart {
+            if cfg.multipart_ttl_secs == 0 {
+                return Err(anyhow!(
+                    "--gc-multipart needs a multipart TTL above 0 (OBJECT_STORE_MULTIPART_TTL_SECS)"
+                ));
+            }
+            RunMode::GcMultipart
+        } else if args.fsck {
+            RunMode::Fsck
+        } else if let Some(name) = args.create_admin_token {
+            let scopes = args.admin_token_scopes.unwrap_or_default();
+            if name.trim().is_empty() || scopes.is_empty() {
+                return Err(anyhow!(
+                    "--create-admin-token needs a name and --admin-token-scopes"
+                ));
+            }
+            if args.admin_token_ttl_secs == Some(0) {
+                return Err(anyhow!("--admin-token-ttl-secs must be above 0"));
+            }
+            RunMode::CreateAdminToken {
+                name,
+                scopes,
+                ttl_secs: args.admin_token_ttl_secs,
+            }
+        } else if args.list_admin_tokens {
+            RunMode::ListAdminTokens
+        } else if let Some(id) = args.revoke_admin_token {
+            RunMode::RevokeAdminToken(id)
+        } else {
+            RunMode::Serve
         };
 
-        Ok((cfg, args.migrate))
+        Ok((cfg, mode))
     }
 
     pub fn addr(&self) -> String {
         format!("{}:{}", self.host, self.port)
     }
 }
+
+/// Read and validate the bucket templates file.
+fn load_bucket_templates(path: &Path) -> Result<BucketTemplates> {
+    let text = std::fs::read_to_string(path)
+        .with_context(|| format!("reading bucket templates {}", path.display()))?;
+    BucketTemplates::from_json(&text)
+        .map_err(|err| anyhow!("bucket templates {}: {}", path.display(), err))
+}
+
+/// Read and parse an environment variable, falling back to `default` when unset.
+fn env_parse<T>(name: &str, default: T) -> Result<T>
+where
+    T: FromStr,
+    T::Err: std::error::Error + Send + Sync + 'static,
+{
+    match env::var(name) {
+        Ok(value) => value
+            .parse::<T>()
+            .with_context(|| format!("parsing {} value `{}`", name, value)),
+        Err(env::VarError::NotPresent) => Ok(default),
+        Err(err) => Err(err).with_context(|| format!("reading {}", name)),
+    }
+}
+
+/// Read a comma-separated list from an environment variable (empty when unset).
+fn env_list<T>(name: &str) -> Result<Vec<T>>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    match env::var(name) {
+        Ok(value) => value
+            .split(',')
+            .filter(|item| !item.trim().is_empty())
+            .map(|item| item.trim().parse::<T>().map_err(|e| anyhow!("{}", e)))
+            .collect::<Result<Vec<_>>>()
+            .with_context(|| format!("parsing {}", name)),
+        Err(_) => Ok(Vec::new()),
+    }
+}
+
+/// Read an optional environment variable (`None` when unset or blank).
+fn env_opt<T>(name: &str) -> Result<Option<T>>
+where
+    T: FromStr,
+    T::Err: fmt::Display,
+{
+    match env::var(name) {
+        Ok(value) if !value.trim().is_empty() => value
+            .trim()
+            .parse::<T>()
+            .map(Some)
+            .map_err(|e| anyhow!("{}", e))
+            .with_context(|| format!("parsing {}", name)),
+        _ => Ok(None),
+    }
+}
@@ -62,13 +62,67 @@ impl From<anyhow::Error> for AppError {
 impl From<StorageError> for AppError {
     fn from(err: StorageError) -> Self {
         match err {
-            StorageError::BucketNotFound(_) | StorageError::ObjectNotFound { .. } => {
-                AppError::not_found(err.to_string())
+            StorageError::BucketNotFound(_)
+            | StorageError::ObjectNotFound { .. }
+            | StorageError::SnapshotNotFound { .. }
+            | StorageError::JobNotFound(_)
+            | StorageError::NoSuchUpload(_)
+            | StorageError::NoSuchLifecycleConfiguration(_)
+            | StorageError::NoSuchObjectLockConfiguration(_)
+            | StorageError::NoSuchReplicationConfiguration(_)
+            | StorageError::NoSuchVersion { .. } => AppError::not_found(err.to_string()),
+            StorageError::VersionIsDeleteMarker { .. } => {
+                AppError::new(StatusCode::METHOD_NOT_ALLOWED, err.to_string())
             }
-            StorageError::BucketAlreadyExists(_) => {
+            StorageError::BucketAlreadyExists(_)
+            | StorageError::BucketNotEmpty(_)
+            | StorageError::InvalidBucketState(_) => {
                 AppError::new(StatusCode::CONFLICT, err.to_string())
             }
-            StorageError::InvalidObjectKey => {
+            StorageError::BucketReadOnly(_)
+            | StorageError::AclNotAllowed(_)
+            | StorageError::ObjectLocked { .. }
+            | StorageError::CustomerKeyMismatch(_)
+            | StorageError::QuotaExceeded(_)
+            | StorageError::ContentRejected { .. }
+            | StorageError::SignatureDoesNotMatch(_)
+            | StorageError::ObjectQuarantined { .. } => {
+                AppError::new(StatusCode::FORBIDDEN, err.to_string())
+            }
+            StorageError::EntityTooLarge(_) => {
+                AppError::new(StatusCode::PAYLOAD_TOO_LARGE, err.to_string())
+            }
+            StorageError::ChangeCursorExpired { .. } => {
+                AppError::new(StatusCode::GONE, err.to_string())
+            }
+            StorageError::VolumeReadOnly(_) => {
+                AppError::new(StatusCode::SERVICE_UNAVAILABLE, err.to_string())
+            }
+            StorageError::InvalidObjectKey
+            | StorageError::InvalidPrefix(_)
+            | StorageError::InvalidContent(_)
+            | StorageError::InvalidPart(_)
+            | StorageError::InvalidSnapshotPolicy(_)
+            | StorageError::InvalidLifecycle(_)
+            | StorageError::InvalidTokenTtl(_)
+            | StorageError::InvalidTag(_)
+            | StorageError::InvalidMetadata(_)
+            | StorageError::InvalidCopy(_)
+            | StorageError::InvalidAcl(_)
+            | StorageError::InvalidDigest(_)
+            | StorageError::InvalidObjectLock(_)
+            | StorageError::InvalidCustomerKey(_)
+            | StorageError::BadDigest(_)
+            | StorageError::ContentSha256Mismatch(_)
+            | StorageError::KeyNotAllowed { .. }
+            | StorageError::InvalidNamingPolicy(_)
+            | StorageError::InvalidAutoTagging(_)
+            | StorageError::InvalidNotification(_)
+            | StorageError::InvalidPlacement(_)
+            | StorageError::InvalidPublisher(_)
+            | StorageError::InvalidReplication(_)
+            | StorageError::InvalidSearch(_)
+            | StorageError::InvalidChangeCursor(_) => {
                 AppError::new(StatusCode::BAD_REQUEST, err.to_string())
             }
             StorageError::InvalidBucketName { .. } => {
@@ -77,7 +131,11 @@ impl From<StorageError> for AppError {
             StorageError::UnsupportedRegion(_) => {
                 AppError::new(StatusCode::BAD_REQUEST, err.to_string())
             }
-            StorageError::Sqlx(_) | StorageError::Io(_) => AppError::internal(err.to_string()),
+            StorageError::Sqlx(_)
+            | StorageError::Io(_)
+            | StorageError::CorruptPayload { .. }
+            | StorageError::SealedMetadata(_)
+            | StorageError::AssemblyFailed { .. } => AppError::internal(err.to_string()),
         }
     }
 }
@@ -0,0 +1,1008 @@
+//! Admin API handlers (JSON).
+//!
+//! Everything under `/admin` sits behind `middleware::admin_auth` when an
+//! identity provider is configured.
+
+use crate::{
+    errors::AppError,
+    middleware::{
+        admin_auth::AdminIdentity,
+        denial_log::{DenialLog, DenialReport},
+        feature_flags::FeatureFlags,
+        slo_stats::{DEFAULT_SLO_MINUTES, SloReport, SloStats},
+    },
+    models::{
+        bucket::Bucket,
+        job::Job,
+        snapshot::{BucketSnapshot, SnapshotPolicy},
+    },
+    services::{
+        auto_tagging::AutoTagging,
+        bucket_template::BucketTemplate,
+        limits::{AdminLimits, ServerLimits},
+        log_filter::LogFilter,
+        manifest::{Manifest, ManifestVerification},
+        naming_policy::NamingPolicy,
+        object_search::{MAX_SEARCH_KEYS, ObjectSearch, ObjectSearchPage},
+        placement::PlacementPolicy,
+        prefix_usage::BucketUsage,
+        publishers::PublisherConfig,
+        purge::PurgeSummary,
+        quota::{BucketQuota, Quota},
+        replication::ReplicationConfig,
+        scanner::{QuarantinedObject, ScanMode},
+        session::{SessionRequest, SessionToken},
+        snapshot::{RestoreSummary, SnapshotTrigger},
+        storage_service::StorageService,
+        upload_progress::{AbortedUploads, UploadProgress},
+        volume::{DEFAULT_VOLUME, VolumeStatus},
+    },
+};
+use axum::{
+    Extension, Json,
+    extract::{Path, Query, State},
+    http::{HeaderValue, StatusCode, header},
+    response::{
+        IntoResponse, Response,
+        sse::{Event, KeepAlive, Sse},
+    },
+};
+use futures::{Stream, stream};
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, convert::Infallible};
+use tokio::sync::broadcast::error::RecvError;
+use uuid::Uuid;
+
+/// Body of `PATCH /admin/buckets/{bucket}`; absent fields are left unchanged.
+#[derive(Debug, Deserialize)]
+pub struct BucketSettingsPatch {
+    pub read_only: Option<bool>,
+    /// `Cache-Control` sent on GET/HEAD; `""` clears it.
+    pub cache_control: Option<String>,
+    /// `Expires` offset in seconds for GET/HEAD; `0` clears it.
+    pub expires_secs: Option<u32>,
+    /// Canned ACL for uploads without `x-amz-acl`; `""` clears it.
+    pub default_acl: Option<String>,
+    /// Require uploads to grant `bucket-owner-full-control`.
+    pub enforce_bucket_owner_full_control: Option<bool>,
+    /// Scan uploads `inline` or `async`; `""` stops scanning.
+    pub scan_uploads: Option<String>,
+    /// Store user metadata and tag values encrypted.
+    pub encrypt_metadata: Option<bool>,
+}
+
+/// Body of `PUT /admin/buckets/{bucket}/snapshot-policy`.
+#[derive(Debug, Deserialize)]
+pub struct SnapshotPolicyReq {
+    /// Cron expression or shorthand such as `@daily`.
+    pub schedule: String,
+    #[serde(default = "default_keep_daily")]
+    pub keep_daily: u32,
+    #[serde(default = "default_keep_weekly")]
+    pub keep_weekly: u32,
+}
+
+fn default_keep_daily() -> u32 {
+    7
+}
+
+fn default_keep_weekly() -> u32 {
+    4
+}
+
+/// `GET /admin/whoami`
+///
+/// Echo the caller's identity and mapped role, so operators can check their
+/// OIDC/LDAP group mapping. Without an identity provider the caller is
+/// reported as anonymous.
+pub async fn whoami(identity: Option<Extension<AdminIdentity>>) -> impl IntoResponse {
+    match identity {
+        Some(Extension(identity)) => Json(WhoAmIResponse {
+            authenticated: true,
+            identity: Some(identity),
+        }),
+        None => Json(WhoAmIResponse {
+            authenticated: false,
+            identity: None,
+        }),
+    }
+}
+
+/// `GET /admin/buckets/{bucket}`
+///
+/// Return the bucket record including its settings.
+pub async fn get_bucket_settings(
+    State(service): State<StorageService>,
+    Path(bucket): Path<String>,
+) -> Result<Json<Bucket>, AppError> {
+    Ok(Json(service.fetch_bucket(&bucket).await?))
+}
+
+/// `PATCH /admin/buckets/{bucket}`
+///
+/// Update bucket settings. `{"read_only": true}` freezes the bucket: object
+/// writes, deletes, recoveries and bucket deletion answer 403 until cleared.
+/// `cache_control` and `expires_secs` set the caching headers returned with
+/// every object GET/HEAD, e.g. for a CDN in front of the store.
+/// `default_acl` and `enforce_bucket_owner_full_control` set the upload ACL
+/// policy (see `services::acl`). `scan_uploads` sends uploads to the content
+/// scanner (see `services::scanner`). `encrypt_metadata` seals user metadata
+/// and tag values at rest (see `services::metadata_encryption`).
+pub async fn patch_bucket_settings(
+    State(service): State<StorageService>,
+    Path(bucket): Path<String>,
+    Json(patch): Json<BucketSettingsPatch>,
+) -> Result<Json<Bucket>, AppError> {
+    let mut updated = service.fetch_bucket(&bucket).await?;
+    if let Some(read_only) = patch.read_only {
+        updated = service.set_bucket_read_only(&bucket, read_only).await?;
+        tracing::info!("bucket `{}` read_only set to {}", bucket, read_only);
+    }
+    if let Some(cache_control) = patch.cache_control {
+        let cache_control = Some(cache_control.as_str()).filter(|v| !v.is_empty());
+        updated = service
+            .set_bucket_cache_control(&bucket, cache_control)
+            .await?;
+        tracing::info!(
+            "bucket `{}` cache_control set to {:?}",
+            bucket,
+            cache_control
+        );
+    }
+    if let Some(expires_secs) = patch.expires_secs {
+        let expires_secs = Some(expires_secs).filter(|&secs| secs > 0);
+        updated = service.set_bucket_expires(&bucket, expires_secs).await?;
+        tracing::info!("bucket `{}` expires_secs set to {:?}", bucket, expires_secs);
+    }
+    if let Some(default_acl) = patch.default_acl {
+        let default_acl = Some(default_acl.as_str()).filter(|v| !v.is_empty());
+        updated = service.set_bucket_default_acl(&bucket, default_acl).await?;
+        tracing::info!("bucket `{}` default_acl set to {:?}", bucket, default_acl);
+    }
+    if let Some(enforce) = patch.enforce_bucket_owner_full_control {
+        updated = service
+            .set_bucket_enforce_owner_full_control(&bucket, enforce)
+            .await?;
+        tracing::info!(
+            "bucket `{}` enforce_bucket_owner_full_control set to {}",
+            bucket,
+            enforce
+        );
+    }
+    if let Some(scan_uploads) = patch.scan_uploads {
+        let mode = Some(scan_uploads.as_str())
+            .filter(|v| !v.is_empty())
+            .map(ScanMode::parse)
+            .transpose()?;
+        updated = service.set_bucket_scan_uploads(&bucket, mode).await?;
+        tracing::info!("bucket `{}` scan_uploads set to {:?}", bucket, mode);
+    }
+    if let Some(encrypt) = patch.encrypt_metadata {
+        updated = service
+            .set_bucket_encrypt_metadata(&bucket, encrypt)
+            .await?;
+        tracing::info!("bucket `{}` encrypt_metadata set to {}", bucket, encrypt);
+    }
+    Ok(Json(updated))
+}
+
+/// `GET /admin/bucket-templates`
+///
+/// The configured bucket templates by name, as `PUT /{bucket}` applies them.
+pub async fn list_bucket_templates(
+    State(service): State<StorageService>,
+) -> Json<BTreeMap<String, BucketTemplate>> {
+    Json(service.options.bucket_templates.all().clone())
+}
+
+/// `GET /admin/uploads`
+///
+/// List uploads that are still receiving data, oldest first, with bytes
+/// received, average rate, ETA and idle time, plus how many uploads were
+/// abandoned (and bytes wasted on them) since the server started.
+pub async fn list_uploads(State(service): State<StorageService>) -> Json<UploadsResponse> {
+    Json(UploadsResponse {
+        uploads: service.uploads.snapshot(),
+        aborted: service.uploads.aborted(),
+    })
+}
+
+/// `GET /admin/volumes`
+///
+/// Each storage volume: reachable, writable, free bytes and write error
+/// counts.
+pub async fn list_volumes(State(service): State<StorageService>) -> Json<Vec<VolumeStatus>> {
+    Json(service.volume_statuses().await)
+}
+
+/// `POST /admin/volumes/{volume}/reset`
+///
+/// Accept writes again on a volume marked read-only after repeated write
+/// failures, and clear its failure streak.
+pub async fn reset_volume(
+    State(service): State<StorageService>,
+    Path(volume): Path<String>,
+) -> Result<Json<Vec<VolumeStatus>>, AppError> {
+    let known = volume == DEFAULT_VOLUME
+        || service
+            .options
+            .volumes
+            .iter()
+            .any(|spec| spec.name == volume);
+    if !known {
+        return Err(AppError::not_found(format!(
+            "volume `{}` not found",
+            volume
+        )));
+    }
+    service.volume.reset();
+    Ok(Json(service.volume_statuses().await))
+}
+
+/// Query of `POST /admin/purge-deleted`.
+#[derive(Debug, Deserialize)]
+pub struct PurgeQuery {
+    /// Purge rows deleted more than this many seconds ago instead of the
+    /// configured retention.
+    pub older_than_secs: Option<u64>,
+}
+
+/// `POST /admin/purge-deleted[?older_than_secs=N]`
+///
+/// Permanently remove soft-deleted object rows (and any payload left behind)
+/// older than the deleted-object retention, as the background purger does.
+/// Without `older_than_secs` this needs a retention to be configured.
+pub async fn purge_deleted(
+    State(service): State<StorageService>,
+    Query(q): Query<PurgeQuery>,
+) -> Result<Json<PurgeSummary>, AppError> {
+    let older_than = q
+        .older_than_secs
+        .map(std::time::Duration::from_secs)
+        .or(service.options.deleted_retention)
+        .ok_or_else(|| {
+            AppError::new(
+                StatusCode::BAD_REQUEST,
+                "no deleted-object retention is configured; pass older_than_secs",
+            )
+        })?;
+    Ok(Json(service.purge_deleted_objects(older_than).await?))
+}
+
+/// Query of `GET /admin/objects`.
+#[derive(Debug, Deserialize)]
+pub struct ObjectSearchQuery {
+    /// Only buckets with this `owner_id`.
+    pub owner: Option<Uuid>,
+    pub prefix: Option<String>,
+    /// `GLOB` pattern keys must match, e.g. `*.tmp`.
+    pub pattern: Option<String>,
+    pub min_size: Option<u64>,
+    pub max_size: Option<u64>,
+    pub older_than_secs: Option<u64>,
+    pub newer_than_secs: Option<u64>,
+    pub max_keys: Option<usize>,
+    pub continuation_token: Option<String>,
+}
+
+/// `GET /admin/objects[?owner=&prefix=&pattern=&min_size=&max_size=&older_than_secs=&newer_than_secs=]`
+///
+/// Live objects across all buckets (or one owner's), in bucket and key
+/// order, up to `max_keys` (1000) per page; pass `next_continuation_token`
+/// back as `continuation_token` for the next one.
+pub async fn search_objects(
+    State(service): State<StorageService>,
+    Query(q): Query<ObjectSearchQuery>,
+) -> Result<Json<ObjectSearchPage>, AppError> {
+    let search = ObjectSearch {
+        owner: q.owner,
+        prefix: q.prefix,
+        pattern: q.pattern,
+        min_size: q.min_size,
+        max_size: q.max_size,
+        older_than: q.older_than_secs.map(std::time::Duration::from_secs),
+        newer_than: q.newer_than_secs.map(std::time::Duration::from_secs),
+        max_keys: q.max_keys.unwrap_or(MAX_SEARCH_KEYS),
+        continuation_token: q.continuation_token,
+    };
+    Ok(Json(service.search_objects(search).await?))
+}
+
+/// `GET /limits`
+///
+/// Effective limits and capabilities (object/key/list/part bounds, checksum
+/// algorithms, regions, optional features, disabled API groups). Public, so
+/// SDK wrappers and test harnesses can adapt without admin credentials.
+pub async fn get_limits(
+    State(service): State<StorageService>,
+    flags: Option<Extension<FeatureFlags>>,
+) -> Json<ServerLimits> {
+    Json(service.limits(disabled_apis(flags)))
+}
+
+/// `GET /admin/limits`
+///
+/// Same as `GET /limits` plus the operator tunables behind it (recycle
+/// retention, mmap threshold, range cache size).
+pub async fn get_admin_limits(
+    State(service): State<StorageService>,
+    flags: Option<Extension<FeatureFlags>>,
+) -> Json<AdminLimits> {
+    Json(service.admin_limits(disabled_apis(flags)))
+}
+
+fn disabled_apis(flags: Option<Extension<FeatureFlags>>) -> Vec<&'static str> {
+    flags
+        .map(|Extension(flags)| flags.disabled().into_iter().map(|g| g.as_str()).collect())
+        .unwrap_or_default()
+}
+
+/// `GET /admin/denials`
+///
+/// Recent refused requests with who/what/why, plus counts per source and
+/// action since startup. Answers 404 unless the server runs with
+/// `OBJECT_STORE_LOG_DENIED_REQUESTS`.
+pub async fn list_denials(
+    log: Option<Extension<DenialLog>>,
+) -> Result<Json<DenialReport>, AppError> {
+    let Some(Extension(log)) = log else {
+        return Err(AppError::new(
+            StatusCode::NOT_FOUND,
+            "denied-request logging is disabled",
+        ));
+    };
+    Ok(Json(log.report()))
+}
+
+/// Query of `GET /admin/slo`.
+#[derive(Debug, Deserialize)]
+pub struct SloQuery {
+    /// Window in minutes, at most `SLO_WINDOW_MINUTES`.
+    pub minutes: Option<u32>,
+}
+
+/// `GET /admin/slo[?minutes=N]`
+///
+/// Latency percentiles, error rates and byte totals per operation and
+/// overall over the last `N` minutes (default `DEFAULT_SLO_MINUTES`).
+pub async fn get_slo_report(
+    stats: Option<Extension<SloStats>>,
+    Query(query): Query<SloQuery>,
+) -> Result<Json<SloReport>, AppError> {
+    let Some(Extension(stats)) = stats else {
+        return Err(AppError::new(
+            StatusCode::NOT_FOUND,
+            "request statistics are not collected",
+        ));
+    };
+    Ok(Json(
+        stats.report(query.minutes.unwrap_or(DEFAULT_SLO_MINUTES)),
+    ))
+}
+
+/// Body of `PUT /admin/log-level` and response of both log-level endpoints.
+#[derive(Debug, Serialize, Deserialize)]
+pub struct LogLevel {
+    /// Filter directives in `RUST_LOG` syntax, e.g.
+    /// `info,object_store::services::storage_service=debug`.
+    pub filter: String,
+}
+
+fn log_filter(filter: Option<Extension<LogFilter>>) -> Result<LogFilter, AppError> {
+    filter.map(|Extension(filter)| filter).ok_or_else(|| {
+        AppError::new(
+            StatusCode::NOT_FOUND,
+            "the log filter cannot be changed at runtime",
+        )
+    })
+}
+
+/// `GET /admin/log-level`
+///
+/// The active tracing filter.
+pub async fn get_log_level(
+    filter: Option<Extension<LogFilter>>,
+) -> Result<Json<LogLevel>, AppError> {
+    let filter = log_filter(filter)?
+        .current()
+        .ok_or_else(|| AppError::internal("the log subscriber is gone"))?;
+    Ok(Json(LogLevel { filter }))
+}
+
+/// `PUT /admin/log-level`
+///
+/// Replace the tracing filter until the next restart (`400` for invalid
+/// directives) and return the new one.
+pub async fn put_log_level(
+    filter: Option<Extension<LogFilter>>,
+    Json(req): Json<LogLevel>,
+) -> Result<Json<LogLevel>, AppError> {
+    let filter = log_filter(filter)?.set(&req.filter)?;
+    Ok(Json(LogLevel { filter }))
+}
+
+/// Query of `GET /admin/events`.
+#[derive(Debug, Deserialize)]
+pub struct EventsQuery {
+    /// Only stream events of this bucket.
+    pub bucket: Option<String>,
+}
+
+/// `GET /admin/events[?bucket=x]`
+///
+/// Stream bucket activity (bucket created/deleted/reconfigured, objects
+/// created/deleted) as Server-Sent Events, starting from the moment of the
+/// request. Each event's `event:` field is its kind, `id:` its sequence number
+/// and `data:` the JSON record. A client that reads too slowly receives a
+/// `lagged` event carrying the number of events it missed.
+pub async fn stream_events(
+    State(service): State<StorageService>,
+    Query(q): Query<EventsQuery>,
+) -> Result<Sse<impl Stream<Item = Result<Event, Infallible>>>, AppError> {
+    if let Some(bucket) = q.bucket.as_deref() {
+        service.fetch_bucket(bucket).await?;
+    }
+    let rx = service.events.subscribe();
+    let stream = stream::unfold((rx, q.bucket), |(mut rx, filter)| async move {
+        loop {
+            let event = match rx.recv().await {
+                Ok(event) => event,
+                Err(RecvError::Lagged(missed)) => {
+                    let lagged = Event::default().event("lagged").data(missed.to_string());
+                    return Some((Ok(lagged), (rx, filter)));
+                }
+                Err(RecvError::Closed) => return None,
+            };
+            if filter
+                .as_deref()
+                .is_some_and(|bucket| bucket != event.bucket)
+            {
+                continue;
+            }
+            let sse = Event::default()
+                .event(event.kind.as_str())
+                .id(event.seq.to_string())
+                .json_data(&event)
+                .unwrap_or_else(|_| Event::default().comment("unserializable event"));
+            return Some((Ok(sse), (rx, filter)));
+        }
+    });
+    Ok(Sse::new(stream).keep_alive(KeepAlive::default()))
+}
+
+/// Body of `POST /admin/buckets/{bucket}/analytics`.
+#[derive(Debug, Deserialize)]
+pub struct AnalyticsReq {
+    /// Bucket the CSV report is written into.
+    pub destination: String,
+    /// Only analyse keys under this prefix.
+    pub prefix: Option<String>,
+}
+
+/// `POST /admin/buckets/{bucket}/analytics`
+///
+/// Queue a storage class analysis of the bucket: objects and reads per
+/// prefix and object age, with a recommended `STANDARD_IA` transition age,
+/// written as CSV to `storage-class-analysis/{bucket}/{job id}.csv` in
+/// `destination`. Answers `202` with the job; needs access analytics.
+pub async fn create_analysis(
+    State(service): State<StorageService>,
+    Path(bucket): Path<String>,
+    Json(req): Json<AnalyticsReq>,
+) -> Result<Response, AppError> {
+    let job = service
+        .enqueue_storage_class_analysis(&bucket, req.prefix.as_deref(), &req.destination)
+        .await?;
+    let location = format!("/admin/jobs/{}", job.id);
+    let mut response = (StatusCode::ACCEPTED, Json(job)).into_response();
+    if let Ok(value) = HeaderValue::from_str(&location) {
+        response.headers_mut().insert(header::LOCATION, value);
+    }
+    Ok(response)
+}
+
+/// `POST /admin/sessions`
+///
+/// Issue a session token limited to one bucket (and optionally a key
+/// prefix) and a set of API groups, valid for `duration_secs` (900–43200,
+/// default 3600). Answers `201` with the token and its policy; needs a
+/// session token key.
+pub async fn create_session(
+    State(service): State<StorageService>,
+    Json(req): Json<SessionRequest>,
+) -> Result<(StatusCode, Json<SessionToken>), AppError> {
+    let token = service.issue_session_token(req).await?;
+    Ok((StatusCode::CREATED, Json(token)))
+}
+
+/// Query of `GET /admin/buckets/{bucket}/stats`.
+#[derive(Debug, Deserialize)]
+pub struct StatsQuery {
+    /// Key segments to group by; defaults to the recorded depth.
+    pub depth: Option<usize>,
+}
+
+/// `GET /admin/buckets/{bucket}/stats[?depth=N]`
+///
+/// Per key prefix: live objects, bytes stored (noncurrent versions
+/// included) and, with `OBJECT_STORE_USAGE_PREFIX_DEPTH` set, bytes uploaded
+/// and downloaded, for charging the teams sharing a bucket.
+pub async fn get_bucket_stats(
+    State(service): State<StorageService>,
+    Path(bucket): Path<String>,
+    Query(q): Query<StatsQuery>,
+) -> Result<Json<BucketUsage>, AppError> {
+    Ok(Json(service.bucket_usage(&bucket, q.depth).await?))
+}
+
+/// `GET /admin/buckets/{bucket}/quota`
+///
+/// The bucket's quota and usage, plus the global ones when set.
+pub async fn get_bucket_quota(
+    State(service): State<StorageService>,
+    Path(bucket): Path<String>,
+) -> Result<Json<BucketQuota>, AppError> {
+    Ok(Json(service.get_bucket_quota(&bucket).await?))
+}
+
+/// `PUT /admin/buckets/{bucket}/quota`
+///
+/// Replace the bucket's quota, e.g. `{"max_bytes": 10737418240,
+/// "max_objects": 100000}`; omitted limits are unlimited. Existing data over
+/// a new limit is kept, but further writes are refused.
+pub async fn put_bucket_quota(
+    State(service): State<StorageService>,
+    Path(bucket): Path<String>,
+    Json(quota): Json<Quota>,
+) -> Result<Json<BucketQuota>, AppError> {
+    service.set_bucket_quota(&bucket, quota).await?;
+    tracing::info!(
+        "bucket `{}` quota set to {:?} bytes, {:?} objects",
+        bucket,
+        quota.max_bytes,
+        quota.max_objects
+    );
+    Ok(Json(service.get_bucket_quota(&bucket).await?))
+}
+
+/// `DELETE /admin/buckets/{bucket}/quota`
+pub async fn delete_bucket_quota(
+    State(service): State<StorageService>,
+    Path(bucket): Path<String>,
+) -> Result<StatusCode, AppError> {
+    service.set_bucket_quota(&bucket, Quota::default()).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// `GET /admin/buckets/{bucket}/naming-policy`
+///
+/// The key constraints the bucket enforces; `{}` when it has none.
+pub async fn get_naming_policy(
+    State(service): State<StorageService>,
+    Path(bucket): Path<String>,
+) -> Result<Json<NamingPolicy>, AppError> {
+    Ok(Json(service.get_naming_policy(&bucket).await?))
+}
+
+/// `PUT /admin/buckets/{bucket}/naming-policy`
+///
+/// Replace the bucket's key constraints, e.g. `{"required_prefix":
+/// "(team-a|team-b)/", "max_depth": 4, "forbidden_extensions": ["exe"]}`.
+/// Keys already stored are not checked.
+pub async fn put_naming_policy(
+    State(service): State<StorageService>,
+    Path(bucket): Path<String>,
+    Json(policy): Json<NamingPolicy>,
+) -> Result<Json<NamingPolicy>, AppError> {
+    let policy = service.set_naming_policy(&bucket, policy).await?;
+    tracing::info!("bucket `{}` naming policy set to {:?}", bucket, policy);
+    Ok(Json(policy))
+}
+
+/// `DELETE /admin/buckets/{bucket}/naming-policy`
+pub async fn delete_naming_policy(
+    State(service): State<StorageService>,
+    Path(bucket): Path<String>,
+) -> Result<StatusCode, AppError> {
+    service
+        .set_naming_policy(&bucket, NamingPolicy::default())
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// `GET /admin/buckets/{bucket}/auto-tagging`
+///
+/// The rules tagging the bucket's uploads; no rules when it has none.
+pub async fn get_auto_tagging(
+    State(service): State<StorageService>,
+    Path(bucket): Path<String>,
+) -> Result<Json<AutoTagging>, AppError> {
+    Ok(Json(service.get_auto_tagging(&bucket).await?))
+}
+
+/// `PUT /admin/buckets/{bucket}/auto-tagging`
+///
+/// Replace the rules tagging the bucket's uploads, e.g. `{"rules":
+/// [{"prefix": "ingest/", "tags": {"tier": "raw"}}]}`. Objects already
+/// stored are not retagged.
+pub async fn put_auto_tagging(
+    State(service): State<StorageService>,
+    Path(bucket): Path<String>,
+    Json(rules): Json<AutoTagging>,
+) -> Result<Json<AutoTagging>, AppError> {
+    let rules = service.set_auto_tagging(&bucket, rules).await?;
+    tracing::info!(
+        "bucket `{}` auto-tagging set to {} rules",
+        bucket,
+        rules.rules.len()
+    );
+    Ok(Json(rules))
+}
+
+/// `DELETE /admin/buckets/{bucket}/auto-tagging`
+pub async fn delete_auto_tagging(
+    State(service): State<StorageService>,
+    Path(bucket): Path<String>,
+) -> Result<StatusCode, AppError> {
+    service
+        .set_auto_tagging(&bucket, AutoTagging::default())
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// `GET /admin/buckets/{bucket}/placement`
+///
+/// The volumes the bucket places key prefixes on; no rules when it has none.
+pub async fn get_placement_policy(
+    State(service): State<StorageService>,
+    Path(bucket): Path<String>,
+) -> Result<Json<PlacementPolicy>, AppError> {
+    Ok(Json(service.get_placement_policy(&bucket).await?))
+}
+
+/// `PUT /admin/buckets/{bucket}/placement`
+///
+/// Replace the bucket's placement rules, e.g. `{"rules": [{"prefix":
+/// "raw/", "volume": "cold"}]}`. Payloads already stored stay on their
+/// volume; only later writes follow the new rules.
+pub async fn put_placement_policy(
+    State(service): State<StorageService>,
+    Path(bucket): Path<String>,
+    Json(policy): Json<PlacementPolicy>,
+) -> Result<Json<PlacementPolicy>, AppError> {
+    let policy = service.set_placement_policy(&bucket, policy).await?;
+    tracing::info!("bucket `{}` placement policy set to {:?}", bucket, policy);
+    Ok(Json(policy))
+}
+
+/// `DELETE /admin/buckets/{bucket}/placement`
+pub async fn delete_placement_policy(
+    State(service): State<StorageService>,
+    Path(bucket): Path<String>,
+) -> Result<StatusCode, AppError> {
+    service
+        .set_placement_policy(&bucket, PlacementPolicy::default())
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// `GET /admin/buckets/{bucket}/publishers`
+///
+/// The NATS / Kafka publishers of the bucket's object events.
+pub async fn get_event_publishers(
+    State(service): State<StorageService>,
+    Path(bucket): Path<String>,
+) -> Result<Json<PublisherConfig>, AppError> {
+    Ok(Json(service.get_event_publishers(&bucket).await?))
+}
+
+/// `PUT /admin/buckets/{bucket}/publishers`
+///
+/// Replace the bucket's event publishers, e.g. `{"publishers": [{"id":
+/// "ingest", "kind": "nats", "url": "nats://nats:4222", "subject":
+/// "uploads"}]}`. Kinds this build lacks the feature for are refused.
+pub async fn put_event_publishers(
+    State(service): State<StorageService>,
+    Path(bucket): Path<String>,
+    Json(config): Json<PublisherConfig>,
+) -> Result<Json<PublisherConfig>, AppError> {
+    let config = service.set_event_publishers(&bucket, config).await?;
+    tracing::info!(
+        "bucket `{}` publishes events to {} publisher(s)",
+        bucket,
+        config.publishers.len()
+    );
+    Ok(Json(config))
+}
+
+/// `DELETE /admin/buckets/{bucket}/publishers`
+pub async fn delete_event_publishers(
+    State(service): State<StorageService>,
+    Path(bucket): Path<String>,
+) -> Result<StatusCode, AppError> {
+    service
+        .set_event_publishers(&bucket, PublisherConfig::default())
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// `GET /admin/buckets/{bucket}/replication`
+///
+/// Where the bucket is replicated to; the secret key is never returned.
+pub async fn get_replication(
+    State(service): State<StorageService>,
+    Path(bucket): Path<String>,
+) -> Result<Json<ReplicationConfig>, AppError> {
+    Ok(Json(service.get_replication(&bucket).await?))
+}
+
+/// `PUT /admin/buckets/{bucket}/replication`
+///
+/// Replicate the bucket's later changes to another S3 endpoint, e.g.
+/// `{"destination": "https://backup:9000/replica", "access_key": "AK",
+/// "secret_key": "SK"}`. Replication lag shows in the bucket's stats.
+pub async fn put_replication(
+    State(service): State<StorageService>,
+    Path(bucket): Path<String>,
+    Json(config): Json<ReplicationConfig>,
+) -> Result<Json<ReplicationConfig>, AppError> {
+    Ok(Json(service.set_replication(&bucket, config).await?))
+}
+
+/// `POST /admin/buckets/{bucket}/replication/reconcile`
+///
+/// Queue a job comparing the bucket with its replication destination by
+/// ETag; missing, differing and (when deletes are replicated) extra keys
+/// are replicated again. Answers `202` with the job, whose `report` holds
+/// the counts once it finished.
+pub async fn reconcile_replication(
+    State(service): State<StorageService>,
+    Path(bucket): Path<String>,
+) -> Result<Response, AppError> {
+    let job = service.enqueue_replication_reconcile(&bucket).await?;
+    let location = format!("/admin/jobs/{}", job.id);
+    let mut response = (StatusCode::ACCEPTED, Json(job)).into_response();
+    if let Ok(value) = HeaderValue::from_str(&location) {
+        response.headers_mut().insert(header::LOCATION, value);
+    }
+    Ok(response)
+}
+
+/// `DELETE /admin/buckets/{bucket}/replication`
+pub async fn delete_replication(
+    State(service): State<StorageService>,
+    Path(bucket): Path<String>,
+) -> Result<StatusCode, AppError> {
+    service.delete_replication(&bucket).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// `GET /admin/buckets/{bucket}/quarantine`
+///
+/// Versions the content scanner flagged, which cannot be read until
+/// released.
+pub async fn list_quarantined(
+    State(service): State<StorageService>,
+    Path(bucket): Path<String>,
+) -> Result<Json<Vec<QuarantinedObject>>, AppError> {
+    Ok(Json(service.list_quarantined(&bucket).await?))
+}
+
+/// Query of `DELETE /admin/buckets/{bucket}/quarantine`.
+#[derive(Debug, Deserialize)]
+pub struct ReleaseQuery {
+    pub key: String,
+    #[serde(rename = "versionId")]
+    pub version_id: Option<String>,
+}
+
+/// `DELETE /admin/buckets/{bucket}/quarantine?key=K[&versionId=V]`
+///
+/// Release a quarantined version (the current one without `versionId`),
+/// e.g. after a false positive was reviewed.
+pub async fn release_quarantined(
+    State(service): State<StorageService>,
+    Path(bucket): Path<String>,
+    Query(q): Query<ReleaseQuery>,
+) -> Result<StatusCode, AppError> {
+    service
+        .release_quarantine(&bucket, &q.key, q.version_id.as_deref())
+        .await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// Query of `GET /admin/buckets/{bucket}/manifest`.
+#[derive(Debug, Deserialize)]
+pub struct ManifestQuery {
+    /// Only list keys under this prefix.
+    pub prefix: Option<String>,
+}
+
+/// `GET /admin/buckets/{bucket}/manifest[?prefix=P]`
+///
+/// Signed manifest of the live objects (key, size, SHA-256 of the stored
+/// bytes, version) for release-artifact attestation. Answers 404 unless the
+/// server has a manifest signing key.
+pub async fn export_manifest(
+    State(service): State<StorageService>,
+    Path(bucket): Path<String>,
+    Query(q): Query<ManifestQuery>,
+) -> Result<Json<Manifest>, AppError> {
+    ensure_manifests_enabled(&service)?;
+    let manifest = service
+        .export_manifest(&bucket, q.prefix.as_deref())
+        .await?;
+    tracing::info!(
+        "exported manifest of `{}` ({} keys under {:?})",
+        bucket,
+        manifest.body.entries.len(),
+        q.prefix
+    );
+    Ok(Json(manifest))
+}
+
+/// `POST /admin/buckets/{bucket}/manifest/verify`
+///
+/// Check a manifest from `export_manifest` (the request body): its signature,
+/// and that every listed key still has the recorded size, version and
+/// content, with no other keys under its prefix. `ok` sums it up.
+pub async fn verify_manifest(
+    State(service): State<StorageService>,
+    Path(bucket): Path<String>,
+    Json(manifest): Json<Manifest>,
+) -> Result<Json<ManifestVerification>, AppError> {
+    ensure_manifests_enabled(&service)?;
+    Ok(Json(service.verify_manifest(&bucket, &manifest).await?))
+}
+
+fn ensure_manifests_enabled(service: &StorageService) -> Result<(), AppError> {
+    if service.options.manifest_key.is_none() {
+        return Err(AppError::new(
+            StatusCode::NOT_FOUND,
+            "manifest signing is disabled",
+        ));
+    }
+    Ok(())
+}
+
+/// `GET /admin/buckets/{bucket}/snapshot-policy`
+pub async fn get_snapshot_policy(
+    State(service): State<StorageService>,
+    Path(bucket): Path<String>,
+) -> Result<Json<SnapshotPolicy>, AppError> {
+    service
+        .get_snapshot_policy(&bucket)
+        .await?
+        .map(Json)
+        .ok_or_else(|| AppError::not_found(format!("bucket `{}` has no snapshot policy", bucket)))
+}
+
+/// `PUT /admin/buckets/{bucket}/snapshot-policy`
+///
+/// Create or replace the bucket's snapshot schedule and retention, e.g.
+/// `{"schedule": "0 3 * * *", "keep_daily": 7, "keep_weekly": 4}`.
+pub async fn put_snapshot_policy(
+    State(service): State<StorageService>,
+    Path(bucket): Path<String>,
+    Json(req): Json<SnapshotPolicyReq>,
+) -> Result<Json<SnapshotPolicy>, AppError> {
+    let policy = service
+        .set_snapshot_policy(&bucket, &req.schedule, req.keep_daily, req.keep_weekly)
+        .await?;
+    tracing::info!(
+        "bucket `{}` snapshot policy set to `{}` (keep {} daily, {} weekly)",
+        bucket,
+        policy.schedule,
+        policy.keep_daily,
+        policy.keep_weekly
+    );
+    Ok(Json(policy))
+}
+
+/// `DELETE /admin/buckets/{bucket}/snapshot-policy`
+///
+/// Stop scheduled snapshots. Existing snapshots are kept.
+pub async fn delete_snapshot_policy(
+    State(service): State<StorageService>,
+    Path(bucket): Path<String>,
+) -> Result<StatusCode, AppError> {
+    if service.delete_snapshot_policy(&bucket).await? {
+        Ok(StatusCode::NO_CONTENT)
+    } else {
+        Err(AppError::not_found(format!(
+            "bucket `{}` has no snapshot policy",
+            bucket
+        )))
+    }
+}
+
+/// `GET /admin/buckets/{bucket}/snapshots`
+///
+/// List the bucket's snapshots, newest first.
+pub async fn list_snapshots(
+    State(service): State<StorageService>,
+    Path(bucket): Path<String>,
+) -> Result<Json<SnapshotsResponse>, AppError> {
+    Ok(Json(SnapshotsResponse {
+        snapshots: service.list_snapshots(&bucket).await?,
+    }))
+}
+
+/// `POST /admin/buckets/{bucket}/snapshots`
+///
+/// Take a manual snapshot now. Manual snapshots are not pruned by retention.
+pub async fn create_snapshot(
+    State(service): State<StorageService>,
+    Path(bucket): Path<String>,
+) -> Result<(StatusCode, Json<BucketSnapshot>), AppError> {
+    let snapshot = service
+        .create_snapshot(&bucket, SnapshotTrigger::Manual)
+        .await?;
+    Ok((StatusCode::CREATED, Json(snapshot)))
+}
+
+/// `DELETE /admin/buckets/{bucket}/snapshots/{id}`
+pub async fn delete_snapshot(
+    State(service): State<StorageService>,
+    Path((bucket, id)): Path<(String, Uuid)>,
+) -> Result<StatusCode, AppError> {
+    service.delete_snapshot(&bucket, id).await?;
+    Ok(StatusCode::NO_CONTENT)
+}
+
+/// `POST /admin/buckets/{bucket}/snapshots/{id}/restore`
+///
+/// Roll the bucket back to the snapshot. The response names the
+/// `pre-restore` snapshot that undoes it.
+pub async fn restore_snapshot(
+    State(service): State<StorageService>,
+    Path((bucket, id)): Path<(String, Uuid)>,
+) -> Result<Json<RestoreSummary>, AppError> {
+    Ok(Json(service.restore_snapshot(&bucket, id).await?))
+}
+
+/// `GET /admin/jobs`
+///
+/// The most recent background jobs, newest first.
+pub async fn list_jobs(
+    State(service): State<StorageService>,
+) -> Result<Json<JobsResponse>, AppError> {
+    Ok(Json(JobsResponse {
+        jobs: service.list_jobs().await?,
+    }))
+}
+
+/// `GET /admin/jobs/{id}`
+///
+/// Status and progress of one job (e.g. keys deleted so far by a prefix
+/// delete).
+pub async fn get_job(
+    State(service): State<StorageService>,
+    Path(id): Path<Uuid>,
+) -> Result<Json<Job>, AppError> {
+    Ok(Json(service.get_job(id).await?))
+}
+
+#[derive(Serialize)]
+pub struct JobsResponse {
+    jobs: Vec<Job>,
+}
+
+#[derive(Serialize)]
+pub struct SnapshotsResponse {
+    snapshots: Vec<BucketSnapshot>,
+}
+
+#[derive(Serialize)]
+pub struct UploadsResponse {
+    uploads: Vec<UploadProgress>,
+    #[serde(flatten)]
+    aborted: AbortedUploads,
+}
+
+#[derive(Serialize)]
+struct WhoAmIResponse {
+    authenticated: bool,
+    #[serde(flatten)]
+    identity: Option<AdminIdentity>,
+}
@@ -0,0 +1,234 @@
+//! HTTP handler for S3 GetObjectAttributes.
+//!
+//! Selected by the `?attributes` flag on `GET /{bucket}/{*key}` in
+//! `object_handlers`. Newer SDKs use it instead of HEAD to read an object's
+//! ETag, checksums, storage class, size and, for objects completed by a
+//! multipart upload, their parts, without downloading anything. Parts are
+//! paged with `x-amz-max-parts` / `x-amz-part-number-marker` and carry the
+//! checksums they were uploaded with, so backup tools can verify a large
+//! object part by part.
+
+use crate::{
+    errors::AppError,
+    handlers::{
+        multipart_handlers::push_part_checksums,
+        object_handlers::{insert_version_header, xml_escape},
+        s3_headers::S3SseHeaders,
+    },
+    models::object::Object,
+    services::{
+        multipart::{DEFAULT_MAX_PARTS, ObjectPartsPage},
+        storage_service::StorageService,
+    },
+};
+use axum::{
+    body::Body,
+    http::{HeaderMap, HeaderValue, StatusCode, header},
+    response::Response,
+};
+
+/// Attribute names accepted in `x-amz-object-attributes`.
+const OBJECT_ATTRIBUTES: [&str; 5] = [
+    "ETag",
+    "Checksum",
+    "ObjectParts",
+    "StorageClass",
+    "ObjectSize",
+];
+
+/// `GET /{bucket}/{*key}?attributes[&versionId=V]`
+///
+/// Returns the attributes listed in `x-amz-object-attributes` (all of them
+/// when the header is absent). Parts are only recorded for the current
+/// version of a key, so older versions report none; at most
+/// `x-amz-max-parts` (default and cap 1000) are listed, after
+/// `x-amz-part-number-marker`. An object encrypted with a customer key
+/// needs the same SSE-C headers as GET and HEAD.
+pub async fn get_object_attributes(
+    service: &StorageService,
+    bucket: &str,
+    key: &str,
+    version_id: Option<&str>,
+    sse: &S3SseHeaders,
+    headers: &HeaderMap,
+) -> Result<Response, AppError> {
+    let wanted = requested_attributes(headers)?;
+    let max_parts = numeric_header(headers, "x-amz-max-parts")?;
+    let part_number_marker = numeric_header(headers, "x-amz-part-number-marker")?;
+    let meta = match version_id {
+        Some(version_id) => {
+            service
+                .get_object_version_metadata(bucket, key, version_id)
+                .await?
+        }
+        None => service.get_object_metadata(bucket, key).await?,
+    };
+    service
+        .payload_cipher(&meta, sse.customer_key.as_ref())
+        .await?;
+    let parts = if !wanted.contains(&"ObjectParts") {
+        None
+    } else if version_id.is_none() || is_current(service, bucket, key, &meta).await {
+        let max_parts = max_parts.map_or(DEFAULT_MAX_PARTS, |max| max as usize);
+        Some(
+            service
+                .object_parts(&meta, part_number_marker, max_parts)
+                .await?,
+        )
+    } else {
+        None
+    };
+    let parts = parts.filter(|page| page.total_parts > 0);
+
+    let xml = build_attributes_xml(&meta, parts.as_ref(), part_number_marker, &wanted);
+    let mut response = Response::new(Body::from(xml));
+    *response.status_mut() = StatusCode::OK;
+    let resp_headers = response.headers_mut();
+    resp_headers.insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/xml"),
+    );
+    if let Ok(value) = HeaderValue::from_str(&meta.last_modified.to_rfc2822()) {
+        resp_headers.insert(header::LAST_MODIFIED, value);
+    }
+    insert_version_header(resp_headers, &meta);
+    Ok(response)
+}
+
+/// Whether `meta` (read by version id) is the current version of `key`.
+async fn is_current(service: &StorageService, bucket: &str, key: &str, meta: &Object) -> bool {
+    service
+        .get_object_metadata(bucket, key)
+        .await
+        .is_ok_and(|current| current.version_id == meta.version_id)
+}
+
+/// A non-negative integer header, if present.
+fn numeric_header(headers: &HeaderMap, name: &str) -> Result<Option<i64>, AppError> {
+    let Some(value) = headers.get(name) else {
+        return Ok(None);
+    };
+    value
+        .to_str()
+        .ok()
+        .and_then(|value| value.trim().parse::<i64>().ok())
+        .filter(|value| *value >= 0)
+        .map(Some)
+        .ok_or_else(|| {
+            AppError::new(
+                StatusCode::BAD_REQUEST,
+                format!("{} must be a non-negative integer", name),
+            )
+        })
+}
+
+/// Attribute names from every `x-amz-object-attributes` header, which may
+/// each hold a comma-separated list.
+fn requested_attributes(headers: &HeaderMap) -> Result<Vec<&'static str>, AppError> {
+    let mut wanted = Vec::new();
+    for value in headers.get_all("x-amz-object-attributes") {
+        let value = value.to_str().map_err(|_| {
+            AppError::new(
+                StatusCode::BAD_REQUEST,
+                "x-amz-object-attributes is not valid text",
+            )
+        })?;
+        for name in value.split(',').map(str::trim).filter(|n| !n.is_empty()) {
+            let known = OBJECT_ATTRIBUTES
+                .iter()
+                .find(|known| known.eq_ignore_ascii_case(name))
+                .ok_or_else(|| {
+                    AppError::new(
+                        StatusCode::BAD_REQUEST,
+                        format!("unknown object attribute `{}`", name),
+                    )
+                })?;
+            if !wanted.contains(known) {
+                wanted.push(*known);
+            }
+        }
+    }
+    if wanted.is_empty() {
+        wanted.extend(OBJECT_ATTRIBUTES);
+    }
+    Ok(wanted)
+}
+
+fn build_attributes_xml(
+    meta: &Object,
+    parts: Option<&ObjectPartsPage>,
+    part_number_marker: Option<i64>,
+    wanted: &[&str],
+) -> String {
+    let mut xml = String::from(concat!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+        r#"<GetObjectAttributesResponse xmlns="http://s3.amazonaws.com/doc/2006-03-01/">"#
+    ));
+    if wanted.contains(&"ETag")
+        && let Some(etag) = meta.etag.as_deref()
+    {
+        xml.push_str(&format!("<ETag>{}</ETag>", xml_escape(etag)));
+    }
+    if wanted.contains(&"Checksum")
+        && (meta.checksum_crc32c.is_some() || meta.checksum_sha256.is_some())
+    {
+        xml.push_str("<Checksum>");
+        if let Some(crc32c) = meta.checksum_crc32c.as_deref() {
+            xml.push_str(&format!(
+                "<ChecksumCRC32C>{}</ChecksumCRC32C>",
+                xml_escape(crc32c)
+            ));
+        }
+        if let Some(sha256) = meta.checksum_sha256.as_deref() {
+            xml.push_str(&format!(
+                "<ChecksumSHA256>{}</ChecksumSHA256>",
+                xml_escape(sha256)
+            ));
+        }
+        xml.push_str("</Checksum>");
+    }
+    if let Some(page) = parts {
+        xml.push_str(&format!(
+            concat!(
+                "<ObjectParts><TotalPartsCount>{}</TotalPartsCount>",
+                "<PartNumberMarker>{}</PartNumberMarker>"
+            ),
+            page.total_parts,
+            part_number_marker.unwrap_or(0)
+        ));
+        if let Some(next) = page.next_part_number_marker {
+            xml.push_str(&format!(
+                "<NextPartNumberMarker>{}</NextPartNumberMarker>",
+                next
+            ));
+        }
+        xml.push_str(&format!(
+            "<MaxParts>{}</MaxParts><IsTruncated>{}</IsTruncated>",
+            page.max_parts, page.is_truncated
+        ));
+        for part in &page.parts {
+            xml.push_str(&format!(
+                "<Part><PartNumber>{}</PartNumber><Size>{}</Size>",
+                part.part_number, part.size_bytes
+            ));
+            push_part_checksums(
+                &mut xml,
+                part.checksum_crc32c.as_deref(),
+                part.checksum_sha256.as_deref(),
+            );
+            xml.push_str("</Part>");
+        }
+        xml.push_str("</ObjectParts>");
+    }
+    if wanted.contains(&"StorageClass") {
+        xml.push_str(&format!(
+            "<StorageClass>{}</StorageClass>",
+            xml_escape(&meta.storage_class)
+        ));
+    }
+    if wanted.contains(&"ObjectSize") {
+        xml.push_str(&format!("<ObjectSize>{}</ObjectSize>", meta.size_bytes));
+    }
+    xml.push_str("</GetObjectAttributesResponse>");
+    xml
+}
@@ -0,0 +1,82 @@
+//! Conditional requests (`If-Match`, `If-None-Match`, `If-Modified-Since`,
+//! `If-Unmodified-Since`).
+//!
+//! Preconditions are evaluated in the order RFC 9110 §13.2.2 prescribes
+//! against the object's current ETag and Last-Modified. Reads answer
+//! `304 Not Modified` when the client's copy is still current, so caches and
+//! CDNs can revalidate without re-downloading; writes and deletes answer
+//! `412 Precondition Failed` when the object changed under the client. The
+//! check happens before the operation and is not atomic with it. The headers
+//! are parsed by `s3_headers::S3ConditionalHeaders`.
+
+use crate::{handlers::s3_headers::S3ConditionalHeaders, models::object::Object};
+
+/// How a request should proceed with respect to its preconditions.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Precondition {
+    /// No precondition failed; carry on.
+    Proceed,
+    /// Answer `304 Not Modified` (reads only).
+    NotModified,
+    /// Answer `412 Precondition Failed`.
+    Failed,
+}
+
+/// Evaluate the request's preconditions against `current`, the object as it
+/// exists now (`None` when the key has no live object). `read` selects GET/
+/// HEAD semantics, where a failed `If-None-Match`/`If-Modified-Since` means
+/// "not modified" rather than a failure.
+pub fn evaluate(
+    conditions: &S3ConditionalHeaders,
+    current: Option<&Object>,
+    read: bool,
+) -> Precondition {
+    if let Some(value) = conditions.if_match.as_deref() {
+        let matches = current.is_some_and(|meta| etag_list_matches(value, meta, true));
+        if !matches {
+            return Precondition::Failed;
+        }
+    } else if let Some(since) = conditions.if_unmodified_since
+        && let Some(meta) = current
+        && meta.last_modified.timestamp() > since.timestamp()
+    {
+        return Precondition::Failed;
+    }
+
+    let unchanged = if read {
+        Precondition::NotModified
+    } else {
+        Precondition::Failed
+    };
+    if let Some(value) = conditions.if_none_match.as_deref() {
+        if current.is_some_and(|meta| etag_list_matches(value, meta, false)) {
+            return unchanged;
+        }
+    } else if read
+        && let Some(since) = conditions.if_modified_since
+        && let Some(meta) = current
+        && meta.last_modified.timestamp() <= since.timestamp()
+    {
+        return unchanged;
+    }
+
+    Precondition::Proceed
+}
+
+/// Match a `*` or comma-separated list of entity tags against the object's
+/// ETag. `strong` comparison (`If-Match`) never matches weak tags.
+fn etag_list_matches(value: &str, meta: &Object, strong: bool) -> bool {
+    if value == "*" {
+        return true;
+    }
+    let Some(etag) = meta.etag.as_deref() else {
+        return false;
+    };
+    value.split(',').map(str::trim).any(|candidate| {
+        let (weak, tag) = match candidate.strip_prefix("W/") {
+            Some(rest) => (true, rest),
+            None => (false, candidate),
+        };
+        !(strong && weak) && tag.trim_matches('"') == etag
+    })
+}
@@ -2,9 +2,9 @@
 //!
 //! - GET /healthz  -> simple liveness ("ok")
 //! - GET /readyz   -> readiness that checks DB connectivity, storage directory metadata,
-//!                   and disk read/write behavior.
+//!   disk read/write behavior and the state of each volume (see `services::volume`).
 
-use crate::services::storage_service::StorageService;
+use crate::services::{storage_service::StorageService, volume::VolumeStatus};
 use axum::{Json, extract::State, http::StatusCode, response::IntoResponse};
 use serde::Serialize;
 use std::{collections::HashMap, time::Instant};
@@ -30,6 +30,9 @@ pub async fn healthz() -> impl IntoResponse {
 /// 1. Validates the metadata database via `SELECT 1`.
 /// 2. Ensures the storage directory exists and is a directory.
 /// 3. Performs a write/read/delete cycle on the storage directory.
+/// 4. Reports each volume (reachable, writable, free space, write errors);
+///    a volume marked read-only after repeated write failures fails the
+///    `volumes` check.
 ///
 /// Returns JSON describing each check. HTTP 200 when all checks pass,
 /// HTTP 503 when any check fails.
@@ -37,13 +40,17 @@ pub async fn readyz(State(service): State<StorageService>) -> impl IntoResponse
     let sqlite_check = check_sqlite(&service).await;
     let storage_dir_check = check_storage_dir(&service.base_path).await;
     let disk_io_check = check_disk_io(&service.base_path).await;
+    let volumes = service.volume_statuses().await;
+    let volumes_check = check_volumes(&volumes);
 
-    let overall_ok = sqlite_check.ok && storage_dir_check.ok && disk_io_check.ok;
+    let overall_ok =
+        sqlite_check.ok && storage_dir_check.ok && disk_io_check.ok && volumes_check.ok;
 
     let mut checks = HashMap::new();
     checks.insert("sqlite", sqlite_check);
     checks.insert("storage_dir", storage_dir_check);
     checks.insert("disk_io", disk_io_check);
+    checks.insert("volumes", volumes_check);
 
     let body = ReadyResponse {
         status: if overall_ok {
@@ -52,6 +59,7 @@ pub async fn readyz(State(service): State<StorageService>) -> impl IntoResponse
             "error".into()
         },
         checks,
+        volumes,
     };
 
     let status = if overall_ok {
@@ -72,6 +80,7 @@ struct HealthResponse {
 struct ReadyResponse {
     status: String,
     checks: HashMap<&'static str, CheckStatus>,
+    volumes: Vec<VolumeStatus>,
 }
 
 #[derive(Serialize)]
@@ -175,3 +184,26 @@ async fn check_disk_io(base_path: &std::path::Path) -> CheckStatus {
         ),
     }
 }
+
+fn check_volumes(volumes: &[VolumeStatus]) -> CheckStatus {
+    let start = Instant::now();
+    let failing: Vec<&str> = volumes
+        .iter()
+        .filter(|volume| !volume.ok())
+        .map(|volume| volume.name.as_str())
+        .collect();
+    let info = Some(format!("{} volume(s)", volumes.len()));
+    if failing.is_empty() {
+        build_check_status(true, None, info, start)
+    } else {
+        build_check_status(
+            false,
+            Some(format!(
+                "unreachable or read-only volumes: {}",
+                failing.join(", ")
+            )),
+            info,
+            start,
+        )
+    }
+}
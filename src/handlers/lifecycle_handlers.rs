@@ -0,0 +1,337 @@
+//! HTTP handlers for bucket lifecycle configuration.
+//!
+//! These share the bucket routes and are selected by the `?lifecycle` flag in
+//! `object_handlers`:
+//! - `PUT    /{bucket}?lifecycle` — PutBucketLifecycleConfiguration
+//! - `GET    /{bucket}?lifecycle` — GetBucketLifecycleConfiguration
+//! - `DELETE /{bucket}?lifecycle` — DeleteBucketLifecycle
+//! - `POST   /{bucket}?lifecycle-dry-run` — what a configuration would do
+//!   now, without applying it (JSON, see `services::lifecycle_dry_run`)
+//!
+//! Only the `Expiration` (by `Days`), `Transition` (by `Days`, one per rule)
+//! and `AbortIncompleteMultipartUpload` actions are supported; rules naming
+//! any other action are rejected rather than stored and silently ignored.
+//! Transitions need a remote tier (see `tiering`).
+
+use crate::{
+    errors::AppError,
+    handlers::object_handlers::xml_escape,
+    models::{
+        lifecycle::{LifecycleRule, LifecycleTransition},
+        object_tag::ObjectTag,
+    },
+    services::storage_service::StorageService,
+};
+use axum::{
+    Json,
+    body::Body,
+    http::{HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, de::IgnoredAny};
+use uuid::Uuid;
+
+/// Largest accepted lifecycle configuration body.
+const MAX_LIFECYCLE_BODY: usize = 1024 * 1024;
+
+/// Body of `PUT /{bucket}?lifecycle`.
+#[derive(Debug, Deserialize)]
+struct LifecycleConfigurationReq {
+    #[serde(rename = "Rule", default)]
+    rules: Vec<RuleReq>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RuleReq {
+    #[serde(rename = "ID")]
+    id: Option<String>,
+    #[serde(rename = "Status")]
+    status: String,
+    /// Filter of the original API version, directly under the rule.
+    #[serde(rename = "Prefix")]
+    prefix: Option<String>,
+    #[serde(rename = "Filter")]
+    filter: Option<FilterReq>,
+    #[serde(rename = "Expiration")]
+    expiration: Option<ExpirationReq>,
+    #[serde(rename = "AbortIncompleteMultipartUpload")]
+    abort_incomplete: Option<AbortIncompleteReq>,
+    #[serde(rename = "Transition")]
+    transition: Option<TransitionReq>,
+    #[serde(rename = "NoncurrentVersionExpiration")]
+    noncurrent_expiration: Option<IgnoredAny>,
+    #[serde(rename = "NoncurrentVersionTransition")]
+    noncurrent_transition: Option<IgnoredAny>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FilterReq {
+    #[serde(rename = "Prefix")]
+    prefix: Option<String>,
+    #[serde(rename = "Tag")]
+    tag: Option<TagReq>,
+    #[serde(rename = "And")]
+    and: Option<AndReq>,
+}
+
+#[derive(Debug, Deserialize)]
+struct AndReq {
+    #[serde(rename = "Prefix")]
+    prefix: Option<String>,
+    #[serde(rename = "Tag", default)]
+    tags: Vec<TagReq>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TagReq {
+    #[serde(rename = "Key")]
+    key: String,
+    #[serde(rename = "Value", default)]
+    value: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct ExpirationReq {
+    #[serde(rename = "Days")]
+    days: Option<i64>,
+    #[serde(rename = "Date")]
+    date: Option<IgnoredAny>,
+    #[serde(rename = "ExpiredObjectDeleteMarker")]
+    expired_object_delete_marker: Option<IgnoredAny>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TransitionReq {
+    #[serde(rename = "Days")]
+    days: Option<i64>,
+    #[serde(rename = "Date")]
+    date: Option<IgnoredAny>,
+    #[serde(rename = "StorageClass")]
+    storage_class: String,
+}
+
+#[derive(Debug, Deserialize)]
+struct AbortIncompleteReq {
+    #[serde(rename = "DaysAfterInitiation")]
+    days_after_initiation: i64,
+}
+
+fn bad_request(message: impl Into<String>) -> AppError {
+    AppError::new(StatusCode::BAD_REQUEST, message.into())
+}
+
+impl RuleReq {
+    fn into_rule(self) -> Result<LifecycleRule, AppError> {
+        let id = self.id.unwrap_or_else(|| Uuid::new_v4().to_string());
+        let enabled = match self.status.as_str() {
+            "Enabled" => true,
+            "Disabled" => false,
+            _ => {
+                return Err(bad_request(format!(
+                    "rule `{}`: Status must be Enabled or Disabled",
+                    id
+                )));
+            }
+        };
+        if self.noncurrent_expiration.is_some() || self.noncurrent_transition.is_some() {
+            return Err(bad_request(format!(
+                "rule `{}`: only Expiration, Transition and AbortIncompleteMultipartUpload are supported",
+                id
+            )));
+        }
+        let expiration_days = match self.expiration {
+            Some(ExpirationReq {
+                days: Some(days),
+                date: None,
+                expired_object_delete_marker: None,
+            }) => Some(days),
+            Some(_) => {
+                return Err(bad_request(format!(
+                    "rule `{}`: Expiration supports Days only",
+                    id
+                )));
+            }
+            None => None,
+        };
+        let transition = match self.transition {
+            Some(TransitionReq {
+                days: Some(days),
+                date: None,
+                storage_class,
+            }) => Some(LifecycleTransition {
+                days,
+                storage_class,
+            }),
+            Some(_) => {
+                return Err(bad_request(format!(
+                    "rule `{}`: Transition supports Days only",
+                    id
+                )));
+            }
+            None => None,
+        };
+        let (prefix, tags) = match self.filter {
+            Some(FilterReq {
+                prefix,
+                tag: None,
+                and: None,
+            }) => (prefix, Vec::new()),
+            Some(FilterReq {
+                prefix: None,
+                tag: Some(tag),
+                and: None,
+            }) => (None, vec![tag]),
+            Some(FilterReq {
+                prefix: None,
+                tag: None,
+                and: Some(and),
+            }) => (and.prefix, and.tags),
+            Some(_) => {
+                return Err(bad_request(format!(
+                    "rule `{}`: Filter takes one of Prefix, Tag or And",
+                    id
+                )));
+            }
+            None => (self.prefix, Vec::new()),
+        };
+        Ok(LifecycleRule {
+            id,
+            enabled,
+            prefix: prefix.filter(|prefix| !prefix.is_empty()),
+            tags: tags
+                .into_iter()
+                .map(|tag| ObjectTag {
+                    key: tag.key,
+                    value: tag.value,
+                })
+                .collect(),
+            expiration_days,
+            abort_incomplete_days: self
+                .abort_incomplete
+                .map(|abort| abort.days_after_initiation),
+            transition,
+        })
+    }
+}
+
+fn parse_lifecycle(body: &[u8]) -> Result<Vec<LifecycleRule>, AppError> {
+    let text = std::str::from_utf8(body).map_err(|_| bad_request("request body is not UTF-8"))?;
+    let req: LifecycleConfigurationReq = quick_xml::de::from_str(text)
+        .map_err(|err| bad_request(format!("malformed LifecycleConfiguration body: {}", err)))?;
+    req.rules.into_iter().map(RuleReq::into_rule).collect()
+}
+
+/// `PUT /{bucket}?lifecycle`
+pub async fn put_bucket_lifecycle(
+    service: &StorageService,
+    bucket: &str,
+    body: Body,
+) -> Result<Response, AppError> {
+    let body = axum::body::to_bytes(body, MAX_LIFECYCLE_BODY)
+        .await
+        .map_err(|_| bad_request("LifecycleConfiguration body is too large"))?;
+    let rules = parse_lifecycle(&body)?;
+    service.put_bucket_lifecycle(bucket, &rules).await?;
+    Ok(StatusCode::OK.into_response())
+}
+
+/// `POST /{bucket}?lifecycle-dry-run`
+///
+/// Per rule of the `LifecycleConfiguration` body: the objects it would
+/// expire or transition and the uploads it would abort if it were applied
+/// now (counts, bytes and sample keys, as JSON), limited to keys under
+/// `prefix` if given. Nothing is stored.
+pub async fn lifecycle_dry_run(
+    service: &StorageService,
+    bucket: &str,
+    prefix: Option<&str>,
+    body: &[u8],
+) -> Result<Response, AppError> {
+    if body.len() > MAX_LIFECYCLE_BODY {
+        return Err(bad_request("LifecycleConfiguration body is too large"));
+    }
+    let rules = parse_lifecycle(body)?;
+    Ok(Json(service.lifecycle_dry_run(bucket, prefix, &rules).await?).into_response())
+}
+
+/// `GET /{bucket}?lifecycle`
+pub async fn get_bucket_lifecycle(
+    service: &StorageService,
+    bucket: &str,
+) -> Result<Response, AppError> {
+    let rules = service.get_bucket_lifecycle(bucket).await?;
+    let mut response = Response::new(Body::from(build_lifecycle_xml(&rules)));
+    response.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/xml"),
+    );
+    Ok(response)
+}
+
+/// `DELETE /{bucket}?lifecycle`
+pub async fn delete_bucket_lifecycle(
+    service: &StorageService,
+    bucket: &str,
+) -> Result<Response, AppError> {
+    service.delete_bucket_lifecycle(bucket).await?;
+    Ok(StatusCode::NO_CONTENT.into_response())
+}
+
+fn build_lifecycle_xml(rules: &[LifecycleRule]) -> String {
+    let mut xml = String::from(concat!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+        r#"<LifecycleConfiguration xmlns="http://s3.amazonaws.com/doc/2006-03-01/">"#
+    ));
+    for rule in rules {
+        xml.push_str(&format!("<Rule><ID>{}</ID>", xml_escape(&rule.id)));
+        let prefix = rule
+            .prefix
+            .as_deref()
+            .map(|prefix| format!("<Prefix>{}</Prefix>", xml_escape(prefix)))
+            .unwrap_or_default();
+        let tags: String = rule
+            .tags
+            .iter()
+            .map(|tag| {
+                format!(
+                    "<Tag><Key>{}</Key><Value>{}</Value></Tag>",
+                    xml_escape(&tag.key),
+                    xml_escape(&tag.value)
+                )
+            })
+            .collect();
+        match (prefix.is_empty(), rule.tags.len()) {
+            (_, 0) => xml.push_str(&format!("<Filter>{}</Filter>", prefix)),
+            (true, 1) => xml.push_str(&format!("<Filter>{}</Filter>", tags)),
+            _ => xml.push_str(&format!("<Filter><And>{}{}</And></Filter>", prefix, tags)),
+        }
+        xml.push_str(if rule.enabled {
+            "<Status>Enabled</Status>"
+        } else {
+            "<Status>Disabled</Status>"
+        });
+        if let Some(days) = rule.expiration_days {
+            xml.push_str(&format!("<Expiration><Days>{}</Days></Expiration>", days));
+        }
+        if let Some(transition) = &rule.transition {
+            xml.push_str(&format!(
+                "<Transition><Days>{}</Days><StorageClass>{}</StorageClass></Transition>",
+                transition.days,
+                xml_escape(&transition.storage_class)
+            ));
+        }
+        if let Some(days) = rule.abort_incomplete_days {
+            xml.push_str(&format!(
+                concat!(
+                    "<AbortIncompleteMultipartUpload>",
+                    "<DaysAfterInitiation>{}</DaysAfterInitiation>",
+                    "</AbortIncompleteMultipartUpload>"
+                ),
+                days
+            ));
+        }
+        xml.push_str("</Rule>");
+    }
+    xml.push_str("</LifecycleConfiguration>");
+    xml
+}
@@ -1,2 +1,12 @@
+pub mod admin_handlers;
+pub mod attributes_handlers;
+pub mod conditional;
 pub mod health_handlers;
+pub mod lifecycle_handlers;
+pub mod multipart_handlers;
+pub mod notification_handlers;
 pub mod object_handlers;
+pub mod object_lock_handlers;
+pub mod range;
+pub mod s3_headers;
+pub mod s3_path;
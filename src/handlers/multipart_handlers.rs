@@ -0,0 +1,326 @@
+//! HTTP handlers for S3 multipart uploads.
+//!
+//! These share the object routes and are selected by query flags in
+//! `object_handlers`:
+//! - `POST   /{bucket}/{*key}?uploads` — CreateMultipartUpload
+//! - `PUT    /{bucket}/{*key}?partNumber=N&uploadId=U` — UploadPart
+//! - `GET    /{bucket}/{*key}?uploadId=U` — ListParts
+//! - `GET    /{bucket}/{*key}?uploadId=U&completion` — completion progress
+//! - `POST   /{bucket}/{*key}?uploadId=U` — CompleteMultipartUpload
+//! - `DELETE /{bucket}/{*key}?uploadId=U` — AbortMultipartUpload
+
+use crate::{
+    errors::AppError,
+    handlers::{
+        object_handlers::{
+            insert_checksum_header, insert_version_header, request_checksums,
+            request_user_metadata, xml_escape,
+        },
+        object_lock_handlers,
+        s3_headers::{S3SseHeaders, S3StreamingHeaders, S3TaggingHeader},
+    },
+    services::{
+        checksum::ExpectedChecksums,
+        multipart::{CompletedPart, DEFAULT_MAX_PARTS},
+        storage_service::{PutObjectParams, StorageService},
+    },
+};
+use axum::{
+    Json,
+    body::{Body, Bytes},
+    http::{HeaderMap, HeaderName, HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use chrono::SecondsFormat;
+use futures::StreamExt;
+use serde::Deserialize;
+use std::io;
+use uuid::Uuid;
+
+/// Body of `CompleteMultipartUpload`.
+#[derive(Debug, Deserialize)]
+struct CompleteMultipartUploadReq {
+    #[serde(rename = "Part", default)]
+    parts: Vec<CompletedPartReq>,
+}
+
+#[derive(Debug, Deserialize)]
+struct CompletedPartReq {
+    #[serde(rename = "PartNumber")]
+    part_number: i64,
+    #[serde(rename = "ETag")]
+    etag: String,
+}
+
+/// An unparseable upload id can never match an upload.
+fn parse_upload_id(raw: &str) -> Result<Uuid, AppError> {
+    Uuid::parse_str(raw)
+        .map_err(|_| AppError::not_found(format!("multipart upload `{}` not found", raw)))
+}
+
+/// Append a part's `<ChecksumCRC32C>` / `<ChecksumSHA256>`, when known.
+pub(crate) fn push_part_checksums(xml: &mut String, crc32c: Option<&str>, sha256: Option<&str>) {
+    if let Some(crc32c) = crc32c {
+        xml.push_str(&format!(
+            "<ChecksumCRC32C>{}</ChecksumCRC32C>",
+            xml_escape(crc32c)
+        ));
+    }
+    if let Some(sha256) = sha256 {
+        xml.push_str(&format!(
+            "<ChecksumSHA256>{}</ChecksumSHA256>",
+            xml_escape(sha256)
+        ));
+    }
+}
+
+fn xml_response(status: StatusCode, xml: String) -> Response {
+    let mut response = Response::new(Body::from(xml));
+    *response.status_mut() = status;
+    response.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/xml"),
+    );
+    response
+}
+
+/// `POST /{bucket}/{*key}?uploads`, recording `uploader` for auto-tagging.
+pub async fn create_multipart_upload(
+    service: &StorageService,
+    bucket: &str,
+    key: &str,
+    headers: &HeaderMap,
+    uploader: String,
+) -> Result<Response, AppError> {
+    S3SseHeaders::from_headers(headers)?.ensure_supported()?;
+    let header_str = |name: HeaderName| {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.to_string())
+    };
+    let params = PutObjectParams {
+        content_type: header_str(header::CONTENT_TYPE),
+        content_encoding: header_str(header::CONTENT_ENCODING),
+        content_length: None,
+        tags: S3TaggingHeader::from_headers(headers)?.0,
+        user_metadata: request_user_metadata(headers)?,
+        acl: header_str(HeaderName::from_static("x-amz-acl")),
+        cache_control: header_str(header::CACHE_CONTROL),
+        content_disposition: header_str(header::CONTENT_DISPOSITION),
+        expires: header_str(header::EXPIRES),
+        checksums: ExpectedChecksums::default(),
+        retention: object_lock_handlers::request_retention(headers)?,
+        customer_key: None,
+        uploader: Some(uploader),
+    };
+    let upload = service.create_multipart_upload(bucket, key, params).await?;
+
+    let xml = format!(
+        concat!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+            r#"<InitiateMultipartUploadResult xmlns="http://s3.amazonaws.com/doc/2006-03-01/">"#,
+            r#"<Bucket>{}</Bucket><Key>{}</Key><UploadId>{}</UploadId>"#,
+            r#"</InitiateMultipartUploadResult>"#
+        ),
+        xml_escape(bucket),
+        xml_escape(key),
+        upload.id
+    );
+    Ok(xml_response(StatusCode::OK, xml))
+}
+
+/// `PUT /{bucket}/{*key}?partNumber=N&uploadId=U`
+///
+/// `Content-MD5` / `x-amz-checksum-*` are checked against the part's bytes.
+pub async fn upload_part(
+    service: &StorageService,
+    bucket: &str,
+    key: &str,
+    upload_id: &str,
+    part_number: i64,
+    headers: &HeaderMap,
+    body: Body,
+) -> Result<Response, AppError> {
+    let upload_id = parse_upload_id(upload_id)?;
+    let S3StreamingHeaders(streaming) = S3StreamingHeaders::from_headers(headers)?;
+    let content_length = match &streaming {
+        Some(upload) => upload.decoded_length,
+        None => headers
+            .get(header::CONTENT_LENGTH)
+            .and_then(|v| v.to_str().ok())
+            .and_then(|v| v.parse().ok()),
+    };
+    let checksums = request_checksums(headers);
+    let stream = body
+        .into_data_stream()
+        .map(|chunk| chunk.map_err(io::Error::other));
+    let stream = match streaming {
+        Some(upload) => service.decode_streaming_upload(upload, stream)?,
+        None => Box::pin(stream),
+    };
+    let part = service
+        .upload_part(
+            bucket,
+            key,
+            upload_id,
+            part_number,
+            content_length,
+            checksums,
+            stream,
+        )
+        .await?;
+
+    let mut response = Response::new(Body::empty());
+    if let Ok(value) = HeaderValue::from_str(&format!("\"{}\"", part.etag)) {
+        response.headers_mut().insert(header::ETAG, value);
+    }
+    Ok(response)
+}
+
+/// `GET /{bucket}/{*key}?uploadId=U`
+pub async fn list_parts(
+    service: &StorageService,
+    bucket: &str,
+    key: &str,
+    upload_id: &str,
+    part_number_marker: Option<i64>,
+    max_parts: Option<usize>,
+) -> Result<Response, AppError> {
+    let upload_id = parse_upload_id(upload_id)?;
+    let max_parts = max_parts.unwrap_or(DEFAULT_MAX_PARTS);
+    let listing = service
+        .list_parts(bucket, key, upload_id, part_number_marker, max_parts)
+        .await?;
+
+    let mut xml = String::from(
+        r#"<?xml version="1.0" encoding="UTF-8"?><ListPartsResult xmlns="http://s3.amazonaws.com/doc/2006-03-01/">"#,
+    );
+    xml.push_str(&format!("<Bucket>{}</Bucket>", xml_escape(bucket)));
+    xml.push_str(&format!("<Key>{}</Key>", xml_escape(key)));
+    xml.push_str(&format!("<UploadId>{}</UploadId>", listing.upload.id));
+    xml.push_str(&format!(
+        "<PartNumberMarker>{}</PartNumberMarker>",
+        part_number_marker.unwrap_or(0)
+    ));
+    if let Some(next) = listing.next_part_number_marker {
+        xml.push_str(&format!(
+            "<NextPartNumberMarker>{}</NextPartNumberMarker>",
+            next
+        ));
+    }
+    xml.push_str(&format!(
+        "<MaxParts>{}</MaxParts>",
+        max_parts.clamp(1, DEFAULT_MAX_PARTS)
+    ));
+    xml.push_str(&format!(
+        "<IsTruncated>{}</IsTruncated>",
+        listing.is_truncated
+    ));
+    xml.push_str("<StorageClass>STANDARD</StorageClass>");
+    for part in &listing.parts {
+        xml.push_str("<Part>");
+        xml.push_str(&format!("<PartNumber>{}</PartNumber>", part.part_number));
+        xml.push_str(&format!(
+            "<LastModified>{}</LastModified>",
+            part.last_modified
+                .to_rfc3339_opts(SecondsFormat::Millis, true)
+        ));
+        xml.push_str(&format!("<ETag>\"{}\"</ETag>", xml_escape(&part.etag)));
+        xml.push_str(&format!("<Size>{}</Size>", part.size_bytes));
+        push_part_checksums(
+            &mut xml,
+            part.checksum_crc32c.as_deref(),
+            part.checksum_sha256.as_deref(),
+        );
+        xml.push_str("</Part>");
+    }
+    xml.push_str("</ListPartsResult>");
+    Ok(xml_response(StatusCode::OK, xml))
+}
+
+/// `GET /{bucket}/{*key}?uploadId=U&completion`
+pub async fn get_completion_status(
+    service: &StorageService,
+    bucket: &str,
+    key: &str,
+    upload_id: &str,
+) -> Result<Response, AppError> {
+    let upload_id = parse_upload_id(upload_id)?;
+    let status = service
+        .multipart_completion_status(bucket, key, upload_id)
+        .await?;
+    Ok(Json(status).into_response())
+}
+
+/// `POST /{bucket}/{*key}?uploadId=U`
+pub async fn complete_multipart_upload(
+    service: &StorageService,
+    bucket: &str,
+    key: &str,
+    upload_id: &str,
+    body: Bytes,
+) -> Result<Response, AppError> {
+    let upload_id = parse_upload_id(upload_id)?;
+    let text = std::str::from_utf8(&body)
+        .map_err(|_| AppError::new(StatusCode::BAD_REQUEST, "request body is not UTF-8"))?;
+    let req: CompleteMultipartUploadReq = quick_xml::de::from_str(text).map_err(|err| {
+        AppError::new(
+            StatusCode::BAD_REQUEST,
+            format!("malformed CompleteMultipartUpload body: {}", err),
+        )
+    })?;
+    let parts: Vec<CompletedPart> = req
+        .parts
+        .into_iter()
+        .map(|p| CompletedPart {
+            part_number: p.part_number,
+            etag: p.etag,
+        })
+        .collect();
+
+    let object = service
+        .complete_multipart_upload(bucket, key, upload_id, &parts)
+        .await?;
+    let etag = object.etag.clone().unwrap_or_default();
+    let checksum = object
+        .checksum_sha256
+        .as_deref()
+        .map(|sha256| format!("<ChecksumSHA256>{}</ChecksumSHA256>", xml_escape(sha256)))
+        .unwrap_or_default();
+    let xml = format!(
+        concat!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+            r#"<CompleteMultipartUploadResult xmlns="http://s3.amazonaws.com/doc/2006-03-01/">"#,
+            r#"<Location>/{}/{}</Location><Bucket>{}</Bucket><Key>{}</Key>"#,
+            r#"<ETag>&quot;{}&quot;</ETag>{}"#,
+            r#"</CompleteMultipartUploadResult>"#
+        ),
+        xml_escape(bucket),
+        xml_escape(key),
+        xml_escape(bucket),
+        xml_escape(key),
+        xml_escape(&etag),
+        checksum
+    );
+    let mut response = xml_response(StatusCode::OK, xml);
+    insert_checksum_header(response.headers_mut(), &object);
+    insert_version_header(response.headers_mut(), &object);
+    Ok(response)
+}
+
+/// `DELETE /{bucket}/{*key}?uploadId=U`
+pub async fn abort_multipart_upload(
+    service: &StorageService,
+    bucket: &str,
+    key: &str,
+    upload_id: &str,
+) -> Result<Response, AppError> {
+    let upload_id = parse_upload_id(upload_id)?;
+    service
+        .abort_multipart_upload(bucket, key, upload_id)
+        .await?;
+    let mut response = Response::new(Body::empty());
+    *response.status_mut() = StatusCode::NO_CONTENT;
+    Ok(response)
+}
@@ -0,0 +1,195 @@
+//! HTTP handlers for bucket notification configuration.
+//!
+//! These share the bucket routes and are selected by the `?notification`
+//! flag in `object_handlers`:
+//! - `PUT /{bucket}?notification` — PutBucketNotificationConfiguration
+//! - `GET /{bucket}?notification` — GetBucketNotificationConfiguration
+//!
+//! Targets are HTTP webhooks, configured as `WebhookConfiguration` elements
+//! shaped like S3's `QueueConfiguration` with an `Endpoint` URL in place of
+//! the queue ARN. SNS, SQS, Lambda and EventBridge targets are rejected
+//! rather than stored and silently ignored. An empty configuration removes
+//! every target. Delivery is done by `services::notifications`.
+
+use crate::{
+    errors::AppError, handlers::object_handlers::xml_escape,
+    models::notification::NotificationTarget, services::storage_service::StorageService,
+};
+use axum::{
+    body::Body,
+    http::{HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use serde::{Deserialize, de::IgnoredAny};
+use uuid::Uuid;
+
+/// Largest accepted notification configuration body.
+const MAX_NOTIFICATION_BODY: usize = 256 * 1024;
+
+/// Body of `PUT /{bucket}?notification`.
+#[derive(Debug, Deserialize)]
+struct NotificationConfigurationReq {
+    #[serde(rename = "WebhookConfiguration", default)]
+    webhooks: Vec<WebhookReq>,
+    #[serde(rename = "TopicConfiguration", default)]
+    topics: Vec<IgnoredAny>,
+    #[serde(rename = "QueueConfiguration", default)]
+    queues: Vec<IgnoredAny>,
+    #[serde(rename = "CloudFunctionConfiguration", default)]
+    functions: Vec<IgnoredAny>,
+    #[serde(rename = "EventBridgeConfiguration")]
+    event_bridge: Option<IgnoredAny>,
+}
+
+#[derive(Debug, Deserialize)]
+struct WebhookReq {
+    #[serde(rename = "Id")]
+    id: Option<String>,
+    #[serde(rename = "Endpoint")]
+    endpoint: String,
+    #[serde(rename = "Event", default)]
+    events: Vec<String>,
+    #[serde(rename = "Filter")]
+    filter: Option<FilterReq>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FilterReq {
+    #[serde(rename = "S3Key")]
+    s3_key: Option<S3KeyReq>,
+}
+
+#[derive(Debug, Deserialize)]
+struct S3KeyReq {
+    #[serde(rename = "FilterRule", default)]
+    rules: Vec<FilterRuleReq>,
+}
+
+#[derive(Debug, Deserialize)]
+struct FilterRuleReq {
+    #[serde(rename = "Name")]
+    name: String,
+    #[serde(rename = "Value")]
+    value: String,
+}
+
+fn bad_request(message: impl Into<String>) -> AppError {
+    AppError::new(StatusCode::BAD_REQUEST, message.into())
+}
+
+impl WebhookReq {
+    fn into_target(self) -> Result<NotificationTarget, AppError> {
+        let id = self.id.unwrap_or_else(|| Uuid::new_v4().to_string());
+        let mut prefix = None;
+        let mut suffix = None;
+        let rules = self
+            .filter
+            .and_then(|filter| filter.s3_key)
+            .map(|key| key.rules)
+            .unwrap_or_default();
+        for rule in rules {
+            let slot = match rule.name.to_ascii_lowercase().as_str() {
+                "prefix" => &mut prefix,
+                "suffix" => &mut suffix,
+                _ => {
+                    return Err(bad_request(format!(
+                        "target `{}`: FilterRule Name must be prefix or suffix",
+                        id
+                    )));
+                }
+            };
+            if slot.replace(rule.value).is_some() {
+                return Err(bad_request(format!(
+                    "target `{}`: {} is given more than once",
+                    id, rule.name
+                )));
+            }
+        }
+        Ok(NotificationTarget {
+            id,
+            endpoint: self.endpoint,
+            events: self.events,
+            prefix: prefix.filter(|prefix| !prefix.is_empty()),
+            suffix: suffix.filter(|suffix| !suffix.is_empty()),
+        })
+    }
+}
+
+/// `PUT /{bucket}?notification`
+pub async fn put_bucket_notification(
+    service: &StorageService,
+    bucket: &str,
+    body: Body,
+) -> Result<Response, AppError> {
+    let body = axum::body::to_bytes(body, MAX_NOTIFICATION_BODY)
+        .await
+        .map_err(|_| bad_request("NotificationConfiguration body is too large"))?;
+    let text = std::str::from_utf8(&body).map_err(|_| bad_request("request body is not UTF-8"))?;
+    let req: NotificationConfigurationReq = quick_xml::de::from_str(text)
+        .map_err(|err| bad_request(format!("malformed NotificationConfiguration body: {}", err)))?;
+    if !req.topics.is_empty()
+        || !req.queues.is_empty()
+        || !req.functions.is_empty()
+        || req.event_bridge.is_some()
+    {
+        return Err(bad_request(
+            "only WebhookConfiguration targets are supported",
+        ));
+    }
+    let targets = req
+        .webhooks
+        .into_iter()
+        .map(WebhookReq::into_target)
+        .collect::<Result<Vec<_>, _>>()?;
+    service.put_bucket_notification(bucket, &targets).await?;
+    Ok(StatusCode::OK.into_response())
+}
+
+/// `GET /{bucket}?notification`
+pub async fn get_bucket_notification(
+    service: &StorageService,
+    bucket: &str,
+) -> Result<Response, AppError> {
+    let targets = service.get_bucket_notification(bucket).await?;
+    let mut response = Response::new(Body::from(build_notification_xml(&targets)));
+    response.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/xml"),
+    );
+    Ok(response)
+}
+
+fn build_notification_xml(targets: &[NotificationTarget]) -> String {
+    let mut xml = String::from(concat!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+        r#"<NotificationConfiguration xmlns="http://s3.amazonaws.com/doc/2006-03-01/">"#
+    ));
+    for target in targets {
+        xml.push_str(&format!(
+            "<WebhookConfiguration><Id>{}</Id><Endpoint>{}</Endpoint>",
+            xml_escape(&target.id),
+            xml_escape(&target.endpoint)
+        ));
+        for event in &target.events {
+            xml.push_str(&format!("<Event>{}</Event>", xml_escape(event)));
+        }
+        let rules: String = [("prefix", &target.prefix), ("suffix", &target.suffix)]
+            .into_iter()
+            .filter_map(|(name, value)| {
+                value.as_deref().map(|value| {
+                    format!(
+                        "<FilterRule><Name>{}</Name><Value>{}</Value></FilterRule>",
+                        name,
+                        xml_escape(value)
+                    )
+                })
+            })
+            .collect();
+        if !rules.is_empty() {
+            xml.push_str(&format!("<Filter><S3Key>{}</S3Key></Filter>", rules));
+        }
+        xml.push_str("</WebhookConfiguration>");
+    }
+    xml.push_str("</NotificationConfiguration>");
+    xml
+}
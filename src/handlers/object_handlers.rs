@@ -4,22 +4,55 @@
 
 use crate::{
     errors::AppError,
-    models::object::Object,
-    services::storage_service::{ListObjectsParams, ListObjectsResult, StorageService},
+    handlers::{
+        attributes_handlers,
+        conditional::{self, Precondition},
+        lifecycle_handlers, multipart_handlers, notification_handlers, object_lock_handlers,
+        range::{self, RangeOutcome},
+        s3_headers::{
+            S3ConditionalHeaders, S3CopySource, S3SseHeaders, S3StreamingHeaders, S3TaggingHeader,
+        },
+        s3_path::S3ObjectPath,
+    },
+    middleware::authorizer::Principal,
+    models::{
+        bucket::Bucket, object::Object, object_metadata::ObjectMetadata, object_tag::ObjectTag,
+    },
+    services::{
+        aws_chunked,
+        batch_delete::{DeleteOutcome, DeleteTarget},
+        changes::{self, MAX_CHANGES},
+        checksum::{self, ExpectedChecksums},
+        mapped_read::ObjectBody,
+        partition::KeyPartition,
+        sse_c::SseCustomerKey,
+        storage_service::{
+            ListObjectsParams, ListObjectsResult, MAX_LIST_KEYS, PutObjectParams, StorageError,
+            StorageService,
+        },
+        user_metadata::{CopySource, MetadataDirective},
+        versioning::{DEFAULT_MAX_VERSION_KEYS, ListVersionsParams, ListVersionsResult},
+    },
 };
 use axum::{
     Json,
-    body::Body,
-    extract::{Path, Query, State},
+    body::{Body, Bytes},
+    extract::{FromRequest, Path, Query, Request, State},
     http::{HeaderMap, HeaderName, HeaderValue, StatusCode, header},
     response::{IntoResponse, Response},
 };
 use base64::{Engine as _, engine::general_purpose};
-use chrono::SecondsFormat;
+use chrono::{SecondsFormat, Utc};
 use futures::StreamExt;
-use serde::Deserialize;
-use std::io;
+use percent_encoding::{AsciiSet, NON_ALPHANUMERIC, utf8_percent_encode};
+use serde::{Deserialize, Serialize};
+use std::{
+    collections::BTreeMap,
+    io::{self, SeekFrom},
+};
+use tokio::io::{AsyncReadExt, AsyncSeekExt};
 use tokio_util::io::ReaderStream;
+use uuid::Uuid;
 
 /// Query params accepted by ListObjectsV2.
 #[derive(Debug, Deserialize)]
@@ -34,6 +67,152 @@ pub struct ListObjectsV2Query {
     pub continuation_token: Option<String>,
     #[serde(rename = "start-after")]
     pub start_after: Option<String>,
+    /// ListObjects (V1): list keys after this one.
+    pub marker: Option<String>,
+    /// Extension: inclusive upper bound on returned keys.
+    #[serde(rename = "end-key")]
+    pub end_key: Option<String>,
+    /// Extension: return up to N key-range partitions instead of a listing.
+    #[serde(rename = "list-partitions")]
+    pub list_partitions: Option<usize>,
+    /// Extension: only objects in this storage class (e.g. `GLACIER`).
+    #[serde(rename = "storage-class")]
+    pub storage_class: Option<String>,
+    /// Extension: only objects of at least this many bytes.
+    #[serde(rename = "min-size")]
+    pub min_size: Option<u64>,
+    /// Extension: only objects of at most this many bytes.
+    #[serde(rename = "max-size")]
+    pub max_size: Option<u64>,
+    /// `?versioning`: return the bucket's versioning state instead.
+    pub versioning: Option<String>,
+    /// `?versions`: ListObjectVersions instead of ListObjectsV2.
+    pub versions: Option<String>,
+    #[serde(rename = "key-marker")]
+    pub key_marker: Option<String>,
+    #[serde(rename = "version-id-marker")]
+    pub version_id_marker: Option<String>,
+    /// Extension: `?validate` checks the bucket name (and `key`, if given)
+    /// against the server's rules without creating anything.
+    pub validate: Option<String>,
+    pub key: Option<String>,
+    /// `?location`: GetBucketLocation instead of a listing.
+    pub location: Option<String>,
+    /// `?lifecycle`: GetBucketLifecycleConfiguration instead of a listing.
+    pub lifecycle: Option<String>,
+    /// `?notification`: GetBucketNotificationConfiguration instead of a
+    /// listing.
+    pub notification: Option<String>,
+    /// `?object-lock`: GetObjectLockConfiguration instead of a listing.
+    #[serde(rename = "object-lock")]
+    pub object_lock: Option<String>,
+    /// Extension: `?deleted=true` lists soft-deleted keys (JSON) instead.
+    pub deleted: Option<bool>,
+    /// Extension: `?list-stream` streams every matching object as NDJSON.
+    #[serde(rename = "list-stream")]
+    pub list_stream: Option<String>,
+    /// Extension: `?changes[&since=C]` returns the bucket's change feed
+    /// (JSON) after cursor `C` instead, of keys under `prefix` if given.
+    pub changes: Option<String>,
+    pub since: Option<String>,
+    /// `url` percent-encodes keys, prefixes and the delimiter in the listing.
+    #[serde(rename = "encoding-type")]
+    pub encoding_type: Option<String>,
+    /// Include each object's owner (the bucket owner).
+    #[serde(rename = "fetch-owner")]
+    pub fetch_owner: Option<bool>,
+}
+
+/// Sub-resource flags recognised on object routes (`?recycled`, `?recover`,
+/// `?restore`, `?versionId`, and the multipart upload parameters).
+#[derive(Debug, Default, Deserialize)]
+pub struct ObjectQuery {
+    #[serde(rename = "versionId")]
+    pub version_id: Option<String>,
+    pub recycled: Option<String>,
+    pub recover: Option<String>,
+    pub restore: Option<String>,
+    pub uploads: Option<String>,
+    #[serde(rename = "uploadId")]
+    pub upload_id: Option<String>,
+    #[serde(rename = "partNumber")]
+    pub part_number: Option<i64>,
+    #[serde(rename = "max-parts")]
+    pub max_parts: Option<usize>,
+    #[serde(rename = "part-number-marker")]
+    pub part_number_marker: Option<i64>,
+    /// With `uploadId`: report the progress of completing the upload.
+    pub completion: Option<String>,
+    /// `?tagging`: Put/Get/DeleteObjectTagging.
+    pub tagging: Option<String>,
+    /// `?attributes`: GetObjectAttributes.
+    pub attributes: Option<String>,
+    /// `response-*`: GET/HEAD header overrides (see `response_overrides`).
+    #[serde(rename = "response-content-type")]
+    pub response_content_type: Option<String>,
+    #[serde(rename = "response-content-language")]
+    pub response_content_language: Option<String>,
+    #[serde(rename = "response-expires")]
+    pub response_expires: Option<String>,
+    #[serde(rename = "response-cache-control")]
+    pub response_cache_control: Option<String>,
+    #[serde(rename = "response-content-disposition")]
+    pub response_content_disposition: Option<String>,
+    #[serde(rename = "response-content-encoding")]
+    pub response_content_encoding: Option<String>,
+}
+
+impl ObjectQuery {
+    /// Headers a presigned download asked to have replaced, so a link can
+    /// force a filename or MIME type without touching the stored object.
+    fn response_overrides(&self) -> Result<Vec<(HeaderName, HeaderValue)>, AppError> {
+        [
+            (header::CONTENT_TYPE, &self.response_content_type),
+            (header::CONTENT_LANGUAGE, &self.response_content_language),
+            (header::EXPIRES, &self.response_expires),
+            (header::CACHE_CONTROL, &self.response_cache_control),
+            (
+                header::CONTENT_DISPOSITION,
+                &self.response_content_disposition,
+            ),
+            (header::CONTENT_ENCODING, &self.response_content_encoding),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| value.as_deref().map(|value| (name, value)))
+        .map(|(name, value)| {
+            HeaderValue::from_str(value)
+                .map(|value| (name.clone(), value))
+                .map_err(|_| {
+                    AppError::new(
+                        StatusCode::BAD_REQUEST,
+                        format!("invalid value for response-{}", name),
+                    )
+                })
+        })
+        .collect()
+    }
+}
+
+/// JSON body returned by `GET /{bucket}?list-partitions=N`.
+#[derive(Debug, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ListPartitionsResponse {
+    pub bucket: String,
+    pub prefix: Option<String>,
+    pub partitions: Vec<KeyPartition>,
+}
+
+/// Query params accepted by `DELETE /{bucket}`.
+#[derive(Debug, Default, Deserialize)]
+pub struct DeleteBucketQuery {
+    /// Extension: delete every key under this prefix in the background
+    /// instead of deleting the bucket.
+    pub prefix: Option<String>,
+    /// `?lifecycle`: DeleteBucketLifecycle instead of deleting the bucket.
+    pub lifecycle: Option<String>,
+    /// Extension: `?force=true` deletes a bucket that still holds objects,
+    /// together with them.
+    pub force: Option<bool>,
 }
 
 /// Minimal request body for `PUT /{bucket}` (create bucket).
@@ -41,34 +220,192 @@ pub struct ListObjectsV2Query {
 pub struct CreateBucketReq {
     #[serde(rename = "LocationConstraint")]
     pub location_constraint: Option<String>,
+    /// Extension: create the bucket with a configured template's settings.
+    #[serde(rename = "Template")]
+    pub template: Option<String>,
+}
+
+/// Query params accepted by `PUT /{bucket}` and `POST /{bucket}`.
+#[derive(Debug, Default, Deserialize)]
+pub struct BucketQuery {
+    /// `?versioning`: PutBucketVersioning instead of creating the bucket.
+    pub versioning: Option<String>,
+    /// `?delete`: DeleteObjects.
+    pub delete: Option<String>,
+    /// `?lifecycle`: PutBucketLifecycleConfiguration instead of creating the
+    /// bucket.
+    pub lifecycle: Option<String>,
+    /// `?notification`: PutBucketNotificationConfiguration instead of
+    /// creating the bucket.
+    pub notification: Option<String>,
+    /// `?object-lock`: PutObjectLockConfiguration instead of creating the
+    /// bucket.
+    #[serde(rename = "object-lock")]
+    pub object_lock: Option<String>,
+    /// `POST ?lifecycle-dry-run`: evaluate a lifecycle configuration without
+    /// applying it.
+    #[serde(rename = "lifecycle-dry-run")]
+    pub lifecycle_dry_run: Option<String>,
+    /// With `lifecycle-dry-run`: only evaluate keys under this prefix.
+    pub prefix: Option<String>,
+}
+
+/// Body of `POST /{bucket}?delete`.
+#[derive(Debug, Deserialize)]
+struct DeleteObjectsReq {
+    #[serde(rename = "Quiet", default)]
+    quiet: bool,
+    #[serde(rename = "Object", default)]
+    objects: Vec<ObjectIdentifierReq>,
+}
+
+#[derive(Debug, Deserialize)]
+struct ObjectIdentifierReq {
+    #[serde(rename = "Key")]
+    key: String,
+    #[serde(rename = "VersionId")]
+    version_id: Option<String>,
+}
+
+/// Body of `PUT /{bucket}/{*key}?tagging`.
+#[derive(Debug, Deserialize)]
+struct TaggingReq {
+    #[serde(rename = "TagSet")]
+    tag_set: TagSetReq,
+}
+
+#[derive(Debug, Deserialize)]
+struct TagSetReq {
+    #[serde(rename = "Tag", default)]
+    tags: Vec<TagReq>,
+}
+
+#[derive(Debug, Deserialize)]
+struct TagReq {
+    #[serde(rename = "Key")]
+    key: String,
+    #[serde(rename = "Value", default)]
+    value: String,
+}
+
+/// Header prefix of user-defined metadata.
+const USER_METADATA_PREFIX: &str = "x-amz-meta-";
+
+/// Largest accepted PutObjectTagging body.
+const MAX_TAGGING_BODY: usize = 64 * 1024;
+
+/// Body of `PUT /{bucket}?versioning`.
+#[derive(Debug, Deserialize)]
+struct VersioningConfigurationReq {
+    #[serde(rename = "Status")]
+    status: Option<String>,
 }
 
 /// Upload an object to `/{bucket}/{*key}`.
+///
+/// `If-Match`/`If-None-Match`/`If-Unmodified-Since` are checked against the
+/// current object first (`412` when they fail). With
+/// `?partNumber=N&uploadId=U`, stores a multipart upload part instead; with
+/// `?tagging`, replaces the tag set of the current object. Tags sent in
+/// `x-amz-tagging`, `x-amz-meta-*` and `x-amz-acl` headers are stored with
+/// the new object (the bucket may supply or require the ACL). With `x-amz-copy-source`, copies that object instead
+/// (CopyObject, honouring `x-amz-metadata-directive`). With the SSE-C
+/// customer key headers, the payload is encrypted with that key (see
+/// `sse_c`). `aws-chunked` bodies are decoded on the way in (see
+/// `aws_chunked`).
+#[allow(clippy::too_many_arguments)]
 pub async fn upload_object(
     State(service): State<StorageService>,
-    Path((bucket, key)): Path<(String, String)>,
+    S3ObjectPath { bucket, key }: S3ObjectPath,
+    Query(q): Query<ObjectQuery>,
+    conditions: S3ConditionalHeaders,
+    copy_source: Option<S3CopySource>,
+    sse: S3SseHeaders,
+    S3TaggingHeader(tags): S3TaggingHeader,
+    S3StreamingHeaders(streaming): S3StreamingHeaders,
+    Principal(principal): Principal,
     headers: HeaderMap,
     body: Body,
-) -> Result<impl IntoResponse, AppError> {
-    let content_type = headers
-        .get(header::CONTENT_TYPE)
-        .and_then(|v| v.to_str().ok())
-        .map(|v| v.to_string());
+) -> Result<Response, AppError> {
+    if q.tagging.is_some() {
+        return put_object_tagging(&service, &bucket, &key, body).await;
+    }
+    match (q.upload_id.as_deref(), q.part_number) {
+        (Some(upload_id), Some(part_number)) => {
+            return multipart_handlers::upload_part(
+                &service,
+                &bucket,
+                &key,
+                upload_id,
+                part_number,
+                &headers,
+                body,
+            )
+            .await;
+        }
+        (None, None) => {}
+        _ => {
+            return Err(AppError::new(
+                StatusCode::BAD_REQUEST,
+                "UploadPart requires both partNumber and uploadId",
+            ));
+        }
+    }
+
+    check_write_preconditions(&service, &bucket, &key, &conditions).await?;
+
+    let header_str = |name: HeaderName| {
+        headers
+            .get(name)
+            .and_then(|v| v.to_str().ok())
+            .map(|v| v.to_string())
+    };
+    let params = PutObjectParams {
+        content_type: header_str(header::CONTENT_TYPE),
+        content_encoding: header_str(header::CONTENT_ENCODING),
+        content_length: match &streaming {
+            Some(upload) => upload.decoded_length,
+            None => header_str(header::CONTENT_LENGTH).and_then(|v| v.parse().ok()),
+        },
+        tags,
+        user_metadata: request_user_metadata(&headers)?,
+        acl: header_str(HeaderName::from_static("x-amz-acl")),
+        cache_control: header_str(header::CACHE_CONTROL),
+        content_disposition: header_str(header::CONTENT_DISPOSITION),
+        expires: header_str(header::EXPIRES),
+        checksums: request_checksums(&headers),
+        retention: object_lock_handlers::request_retention(&headers)?,
+        customer_key: sse.customer_key,
+        uploader: Some(principal),
+    };
+    if let Some(S3CopySource(source)) = copy_source {
+        return copy_object(&service, &bucket, &key, source, &headers, params).await;
+    }
 
     let stream = body
         .into_data_stream()
-        .map(|chunk| chunk.map_err(|err| io::Error::new(io::ErrorKind::Other, err)));
+        .map(|chunk| chunk.map_err(io::Error::other));
+    let stream = match streaming {
+        Some(upload) => service.decode_streaming_upload(upload, stream)?,
+        None => Box::pin(stream),
+    };
 
+    let customer_key = params.customer_key.clone();
     let object = service
-        .upload_object_stream(&bucket, &key, content_type, stream)
+        .upload_object_stream(&bucket, &key, params, stream)
         .await?;
 
     let etag = object.etag.as_ref().map(|e| format!("\"{}\"", e));
     let mut resp_headers = HeaderMap::new();
-    if let Some(value) = etag.as_deref() {
-        if let Ok(header_value) = HeaderValue::from_str(value) {
-            resp_headers.insert(header::ETAG, header_value);
-        }
+    if let Some(value) = etag.as_deref()
+        && let Ok(header_value) = HeaderValue::from_str(value)
+    {
+        resp_headers.insert(header::ETAG, header_value);
+    }
+    insert_checksum_header(&mut resp_headers, &object);
+    insert_version_header(&mut resp_headers, &object);
+    if let Some(customer_key) = customer_key.as_ref() {
+        insert_customer_key_headers(&mut resp_headers, customer_key);
     }
 
     let mut response = Response::new(Body::empty());
@@ -78,17 +415,220 @@ pub async fn upload_object(
 }
 
 /// Download an object `/{bucket}/{*key}` as a streaming response.
+///
+/// A single `Range` is served as `206 Partial Content` (subject to
+/// `If-Range`); the bucket's `Cache-Control`/`Expires` defaults are added.
+/// Conditional headers are honoured (`304 Not Modified`, `412`).
+///
+/// With `?versionId=V`, reads that version instead of the current one. With
+/// `?recycled`, lists the recoverable payloads displaced by earlier
+/// overwrites instead; with `?uploadId=U`, lists the parts of a multipart
+/// upload (or, adding `&completion`, reports the progress of completing it);
+/// with `?tagging`, returns the object's tag set; with
+/// `?attributes`, answers GetObjectAttributes. `response-*`
+/// parameters (`response-content-type`, `response-content-disposition`, ...)
+/// replace the matching response headers. With access analytics on, the
+/// read is counted for storage class analysis. Full downloads are checked
+/// against the stored checksums when sent with `x-amz-checksum-mode:
+/// ENABLED` or when the server verifies every read. Objects encrypted with
+/// a customer key are only served to requests sending that key.
 pub async fn get_object(
     State(service): State<StorageService>,
-    Path((bucket, key)): Path<(String, String)>,
+    S3ObjectPath { bucket, key }: S3ObjectPath,
+    Query(q): Query<ObjectQuery>,
+    conditions: S3ConditionalHeaders,
+    sse: S3SseHeaders,
+    headers: HeaderMap,
 ) -> Result<Response, AppError> {
-    let (meta, file) = service.get_object_reader(&bucket, &key).await?;
-    let stream = ReaderStream::new(file);
-    let body = Body::from_stream(stream);
+    if let Some(upload_id) = q.upload_id.as_deref() {
+        if q.completion.is_some() {
+            return multipart_handlers::get_completion_status(&service, &bucket, &key, upload_id)
+                .await;
+        }
+        return multipart_handlers::list_parts(
+            &service,
+            &bucket,
+            &key,
+            upload_id,
+            q.part_number_marker,
+            q.max_parts,
+        )
+        .await;
+    }
+    if q.recycled.is_some() {
+        let recycled = service.list_recycled(&bucket, &key).await?;
+        return Ok(Json(recycled).into_response());
+    }
+    if q.tagging.is_some() {
+        let tags = service.get_object_tags(&bucket, &key).await?;
+        return Ok(xml_response(build_tagging_xml(&tags)));
+    }
+    if q.attributes.is_some() {
+        return attributes_handlers::get_object_attributes(
+            &service,
+            &bucket,
+            &key,
+            q.version_id.as_deref(),
+            &sse,
+            &headers,
+        )
+        .await;
+    }
 
-    let mut response = Response::new(body);
-    *response.status_mut() = StatusCode::OK;
-    set_object_headers(response.headers_mut(), &meta, Some(meta.size_bytes));
+    let overrides = q.response_overrides()?;
+    let verify = checksum_mode_enabled(&headers)? || service.options.verify_read_checksums;
+    let bucket_rec = service.fetch_bucket(&bucket).await?;
+    let (meta, file) = match q.version_id.as_deref() {
+        Some(version_id) => {
+            service
+                .get_object_version_reader(&bucket, &key, version_id)
+                .await?
+        }
+        None => service.get_object_reader(&bucket, &key).await?,
+    };
+    let cipher = service
+        .payload_cipher(&meta, sse.customer_key.as_ref())
+        .await?;
+    if let Some(response) = read_precondition_response(&conditions, &meta, &bucket_rec)? {
+        return Ok(response);
+    }
+    let size = meta.size_bytes.max(0) as u64;
+    // Tags and user metadata belong to the current version only.
+    let (tag_count, user_metadata) = match q.version_id {
+        Some(_) => (0, Vec::new()),
+        None => (
+            service.object_tag_count(&meta).await?,
+            service.user_metadata(&meta).await?,
+        ),
+    };
+    let payload = service.object_body(&meta, file).await?;
+
+    let (mut response, served) = match range::evaluate(&headers, &meta) {
+        RangeOutcome::Full => {
+            let expected = if verify {
+                checksum::stored_checksums(&meta)
+            } else {
+                ExpectedChecksums::default()
+            };
+            let body = match payload {
+                ObjectBody::File(file) => {
+                    let (bucket, key) = (bucket.clone(), key.clone());
+                    let stored = ReaderStream::new(file);
+                    let plain = match cipher.as_ref() {
+                        Some(cipher) => cipher.decrypt_stream(stored, 0),
+                        None => Box::pin(stored),
+                    };
+                    let stream = checksum::verify_stream(plain, expected).inspect(move |chunk| {
+                        if let Err(err) = chunk
+                            && let Some(mismatch) = checksum::mismatch(err)
+                        {
+                            tracing::error!(
+                                "aborted download of corrupt payload {}/{}: {}",
+                                bucket,
+                                key,
+                                mismatch
+                            );
+                        }
+                    });
+                    Body::from_stream(stream)
+                }
+                ObjectBody::Mapped(bytes) => {
+                    let bytes = match cipher.as_ref() {
+                        Some(cipher) => cipher.decrypt_bytes(&bytes, 0),
+                        None => bytes,
+                    };
+                    checksum::verify_bytes(&bytes, expected).map_err(|mismatch| {
+                        StorageError::CorruptPayload {
+                            key: key.clone(),
+                            reason: mismatch.to_string(),
+                        }
+                    })?;
+                    Body::from(bytes)
+                }
+            };
+            let mut response = Response::new(body);
+            set_object_headers(response.headers_mut(), &meta, Some(meta.size_bytes));
+            (response, size)
+        }
+        RangeOutcome::Partial(range) => {
+            // The stored bytes of a range are those of the plaintext range,
+            // so encrypted payloads are decrypted from `range.start`.
+            let body = match payload {
+                ObjectBody::File(mut file) => {
+                    match service
+                        .cached_range(&meta, &mut file, range.start, range.end)
+                        .await?
+                    {
+                        Some(bytes) => match cipher.as_ref() {
+                            Some(cipher) => Body::from(cipher.decrypt_bytes(&bytes, range.start)),
+                            None => Body::from(bytes),
+                        },
+                        None => {
+                            file.seek(SeekFrom::Start(range.start))
+                                .await
+                                .map_err(|err| AppError::internal(err.to_string()))?;
+                            let stored = ReaderStream::new(file.take(range.length()));
+                            match cipher.as_ref() {
+                                Some(cipher) => {
+                                    Body::from_stream(cipher.decrypt_stream(stored, range.start))
+                                }
+                                None => Body::from_stream(stored),
+                            }
+                        }
+                    }
+                }
+                ObjectBody::Mapped(bytes) => {
+                    let stored = bytes.slice(range.start as usize..=range.end as usize);
+                    match cipher.as_ref() {
+                        Some(cipher) => Body::from(cipher.decrypt_bytes(&stored, range.start)),
+                        None => Body::from(stored),
+                    }
+                }
+            };
+            let mut response = Response::new(body);
+            *response.status_mut() = StatusCode::PARTIAL_CONTENT;
+            set_object_headers(response.headers_mut(), &meta, Some(range.length() as i64));
+            if let Ok(value) = HeaderValue::from_str(&range.content_range(size)) {
+                response.headers_mut().insert(header::CONTENT_RANGE, value);
+            }
+            (response, range.length())
+        }
+        RangeOutcome::Unsatisfiable => {
+            let mut response = Response::new(Body::empty());
+            *response.status_mut() = StatusCode::RANGE_NOT_SATISFIABLE;
+            if let Ok(value) = HeaderValue::from_str(&format!("bytes */{}", size)) {
+                response.headers_mut().insert(header::CONTENT_RANGE, value);
+            }
+            return Ok(response);
+        }
+    };
+    set_cache_headers(response.headers_mut(), &bucket_rec, &meta);
+    insert_user_metadata_headers(response.headers_mut(), &user_metadata);
+    insert_lock_headers(&service, response.headers_mut(), &meta).await?;
+    if q.version_id.is_none() {
+        insert_replication_header(&service, response.headers_mut(), &meta).await?;
+    }
+    if let Some(customer_key) = sse.customer_key.as_ref() {
+        insert_customer_key_headers(response.headers_mut(), customer_key);
+    }
+    for (name, value) in overrides {
+        response.headers_mut().insert(name, value);
+    }
+    if tag_count > 0 {
+        response.headers_mut().insert(
+            HeaderName::from_static("x-amz-tagging-count"),
+            HeaderValue::from(tag_count),
+        );
+    }
+    if let Err(err) = service.record_read(&meta, served).await {
+        tracing::debug!("failed to record read of `{}/{}`: {}", bucket, key, err);
+    }
+    if let Err(err) = service
+        .record_prefix_transfer(meta.bucket_id, &meta.key, 0, served)
+        .await
+    {
+        tracing::debug!("failed to record download of `{}/{}`: {}", bucket, key, err);
+    }
 
     Ok(response)
 }
@@ -96,22 +636,95 @@ pub async fn get_object(
 /// HEAD `/{bucket}/{*key}` — same headers as GET but no body.
 pub async fn head_object(
     State(service): State<StorageService>,
-    Path((bucket, key)): Path<(String, String)>,
+    S3ObjectPath { bucket, key }: S3ObjectPath,
+    Query(q): Query<ObjectQuery>,
+    conditions: S3ConditionalHeaders,
+    sse: S3SseHeaders,
+    headers: HeaderMap,
 ) -> Result<Response, AppError> {
-    let meta = service.get_object_metadata(&bucket, &key).await?;
+    let overrides = q.response_overrides()?;
+    checksum_mode_enabled(&headers)?;
+    let bucket_rec = service.fetch_bucket(&bucket).await?;
+    let meta = match q.version_id.as_deref() {
+        Some(version_id) => {
+            service
+                .get_object_version_metadata(&bucket, &key, version_id)
+                .await?
+        }
+        None => service.get_object_metadata(&bucket, &key).await?,
+    };
+    // Checks the customer key, as HEAD of an encrypted object needs it too.
+    service
+        .payload_cipher(&meta, sse.customer_key.as_ref())
+        .await?;
+    if let Some(response) = read_precondition_response(&conditions, &meta, &bucket_rec)? {
+        return Ok(response);
+    }
+    let user_metadata = match q.version_id {
+        Some(_) => Vec::new(),
+        None => service.user_metadata(&meta).await?,
+    };
     let mut response = Response::new(Body::empty());
     *response.status_mut() = StatusCode::OK;
     set_object_headers(response.headers_mut(), &meta, Some(meta.size_bytes));
+    set_cache_headers(response.headers_mut(), &bucket_rec, &meta);
+    insert_user_metadata_headers(response.headers_mut(), &user_metadata);
+    insert_lock_headers(&service, response.headers_mut(), &meta).await?;
+    if q.version_id.is_none() {
+        insert_replication_header(&service, response.headers_mut(), &meta).await?;
+    }
+    if let Some(customer_key) = sse.customer_key.as_ref() {
+        insert_customer_key_headers(response.headers_mut(), customer_key);
+    }
+    for (name, value) in overrides {
+        response.headers_mut().insert(name, value);
+    }
 
     Ok(response)
 }
 
 /// DELETE `/{bucket}/{*key}` — soft-delete object
+///
+/// In a versioned bucket this adds a delete marker and keeps the history;
+/// with `?versionId=V`, that version is removed permanently instead. With
+/// `?uploadId=U`, aborts a multipart upload; with `?tagging`, removes the
+/// object's tags. `If-Match`/
+/// `If-Unmodified-Since` guard the current object (`412` when they fail).
 pub async fn delete_object(
     State(service): State<StorageService>,
-    Path((bucket, key)): Path<(String, String)>,
+    S3ObjectPath { bucket, key }: S3ObjectPath,
+    Query(q): Query<ObjectQuery>,
+    conditions: S3ConditionalHeaders,
 ) -> Result<Response, AppError> {
-    let _meta = service.delete_object(&bucket, &key).await?;
+    if let Some(upload_id) = q.upload_id.as_deref() {
+        return multipart_handlers::abort_multipart_upload(&service, &bucket, &key, upload_id)
+            .await;
+    }
+    if q.tagging.is_some() {
+        let object = service.delete_object_tags(&bucket, &key).await?;
+        let mut response = StatusCode::NO_CONTENT.into_response();
+        insert_version_header(response.headers_mut(), &object);
+        return Ok(response);
+    }
+    if let Some(version_id) = q.version_id.as_deref() {
+        let deleted = service
+            .delete_object_version(&bucket, &key, version_id)
+            .await?;
+        let mut response = StatusCode::NO_CONTENT.into_response();
+        let headers = response.headers_mut();
+        if let Ok(value) = HeaderValue::from_str(&deleted.version_id) {
+            headers.insert(HeaderName::from_static("x-amz-version-id"), value);
+        }
+        if deleted.delete_marker {
+            headers.insert(
+                HeaderName::from_static("x-amz-delete-marker"),
+                HeaderValue::from_static("true"),
+            );
+        }
+        return Ok(response);
+    }
+    check_write_preconditions(&service, &bucket, &key, &conditions).await?;
+    let meta = service.delete_object(&bucket, &key).await?;
 
     let xml = format!(
         concat!(
@@ -135,26 +748,205 @@ pub async fn delete_object(
         HeaderName::from_static("x-amz-delete-marker"),
         HeaderValue::from_static("true"),
     );
+    insert_version_header(headers, &meta);
     *response.status_mut() = StatusCode::NO_CONTENT;
     Ok(response)
 }
 
-/// GET `/{bucket}` — list objects, supports ?prefix=&delimiter=&max-keys=
-pub async fn list_objects(
+/// POST `/{bucket}/{*key}?recover` — restore the payload displaced by the
+/// most recent overwrite; `?restore` undeletes a soft-deleted key.
+///
+/// Also `?uploads` (initiate) and `?uploadId=U` (complete) for multipart
+/// uploads.
+pub async fn post_object(
     State(service): State<StorageService>,
-    Path(bucket): Path<String>,
-    Query(q): Query<ListObjectsV2Query>,
+    S3ObjectPath { bucket, key }: S3ObjectPath,
+    Query(q): Query<ObjectQuery>,
+    Principal(principal): Principal,
+    headers: HeaderMap,
+    body: Bytes,
 ) -> Result<Response, AppError> {
-    let list_type = q.list_type.unwrap_or(2);
-    if list_type != 2 {
+    if q.uploads.is_some() {
+        return multipart_handlers::create_multipart_upload(
+            &service, &bucket, &key, &headers, principal,
+        )
+        .await;
+    }
+    if let Some(upload_id) = q.upload_id.as_deref() {
+        return multipart_handlers::complete_multipart_upload(
+            &service, &bucket, &key, upload_id, body,
+        )
+        .await;
+    }
+    let object = if q.restore.is_some() {
+        service.restore_deleted(&bucket, &key).await?
+    } else if q.recover.is_some() {
+        service.recover_overwritten(&bucket, &key).await?
+    } else {
         return Err(AppError::new(
             StatusCode::BAD_REQUEST,
-            "Only list-type=2 is supported",
+            "unsupported POST operation on object",
         ));
+    };
+    let mut response = Response::new(Body::empty());
+    *response.status_mut() = StatusCode::OK;
+    if let Some(etag) = object.etag.as_ref()
+        && let Ok(value) = HeaderValue::from_str(&format!("\"{}\"", etag))
+    {
+        response.headers_mut().insert(header::ETAG, value);
     }
+    insert_checksum_header(response.headers_mut(), &object);
+    Ok(response)
+}
 
-    let continuation_token_raw = q.continuation_token.clone();
-    let continuation_decoded = if let Some(raw_token) = continuation_token_raw.as_deref() {
+/// GET `/{bucket}` — list objects (ListObjectsV2), supports ?prefix=&delimiter=&max-keys=
+///
+/// Pages continue with `continuation-token` (the previous
+/// `NextContinuationToken`) or `start-after`; keys and common prefixes both
+/// count toward `max-keys`. `encoding-type=url` percent-encodes keys and
+/// prefixes, `fetch-owner=true` adds each object's `Owner`.
+///
+/// Without `list-type=2`, answers as ListObjects (V1): pages continue from
+/// `marker` (the previous `NextMarker`) and every object names its `Owner`.
+///
+/// Extension filters `storage-class`, `min-size` and `max-size` narrow the
+/// listing server-side; pagination works as usual over the filtered keys.
+///
+/// With `?list-partitions=N`, returns key-range boundaries (JSON) that clients
+/// can list concurrently using `start-after` / `end-key`.
+///
+/// `?versioning` returns the bucket's versioning state and `?versions` lists
+/// every version and delete marker (ListObjectVersions); `?lifecycle`
+/// returns the lifecycle configuration, `?notification` the notification
+/// configuration.
+///
+/// With `?validate[&key=K]`, returns a JSON report on whether the bucket
+/// could be created (or `K` uploaded into it) instead; nothing is changed.
+///
+/// With `?deleted=true`, lists soft-deleted keys (JSON; `prefix`,
+/// `start-after` and `max-keys` apply) that `?restore` can bring back.
+///
+/// With `?changes[&since=C]`, returns the creates, updates and deletes
+/// recorded after cursor `C` (JSON, oldest first; `prefix` and `max-keys`
+/// apply).
+///
+/// With `?list-stream`, returns every matching object in one response as
+/// newline-delimited JSON (`application/x-ndjson`), one object per line, with
+/// no pagination; `delimiter` is refused and `max-keys` ignored.
+pub async fn list_objects(
+    State(service): State<StorageService>,
+    Path(bucket): Path<String>,
+    Query(q): Query<ListObjectsV2Query>,
+) -> Result<Response, AppError> {
+    if q.validate.is_some() {
+        let report = service.validate_proposal(&bucket, q.key.as_deref()).await?;
+        return Ok(Json(report).into_response());
+    }
+    if q.location.is_some() {
+        let bucket_rec = service.fetch_bucket(&bucket).await?;
+        return Ok(xml_response(build_location_xml(&bucket_rec.region)));
+    }
+    if q.lifecycle.is_some() {
+        return lifecycle_handlers::get_bucket_lifecycle(&service, &bucket).await;
+    }
+    if q.notification.is_some() {
+        return notification_handlers::get_bucket_notification(&service, &bucket).await;
+    }
+    if q.object_lock.is_some() {
+        return object_lock_handlers::get_object_lock_configuration(&service, &bucket).await;
+    }
+    if q.versioning.is_some() {
+        let bucket_rec = service.fetch_bucket(&bucket).await?;
+        let status = if bucket_rec.versioning_enabled {
+            "<Status>Enabled</Status>"
+        } else {
+            ""
+        };
+        let xml = format!(
+            concat!(
+                r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+                r#"<VersioningConfiguration xmlns="http://s3.amazonaws.com/doc/2006-03-01/">"#,
+                "{}</VersioningConfiguration>"
+            ),
+            status
+        );
+        return Ok(xml_response(xml));
+    }
+    if q.versions.is_some() {
+        let params = ListVersionsParams {
+            prefix: q.prefix.clone(),
+            key_marker: q.key_marker.clone(),
+            version_id_marker: q.version_id_marker.clone(),
+            max_keys: q
+                .max_keys
+                .unwrap_or(DEFAULT_MAX_VERSION_KEYS)
+                .clamp(1, DEFAULT_MAX_VERSION_KEYS),
+        };
+        let url_encoding = url_encoding(q.encoding_type.as_deref())?;
+        let result = service
+            .list_object_versions(&bucket, params.clone())
+            .await?;
+        return Ok(xml_response(build_list_versions_xml(
+            &bucket,
+            &params,
+            &result,
+            url_encoding,
+        )));
+    }
+    if q.changes.is_some() {
+        let since = q.since.as_deref().map(changes::parse_cursor).transpose()?;
+        let page = service
+            .list_changes(
+                &bucket,
+                q.prefix.as_deref(),
+                since,
+                q.max_keys.unwrap_or(MAX_CHANGES),
+            )
+            .await?;
+        return Ok(Json(page).into_response());
+    }
+    if q.deleted == Some(true) {
+        let listing = service
+            .list_deleted(
+                &bucket,
+                q.prefix.as_deref(),
+                q.start_after.as_deref(),
+                q.max_keys.unwrap_or(MAX_LIST_KEYS),
+            )
+            .await?;
+        return Ok(Json(listing).into_response());
+    }
+    if let Some(count) = q.list_partitions {
+        let partitions = service
+            .list_partitions(&bucket, q.prefix.as_deref(), count)
+            .await?;
+        return Ok(Json(ListPartitionsResponse {
+            bucket,
+            prefix: q.prefix,
+            partitions,
+        })
+        .into_response());
+    }
+
+    // Without `list-type=2` this is the original ListObjects, paginated by
+    // `marker` instead of a continuation token.
+    let list_v1 = match q.list_type {
+        None | Some(1) => true,
+        Some(2) => false,
+        Some(_) => {
+            return Err(AppError::new(
+                StatusCode::BAD_REQUEST,
+                "list-type must be 1 or 2",
+            ));
+        }
+    };
+
+    let continuation_token_raw = q.continuation_token.clone().filter(|_| !list_v1);
+    let continuation_decoded = if list_v1 {
+        // `NextMarker` names the last key or common prefix returned, which
+        // is what a continuation token resumes after.
+        q.marker.clone()
+    } else if let Some(raw_token) = continuation_token_raw.as_deref() {
         Some(
             decode_continuation_token(raw_token)
                 .map_err(|msg| AppError::new(StatusCode::BAD_REQUEST, msg))?,
@@ -162,46 +954,148 @@ pub async fn list_objects(
     } else {
         None
     };
-    let start_after = q.start_after.clone();
-    let max_keys = q.max_keys.unwrap_or(1000).clamp(1, 1000);
+    if let (Some(min), Some(max)) = (q.min_size, q.max_size)
+        && min > max
+    {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            "min-size must not exceed max-size",
+        ));
+    }
+    let url_encoding = url_encoding(q.encoding_type.as_deref())?;
+    let start_after = q.start_after.clone().filter(|_| !list_v1);
+    let max_keys = q.max_keys.unwrap_or(MAX_LIST_KEYS).clamp(1, MAX_LIST_KEYS);
 
     let params = ListObjectsParams {
         prefix: q.prefix.clone(),
         delimiter: q.delimiter.clone(),
         continuation_token: continuation_decoded,
         start_after: start_after.clone(),
+        end_key: q.end_key.clone(),
+        storage_class: q.storage_class.clone(),
+        min_size: q.min_size,
+        max_size: q.max_size,
         max_keys,
     };
 
+    if q.list_stream.is_some() {
+        let objects = service.list_stream(&bucket, params).await?;
+        let lines = objects.map(|object| {
+            let object = object.map_err(io::Error::other)?;
+            let mut line = serde_json::to_vec(&object).map_err(io::Error::other)?;
+            line.push(b'\n');
+            Ok::<_, io::Error>(Bytes::from(line))
+        });
+        let mut response = Response::new(Body::from_stream(lines));
+        response.headers_mut().insert(
+            header::CONTENT_TYPE,
+            HeaderValue::from_static("application/x-ndjson"),
+        );
+        return Ok(response);
+    }
+
     let result = service.list_objects_v2(&bucket, params.clone()).await?;
-    let xml = build_list_objects_v2_xml(
-        &bucket,
-        &params,
-        continuation_token_raw.as_deref(),
-        start_after.as_deref(),
-        &result,
-    );
+    // ListObjects always names the owner; V2 only with `fetch-owner`.
+    let owner = match list_v1 || q.fetch_owner == Some(true) {
+        true => Some(service.fetch_bucket(&bucket).await?.owner_id),
+        false => None,
+    };
+    let xml = if list_v1 {
+        build_list_objects_v1_xml(&bucket, &params, &result, url_encoding, owner)
+    } else {
+        build_list_objects_v2_xml(
+            &bucket,
+            &params,
+            continuation_token_raw.as_deref(),
+            start_after.as_deref(),
+            &result,
+            url_encoding,
+            owner,
+        )
+    };
 
-    let mut response = Response::new(Body::from(xml));
-    response.headers_mut().insert(
-        header::CONTENT_TYPE,
-        HeaderValue::from_static("application/xml"),
-    );
-    *response.status_mut() = StatusCode::OK;
-    Ok(response)
+    Ok(xml_response(xml))
 }
 
 /// PUT `/{bucket}` — create bucket.
+///
+/// A JSON body `{"Template": "name"}` creates it with the settings of a
+/// configured template, atomically (see `services::bucket_template`).
+///
+/// With `?versioning`, applies a `VersioningConfiguration` body
+/// (`Enabled` or `Suspended`) to an existing bucket instead; with
+/// `?lifecycle` or `?notification`, replaces its lifecycle or notification
+/// configuration.
 pub async fn create_bucket(
     State(service): State<StorageService>,
     Path(bucket): Path<String>,
-    Json(payload): Json<Option<CreateBucketReq>>,
-) -> Result<impl IntoResponse, AppError> {
-    let region = payload
-        .and_then(|p| p.location_constraint)
-        .unwrap_or_else(|| "local".into());
+    Query(q): Query<BucketQuery>,
+    request: Request,
+) -> Result<Response, AppError> {
+    if q.lifecycle.is_some() {
+        return lifecycle_handlers::put_bucket_lifecycle(&service, &bucket, request.into_body())
+            .await;
+    }
+    if q.notification.is_some() {
+        return notification_handlers::put_bucket_notification(
+            &service,
+            &bucket,
+            request.into_body(),
+        )
+        .await;
+    }
+    if q.object_lock.is_some() {
+        return object_lock_handlers::put_object_lock_configuration(
+            &service,
+            &bucket,
+            request.into_body(),
+        )
+        .await;
+    }
+    if q.versioning.is_some() {
+        let body = Bytes::from_request(request, &())
+            .await
+            .map_err(|err| AppError::new(StatusCode::BAD_REQUEST, err.body_text()))?;
+        let text = std::str::from_utf8(&body)
+            .map_err(|_| AppError::new(StatusCode::BAD_REQUEST, "request body is not UTF-8"))?;
+        let config: VersioningConfigurationReq = quick_xml::de::from_str(text).map_err(|err| {
+            AppError::new(
+                StatusCode::BAD_REQUEST,
+                format!("malformed VersioningConfiguration body: {}", err),
+            )
+        })?;
+        let enabled = match config.status.as_deref() {
+            Some("Enabled") => true,
+            Some("Suspended") => false,
+            _ => {
+                return Err(AppError::new(
+                    StatusCode::BAD_REQUEST,
+                    "versioning Status must be Enabled or Suspended",
+                ));
+            }
+        };
+        service.set_bucket_versioning(&bucket, enabled).await?;
+        return Ok(StatusCode::OK.into_response());
+    }
+
+    let payload = match Json::<Option<CreateBucketReq>>::from_request(request, &()).await {
+        Ok(Json(payload)) => payload,
+        Err(rejection) => return Ok(rejection.into_response()),
+    };
+    let (region, template) = match payload {
+        Some(p) => (p.location_constraint, p.template),
+        None => (None, None),
+    };
+    let region = region.unwrap_or_else(|| "local".into());
 
-    service.create_bucket(&bucket, region).await?;
+    match template {
+        Some(template) => {
+            service
+                .create_bucket_from_template(&bucket, region, &template)
+                .await?
+        }
+        None => service.create_bucket(&bucket, region).await?,
+    };
 
     let xml = format!(
         concat!(
@@ -212,21 +1106,101 @@ pub async fn create_bucket(
         ),
         xml_escape(&bucket)
     );
-    let mut response = Response::new(Body::from(xml));
-    response.headers_mut().insert(
-        header::CONTENT_TYPE,
-        HeaderValue::from_static("application/xml"),
-    );
+    Ok(xml_response(xml))
+}
+
+/// POST `/{bucket}?delete` — delete up to 1000 keys at once (DeleteObjects).
+///
+/// Responds with a `DeleteResult` listing each key as `Deleted` or `Error`;
+/// with `<Quiet>true</Quiet>`, only the errors.
+///
+/// With `?lifecycle-dry-run`, reports (JSON) what the `LifecycleConfiguration`
+/// body would expire, transition and abort if applied now instead.
+pub async fn post_bucket(
+    State(service): State<StorageService>,
+    Path(bucket): Path<String>,
+    Query(q): Query<BucketQuery>,
+    body: Bytes,
+) -> Result<Response, AppError> {
+    if q.lifecycle_dry_run.is_some() {
+        return lifecycle_handlers::lifecycle_dry_run(
+            &service,
+            &bucket,
+            q.prefix.as_deref(),
+            &body,
+        )
+        .await;
+    }
+    if q.delete.is_none() {
+        return Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            "unsupported POST operation on bucket",
+        ));
+    }
+    let text = std::str::from_utf8(&body)
+        .map_err(|_| AppError::new(StatusCode::BAD_REQUEST, "request body is not UTF-8"))?;
+    let req: DeleteObjectsReq = quick_xml::de::from_str(text).map_err(|err| {
+        AppError::new(
+            StatusCode::BAD_REQUEST,
+            format!("malformed Delete body: {}", err),
+        )
+    })?;
+    let targets: Vec<DeleteTarget> = req
+        .objects
+        .into_iter()
+        .map(|o| DeleteTarget {
+            key: o.key,
+            version_id: o.version_id,
+        })
+        .collect();
+
+    let outcomes = service.delete_objects(&bucket, &targets).await?;
+    Ok(xml_response(build_delete_result_xml(&outcomes, req.quiet)))
+}
+
+/// HEAD `/{bucket}` — 200 if the bucket exists, 404 otherwise, with the
+/// bucket's region in `x-amz-bucket-region` as SDKs expect.
+pub async fn head_bucket(
+    State(service): State<StorageService>,
+    Path(bucket): Path<String>,
+) -> Result<Response, AppError> {
+    let bucket_rec = service.fetch_bucket(&bucket).await?;
+    let mut response = StatusCode::OK.into_response();
+    if let Ok(value) = HeaderValue::from_str(&bucket_rec.region) {
+        response.headers_mut().insert("x-amz-bucket-region", value);
+    }
     Ok(response)
 }
 
 /// DELETE `/{bucket}` — delete bucket.
+///
+/// With `?prefix=P`, the bucket is kept and every key under `P` is deleted
+/// by a background job instead; the response is `202 Accepted` with the job
+/// record, and `Location` points at its status under `/admin/jobs`. With
+/// `?lifecycle`, only the bucket's lifecycle configuration is removed.
+/// A bucket that still holds objects is refused with `409` unless
+/// `?force=true` is given.
 pub async fn delete_bucket(
     State(service): State<StorageService>,
     Path(bucket): Path<String>,
-) -> Result<impl IntoResponse, AppError> {
-    service.delete_bucket(&bucket).await?;
-    Ok(StatusCode::NO_CONTENT)
+    Query(q): Query<DeleteBucketQuery>,
+) -> Result<Response, AppError> {
+    if q.lifecycle.is_some() {
+        return lifecycle_handlers::delete_bucket_lifecycle(&service, &bucket).await;
+    }
+    if let Some(prefix) = q.prefix {
+        let job = service.enqueue_prefix_delete(&bucket, &prefix).await?;
+        let location = format!("/admin/jobs/{}", job.id);
+        let mut response = (StatusCode::ACCEPTED, Json(job)).into_response();
+        if let Ok(value) = HeaderValue::from_str(&location) {
+            response.headers_mut().insert(header::LOCATION, value);
+        }
+        return Ok(response);
+    }
+    service
+        .delete_bucket(&bucket, q.force.unwrap_or(false))
+        .await?;
+    Ok(StatusCode::NO_CONTENT.into_response())
 }
 
 fn set_object_headers(headers: &mut HeaderMap, meta: &Object, len_override: Option<i64>) {
@@ -240,6 +1214,18 @@ fn set_object_headers(headers: &mut HeaderMap, meta: &Object, len_override: Opti
             .unwrap_or_else(|_| HeaderValue::from_static("application/octet-stream")),
     );
 
+    if let Some(encoding) = meta.content_encoding.as_deref()
+        && let Ok(value) = HeaderValue::from_str(encoding)
+    {
+        headers.insert(header::CONTENT_ENCODING, value);
+    }
+
+    if let Some(disposition) = meta.content_disposition.as_deref()
+        && let Ok(value) = HeaderValue::from_str(disposition)
+    {
+        headers.insert(header::CONTENT_DISPOSITION, value);
+    }
+
     let length = len_override.unwrap_or(meta.size_bytes).max(0);
     headers.insert(
         header::CONTENT_LENGTH,
@@ -254,11 +1240,501 @@ fn set_object_headers(headers: &mut HeaderMap, meta: &Object, len_override: Opti
         }
     }
 
+    insert_checksum_header(headers, meta);
+    insert_version_header(headers, meta);
+
+    // S3 omits the header for STANDARD objects.
+    if meta.storage_class != "STANDARD"
+        && let Ok(value) = HeaderValue::from_str(&meta.storage_class)
+    {
+        headers.insert("x-amz-storage-class", value);
+    }
+
     headers.insert(
         header::LAST_MODIFIED,
         HeaderValue::from_str(&meta.last_modified.to_rfc2822())
             .unwrap_or_else(|_| HeaderValue::from_static("")),
     );
+    headers.insert(header::ACCEPT_RANGES, HeaderValue::from_static("bytes"));
+}
+
+/// Answer a GET/HEAD whose preconditions say not to send the object:
+/// `304` with the validators and caching headers, or `412`.
+fn read_precondition_response(
+    conditions: &S3ConditionalHeaders,
+    meta: &Object,
+    bucket: &Bucket,
+) -> Result<Option<Response>, AppError> {
+    match conditional::evaluate(conditions, Some(meta), true) {
+        Precondition::Proceed => Ok(None),
+        Precondition::Failed => Err(precondition_failed()),
+        Precondition::NotModified => {
+            let mut response = StatusCode::NOT_MODIFIED.into_response();
+            let resp_headers = response.headers_mut();
+            if let Some(etag) = meta.etag.as_ref()
+                && let Ok(value) = HeaderValue::from_str(&format!("\"{}\"", etag))
+            {
+                resp_headers.insert(header::ETAG, value);
+            }
+            if let Ok(value) = HeaderValue::from_str(&meta.last_modified.to_rfc2822()) {
+                resp_headers.insert(header::LAST_MODIFIED, value);
+            }
+            insert_version_header(resp_headers, meta);
+            set_cache_headers(resp_headers, bucket, meta);
+            Ok(Some(response))
+        }
+    }
+}
+
+/// Check a PUT/DELETE's preconditions against the current object.
+async fn check_write_preconditions(
+    service: &StorageService,
+    bucket: &str,
+    key: &str,
+    conditions: &S3ConditionalHeaders,
+) -> Result<(), AppError> {
+    if conditions.is_empty() {
+        return Ok(());
+    }
+    let current = match service.get_object_metadata(bucket, key).await {
+        Ok(meta) => Some(meta),
+        Err(StorageError::ObjectNotFound { .. }) => None,
+        Err(err) => return Err(err.into()),
+    };
+    match conditional::evaluate(conditions, current.as_ref(), false) {
+        Precondition::Proceed => Ok(()),
+        Precondition::NotModified | Precondition::Failed => Err(precondition_failed()),
+    }
+}
+
+fn precondition_failed() -> AppError {
+    AppError::new(
+        StatusCode::PRECONDITION_FAILED,
+        "at least one of the preconditions you specified did not hold",
+    )
+}
+
+/// `Cache-Control`/`Expires` for object reads: the values stored with the
+/// object, else the bucket defaults.
+fn set_cache_headers(headers: &mut HeaderMap, bucket: &Bucket, meta: &Object) {
+    let cache_control = meta
+        .cache_control
+        .as_deref()
+        .or(bucket.cache_control.as_deref());
+    if let Some(cache_control) = cache_control
+        && let Ok(value) = HeaderValue::from_str(cache_control)
+    {
+        headers.insert(header::CACHE_CONTROL, value);
+    }
+    if let Some(expires) = meta.expires.as_deref() {
+        if let Ok(value) = HeaderValue::from_str(expires) {
+            headers.insert(header::EXPIRES, value);
+        }
+    } else if let Some(secs) = bucket.expires_secs {
+        let expires = Utc::now() + chrono::Duration::seconds(secs);
+        let formatted = expires.format("%a, %d %b %Y %H:%M:%S GMT").to_string();
+        if let Ok(value) = HeaderValue::from_str(&formatted) {
+            headers.insert(header::EXPIRES, value);
+        }
+    }
+}
+
+/// `x-amz-checksum-sha256` / `x-amz-checksum-crc32c` for objects stored
+/// with a recorded digest.
+pub(crate) fn insert_checksum_header(headers: &mut HeaderMap, meta: &Object) {
+    if let Some(sha256) = meta.checksum_sha256.as_deref()
+        && let Ok(value) = HeaderValue::from_str(sha256)
+    {
+        headers.insert(HeaderName::from_static("x-amz-checksum-sha256"), value);
+    }
+    if let Some(crc32c) = meta.checksum_crc32c.as_deref()
+        && let Ok(value) = HeaderValue::from_str(crc32c)
+    {
+        headers.insert(HeaderName::from_static("x-amz-checksum-crc32c"), value);
+    }
+}
+
+/// Object Lock retention of the version being served, if any.
+async fn insert_lock_headers(
+    service: &StorageService,
+    headers: &mut HeaderMap,
+    meta: &Object,
+) -> Result<(), AppError> {
+    if let Some(version_id) = meta.version_id.as_deref()
+        && let Some(retention) = service
+            .object_retention(meta.bucket_id, &meta.key, version_id)
+            .await?
+    {
+        object_lock_handlers::insert_retention_headers(headers, &retention);
+    }
+    Ok(())
+}
+
+/// `x-amz-replication-status` of a current object the replicator has
+/// queued.
+async fn insert_replication_header(
+    service: &StorageService,
+    headers: &mut HeaderMap,
+    meta: &Object,
+) -> Result<(), AppError> {
+    if let Some(state) = service.replication_state(meta.bucket_id, &meta.key).await? {
+        headers.insert(
+            HeaderName::from_static("x-amz-replication-status"),
+            HeaderValue::from_static(state.header_value()),
+        );
+    }
+    Ok(())
+}
+
+/// SSE-C algorithm and key MD5 echoed for objects encrypted with a customer
+/// key.
+fn insert_customer_key_headers(headers: &mut HeaderMap, customer_key: &SseCustomerKey) {
+    headers.insert(
+        HeaderName::from_static("x-amz-server-side-encryption-customer-algorithm"),
+        HeaderValue::from_static("AES256"),
+    );
+    if let Ok(value) = HeaderValue::from_str(&customer_key.key_md5) {
+        headers.insert(
+            HeaderName::from_static("x-amz-server-side-encryption-customer-key-md5"),
+            value,
+        );
+    }
+}
+
+/// `x-amz-version-id` for objects written while versioning was enabled.
+pub(crate) fn insert_version_header(headers: &mut HeaderMap, meta: &Object) {
+    if let Some(version_id) = meta.version_id.as_deref()
+        && let Ok(value) = HeaderValue::from_str(version_id)
+    {
+        headers.insert(HeaderName::from_static("x-amz-version-id"), value);
+    }
+}
+
+/// Whether `x-amz-checksum-mode: ENABLED` was sent; other values are
+/// rejected. Stored checksums are returned either way.
+fn checksum_mode_enabled(headers: &HeaderMap) -> Result<bool, AppError> {
+    match headers.get("x-amz-checksum-mode") {
+        None => Ok(false),
+        Some(value) if value == "ENABLED" => Ok(true),
+        Some(_) => Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            "x-amz-checksum-mode must be ENABLED",
+        )),
+    }
+}
+
+/// Body digests from `Content-MD5`, `x-amz-checksum-sha256` /
+/// `x-amz-checksum-crc32c` and a digest `x-amz-content-sha256` on PutObject
+/// / UploadPart.
+pub(crate) fn request_checksums(headers: &HeaderMap) -> ExpectedChecksums {
+    let header_str = |name: &str| {
+        headers
+            .get(name)
+            .map(|v| String::from_utf8_lossy(v.as_bytes()).into_owned())
+    };
+    ExpectedChecksums {
+        md5: header_str("content-md5"),
+        sha256: header_str("x-amz-checksum-sha256"),
+        crc32c: header_str("x-amz-checksum-crc32c"),
+        content_sha256: header_str("x-amz-content-sha256").filter(|value| {
+            value != "UNSIGNED-PAYLOAD" && !value.starts_with(aws_chunked::STREAMING_PREFIX)
+        }),
+    }
+}
+
+/// User metadata from `x-amz-meta-*` headers. Repeated headers are joined
+/// with `,`.
+pub(crate) fn request_user_metadata(headers: &HeaderMap) -> Result<Vec<ObjectMetadata>, AppError> {
+    let mut entries: BTreeMap<String, String> = BTreeMap::new();
+    for (name, value) in headers {
+        let Some(suffix) = name.as_str().strip_prefix(USER_METADATA_PREFIX) else {
+            continue;
+        };
+        let value = value.to_str().map_err(|_| {
+            AppError::new(
+                StatusCode::BAD_REQUEST,
+                format!("{} is not ASCII", name.as_str()),
+            )
+        })?;
+        entries
+            .entry(suffix.to_string())
+            .and_modify(|joined| {
+                joined.push(',');
+                joined.push_str(value);
+            })
+            .or_insert_with(|| value.to_string());
+    }
+    Ok(entries
+        .into_iter()
+        .map(|(name, value)| ObjectMetadata { name, value })
+        .collect())
+}
+
+fn insert_user_metadata_headers(headers: &mut HeaderMap, entries: &[ObjectMetadata]) {
+    for entry in entries {
+        if let Ok(name) =
+            HeaderName::from_bytes(format!("{}{}", USER_METADATA_PREFIX, entry.name).as_bytes())
+            && let Ok(value) = HeaderValue::from_str(&entry.value)
+        {
+            headers.insert(name, value);
+        }
+    }
+}
+
+/// CopyObject from the object named in `x-amz-copy-source`.
+async fn copy_object(
+    service: &StorageService,
+    bucket: &str,
+    key: &str,
+    source: CopySource,
+    headers: &HeaderMap,
+    params: PutObjectParams,
+) -> Result<Response, AppError> {
+    let directive = match headers.get("x-amz-metadata-directive") {
+        Some(value) => value
+            .to_str()
+            .ok()
+            .and_then(MetadataDirective::parse)
+            .ok_or_else(|| {
+                AppError::new(
+                    StatusCode::BAD_REQUEST,
+                    "x-amz-metadata-directive must be COPY or REPLACE",
+                )
+            })?,
+        None => MetadataDirective::default(),
+    };
+    let object = service
+        .copy_object(bucket, key, &source, directive, params)
+        .await?;
+
+    let xml = format!(
+        concat!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+            r#"<CopyObjectResult xmlns="http://s3.amazonaws.com/doc/2006-03-01/">"#,
+            r#"<LastModified>{}</LastModified><ETag>&quot;{}&quot;</ETag>"#,
+            r#"</CopyObjectResult>"#
+        ),
+        object
+            .last_modified
+            .to_rfc3339_opts(SecondsFormat::Millis, true),
+        xml_escape(object.etag.as_deref().unwrap_or_default())
+    );
+    let mut response = xml_response(xml);
+    insert_checksum_header(response.headers_mut(), &object);
+    insert_version_header(response.headers_mut(), &object);
+    if let Some(version_id) = source.version_id.as_deref()
+        && let Ok(value) = HeaderValue::from_str(version_id)
+    {
+        response.headers_mut().insert(
+            HeaderName::from_static("x-amz-copy-source-version-id"),
+            value,
+        );
+    }
+    Ok(response)
+}
+
+/// PutObjectTagging: replace the tag set of the current object.
+async fn put_object_tagging(
+    service: &StorageService,
+    bucket: &str,
+    key: &str,
+    body: Body,
+) -> Result<Response, AppError> {
+    let body = axum::body::to_bytes(body, MAX_TAGGING_BODY)
+        .await
+        .map_err(|_| AppError::new(StatusCode::BAD_REQUEST, "Tagging body is too large"))?;
+    let text = std::str::from_utf8(&body)
+        .map_err(|_| AppError::new(StatusCode::BAD_REQUEST, "request body is not UTF-8"))?;
+    let req: TaggingReq = quick_xml::de::from_str(text).map_err(|err| {
+        AppError::new(
+            StatusCode::BAD_REQUEST,
+            format!("malformed Tagging body: {}", err),
+        )
+    })?;
+    let tags: Vec<ObjectTag> = req
+        .tag_set
+        .tags
+        .into_iter()
+        .map(|t| ObjectTag {
+            key: t.key,
+            value: t.value,
+        })
+        .collect();
+    let object = service.put_object_tags(bucket, key, &tags).await?;
+    let mut response = StatusCode::OK.into_response();
+    insert_version_header(response.headers_mut(), &object);
+    Ok(response)
+}
+
+fn build_tagging_xml(tags: &[ObjectTag]) -> String {
+    let mut xml = String::from(concat!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+        r#"<Tagging xmlns="http://s3.amazonaws.com/doc/2006-03-01/"><TagSet>"#
+    ));
+    for tag in tags {
+        xml.push_str(&format!(
+            "<Tag><Key>{}</Key><Value>{}</Value></Tag>",
+            xml_escape(&tag.key),
+            xml_escape(&tag.value)
+        ));
+    }
+    xml.push_str("</TagSet></Tagging>");
+    xml
+}
+
+/// Body of GetBucketLocation. As in S3, `us-east-1` is reported as an empty
+/// constraint.
+fn build_location_xml(region: &str) -> String {
+    let region = if region == "us-east-1" { "" } else { region };
+    format!(
+        concat!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+            r#"<LocationConstraint xmlns="http://s3.amazonaws.com/doc/2006-03-01/">"#,
+            "{}</LocationConstraint>"
+        ),
+        xml_escape(region)
+    )
+}
+
+fn xml_response(xml: String) -> Response {
+    let mut response = Response::new(Body::from(xml));
+    response.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/xml"),
+    );
+    response
+}
+
+fn build_delete_result_xml(outcomes: &[DeleteOutcome], quiet: bool) -> String {
+    let mut xml = String::from(
+        r#"<?xml version="1.0" encoding="UTF-8"?><DeleteResult xmlns="http://s3.amazonaws.com/doc/2006-03-01/">"#,
+    );
+    for outcome in outcomes {
+        match outcome {
+            DeleteOutcome::Deleted { .. } if quiet => {}
+            DeleteOutcome::Deleted {
+                key,
+                version_id,
+                delete_marker,
+                delete_marker_version_id,
+            } => {
+                xml.push_str("<Deleted>");
+                xml.push_str(&format!("<Key>{}</Key>", xml_escape(key)));
+                if let Some(version_id) = version_id {
+                    xml.push_str(&format!(
+                        "<VersionId>{}</VersionId>",
+                        xml_escape(version_id)
+                    ));
+                }
+                if *delete_marker {
+                    xml.push_str("<DeleteMarker>true</DeleteMarker>");
+                }
+                if let Some(marker_id) = delete_marker_version_id {
+                    xml.push_str(&format!(
+                        "<DeleteMarkerVersionId>{}</DeleteMarkerVersionId>",
+                        xml_escape(marker_id)
+                    ));
+                }
+                xml.push_str("</Deleted>");
+            }
+            DeleteOutcome::Failed {
+                key,
+                version_id,
+                code,
+                message,
+            } => {
+                xml.push_str("<Error>");
+                xml.push_str(&format!("<Key>{}</Key>", xml_escape(key)));
+                if let Some(version_id) = version_id {
+                    xml.push_str(&format!(
+                        "<VersionId>{}</VersionId>",
+                        xml_escape(version_id)
+                    ));
+                }
+                xml.push_str(&format!("<Code>{}</Code>", code));
+                xml.push_str(&format!("<Message>{}</Message>", xml_escape(message)));
+                xml.push_str("</Error>");
+            }
+        }
+    }
+    xml.push_str("</DeleteResult>");
+    xml
+}
+
+fn build_list_versions_xml(
+    bucket: &str,
+    params: &ListVersionsParams,
+    result: &ListVersionsResult,
+    url_encoding: bool,
+) -> String {
+    let name = |value: &str| listed_name(value, url_encoding);
+    let mut xml = String::from(
+        r#"<?xml version="1.0" encoding="UTF-8"?><ListVersionsResult xmlns="http://s3.amazonaws.com/doc/2006-03-01/">"#,
+    );
+    xml.push_str(&format!("<Name>{}</Name>", xml_escape(bucket)));
+    xml.push_str(&format!(
+        "<Prefix>{}</Prefix>",
+        name(params.prefix.as_deref().unwrap_or(""))
+    ));
+    xml.push_str(&format!(
+        "<KeyMarker>{}</KeyMarker>",
+        name(params.key_marker.as_deref().unwrap_or(""))
+    ));
+    xml.push_str(&format!(
+        "<VersionIdMarker>{}</VersionIdMarker>",
+        xml_escape(params.version_id_marker.as_deref().unwrap_or(""))
+    ));
+    xml.push_str(&format!("<MaxKeys>{}</MaxKeys>", params.max_keys));
+    if url_encoding {
+        xml.push_str("<EncodingType>url</EncodingType>");
+    }
+    xml.push_str(&format!(
+        "<IsTruncated>{}</IsTruncated>",
+        if result.is_truncated { "true" } else { "false" }
+    ));
+    if let Some(marker) = &result.next_key_marker {
+        xml.push_str(&format!("<NextKeyMarker>{}</NextKeyMarker>", name(marker)));
+    }
+    if let Some(marker) = &result.next_version_id_marker {
+        xml.push_str(&format!(
+            "<NextVersionIdMarker>{}</NextVersionIdMarker>",
+            xml_escape(marker)
+        ));
+    }
+
+    for entry in &result.entries {
+        let tag = if entry.is_delete_marker {
+            "DeleteMarker"
+        } else {
+            "Version"
+        };
+        xml.push_str(&format!("<{}>", tag));
+        xml.push_str(&format!("<Key>{}</Key>", name(&entry.key)));
+        xml.push_str(&format!(
+            "<VersionId>{}</VersionId>",
+            xml_escape(&entry.version_id)
+        ));
+        xml.push_str(&format!("<IsLatest>{}</IsLatest>", entry.is_latest));
+        xml.push_str(&format!(
+            "<LastModified>{}</LastModified>",
+            entry
+                .last_modified
+                .to_rfc3339_opts(SecondsFormat::Millis, true)
+        ));
+        if !entry.is_delete_marker {
+            let etag = entry.etag.as_deref().unwrap_or("");
+            xml.push_str(&format!("<ETag>\"{}\"</ETag>", xml_escape(etag)));
+            xml.push_str(&format!("<Size>{}</Size>", entry.size_bytes));
+            xml.push_str(&format!(
+                "<StorageClass>{}</StorageClass>",
+                xml_escape(&entry.storage_class)
+            ));
+        }
+        xml.push_str(&format!("</{}>", tag));
+    }
+
+    xml.push_str("</ListVersionsResult>");
+    xml
 }
 
 fn build_list_objects_v2_xml(
@@ -267,14 +1743,17 @@ fn build_list_objects_v2_xml(
     continuation_token: Option<&str>,
     start_after: Option<&str>,
     result: &ListObjectsResult,
+    url_encoding: bool,
+    owner: Option<Uuid>,
 ) -> String {
+    let name = |value: &str| listed_name(value, url_encoding);
     let mut xml = String::from(
         r#"<?xml version="1.0" encoding="UTF-8"?><ListBucketResult xmlns="http://s3.amazonaws.com/doc/2006-03-01/">"#,
     );
     xml.push_str(&format!("<Name>{}</Name>", xml_escape(bucket)));
     xml.push_str(&format!(
         "<Prefix>{}</Prefix>",
-        xml_escape(params.prefix.as_deref().unwrap_or(""))
+        name(params.prefix.as_deref().unwrap_or(""))
     ));
     xml.push_str(&format!("<MaxKeys>{}</MaxKeys>", params.max_keys));
     xml.push_str(&format!("<KeyCount>{}</KeyCount>", result.key_count));
@@ -285,10 +1764,13 @@ fn build_list_objects_v2_xml(
         ));
     }
     if let Some(sa) = start_after {
-        xml.push_str(&format!("<StartAfter>{}</StartAfter>", xml_escape(sa)));
+        xml.push_str(&format!("<StartAfter>{}</StartAfter>", name(sa)));
     }
     if let Some(delim) = &params.delimiter {
-        xml.push_str(&format!("<Delimiter>{}</Delimiter>", xml_escape(delim)));
+        xml.push_str(&format!("<Delimiter>{}</Delimiter>", name(delim)));
+    }
+    if url_encoding {
+        xml.push_str("<EncodingType>url</EncodingType>");
     }
     xml.push_str(&format!(
         "<IsTruncated>{}</IsTruncated>",
@@ -301,10 +1783,65 @@ fn build_list_objects_v2_xml(
             xml_escape(&encoded)
         ));
     }
+    push_listed_entries(&mut xml, result, url_encoding, owner);
+    xml.push_str("</ListBucketResult>");
+    xml
+}
+
+/// ListObjects (V1) response. `NextMarker` is sent whenever the listing is
+/// truncated, not only with a delimiter, so clients need not derive it.
+fn build_list_objects_v1_xml(
+    bucket: &str,
+    params: &ListObjectsParams,
+    result: &ListObjectsResult,
+    url_encoding: bool,
+    owner: Option<Uuid>,
+) -> String {
+    let name = |value: &str| listed_name(value, url_encoding);
+    let mut xml = String::from(
+        r#"<?xml version="1.0" encoding="UTF-8"?><ListBucketResult xmlns="http://s3.amazonaws.com/doc/2006-03-01/">"#,
+    );
+    xml.push_str(&format!("<Name>{}</Name>", xml_escape(bucket)));
+    xml.push_str(&format!(
+        "<Prefix>{}</Prefix>",
+        name(params.prefix.as_deref().unwrap_or(""))
+    ));
+    xml.push_str(&format!(
+        "<Marker>{}</Marker>",
+        name(params.continuation_token.as_deref().unwrap_or(""))
+    ));
+    if let Some(next) = &result.next_continuation_token {
+        xml.push_str(&format!("<NextMarker>{}</NextMarker>", name(next)));
+    }
+    xml.push_str(&format!("<MaxKeys>{}</MaxKeys>", params.max_keys));
+    if let Some(delim) = &params.delimiter {
+        xml.push_str(&format!("<Delimiter>{}</Delimiter>", name(delim)));
+    }
+    if url_encoding {
+        xml.push_str("<EncodingType>url</EncodingType>");
+    }
+    xml.push_str(&format!(
+        "<IsTruncated>{}</IsTruncated>",
+        if result.is_truncated { "true" } else { "false" }
+    ));
+    push_listed_entries(&mut xml, result, url_encoding, owner);
+    xml.push_str("</ListBucketResult>");
+    xml
+}
 
+/// `Contents` and `CommonPrefixes` of a ListObjects / ListObjectsV2 response.
+fn push_listed_entries(
+    xml: &mut String,
+    result: &ListObjectsResult,
+    url_encoding: bool,
+    owner: Option<Uuid>,
+) {
     for obj in &result.objects {
         xml.push_str("<Contents>");
-        xml.push_str(&format!("<Key>{}</Key>", xml_escape(&obj.key)));
+        xml.push_str(&format!(
+            "<Key>{}</Key>",
+            listed_name(&obj.key, url_encoding)
+        ));
         xml.push_str(&format!(
             "<LastModified>{}</LastModified>",
             obj.last_modified
@@ -313,6 +1850,12 @@ fn build_list_objects_v2_xml(
         let etag = obj.etag.as_deref().unwrap_or("");
         xml.push_str(&format!("<ETag>\"{}\"</ETag>", xml_escape(etag)));
         xml.push_str(&format!("<Size>{}</Size>", obj.size_bytes));
+        if let Some(owner) = owner {
+            xml.push_str(&format!(
+                "<Owner><ID>{0}</ID><DisplayName>{0}</DisplayName></Owner>",
+                owner
+            ));
+        }
         xml.push_str(&format!(
             "<StorageClass>{}</StorageClass>",
             xml_escape(&obj.storage_class)
@@ -322,15 +1865,52 @@ fn build_list_objects_v2_xml(
 
     for prefix in &result.common_prefixes {
         xml.push_str("<CommonPrefixes><Prefix>");
-        xml.push_str(&xml_escape(prefix));
+        xml.push_str(&listed_name(prefix, url_encoding));
         xml.push_str("</Prefix></CommonPrefixes>");
     }
+}
 
-    xml.push_str("</ListBucketResult>");
-    xml
+/// A key, prefix or delimiter in a listing: percent-encoded with
+/// `encoding-type=url`, so clients can round-trip characters XML 1.0 cannot
+/// carry, else XML-escaped.
+fn listed_name(value: &str, url_encoding: bool) -> String {
+    if url_encoding {
+        url_encode(value)
+    } else {
+        xml_escape(value)
+    }
+}
+
+/// Whether `encoding-type` asks for percent-encoded names; `url` is the
+/// only encoding S3 defines.
+fn url_encoding(encoding_type: Option<&str>) -> Result<bool, AppError> {
+    match encoding_type {
+        None => Ok(false),
+        Some(encoding) if encoding.eq_ignore_ascii_case("url") => Ok(true),
+        Some(encoding) => Err(AppError::new(
+            StatusCode::BAD_REQUEST,
+            format!(
+                "invalid encoding-type `{}`; only `url` is supported",
+                encoding
+            ),
+        )),
+    }
+}
+
+/// Characters left as they are by `encoding-type=url`: the unreserved set
+/// plus `/`, which S3 does not encode in keys either.
+const URL_ENCODE_SET: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'.')
+    .remove(b'_')
+    .remove(b'~')
+    .remove(b'/');
+
+fn url_encode(value: &str) -> String {
+    utf8_percent_encode(value, URL_ENCODE_SET).to_string()
 }
 
-fn xml_escape(value: &str) -> String {
+pub(crate) fn xml_escape(value: &str) -> String {
     value
         .replace('&', "&amp;")
         .replace('<', "&lt;")
@@ -0,0 +1,154 @@
+//! HTTP handlers for bucket Object Lock configuration.
+//!
+//! These share the bucket routes and are selected by the `?object-lock` flag
+//! in `object_handlers`:
+//! - `PUT /{bucket}?object-lock` — PutObjectLockConfiguration
+//! - `GET /{bucket}?object-lock` — GetObjectLockConfiguration
+//!
+//! Per-version retention is sent with uploads as `x-amz-object-lock-mode` and
+//! `x-amz-object-lock-retain-until-date` and returned on GET/HEAD.
+
+use crate::{
+    errors::AppError,
+    models::object_lock::{
+        DefaultRetention, ObjectLockConfiguration, ObjectRetention, RetentionMode,
+    },
+    services::{object_lock::parse_requested_retention, storage_service::StorageService},
+};
+use axum::{
+    body::Body,
+    http::{HeaderMap, HeaderName, HeaderValue, StatusCode, header},
+    response::{IntoResponse, Response},
+};
+use chrono::SecondsFormat;
+use serde::Deserialize;
+use std::str::FromStr;
+
+/// Largest accepted Object Lock configuration body.
+const MAX_OBJECT_LOCK_BODY: usize = 64 * 1024;
+
+/// Body of `PUT /{bucket}?object-lock`.
+#[derive(Debug, Deserialize)]
+struct ObjectLockConfigurationReq {
+    #[serde(rename = "ObjectLockEnabled")]
+    object_lock_enabled: Option<String>,
+    #[serde(rename = "Rule")]
+    rule: Option<RuleReq>,
+}
+
+#[derive(Debug, Deserialize)]
+struct RuleReq {
+    #[serde(rename = "DefaultRetention")]
+    default_retention: DefaultRetentionReq,
+}
+
+#[derive(Debug, Deserialize)]
+struct DefaultRetentionReq {
+    #[serde(rename = "Mode")]
+    mode: String,
+    #[serde(rename = "Days")]
+    days: Option<i64>,
+    #[serde(rename = "Years")]
+    years: Option<i64>,
+}
+
+fn bad_request(message: impl Into<String>) -> AppError {
+    AppError::new(StatusCode::BAD_REQUEST, message.into())
+}
+
+/// `PUT /{bucket}?object-lock`
+pub async fn put_object_lock_configuration(
+    service: &StorageService,
+    bucket: &str,
+    body: Body,
+) -> Result<Response, AppError> {
+    let body = axum::body::to_bytes(body, MAX_OBJECT_LOCK_BODY)
+        .await
+        .map_err(|_| bad_request("ObjectLockConfiguration body is too large"))?;
+    let text = std::str::from_utf8(&body).map_err(|_| bad_request("request body is not UTF-8"))?;
+    let req: ObjectLockConfigurationReq = quick_xml::de::from_str(text)
+        .map_err(|err| bad_request(format!("malformed ObjectLockConfiguration body: {}", err)))?;
+    if req.object_lock_enabled.as_deref() != Some("Enabled") {
+        return Err(bad_request("ObjectLockEnabled must be Enabled"));
+    }
+    let default_retention = req
+        .rule
+        .map(|rule| {
+            let retention = rule.default_retention;
+            RetentionMode::from_str(&retention.mode).map(|mode| DefaultRetention {
+                mode,
+                days: retention.days,
+                years: retention.years,
+            })
+        })
+        .transpose()
+        .map_err(bad_request)?;
+    service
+        .put_object_lock_configuration(bucket, ObjectLockConfiguration { default_retention })
+        .await?;
+    Ok(StatusCode::OK.into_response())
+}
+
+/// `GET /{bucket}?object-lock`
+pub async fn get_object_lock_configuration(
+    service: &StorageService,
+    bucket: &str,
+) -> Result<Response, AppError> {
+    let config = service.get_object_lock_configuration(bucket).await?;
+    let mut response = Response::new(Body::from(build_object_lock_xml(&config)));
+    response.headers_mut().insert(
+        header::CONTENT_TYPE,
+        HeaderValue::from_static("application/xml"),
+    );
+    Ok(response)
+}
+
+/// Retention requested by an upload's `x-amz-object-lock-*` headers.
+pub(crate) fn request_retention(headers: &HeaderMap) -> Result<Option<ObjectRetention>, AppError> {
+    let header = |name: &str| headers.get(name).and_then(|value| value.to_str().ok());
+    Ok(parse_requested_retention(
+        header("x-amz-object-lock-mode"),
+        header("x-amz-object-lock-retain-until-date"),
+    )?)
+}
+
+/// `x-amz-object-lock-mode` / `-retain-until-date` for a retained version.
+pub(crate) fn insert_retention_headers(headers: &mut HeaderMap, retention: &ObjectRetention) {
+    headers.insert(
+        HeaderName::from_static("x-amz-object-lock-mode"),
+        HeaderValue::from_static(retention.mode.as_str()),
+    );
+    if let Ok(value) = HeaderValue::from_str(
+        &retention
+            .retain_until
+            .to_rfc3339_opts(SecondsFormat::Millis, true),
+    ) {
+        headers.insert(
+            HeaderName::from_static("x-amz-object-lock-retain-until-date"),
+            value,
+        );
+    }
+}
+
+fn build_object_lock_xml(config: &ObjectLockConfiguration) -> String {
+    let mut xml = String::from(concat!(
+        r#"<?xml version="1.0" encoding="UTF-8"?>"#,
+        r#"<ObjectLockConfiguration xmlns="http://s3.amazonaws.com/doc/2006-03-01/">"#,
+        "<ObjectLockEnabled>Enabled</ObjectLockEnabled>"
+    ));
+    if let Some(default) = config.default_retention {
+        xml.push_str(&format!(
+            "<Rule><DefaultRetention><Mode>{}</Mode>",
+            default.mode
+        ));
+        if let Some(days) = default.days {
+            xml.push_str(&format!("<Days>{}</Days>", days));
+        }
+        if let Some(years) = default.years {
+            xml.push_str(&format!("<Years>{}</Years>", years));
+        }
+        xml.push_str("</DefaultRetention></Rule>");
+    }
+    xml.push_str("</ObjectLockConfiguration>");
+    xml
+}
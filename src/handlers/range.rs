@@ -0,0 +1,121 @@
+//! Byte-range reads for object GETs (`Range`, `If-Range`).
+//!
+//! Only a single `bytes=` range is served; multi-range and malformed headers
+//! are ignored and the whole object is returned, as RFC 9110 allows. A
+//! `Range` guarded by `If-Range` is honoured only while the validator (ETag or
+//! Last-Modified date) still matches, so a client resuming a download of a
+//! changed object receives the new object in full instead of a spliced body.
+
+use crate::models::object::Object;
+use axum::http::{HeaderMap, header};
+use chrono::DateTime;
+
+/// An inclusive byte range within an object.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct ByteRange {
+    pub start: u64,
+    pub end: u64,
+}
+
+impl ByteRange {
+    /// Number of bytes covered.
+    pub fn length(&self) -> u64 {
+        self.end - self.start + 1
+    }
+
+    /// `Content-Range` value for this range of an object of `size` bytes.
+    pub fn content_range(&self, size: u64) -> String {
+        format!("bytes {}-{}/{}", self.start, self.end, size)
+    }
+}
+
+/// How a GET should be answered with respect to `Range`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum RangeOutcome {
+    /// Send the whole object (no usable range, or `If-Range` failed).
+    Full,
+    /// Send `206 Partial Content` for this range.
+    Partial(ByteRange),
+    /// Send `416 Range Not Satisfiable`.
+    Unsatisfiable,
+}
+
+/// Decide how to answer a GET of `meta` given the request headers.
+pub fn evaluate(headers: &HeaderMap, meta: &Object) -> RangeOutcome {
+    let Some(range) = headers.get(header::RANGE).and_then(|v| v.to_str().ok()) else {
+        return RangeOutcome::Full;
+    };
+    if let Some(if_range) = headers.get(header::IF_RANGE) {
+        let matches = if_range
+            .to_str()
+            .map(|v| if_range_matches(v.trim(), meta))
+            .unwrap_or(false);
+        if !matches {
+            return RangeOutcome::Full;
+        }
+    }
+    parse_range(range, meta.size_bytes.max(0) as u64)
+}
+
+/// `If-Range` needs a strong match: weak ETags never match and dates must
+/// equal Last-Modified exactly (at the one-second resolution it is sent with).
+fn if_range_matches(value: &str, meta: &Object) -> bool {
+    if value.starts_with("W/") {
+        return false;
+    }
+    if value.starts_with('"') {
+        let tag = value.trim_matches('"');
+        return meta.etag.as_deref() == Some(tag);
+    }
+    match DateTime::parse_from_rfc2822(value) {
+        Ok(date) => date.timestamp() == meta.last_modified.timestamp(),
+        Err(_) => false,
+    }
+}
+
+/// Parse a single `bytes=` range against an object of `size` bytes.
+fn parse_range(value: &str, size: u64) -> RangeOutcome {
+    let Some(spec) = value.trim().strip_prefix("bytes=") else {
+        return RangeOutcome::Full;
+    };
+    if spec.contains(',') {
+        return RangeOutcome::Full;
+    }
+    let Some((first, last)) = spec.trim().split_once('-') else {
+        return RangeOutcome::Full;
+    };
+    let (first, last) = (first.trim(), last.trim());
+
+    if first.is_empty() {
+        // Suffix range: the last `n` bytes.
+        let Ok(n) = last.parse::<u64>() else {
+            return RangeOutcome::Full;
+        };
+        if n == 0 || size == 0 {
+            return RangeOutcome::Unsatisfiable;
+        }
+        return RangeOutcome::Partial(ByteRange {
+            start: size.saturating_sub(n),
+            end: size - 1,
+        });
+    }
+
+    let Ok(start) = first.parse::<u64>() else {
+        return RangeOutcome::Full;
+    };
+    let end = if last.is_empty() {
+        u64::MAX
+    } else {
+        match last.parse::<u64>() {
+            Ok(end) if end >= start => end,
+            _ => return RangeOutcome::Full,
+        }
+    };
+    if start >= size {
+        return RangeOutcome::Unsatisfiable;
+    }
+    RangeOutcome::Partial(ByteRange {
+        start,
+        end: end.min(size - 1),
+    })
+}
@@ -0,0 +1,329 @@
+//! Typed extractors for groups of S3 request headers.
+//!
+//! Each extractor parses and validates its headers once and rejects malformed
+//! input with a `400`, so handlers take what they need as arguments instead
+//! of reading the `HeaderMap` themselves:
+//! - `S3ConditionalHeaders`: `If-Match`, `If-None-Match`,
+//!   `If-Modified-Since`, `If-Unmodified-Since` (see `handlers::conditional`);
+//! - `S3CopySource`: `x-amz-copy-source` (as `Option<S3CopySource>`);
+//! - `S3SseHeaders`: `x-amz-server-side-encryption*`, including the SSE-C
+//!   customer key headers;
+//! - `S3TaggingHeader`: `x-amz-tagging`;
+//! - `S3StreamingHeaders`: `x-amz-content-sha256: STREAMING-...` /
+//!   `Content-Encoding: aws-chunked` bodies (see `services::aws_chunked`).
+//!
+//! Sub-operations that are handed the `HeaderMap` by a dispatching handler
+//! (multipart uploads, for instance) use the same parsing through
+//! `from_headers`.
+
+use crate::{
+    errors::AppError,
+    handlers::s3_path::decode_path_segment,
+    models::object_tag::ObjectTag,
+    services::{
+        aws_chunked::{STREAMING_PREFIX, StreamingUpload},
+        sse_c::{SSE_CUSTOMER_KEY_LEN, SseCustomerKey},
+        tagging,
+        user_metadata::CopySource,
+    },
+};
+use axum::{
+    extract::{FromRequestParts, OptionalFromRequestParts},
+    http::{HeaderMap, HeaderName, StatusCode, header, request::Parts},
+};
+use base64::{Engine as _, engine::general_purpose};
+use chrono::{DateTime, Utc};
+
+fn bad_request(message: impl Into<String>) -> AppError {
+    AppError::new(StatusCode::BAD_REQUEST, message)
+}
+
+/// The header's value, trimmed; `400` when it is not visible ASCII.
+fn header_str<'a>(headers: &'a HeaderMap, name: &str) -> Result<Option<&'a str>, AppError> {
+    headers
+        .get(name)
+        .map(|value| {
+            value
+                .to_str()
+                .map(str::trim)
+                .map_err(|_| bad_request(format!("{} is not ASCII", name)))
+        })
+        .transpose()
+}
+
+/// Conditional request headers.
+#[derive(Debug, Clone, Default)]
+pub struct S3ConditionalHeaders {
+    pub if_match: Option<String>,
+    pub if_none_match: Option<String>,
+    /// `None` when absent or not a valid date, which RFC 9110 says to ignore.
+    pub if_modified_since: Option<DateTime<Utc>>,
+    pub if_unmodified_since: Option<DateTime<Utc>>,
+}
+
+impl S3ConditionalHeaders {
+    pub fn from_headers(headers: &HeaderMap) -> Result<Self, AppError> {
+        let date = |name: HeaderName| {
+            header_str(headers, name.as_str()).map(|value| {
+                value
+                    .and_then(|v| DateTime::parse_from_rfc2822(v).ok())
+                    .map(|date| date.with_timezone(&Utc))
+            })
+        };
+        Ok(S3ConditionalHeaders {
+            if_match: header_str(headers, header::IF_MATCH.as_str())?.map(str::to_string),
+            if_none_match: header_str(headers, header::IF_NONE_MATCH.as_str())?.map(str::to_string),
+            if_modified_since: date(header::IF_MODIFIED_SINCE)?,
+            if_unmodified_since: date(header::IF_UNMODIFIED_SINCE)?,
+        })
+    }
+
+    /// Whether the request carries no usable precondition, so callers can
+    /// skip looking up the current object.
+    pub fn is_empty(&self) -> bool {
+        self.if_match.is_none()
+            && self.if_none_match.is_none()
+            && self.if_modified_since.is_none()
+            && self.if_unmodified_since.is_none()
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for S3ConditionalHeaders {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Self::from_headers(&parts.headers)
+    }
+}
+
+/// `x-amz-copy-source`: `bucket/key`, URL-encoded, with an optional leading
+/// `/` and `?versionId=V`.
+#[derive(Debug, Clone)]
+pub struct S3CopySource(pub CopySource);
+
+impl S3CopySource {
+    /// `None` when the header is absent.
+    pub fn from_headers(headers: &HeaderMap) -> Result<Option<Self>, AppError> {
+        let invalid = || bad_request("invalid x-amz-copy-source");
+        let Some(value) = headers.get("x-amz-copy-source") else {
+            return Ok(None);
+        };
+        let value = value.to_str().map_err(|_| invalid())?;
+        let (path, query) = match value.split_once('?') {
+            Some((path, query)) => (path, Some(query)),
+            None => (value, None),
+        };
+        let version_id = match query {
+            Some(query) => Some(
+                query
+                    .strip_prefix("versionId=")
+                    .filter(|v| !v.is_empty())
+                    .ok_or_else(invalid)?
+                    .to_string(),
+            ),
+            None => None,
+        };
+        let path = decode_path_segment(path).map_err(|_| invalid())?;
+        let (bucket, key) = path
+            .trim_start_matches('/')
+            .split_once('/')
+            .filter(|(bucket, key)| !bucket.is_empty() && !key.is_empty())
+            .ok_or_else(invalid)?;
+        Ok(Some(S3CopySource(CopySource {
+            bucket: bucket.to_string(),
+            key: key.to_string(),
+            version_id,
+        })))
+    }
+}
+
+impl<S: Send + Sync> OptionalFromRequestParts<S> for S3CopySource {
+    type Rejection = AppError;
+
+    async fn from_request_parts(
+        parts: &mut Parts,
+        _state: &S,
+    ) -> Result<Option<Self>, Self::Rejection> {
+        Self::from_headers(&parts.headers)
+    }
+}
+
+/// Server-side encryption requested for an object.
+#[derive(Debug, Clone, Default)]
+pub struct S3SseHeaders {
+    /// `x-amz-server-side-encryption`: `AES256` or `aws:kms`.
+    pub algorithm: Option<String>,
+    /// `x-amz-server-side-encryption-aws-kms-key-id` (with `aws:kms` only).
+    pub kms_key_id: Option<String>,
+    /// The SSE-C key, when all three customer key headers were sent.
+    pub customer_key: Option<SseCustomerKey>,
+}
+
+impl S3SseHeaders {
+    pub fn from_headers(headers: &HeaderMap) -> Result<Self, AppError> {
+        let algorithm = header_str(headers, "x-amz-server-side-encryption")?;
+        if let Some(algorithm) = algorithm
+            && algorithm != "AES256"
+            && algorithm != "aws:kms"
+        {
+            return Err(bad_request(
+                "x-amz-server-side-encryption must be AES256 or aws:kms",
+            ));
+        }
+        let kms_key_id = header_str(headers, "x-amz-server-side-encryption-aws-kms-key-id")?;
+        if kms_key_id.is_some() && algorithm != Some("aws:kms") {
+            return Err(bad_request(
+                "x-amz-server-side-encryption-aws-kms-key-id requires aws:kms encryption",
+            ));
+        }
+
+        let customer = (
+            header_str(headers, "x-amz-server-side-encryption-customer-algorithm")?,
+            header_str(headers, "x-amz-server-side-encryption-customer-key")?,
+            header_str(headers, "x-amz-server-side-encryption-customer-key-md5")?,
+        );
+        let customer_key = match customer {
+            (None, None, None) => None,
+            (Some(customer_algorithm), Some(key), Some(key_md5)) => {
+                if algorithm.is_some() {
+                    return Err(bad_request(
+                        "x-amz-server-side-encryption cannot be combined with a customer key",
+                    ));
+                }
+                Some(parse_customer_key(customer_algorithm, key, key_md5)?)
+            }
+            _ => {
+                return Err(bad_request(
+                    "SSE-C requires the customer algorithm, key and key MD5 headers",
+                ));
+            }
+        };
+        Ok(S3SseHeaders {
+            algorithm: algorithm.map(str::to_string),
+            kms_key_id: kms_key_id.map(str::to_string),
+            customer_key,
+        })
+    }
+
+    /// Refuse a customer key where the store cannot apply one (multipart
+    /// uploads) with `501`. `AES256`/`aws:kms` are accepted for
+    /// compatibility but not applied.
+    pub fn ensure_supported(&self) -> Result<(), AppError> {
+        if self.customer_key.is_some() {
+            return Err(AppError::new(
+                StatusCode::NOT_IMPLEMENTED,
+                "server-side encryption with customer keys is not supported for multipart uploads",
+            ));
+        }
+        Ok(())
+    }
+}
+
+fn parse_customer_key(
+    algorithm: &str,
+    key: &str,
+    key_md5: &str,
+) -> Result<SseCustomerKey, AppError> {
+    if algorithm != "AES256" {
+        return Err(bad_request(
+            "x-amz-server-side-encryption-customer-algorithm must be AES256",
+        ));
+    }
+    let key: [u8; SSE_CUSTOMER_KEY_LEN] = general_purpose::STANDARD
+        .decode(key)
+        .ok()
+        .and_then(|key| key.try_into().ok())
+        .ok_or_else(|| {
+            bad_request("x-amz-server-side-encryption-customer-key must be 256 bits, base64")
+        })?;
+    if general_purpose::STANDARD.encode(md5::compute(key).0) != key_md5 {
+        return Err(bad_request(
+            "x-amz-server-side-encryption-customer-key-MD5 does not match the key",
+        ));
+    }
+    Ok(SseCustomerKey {
+        key,
+        key_md5: key_md5.to_string(),
+    })
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for S3SseHeaders {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Self::from_headers(&parts.headers)
+    }
+}
+
+/// Tags from `x-amz-tagging` on PutObject / CreateMultipartUpload; empty
+/// when the header is absent.
+#[derive(Debug, Clone, Default)]
+pub struct S3TaggingHeader(pub Vec<ObjectTag>);
+
+impl S3TaggingHeader {
+    pub fn from_headers(headers: &HeaderMap) -> Result<Self, AppError> {
+        match header_str(headers, "x-amz-tagging")? {
+            Some(value) => Ok(S3TaggingHeader(tagging::parse_tagging_header(value)?)),
+            None => Ok(S3TaggingHeader::default()),
+        }
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for S3TaggingHeader {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Self::from_headers(&parts.headers)
+    }
+}
+
+/// Framing and signing of an `aws-chunked` upload body; `None` when the
+/// body is sent as is.
+#[derive(Debug, Clone, Default)]
+pub struct S3StreamingHeaders(pub Option<StreamingUpload>);
+
+impl S3StreamingHeaders {
+    pub fn from_headers(headers: &HeaderMap) -> Result<Self, AppError> {
+        let payload = header_str(headers, "x-amz-content-sha256")?.unwrap_or_default();
+        let aws_chunked =
+            header_str(headers, header::CONTENT_ENCODING.as_str())?.is_some_and(|value| {
+                value
+                    .split(',')
+                    .any(|coding| coding.trim().eq_ignore_ascii_case("aws-chunked"))
+            });
+        if !payload.starts_with(STREAMING_PREFIX) && !aws_chunked {
+            return Ok(S3StreamingHeaders(None));
+        }
+        let decoded_length = header_str(headers, "x-amz-decoded-content-length")?
+            .map(|value| {
+                value
+                    .parse::<u64>()
+                    .map_err(|_| bad_request("x-amz-decoded-content-length is not a number"))
+            })
+            .transpose()?;
+        let authorization = header_str(headers, header::AUTHORIZATION.as_str())?
+            .and_then(|value| value.strip_prefix("AWS4-HMAC-SHA256"))
+            .unwrap_or_default();
+        let field = |name: &str| {
+            authorization
+                .split(',')
+                .find_map(|part| part.trim().strip_prefix(name))
+                .map(str::to_string)
+        };
+        Ok(S3StreamingHeaders(Some(StreamingUpload {
+            payload: payload.to_string(),
+            decoded_length,
+            credential: field("Credential="),
+            seed_signature: field("Signature="),
+            amz_date: header_str(headers, "x-amz-date")?.map(str::to_string),
+        })))
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for S3StreamingHeaders {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Self::from_headers(&parts.headers)
+    }
+}
@@ -0,0 +1,79 @@
+//! Object paths (`/{bucket}/{*key}`) decoded the way S3 clients encode them.
+//!
+//! SDKs percent-encode every key byte outside the unreserved set, so a key
+//! is the path after the bucket decoded exactly once: `%20` is a space,
+//! `+` is a plus sign (form encoding does not apply to paths), `%25` is a
+//! literal `%` and `%2F` a `/`. A `%` not followed by two hex digits is
+//! taken literally, as curl and hand-written URLs send it (`100%/a`). The
+//! decoded bytes must be UTF-8.
+//!
+//! `S3ObjectPath` works on the raw path parameters rather than `Path`, so
+//! the rules are these whatever the router does, and keys that do not
+//! decode are answered with a `400` naming the problem.
+
+use crate::errors::AppError;
+use axum::{
+    extract::{FromRequestParts, RawPathParams},
+    http::{StatusCode, request::Parts},
+};
+
+/// The bucket and decoded key of an object route.
+#[derive(Debug, Clone)]
+pub struct S3ObjectPath {
+    pub bucket: String,
+    pub key: String,
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for S3ObjectPath {
+    type Rejection = AppError;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        let params = RawPathParams::from_request_parts(parts, state)
+            .await
+            .map_err(|err| AppError::new(StatusCode::BAD_REQUEST, err.to_string()))?;
+        let (mut bucket, mut key) = (None, None);
+        for (name, value) in &params {
+            match name {
+                "bucket" => bucket = Some(decode_path_segment(value)?),
+                "key" => key = Some(decode_path_segment(value)?),
+                _ => {}
+            }
+        }
+        match (bucket, key) {
+            (Some(bucket), Some(key)) => Ok(S3ObjectPath { bucket, key }),
+            _ => Err(AppError::internal("object route without bucket and key")),
+        }
+    }
+}
+
+/// Percent-decode `raw` once, keeping a `%` that starts no escape; `400`
+/// when the result is not UTF-8.
+pub fn decode_path_segment(raw: &str) -> Result<String, AppError> {
+    let bytes = raw.as_bytes();
+    let mut decoded = Vec::with_capacity(bytes.len());
+    let mut i = 0;
+    while i < bytes.len() {
+        let escaped = (bytes[i] == b'%')
+            .then(|| bytes.get(i + 1..i + 3))
+            .flatten()
+            .filter(|hex| hex.iter().all(u8::is_ascii_hexdigit))
+            .and_then(|hex| std::str::from_utf8(hex).ok())
+            .and_then(|hex| u8::from_str_radix(hex, 16).ok());
+        match escaped {
+            Some(byte) => {
+                decoded.push(byte);
+                i += 3;
+            }
+            None => {
+                decoded.push(bytes[i]);
+                i += 1;
+            }
+        }
+    }
+    String::from_utf8(decoded).map_err(|_| {
+        AppError::new(
+            StatusCode::BAD_REQUEST,
+            format!("invalid URI `{}`: the decoded key is not UTF-8", raw),
+        )
+    })
+}
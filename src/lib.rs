@@ -1,6 +1,9 @@
+#[cfg(feature = "client")]
+pub mod client;
 pub mod config;
 pub mod errors;
 pub mod handlers;
+pub mod middleware;
 pub mod models;
 pub mod routes;
 pub mod services;
@@ -5,17 +5,14 @@ use std::{
     fs,
     io::ErrorKind,
     path::{Path, PathBuf},
+    pin::Pin,
     sync::Arc,
+    time::Duration,
 };
 use tokio::net::TcpListener;
-use tracing_subscriber::EnvFilter;
+use tracing_subscriber::{EnvFilter, layer::SubscriberExt, reload, util::SubscriberInitExt};
 
-mod config;
-mod errors;
-mod handlers;
-mod models;
-mod routes;
-mod services;
+use object_store::{config, middleware, routes, services};
 
 #[tokio::main]
 async fn main() -> Result<()> {
@@ -27,10 +24,17 @@ async fn main() -> Result<()> {
         );
         EnvFilter::new("info")
     });
-    tracing_subscriber::fmt().with_env_filter(env_filter).init();
+    // The filter sits behind a reload layer so `PUT /admin/log-level` can
+    // change it at runtime.
+    let (env_filter, log_filter_handle) = reload::Layer::new(env_filter);
+    tracing_subscriber::registry()
+        .with(env_filter)
+        .with(tracing_subscriber::fmt::layer())
+        .init();
+    let log_filter = services::log_filter::LogFilter::new(log_filter_handle);
 
-    // --- Parse config + migrate flag ---
-    let (cfg, migrate) =
+    // --- Parse config + run mode ---
+    let (cfg, mode) =
         config::AppConfig::from_env_and_args().context("loading configuration from CLI/ENV")?;
 
     tracing::info!("Starting object-store with config: {:?}", cfg);
@@ -54,6 +58,34 @@ async fn main() -> Result<()> {
         );
     }
 
+    let staging_dir = match &cfg.staging_dir {
+        Some(dir) => {
+            fs::create_dir_all(dir)
+                .with_context(|| format!("creating staging directory {}", dir))?;
+            let canonical = fs::canonicalize(dir).unwrap_or_else(|_| PathBuf::from(dir));
+            tracing::info!("Staging uploads in {}", canonical.display());
+            Some(canonical)
+        }
+        None => None,
+    };
+
+    let mut volumes = Vec::with_capacity(cfg.volumes.len());
+    for volume in &cfg.volumes {
+        fs::create_dir_all(&volume.path).with_context(|| {
+            format!(
+                "creating volume `{}` at {}",
+                volume.name,
+                volume.path.display()
+            )
+        })?;
+        let path = fs::canonicalize(&volume.path).unwrap_or_else(|_| volume.path.clone());
+        tracing::info!("Volume `{}` at {}", volume.name, path.display());
+        volumes.push(services::placement::VolumeSpec {
+            name: volume.name.clone(),
+            path,
+        });
+    }
+
     // --- Initialize SQLite connection ---
     let db_url = &cfg.database_url;
     tracing::debug!("Connecting using raw URL => {}", db_url);
@@ -78,17 +110,18 @@ async fn main() -> Result<()> {
     );
 
     // Create parent directory if needed
-    if let Some(parent) = db_path_obj.parent() {
-        if !parent.exists() {
-            std::fs::create_dir_all(parent)
-                .with_context(|| format!("creating database directory {:?}", parent))?;
-            tracing::info!("Created missing directory {:?}", parent);
-        }
+    if let Some(parent) = db_path_obj.parent()
+        && !parent.exists()
+    {
+        std::fs::create_dir_all(parent)
+            .with_context(|| format!("creating database directory {:?}", parent))?;
+        tracing::info!("Created missing directory {:?}", parent);
     }
 
     // Try opening manually before SQLx
     match std::fs::OpenOptions::new()
         .create(true)
+        .truncate(false)
         .write(true)
         .open(db_path)
     {
@@ -105,18 +138,482 @@ async fn main() -> Result<()> {
     );
 
     // --- Handle migration mode ---
-    if migrate {
+    if mode == config::RunMode::Migrate {
         run_migrations(&db).await?;
         tracing::info!("Database migration complete.");
         return Ok(()); // exit after migration
     }
 
+    // --- Outbound HTTP (workers calling external endpoints) ---
+    let outbound = services::outbound::OutboundHttp::from_env(cfg.outbound_proxy_rules.clone())
+        .map_err(anyhow::Error::msg)
+        .context("loading outbound proxy configuration")?;
+    tracing::info!("Outbound proxy settings: {}", outbound.describe());
+
+    // --- Remote tier for lifecycle transitions ---
+    let remote_tier = match (&cfg.tier_url, &cfg.tier_credentials) {
+        (Some(url), Some(credentials)) => {
+            let store = services::blob_store::RemoteS3Store::new(
+                outbound.client().context("building remote tier client")?,
+                url.clone(),
+                cfg.tier_region.clone(),
+                credentials.clone(),
+            )
+            .map_err(anyhow::Error::msg)?;
+            tracing::info!("Remote tier: {:?}", store);
+            Some(Arc::new(store) as Arc<dyn services::blob_store::BlobStore>)
+        }
+        _ => None,
+    };
+
+    // --- Content scanner for buckets that scan uploads ---
+    let scanner = match &cfg.scanner {
+        Some(endpoint) => {
+            let scanner = endpoint
+                .connect(&outbound, Duration::from_secs(cfg.scanner_timeout_secs))
+                .context("building content scanner client")?;
+            tracing::info!("Content scanner: {:?}", scanner);
+            Some(scanner)
+        }
+        None => None,
+    };
+
     // --- Initialize core service ---
+    let overwrite_retention = (cfg.overwrite_retention_secs > 0)
+        .then(|| Duration::from_secs(cfg.overwrite_retention_secs));
+    let multipart_ttl =
+        (cfg.multipart_ttl_secs > 0).then(|| Duration::from_secs(cfg.multipart_ttl_secs));
+    let deleted_retention =
+        (cfg.deleted_retention_secs > 0).then(|| Duration::from_secs(cfg.deleted_retention_secs));
     let storage =
-        services::storage_service::StorageService::new(db.clone(), storage_dir_canonical.clone());
+        services::storage_service::StorageService::new(db.clone(), storage_dir_canonical.clone())
+            .with_options(services::storage_service::StorageOptions {
+                overwrite_retention,
+                deleted_retention,
+                decode_content_encoding: cfg.decode_content_encoding,
+                compute_sha256: cfg.compute_sha256,
+                verify_read_checksums: cfg.verify_read_checksums,
+                mmap_read_threshold: (cfg.mmap_read_threshold > 0)
+                    .then_some(cfg.mmap_read_threshold),
+                range_cache_bytes: (cfg.range_cache_bytes > 0).then_some(cfg.range_cache_bytes),
+                volume_failure_threshold: (cfg.volume_failure_threshold > 0)
+                    .then_some(cfg.volume_failure_threshold),
+                access_analytics: cfg.access_analytics,
+                usage_prefix_depth: (cfg.usage_prefix_depth > 0).then_some(cfg.usage_prefix_depth),
+                dedup: cfg.dedup,
+                bucket_templates: cfg.bucket_templates.clone(),
+                manifest_key: cfg.manifest_signing_key.clone(),
+                session_key: cfg.session_token_key.clone(),
+                metadata_key: cfg.metadata_encryption_key.clone(),
+                remote_tier,
+                global_quota: services::quota::Quota {
+                    max_bytes: (cfg.quota_bytes > 0).then_some(cfg.quota_bytes),
+                    max_objects: (cfg.quota_objects > 0).then_some(cfg.quota_objects),
+                },
+                scanner,
+                staging_dir,
+                volumes,
+                outbound: outbound.clone(),
+                access_keys: services::aws_chunked::AccessKeys::new(cfg.access_keys.clone()),
+                max_object_size: (cfg.max_object_size > 0).then_some(cfg.max_object_size),
+                max_request_body: (cfg.max_request_body > 0).then_some(cfg.max_request_body),
+            });
+    storage
+        .prepare_staging_dir()
+        .await
+        .context("checking the staging directory")?;
+    storage
+        .check_placed_volumes()
+        .await
+        .context("checking the configured volumes")?;
+    storage
+        .check_metadata_key()
+        .await
+        .context("checking the metadata encryption key")?;
+    let layout = services::shard_layout::Layout {
+        depth: cfg.shard_depth,
+        portable: cfg.portable_filenames,
+    };
+    let migrations = storage
+        .adopt_shard_layout(layout)
+        .await
+        .context("adopting the shard layout")?;
+    if migrations > 0 {
+        tracing::info!(
+            "Queued {} jobs moving payloads to the layout of {}",
+            migrations,
+            layout
+        );
+    }
+
+    // --- Handle metadata export/import modes ---
+    match &mode {
+        config::RunMode::ExportMetadata(path) => {
+            let file = fs::File::create(path)
+                .with_context(|| format!("creating export file {}", path.display()))?;
+            let counts = storage
+                .export_metadata(std::io::BufWriter::new(file))
+                .await
+                .context("exporting metadata")?;
+            tracing::info!(
+                "Exported {} buckets and {} objects to {}",
+                counts.buckets,
+                counts.objects,
+                path.display()
+            );
+            return Ok(());
+        }
+        config::RunMode::ImportMetadata(path) => {
+            let file = fs::File::open(path)
+                .with_context(|| format!("opening import file {}", path.display()))?;
+            let counts = storage
+                .import_metadata(std::io::BufReader::new(file))
+                .await
+                .context("importing metadata")?;
+            tracing::info!(
+                "Imported {} buckets and {} objects from {}",
+                counts.buckets,
+                counts.objects,
+                path.display()
+            );
+            return Ok(());
+        }
+        config::RunMode::GcMultipart => {
+            let summary = storage
+                .gc_multipart_uploads(multipart_ttl.unwrap_or_default())
+                .await
+                .context("reaping abandoned multipart uploads")?;
+            tracing::info!(
+                "Aborted {} multipart uploads older than {}s ({} bytes freed)",
+                summary.aborted_uploads,
+                cfg.multipart_ttl_secs,
+                summary.freed_bytes
+            );
+            return Ok(());
+        }
+        config::RunMode::Fsck => {
+            let report = storage
+                .fsck(services::fsck::FSCK_TEMP_MIN_AGE)
+                .await
+                .context("reconciling payloads with metadata")?;
+            println!("{}", serde_json::to_string_pretty(&report)?);
+            tracing::info!(
+                "Removed {} stale temp files; {} missing payloads, {} orphaned payload files",
+                report.temp_files_removed,
+                report.missing_payloads.len(),
+                report.orphaned_payloads.len()
+            );
+            return Ok(());
+        }
+        config::RunMode::CreateAdminToken {
+            name,
+            scopes,
+            ttl_secs,
+        } => {
+            let created = storage
+                .create_admin_token(name, scopes, ttl_secs.map(Duration::from_secs))
+                .await
+                .context("creating admin token")?;
+            println!("{}", serde_json::to_string_pretty(&created)?);
+            if !cfg.admin_tokens {
+                tracing::warn!(
+                    "Admin tokens are only accepted with OBJECT_STORE_ADMIN_TOKENS enabled"
+                );
+            }
+            return Ok(());
+        }
+        config::RunMode::ListAdminTokens => {
+            let tokens = storage
+                .list_admin_tokens()
+                .await
+                .context("listing admin tokens")?;
+            println!("{}", serde_json::to_string_pretty(&tokens)?);
+            return Ok(());
+        }
+        config::RunMode::RevokeAdminToken(id) => {
+            if !storage
+                .revoke_admin_token(*id)
+                .await
+                .context("revoking admin token")?
+            {
+                return Err(anyhow::anyhow!("no admin token with id {}", id));
+            }
+            tracing::info!("Revoked admin token {}", id);
+            return Ok(());
+        }
+        config::RunMode::Serve | config::RunMode::Migrate => {}
+    }
+
+    // --- TLS: load the certificate before anything starts serving ---
+    let tls = match (&cfg.tls_cert, &cfg.tls_key) {
+        (Some(cert), Some(key)) => {
+            let files = services::tls::TlsFiles::new(cert, key);
+            let tls = files.load()?;
+            if cfg.tls_reload_secs > 0 {
+                tracing::info!(
+                    "Reloading the TLS certificate when it changes (checked every {}s)",
+                    cfg.tls_reload_secs
+                );
+                services::tls::spawn_tls_reloader(
+                    files,
+                    tls.clone(),
+                    Duration::from_secs(cfg.tls_reload_secs),
+                );
+            }
+            Some(tls)
+        }
+        _ => None,
+    };
+
+    // --- Crash recovery: nothing is writing yet, so every temp file is stale ---
+    let report = storage
+        .fsck(Duration::ZERO)
+        .await
+        .context("reconciling payloads with metadata")?;
+    if !report.is_clean() {
+        tracing::warn!(
+            "Startup check found {} missing payloads and {} orphaned payload files; run --fsck for details",
+            report.missing_payloads.len(),
+            report.orphaned_payloads.len()
+        );
+    }
+
+    // --- Background maintenance ---
+    if let Some(retention) = overwrite_retention {
+        let period = retention.min(Duration::from_secs(60));
+        services::recycle::spawn_recycle_purger(storage.clone(), period);
+    }
+    if let Some(ttl) = multipart_ttl {
+        services::multipart::spawn_multipart_reaper(
+            storage.clone(),
+            ttl,
+            services::multipart::MULTIPART_GC_TICK.min(ttl),
+        );
+    }
+    if let Some(retention) = deleted_retention {
+        services::purge::spawn_deleted_purger(
+            storage.clone(),
+            retention,
+            services::purge::PURGE_TICK.min(retention),
+        );
+    }
+    let notifications =
+        services::notifications::NotificationDispatcher::new(storage.clone(), &outbound)
+            .context("building notification webhook client")?;
+    services::notifications::spawn_notification_dispatcher(
+        notifications,
+        services::notifications::NOTIFICATION_TICK,
+    );
+    services::publishers::spawn_event_publishers(storage.clone());
+    let replicator = services::replication::Replicator::new(storage.clone(), &outbound)
+        .context("building replication client")?;
+    services::replication::spawn_replicator(replicator, services::replication::REPLICATION_TICK);
+    if cfg.changes_retention_secs > 0 {
+        let retention = Duration::from_secs(cfg.changes_retention_secs);
+        services::changes::spawn_change_pruner(
+            storage.clone(),
+            retention,
+            services::changes::CHANGES_PRUNE_TICK.min(retention),
+        );
+    }
+    if cfg.dedup {
+        services::dedup::spawn_dedup_gc(storage.clone(), services::dedup::DEDUP_GC_TICK);
+    }
+    match storage.resume_multipart_assemblies().await {
+        Ok(0) => {}
+        Ok(count) => tracing::info!("Resuming {} interrupted multipart completions", count),
+        Err(err) => tracing::warn!("Could not resume multipart completions: {}", err),
+    }
+    services::reclaim::spawn_reclaimers(&storage, services::reclaim::RECLAIM_WORKERS).await;
+    services::jobs::spawn_job_runner(storage.clone(), services::jobs::JOB_POLL);
+    services::snapshot::spawn_snapshot_scheduler(
+        storage.clone(),
+        services::snapshot::SNAPSHOT_TICK,
+    );
+    services::lifecycle::spawn_lifecycle_worker(
+        storage.clone(),
+        services::lifecycle::LIFECYCLE_TICK,
+    );
+    if storage.options.scanner.is_some() {
+        services::scanner::spawn_scan_worker(storage.clone(), services::scanner::SCAN_POLL);
+    }
+    let alert_settings = services::alerts::AlertSettings {
+        bucket_bytes: (cfg.alert_bucket_bytes > 0).then_some(cfg.alert_bucket_bytes),
+        account_bytes: (cfg.alert_account_bytes > 0).then_some(cfg.alert_account_bytes),
+        min_disk_free_bytes: (cfg.alert_min_disk_free_bytes > 0)
+            .then_some(cfg.alert_min_disk_free_bytes),
+        max_error_rate: (cfg.alert_error_rate > 0.0).then_some(cfg.alert_error_rate),
+        webhook: cfg.alert_webhook.clone(),
+    };
+    let response_stats = alert_settings
+        .max_error_rate
+        .map(|_| middleware::response_stats::ResponseStats::default());
+    if alert_settings.is_enabled() {
+        let monitor = services::alerts::AlertMonitor::new(
+            storage.clone(),
+            alert_settings,
+            &outbound,
+            response_stats.clone(),
+        )
+        .context("building alert webhook client")?;
+        services::alerts::spawn_alert_monitor(monitor, services::alerts::ALERT_TICK);
+        tracing::info!("Alert monitor enabled");
+    } else if alert_settings.webhook.is_some() {
+        tracing::warn!("OBJECT_STORE_ALERT_WEBHOOK is set but no alert threshold is configured");
+    }
 
     // --- Build router ---
-    let app: Router = routes::routes::routes().with_state(storage);
+    let feature_flags = middleware::feature_flags::FeatureFlags::new(cfg.disabled_apis.clone());
+    if !feature_flags.is_empty() {
+        tracing::info!("Disabled API groups: {:?}", cfg.disabled_apis);
+    }
+    let max_request_body = storage.options.max_request_body;
+    let mut app: Router = routes::routes::routes()
+        .with_state(storage.clone())
+        .layer(axum::middleware::from_fn_with_state(
+            storage.clone(),
+            middleware::config_etag::config_etags,
+        ))
+        .layer(axum::Extension(log_filter))
+        .layer(axum::middleware::from_fn_with_state(
+            feature_flags,
+            middleware::feature_flags::enforce_feature_flags,
+        ));
+    if let Some(max_bytes) = max_request_body {
+        tracing::info!("Limiting request bodies to {} bytes", max_bytes);
+        app = app.layer(axum::middleware::from_fn_with_state(
+            middleware::body_limit::BodyLimit { max_bytes },
+            middleware::body_limit::limit_request_body,
+        ));
+    }
+    if let Some(endpoint) = cfg.authorizer.clone() {
+        let authorizer = middleware::authorizer::Authorizer::new(
+            endpoint,
+            &outbound,
+            Duration::from_millis(cfg.authorizer_timeout_ms),
+            Duration::from_secs(cfg.authorizer_cache_ttl_secs),
+        )
+        .context("building authorizer client")?
+        .with_session_key(cfg.session_token_key.clone())
+        .with_access_keys(storage.options.access_keys.clone())
+        .with_signature_debug(cfg.debug_signature_token.as_deref());
+        let endpoint = authorizer.endpoint();
+        tracing::info!(
+            "Delegating authorization to {:?} endpoint at {}",
+            endpoint.kind,
+            endpoint.url.host_str().unwrap_or("?")
+        );
+        app = app.layer(axum::middleware::from_fn_with_state(
+            authorizer,
+            middleware::authorizer::enforce_authorization,
+        ));
+    } else if cfg.session_token_key.is_some() {
+        tracing::warn!(
+            "OBJECT_STORE_SESSION_TOKEN_KEY is set but no authorizer is configured; session policies are not enforced"
+        );
+    }
+    if let Some(target) = cfg.shadow_url.clone() {
+        let shadow = middleware::shadow::Shadow::new(
+            middleware::shadow::ShadowSettings {
+                target,
+                percent: cfg.shadow_percent,
+                include_writes: cfg.shadow_writes,
+            },
+            &outbound,
+        )
+        .context("building shadow client")?;
+        let settings = shadow.settings();
+        tracing::info!(
+            "Shadowing {}% of {} to {}",
+            settings.percent,
+            if settings.include_writes {
+                "reads and writes"
+            } else {
+                "reads"
+            },
+            settings.target.host_str().unwrap_or("?")
+        );
+        app = app.layer(axum::middleware::from_fn_with_state(
+            shadow,
+            middleware::shadow::shadow_requests,
+        ));
+    }
+    let mut admin_auth = build_admin_auth(&cfg, &outbound)?;
+    if cfg.admin_tokens {
+        tracing::info!("Admin API accepts scoped admin tokens");
+        admin_auth = admin_auth.with_tokens(storage.clone());
+    }
+    let admin_auth_enabled = admin_auth.is_enabled();
+    if !admin_auth_enabled {
+        tracing::warn!("No OIDC or LDAP provider configured; the admin API is unauthenticated");
+    }
+    let mut app = app.layer(axum::middleware::from_fn_with_state(
+        admin_auth,
+        middleware::admin_auth::require_admin_identity,
+    ));
+    if cfg.log_denied_requests {
+        tracing::info!("Recording denied requests (audit log, GET /admin/denials)");
+        app = app.layer(axum::middleware::from_fn_with_state(
+            middleware::denial_log::DenialLog::default(),
+            middleware::denial_log::record_denials,
+        ));
+    }
+    if cfg.proxy_cache_headers {
+        tracing::info!("Adding caching-proxy headers to GET/HEAD responses");
+        app = app.layer(axum::middleware::from_fn(
+            middleware::proxy_cache::cache_friendly_headers,
+        ));
+    }
+    if let Some(stats) = response_stats {
+        app = app.layer(axum::middleware::from_fn_with_state(
+            stats,
+            middleware::response_stats::count_responses,
+        ));
+    }
+    if let Some(token) = &cfg.debug_timing_token {
+        tracing::info!("Reporting Server-Timing for requests sending the debug timing token");
+        app = app.layer(axum::middleware::from_fn_with_state(
+            middleware::server_timing::DebugTiming::new(token),
+            middleware::server_timing::server_timing,
+        ));
+    }
+    let throttle = middleware::throttle::Throttle::new(middleware::throttle::ThrottleSettings {
+        rate_per_ip: cfg.rate_limit_per_ip,
+        burst: cfg.rate_limit_burst,
+        max_concurrent_uploads: cfg.max_concurrent_uploads,
+    });
+    if throttle.is_enabled() {
+        let settings = throttle.settings();
+        tracing::info!(
+            "Throttling clients to {} requests/s (burst {}) and uploads to {} at once (0 = unlimited)",
+            settings.rate_per_ip,
+            settings.burst,
+            settings.max_concurrent_uploads
+        );
+        app = app.layer(axum::middleware::from_fn_with_state(
+            throttle,
+            middleware::throttle::throttle_requests,
+        ));
+    }
+    let app = app.layer(axum::middleware::from_fn_with_state(
+        middleware::slo_stats::SloStats::default(),
+        middleware::slo_stats::record_slo_stats,
+    ));
+    if cfg.access_log {
+        tracing::info!("Writing the access log (target `access`)");
+    }
+    let app = app.layer(axum::middleware::from_fn_with_state(
+        middleware::access_log::AccessLog {
+            enabled: cfg.access_log,
+        },
+        middleware::access_log::log_access,
+    ));
+    let app = app.layer(axum::middleware::from_fn_with_state(
+        middleware::client_info::TrustedProxies::new(cfg.trusted_proxies.clone())
+            .with_header(cfg.forwarded_header)
+            .with_tls(tls.is_some()),
+        middleware::client_info::resolve_client_info,
+    ));
 
     // --- Start server ---
     let addr = cfg.addr();
@@ -151,12 +648,98 @@ async fn main() -> Result<()> {
         }
     };
 
-    tracing::info!("Server listening on http://{}", listener.local_addr()?);
-    axum::serve(listener, app).await?;
+    let local_addr = listener.local_addr()?;
+    let scheme = if tls.is_some() { "https" } else { "http" };
+    tracing::info!("Server listening on {}://{}", scheme, local_addr);
+    let make_service = app.into_make_service_with_connect_info::<std::net::SocketAddr>();
+    let server: Pin<Box<dyn Future<Output = std::io::Result<()>> + Send>> = match tls {
+        Some(tls) => {
+            Box::pin(axum_server::from_tcp_rustls(listener.into_std()?, tls).serve(make_service))
+        }
+        None => Box::pin(axum::serve(listener, make_service).into_future()),
+    };
+    if cfg.self_test {
+        let server = tokio::spawn(server);
+        // A wildcard bind is reachable over loopback.
+        let mut target = local_addr;
+        if target.ip().is_unspecified() {
+            target.set_ip(match target {
+                std::net::SocketAddr::V4(_) => std::net::Ipv4Addr::LOCALHOST.into(),
+                std::net::SocketAddr::V6(_) => std::net::Ipv6Addr::LOCALHOST.into(),
+            });
+        }
+        services::self_test::run_self_test(
+            &format!("{}://{}", scheme, target),
+            admin_auth_enabled,
+            &storage.options.access_keys,
+        )
+        .await
+        .map_err(anyhow::Error::msg)
+        .context("self-test failed")?;
+        tracing::info!("Self-test passed");
+        server.await??;
+    } else {
+        server.await?;
+    }
 
     Ok(())
 }
 
+/// Build the admin API identity providers from configuration.
+fn build_admin_auth(
+    cfg: &config::AppConfig,
+    outbound: &services::outbound::OutboundHttp,
+) -> Result<middleware::admin_auth::AdminAuth> {
+    use services::identity::{LdapAuthenticator, LdapSettings, OidcSettings, OidcValidator};
+
+    let oidc = match (&cfg.oidc_issuer, &cfg.oidc_audience) {
+        (Some(issuer), Some(audience)) => {
+            tracing::info!("Admin API accepts OIDC tokens from {}", issuer);
+            Some(
+                OidcValidator::new(
+                    OidcSettings {
+                        issuer: issuer.clone(),
+                        audience: audience.clone(),
+                        jwks_url: cfg.oidc_jwks_url.clone(),
+                        groups_claim: cfg.oidc_groups_claim.clone(),
+                    },
+                    outbound,
+                )
+                .context("building OIDC client")?,
+            )
+        }
+        _ => None,
+    };
+    let ldap = match (&cfg.ldap_url, &cfg.ldap_user_dn) {
+        (Some(url), Some(user_dn)) => {
+            tracing::info!("Admin API accepts LDAP logins against {}", url);
+            Some(LdapAuthenticator::new(LdapSettings {
+                url: url.clone(),
+                user_dn_template: user_dn.clone(),
+                group_attribute: cfg.ldap_group_attribute.clone(),
+            }))
+        }
+        _ => None,
+    };
+    if (oidc.is_some() || ldap.is_some())
+        && cfg.admin_groups.is_empty()
+        && cfg.readonly_groups.is_empty()
+    {
+        tracing::warn!(
+            "No admin or read-only groups configured; every admin login will be refused"
+        );
+    }
+
+    Ok(middleware::admin_auth::AdminAuth::new(
+        oidc,
+        ldap,
+        middleware::admin_auth::RoleMapping {
+            admin_groups: cfg.admin_groups.clone(),
+            readonly_groups: cfg.readonly_groups.clone(),
+        },
+    ))
+}
+
 /// Run SQLite migrations with SQLx’s embedded runner so statements can span lines, include
 /// comments, and keep semicolons without manual splitting.
 async fn run_migrations(db: &Arc<sqlx::Pool<sqlx::Sqlite>>) -> Result<()> {
@@ -0,0 +1,163 @@
+//! Request IDs and the structured access log.
+//!
+//! Every request gets an `x-amz-request-id`, returned on its response
+//! whatever produced it (handler, error or an enforcement layer) and stored
+//! as a [`RequestId`] request extension so inner layers can refer to it.
+//!
+//! With `OBJECT_STORE_ACCESS_LOG` enabled, each request also writes one JSON
+//! line to the `access` tracing target once its response body has been sent
+//! (or the client went away): request id, method, bucket, key, status, bytes
+//! sent, duration and client address. Admin and health endpoints are logged
+//! without a bucket or key.
+
+use crate::middleware::{
+    authorizer::AuthzContext, client_info::ClientInfo, feature_flags::ApiGroup,
+};
+use axum::{
+    body::{Body, HttpBody},
+    extract::{Request, State},
+    http::{HeaderName, HeaderValue},
+    middleware::Next,
+    response::Response,
+};
+use chrono::{DateTime, Utc};
+use futures::StreamExt;
+use serde::Serialize;
+use std::{fmt, time::Instant};
+use uuid::Uuid;
+
+pub const REQUEST_ID_HEADER: HeaderName = HeaderName::from_static("x-amz-request-id");
+
+/// Identifier of one request, as returned in `x-amz-request-id`.
+#[derive(Debug, Clone, PartialEq, Eq, Hash)]
+pub struct RequestId(String);
+
+impl RequestId {
+    /// A fresh id: 16 uppercase hex digits, like S3's.
+    pub fn generate() -> Self {
+        let mut id = Uuid::new_v4().simple().to_string();
+        id.truncate(16);
+        Self(id.to_ascii_uppercase())
+    }
+
+    pub fn as_str(&self) -> &str {
+        &self.0
+    }
+}
+
+impl fmt::Display for RequestId {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+/// Whether requests are written to the access log.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct AccessLog {
+    pub enabled: bool,
+}
+
+/// One line of the access log.
+#[derive(Debug, Clone, Serialize)]
+pub struct AccessLogEntry {
+    pub time: DateTime<Utc>,
+    pub request_id: String,
+    pub method: String,
+    pub bucket: Option<String>,
+    pub key: Option<String>,
+    pub status: u16,
+    /// Response body bytes sent.
+    pub bytes: u64,
+    /// From the request until the last body byte was sent.
+    pub duration_ms: f64,
+    pub remote_addr: Option<String>,
+}
+
+impl AccessLogEntry {
+    fn emit(mut self, bytes: u64, started: Instant) {
+        self.bytes = bytes;
+        self.duration_ms = started.elapsed().as_secs_f64() * 1000.0;
+        match serde_json::to_string(&self) {
+            Ok(line) => tracing::info!(target: "access", "{}", line),
+            Err(err) => tracing::warn!("could not encode access log entry: {}", err),
+        }
+    }
+}
+
+/// Logs its entry when dropped, i.e. once the body stream is done with.
+struct PendingEntry {
+    entry: Option<AccessLogEntry>,
+    bytes: u64,
+    started: Instant,
+}
+
+impl PendingEntry {
+    fn sent(&mut self, bytes: usize) {
+        self.bytes += bytes as u64;
+    }
+}
+
+impl Drop for PendingEntry {
+    fn drop(&mut self) {
+        if let Some(entry) = self.entry.take() {
+            entry.emit(self.bytes, self.started);
+        }
+    }
+}
+
+/// Tag the request and its response with a request id and, when enabled,
+/// write the access log line.
+pub async fn log_access(
+    State(log): State<AccessLog>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let started = Instant::now();
+    let request_id = RequestId::generate();
+    request.extensions_mut().insert(request_id.clone());
+    let entry = log.enabled.then(|| {
+        let group = ApiGroup::classify(request.method(), request.uri()).unwrap_or(ApiGroup::Admin);
+        let client = request.extensions().get::<ClientInfo>();
+        let context = AuthzContext::from_request(group, request.uri(), request.headers(), client);
+        AccessLogEntry {
+            time: Utc::now(),
+            request_id: request_id.to_string(),
+            method: request.method().to_string(),
+            bucket: context.bucket,
+            key: context.key,
+            status: 0,
+            bytes: 0,
+            duration_ms: 0.0,
+            remote_addr: context.client_ip,
+        }
+    });
+
+    let mut response = next.run(request).await;
+    if let Ok(value) = HeaderValue::from_str(request_id.as_str()) {
+        response.headers_mut().insert(REQUEST_ID_HEADER, value);
+    }
+    let Some(mut entry) = entry else {
+        return response;
+    };
+    entry.status = response.status().as_u16();
+
+    // Bodies of a known size are sent as is; streamed ones are counted as
+    // they go, so the line reflects what the client actually received.
+    if let Some(size) = response.body().size_hint().exact() {
+        entry.emit(size, started);
+        return response;
+    }
+    let (parts, body) = response.into_parts();
+    let mut pending = PendingEntry {
+        entry: Some(entry),
+        bytes: 0,
+        started,
+    };
+    let stream = body.into_data_stream().map(move |chunk| {
+        if let Ok(chunk) = &chunk {
+            pending.sent(chunk.len());
+        }
+        chunk
+    });
+    Response::from_parts(parts, Body::from_stream(stream))
+}
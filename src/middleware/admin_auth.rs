@@ -0,0 +1,353 @@
+//! Authentication for the admin API.
+//!
+//! Requests under `/admin` must present either an OIDC bearer token
+//! (`Authorization: Bearer ...`) or LDAP credentials (`Authorization: Basic
+//! ...`), depending on which providers are configured. The user's groups are
+//! mapped to a [`AdminRole`]: members of `OBJECT_STORE_ADMIN_GROUPS` get full
+//! access, members of `OBJECT_STORE_READONLY_GROUPS` may only `GET`/`HEAD`.
+//! Anyone else is refused with `403`.
+//!
+//! Group names match exactly (case-insensitively) or by the first RDN value of
+//! a DN, so `cn=storage-admins,ou=groups,dc=example,dc=com` matches
+//! `storage-admins`.
+//!
+//! With `OBJECT_STORE_ADMIN_TOKENS` on, bearer tokens starting `osat_` are
+//! admin tokens (see `services::admin_tokens`) instead of OIDC tokens. They
+//! only reach the endpoints their scopes cover (see [`scope_permits`]);
+//! anything else is refused with `403`, and unknown, revoked or expired
+//! tokens with `401`.
+//!
+//! With no provider configured and admin tokens off, the admin API stays
+//! open, as before.
+
+use crate::{
+    errors::AppError,
+    middleware::{
+        denial_log::{Denial, DenialSource},
+        server_timing::{Phase, timed},
+    },
+    models::admin_token::AdminScope,
+    services::{
+        admin_tokens::ADMIN_TOKEN_PREFIX,
+        identity::{IdentityError, LdapAuthenticator, OidcValidator, VerifiedUser},
+        storage_service::StorageService,
+    },
+};
+use axum::{
+    extract::{Request, State},
+    http::{HeaderValue, Method, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use base64::{Engine as _, engine::general_purpose};
+use serde::Serialize;
+use std::sync::Arc;
+
+/// What an authenticated admin user may do.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AdminRole {
+    Admin,
+    ReadOnly,
+}
+
+/// The authenticated caller, attached as a request extension.
+#[derive(Debug, Clone, Serialize)]
+pub struct AdminIdentity {
+    pub subject: String,
+    pub role: AdminRole,
+    pub groups: Vec<String>,
+    /// `oidc`, `ldap` or `token`.
+    pub provider: &'static str,
+    /// What an admin token may do; empty for logins.
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    pub scopes: Vec<AdminScope>,
+}
+
+/// Group-to-role mapping.
+#[derive(Debug, Clone, Default)]
+pub struct RoleMapping {
+    pub admin_groups: Vec<String>,
+    pub readonly_groups: Vec<String>,
+}
+
+impl RoleMapping {
+    /// The strongest role granted by any of `groups`.
+    pub fn role_for(&self, groups: &[String]) -> Option<AdminRole> {
+        let member_of = |wanted: &[String]| {
+            groups
+                .iter()
+                .any(|group| wanted.iter().any(|w| group_matches(group, w)))
+        };
+        if member_of(&self.admin_groups) {
+            Some(AdminRole::Admin)
+        } else if member_of(&self.readonly_groups) {
+            Some(AdminRole::ReadOnly)
+        } else {
+            None
+        }
+    }
+}
+
+fn group_matches(group: &str, wanted: &str) -> bool {
+    if group.eq_ignore_ascii_case(wanted) {
+        return true;
+    }
+    group
+        .split(',')
+        .next()
+        .and_then(|rdn| rdn.split_once('='))
+        .is_some_and(|(_, value)| value.trim().eq_ignore_ascii_case(wanted))
+}
+
+/// Whether a token with `scope` may send `method` to the admin `path`.
+/// Every token may ask `/admin/whoami`.
+pub fn scope_permits(scope: AdminScope, method: &Method, path: &str) -> bool {
+    let read = matches!(*method, Method::GET | Method::HEAD);
+    let segments: Vec<&str> = path
+        .trim_start_matches("/admin")
+        .split('/')
+        .filter(|segment| !segment.is_empty())
+        .collect();
+    match (scope, segments.as_slice()) {
+        (_, ["whoami"]) => read,
+        (AdminScope::Admin, _) => true,
+        (AdminScope::ReadOnly, _) => read,
+        (
+            AdminScope::Metrics,
+            [
+                "slo" | "limits" | "uploads" | "volumes" | "denials" | "jobs",
+                ..,
+            ]
+            | ["buckets", _, "stats"],
+        ) => read,
+        (AdminScope::Backup, ["buckets", _] | ["buckets", _, "manifest"] | ["jobs", ..]) => read,
+        (AdminScope::Backup, ["buckets", _, "snapshots"]) => read || method == Method::POST,
+        (AdminScope::Backup, ["buckets", _, "manifest", "verify"]) => method == Method::POST,
+        (AdminScope::Sessions, ["sessions"]) => method == Method::POST,
+        _ => false,
+    }
+}
+
+/// Configured identity providers plus the role mapping.
+#[derive(Clone, Default)]
+pub struct AdminAuth {
+    oidc: Option<Arc<OidcValidator>>,
+    ldap: Option<Arc<LdapAuthenticator>>,
+    roles: RoleMapping,
+    /// Where admin tokens are looked up, when they are accepted.
+    tokens: Option<StorageService>,
+}
+
+impl AdminAuth {
+    pub fn new(
+        oidc: Option<OidcValidator>,
+        ldap: Option<LdapAuthenticator>,
+        roles: RoleMapping,
+    ) -> Self {
+        Self {
+            oidc: oidc.map(Arc::new),
+            ldap: ldap.map(Arc::new),
+            roles,
+            tokens: None,
+        }
+    }
+
+    /// Also accept admin tokens stored by `service`.
+    pub fn with_tokens(mut self, service: StorageService) -> Self {
+        self.tokens = Some(service);
+        self
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.oidc.is_some() || self.ldap.is_some() || self.tokens.is_some()
+    }
+
+    /// The identity of the admin token in `authorization`, if it holds one.
+    async fn authenticate_token(
+        &self,
+        authorization: Option<&str>,
+    ) -> Option<Result<AdminIdentity, AppError>> {
+        let service = self.tokens.as_ref()?;
+        let (scheme, secret) = authorization?.split_once(' ')?;
+        let secret = secret.trim();
+        if !scheme.eq_ignore_ascii_case("bearer") || !secret.starts_with(ADMIN_TOKEN_PREFIX) {
+            return None;
+        }
+        let token = match service.authenticate_admin_token(secret).await {
+            Ok(Some(token)) => token,
+            Ok(None) => {
+                return Some(Err(AppError::new(
+                    StatusCode::UNAUTHORIZED,
+                    "invalid credentials",
+                )));
+            }
+            Err(err) => {
+                tracing::warn!("admin token lookup failed: {}", err);
+                return Some(Err(AppError::new(
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    "admin token store unavailable",
+                )));
+            }
+        };
+        let scopes = token.scopes.0;
+        let role = if scopes.contains(&AdminScope::Admin) {
+            AdminRole::Admin
+        } else {
+            AdminRole::ReadOnly
+        };
+        Some(Ok(AdminIdentity {
+            subject: format!("token:{}", token.name),
+            role,
+            groups: Vec::new(),
+            provider: "token",
+            scopes,
+        }))
+    }
+
+    /// Authenticate an `Authorization` header value.
+    async fn authenticate(
+        &self,
+        authorization: Option<&str>,
+    ) -> Result<(VerifiedUser, &'static str), AppError> {
+        let unauthorized = |msg: &str| AppError::new(StatusCode::UNAUTHORIZED, msg);
+        let authorization = authorization.ok_or_else(|| unauthorized("authentication required"))?;
+        let (scheme, credentials) = authorization
+            .split_once(' ')
+            .ok_or_else(|| unauthorized("malformed Authorization header"))?;
+
+        let result = if scheme.eq_ignore_ascii_case("bearer") {
+            let oidc = self
+                .oidc
+                .as_ref()
+                .ok_or_else(|| unauthorized("bearer tokens are not accepted"))?;
+            oidc.validate(credentials.trim()).await.map(|u| (u, "oidc"))
+        } else if scheme.eq_ignore_ascii_case("basic") {
+            let ldap = self
+                .ldap
+                .as_ref()
+                .ok_or_else(|| unauthorized("basic credentials are not accepted"))?;
+            let decoded = general_purpose::STANDARD
+                .decode(credentials.trim())
+                .ok()
+                .and_then(|bytes| String::from_utf8(bytes).ok())
+                .ok_or_else(|| unauthorized("malformed basic credentials"))?;
+            let (user, password) = decoded
+                .split_once(':')
+                .ok_or_else(|| unauthorized("malformed basic credentials"))?;
+            ldap.authenticate(user, password).await.map(|u| (u, "ldap"))
+        } else {
+            return Err(unauthorized("unsupported authentication scheme"));
+        };
+
+        result.map_err(|err| match err {
+            IdentityError::Rejected(reason) => {
+                tracing::debug!("admin login rejected: {}", reason);
+                unauthorized("invalid credentials")
+            }
+            IdentityError::Unavailable(reason) => {
+                tracing::warn!("admin identity provider error: {}", reason);
+                AppError::new(
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    "identity provider unavailable",
+                )
+            }
+        })
+    }
+
+    fn challenge(&self) -> HeaderValue {
+        let mut schemes = Vec::new();
+        if self.oidc.is_some() || self.tokens.is_some() {
+            schemes.push(r#"Bearer realm="object-store-admin""#);
+        }
+        if self.ldap.is_some() {
+            schemes.push(r#"Basic realm="object-store-admin""#);
+        }
+        HeaderValue::from_str(&schemes.join(", ")).expect("static challenge is valid")
+    }
+}
+
+/// Authenticate and authorize requests under `/admin`.
+pub async fn require_admin_identity(
+    State(auth): State<AdminAuth>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let path = request.uri().path();
+    let is_admin = path == "/admin" || path.starts_with("/admin/");
+    if !is_admin || !auth.is_enabled() {
+        return next.run(request).await;
+    }
+
+    let authorization = request
+        .headers()
+        .get(header::AUTHORIZATION)
+        .and_then(|v| v.to_str().ok());
+    if let Some(result) = timed(Phase::Auth, auth.authenticate_token(authorization)).await {
+        let identity = match result {
+            Ok(identity) => identity,
+            Err(err) => return refuse_login(&auth, err),
+        };
+        let (method, path) = (request.method(), request.uri().path());
+        if !identity
+            .scopes
+            .iter()
+            .any(|scope| scope_permits(*scope, method, path))
+        {
+            let scopes: Vec<&str> = identity.scopes.iter().map(AdminScope::as_str).collect();
+            let message = format!(
+                "admin token scopes ({}) do not cover {} {}",
+                scopes.join(", "),
+                method,
+                path
+            );
+            return Denial::new(DenialSource::AdminAuth, message.clone())
+                .with_principal(&identity.subject)
+                .attach(AppError::new(StatusCode::FORBIDDEN, message).into_response());
+        }
+        request.extensions_mut().insert(identity);
+        return next.run(request).await;
+    }
+    let (user, provider) = match timed(Phase::Auth, auth.authenticate(authorization)).await {
+        Ok(verified) => verified,
+        Err(err) => return refuse_login(&auth, err),
+    };
+
+    let Some(role) = auth.roles.role_for(&user.groups) else {
+        let message = format!("`{}` is not a member of an admin group", user.subject);
+        return Denial::new(DenialSource::AdminAuth, message.clone())
+            .with_principal(&user.subject)
+            .attach(AppError::new(StatusCode::FORBIDDEN, message).into_response());
+    };
+    if role == AdminRole::ReadOnly && !matches!(*request.method(), Method::GET | Method::HEAD) {
+        return Denial::new(
+            DenialSource::AdminAuth,
+            "missing permission: admin role required, caller is read-only",
+        )
+        .with_principal(&user.subject)
+        .attach(AppError::new(StatusCode::FORBIDDEN, "read-only admin role").into_response());
+    }
+
+    request.extensions_mut().insert(AdminIdentity {
+        subject: user.subject,
+        role,
+        groups: user.groups,
+        provider,
+        scopes: Vec::new(),
+    });
+    next.run(request).await
+}
+
+/// Answer a failed login, challenging the caller when it was refused.
+fn refuse_login(auth: &AdminAuth, err: AppError) -> Response {
+    let unauthorized = err.status == StatusCode::UNAUTHORIZED;
+    let reason = err.message.clone();
+    let mut response = err.into_response();
+    if unauthorized {
+        response
+            .headers_mut()
+            .insert(header::WWW_AUTHENTICATE, auth.challenge());
+        response = Denial::new(DenialSource::AdminAuth, reason).attach(response);
+    }
+    response
+}
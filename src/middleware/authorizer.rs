@@ -0,0 +1,600 @@
+//! Delegated authorization.
+//!
+//! Organisations with a central policy engine can have every request checked
+//! by an external authorizer before it reaches a handler. The request context
+//! (principal, action, bucket, key, client address) is POSTed as JSON to the
+//! configured endpoint, either as a plain webhook or through OPA's Data API:
+//!
+//! ```text
+//! OBJECT_STORE_AUTHORIZER="http:https://authz.corp.internal/check"
+//! OBJECT_STORE_AUTHORIZER="opa:http://localhost:8181/v1/data/objectstore/allow"
+//! ```
+//!
+//! A webhook answers `{"allow": true|false}`; OPA answers `{"result": bool}`
+//! or `{"result": {"allow": bool}}`. Either may add a `"reason"` string (a
+//! policy id, a missing permission) that is recorded with the denial when
+//! `OBJECT_STORE_LOG_DENIED_REQUESTS` is on. Decisions are cached per context for
+//! `OBJECT_STORE_AUTHORIZER_CACHE_TTL_SECS`. Errors and timeouts fail closed
+//! with `503 Service Unavailable`.
+//!
+//! The principal is the access key that signed the request (SigV4, in the
+//! `Authorization` header or a presigned URL), verified against the secrets
+//! of `OBJECT_STORE_ACCESS_KEYS` (see `services::sigv4`). Unsigned requests,
+//! and any whose signature does not verify (an unknown access key, a wrong
+//! or expired signature, SigV2, or no access keys configured at all), are
+//! asked about as `anonymous`: a claimed access key alone never earns its
+//! policy.
+//!
+//! With `OBJECT_STORE_SESSION_TOKEN_KEY` set, requests may instead carry a
+//! session token (`x-amz-security-token`, see `services::session`). Its
+//! signature and expiry are checked here, its principal is used, and requests
+//! outside its bucket/prefix/actions are refused without a callout; the
+//! context sent to the engine then includes the token's `session` policy.
+//!
+//! Clients hunting a signing bug can send `x-debug-signature` with the
+//! configured token (`OBJECT_STORE_DEBUG_SIGNATURE_TOKEN`): a SigV4
+//! signature that does not match is then refused with `403` and the
+//! canonical request and string-to-sign the server computed, as AWS reports
+//! with `SignatureDoesNotMatch`, instead of falling back to `anonymous`.
+
+use crate::{
+    errors::AppError,
+    middleware::{
+        client_info::ClientInfo,
+        denial_log::{Denial, DenialSource},
+        feature_flags::ApiGroup,
+        server_timing::{Phase, timed},
+    },
+    services::{
+        aws_chunked::AccessKeys,
+        outbound::OutboundHttp,
+        session::{SessionKey, SessionPolicy},
+        sigv4,
+        storage_service::StorageService,
+    },
+};
+use axum::{
+    extract::{FromRef, FromRequestParts, Request, State},
+    http::{HeaderMap, StatusCode, Uri, request::Parts},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use percent_encoding::percent_decode_str;
+use reqwest::{Client, Url};
+use serde::{Deserialize, Serialize};
+use serde_json::{Value, json};
+use sha2::{Digest, Sha256};
+use std::{
+    collections::HashMap,
+    convert::Infallible,
+    fmt,
+    str::FromStr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+type CacheKey = (String, String, Option<String>, Option<String>);
+
+/// Upper bound on cached decisions; the cache is flushed when it fills up.
+const MAX_CACHED_DECISIONS: usize = 10_000;
+
+/// Principal used when a request carries no credentials.
+pub const ANONYMOUS_PRINCIPAL: &str = "anonymous";
+
+/// The principal a request acts as, once checked: the session principal or
+/// the access key whose signature verified, else `anonymous`. The authorizer
+/// stores the one it settled on in the request extensions; without an
+/// authorizer, extracting it checks the SigV4 signature against the
+/// service's access keys.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Principal(pub String);
+
+impl<S> FromRequestParts<S> for Principal
+where
+    S: Send + Sync,
+    StorageService: FromRef<S>,
+{
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, state: &S) -> Result<Self, Self::Rejection> {
+        if let Some(principal) = parts.extensions.get::<Principal>() {
+            return Ok(principal.clone());
+        }
+        let service = StorageService::from_ref(state);
+        let principal = match sigv4::verify_request(
+            &service.options.access_keys,
+            &parts.method,
+            &parts.uri,
+            &parts.headers,
+            chrono::Utc::now(),
+        ) {
+            Ok(Some(access_key)) => access_key,
+            _ => ANONYMOUS_PRINCIPAL.to_string(),
+        };
+        Ok(Principal(principal))
+    }
+}
+
+/// Request header carrying the debug signature token.
+pub const DEBUG_SIGNATURE_HEADER: &str = "x-debug-signature";
+
+/// Wire protocol spoken by the authorizer endpoint.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AuthorizerKind {
+    /// Plain webhook: body is the context, reply is `{"allow": bool}`.
+    Http,
+    /// OPA Data API: body is `{"input": context}`, reply is `{"result": ...}`.
+    Opa,
+}
+
+/// `kind:url` as given on the command line or in the environment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AuthorizerEndpoint {
+    pub kind: AuthorizerKind,
+    pub url: Url,
+}
+
+impl FromStr for AuthorizerEndpoint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (kind, url) = match s.split_once(':') {
+            Some((kind, rest)) if kind.eq_ignore_ascii_case("http") && !rest.starts_with("//") => {
+                (AuthorizerKind::Http, rest)
+            }
+            Some((kind, rest)) if kind.eq_ignore_ascii_case("opa") => (AuthorizerKind::Opa, rest),
+            // A bare URL is a plain webhook.
+            _ => (AuthorizerKind::Http, s),
+        };
+        let url = Url::parse(url).map_err(|err| format!("authorizer `{}`: {}", s, err))?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(format!("authorizer `{}` must be an http(s) URL", s));
+        }
+        Ok(Self { kind, url })
+    }
+}
+
+impl fmt::Display for AuthorizerEndpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let kind = match self.kind {
+            AuthorizerKind::Http => "http",
+            AuthorizerKind::Opa => "opa",
+        };
+        write!(f, "{}:{}", kind, self.url)
+    }
+}
+
+/// What is being asked of the policy engine.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize)]
+pub struct AuthzContext {
+    pub principal: String,
+    pub action: String,
+    pub bucket: Option<String>,
+    pub key: Option<String>,
+    /// Informational only; not part of the cache key.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub client_ip: Option<String>,
+    /// Policy of the session token the request carried. Informational
+    /// only; it has already been enforced.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub session: Option<SessionPolicy>,
+}
+
+impl AuthzContext {
+    /// Build the context for a classified request.
+    pub fn from_request(
+        group: ApiGroup,
+        uri: &Uri,
+        headers: &HeaderMap,
+        client: Option<&ClientInfo>,
+    ) -> Self {
+        let path = uri.path().trim_start_matches('/');
+        let (bucket, key) = match path.split_once('/') {
+            Some((bucket, key)) => (bucket, Some(key).filter(|k| !k.is_empty())),
+            None => (path, None),
+        };
+        let decode = |s: &str| percent_decode_str(s).decode_utf8_lossy().into_owned();
+        Self {
+            principal: principal(headers, uri.query()),
+            action: group.as_str().to_string(),
+            bucket: (!bucket.is_empty() && group != ApiGroup::Admin).then(|| decode(bucket)),
+            key: key.filter(|_| group != ApiGroup::Admin).map(decode),
+            client_ip: client.and_then(|c| c.ip).map(|ip| ip.to_string()),
+            session: None,
+        }
+    }
+
+    fn cache_key(&self) -> CacheKey {
+        (
+            self.principal.clone(),
+            self.action.clone(),
+            self.bucket.clone(),
+            self.key.clone(),
+        )
+    }
+}
+
+#[derive(Deserialize)]
+struct WebhookReply {
+    allow: bool,
+    #[serde(default)]
+    reason: Option<String>,
+}
+
+#[derive(Deserialize)]
+struct OpaReply {
+    result: Option<Value>,
+}
+
+/// The policy engine's answer for one context.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Decision {
+    pub allowed: bool,
+    /// Why, if the engine said (typically only for denials).
+    pub reason: Option<String>,
+}
+
+impl Decision {
+    fn deny() -> Self {
+        Self {
+            allowed: false,
+            reason: None,
+        }
+    }
+}
+
+/// Client for the external authorizer plus its decision cache.
+#[derive(Debug, Clone)]
+pub struct Authorizer {
+    endpoint: AuthorizerEndpoint,
+    client: Client,
+    ttl: Duration,
+    cache: Arc<Mutex<HashMap<CacheKey, (Decision, Instant)>>>,
+    session_key: Option<SessionKey>,
+    access_keys: AccessKeys,
+    /// SHA-256 of the debug signature token, when one is configured.
+    debug_signature: Option<[u8; 32]>,
+}
+
+impl Authorizer {
+    /// Build an authorizer whose callouts go through the shared outbound
+    /// client factory. A zero `ttl` disables caching.
+    pub fn new(
+        endpoint: AuthorizerEndpoint,
+        outbound: &OutboundHttp,
+        timeout: Duration,
+        ttl: Duration,
+    ) -> reqwest::Result<Self> {
+        let client = outbound.client_builder().timeout(timeout).build()?;
+        Ok(Self {
+            endpoint,
+            client,
+            ttl,
+            cache: Arc::new(Mutex::new(HashMap::new())),
+            session_key: None,
+            access_keys: AccessKeys::default(),
+            debug_signature: None,
+        })
+    }
+
+    /// Verify request signatures against `keys`; without them every request
+    /// is anonymous.
+    pub fn with_access_keys(mut self, keys: AccessKeys) -> Self {
+        self.access_keys = keys;
+        self
+    }
+
+    /// Report signature mismatches in detail to requests sending `token` in
+    /// `x-debug-signature`.
+    pub fn with_signature_debug(mut self, token: Option<&str>) -> Self {
+        self.debug_signature = token.map(|token| Sha256::digest(token).into());
+        self
+    }
+
+    /// The access key that signed `request`, `anonymous` when there is none
+    /// or its signature does not verify. A mismatch is returned instead when
+    /// the request carries the debug signature token.
+    fn verified_principal(&self, request: &Request) -> Result<String, sigv4::SignatureError> {
+        match sigv4::verify_request(
+            &self.access_keys,
+            request.method(),
+            request.uri(),
+            request.headers(),
+            chrono::Utc::now(),
+        ) {
+            Ok(Some(access_key)) => Ok(access_key),
+            Ok(None) => Ok(ANONYMOUS_PRINCIPAL.to_string()),
+            Err(err @ sigv4::SignatureError::Mismatch { .. }) if self.debugs_signature(request) => {
+                Err(err)
+            }
+            Err(err) => {
+                tracing::debug!("treating the request as anonymous: {}", err);
+                Ok(ANONYMOUS_PRINCIPAL.to_string())
+            }
+        }
+    }
+
+    fn debugs_signature(&self, request: &Request) -> bool {
+        self.debug_signature.is_some_and(|digest| {
+            request
+                .headers()
+                .get(DEBUG_SIGNATURE_HEADER)
+                .is_some_and(|token| <[u8; 32]>::from(Sha256::digest(token.as_bytes())) == digest)
+        })
+    }
+
+    /// Accept session tokens signed with `key`.
+    pub fn with_session_key(mut self, key: Option<SessionKey>) -> Self {
+        self.session_key = key;
+        self
+    }
+
+    /// Apply the session token `request` carries, if any: check it, confine
+    /// the request to its policy and act as its principal.
+    fn apply_session(
+        &self,
+        group: ApiGroup,
+        request: &Request,
+        ctx: &mut AuthzContext,
+    ) -> Result<(), String> {
+        let Some(token) = session_token(request.headers(), request.uri().query()) else {
+            return Ok(());
+        };
+        let key = self
+            .session_key
+            .as_ref()
+            .ok_or_else(|| "session tokens are not accepted by this server".to_string())?;
+        let policy = key.verify(&token, chrono::Utc::now())?;
+        let copy_source = request
+            .headers()
+            .get("x-amz-copy-source")
+            .and_then(|v| v.to_str().ok())
+            .map(|source| {
+                let source = percent_decode_str(source.trim_start_matches('/')).decode_utf8_lossy();
+                let source = source
+                    .split_once("?versionId=")
+                    .map_or(&*source, |(path, _)| path);
+                let (bucket, key) = source.split_once('/').unwrap_or((source, ""));
+                (bucket.to_string(), key.to_string())
+            });
+        policy.permits(
+            group,
+            ctx.bucket.as_deref(),
+            ctx.key.as_deref(),
+            request.uri().query(),
+            copy_source
+                .as_ref()
+                .map(|(bucket, key)| (bucket.as_str(), key.as_str())),
+        )?;
+        ctx.principal = policy.principal.clone();
+        ctx.session = Some(policy);
+        Ok(())
+    }
+
+    pub fn endpoint(&self) -> &AuthorizerEndpoint {
+        &self.endpoint
+    }
+
+    /// Ask the policy engine (or the cache) whether `ctx` is allowed.
+    pub async fn authorize(&self, ctx: &AuthzContext) -> Result<Decision, String> {
+        let key = ctx.cache_key();
+        if let Some(decision) = self.cached(&key) {
+            return Ok(decision);
+        }
+
+        let decision = self.call(ctx).await?;
+        if !self.ttl.is_zero() {
+            let mut cache = self.cache.lock().expect("authorizer cache poisoned");
+            if cache.len() >= MAX_CACHED_DECISIONS {
+                let now = Instant::now();
+                cache.retain(|_, (_, expires)| *expires > now);
+                if cache.len() >= MAX_CACHED_DECISIONS {
+                    cache.clear();
+                }
+            }
+            cache.insert(key, (decision.clone(), Instant::now() + self.ttl));
+        }
+        Ok(decision)
+    }
+
+    fn cached(&self, key: &CacheKey) -> Option<Decision> {
+        let cache = self.cache.lock().expect("authorizer cache poisoned");
+        cache
+            .get(key)
+            .filter(|(_, expires)| *expires > Instant::now())
+            .map(|(decision, _)| decision.clone())
+    }
+
+    async fn call(&self, ctx: &AuthzContext) -> Result<Decision, String> {
+        let request = self.client.post(self.endpoint.url.clone());
+        let request = match self.endpoint.kind {
+            AuthorizerKind::Http => request.json(ctx),
+            AuthorizerKind::Opa => request.json(&serde_json::json!({ "input": ctx })),
+        };
+        let response = request
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|err| format!("authorizer request failed: {}", err))?;
+
+        match self.endpoint.kind {
+            AuthorizerKind::Http => response
+                .json::<WebhookReply>()
+                .await
+                .map(|reply| Decision {
+                    allowed: reply.allow,
+                    reason: reply.reason,
+                })
+                .map_err(|err| format!("invalid authorizer reply: {}", err)),
+            AuthorizerKind::Opa => {
+                let reply = response
+                    .json::<OpaReply>()
+                    .await
+                    .map_err(|err| format!("invalid OPA reply: {}", err))?;
+                // An undefined decision (no `result`) is a deny.
+                match reply.result {
+                    None => Ok(Decision::deny()),
+                    Some(Value::Bool(allowed)) => Ok(Decision {
+                        allowed,
+                        reason: None,
+                    }),
+                    Some(Value::Object(map)) => Ok(Decision {
+                        allowed: map.get("allow").and_then(Value::as_bool).unwrap_or(false),
+                        reason: map
+                            .get("reason")
+                            .and_then(Value::as_str)
+                            .map(str::to_string),
+                    }),
+                    Some(other) => Err(format!("unexpected OPA result: {}", other)),
+                }
+            }
+        }
+    }
+}
+
+/// `403 SignatureDoesNotMatch` with what the server signed, for a request
+/// carrying the debug signature token.
+fn signature_mismatch(err: sigv4::SignatureError) -> Response {
+    let reason = err.to_string();
+    let sigv4::SignatureError::Mismatch {
+        canonical_request,
+        string_to_sign,
+        ..
+    } = err
+    else {
+        return AppError::new(StatusCode::FORBIDDEN, reason).into_response();
+    };
+    let body = json!({
+        "error": reason,
+        "status": StatusCode::FORBIDDEN.as_u16(),
+        "code": "SignatureDoesNotMatch",
+        "canonical_request": canonical_request,
+        "string_to_sign": string_to_sign,
+    });
+    Denial::new(DenialSource::Authorizer, reason)
+        .attach((StatusCode::FORBIDDEN, axum::Json(body)).into_response())
+}
+
+/// Check every gated request against the external authorizer.
+pub async fn enforce_authorization(
+    State(authorizer): State<Authorizer>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let Some(group) = ApiGroup::classify(request.method(), request.uri()) else {
+        return next.run(request).await;
+    };
+    let mut ctx = AuthzContext::from_request(
+        group,
+        request.uri(),
+        request.headers(),
+        request.extensions().get::<ClientInfo>(),
+    );
+    ctx.principal = match authorizer.verified_principal(&request) {
+        Ok(principal) => principal,
+        Err(err) => return signature_mismatch(err),
+    };
+    if let Err(reason) = authorizer.apply_session(group, &request, &mut ctx) {
+        tracing::debug!(
+            "session token refused for {} on {:?}/{:?}: {}",
+            ctx.action,
+            ctx.bucket,
+            ctx.key,
+            reason
+        );
+        return Denial::new(DenialSource::Authorizer, reason.clone())
+            .attach(AppError::new(StatusCode::FORBIDDEN, reason).into_response());
+    }
+
+    let decision = timed(Phase::Auth, authorizer.authorize(&ctx)).await;
+    match decision {
+        Ok(decision) if decision.allowed => {
+            request
+                .extensions_mut()
+                .insert(Principal(ctx.principal.clone()));
+            next.run(request).await
+        }
+        Ok(decision) => {
+            tracing::debug!(
+                "authorizer denied {} on {:?}/{:?} for {}",
+                ctx.action,
+                ctx.bucket,
+                ctx.key,
+                ctx.principal
+            );
+            let reason = decision
+                .reason
+                .unwrap_or_else(|| "denied by policy (no reason given)".into());
+            Denial::new(DenialSource::Authorizer, reason)
+                .with_principal(ctx.principal)
+                .attach(
+                    AppError::new(StatusCode::FORBIDDEN, "access denied by policy").into_response(),
+                )
+        }
+        Err(err) => {
+            tracing::warn!("{}", err);
+            AppError::new(StatusCode::SERVICE_UNAVAILABLE, "authorization unavailable")
+                .into_response()
+        }
+    }
+}
+
+/// The session token of a request, from `x-amz-security-token` or a
+/// presigned `X-Amz-Security-Token`.
+fn session_token(headers: &HeaderMap, query: Option<&str>) -> Option<String> {
+    headers
+        .get("x-amz-security-token")
+        .and_then(|v| v.to_str().ok())
+        .map(str::to_string)
+        .or_else(|| query_param(query, "X-Amz-Security-Token"))
+        .filter(|token| !token.is_empty())
+}
+
+/// Percent-decoded value of the first `name` query parameter.
+pub(crate) fn query_param(query: Option<&str>, name: &str) -> Option<String> {
+    query?.split('&').find_map(|pair| {
+        let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+        (key == name).then(|| {
+            percent_decode_str(&value.replace('+', " "))
+                .decode_utf8_lossy()
+                .into_owned()
+        })
+    })
+}
+
+/// The access key the caller claims to be, from a SigV4/SigV2 `Authorization`
+/// header or a presigned URL. Unverified: fine for labels, never for access
+/// decisions.
+pub(crate) fn principal(headers: &HeaderMap, query: Option<&str>) -> String {
+    let from_header = headers
+        .get("authorization")
+        .and_then(|v| v.to_str().ok())
+        .and_then(|auth| {
+            if let Some(rest) = auth.strip_prefix("AWS4-HMAC-SHA256") {
+                rest.split(',')
+                    .find_map(|part| part.trim().strip_prefix("Credential="))
+                    .and_then(|cred| cred.split('/').next())
+                    .map(str::to_string)
+            } else {
+                auth.strip_prefix("AWS ")
+                    .and_then(|rest| rest.split(':').next())
+                    .map(|key| key.trim().to_string())
+            }
+        });
+    let from_query = || {
+        query?.split('&').find_map(|pair| {
+            let (name, value) = pair.split_once('=')?;
+            let value = percent_decode_str(value).decode_utf8_lossy();
+            match name {
+                "X-Amz-Credential" => value.split('/').next().map(str::to_string),
+                "AWSAccessKeyId" => Some(value.into_owned()),
+                _ => None,
+            }
+        })
+    };
+    from_header
+        .or_else(from_query)
+        .filter(|p| !p.is_empty())
+        .unwrap_or_else(|| ANONYMOUS_PRINCIPAL.to_string())
+}
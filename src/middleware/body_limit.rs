@@ -0,0 +1,55 @@
+//! Request body size limit.
+//!
+//! With `OBJECT_STORE_MAX_REQUEST_BODY` set, a request declaring a larger
+//! `Content-Length` is answered `413` before its body is read, and any other
+//! body is cut off once it outgrows the limit. Uploads see the cut as
+//! `EntityTooLarge` and remove their temp file (see `services::size_limit`);
+//! handlers buffering small documents refuse the truncated body.
+
+use crate::{
+    errors::AppError,
+    services::{size_limit, storage_service::StorageError},
+};
+use axum::{
+    body::{Body, HttpBody},
+    extract::{Request, State},
+    http::header::CONTENT_LENGTH,
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use futures::TryStreamExt;
+use std::io;
+
+/// Largest request body accepted, in bytes.
+#[derive(Debug, Clone, Copy)]
+pub struct BodyLimit {
+    pub max_bytes: u64,
+}
+
+/// Refuse or cut off request bodies over the limit.
+pub async fn limit_request_body(
+    State(limit): State<BodyLimit>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let reason = size_limit::body_too_large(limit.max_bytes);
+    let declared = request
+        .headers()
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse::<u64>().ok());
+    let hint = request.body().size_hint();
+    if declared.is_some_and(|len| len > limit.max_bytes) || hint.lower() > limit.max_bytes {
+        return AppError::from(StorageError::EntityTooLarge(reason)).into_response();
+    }
+    // Bodies of a known length (hyper holds them to `Content-Length`) are
+    // within the limit; only the others need counting.
+    if hint.exact().is_some() {
+        return next.run(request).await;
+    }
+    let request = request.map(|body| {
+        let stream = body.into_data_stream().map_err(io::Error::other);
+        Body::from_stream(size_limit::limit_stream(stream, limit.max_bytes, reason))
+    });
+    next.run(request).await
+}
@@ -0,0 +1,345 @@
+//! Client identity behind reverse proxies.
+//!
+//! When the store runs behind a load balancer the TCP peer is the proxy, not
+//! the client. For peers listed in `OBJECT_STORE_TRUSTED_PROXIES`, this
+//! middleware reads the one header `OBJECT_STORE_FORWARDED_HEADER` names:
+//! `X-Forwarded-For` / `X-Forwarded-Proto` (the default) or `Forwarded`
+//! (RFC 7239). The other is ignored: a proxy that only appends to one of
+//! them passes the other through as the client sent it, so falling back
+//! between them would let clients pick their own address.
+//!
+//! The hop list is walked from the right, stopping at the first address
+//! that is not itself a trusted proxy. A hop that is not an address
+//! (`unknown`, an obfuscated `_node`) also stops the walk: the request is
+//! then attributed to the trusted proxy that reported it, never to anything
+//! further left. The result is stored as a [`ClientInfo`] request extension
+//! for logging, rate limiting and signature checks.
+
+use axum::{
+    extract::{ConnectInfo, FromRequestParts, Request, State},
+    http::{HeaderMap, request::Parts},
+    middleware::Next,
+    response::Response,
+};
+use std::{
+    convert::Infallible,
+    fmt,
+    net::{IpAddr, SocketAddr},
+    str::FromStr,
+    sync::Arc,
+};
+
+/// The resolved identity of the party that originated a request.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ClientInfo {
+    /// Best-known client address (`None` when no peer address is available,
+    /// e.g. in-process tests).
+    pub ip: Option<IpAddr>,
+    /// `http` or `https` as seen by the client.
+    pub scheme: String,
+}
+
+impl Default for ClientInfo {
+    fn default() -> Self {
+        Self {
+            ip: None,
+            scheme: "http".into(),
+        }
+    }
+}
+
+impl<S: Send + Sync> FromRequestParts<S> for ClientInfo {
+    type Rejection = Infallible;
+
+    async fn from_request_parts(parts: &mut Parts, _state: &S) -> Result<Self, Self::Rejection> {
+        Ok(parts
+            .extensions
+            .get::<ClientInfo>()
+            .cloned()
+            .unwrap_or_default())
+    }
+}
+
+/// An IP network in CIDR notation (`10.0.0.0/8`, `::1/128`, or a bare address).
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct IpNetwork {
+    addr: IpAddr,
+    prefix: u8,
+}
+
+impl IpNetwork {
+    pub fn contains(&self, ip: IpAddr) -> bool {
+        match (self.addr, ip) {
+            (IpAddr::V4(net), IpAddr::V4(ip)) => {
+                let mask = u32::MAX.checked_shl(32 - self.prefix as u32).unwrap_or(0);
+                u32::from(net) & mask == u32::from(ip) & mask
+            }
+            (IpAddr::V6(net), IpAddr::V6(ip)) => {
+                let mask = u128::MAX.checked_shl(128 - self.prefix as u32).unwrap_or(0);
+                u128::from(net) & mask == u128::from(ip) & mask
+            }
+            (IpAddr::V6(_), IpAddr::V4(ip)) => self.contains(IpAddr::V6(ip.to_ipv6_mapped())),
+            (IpAddr::V4(_), IpAddr::V6(ip)) => ip
+                .to_ipv4_mapped()
+                .is_some_and(|v4| self.contains(IpAddr::V4(v4))),
+        }
+    }
+}
+
+impl FromStr for IpNetwork {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        let (addr, prefix) = match s.split_once('/') {
+            Some((addr, prefix)) => (addr, Some(prefix)),
+            None => (s, None),
+        };
+        let addr: IpAddr = addr
+            .parse()
+            .map_err(|_| format!("invalid proxy address `{}`", s))?;
+        let max = if addr.is_ipv4() { 32 } else { 128 };
+        let prefix = match prefix {
+            Some(p) => p
+                .parse::<u8>()
+                .ok()
+                .filter(|p| *p <= max)
+                .ok_or_else(|| format!("invalid prefix length in `{}`", s))?,
+            None => max,
+        };
+        Ok(Self { addr, prefix })
+    }
+}
+
+impl fmt::Display for IpNetwork {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}/{}", self.addr, self.prefix)
+    }
+}
+
+/// The forwarding header trusted proxies identify the client with.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum ForwardedHeader {
+    /// `X-Forwarded-For` and `X-Forwarded-Proto`.
+    #[default]
+    XForwardedFor,
+    /// RFC 7239 `Forwarded` (`for=` and `proto=`).
+    Forwarded,
+}
+
+impl FromStr for ForwardedHeader {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.trim().to_ascii_lowercase().as_str() {
+            "x-forwarded-for" => Ok(Self::XForwardedFor),
+            "forwarded" => Ok(Self::Forwarded),
+            other => Err(format!(
+                "unknown forwarding header `{}` (expected `x-forwarded-for` or `forwarded`)",
+                other
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ForwardedHeader {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            Self::XForwardedFor => "x-forwarded-for",
+            Self::Forwarded => "forwarded",
+        })
+    }
+}
+
+/// Proxies whose forwarding headers are believed.
+#[derive(Debug, Clone, Default)]
+pub struct TrustedProxies {
+    networks: Arc<Vec<IpNetwork>>,
+    header: ForwardedHeader,
+    /// Whether the listener itself serves HTTPS.
+    tls: bool,
+}
+
+impl TrustedProxies {
+    pub fn new(networks: Vec<IpNetwork>) -> Self {
+        Self {
+            networks: Arc::new(networks),
+            header: ForwardedHeader::default(),
+            tls: false,
+        }
+    }
+
+    /// Read the client from `header` only.
+    pub fn with_header(mut self, header: ForwardedHeader) -> Self {
+        self.header = header;
+        self
+    }
+
+    /// Report `https` for connections a proxy says nothing about.
+    pub fn with_tls(mut self, tls: bool) -> Self {
+        self.tls = tls;
+        self
+    }
+
+    fn direct_scheme(&self) -> String {
+        if self.tls { "https" } else { "http" }.into()
+    }
+
+    pub fn is_trusted(&self, ip: IpAddr) -> bool {
+        self.networks.iter().any(|net| net.contains(ip))
+    }
+
+    /// Resolve the client identity from the TCP peer and request headers.
+    pub fn resolve(&self, peer: Option<IpAddr>, headers: &HeaderMap) -> ClientInfo {
+        let Some(peer) = peer.filter(|ip| self.is_trusted(*ip)) else {
+            return ClientInfo {
+                ip: peer,
+                scheme: self.direct_scheme(),
+            };
+        };
+
+        let hops = match self.header {
+            ForwardedHeader::XForwardedFor => x_forwarded(headers),
+            ForwardedHeader::Forwarded => forwarded_header(headers),
+        };
+
+        // Walk right-to-left: the rightmost entries were appended by our own
+        // proxies; the first untrusted hop is the real client. Past a hop
+        // that is no address nothing can be attributed, so the last proxy
+        // stands in for the client. The scheme is never read left of the hop
+        // taken as the client, where the client could have written it: with
+        // `Forwarded` it is that hop's own `proto=`; an `X-Forwarded-Proto`
+        // is often set once by the outermost proxy, so there it is the one
+        // paired with the client or, failing that, the nearest one paired
+        // with a trusted hop to its right.
+        let mut client = peer;
+        let mut proto = None;
+        for hop in hops.iter().rev() {
+            let Some(ip) = hop.ip else {
+                break;
+            };
+            client = ip;
+            proto = match self.header {
+                ForwardedHeader::Forwarded => hop.proto.as_deref(),
+                ForwardedHeader::XForwardedFor => hop.proto.as_deref().or(proto),
+            };
+            if !self.is_trusted(ip) {
+                break;
+            }
+        }
+
+        ClientInfo {
+            ip: Some(client),
+            scheme: proto
+                .filter(|p| p.eq_ignore_ascii_case("http") || p.eq_ignore_ascii_case("https"))
+                .map(|p| p.to_ascii_lowercase())
+                .unwrap_or_else(|| self.direct_scheme()),
+        }
+    }
+}
+
+/// Resolve and attach [`ClientInfo`] to every request.
+pub async fn resolve_client_info(
+    State(proxies): State<TrustedProxies>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let peer = request
+        .extensions()
+        .get::<ConnectInfo<SocketAddr>>()
+        .map(|ConnectInfo(addr)| addr.ip());
+    let info = proxies.resolve(peer, request.headers());
+    tracing::debug!(
+        "{} {} from {:?} ({})",
+        request.method(),
+        request.uri().path(),
+        info.ip,
+        info.scheme
+    );
+    request.extensions_mut().insert(info);
+    next.run(request).await
+}
+
+/// One forwarding hop: the address it reports (`None` when it is not an
+/// address) and the scheme it was reached with, if reported.
+#[derive(Debug)]
+struct Hop {
+    ip: Option<IpAddr>,
+    proto: Option<String>,
+}
+
+/// Parse RFC 7239 `Forwarded` headers into their `for=` hops, each with the
+/// `proto=` of its own element.
+fn forwarded_header(headers: &HeaderMap) -> Vec<Hop> {
+    let values: Vec<&str> = headers
+        .get_all("forwarded")
+        .iter()
+        .filter_map(|v| v.to_str().ok())
+        .collect();
+
+    let mut hops = Vec::new();
+    for element in values.iter().flat_map(|v| v.split(',')) {
+        let mut node = None;
+        let mut proto = None;
+        for pair in element.split(';') {
+            let Some((name, value)) = pair.split_once('=') else {
+                continue;
+            };
+            let value = value.trim().trim_matches('"');
+            match name.trim().to_ascii_lowercase().as_str() {
+                "for" => node = Some(parse_node(value)),
+                "proto" => proto = Some(value.to_string()),
+                _ => {}
+            }
+        }
+        if let Some(ip) = node {
+            hops.push(Hop { ip, proto });
+        }
+    }
+    hops
+}
+
+/// Parse every `X-Forwarded-For` and `X-Forwarded-Proto` header instance
+/// into hops. Each proxy appends to both lists, so they are paired from the
+/// right; hops left of the shorter `X-Forwarded-Proto` list get no scheme.
+fn x_forwarded(headers: &HeaderMap) -> Vec<Hop> {
+    let list = |name: &str| -> Vec<String> {
+        headers
+            .get_all(name)
+            .iter()
+            .filter_map(|v| v.to_str().ok())
+            .flat_map(|v| v.split(','))
+            .map(|v| v.trim().to_string())
+            .collect()
+    };
+    let nodes = list("x-forwarded-for");
+    let protos = list("x-forwarded-proto");
+    // Hops without a scheme on the left, schemes without a hop on the left.
+    let unmatched = nodes.len().saturating_sub(protos.len());
+    let surplus = protos.len().saturating_sub(nodes.len());
+    nodes
+        .iter()
+        .enumerate()
+        .map(|(index, node)| Hop {
+            ip: parse_node(node),
+            proto: index
+                .checked_sub(unmatched)
+                .map(|at| protos[surplus + at].clone()),
+        })
+        .collect()
+}
+
+/// Parse a node identifier: `1.2.3.4`, `1.2.3.4:80`, `[::1]`, `[::1]:80`.
+fn parse_node(value: &str) -> Option<IpAddr> {
+    let value = value.trim();
+    if let Ok(ip) = value.parse::<IpAddr>() {
+        return Some(ip);
+    }
+    if let Ok(addr) = value.parse::<SocketAddr>() {
+        return Some(addr.ip());
+    }
+    value
+        .strip_prefix('[')
+        .and_then(|rest| rest.split(']').next())
+        .and_then(|inner| inner.parse().ok())
+}
@@ -0,0 +1,203 @@
+//! Audit trail of refused requests.
+//!
+//! When a policy is misconfigured, a bare `403` tells an operator very little.
+//! With `OBJECT_STORE_LOG_DENIED_REQUESTS` enabled, every request refused by
+//! a disabled API group, the external authorizer or the admin role mapping is
+//! recorded with who asked (principal and client address), what for (action,
+//! bucket, key) and why (the layer that refused it and its reason, e.g. the
+//! policy id returned by the authorizer or the missing admin role).
+//!
+//! Each denial is written to the `audit` tracing target, counted per
+//! source and action, and kept in a bounded in-memory log served at
+//! `GET /admin/denials`.
+//!
+//! Enforcement layers mark a refusal by attaching a [`Denial`] to their
+//! response; this layer, installed outside them, does the recording.
+
+use crate::middleware::{
+    authorizer::AuthzContext, client_info::ClientInfo, feature_flags::ApiGroup,
+};
+use axum::{
+    extract::{Request, State},
+    http::Method,
+    middleware::Next,
+    response::Response,
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::{Arc, Mutex},
+};
+
+/// Denials kept for `GET /admin/denials`; older entries are dropped.
+pub const DENIAL_LOG_CAPACITY: usize = 500;
+
+/// Which layer refused the request.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum DenialSource {
+    /// The API group is disabled (`OBJECT_STORE_DISABLED_APIS`).
+    FeatureFlag,
+    /// The external authorizer answered deny.
+    Authorizer,
+    /// Admin API authentication or role mapping.
+    AdminAuth,
+}
+
+impl DenialSource {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            DenialSource::FeatureFlag => "feature-flag",
+            DenialSource::Authorizer => "authorizer",
+            DenialSource::AdminAuth => "admin-auth",
+        }
+    }
+}
+
+/// Attached to a refusal response by the layer that produced it.
+#[derive(Debug, Clone)]
+pub struct Denial {
+    pub source: DenialSource,
+    pub reason: String,
+    /// Caller identity established by the refusing layer, if better than
+    /// the access key claimed in the request.
+    pub principal: Option<String>,
+}
+
+impl Denial {
+    pub fn new(source: DenialSource, reason: impl Into<String>) -> Self {
+        Self {
+            source,
+            reason: reason.into(),
+            principal: None,
+        }
+    }
+
+    pub fn with_principal(mut self, principal: impl Into<String>) -> Self {
+        self.principal = Some(principal.into());
+        self
+    }
+
+    /// Attach this denial to `response`.
+    pub fn attach(self, mut response: Response) -> Response {
+        response.extensions_mut().insert(self);
+        response
+    }
+}
+
+/// One recorded denial.
+#[derive(Debug, Clone, Serialize)]
+pub struct DeniedRequest {
+    pub time: DateTime<Utc>,
+    pub method: String,
+    pub path: String,
+    pub status: u16,
+    pub source: DenialSource,
+    pub reason: String,
+    #[serde(flatten)]
+    pub context: AuthzContext,
+}
+
+/// Snapshot returned by `GET /admin/denials`.
+#[derive(Debug, Clone, Serialize)]
+pub struct DenialReport {
+    /// Denials since startup, per source and action.
+    pub counts: BTreeMap<String, BTreeMap<String, u64>>,
+    pub total: u64,
+    /// Most recent first, at most `DENIAL_LOG_CAPACITY`.
+    pub recent: Vec<DeniedRequest>,
+}
+
+#[derive(Debug, Default)]
+struct LogState {
+    recent: VecDeque<DeniedRequest>,
+    counts: BTreeMap<(DenialSource, String), u64>,
+    total: u64,
+}
+
+/// Shared denial log and counters.
+#[derive(Debug, Clone, Default)]
+pub struct DenialLog {
+    state: Arc<Mutex<LogState>>,
+}
+
+impl DenialLog {
+    fn record(&self, entry: DeniedRequest) {
+        tracing::info!(
+            target: "audit",
+            source = entry.source.as_str(),
+            principal = %entry.context.principal,
+            action = %entry.context.action,
+            bucket = entry.context.bucket.as_deref(),
+            key = entry.context.key.as_deref(),
+            client_ip = entry.context.client_ip.as_deref(),
+            status = entry.status,
+            "denied {} {}: {}",
+            entry.method,
+            entry.path,
+            entry.reason
+        );
+        let mut state = self.state.lock().expect("denial log poisoned");
+        state.total += 1;
+        *state
+            .counts
+            .entry((entry.source, entry.context.action.clone()))
+            .or_default() += 1;
+        if state.recent.len() == DENIAL_LOG_CAPACITY {
+            state.recent.pop_back();
+        }
+        state.recent.push_front(entry);
+    }
+
+    pub fn report(&self) -> DenialReport {
+        let state = self.state.lock().expect("denial log poisoned");
+        let mut counts: BTreeMap<String, BTreeMap<String, u64>> = BTreeMap::new();
+        for ((source, action), count) in &state.counts {
+            counts
+                .entry(source.as_str().to_string())
+                .or_default()
+                .insert(action.clone(), *count);
+        }
+        DenialReport {
+            counts,
+            total: state.total,
+            recent: state.recent.iter().cloned().collect(),
+        }
+    }
+}
+
+/// Record responses that an inner layer marked with a [`Denial`], and make
+/// the log available to `GET /admin/denials`.
+pub async fn record_denials(
+    State(log): State<DenialLog>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    request.extensions_mut().insert(log.clone());
+    let method: Method = request.method().clone();
+    let uri = request.uri().clone();
+    let headers = request.headers().clone();
+    let client = request.extensions().get::<ClientInfo>().cloned();
+
+    let response = next.run(request).await;
+    let Some(denial) = response.extensions().get::<Denial>().cloned() else {
+        return response;
+    };
+
+    let group = ApiGroup::classify(&method, &uri).unwrap_or(ApiGroup::Admin);
+    let mut context = AuthzContext::from_request(group, &uri, &headers, client.as_ref());
+    if let Some(principal) = denial.principal {
+        context.principal = principal;
+    }
+    log.record(DeniedRequest {
+        time: Utc::now(),
+        method: method.to_string(),
+        path: uri.path().to_string(),
+        status: response.status().as_u16(),
+        source: denial.source,
+        reason: denial.reason,
+        context,
+    });
+    response
+}
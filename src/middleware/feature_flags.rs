@@ -0,0 +1,172 @@
+//! Per-endpoint feature flags.
+//!
+//! Hardened deployments often need only a subset of the API (e.g. a public
+//! read-only mirror). Each request is classified into an [`ApiGroup`]; groups
+//! listed in `OBJECT_STORE_DISABLED_APIS` are rejected with `403 Forbidden`
+//! before reaching a handler. Health endpoints are never gated.
+
+use crate::{
+    errors::AppError,
+    middleware::denial_log::{Denial, DenialSource},
+};
+use axum::{
+    extract::{Request, State},
+    http::{Method, StatusCode, Uri},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::{collections::HashSet, fmt, str::FromStr, sync::Arc};
+
+/// Coarse API surface groups that can be switched off.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ApiGroup {
+    /// `PUT /{bucket}` (including `?versioning`)
+    BucketCreate,
+    /// `DELETE /{bucket}`
+    BucketDelete,
+    /// `GET|HEAD /{bucket}` (listings and bucket lookups)
+    BucketList,
+    /// `GET|HEAD /{bucket}/{*key}`
+    ObjectRead,
+    /// `PUT|POST /{bucket}/{*key}`
+    ObjectWrite,
+    /// `DELETE /{bucket}/{*key}`, prefix deletes (`DELETE /{bucket}?prefix=`)
+    /// and batch deletes (`POST /{bucket}?delete`)
+    ObjectDelete,
+    /// Everything under `/admin`
+    Admin,
+}
+
+impl ApiGroup {
+    pub const ALL: [ApiGroup; 7] = [
+        ApiGroup::BucketCreate,
+        ApiGroup::BucketDelete,
+        ApiGroup::BucketList,
+        ApiGroup::ObjectRead,
+        ApiGroup::ObjectWrite,
+        ApiGroup::ObjectDelete,
+        ApiGroup::Admin,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            ApiGroup::BucketCreate => "bucket-create",
+            ApiGroup::BucketDelete => "bucket-delete",
+            ApiGroup::BucketList => "bucket-list",
+            ApiGroup::ObjectRead => "object-read",
+            ApiGroup::ObjectWrite => "object-write",
+            ApiGroup::ObjectDelete => "object-delete",
+            ApiGroup::Admin => "admin",
+        }
+    }
+
+    /// Classify a request by method and URI. Returns `None` for endpoints
+    /// that are never gated (health probes, public limits).
+    pub fn classify(method: &Method, uri: &Uri) -> Option<ApiGroup> {
+        let trimmed = uri.path().trim_start_matches('/');
+        if trimmed.is_empty() || matches!(trimmed, "healthz" | "readyz" | "limits") {
+            return None;
+        }
+        if trimmed == "admin" || trimmed.starts_with("admin/") {
+            return Some(ApiGroup::Admin);
+        }
+
+        let has_key = trimmed
+            .split_once('/')
+            .is_some_and(|(_, key)| !key.is_empty());
+        let has_param = |name: &str| {
+            uri.query().is_some_and(|query| {
+                query
+                    .split('&')
+                    .any(|pair| pair.split('=').next() == Some(name))
+            })
+        };
+        let group = match (has_key, method) {
+            (false, &Method::PUT) => ApiGroup::BucketCreate,
+            // `DELETE /{bucket}?prefix=` removes objects, not the bucket.
+            (false, &Method::DELETE) if has_param("prefix") => ApiGroup::ObjectDelete,
+            (false, &Method::DELETE) => ApiGroup::BucketDelete,
+            // A lifecycle dry run only reads the bucket.
+            (false, &Method::POST) if has_param("lifecycle-dry-run") => ApiGroup::BucketList,
+            // Any other POST on a bucket is `?delete` (DeleteObjects).
+            (false, &Method::POST) => ApiGroup::ObjectDelete,
+            (false, _) => ApiGroup::BucketList,
+            (true, &Method::GET) | (true, &Method::HEAD) => ApiGroup::ObjectRead,
+            (true, &Method::DELETE) => ApiGroup::ObjectDelete,
+            (true, _) => ApiGroup::ObjectWrite,
+        };
+        Some(group)
+    }
+}
+
+impl fmt::Display for ApiGroup {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for ApiGroup {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        ApiGroup::ALL
+            .into_iter()
+            .find(|group| group.as_str().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| {
+                let known: Vec<_> = ApiGroup::ALL.iter().map(|g| g.as_str()).collect();
+                format!(
+                    "unknown API group `{}` (expected one of {})",
+                    s,
+                    known.join(", ")
+                )
+            })
+    }
+}
+
+/// The set of disabled groups, shared with the middleware.
+#[derive(Debug, Clone, Default)]
+pub struct FeatureFlags {
+    disabled: Arc<HashSet<ApiGroup>>,
+}
+
+impl FeatureFlags {
+    pub fn new(disabled: impl IntoIterator<Item = ApiGroup>) -> Self {
+        Self {
+            disabled: Arc::new(disabled.into_iter().collect()),
+        }
+    }
+
+    pub fn is_enabled(&self, group: ApiGroup) -> bool {
+        !self.disabled.contains(&group)
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.disabled.is_empty()
+    }
+
+    /// Disabled groups, in `ApiGroup::ALL` order.
+    pub fn disabled(&self) -> Vec<ApiGroup> {
+        ApiGroup::ALL
+            .into_iter()
+            .filter(|group| self.disabled.contains(group))
+            .collect()
+    }
+}
+
+/// Reject requests that fall into a disabled API group. The flags are also
+/// attached to the request so the limits endpoints can report them.
+pub async fn enforce_feature_flags(
+    State(flags): State<FeatureFlags>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    if let Some(group) = ApiGroup::classify(request.method(), request.uri())
+        && !flags.is_enabled(group)
+    {
+        let message = format!("the `{}` API is disabled on this server", group);
+        return Denial::new(DenialSource::FeatureFlag, message.clone())
+            .attach(AppError::new(StatusCode::FORBIDDEN, message).into_response());
+    }
+    request.extensions_mut().insert(flags);
+    next.run(request).await
+}
@@ -0,0 +1,16 @@
+//! Tower/axum middleware applied around the S3 router.
+
+pub mod access_log;
+pub mod admin_auth;
+pub mod authorizer;
+pub mod body_limit;
+pub mod client_info;
+pub mod config_etag;
+pub mod denial_log;
+pub mod feature_flags;
+pub mod proxy_cache;
+pub mod response_stats;
+pub mod server_timing;
+pub mod shadow;
+pub mod slo_stats;
+pub mod throttle;
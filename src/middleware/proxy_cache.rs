@@ -0,0 +1,162 @@
+//! Headers for running behind a caching reverse proxy.
+//!
+//! Varnish, nginx and CDNs decide what to store, for how long and when to
+//! revalidate from a handful of response headers. Objects already carry an
+//! ETag, Last-Modified and the bucket's Cache-Control, but listings and other
+//! generated documents carry no validator, errors are heuristically cacheable
+//! (a cached `404` outlives the upload that follows it), and Last-Modified is
+//! not in the HTTP-date format strict parsers expect. With
+//! `OBJECT_STORE_PROXY_CACHE_HEADERS` on, this layer fixes up every GET/HEAD
+//! response on its way out:
+//! - `Date` is set (IMF-fixdate) and any `Age` removed: the origin's
+//!   responses are never aged, so caches compute age from `Date` alone;
+//! - `Vary` names the request headers a response can depend on besides the
+//!   URL (the caller's credentials and `x-amz-object-attributes`);
+//! - `Last-Modified` is rewritten as an IMF-fixdate;
+//! - errors get `Cache-Control: no-store`;
+//! - a `200` GET without an ETag and with a small in-memory body (listings,
+//!   tagging, configuration documents) gets a strong ETag over the body and
+//!   `Cache-Control: no-cache` unless it has one, and a matching
+//!   `If-None-Match` is answered `304 Not Modified`.
+//!
+//! Other methods pass through untouched.
+
+use axum::{
+    body::{Body, HttpBody},
+    extract::Request,
+    http::{HeaderMap, HeaderValue, Method, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use chrono::{DateTime, Utc};
+
+/// Request headers besides the URL that GET/HEAD responses depend on.
+const VARY_ON: [&str; 3] = [
+    "authorization",
+    "x-amz-security-token",
+    "x-amz-object-attributes",
+];
+
+/// Largest generated body given an ETag; bigger ones are left alone.
+const MAX_TAGGED_BODY: u64 = 1024 * 1024;
+
+/// Make GET/HEAD responses safe and revalidatable for shared caches.
+pub async fn cache_friendly_headers(request: Request, next: Next) -> Response {
+    let method = request.method().clone();
+    if method != Method::GET && method != Method::HEAD {
+        return next.run(request).await;
+    }
+    let if_none_match = request.headers().get(header::IF_NONE_MATCH).cloned();
+    let mut response = next.run(request).await;
+
+    if method == Method::GET
+        && response.status() == StatusCode::OK
+        && !response.headers().contains_key(header::ETAG)
+        && response
+            .body()
+            .size_hint()
+            .exact()
+            .is_some_and(|len| len <= MAX_TAGGED_BODY)
+    {
+        response = tag_generated_body(response, if_none_match.as_ref()).await;
+    }
+
+    let failed = response.status().is_client_error() || response.status().is_server_error();
+    let headers = response.headers_mut();
+    if failed {
+        headers.insert(header::CACHE_CONTROL, HeaderValue::from_static("no-store"));
+    }
+    headers.remove(header::AGE);
+    if !headers.contains_key(header::DATE)
+        && let Ok(value) = HeaderValue::from_str(&http_date(Utc::now()))
+    {
+        headers.insert(header::DATE, value);
+    }
+    normalize_last_modified(headers);
+    add_vary(headers);
+    response
+}
+
+/// Give a buffered body a strong ETag, answering `304` when the client
+/// already has it.
+async fn tag_generated_body(response: Response, if_none_match: Option<&HeaderValue>) -> Response {
+    let (mut parts, body) = response.into_parts();
+    let bytes = match axum::body::to_bytes(body, MAX_TAGGED_BODY as usize).await {
+        Ok(bytes) => bytes,
+        Err(_) => return StatusCode::INTERNAL_SERVER_ERROR.into_response(),
+    };
+    let etag = format!("\"{:x}\"", md5::compute(&bytes));
+    let Ok(etag_value) = HeaderValue::from_str(&etag) else {
+        return Response::from_parts(parts, Body::from(bytes));
+    };
+    parts
+        .headers
+        .entry(header::CACHE_CONTROL)
+        .or_insert(HeaderValue::from_static("no-cache"));
+    if if_none_match
+        .and_then(|value| value.to_str().ok())
+        .is_some_and(|value| etag_list_contains(value, &etag))
+    {
+        let mut not_modified = StatusCode::NOT_MODIFIED.into_response();
+        let headers = not_modified.headers_mut();
+        headers.insert(header::ETAG, etag_value);
+        for name in [header::CACHE_CONTROL, header::LAST_MODIFIED] {
+            if let Some(value) = parts.headers.get(&name) {
+                headers.insert(name, value.clone());
+            }
+        }
+        return not_modified;
+    }
+    parts.headers.insert(header::ETAG, etag_value);
+    Response::from_parts(parts, Body::from(bytes))
+}
+
+/// Weak comparison of an `If-None-Match` list against `etag`.
+fn etag_list_contains(list: &str, etag: &str) -> bool {
+    list.split(',')
+        .map(str::trim)
+        .any(|candidate| candidate == "*" || candidate.trim_start_matches("W/") == etag)
+}
+
+fn normalize_last_modified(headers: &mut HeaderMap) {
+    let parsed = headers
+        .get(header::LAST_MODIFIED)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| DateTime::parse_from_rfc2822(value).ok());
+    if let Some(date) = parsed
+        && let Ok(value) = HeaderValue::from_str(&http_date(date.with_timezone(&Utc)))
+    {
+        headers.insert(header::LAST_MODIFIED, value);
+    }
+}
+
+/// Merge `VARY_ON` into the response's `Vary`, keeping what handlers set.
+fn add_vary(headers: &mut HeaderMap) {
+    let mut names: Vec<String> = headers
+        .get_all(header::VARY)
+        .iter()
+        .filter_map(|value| value.to_str().ok())
+        .flat_map(|value| value.split(','))
+        .map(|name| name.trim().to_string())
+        .filter(|name| !name.is_empty())
+        .collect();
+    if names.iter().any(|name| name == "*") {
+        return;
+    }
+    for name in VARY_ON {
+        if !names
+            .iter()
+            .any(|existing| existing.eq_ignore_ascii_case(name))
+        {
+            names.push(name.to_string());
+        }
+    }
+    if let Ok(value) = HeaderValue::from_str(&names.join(", ")) {
+        headers.insert(header::VARY, value);
+    }
+}
+
+/// An IMF-fixdate (RFC 9110 §5.6.7), e.g. `Sun, 06 Nov 1994 08:49:37 GMT`.
+fn http_date(date: DateTime<Utc>) -> String {
+    date.format("%a, %d %b %Y %H:%M:%S GMT").to_string()
+}
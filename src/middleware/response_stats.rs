@@ -0,0 +1,46 @@
+//! Response counters for error-rate alerting.
+//!
+//! Counts every response and those with a `5xx` status. The alert monitor
+//! (`services::alerts`) samples the counters each tick and compares the
+//! error ratio since its previous sample against `OBJECT_STORE_ALERT_ERROR_RATE`.
+
+use axum::{
+    extract::{Request, State},
+    middleware::Next,
+    response::Response,
+};
+use std::sync::{
+    Arc,
+    atomic::{AtomicU64, Ordering},
+};
+
+/// Shared response and server-error counters.
+#[derive(Debug, Clone, Default)]
+pub struct ResponseStats {
+    total: Arc<AtomicU64>,
+    server_errors: Arc<AtomicU64>,
+}
+
+impl ResponseStats {
+    /// `(responses, server errors)` since startup.
+    pub fn snapshot(&self) -> (u64, u64) {
+        (
+            self.total.load(Ordering::Relaxed),
+            self.server_errors.load(Ordering::Relaxed),
+        )
+    }
+}
+
+/// Count the response to every request.
+pub async fn count_responses(
+    State(stats): State<ResponseStats>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let response = next.run(request).await;
+    stats.total.fetch_add(1, Ordering::Relaxed);
+    if response.status().is_server_error() {
+        stats.server_errors.fetch_add(1, Ordering::Relaxed);
+    }
+    response
+}
@@ -0,0 +1,142 @@
+//! Opt-in `Server-Timing` breakdown of a request.
+//!
+//! A client reporting a slow request can send `x-debug-timing` with the
+//! configured token (`OBJECT_STORE_DEBUG_TIMING_TOKEN`) and gets a
+//! `Server-Timing` header splitting the time spent before the response was
+//! ready into phases:
+//! - `auth` — the authorizer and admin identity checks;
+//! - `db` — metadata reads and writes (bucket and object lookups, listing
+//!   queries, committing an upload);
+//! - `disk-read` — opening the stored payload;
+//! - `disk-write` — writing, encrypting and syncing an upload's payload;
+//! - `hash` — computing its MD5 (and SHA-256);
+//! - `total` — everything inside this layer.
+//!
+//! Phases are summed over the request and do not add up to `total`: the
+//! rest is request handling, waiting for the upload body and locks. Bytes
+//! streamed out after the headers (a GET body) are not covered.
+//!
+//! The phases are recorded in a task-local set up by `server_timing` only
+//! for requests carrying the token, so `record` and `timed` cost nothing
+//! otherwise, and work spawned onto other tasks is not counted.
+
+use axum::{
+    extract::{Request, State},
+    http::HeaderValue,
+    middleware::Next,
+    response::Response,
+};
+use sha2::{Digest, Sha256};
+use std::{
+    future::Future,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+
+/// Request header carrying the debug timing token.
+pub const DEBUG_TIMING_HEADER: &str = "x-debug-timing";
+
+/// A phase of request handling reported in `Server-Timing`.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Phase {
+    Auth,
+    Db,
+    DiskRead,
+    DiskWrite,
+    Hash,
+}
+
+impl Phase {
+    const ALL: [Phase; 5] = [
+        Phase::Auth,
+        Phase::Db,
+        Phase::DiskRead,
+        Phase::DiskWrite,
+        Phase::Hash,
+    ];
+
+    fn as_str(self) -> &'static str {
+        match self {
+            Phase::Auth => "auth",
+            Phase::Db => "db",
+            Phase::DiskRead => "disk-read",
+            Phase::DiskWrite => "disk-write",
+            Phase::Hash => "hash",
+        }
+    }
+}
+
+tokio::task_local! {
+    static TIMINGS: Arc<Mutex<[Duration; 5]>>;
+}
+
+/// Add `elapsed` to `phase` of the request being timed, if any.
+pub fn record(phase: Phase, elapsed: Duration) {
+    let _ = TIMINGS.try_with(|timings| {
+        if let Ok(mut timings) = timings.lock() {
+            timings[phase as usize] += elapsed;
+        }
+    });
+}
+
+/// Await `future`, counting the time it takes toward `phase`.
+pub async fn timed<F: Future>(phase: Phase, future: F) -> F::Output {
+    let started = Instant::now();
+    let output = future.await;
+    record(phase, started.elapsed());
+    output
+}
+
+/// The token requests must send to be timed.
+#[derive(Clone)]
+pub struct DebugTiming {
+    token_digest: [u8; 32],
+}
+
+impl DebugTiming {
+    pub fn new(token: &str) -> Self {
+        DebugTiming {
+            token_digest: Sha256::digest(token.as_bytes()).into(),
+        }
+    }
+
+    /// Compared by digest, so the time taken says nothing about the token.
+    fn accepts(&self, token: &[u8]) -> bool {
+        <[u8; 32]>::from(Sha256::digest(token)) == self.token_digest
+    }
+}
+
+/// Time requests that carry the debug timing token and report the phases in
+/// `Server-Timing`.
+pub async fn server_timing(
+    State(settings): State<DebugTiming>,
+    request: Request,
+    next: Next,
+) -> Response {
+    let wanted = request
+        .headers()
+        .get(DEBUG_TIMING_HEADER)
+        .is_some_and(|token| settings.accepts(token.as_bytes()));
+    if !wanted {
+        return next.run(request).await;
+    }
+    let timings = Arc::new(Mutex::new([Duration::ZERO; 5]));
+    let started = Instant::now();
+    let mut response = TIMINGS.scope(timings.clone(), next.run(request)).await;
+    let total = started.elapsed();
+
+    let timings = timings.lock().map(|t| *t).unwrap_or_default();
+    let mut metrics: Vec<String> = Phase::ALL
+        .iter()
+        .map(|phase| metric(phase.as_str(), timings[*phase as usize]))
+        .collect();
+    metrics.push(metric("total", total));
+    if let Ok(value) = HeaderValue::from_str(&metrics.join(", ")) {
+        response.headers_mut().append("server-timing", value);
+    }
+    response
+}
+
+fn metric(name: &str, elapsed: Duration) -> String {
+    format!("{};dur={:.3}", name, elapsed.as_secs_f64() * 1000.0)
+}
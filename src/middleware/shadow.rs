@@ -0,0 +1,214 @@
+//! Request shadowing.
+//!
+//! Mirrors a sample of live traffic to a secondary instance so a new version
+//! or backend can be validated under real load before cutover. Shadow calls
+//! are fire-and-forget: they run on background tasks, their responses are
+//! discarded, and they never delay or fail the primary request.
+//!
+//! Reads (`GET`/`HEAD`) are sampled at `OBJECT_STORE_SHADOW_PERCENT`. Writes
+//! are only mirrored when `OBJECT_STORE_SHADOW_WRITES` is set; their bodies are
+//! teed chunk by chunk while the primary consumes them, and the shadow copy
+//! is abandoned (rather than slowing the client) if the secondary falls
+//! behind. Health probes, the admin API and requests that are themselves
+//! shadow copies (`x-object-store-shadow`) are never mirrored.
+
+use crate::services::outbound::OutboundHttp;
+use axum::{
+    body::Body,
+    extract::{Request, State},
+    http::{HeaderMap, HeaderName, Method, header},
+    middleware::Next,
+    response::Response,
+};
+use bytes::Bytes;
+use futures::{StreamExt, channel::mpsc, stream};
+use reqwest::{Client, Url};
+use std::{
+    io,
+    sync::{
+        Arc,
+        atomic::{AtomicBool, Ordering},
+    },
+    time::Duration,
+};
+use tokio::sync::Semaphore;
+use uuid::Uuid;
+
+/// Shadow requests allowed in flight at once; extra samples are skipped.
+const MAX_IN_FLIGHT: usize = 64;
+
+/// Body chunks buffered for a slow secondary before its copy is abandoned.
+const BODY_BUFFER_CHUNKS: usize = 128;
+
+/// Timeout for a single shadow request.
+const SHADOW_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// Marks mirrored requests so the secondary can tell them apart.
+pub const SHADOW_HEADER: &str = "x-object-store-shadow";
+
+/// Where and how much traffic to mirror.
+#[derive(Debug, Clone)]
+pub struct ShadowSettings {
+    /// Base URL of the secondary instance.
+    pub target: Url,
+    /// Percentage (0-100) of eligible requests to mirror.
+    pub percent: u8,
+    /// Mirror `PUT`/`POST`/`DELETE` as well as reads.
+    pub include_writes: bool,
+}
+
+/// Shared state for the shadowing middleware.
+#[derive(Debug, Clone)]
+pub struct Shadow {
+    settings: Arc<ShadowSettings>,
+    client: Client,
+    permits: Arc<Semaphore>,
+}
+
+impl Shadow {
+    pub fn new(settings: ShadowSettings, outbound: &OutboundHttp) -> reqwest::Result<Self> {
+        let client = outbound.client_builder().timeout(SHADOW_TIMEOUT).build()?;
+        Ok(Self {
+            settings: Arc::new(settings),
+            client,
+            permits: Arc::new(Semaphore::new(MAX_IN_FLIGHT)),
+        })
+    }
+
+    pub fn settings(&self) -> &ShadowSettings {
+        &self.settings
+    }
+
+    fn is_eligible(&self, method: &Method, path: &str) -> bool {
+        let trimmed = path.trim_start_matches('/');
+        if trimmed.is_empty()
+            || matches!(trimmed, "healthz" | "readyz")
+            || trimmed == "admin"
+            || trimmed.starts_with("admin/")
+        {
+            return false;
+        }
+        let is_read = matches!(*method, Method::GET | Method::HEAD);
+        is_read || self.settings.include_writes
+    }
+
+    fn sampled(&self) -> bool {
+        match self.settings.percent {
+            0 => false,
+            p if p >= 100 => true,
+            p => (Uuid::new_v4().as_u128() % 100) < p as u128,
+        }
+    }
+
+    fn target_url(&self, request: &Request) -> Option<Url> {
+        let path_and_query = request
+            .uri()
+            .path_and_query()
+            .map(|pq| pq.as_str())
+            .unwrap_or("/");
+        self.settings.target.join(path_and_query).ok()
+    }
+}
+
+/// Mirror sampled requests to the secondary instance.
+pub async fn shadow_requests(
+    State(shadow): State<Shadow>,
+    request: Request,
+    next: Next,
+) -> Response {
+    // Never re-mirror traffic that is itself a shadow copy.
+    let is_shadow_copy = request.headers().contains_key(SHADOW_HEADER);
+    if is_shadow_copy
+        || !shadow.is_eligible(request.method(), request.uri().path())
+        || !shadow.sampled()
+    {
+        return next.run(request).await;
+    }
+    let Ok(permit) = shadow.permits.clone().try_acquire_owned() else {
+        tracing::debug!("shadow capacity exhausted; skipping sample");
+        return next.run(request).await;
+    };
+    let Some(url) = shadow.target_url(&request) else {
+        return next.run(request).await;
+    };
+
+    let method = request.method().clone();
+    let headers = forwarded_headers(request.headers());
+    let (parts, body) = request.into_parts();
+
+    // Tee the body: the primary reads it as usual while each chunk is also
+    // offered to the shadow copy. A full buffer means the secondary is too
+    // slow; its copy is cut off instead of stalling the client.
+    let (body, shadow_body) = if matches!(method, Method::GET | Method::HEAD) {
+        (body, None)
+    } else {
+        let (mut tx, rx) = mpsc::channel::<io::Result<Bytes>>(BODY_BUFFER_CHUNKS);
+        let aborted = Arc::new(AtomicBool::new(false));
+        let abort = aborted.clone();
+        let teed = body.into_data_stream().map(move |chunk| {
+            let delivered = match &chunk {
+                Ok(bytes) => tx.is_closed() || tx.try_send(Ok(bytes.clone())).is_ok(),
+                Err(_) => false,
+            };
+            if !delivered {
+                abort.store(true, Ordering::Relaxed);
+                tx.close_channel();
+            }
+            chunk
+        });
+        // End the shadow body with an error when it was cut short, so the
+        // secondary never stores a truncated object.
+        let trailer = stream::once(async move { aborted.load(Ordering::Relaxed) }).filter_map(
+            |aborted| async move {
+                aborted.then(|| Err(io::Error::other("shadow body abandoned")))
+            },
+        );
+        (
+            Body::from_stream(teed),
+            Some(reqwest::Body::wrap_stream(rx.chain(trailer))),
+        )
+    };
+
+    let client = shadow.client.clone();
+    tokio::spawn(async move {
+        let _permit = permit;
+        let mut builder = client.request(method.clone(), url.clone()).headers(headers);
+        if let Some(body) = shadow_body {
+            builder = builder.body(body);
+        }
+        match builder.send().await {
+            Ok(response) => {
+                let status = response.status();
+                // Drain so the connection can be reused.
+                let _ = response.bytes().await;
+                tracing::debug!("shadow {} {} -> {}", method, url.path(), status);
+            }
+            Err(err) => tracing::debug!("shadow {} {} failed: {}", method, url.path(), err),
+        }
+    });
+
+    next.run(Request::from_parts(parts, body)).await
+}
+
+/// Copy end-to-end headers for the shadow request.
+fn forwarded_headers(headers: &HeaderMap) -> HeaderMap {
+    const HOP_BY_HOP: [HeaderName; 7] = [
+        header::CONNECTION,
+        header::HOST,
+        header::PROXY_AUTHORIZATION,
+        header::TE,
+        header::TRAILER,
+        header::TRANSFER_ENCODING,
+        header::UPGRADE,
+    ];
+    let mut forwarded: HeaderMap = headers
+        .iter()
+        .filter(|(name, _)| !HOP_BY_HOP.contains(name))
+        .map(|(name, value)| (name.clone(), value.clone()))
+        .collect();
+    forwarded.insert(
+        HeaderName::from_static(SHADOW_HEADER),
+        header::HeaderValue::from_static("1"),
+    );
+    forwarded
+}
@@ -0,0 +1,286 @@
+//! SLO-style request statistics.
+//!
+//! Every response is counted per operation (the API group of the request,
+//! see `feature_flags::ApiGroup`, or `other` for health probes and the
+//! like) into one-minute slots holding a latency histogram, error counts
+//! and request/response byte totals. `GET /admin/slo?minutes=N` merges the
+//! last `N` slots into p50/p95/p99 latency and error rates, per operation
+//! and overall, without an external metrics stack.
+//!
+//! Latency runs from the request to its response head; streamed bodies are
+//! not waited for. Histogram buckets grow by 20%, so a reported percentile
+//! is the upper bound of its bucket and at most 20% above the true value.
+
+use crate::middleware::feature_flags::ApiGroup;
+use axum::{
+    body::HttpBody,
+    extract::{Request, State},
+    http::{HeaderMap, header::CONTENT_LENGTH},
+    middleware::Next,
+    response::Response,
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::{
+    collections::{BTreeMap, VecDeque},
+    sync::{Arc, Mutex},
+    time::Instant,
+};
+
+/// Minutes of statistics kept; the longest window `GET /admin/slo` serves.
+pub const SLO_WINDOW_MINUTES: u32 = 60;
+
+/// Window used when `GET /admin/slo` is not given one.
+pub const DEFAULT_SLO_MINUTES: u32 = 5;
+
+/// Growth factor between latency buckets.
+const BUCKET_GROWTH: f64 = 1.2;
+
+/// Latency buckets; the first covers up to 1µs, the last everything from
+/// about four hours up.
+const BUCKETS: usize = 130;
+
+/// Latency histogram plus counters of one operation over some span.
+#[derive(Debug, Clone)]
+struct Histogram {
+    buckets: Vec<u64>,
+    requests: u64,
+    client_errors: u64,
+    server_errors: u64,
+    request_bytes: u64,
+    response_bytes: u64,
+    max_micros: u64,
+}
+
+impl Default for Histogram {
+    fn default() -> Self {
+        Self {
+            buckets: vec![0; BUCKETS],
+            requests: 0,
+            client_errors: 0,
+            server_errors: 0,
+            request_bytes: 0,
+            response_bytes: 0,
+            max_micros: 0,
+        }
+    }
+}
+
+impl Histogram {
+    fn bucket_of(micros: u64) -> usize {
+        if micros <= 1 {
+            return 0;
+        }
+        let index = ((micros as f64).ln() / BUCKET_GROWTH.ln()).ceil() as usize;
+        index.min(BUCKETS - 1)
+    }
+
+    fn upper_bound_micros(bucket: usize) -> f64 {
+        BUCKET_GROWTH.powi(bucket as i32)
+    }
+
+    fn record(&mut self, sample: &Sample) {
+        self.buckets[Self::bucket_of(sample.micros)] += 1;
+        self.requests += 1;
+        match sample.status {
+            400..=499 => self.client_errors += 1,
+            500.. => self.server_errors += 1,
+            _ => {}
+        }
+        self.request_bytes += sample.request_bytes;
+        self.response_bytes += sample.response_bytes;
+        self.max_micros = self.max_micros.max(sample.micros);
+    }
+
+    fn merge(&mut self, other: &Histogram) {
+        for (mine, theirs) in self.buckets.iter_mut().zip(&other.buckets) {
+            *mine += theirs;
+        }
+        self.requests += other.requests;
+        self.client_errors += other.client_errors;
+        self.server_errors += other.server_errors;
+        self.request_bytes += other.request_bytes;
+        self.response_bytes += other.response_bytes;
+        self.max_micros = self.max_micros.max(other.max_micros);
+    }
+
+    /// Latency (ms) under which a `quantile` of the requests completed.
+    fn percentile_ms(&self, quantile: f64) -> Option<f64> {
+        if self.requests == 0 {
+            return None;
+        }
+        let rank = ((self.requests as f64) * quantile).ceil().max(1.0) as u64;
+        let mut seen = 0;
+        for (bucket, count) in self.buckets.iter().enumerate() {
+            seen += count;
+            if seen >= rank {
+                let micros = Self::upper_bound_micros(bucket).min(self.max_micros as f64);
+                return Some(micros / 1000.0);
+            }
+        }
+        Some(self.max_micros as f64 / 1000.0)
+    }
+
+    fn summary(&self) -> OperationSlo {
+        let ratio = |count: u64| {
+            if self.requests == 0 {
+                0.0
+            } else {
+                count as f64 / self.requests as f64
+            }
+        };
+        OperationSlo {
+            requests: self.requests,
+            client_errors: self.client_errors,
+            server_errors: self.server_errors,
+            error_rate: ratio(self.server_errors),
+            client_error_rate: ratio(self.client_errors),
+            p50_ms: self.percentile_ms(0.50),
+            p95_ms: self.percentile_ms(0.95),
+            p99_ms: self.percentile_ms(0.99),
+            max_ms: (self.requests > 0).then(|| self.max_micros as f64 / 1000.0),
+            request_bytes: self.request_bytes,
+            response_bytes: self.response_bytes,
+        }
+    }
+}
+
+/// One observed response.
+struct Sample {
+    micros: u64,
+    status: u16,
+    request_bytes: u64,
+    response_bytes: u64,
+}
+
+/// Statistics of one operation (or all of them) over the window.
+#[derive(Debug, Clone, Serialize)]
+pub struct OperationSlo {
+    pub requests: u64,
+    /// `4xx` responses.
+    pub client_errors: u64,
+    /// `5xx` responses.
+    pub server_errors: u64,
+    /// Share of responses that were `5xx`.
+    pub error_rate: f64,
+    /// Share of responses that were `4xx`.
+    pub client_error_rate: f64,
+    pub p50_ms: Option<f64>,
+    pub p95_ms: Option<f64>,
+    pub p99_ms: Option<f64>,
+    pub max_ms: Option<f64>,
+    /// Request bodies, by their `Content-Length`.
+    pub request_bytes: u64,
+    /// Response bodies of a known length (`Content-Length`).
+    pub response_bytes: u64,
+}
+
+/// Response of `GET /admin/slo`.
+#[derive(Debug, Clone, Serialize)]
+pub struct SloReport {
+    pub window_minutes: u32,
+    /// Start of the oldest minute included.
+    pub since: DateTime<Utc>,
+    pub overall: OperationSlo,
+    pub operations: BTreeMap<String, OperationSlo>,
+}
+
+/// One minute of statistics.
+#[derive(Debug)]
+struct Slot {
+    /// Minutes since the Unix epoch.
+    minute: i64,
+    operations: BTreeMap<&'static str, Histogram>,
+}
+
+/// Shared per-minute statistics of the last `SLO_WINDOW_MINUTES`.
+#[derive(Debug, Clone, Default)]
+pub struct SloStats {
+    slots: Arc<Mutex<VecDeque<Slot>>>,
+}
+
+impl SloStats {
+    fn record(&self, operation: &'static str, sample: Sample) {
+        let minute = Utc::now().timestamp().div_euclid(60);
+        let mut slots = self.slots.lock().expect("slo stats poisoned");
+        if slots.back().is_none_or(|slot| slot.minute < minute) {
+            slots.push_back(Slot {
+                minute,
+                operations: BTreeMap::new(),
+            });
+        }
+        while slots
+            .front()
+            .is_some_and(|slot| slot.minute <= minute - SLO_WINDOW_MINUTES as i64)
+        {
+            slots.pop_front();
+        }
+        let slot = slots.back_mut().expect("slot just pushed");
+        slot.operations
+            .entry(operation)
+            .or_default()
+            .record(&sample);
+    }
+
+    /// Statistics of the last `minutes` (the current one included), capped
+    /// at `SLO_WINDOW_MINUTES`.
+    pub fn report(&self, minutes: u32) -> SloReport {
+        let minutes = minutes.clamp(1, SLO_WINDOW_MINUTES);
+        let first = Utc::now().timestamp().div_euclid(60) - minutes as i64 + 1;
+        let mut overall = Histogram::default();
+        let mut operations: BTreeMap<&'static str, Histogram> = BTreeMap::new();
+        let slots = self.slots.lock().expect("slo stats poisoned");
+        for slot in slots.iter().filter(|slot| slot.minute >= first) {
+            for (operation, histogram) in &slot.operations {
+                operations.entry(operation).or_default().merge(histogram);
+                overall.merge(histogram);
+            }
+        }
+        drop(slots);
+        SloReport {
+            window_minutes: minutes,
+            since: DateTime::from_timestamp(first * 60, 0).unwrap_or_default(),
+            overall: overall.summary(),
+            operations: operations
+                .into_iter()
+                .map(|(operation, histogram)| (operation.to_string(), histogram.summary()))
+                .collect(),
+        }
+    }
+}
+
+fn content_length(headers: &HeaderMap) -> Option<u64> {
+    headers
+        .get(CONTENT_LENGTH)
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.parse().ok())
+}
+
+/// Count the response to every request, and make the statistics available
+/// to `GET /admin/slo`.
+pub async fn record_slo_stats(
+    State(stats): State<SloStats>,
+    mut request: Request,
+    next: Next,
+) -> Response {
+    let started = Instant::now();
+    request.extensions_mut().insert(stats.clone());
+    let operation = ApiGroup::classify(request.method(), request.uri())
+        .map(ApiGroup::as_str)
+        .unwrap_or("other");
+    let request_bytes = content_length(request.headers()).unwrap_or(0);
+
+    let response = next.run(request).await;
+    stats.record(
+        operation,
+        Sample {
+            micros: started.elapsed().as_micros() as u64,
+            status: response.status().as_u16(),
+            request_bytes,
+            response_bytes: content_length(response.headers())
+                .or_else(|| response.body().size_hint().exact())
+                .unwrap_or(0),
+        },
+    );
+    response
+}
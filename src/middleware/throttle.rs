@@ -0,0 +1,173 @@
+//! Request rate limits and upload concurrency.
+//!
+//! One client hammering the store can exhaust the SQLite pool or saturate
+//! the disk for everybody else. Two independent guards keep that in check:
+//! - with `OBJECT_STORE_RATE_LIMIT_PER_IP`, each client address (as
+//!   resolved by `client_info`, so proxies are seen through) gets a token
+//!   bucket refilled at that many requests per second and holding up to
+//!   `OBJECT_STORE_RATE_LIMIT_BURST`; a request finding it empty is answered
+//!   `429` SlowDown with a `Retry-After`;
+//! - with `OBJECT_STORE_MAX_CONCURRENT_UPLOADS`, `PUT` and `POST` requests
+//!   on object keys (uploads, parts, copies, completions) beyond that many
+//!   in flight are answered `503` SlowDown straight away rather than queued.
+//!
+//! Health probes are never throttled, and requests without a known client
+//! address (in-process callers) are not rate limited.
+
+use crate::{errors::AppError, middleware::client_info::ClientInfo};
+use axum::{
+    extract::{Request, State},
+    http::{HeaderValue, Method, StatusCode, header},
+    middleware::Next,
+    response::{IntoResponse, Response},
+};
+use std::{
+    collections::HashMap,
+    net::IpAddr,
+    sync::{Arc, Mutex},
+    time::{Duration, Instant},
+};
+use tokio::sync::Semaphore;
+
+/// Clients tracked before idle ones (whose bucket is full again) are
+/// forgotten.
+const MAX_TRACKED_CLIENTS: usize = 10_000;
+
+/// Paths never throttled.
+const EXEMPT_PATHS: [&str; 2] = ["/healthz", "/readyz"];
+
+/// Throttling settings.
+#[derive(Debug, Clone, Copy, Default)]
+pub struct ThrottleSettings {
+    /// Requests per second per client address; 0 disables.
+    pub rate_per_ip: u32,
+    /// Requests a client may send at once; 0 means `rate_per_ip`.
+    pub burst: u32,
+    /// Object uploads in flight; 0 disables.
+    pub max_concurrent_uploads: usize,
+}
+
+#[derive(Debug, Clone, Copy)]
+struct TokenBucket {
+    tokens: f64,
+    refilled: Instant,
+}
+
+/// Shared throttling state.
+#[derive(Clone)]
+pub struct Throttle {
+    settings: ThrottleSettings,
+    clients: Arc<Mutex<HashMap<IpAddr, TokenBucket>>>,
+    uploads: Option<Arc<Semaphore>>,
+}
+
+impl Throttle {
+    pub fn new(mut settings: ThrottleSettings) -> Self {
+        if settings.burst == 0 {
+            settings.burst = settings.rate_per_ip;
+        }
+        Self {
+            settings,
+            clients: Arc::default(),
+            uploads: (settings.max_concurrent_uploads > 0)
+                .then(|| Arc::new(Semaphore::new(settings.max_concurrent_uploads))),
+        }
+    }
+
+    pub fn settings(&self) -> ThrottleSettings {
+        self.settings
+    }
+
+    pub fn is_enabled(&self) -> bool {
+        self.settings.rate_per_ip > 0 || self.uploads.is_some()
+    }
+
+    /// Take a token for `ip`, or say how long until one is available.
+    fn admit(&self, ip: IpAddr, now: Instant) -> Result<(), Duration> {
+        let rate = self.settings.rate_per_ip as f64;
+        let burst = self.settings.burst as f64;
+        let mut clients = self.clients.lock().unwrap_or_else(|e| e.into_inner());
+        if clients.len() >= MAX_TRACKED_CLIENTS && !clients.contains_key(&ip) {
+            clients.retain(|_, bucket| {
+                bucket.tokens + now.duration_since(bucket.refilled).as_secs_f64() * rate < burst
+            });
+        }
+        let bucket = clients.entry(ip).or_insert(TokenBucket {
+            tokens: burst,
+            refilled: now,
+        });
+        let elapsed = now.duration_since(bucket.refilled).as_secs_f64();
+        bucket.tokens = (bucket.tokens + elapsed * rate).min(burst);
+        bucket.refilled = now;
+        if bucket.tokens >= 1.0 {
+            bucket.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - bucket.tokens) / rate))
+        }
+    }
+}
+
+/// Whether `request` uploads to an object key.
+fn is_upload(request: &Request) -> bool {
+    let method = request.method();
+    if method != Method::PUT && method != Method::POST {
+        return false;
+    }
+    let path = request.uri().path().trim_start_matches('/');
+    !path.starts_with("admin/") && path.split_once('/').is_some_and(|(_, key)| !key.is_empty())
+}
+
+/// Rate limit clients and cap concurrent uploads.
+pub async fn throttle_requests(
+    State(throttle): State<Throttle>,
+    request: Request,
+    next: Next,
+) -> Response {
+    if EXEMPT_PATHS.contains(&request.uri().path()) {
+        return next.run(request).await;
+    }
+    if throttle.settings.rate_per_ip > 0
+        && let Some(ip) = request
+            .extensions()
+            .get::<ClientInfo>()
+            .and_then(|client| client.ip)
+        && let Err(wait) = throttle.admit(ip, Instant::now())
+    {
+        return slow_down(
+            StatusCode::TOO_MANY_REQUESTS,
+            format!(
+                "slow down: {} exceeds {} requests per second",
+                ip, throttle.settings.rate_per_ip
+            ),
+            wait,
+        );
+    }
+    let _permit = match &throttle.uploads {
+        Some(uploads) if is_upload(&request) => match uploads.clone().try_acquire_owned() {
+            Ok(permit) => Some(permit),
+            Err(_) => {
+                return slow_down(
+                    StatusCode::SERVICE_UNAVAILABLE,
+                    format!(
+                        "slow down: {} uploads are already in progress",
+                        throttle.settings.max_concurrent_uploads
+                    ),
+                    Duration::from_secs(1),
+                );
+            }
+        },
+        _ => None,
+    };
+    next.run(request).await
+}
+
+fn slow_down(status: StatusCode, message: String, wait: Duration) -> Response {
+    let mut response = AppError::new(status, message).into_response();
+    // Whole seconds, rounded up so a client honouring it is admitted.
+    let secs = wait.as_secs() + u64::from(wait.subsec_nanos() > 0);
+    response
+        .headers_mut()
+        .insert(header::RETRY_AFTER, HeaderValue::from(secs.max(1)));
+    response
+}
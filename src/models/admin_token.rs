@@ -0,0 +1,89 @@
+//! Represents a scoped admin API token tracked in the `admin_tokens` table.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, types::Json};
+use std::{fmt, str::FromStr};
+use uuid::Uuid;
+
+/// Part of the admin API a token may use.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AdminScope {
+    /// Everything, like the admin role.
+    Admin,
+    /// Every `GET`/`HEAD`, like the read-only role.
+    ReadOnly,
+    /// Monitoring reads: SLOs, limits, uploads, volumes, jobs, denials and
+    /// bucket stats.
+    Metrics,
+    /// Snapshots (list and take) and content manifests (fetch and verify).
+    Backup,
+    /// Issuing session tokens (`POST /admin/sessions`).
+    Sessions,
+}
+
+impl AdminScope {
+    pub const ALL: [AdminScope; 5] = [
+        AdminScope::Admin,
+        AdminScope::ReadOnly,
+        AdminScope::Metrics,
+        AdminScope::Backup,
+        AdminScope::Sessions,
+    ];
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            AdminScope::Admin => "admin",
+            AdminScope::ReadOnly => "read-only",
+            AdminScope::Metrics => "metrics",
+            AdminScope::Backup => "backup",
+            AdminScope::Sessions => "sessions",
+        }
+    }
+}
+
+impl fmt::Display for AdminScope {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for AdminScope {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        AdminScope::ALL
+            .into_iter()
+            .find(|scope| scope.as_str().eq_ignore_ascii_case(s.trim()))
+            .ok_or_else(|| {
+                format!(
+                    "unknown admin token scope `{}` (expected one of {})",
+                    s,
+                    AdminScope::ALL.map(|scope| scope.as_str()).join(", ")
+                )
+            })
+    }
+}
+
+/// An admin API token. The secret itself is only shown when it is created.
+#[derive(Serialize, Deserialize, Clone, FromRow, Debug)]
+pub struct AdminToken {
+    /// Identifier used to revoke the token.
+    pub id: Uuid,
+
+    /// What the token is for, e.g. `ci-backup`; reported as its subject.
+    pub name: String,
+
+    /// What the token may do.
+    pub scopes: Json<Vec<AdminScope>>,
+
+    /// When the token was created.
+    pub created_at: DateTime<Utc>,
+
+    /// When the token stops working; never when `None`.
+    pub expires_at: Option<DateTime<Utc>>,
+
+    /// When the token was last accepted.
+    pub last_used_at: Option<DateTime<Utc>>,
+}
@@ -28,4 +28,35 @@ pub struct Bucket {
 
     /// Optional bucket versioning flag.
     pub versioning_enabled: bool,
+
+    /// When set, writes and deletes are rejected while reads keep working.
+    #[serde(default)]
+    pub read_only: bool,
+
+    /// `Cache-Control` value sent with every GET/HEAD of an object.
+    #[serde(default)]
+    pub cache_control: Option<String>,
+
+    /// When set, GET/HEAD send `Expires` this many seconds in the future.
+    #[serde(default)]
+    pub expires_secs: Option<i64>,
+
+    /// Canned ACL applied to uploads that do not send `x-amz-acl`; `None`
+    /// is `private`.
+    #[serde(default)]
+    pub default_acl: Option<String>,
+
+    /// When set, uploads must use (or are given) `bucket-owner-full-control`.
+    #[serde(default)]
+    pub enforce_bucket_owner_full_control: bool,
+
+    /// How uploads are checked by the content scanner: `inline`, `async`,
+    /// or `None` to not scan them.
+    #[serde(default)]
+    pub scan_uploads: Option<String>,
+
+    /// When set, user metadata and tag values are stored encrypted (see
+    /// `metadata_encryption`).
+    #[serde(default)]
+    pub encrypt_metadata: bool,
 }
@@ -0,0 +1,49 @@
+//! Represents a background job tracked in the `jobs` table.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::{FromRow, types::Json};
+use uuid::Uuid;
+
+/// A unit of background work and its progress.
+#[derive(Serialize, Deserialize, Clone, FromRow, Debug)]
+pub struct Job {
+    /// Identifier handed back to the client that enqueued the job.
+    pub id: Uuid,
+
+    /// What the job does, e.g. `delete-prefix`, `storage-class-analysis`,
+    /// `replication-reconcile` or `shard-migration`.
+    pub kind: String,
+
+    /// Bucket the job operates on.
+    pub bucket_id: Uuid,
+
+    /// Key prefix the job is restricted to, when applicable.
+    pub prefix: Option<String>,
+
+    /// Bucket the job writes its output to, when it has any.
+    #[serde(default)]
+    pub destination: Option<String>,
+
+    /// `queued`, `running`, `succeeded` or `failed`.
+    pub status: String,
+
+    /// Items handled so far.
+    pub processed: i64,
+
+    /// Why the job failed.
+    pub error: Option<String>,
+
+    /// What the job found, for kinds that report a result.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub report: Option<Json<serde_json::Value>>,
+
+    /// When the job was enqueued.
+    pub created_at: DateTime<Utc>,
+
+    /// When a runner picked the job up.
+    pub started_at: Option<DateTime<Utc>>,
+
+    /// When the job succeeded or failed.
+    pub finished_at: Option<DateTime<Utc>>,
+}
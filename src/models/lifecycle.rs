@@ -0,0 +1,42 @@
+//! Represents bucket lifecycle rules.
+
+use crate::models::object_tag::ObjectTag;
+use serde::{Deserialize, Serialize};
+
+/// One rule of a bucket's lifecycle configuration.
+///
+/// A rule applies to the keys matching its filter (a prefix and/or tags, all
+/// of which must match) and carries at least one action.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct LifecycleRule {
+    /// Rule identifier (`ID`), unique within the bucket.
+    pub id: String,
+
+    /// Disabled rules are kept but never applied.
+    pub enabled: bool,
+
+    /// Only keys starting with this prefix.
+    pub prefix: Option<String>,
+
+    /// Only objects carrying every one of these tags.
+    #[serde(default)]
+    pub tags: Vec<ObjectTag>,
+
+    /// Expire current objects this many days after they were last modified.
+    pub expiration_days: Option<i64>,
+
+    /// Abort multipart uploads this many days after they were initiated.
+    pub abort_incomplete_days: Option<i64>,
+
+    /// Move current objects to the remote tier once untouched this long.
+    #[serde(default)]
+    pub transition: Option<LifecycleTransition>,
+}
+
+/// A `Transition` action: objects neither modified nor read for `days` are
+/// moved to the remote tier and reported with `storage_class`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct LifecycleTransition {
+    pub days: i64,
+    pub storage_class: String,
+}
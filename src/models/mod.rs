@@ -4,5 +4,16 @@
 //! They map cleanly to database tables via `sqlx::FromRow` and serialize
 //! naturally as JSON via `serde`.
 
+pub mod admin_token;
 pub mod bucket;
+pub mod job;
+pub mod lifecycle;
+pub mod multipart;
+pub mod notification;
 pub mod object;
+pub mod object_lock;
+pub mod object_metadata;
+pub mod object_tag;
+pub mod object_version;
+pub mod recycled_object;
+pub mod snapshot;
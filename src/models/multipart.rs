@@ -0,0 +1,93 @@
+//! Represents S3 multipart uploads, their parts, and the parts of completed
+//! objects.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+/// An initiated multipart upload that has not been completed or aborted.
+///
+/// Parts are stored under `base_path/.multipart/{bucket}/{id}/`.
+#[derive(Serialize, Deserialize, Clone, FromRow, Debug)]
+pub struct MultipartUpload {
+    /// Upload identifier handed to the client (`UploadId`).
+    pub id: Uuid,
+
+    /// Foreign key linking to the parent bucket.
+    pub bucket_id: Uuid,
+
+    /// Key the object will be stored under once completed.
+    pub key: String,
+
+    /// Content type given at initiation, applied to the final object.
+    pub content_type: Option<String>,
+
+    /// Content encoding given at initiation, applied to the final object.
+    pub content_encoding: Option<String>,
+
+    /// `x-amz-tagging` given at initiation, applied to the final object.
+    pub tagging: Option<String>,
+
+    /// `x-amz-meta-*` given at initiation (JSON), applied to the final
+    /// object.
+    pub user_metadata: Option<String>,
+
+    /// Canned ACL resolved at initiation, applied to the final object.
+    pub acl: Option<String>,
+
+    /// `Cache-Control`, `Content-Disposition` and `Expires` given at
+    /// initiation, applied to the final object.
+    pub cache_control: Option<String>,
+    pub content_disposition: Option<String>,
+    pub expires: Option<String>,
+
+    /// Access key that initiated the upload, matched by auto-tagging rules
+    /// on completion.
+    pub uploader: Option<String>,
+
+    /// When the upload was initiated.
+    pub initiated_at: DateTime<Utc>,
+}
+
+/// One uploaded part of a multipart upload.
+#[derive(Serialize, Deserialize, Clone, FromRow, Debug)]
+pub struct UploadPart {
+    /// Upload the part belongs to.
+    pub upload_id: Uuid,
+
+    /// Part number (1-10000); parts are concatenated in this order.
+    pub part_number: i64,
+
+    /// Size in bytes.
+    pub size_bytes: i64,
+
+    /// MD5 of the part payload (hex), echoed as the part's `ETag`.
+    pub etag: String,
+
+    /// Base64 SHA-256 and CRC32C of the part payload, when known (see
+    /// `services::checksum`).
+    pub checksum_sha256: Option<String>,
+    pub checksum_crc32c: Option<String>,
+
+    /// When the part was (last) uploaded.
+    pub last_modified: DateTime<Utc>,
+}
+
+/// One part of an object assembled by `CompleteMultipartUpload`, kept for
+/// the current version of the key.
+#[derive(Serialize, Deserialize, Clone, FromRow, Debug, PartialEq, Eq)]
+pub struct ObjectPart {
+    /// Part number, as given when the part was uploaded.
+    pub part_number: i64,
+
+    /// Size in bytes.
+    pub size_bytes: i64,
+
+    /// MD5 of the part payload (hex).
+    pub etag: String,
+
+    /// Base64 SHA-256 and CRC32C of the part payload, when known.
+    pub checksum_sha256: Option<String>,
+    pub checksum_crc32c: Option<String>,
+}
@@ -0,0 +1,42 @@
+//! Represents bucket notification targets.
+
+use serde::{Deserialize, Serialize};
+
+/// One webhook of a bucket's notification configuration.
+///
+/// Events of the listed kinds on keys matching the filter (prefix and
+/// suffix, both of which must match) are POSTed to `endpoint`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq, Eq)]
+pub struct NotificationTarget {
+    /// Target identifier (`Id`), unique within the bucket; sent as the
+    /// event's `configurationId`.
+    pub id: String,
+
+    /// `http` or `https` URL the events are POSTed to.
+    pub endpoint: String,
+
+    /// Event names such as `s3:ObjectCreated:*`.
+    pub events: Vec<String>,
+
+    /// Only keys starting with this prefix.
+    pub prefix: Option<String>,
+
+    /// Only keys ending with this suffix.
+    pub suffix: Option<String>,
+}
+
+impl NotificationTarget {
+    /// Whether an event named `event_name` (e.g. `ObjectCreated:Put`) on
+    /// `key` goes to this target.
+    pub fn matches(&self, event_name: &str, key: &str) -> bool {
+        self.prefix.as_deref().is_none_or(|p| key.starts_with(p))
+            && self.suffix.as_deref().is_none_or(|s| key.ends_with(s))
+            && self.events.iter().any(|pattern| {
+                let pattern = pattern.strip_prefix("s3:").unwrap_or(pattern);
+                match pattern.strip_suffix('*') {
+                    Some(family) => event_name.starts_with(family),
+                    None => event_name == pattern,
+                }
+            })
+    }
+}
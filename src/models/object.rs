@@ -26,12 +26,25 @@ pub struct Object {
     /// Content type (MIME type).
     pub content_type: Option<String>,
 
+    /// Content encoding the payload is stored with (e.g. `gzip`), replayed on GET.
+    pub content_encoding: Option<String>,
+
     /// Size in bytes.
     pub size_bytes: i64,
 
     /// MD5 or SHA-256 checksum for integrity verification.
     pub etag: Option<String>,
 
+    /// Server-computed SHA-256 of the stored payload, base64-encoded. Only
+    /// recorded when checksum computation is enabled.
+    #[serde(default)]
+    pub checksum_sha256: Option<String>,
+
+    /// CRC32C of the stored payload, base64-encoded. Only recorded when the
+    /// client sent `x-amz-checksum-crc32c` with the upload.
+    #[serde(default)]
+    pub checksum_crc32c: Option<String>,
+
     /// Storage class (e.g., STANDARD, INFREQUENT_ACCESS).
     pub storage_class: String,
 
@@ -43,4 +56,26 @@ pub struct Object {
 
     /// Whether the object is marked as deleted (soft delete / delete marker).
     pub is_deleted: bool,
+
+    /// Canned ACL (`x-amz-acl`) the object was written with; `None` is
+    /// `private`.
+    #[serde(default)]
+    pub acl: Option<String>,
+
+    /// `Cache-Control` sent at upload, replayed on GET/HEAD.
+    #[serde(default)]
+    pub cache_control: Option<String>,
+
+    /// `Content-Disposition` sent at upload, replayed on GET/HEAD.
+    #[serde(default)]
+    pub content_disposition: Option<String>,
+
+    /// `Expires` (an HTTP date) sent at upload, replayed on GET/HEAD.
+    #[serde(default)]
+    pub expires: Option<String>,
+
+    /// Volume the payload was placed on (see `placement`); `None` is the
+    /// default volume.
+    #[serde(default)]
+    pub volume: Option<String>,
 }
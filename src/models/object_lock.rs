@@ -0,0 +1,69 @@
+//! Represents Object Lock settings of buckets and object versions.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use std::{fmt, str::FromStr};
+
+/// How strictly a retention protects a version.
+///
+/// Both modes refuse to delete a version before its retain-until date;
+/// bypassing `GOVERNANCE` retention is not supported.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+#[serde(rename_all = "UPPERCASE")]
+pub enum RetentionMode {
+    Governance,
+    Compliance,
+}
+
+impl RetentionMode {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            RetentionMode::Governance => "GOVERNANCE",
+            RetentionMode::Compliance => "COMPLIANCE",
+        }
+    }
+}
+
+impl fmt::Display for RetentionMode {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+impl FromStr for RetentionMode {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "GOVERNANCE" => Ok(RetentionMode::Governance),
+            "COMPLIANCE" => Ok(RetentionMode::Compliance),
+            _ => Err(format!(
+                "retention mode must be GOVERNANCE or COMPLIANCE, not `{}`",
+                s
+            )),
+        }
+    }
+}
+
+/// Retention given to new versions that do not name their own. Exactly one
+/// of `days` and `years` is set.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct DefaultRetention {
+    pub mode: RetentionMode,
+    pub days: Option<i64>,
+    pub years: Option<i64>,
+}
+
+/// Object Lock configuration of a bucket that has it enabled.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct ObjectLockConfiguration {
+    pub default_retention: Option<DefaultRetention>,
+}
+
+/// Retention of one object version.
+#[derive(Serialize, Deserialize, Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ObjectRetention {
+    pub mode: RetentionMode,
+    /// The version cannot be deleted before this instant.
+    pub retain_until: DateTime<Utc>,
+}
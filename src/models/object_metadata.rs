@@ -0,0 +1,15 @@
+//! Represents one user-defined metadata entry of an object.
+
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+/// An `x-amz-meta-{name}: {value}` pair stored with the current version of
+/// an object and echoed back on GET/HEAD.
+#[derive(Serialize, Deserialize, Clone, FromRow, Debug, PartialEq, Eq)]
+pub struct ObjectMetadata {
+    /// Header suffix after `x-amz-meta-`, lowercase.
+    pub name: String,
+
+    /// Header value as sent.
+    pub value: String,
+}
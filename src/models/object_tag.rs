@@ -0,0 +1,19 @@
+//! Represents one tag of an object.
+
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+
+/// A key/value tag attached to the current version of an object.
+///
+/// Tags are the basis for lifecycle filters and search; at most
+/// `tagging::MAX_TAGS_PER_OBJECT` per object, with unique keys.
+#[derive(Serialize, Deserialize, Clone, FromRow, Debug, PartialEq, Eq)]
+pub struct ObjectTag {
+    /// Tag key (1–128 characters).
+    #[sqlx(rename = "tag_key")]
+    pub key: String,
+
+    /// Tag value (0–256 characters).
+    #[sqlx(rename = "tag_value")]
+    pub value: String,
+}
@@ -0,0 +1,69 @@
+//! Represents a noncurrent version of an object in a versioned bucket.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+/// A previous version of a key, or a delete marker that is no longer current.
+///
+/// The current version of a key always lives in `objects`; once superseded it
+/// is moved here and its payload to `base_path/.versions/{bucket}/{id}`.
+#[derive(Serialize, Deserialize, Clone, FromRow, Debug)]
+pub struct ObjectVersion {
+    /// Identifier of the stored payload (also its file name in the versions area).
+    pub id: Uuid,
+
+    /// Foreign key linking to the parent bucket.
+    pub bucket_id: Uuid,
+
+    /// Object key the version belongs to.
+    pub key: String,
+
+    /// S3 version id; `null` for the version written before versioning was
+    /// enabled.
+    pub version_id: String,
+
+    /// Content type of the version.
+    pub content_type: Option<String>,
+
+    /// Content encoding the payload is stored with.
+    pub content_encoding: Option<String>,
+
+    /// Size in bytes (0 for delete markers).
+    pub size_bytes: i64,
+
+    /// ETag of the version's payload.
+    pub etag: Option<String>,
+
+    /// Server-computed SHA-256 of the payload, if recorded.
+    pub checksum_sha256: Option<String>,
+
+    /// Client-verified CRC32C of the payload, if recorded.
+    pub checksum_crc32c: Option<String>,
+
+    /// Storage class of the version.
+    pub storage_class: String,
+
+    /// When the version was written.
+    pub last_modified: DateTime<Utc>,
+
+    /// Whether this entry is a delete marker (no payload).
+    pub is_delete_marker: bool,
+
+    /// Canned ACL of the version.
+    #[serde(default)]
+    pub acl: Option<String>,
+
+    /// `Cache-Control` of the version.
+    #[serde(default)]
+    pub cache_control: Option<String>,
+
+    /// `Content-Disposition` of the version.
+    #[serde(default)]
+    pub content_disposition: Option<String>,
+
+    /// `Expires` of the version.
+    #[serde(default)]
+    pub expires: Option<String>,
+}
@@ -0,0 +1,65 @@
+//! Represents a payload displaced by an overwrite and parked in the recycle area.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+/// A previous object payload kept around after an overwrite.
+///
+/// The payload itself lives under `base_path/.recycle/{bucket}/{id}`; this
+/// record keeps the metadata needed to put it back in place.
+#[derive(Serialize, Deserialize, Clone, FromRow, Debug)]
+pub struct RecycledObject {
+    /// Identifier of the recycled payload (also its file name in the recycle area).
+    pub id: Uuid,
+
+    /// Foreign key linking to the parent bucket.
+    pub bucket_id: Uuid,
+
+    /// Object key the payload was stored under.
+    pub key: String,
+
+    /// Content type of the overwritten object.
+    pub content_type: Option<String>,
+
+    /// Content encoding of the overwritten object.
+    pub content_encoding: Option<String>,
+
+    /// Size in bytes.
+    pub size_bytes: i64,
+
+    /// ETag of the overwritten payload.
+    pub etag: Option<String>,
+
+    /// Server-computed SHA-256 of the overwritten payload, if recorded.
+    pub checksum_sha256: Option<String>,
+
+    /// Client-verified CRC32C of the overwritten payload, if recorded.
+    pub checksum_crc32c: Option<String>,
+
+    /// When the overwritten object was last modified.
+    pub last_modified: DateTime<Utc>,
+
+    /// When the payload was moved into the recycle area.
+    pub recycled_at: DateTime<Utc>,
+
+    /// When the payload becomes eligible for purging.
+    pub expires_at: DateTime<Utc>,
+
+    /// Canned ACL of the overwritten object.
+    #[serde(default)]
+    pub acl: Option<String>,
+
+    /// `Cache-Control` of the overwritten object.
+    #[serde(default)]
+    pub cache_control: Option<String>,
+
+    /// `Content-Disposition` of the overwritten object.
+    #[serde(default)]
+    pub content_disposition: Option<String>,
+
+    /// `Expires` of the overwritten object.
+    #[serde(default)]
+    pub expires: Option<String>,
+}
@@ -0,0 +1,55 @@
+//! Represents point-in-time bucket snapshots and their schedules.
+
+use chrono::{DateTime, Utc};
+use serde::{Deserialize, Serialize};
+use sqlx::FromRow;
+use uuid::Uuid;
+
+/// A point-in-time copy of a bucket's live objects.
+///
+/// Payloads live under `base_path/.snapshots/{bucket}/{id}/`; per-object
+/// metadata is kept in `snapshot_objects`.
+#[derive(Serialize, Deserialize, Clone, FromRow, Debug)]
+pub struct BucketSnapshot {
+    /// Identifier of the snapshot (also its directory name).
+    pub id: Uuid,
+
+    /// Foreign key linking to the parent bucket.
+    pub bucket_id: Uuid,
+
+    /// When the snapshot was taken.
+    pub created_at: DateTime<Utc>,
+
+    /// What took the snapshot: `scheduled`, `manual` or `pre-restore`.
+    /// Only scheduled snapshots are subject to the retention policy.
+    pub trigger: String,
+
+    /// Number of objects captured.
+    pub object_count: i64,
+
+    /// Sum of the captured object sizes.
+    pub total_bytes: i64,
+}
+
+/// A bucket's snapshot schedule and retention counts.
+#[derive(Serialize, Deserialize, Clone, FromRow, Debug)]
+pub struct SnapshotPolicy {
+    /// Bucket the policy applies to.
+    pub bucket_id: Uuid,
+
+    /// Cron expression (`min hour dom month dow`, optionally with a leading
+    /// seconds field) or a shorthand such as `@daily`.
+    pub schedule: String,
+
+    /// Keep the newest scheduled snapshot of each of this many most recent days.
+    pub keep_daily: i64,
+
+    /// Keep the newest scheduled snapshot of each of this many most recent ISO weeks.
+    pub keep_weekly: i64,
+
+    /// When the policy was created or last replaced.
+    pub created_at: DateTime<Utc>,
+
+    /// When the schedule last fired.
+    pub last_run_at: Option<DateTime<Utc>>,
+}
@@ -2,31 +2,137 @@
 //!
 //! ## Structure
 //! - **Bucket-level endpoints**
-//!   - `GET    /{bucket}` — list objects (supports prefix, delimiter, max-keys)
-//!   - `PUT    /{bucket}` — create bucket
-//!   - `DELETE /{bucket}` — delete bucket
+//!   - `GET    /{bucket}` — list objects (supports prefix, delimiter, max-keys,
+//!     continuation-token, start-after, encoding-type, fetch-owner; filters
+//!     storage-class, min-size, max-size); ListObjects (V1, `marker`) without
+//!     `list-type=2`
+//!   - `HEAD   /{bucket}` — 200/404 bucket existence check, with its region
+//!   - `GET    /{bucket}?location` — the bucket's region (GetBucketLocation)
+//!   - `GET    /{bucket}?versions` — list object versions and delete markers
+//!   - `GET    /{bucket}?deleted=true` — list soft-deleted keys (JSON)
+//!   - `GET    /{bucket}?changes[&since=C]` — change feed after cursor `C` (JSON; `prefix` filters keys)
+//!   - `GET    /{bucket}?list-stream` — every matching object as NDJSON, unpaginated
+//!   - `GET|PUT /{bucket}?versioning` — read / set the versioning state
+//!   - `GET|PUT|DELETE /{bucket}?lifecycle` — lifecycle rules (expiration,
+//!     aborting stale multipart uploads), see `handlers::lifecycle_handlers`
+//!   - `POST   /{bucket}?lifecycle-dry-run` — what a lifecycle configuration
+//!     would expire, transition and abort now under `prefix` (JSON, nothing
+//!     applied)
+//!   - `GET|PUT /{bucket}?notification` — webhook targets for object created /
+//!     removed events, see `handlers::notification_handlers`
+//!   - `GET|PUT /{bucket}?object-lock` — Object Lock with a default
+//!     retention for new versions, see `handlers::object_lock_handlers`
+//!   - `GET    /{bucket}?validate[&key=K]` — pre-flight check of a bucket name
+//!     or upload target (JSON, no side effects)
+//!   - `PUT    /{bucket}` — create bucket (`{"Template": "name"}` applies a
+//!     configured bucket template)
+//!   - `DELETE /{bucket}` — delete an empty bucket (`?force=true` also deletes
+//!     its objects)
+//!   - `DELETE /{bucket}?prefix=P` — queue a background delete of keys under `P`
+//!   - `POST   /{bucket}?delete` — delete up to 1000 keys (DeleteObjects)
 //!
 //! - **Object-level endpoints**
-//!   - `PUT    /{bucket}/{*key}` — upload object
-//!   - `GET    /{bucket}/{*key}` — download object
+//!   - `PUT    /{bucket}/{*key}` — upload object (`x-amz-meta-*` kept as user
+//!     metadata; `Content-MD5` / `x-amz-checksum-*` verified; with
+//!     `x-amz-copy-source`, CopyObject)
+//!   - `GET    /{bucket}/{*key}` — download object (`Range`/`If-Range`,
+//!     `?versionId=`, `response-*` header overrides)
+//!   - conditional headers on GET/HEAD (304) and PUT/DELETE (412), see
+//!     `handlers::conditional`
 //!   - `HEAD   /{bucket}/{*key}` — retrieve metadata only
-//!   - `DELETE /{bucket}/{*key}` — soft-delete object
+//!   - `x-amz-replication-status` on GET/HEAD of keys queued for replication
+//!   - `DELETE /{bucket}/{*key}` — soft-delete object (delete marker when
+//!     versioned; `?versionId=` removes a version)
+//!   - `POST   /{bucket}/{*key}?recover` — restore payload displaced by an overwrite
+//!   - `POST   /{bucket}/{*key}?restore` — undelete a soft-deleted key
+//!   - `GET|PUT|DELETE /{bucket}/{*key}?tagging` — object tags (also
+//!     `x-amz-tagging` on upload)
+//!   - `GET /{bucket}/{*key}?attributes` — GetObjectAttributes
+//!   - multipart uploads (`?uploads`, `?partNumber=&uploadId=`, `?uploadId=`,
+//!     `?uploadId=&completion`), see `handlers::multipart_handlers`
+//!
+//! - **Admin endpoints** (behind OIDC/LDAP and scoped admin tokens when
+//!   configured)
+//!   - `GET    /admin/whoami` — caller identity and mapped role
+//!   - `GET    /admin/buckets/{bucket}` — bucket record and settings
+//!   - `PATCH  /admin/buckets/{bucket}` — update settings (`read_only`,
+//!     `cache_control`, `expires_secs`, `default_acl`,
+//!     `enforce_bucket_owner_full_control`, `scan_uploads`)
+//!   - `POST   /admin/buckets/{bucket}/analytics` — queue a storage class
+//!     analysis (CSV report into a destination bucket)
+//!   - `GET    /admin/buckets/{bucket}/manifest[?prefix=P]` — signed content
+//!     manifest (key, size, SHA-256, version)
+//!   - `POST   /admin/buckets/{bucket}/manifest/verify` — check a manifest
+//!     against the bucket
+//!   - `GET    /admin/buckets/{bucket}/stats[?depth=N]` — objects, bytes
+//!     stored and bytes transferred per key prefix, replication lag
+//!   - `GET|PUT|DELETE /admin/buckets/{bucket}/quota` — bytes / objects limit
+//!     and current usage
+//!   - `GET|PUT|DELETE /admin/buckets/{bucket}/naming-policy` — key
+//!     constraints enforced on writes
+//!   - `GET|PUT|DELETE /admin/buckets/{bucket}/auto-tagging` — rules tagging
+//!     uploads by key, content type, size and uploader
+//!   - `GET|PUT|DELETE /admin/buckets/{bucket}/placement` — volumes payloads
+//!     of key prefixes are placed on
+//!   - `GET|PUT|DELETE /admin/buckets/{bucket}/publishers` — NATS / Kafka
+//!     publishers of object events
+//!   - `GET|PUT|DELETE /admin/buckets/{bucket}/replication` — asynchronous
+//!     replication to another S3 endpoint
+//!   - `POST   /admin/buckets/{bucket}/replication/reconcile` — queue a job
+//!     comparing the bucket with its destination and re-queueing differences
+//!   - `GET|DELETE /admin/buckets/{bucket}/quarantine[?key=K&versionId=V]` —
+//!     list / release versions flagged by the content scanner
+//!   - `GET|PUT|DELETE /admin/buckets/{bucket}/snapshot-policy` — snapshot schedule and retention
+//!   - `GET|POST /admin/buckets/{bucket}/snapshots` — list / take snapshots
+//!   - `DELETE /admin/buckets/{bucket}/snapshots/{id}` — drop a snapshot
+//!   - `POST   /admin/buckets/{bucket}/snapshots/{id}/restore` — roll the bucket back
+//!   - `GET    /admin/bucket-templates` — configured bucket templates
+//!   - `GET    /admin/uploads` — progress of in-flight uploads, abandoned upload counts
+//!   - `GET    /admin/denials` — recently refused requests and counts
+//!   - `GET    /admin/slo[?minutes=N]` — latency percentiles and error rates
+//!     per operation over the last `N` minutes
+//!   - `GET    /admin/limits` — effective limits plus server tunables
+//!   - `GET|PUT /admin/log-level` — read / replace the tracing filter at runtime
+//!   - `GET    /admin/jobs`, `/admin/jobs/{id}` — background job status
+//!   - `GET    /admin/events[?bucket=x]` — live bucket activity as Server-Sent Events
+//!   - `GET    /admin/objects[?owner=&prefix=&pattern=&min_size=&max_size=&older_than_secs=&newer_than_secs=]`
+//!     — search live objects across buckets (paginated JSON)
+//!   - `POST   /admin/purge-deleted[?older_than_secs=N]` — permanently remove
+//!     soft-deleted object rows
+//!   - `POST   /admin/sessions` — issue a session token scoped to a bucket/prefix
+//!   - `GET    /admin/volumes` — per-volume reachability, free space and write errors
+//!   - `POST   /admin/volumes/{volume}/reset` — accept writes again after the
+//!     volume was marked read-only
 //!
 //! The wildcard `*key` allows nested keys like `photos/2025/img.jpg`.
 
 use crate::{
     handlers::{
+        admin_handlers::{
+            create_analysis, create_session, create_snapshot, delete_auto_tagging,
+            delete_bucket_quota, delete_event_publishers, delete_naming_policy,
+            delete_placement_policy, delete_replication, delete_snapshot, delete_snapshot_policy,
+            export_manifest, get_admin_limits, get_auto_tagging, get_bucket_quota,
+            get_bucket_settings, get_bucket_stats, get_event_publishers, get_job, get_limits,
+            get_log_level, get_naming_policy, get_placement_policy, get_replication,
+            get_slo_report, get_snapshot_policy, list_bucket_templates, list_denials, list_jobs,
+            list_quarantined, list_snapshots, list_uploads, list_volumes, patch_bucket_settings,
+            purge_deleted, put_auto_tagging, put_bucket_quota, put_event_publishers, put_log_level,
+            put_naming_policy, put_placement_policy, put_replication, put_snapshot_policy,
+            reconcile_replication, release_quarantined, reset_volume, restore_snapshot,
+            search_objects, stream_events, verify_manifest, whoami,
+        },
         health_handlers::{healthz, readyz},
         object_handlers::{
-            create_bucket, delete_bucket, delete_object, get_object, head_object, list_objects,
-            upload_object,
+            create_bucket, delete_bucket, delete_object, get_object, head_bucket, head_object,
+            list_objects, post_bucket, post_object, upload_object,
         },
     },
     services::storage_service::StorageService,
 };
 use axum::{
     Router,
-    routing::{get, put},
+    routing::{delete, get, post, put},
 };
 
 /// Build and return the router for all S3-compatible routes.
@@ -38,17 +144,112 @@ pub fn routes() -> Router<StorageService> {
         // health endpoints (mounted at root)
         .route("/healthz", get(healthz))
         .route("/readyz", get(readyz))
+        .route("/limits", get(get_limits))
+        // admin endpoints
+        .route("/admin/whoami", get(whoami))
+        .route("/admin/uploads", get(list_uploads))
+        .route("/admin/bucket-templates", get(list_bucket_templates))
+        .route("/admin/denials", get(list_denials))
+        .route("/admin/slo", get(get_slo_report))
+        .route("/admin/limits", get(get_admin_limits))
+        .route("/admin/log-level", get(get_log_level).put(put_log_level))
+        .route("/admin/jobs", get(list_jobs))
+        .route("/admin/jobs/{id}", get(get_job))
+        .route("/admin/events", get(stream_events))
+        .route("/admin/objects", get(search_objects))
+        .route("/admin/purge-deleted", post(purge_deleted))
+        .route("/admin/sessions", post(create_session))
+        .route("/admin/volumes", get(list_volumes))
+        .route("/admin/volumes/{volume}/reset", post(reset_volume))
+        .route(
+            "/admin/buckets/{bucket}",
+            get(get_bucket_settings).patch(patch_bucket_settings),
+        )
+        .route("/admin/buckets/{bucket}/analytics", post(create_analysis))
+        .route("/admin/buckets/{bucket}/manifest", get(export_manifest))
+        .route("/admin/buckets/{bucket}/stats", get(get_bucket_stats))
+        .route(
+            "/admin/buckets/{bucket}/quota",
+            get(get_bucket_quota)
+                .put(put_bucket_quota)
+                .delete(delete_bucket_quota),
+        )
+        .route(
+            "/admin/buckets/{bucket}/naming-policy",
+            get(get_naming_policy)
+                .put(put_naming_policy)
+                .delete(delete_naming_policy),
+        )
+        .route(
+            "/admin/buckets/{bucket}/auto-tagging",
+            get(get_auto_tagging)
+                .put(put_auto_tagging)
+                .delete(delete_auto_tagging),
+        )
+        .route(
+            "/admin/buckets/{bucket}/placement",
+            get(get_placement_policy)
+                .put(put_placement_policy)
+                .delete(delete_placement_policy),
+        )
+        .route(
+            "/admin/buckets/{bucket}/publishers",
+            get(get_event_publishers)
+                .put(put_event_publishers)
+                .delete(delete_event_publishers),
+        )
+        .route(
+            "/admin/buckets/{bucket}/replication",
+            get(get_replication)
+                .put(put_replication)
+                .delete(delete_replication),
+        )
+        .route(
+            "/admin/buckets/{bucket}/replication/reconcile",
+            post(reconcile_replication),
+        )
+        .route(
+            "/admin/buckets/{bucket}/quarantine",
+            get(list_quarantined).delete(release_quarantined),
+        )
+        .route(
+            "/admin/buckets/{bucket}/manifest/verify",
+            post(verify_manifest),
+        )
+        .route(
+            "/admin/buckets/{bucket}/snapshot-policy",
+            get(get_snapshot_policy)
+                .put(put_snapshot_policy)
+                .delete(delete_snapshot_policy),
+        )
+        .route(
+            "/admin/buckets/{bucket}/snapshots",
+            get(list_snapshots).post(create_snapshot),
+        )
+        .route(
+            "/admin/buckets/{bucket}/snapshots/{id}",
+            delete(delete_snapshot),
+        )
+        .route(
+            "/admin/buckets/{bucket}/snapshots/{id}/restore",
+            post(restore_snapshot),
+        )
         // Object-level routes
         .route(
             "/{bucket}/{*key}",
             put(upload_object)
                 .get(get_object)
                 .head(head_object)
-                .delete(delete_object),
+                .delete(delete_object)
+                .post(post_object),
         )
         // Bucket-level routes
         .route(
             "/{bucket}",
-            get(list_objects).put(create_bucket).delete(delete_bucket),
+            get(list_objects)
+                .head(head_bucket)
+                .put(create_bucket)
+                .delete(delete_bucket)
+                .post(post_bucket),
         )
 }
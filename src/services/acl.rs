@@ -0,0 +1,152 @@
+//! Canned object ACLs and the per-bucket upload ACL policy.
+//!
+//! Uploads may name a canned ACL in `x-amz-acl`; without one the bucket's
+//! `default_acl` applies (`private` when unset). A bucket can also require
+//! `bucket-owner-full-control`, the usual condition for accepting uploads
+//! from other accounts: uploads then get that ACL when they send none and
+//! are refused with `403` when they ask for another.
+//!
+//! The resolved ACL is stored with the object. Access checks are left to
+//! the authorizer; this layer only records what the uploader asked for.
+
+use crate::{
+    models::bucket::Bucket,
+    services::{
+        events::EventKind,
+        storage_service::{BUCKET_COLUMNS, StorageError, StorageResult, StorageService},
+    },
+};
+
+/// S3 canned ACLs accepted in `x-amz-acl` and as a bucket default.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CannedAcl {
+    Private,
+    PublicRead,
+    PublicReadWrite,
+    AuthenticatedRead,
+    AwsExecRead,
+    BucketOwnerRead,
+    BucketOwnerFullControl,
+}
+
+impl CannedAcl {
+    pub const ALL: [CannedAcl; 7] = [
+        CannedAcl::Private,
+        CannedAcl::PublicRead,
+        CannedAcl::PublicReadWrite,
+        CannedAcl::AuthenticatedRead,
+        CannedAcl::AwsExecRead,
+        CannedAcl::BucketOwnerRead,
+        CannedAcl::BucketOwnerFullControl,
+    ];
+
+    pub fn as_str(self) -> &'static str {
+        match self {
+            CannedAcl::Private => "private",
+            CannedAcl::PublicRead => "public-read",
+            CannedAcl::PublicReadWrite => "public-read-write",
+            CannedAcl::AuthenticatedRead => "authenticated-read",
+            CannedAcl::AwsExecRead => "aws-exec-read",
+            CannedAcl::BucketOwnerRead => "bucket-owner-read",
+            CannedAcl::BucketOwnerFullControl => "bucket-owner-full-control",
+        }
+    }
+
+    /// Parse a canned ACL name.
+    pub fn parse(value: &str) -> StorageResult<Self> {
+        Self::ALL
+            .into_iter()
+            .find(|acl| acl.as_str() == value)
+            .ok_or_else(|| StorageError::InvalidAcl(value.to_string()))
+    }
+}
+
+impl StorageService {
+    /// The ACL an upload to `bucket` is stored with, given the `x-amz-acl`
+    /// it sent (if any).
+    pub(crate) fn resolve_object_acl(
+        &self,
+        bucket: &Bucket,
+        requested: Option<&str>,
+    ) -> StorageResult<String> {
+        let requested = requested.map(CannedAcl::parse).transpose()?;
+        if bucket.enforce_bucket_owner_full_control {
+            return match requested {
+                None | Some(CannedAcl::BucketOwnerFullControl) => {
+                    Ok(CannedAcl::BucketOwnerFullControl.as_str().to_string())
+                }
+                Some(other) => Err(StorageError::AclNotAllowed(format!(
+                    "bucket `{}` requires bucket-owner-full-control, got {}",
+                    bucket.name,
+                    other.as_str()
+                ))),
+            };
+        }
+        let acl = match requested {
+            Some(acl) => acl,
+            None => match bucket.default_acl.as_deref() {
+                Some(default) => CannedAcl::parse(default)?,
+                None => CannedAcl::Private,
+            },
+        };
+        Ok(acl.as_str().to_string())
+    }
+
+    /// Set or clear (`None`) the canned ACL applied to uploads without
+    /// `x-amz-acl`.
+    pub async fn set_bucket_default_acl(
+        &self,
+        name: &str,
+        default_acl: Option<&str>,
+    ) -> StorageResult<Bucket> {
+        self.ensure_bucket_name_safe(name)?;
+        if let Some(acl) = default_acl {
+            CannedAcl::parse(acl)?;
+        }
+        let updated = sqlx::query_as::<_, Bucket>(&format!(
+            "UPDATE buckets SET default_acl = ? WHERE name = ? RETURNING {BUCKET_COLUMNS}"
+        ))
+        .bind(default_acl)
+        .bind(name)
+        .fetch_one(&*self.db)
+        .await
+        .map_err(|err| match err {
+            sqlx::Error::RowNotFound => StorageError::BucketNotFound(name.to_string()),
+            other => StorageError::Sqlx(other),
+        })?;
+        self.events.bucket(
+            EventKind::BucketConfigChanged,
+            name,
+            Some(format!("default_acl={}", default_acl.unwrap_or(""))),
+        );
+        Ok(updated)
+    }
+
+    /// Require (or stop requiring) uploads to grant the bucket owner full
+    /// control.
+    pub async fn set_bucket_enforce_owner_full_control(
+        &self,
+        name: &str,
+        enforce: bool,
+    ) -> StorageResult<Bucket> {
+        self.ensure_bucket_name_safe(name)?;
+        let updated = sqlx::query_as::<_, Bucket>(&format!(
+            "UPDATE buckets SET enforce_bucket_owner_full_control = ? WHERE name = ?
+             RETURNING {BUCKET_COLUMNS}"
+        ))
+        .bind(enforce)
+        .bind(name)
+        .fetch_one(&*self.db)
+        .await
+        .map_err(|err| match err {
+            sqlx::Error::RowNotFound => StorageError::BucketNotFound(name.to_string()),
+            other => StorageError::Sqlx(other),
+        })?;
+        self.events.bucket(
+            EventKind::BucketConfigChanged,
+            name,
+            Some(format!("enforce_bucket_owner_full_control={}", enforce)),
+        );
+        Ok(updated)
+    }
+}
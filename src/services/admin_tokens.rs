@@ -0,0 +1,124 @@
+//! Scoped admin API tokens.
+//!
+//! CI jobs and monitoring agents should not need a full admin login. An
+//! admin token carries a name, a set of [`AdminScope`]s and optionally an
+//! expiry; it is sent as `Authorization: Bearer osat_...` and accepted by
+//! `middleware::admin_auth` when `OBJECT_STORE_ADMIN_TOKENS` is on.
+//!
+//! Tokens are created, listed and revoked with the binary's
+//! `--create-admin-token`, `--list-admin-tokens` and `--revoke-admin-token`
+//! flags. Only the SHA-256 of a token is stored, so its secret is printed
+//! once, when it is created; a lost token is revoked and replaced.
+
+use crate::{
+    models::admin_token::{AdminScope, AdminToken},
+    services::storage_service::{StorageError, StorageResult, StorageService},
+};
+use chrono::{Duration as ChronoDuration, Utc};
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use sqlx::types::Json;
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Prefix of every admin token, telling them apart from OIDC bearer tokens.
+pub const ADMIN_TOKEN_PREFIX: &str = "osat_";
+
+const TOKEN_COLUMNS: &str = "id, name, scopes, created_at, expires_at, last_used_at";
+
+/// A token just created, with the secret that is never shown again.
+#[derive(Debug, Serialize)]
+pub struct NewAdminToken {
+    #[serde(flatten)]
+    pub token: AdminToken,
+    pub secret: String,
+}
+
+fn token_digest(secret: &str) -> String {
+    format!("{:x}", Sha256::digest(secret.as_bytes()))
+}
+
+impl StorageService {
+    /// Create a token named `name` with `scopes`, expiring after `ttl`. A
+    /// `ttl` past what a timestamp can hold is refused rather than dropped,
+    /// which would leave the token without an expiry.
+    pub async fn create_admin_token(
+        &self,
+        name: &str,
+        scopes: &[AdminScope],
+        ttl: Option<Duration>,
+    ) -> StorageResult<NewAdminToken> {
+        let secret = format!(
+            "{}{}{}",
+            ADMIN_TOKEN_PREFIX,
+            Uuid::new_v4().simple(),
+            Uuid::new_v4().simple()
+        );
+        let now = Utc::now();
+        let expires_at = ttl
+            .map(|ttl| {
+                ChronoDuration::from_std(ttl)
+                    .ok()
+                    .and_then(|ttl| now.checked_add_signed(ttl))
+                    .ok_or_else(|| {
+                        StorageError::InvalidTokenTtl(format!("{}s is out of range", ttl.as_secs()))
+                    })
+            })
+            .transpose()?;
+        let mut unique = Vec::with_capacity(scopes.len());
+        for scope in scopes {
+            if !unique.contains(scope) {
+                unique.push(*scope);
+            }
+        }
+        let token = sqlx::query_as::<_, AdminToken>(&format!(
+            "INSERT INTO admin_tokens (id, name, token_sha256, scopes, created_at, expires_at)
+             VALUES (?, ?, ?, ?, ?, ?) RETURNING {TOKEN_COLUMNS}"
+        ))
+        .bind(Uuid::new_v4())
+        .bind(name)
+        .bind(token_digest(&secret))
+        .bind(Json(unique))
+        .bind(now)
+        .bind(expires_at)
+        .fetch_one(&*self.db)
+        .await?;
+        Ok(NewAdminToken { token, secret })
+    }
+
+    /// Every token, newest first, expired ones included.
+    pub async fn list_admin_tokens(&self) -> StorageResult<Vec<AdminToken>> {
+        Ok(sqlx::query_as::<_, AdminToken>(&format!(
+            "SELECT {TOKEN_COLUMNS} FROM admin_tokens ORDER BY created_at DESC"
+        ))
+        .fetch_all(&*self.db)
+        .await?)
+    }
+
+    /// Delete a token; `false` when there is none with that id.
+    pub async fn revoke_admin_token(&self, id: Uuid) -> StorageResult<bool> {
+        let result = sqlx::query("DELETE FROM admin_tokens WHERE id = ?")
+            .bind(id)
+            .execute(&*self.db)
+            .await?;
+        Ok(result.rows_affected() > 0)
+    }
+
+    /// The unexpired token `secret` names, recording that it was used.
+    pub async fn authenticate_admin_token(
+        &self,
+        secret: &str,
+    ) -> StorageResult<Option<AdminToken>> {
+        let now = Utc::now();
+        Ok(sqlx::query_as::<_, AdminToken>(&format!(
+            "UPDATE admin_tokens SET last_used_at = ?
+             WHERE token_sha256 = ? AND (expires_at IS NULL OR expires_at > ?)
+             RETURNING {TOKEN_COLUMNS}"
+        ))
+        .bind(now)
+        .bind(token_digest(secret))
+        .bind(now)
+        .fetch_optional(&*self.db)
+        .await?)
+    }
+}
@@ -0,0 +1,347 @@
+//! Threshold alerts for small deployments.
+//!
+//! A background monitor samples, every `ALERT_TICK`:
+//!
+//! - bytes stored per bucket and per account (bucket owner), counting live
+//!   objects;
+//! - free space on the filesystem holding `base_path`;
+//! - the share of `5xx` responses since the previous sample (needs the
+//!   `middleware::response_stats` layer).
+//!
+//! Alerts are edge-triggered: crossing a threshold emits one `firing` alert,
+//! returning below it one `resolved` alert. Each transition is logged on the
+//! `alerts` tracing target and, with `OBJECT_STORE_ALERT_WEBHOOK` set, POSTed
+//! as JSON (`{"alerts": [...]}`) through the outbound HTTP client. Delivery
+//! is best effort; a failed webhook is logged and not retried.
+
+use crate::{
+    middleware::response_stats::ResponseStats,
+    services::{
+        outbound::OutboundHttp,
+        storage_service::{StorageResult, StorageService},
+        volume::disk_free_bytes,
+    },
+};
+use chrono::{DateTime, Utc};
+use reqwest::{Client, Url};
+use serde::Serialize;
+use std::{
+    collections::{HashMap, HashSet},
+    time::Duration,
+};
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// How often thresholds are evaluated.
+pub const ALERT_TICK: Duration = Duration::from_secs(60);
+
+/// Fewer responses than this between samples are too few for a meaningful
+/// error rate.
+const MIN_RESPONSES_FOR_ERROR_RATE: u64 = 20;
+
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Thresholds and delivery target; `None` disables a check.
+#[derive(Debug, Clone, Default)]
+pub struct AlertSettings {
+    /// Bytes stored in any one bucket.
+    pub bucket_bytes: Option<u64>,
+    /// Bytes stored across the buckets of one owner.
+    pub account_bytes: Option<u64>,
+    /// Alert when free disk space drops below this many bytes.
+    pub min_disk_free_bytes: Option<u64>,
+    /// Alert when this fraction (0..1) of responses are server errors.
+    pub max_error_rate: Option<f64>,
+    pub webhook: Option<Url>,
+}
+
+impl AlertSettings {
+    /// Whether any threshold is configured.
+    pub fn is_enabled(&self) -> bool {
+        self.bucket_bytes.is_some()
+            || self.account_bytes.is_some()
+            || self.min_disk_free_bytes.is_some()
+            || self.max_error_rate.is_some()
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AlertKind {
+    BucketBytes,
+    AccountBytes,
+    DiskFree,
+    ErrorRate,
+}
+
+impl AlertKind {
+    pub fn as_str(self) -> &'static str {
+        match self {
+            AlertKind::BucketBytes => "bucket-bytes",
+            AlertKind::AccountBytes => "account-bytes",
+            AlertKind::DiskFree => "disk-free",
+            AlertKind::ErrorRate => "error-rate",
+        }
+    }
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum AlertState {
+    Firing,
+    Resolved,
+}
+
+/// One threshold transition.
+#[derive(Debug, Clone, Serialize)]
+pub struct Alert {
+    pub kind: AlertKind,
+    pub state: AlertState,
+    /// Bucket name or owner id for per-bucket / per-account alerts.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub subject: Option<String>,
+    /// Last observed value (bytes, or a 0..1 ratio for `error-rate`).
+    pub value: f64,
+    pub threshold: f64,
+    pub time: DateTime<Utc>,
+}
+
+type AlertKey = (AlertKind, Option<String>);
+
+/// One sampled value and whether it breaches its threshold.
+struct Observation {
+    key: AlertKey,
+    value: f64,
+    threshold: f64,
+    breached: bool,
+}
+
+/// Evaluates thresholds and remembers which alerts are firing.
+pub struct AlertMonitor {
+    service: StorageService,
+    settings: AlertSettings,
+    client: Client,
+    stats: Option<ResponseStats>,
+    last_sample: (u64, u64),
+    firing: HashSet<AlertKey>,
+}
+
+impl AlertMonitor {
+    /// Build a monitor whose webhook calls go through the shared outbound
+    /// client factory. Pass `stats` to enable the error-rate check.
+    pub fn new(
+        service: StorageService,
+        settings: AlertSettings,
+        outbound: &OutboundHttp,
+        stats: Option<ResponseStats>,
+    ) -> reqwest::Result<Self> {
+        let client = outbound.client_builder().timeout(WEBHOOK_TIMEOUT).build()?;
+        let last_sample = stats.as_ref().map(|s| s.snapshot()).unwrap_or_default();
+        Ok(Self {
+            service,
+            settings,
+            client,
+            stats,
+            last_sample,
+            firing: HashSet::new(),
+        })
+    }
+
+    /// Sample every configured metric and return the alerts whose state
+    /// changed since the previous call.
+    pub async fn evaluate(&mut self) -> StorageResult<Vec<Alert>> {
+        let observations = self.observe().await?;
+        let now = Utc::now();
+        let mut alerts = Vec::new();
+        let mut seen = HashSet::new();
+        for obs in observations {
+            seen.insert(obs.key.clone());
+            let was_firing = self.firing.contains(&obs.key);
+            let state = match (obs.breached, was_firing) {
+                (true, false) => AlertState::Firing,
+                (false, true) => AlertState::Resolved,
+                _ => continue,
+            };
+            if state == AlertState::Firing {
+                self.firing.insert(obs.key.clone());
+            } else {
+                self.firing.remove(&obs.key);
+            }
+            alerts.push(Alert {
+                kind: obs.key.0,
+                state,
+                subject: obs.key.1,
+                value: obs.value,
+                threshold: obs.threshold,
+                time: now,
+            });
+        }
+        // A bucket or account that no longer exists cannot stay over quota.
+        let vanished: Vec<AlertKey> = self
+            .firing
+            .iter()
+            .filter(|key| !seen.contains(*key))
+            .cloned()
+            .collect();
+        for key in vanished {
+            self.firing.remove(&key);
+            alerts.push(Alert {
+                kind: key.0,
+                state: AlertState::Resolved,
+                subject: key.1,
+                value: 0.0,
+                threshold: 0.0,
+                time: now,
+            });
+        }
+        Ok(alerts)
+    }
+
+    async fn observe(&mut self) -> StorageResult<Vec<Observation>> {
+        let mut observations = Vec::new();
+        let settings = &self.settings;
+
+        if settings.bucket_bytes.is_some() || settings.account_bytes.is_some() {
+            let rows: Vec<(String, Uuid, i64)> = sqlx::query_as(
+                "SELECT b.name, b.owner_id, COALESCE(SUM(o.size_bytes), 0)
+                 FROM buckets b
+                 LEFT JOIN objects o ON o.bucket_id = b.id AND o.is_deleted = 0
+                 GROUP BY b.id",
+            )
+            .fetch_all(&*self.service.db)
+            .await?;
+
+            let mut per_account: HashMap<Uuid, u64> = HashMap::new();
+            for (bucket, owner, bytes) in rows {
+                let bytes = bytes.max(0) as u64;
+                *per_account.entry(owner).or_default() += bytes;
+                if let Some(limit) = settings.bucket_bytes {
+                    observations.push(Observation {
+                        key: (AlertKind::BucketBytes, Some(bucket)),
+                        value: bytes as f64,
+                        threshold: limit as f64,
+                        breached: bytes > limit,
+                    });
+                }
+            }
+            if let Some(limit) = settings.account_bytes {
+                for (owner, bytes) in per_account {
+                    observations.push(Observation {
+                        key: (AlertKind::AccountBytes, Some(owner.to_string())),
+                        value: bytes as f64,
+                        threshold: limit as f64,
+                        breached: bytes > limit,
+                    });
+                }
+            }
+        }
+
+        if let Some(min_free) = settings.min_disk_free_bytes {
+            match disk_free_bytes(&self.service.base_path) {
+                Ok(Some(free)) => observations.push(Observation {
+                    key: (AlertKind::DiskFree, None),
+                    value: free as f64,
+                    threshold: min_free as f64,
+                    breached: free < min_free,
+                }),
+                Ok(None) => {}
+                Err(err) => {
+                    warn!(
+                        "could not read free space of {}: {}",
+                        self.service.base_path.display(),
+                        err
+                    );
+                    // Unknown is not resolved: keep a firing alert firing.
+                    if self.firing.contains(&(AlertKind::DiskFree, None)) {
+                        observations.push(Observation {
+                            key: (AlertKind::DiskFree, None),
+                            value: min_free as f64,
+                            threshold: min_free as f64,
+                            breached: true,
+                        });
+                    }
+                }
+            }
+        }
+
+        if let (Some(max_rate), Some(stats)) = (settings.max_error_rate, &self.stats) {
+            let (total, errors) = stats.snapshot();
+            let (last_total, last_errors) = self.last_sample;
+            let responses = total - last_total;
+            // Too little traffic to judge: keep the previous state.
+            if responses >= MIN_RESPONSES_FOR_ERROR_RATE {
+                self.last_sample = (total, errors);
+                let rate = (errors - last_errors) as f64 / responses as f64;
+                observations.push(Observation {
+                    key: (AlertKind::ErrorRate, None),
+                    value: rate,
+                    threshold: max_rate,
+                    breached: rate > max_rate,
+                });
+            } else if self.firing.contains(&(AlertKind::ErrorRate, None)) {
+                observations.push(Observation {
+                    key: (AlertKind::ErrorRate, None),
+                    value: max_rate,
+                    threshold: max_rate,
+                    breached: true,
+                });
+            }
+        }
+
+        Ok(observations)
+    }
+
+    /// Log `alerts` and send them to the webhook, if configured.
+    pub async fn deliver(&self, alerts: &[Alert]) {
+        for alert in alerts {
+            let subject = alert.subject.as_deref().unwrap_or("-");
+            match alert.state {
+                AlertState::Firing => warn!(
+                    target: "alerts",
+                    "{} alert firing for {}: {} (threshold {})",
+                    alert.kind.as_str(),
+                    subject,
+                    alert.value,
+                    alert.threshold
+                ),
+                AlertState::Resolved => info!(
+                    target: "alerts",
+                    "{} alert resolved for {}",
+                    alert.kind.as_str(),
+                    subject
+                ),
+            }
+        }
+        let Some(webhook) = &self.settings.webhook else {
+            return;
+        };
+        if alerts.is_empty() {
+            return;
+        }
+        let result = self
+            .client
+            .post(webhook.clone())
+            .json(&serde_json::json!({ "alerts": alerts }))
+            .send()
+            .await
+            .and_then(|r| r.error_for_status());
+        if let Err(err) = result {
+            warn!("alert webhook failed: {}", err);
+        }
+    }
+}
+
+/// Spawn a background task that evaluates `monitor` every `period`.
+pub fn spawn_alert_monitor(mut monitor: AlertMonitor, period: Duration) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(period);
+        loop {
+            ticker.tick().await;
+            match monitor.evaluate().await {
+                Ok(alerts) => monitor.deliver(&alerts).await,
+                Err(err) => warn!("alert evaluation failed: {}", err),
+            }
+        }
+    })
+}
@@ -0,0 +1,287 @@
+//! Storage class analysis.
+//!
+//! With access analytics on, every object GET is counted in `object_reads`
+//! under the age group the object was in when read (`0-15` days, `15-30`,
+//! ..., `365+`). A `storage-class-analysis` job then summarises a bucket (or
+//! a prefix of it) per key prefix: objects and bytes currently stored in each
+//! age group next to the reads and bytes read at that age. From those it
+//! recommends the age after which a prefix could move to `STANDARD_IA`,
+//! similar to S3 Storage Class Analysis.
+//!
+//! Prefixes are grouped one level below the analysed prefix, up to and
+//! including the next `/`. The report is written as CSV into the job's
+//! destination bucket under `storage-class-analysis/{bucket}/{job id}.csv`.
+
+use crate::{
+    models::{job::Job, object::Object},
+    services::{
+        jobs::JOB_COLUMNS,
+        storage_service::{PutObjectParams, StorageError, StorageResult, StorageService},
+    },
+};
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use futures::TryStreamExt;
+use std::{collections::BTreeMap, fmt::Write as _};
+use tracing::{debug, info};
+use uuid::Uuid;
+
+/// Lower bounds (in days) of the age groups reads and storage are binned by.
+const AGE_GROUP_STARTS: [i64; 8] = [0, 15, 30, 45, 60, 90, 180, 365];
+
+/// Objects younger than this are never recommended for transition, as
+/// `STANDARD_IA` bills a 30-day minimum.
+const MIN_TRANSITION_AGE_DAYS: i64 = 30;
+
+/// Bytes read per byte stored below which an age group counts as
+/// infrequently accessed.
+const INFREQUENT_RETRIEVAL_RATE: f64 = 0.1;
+
+/// Index into `AGE_GROUP_STARTS` of an object last modified at `modified`.
+fn age_group_index(modified: DateTime<Utc>, now: DateTime<Utc>) -> usize {
+    let days = (now - modified).num_days().max(0);
+    AGE_GROUP_STARTS
+        .iter()
+        .rposition(|&start| days >= start)
+        .unwrap_or(0)
+}
+
+fn age_group_label(index: usize) -> String {
+    match AGE_GROUP_STARTS.get(index + 1) {
+        Some(end) => format!("{}-{}", AGE_GROUP_STARTS[index], end),
+        None => format!("{}+", AGE_GROUP_STARTS[index]),
+    }
+}
+
+/// Storage and reads of one prefix in one age group.
+#[derive(Debug, Default, Clone, Copy)]
+struct AgeGroupStats {
+    objects: u64,
+    bytes_stored: u64,
+    reads: u64,
+    bytes_read: u64,
+}
+
+impl AgeGroupStats {
+    fn retrieval_rate(&self) -> Option<f64> {
+        (self.bytes_stored > 0).then(|| self.bytes_read as f64 / self.bytes_stored as f64)
+    }
+}
+
+impl StorageService {
+    /// Count a read of `object` for storage class analysis. A no-op unless
+    /// access analytics is enabled.
+    pub async fn record_read(&self, object: &Object, bytes: u64) -> StorageResult<()> {
+        if !self.options.access_analytics {
+            return Ok(());
+        }
+        let now = Utc::now();
+        let group = age_group_label(age_group_index(object.last_modified, now));
+        sqlx::query(
+            "INSERT INTO object_reads (bucket_id, key, age_group, reads, bytes_read, last_read_at)
+             VALUES (?, ?, ?, 1, ?, ?)
+             ON CONFLICT(bucket_id, key, age_group) DO UPDATE SET
+                 reads = reads + 1,
+                 bytes_read = bytes_read + excluded.bytes_read,
+                 last_read_at = excluded.last_read_at",
+        )
+        .bind(object.bucket_id)
+        .bind(&object.key)
+        .bind(group)
+        .bind(i64::try_from(bytes).unwrap_or(i64::MAX))
+        .bind(now)
+        .execute(&*self.db)
+        .await?;
+        Ok(())
+    }
+
+    /// Enqueue a storage class analysis of `bucket` (restricted to `prefix`)
+    /// whose CSV report is written into `destination`.
+    pub async fn enqueue_storage_class_analysis(
+        &self,
+        bucket: &str,
+        prefix: Option<&str>,
+        destination: &str,
+    ) -> StorageResult<Job> {
+        if !self.options.access_analytics {
+            return Err(StorageError::InvalidContent(
+                "access analytics is disabled, so there are no reads to analyse".into(),
+            ));
+        }
+        if let Some(prefix) = prefix {
+            self.ensure_key_safe(prefix)?;
+        }
+        let bucket_rec = self.fetch_bucket(bucket).await?;
+        let destination = self.fetch_writable_bucket(destination).await?;
+        let job = sqlx::query_as::<_, Job>(&format!(
+            "INSERT INTO jobs (id, kind, bucket_id, prefix, destination, status, created_at)
+             VALUES (?, 'storage-class-analysis', ?, ?, ?, 'queued', ?)
+             RETURNING {JOB_COLUMNS}"
+        ))
+        .bind(Uuid::new_v4())
+        .bind(bucket_rec.id)
+        .bind(prefix)
+        .bind(&destination.name)
+        .bind(Utc::now())
+        .fetch_one(&*self.db)
+        .await?;
+        self.jobs.notify();
+        info!(
+            "queued job {}: storage class analysis of `{}` into `{}`",
+            job.id, bucket_rec.name, destination.name
+        );
+        Ok(job)
+    }
+
+    /// Build the report for `job` and upload it to its destination bucket.
+    pub(crate) async fn run_storage_class_analysis(&self, job: &Job) -> StorageResult<()> {
+        let bucket_name: String = sqlx::query_scalar("SELECT name FROM buckets WHERE id = ?")
+            .bind(job.bucket_id)
+            .fetch_optional(&*self.db)
+            .await?
+            .ok_or_else(|| StorageError::BucketNotFound(job.bucket_id.to_string()))?;
+        let destination = job.destination.as_deref().ok_or_else(|| {
+            StorageError::InvalidContent("analysis job has no destination bucket".into())
+        })?;
+        let prefix = job.prefix.clone().unwrap_or_default();
+        let now = Utc::now();
+        let mut stats: BTreeMap<String, [AgeGroupStats; AGE_GROUP_STARTS.len()]> = BTreeMap::new();
+
+        // Keys sharing a prefix are contiguous in key order; range scans
+        // avoid LIKE wildcards matching unrelated keys.
+        let mut objects = sqlx::query_as::<_, (String, i64, DateTime<Utc>)>(
+            "SELECT key, size_bytes, last_modified FROM objects
+             WHERE bucket_id = ? AND is_deleted = 0 AND key >= ?
+             ORDER BY key ASC",
+        )
+        .bind(job.bucket_id)
+        .bind(&prefix)
+        .fetch(&*self.db);
+        let mut analysed: i64 = 0;
+        while let Some((key, size, modified)) = objects.try_next().await? {
+            if !key.starts_with(&prefix) {
+                break;
+            }
+            let group = &mut stats.entry(report_prefix(&key, &prefix)).or_default()
+                [age_group_index(modified, now)];
+            group.objects += 1;
+            group.bytes_stored += size.max(0) as u64;
+            analysed += 1;
+        }
+        drop(objects);
+
+        let mut reads = sqlx::query_as::<_, (String, String, i64, i64)>(
+            "SELECT key, age_group, reads, bytes_read FROM object_reads
+             WHERE bucket_id = ? AND key >= ?
+             ORDER BY key ASC",
+        )
+        .bind(job.bucket_id)
+        .bind(&prefix)
+        .fetch(&*self.db);
+        while let Some((key, label, count, bytes)) = reads.try_next().await? {
+            if !key.starts_with(&prefix) {
+                break;
+            }
+            let Some(index) = (0..AGE_GROUP_STARTS.len()).find(|&i| age_group_label(i) == label)
+            else {
+                continue;
+            };
+            let group = &mut stats.entry(report_prefix(&key, &prefix)).or_default()[index];
+            group.reads += count.max(0) as u64;
+            group.bytes_read += bytes.max(0) as u64;
+        }
+        drop(reads);
+
+        let csv = build_report_csv(&stats);
+        let report_key = format!("storage-class-analysis/{}/{}.csv", bucket_name, job.id);
+        let body = futures::stream::iter([Ok(Bytes::from(csv))]);
+        self.upload_object_stream(
+            destination,
+            &report_key,
+            PutObjectParams {
+                content_type: Some("text/csv".into()),
+                ..Default::default()
+            },
+            body,
+        )
+        .await?;
+        sqlx::query("UPDATE jobs SET processed = ? WHERE id = ?")
+            .bind(analysed)
+            .bind(job.id)
+            .execute(&*self.db)
+            .await?;
+        debug!(
+            "job {}: analysed {} objects of `{}` into {}/{}",
+            job.id, analysed, bucket_name, destination, report_key
+        );
+        Ok(())
+    }
+}
+
+/// The report row a key is counted under: `prefix` plus the key's next path
+/// segment, if it has one.
+fn report_prefix(key: &str, prefix: &str) -> String {
+    let rest = &key[prefix.len()..];
+    match rest.find('/') {
+        Some(pos) => format!("{}{}", prefix, &rest[..=pos]),
+        None => prefix.to_string(),
+    }
+}
+
+/// The youngest age from which every older group holding data is read
+/// rarely, as a transition recommendation.
+fn recommendation(groups: &[AgeGroupStats]) -> String {
+    let mut candidate = None;
+    for (index, group) in groups.iter().enumerate().rev() {
+        match group.retrieval_rate() {
+            None => continue,
+            Some(rate) if rate < INFREQUENT_RETRIEVAL_RATE => {
+                if AGE_GROUP_STARTS[index] >= MIN_TRANSITION_AGE_DAYS {
+                    candidate = Some(AGE_GROUP_STARTS[index]);
+                }
+            }
+            Some(_) => break,
+        }
+    }
+    match candidate {
+        Some(days) => format!("transition to STANDARD_IA after {} days", days),
+        None => "keep in STANDARD".to_string(),
+    }
+}
+
+fn build_report_csv(stats: &BTreeMap<String, [AgeGroupStats; AGE_GROUP_STARTS.len()]>) -> String {
+    let mut csv = String::from(
+        "prefix,age_group_days,objects,bytes_stored,reads,bytes_read,retrieval_rate,recommendation\n",
+    );
+    for (prefix, groups) in stats {
+        let advice = recommendation(groups);
+        for (index, group) in groups.iter().enumerate() {
+            let rate = group
+                .retrieval_rate()
+                .map(|rate| format!("{:.4}", rate))
+                .unwrap_or_default();
+            let _ = writeln!(
+                csv,
+                "{},{},{},{},{},{},{},{}",
+                csv_field(prefix),
+                age_group_label(index),
+                group.objects,
+                group.bytes_stored,
+                group.reads,
+                group.bytes_read,
+                rate,
+                advice
+            );
+        }
+    }
+    csv
+}
+
+/// Quote a CSV field when it contains a separator, quote or line break.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
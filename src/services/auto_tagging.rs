@@ -0,0 +1,307 @@
+//! Automatic object tagging.
+//!
+//! Lifecycle rules, tiering and object search filter on tags, which only
+//! works if every writer tags its uploads the same way. A bucket can carry
+//! rules that tag uploads itself (`PUT /admin/buckets/{bucket}/auto-tagging`,
+//! or a bucket template):
+//!
+//! ```json
+//! {
+//!   "rules": [
+//!     {"prefix": "ingest/", "tags": {"tier": "raw"}},
+//!     {"content_type": "image/*", "min_size": 1048576, "tags": {"media": "large-image"}},
+//!     {"key_pattern": ".*\\.parquet", "uploader": "etl-writer", "tags": {"source": "etl"}}
+//!   ]
+//! }
+//! ```
+//!
+//! A rule applies when all of its conditions hold for the new object:
+//! - `prefix`: the key starts with it;
+//! - `key_pattern`: the key matches this regular expression in full;
+//! - `content_type`: the media type, ignoring parameters and case, is this
+//!   one (or, for `type/*`, of this type);
+//! - `min_size` / `max_size`: bounds on the stored size in bytes;
+//! - `uploader`: the access key whose signature verified on the upload, or
+//!   the session principal (`anonymous` for unsigned or unverified requests;
+//!   for a multipart upload, the one that initiated it).
+//!
+//! Rules are applied in order on every new payload (PUT, CopyObject,
+//! CompleteMultipartUpload) and only add tags the object does not already
+//! have: tags sent by the client, or by an earlier rule, win. An upload whose
+//! tags would then exceed the per-object limit is refused (`InvalidTag`).
+//! Existing objects, and later `PutObjectTagging` calls, are left alone.
+
+use crate::{
+    models::{bucket::Bucket, object_tag::ObjectTag},
+    services::{
+        storage_service::{StorageError, StorageResult, StorageService},
+        tagging::{self, MAX_TAGS_PER_OBJECT},
+    },
+};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use sqlx::{Sqlite, Transaction};
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+/// Compiled size limit for key patterns, so rules cannot make every write
+/// expensive.
+const PATTERN_SIZE_LIMIT: usize = 1 << 20;
+
+/// Most rules a bucket may have.
+pub const MAX_AUTO_TAG_RULES: usize = 100;
+
+/// The auto-tagging rules of a bucket; empty when it has none.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AutoTagging {
+    #[serde(default)]
+    pub rules: Vec<AutoTagRule>,
+}
+
+/// Tags to add to uploads matching every condition given.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AutoTagRule {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prefix: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub key_pattern: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub content_type: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub min_size: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_size: Option<u64>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub uploader: Option<String>,
+    pub tags: BTreeMap<String, String>,
+}
+
+/// What the rules are matched against.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct UploadFacts<'a> {
+    pub key: &'a str,
+    pub content_type: Option<&'a str>,
+    pub size: u64,
+    pub uploader: Option<&'a str>,
+}
+
+impl AutoTagging {
+    pub fn is_empty(&self) -> bool {
+        self.rules.is_empty()
+    }
+
+    /// Check the rules' patterns compile, their conditions make sense and
+    /// their tags are valid, and return them with content types normalized
+    /// (lowercase, no parameters).
+    pub fn validate(mut self) -> StorageResult<Self> {
+        let invalid = |reason: String| StorageError::InvalidAutoTagging(reason);
+        if self.rules.len() > MAX_AUTO_TAG_RULES {
+            return Err(invalid(format!(
+                "at most {} rules are allowed",
+                MAX_AUTO_TAG_RULES
+            )));
+        }
+        for (index, rule) in self.rules.iter_mut().enumerate() {
+            let invalid = |reason: &str| invalid(format!("rule {}: {}", index + 1, reason));
+            if rule.tags.is_empty() {
+                return Err(invalid("`tags` may not be empty"));
+            }
+            tagging::validate_tags(&rule.tag_set()).map_err(|err| invalid(&err.to_string()))?;
+            if let (Some(min), Some(max)) = (rule.min_size, rule.max_size)
+                && min > max
+            {
+                return Err(invalid("`min_size` is larger than `max_size`"));
+            }
+            if let Some(content_type) = &mut rule.content_type {
+                *content_type = media_type(content_type);
+                if !content_type.contains('/') {
+                    return Err(invalid("`content_type` must look like `type/subtype`"));
+                }
+            }
+            if rule.uploader.as_deref().is_some_and(str::is_empty) {
+                return Err(invalid("`uploader` may not be empty"));
+            }
+            rule.compile().map_err(|reason| invalid(&reason))?;
+        }
+        Ok(self)
+    }
+
+    /// The tags the rules add to an upload already carrying `tags`.
+    pub(crate) fn tags_for(
+        &self,
+        upload: UploadFacts<'_>,
+        tags: &[ObjectTag],
+    ) -> StorageResult<Vec<ObjectTag>> {
+        let mut added: Vec<ObjectTag> = Vec::new();
+        for rule in &self.rules {
+            if !rule.matches(&upload)? {
+                continue;
+            }
+            for tag in rule.tag_set() {
+                let present = |t: &ObjectTag| t.key == tag.key;
+                if !tags.iter().any(present) && !added.iter().any(present) {
+                    added.push(tag);
+                }
+            }
+        }
+        Ok(added)
+    }
+}
+
+impl AutoTagRule {
+    fn tag_set(&self) -> Vec<ObjectTag> {
+        self.tags
+            .iter()
+            .map(|(key, value)| ObjectTag {
+                key: key.clone(),
+                value: value.clone(),
+            })
+            .collect()
+    }
+
+    fn compile(&self) -> Result<Option<Regex>, String> {
+        self.key_pattern
+            .as_deref()
+            .map(|pattern| {
+                RegexBuilder::new(&format!("^(?:{})$", pattern))
+                    .size_limit(PATTERN_SIZE_LIMIT)
+                    .build()
+                    .map_err(|err| format!("pattern `{}`: {}", pattern, err))
+            })
+            .transpose()
+    }
+
+    fn matches(&self, upload: &UploadFacts<'_>) -> StorageResult<bool> {
+        if self
+            .prefix
+            .as_deref()
+            .is_some_and(|prefix| !upload.key.starts_with(prefix))
+        {
+            return Ok(false);
+        }
+        if self.min_size.is_some_and(|min| upload.size < min)
+            || self.max_size.is_some_and(|max| upload.size > max)
+        {
+            return Ok(false);
+        }
+        if let Some(wanted) = self.uploader.as_deref()
+            && upload.uploader != Some(wanted)
+        {
+            return Ok(false);
+        }
+        if let Some(wanted) = self.content_type.as_deref() {
+            let actual = media_type(upload.content_type.unwrap_or_default());
+            let matched = match wanted.strip_suffix("/*") {
+                Some(kind) => actual.split('/').next() == Some(kind),
+                None => actual == wanted,
+            };
+            if !matched {
+                return Ok(false);
+            }
+        }
+        match self.compile().map_err(StorageError::InvalidAutoTagging)? {
+            Some(pattern) => Ok(pattern.is_match(upload.key)),
+            None => Ok(true),
+        }
+    }
+}
+
+/// `Text/HTML; charset=utf-8` → `text/html`.
+fn media_type(content_type: &str) -> String {
+    content_type
+        .split(';')
+        .next()
+        .unwrap_or_default()
+        .trim()
+        .to_ascii_lowercase()
+}
+
+impl StorageService {
+    /// The auto-tagging rules of `bucket`; empty when it has none.
+    pub async fn get_auto_tagging(&self, bucket: &str) -> StorageResult<AutoTagging> {
+        let bucket_rec = self.fetch_bucket(bucket).await?;
+        self.auto_tagging(bucket_rec.id).await
+    }
+
+    /// Replace the auto-tagging rules of `bucket`; no rules removes them.
+    pub async fn set_auto_tagging(
+        &self,
+        bucket: &str,
+        rules: AutoTagging,
+    ) -> StorageResult<AutoTagging> {
+        let bucket_rec = self.fetch_bucket(bucket).await?;
+        let rules = rules.validate()?;
+        let mut tx = self.db.begin().await?;
+        store_auto_tagging(&mut tx, bucket_rec.id, &rules).await?;
+        tx.commit().await?;
+        Ok(rules)
+    }
+
+    /// `tags` plus those the bucket's rules add for `upload`, checked
+    /// against the per-object limit.
+    pub(crate) async fn with_auto_tags(
+        &self,
+        bucket: &Bucket,
+        upload: UploadFacts<'_>,
+        mut tags: Vec<ObjectTag>,
+    ) -> StorageResult<Vec<ObjectTag>> {
+        let rules = self.auto_tagging(bucket.id).await?;
+        if rules.is_empty() {
+            return Ok(tags);
+        }
+        let added = rules.tags_for(upload, &tags)?;
+        if tags.len() + added.len() > MAX_TAGS_PER_OBJECT {
+            return Err(StorageError::InvalidTag(format!(
+                "auto-tagging rules of bucket `{}` would give `{}` {} tags, more than the {} allowed",
+                bucket.name,
+                upload.key,
+                tags.len() + added.len(),
+                MAX_TAGS_PER_OBJECT
+            )));
+        }
+        tags.extend(added);
+        Ok(tags)
+    }
+
+    async fn auto_tagging(&self, bucket_id: Uuid) -> StorageResult<AutoTagging> {
+        let row: Option<(String,)> =
+            sqlx::query_as("SELECT rules FROM bucket_auto_tagging WHERE bucket_id = ?")
+                .bind(bucket_id)
+                .fetch_optional(&*self.db)
+                .await?;
+        match row {
+            Some((json,)) => serde_json::from_str(&json).map_err(|err| {
+                StorageError::InvalidAutoTagging(format!("stored rules are unreadable: {}", err))
+            }),
+            None => Ok(AutoTagging::default()),
+        }
+    }
+}
+
+/// Store (or, when empty, remove) the auto-tagging rules of a bucket in `tx`.
+pub(crate) async fn store_auto_tagging(
+    tx: &mut Transaction<'_, Sqlite>,
+    bucket_id: Uuid,
+    rules: &AutoTagging,
+) -> StorageResult<()> {
+    if rules.is_empty() {
+        sqlx::query("DELETE FROM bucket_auto_tagging WHERE bucket_id = ?")
+            .bind(bucket_id)
+            .execute(&mut **tx)
+            .await?;
+        return Ok(());
+    }
+    let json = serde_json::to_string(rules)
+        .map_err(|err| StorageError::InvalidAutoTagging(err.to_string()))?;
+    sqlx::query(
+        "INSERT INTO bucket_auto_tagging (bucket_id, rules) VALUES (?, ?)
+         ON CONFLICT(bucket_id) DO UPDATE SET rules = excluded.rules",
+    )
+    .bind(bucket_id)
+    .bind(json)
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,396 @@
+//! `aws-chunked` upload bodies.
+//!
+//! AWS SDKs stream uploads (and parts) with `Content-Encoding: aws-chunked`
+//! and an `x-amz-content-sha256` of `STREAMING-...`: the body is a series of
+//! `{hex size};chunk-signature={signature}\r\n{data}\r\n` chunks ending with
+//! a zero-size one, followed by trailing headers (`x-amz-checksum-*`) for
+//! the `-TRAILER` variants. The framing is stripped while the body streams
+//! in, so the payload stored is the plain content and
+//! `x-amz-decoded-content-length` stands in for `Content-Length`; a body
+//! that is cut short or longer than declared is refused with `400`.
+//!
+//! With `--access-keys`, every chunk signature of a
+//! `STREAMING-AWS4-HMAC-SHA256-PAYLOAD[-TRAILER]` body is verified as it
+//! arrives. Each one chains on the previous, starting from the seed
+//! signature of `Authorization`, and is keyed by the secret of the access
+//! key named there, so only a holder of that secret can produce them. A bad
+//! signature fails the upload with `403` and nothing is stored. Signed
+//! streams from other access keys, and SigV4a (ECDSA) streams, are refused
+//! up front. The seed signature itself is not checked (see
+//! `middleware::authorizer`), nor are trailers. Without access keys the
+//! signatures are dropped unverified.
+
+use crate::services::{
+    blob_store::{S3Credentials, hmac_sha256},
+    content_encoding::ByteStream,
+    storage_service::{StorageError, StorageResult, StorageService},
+};
+use bytes::{Bytes, BytesMut};
+use futures::{Stream, StreamExt, stream};
+use hmac::{Hmac, Mac};
+use sha2::{Digest, Sha256};
+use std::{collections::HashMap, fmt, io, sync::Arc};
+
+/// `x-amz-content-sha256` prefix of every streaming variant.
+pub const STREAMING_PREFIX: &str = "STREAMING-";
+
+/// Streaming variants whose chunks carry SigV4 signatures.
+const SIGNED_PAYLOAD: &str = "STREAMING-AWS4-HMAC-SHA256-PAYLOAD";
+
+/// Streaming variants signed with SigV4a, which cannot be verified.
+const ECDSA_PAYLOAD: &str = "STREAMING-AWS4-ECDSA-P256-SHA256-PAYLOAD";
+
+/// Longest chunk header or trailer line accepted.
+const MAX_LINE: usize = 4096;
+
+/// SHA-256 of the empty string, part of every chunk's string to sign.
+const EMPTY_SHA256: &str = "e3b0c44298fc1c149afbf4c8996fb92427ae41e4649b934ca495991b7852b855";
+
+/// Secrets of the access keys clients sign requests with.
+#[derive(Clone, Default)]
+pub struct AccessKeys(Arc<HashMap<String, String>>);
+
+impl AccessKeys {
+    pub fn new(credentials: impl IntoIterator<Item = S3Credentials>) -> Self {
+        Self(Arc::new(
+            credentials
+                .into_iter()
+                .map(|credentials| {
+                    (
+                        credentials.access_key().to_string(),
+                        credentials.secret_key().to_string(),
+                    )
+                })
+                .collect(),
+        ))
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.0.is_empty()
+    }
+
+    /// Secret of `access_key`, if it is configured.
+    pub(crate) fn secret(&self, access_key: &str) -> Option<&str> {
+        self.0.get(access_key).map(String::as_str)
+    }
+}
+
+impl fmt::Debug for AccessKeys {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_list().entries(self.0.keys()).finish()
+    }
+}
+
+/// How a streaming upload is framed and signed, from its request headers.
+#[derive(Debug, Clone, Default)]
+pub struct StreamingUpload {
+    /// `x-amz-content-sha256`, e.g. `STREAMING-AWS4-HMAC-SHA256-PAYLOAD`.
+    pub payload: String,
+    /// `x-amz-decoded-content-length`: the size of the payload.
+    pub decoded_length: Option<u64>,
+    /// `Credential=` of the SigV4 `Authorization` header.
+    pub credential: Option<String>,
+    /// `Signature=` of the SigV4 `Authorization` header.
+    pub seed_signature: Option<String>,
+    /// `x-amz-date` of the request.
+    pub amz_date: Option<String>,
+}
+
+/// Carried by the error of a decoded stream whose chunk signature is wrong.
+#[derive(Debug)]
+struct SignatureMismatch(String);
+
+impl fmt::Display for SignatureMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for SignatureMismatch {}
+
+/// The signature error carried by an error from a decoded stream, if any.
+pub(crate) fn mismatch(err: &io::Error) -> Option<StorageError> {
+    err.get_ref()?
+        .downcast_ref::<SignatureMismatch>()
+        .map(|mismatch| StorageError::SignatureDoesNotMatch(mismatch.0.clone()))
+}
+
+fn framing_error(message: impl Into<String>) -> io::Error {
+    io::Error::new(
+        io::ErrorKind::InvalidData,
+        format!("malformed aws-chunked body: {}", message.into()),
+    )
+}
+
+impl StorageService {
+    /// Strip the `aws-chunked` framing of `stream`, verifying the chunk
+    /// signatures when access keys are configured.
+    pub fn decode_streaming_upload<S>(
+        &self,
+        upload: StreamingUpload,
+        stream: S,
+    ) -> StorageResult<ByteStream>
+    where
+        S: Stream<Item = io::Result<Bytes>> + Send + 'static,
+    {
+        let keys = &self.options.access_keys;
+        let signer = if keys.is_empty() {
+            None
+        } else if upload.payload.starts_with(ECDSA_PAYLOAD) {
+            return Err(StorageError::SignatureDoesNotMatch(
+                "SigV4a streaming signatures cannot be verified".into(),
+            ));
+        } else if upload.payload.starts_with(SIGNED_PAYLOAD) {
+            Some(ChunkSigner::new(&upload, keys)?)
+        } else {
+            None
+        };
+        let decoder = Decoder {
+            stream: Box::pin(stream),
+            buf: BytesMut::new(),
+            state: State::Header,
+            chunk: Sha256::new(),
+            signature: None,
+            remaining: 0,
+            decoded: 0,
+            decoded_length: upload.decoded_length,
+            signer,
+        };
+        Ok(Box::pin(stream::try_unfold(
+            decoder,
+            |mut decoder| async move { Ok(decoder.next_data().await?.map(|data| (data, decoder))) },
+        )))
+    }
+}
+
+/// Verifies the chain of chunk signatures of one upload.
+struct ChunkSigner {
+    key: Vec<u8>,
+    amz_date: String,
+    scope: String,
+    previous: String,
+}
+
+impl ChunkSigner {
+    fn new(upload: &StreamingUpload, keys: &AccessKeys) -> StorageResult<Self> {
+        let mismatch = |message: &str| StorageError::SignatureDoesNotMatch(message.to_string());
+        let (access_key, scope) = upload
+            .credential
+            .as_deref()
+            .and_then(|credential| credential.split_once('/'))
+            .ok_or_else(|| mismatch("signed stream without a SigV4 credential"))?;
+        let secret = keys.0.get(access_key).ok_or_else(|| {
+            StorageError::SignatureDoesNotMatch(format!("unknown access key `{}`", access_key))
+        })?;
+        let parts: Vec<&str> = scope.split('/').collect();
+        let [date, region, service, "aws4_request"] = parts[..] else {
+            return Err(mismatch("malformed SigV4 credential scope"));
+        };
+        let previous = upload
+            .seed_signature
+            .clone()
+            .ok_or_else(|| mismatch("signed stream without a seed signature"))?;
+        let amz_date = upload
+            .amz_date
+            .clone()
+            .ok_or_else(|| mismatch("signed stream without x-amz-date"))?;
+        let mut key = format!("AWS4{}", secret).into_bytes();
+        for part in [date, region, service, "aws4_request"] {
+            key = hmac_sha256(&key, part.as_bytes());
+        }
+        Ok(Self {
+            key,
+            amz_date,
+            scope: scope.to_string(),
+            previous,
+        })
+    }
+
+    /// Check the signature of a chunk whose data hashed to `digest`.
+    fn verify(&mut self, signature: Option<&str>, digest: &[u8]) -> io::Result<()> {
+        let string_to_sign = format!(
+            "AWS4-HMAC-SHA256-PAYLOAD\n{}\n{}\n{}\n{}\n{}",
+            self.amz_date,
+            self.scope,
+            self.previous,
+            EMPTY_SHA256,
+            hex(digest)
+        );
+        let mut mac =
+            Hmac::<Sha256>::new_from_slice(&self.key).expect("HMAC accepts any key length");
+        mac.update(string_to_sign.as_bytes());
+        let matches = signature
+            .and_then(unhex)
+            .is_some_and(|signature| mac.verify_slice(&signature).is_ok());
+        if !matches {
+            return Err(io::Error::other(SignatureMismatch(
+                "chunk signature does not match".into(),
+            )));
+        }
+        self.previous = signature.unwrap_or_default().to_ascii_lowercase();
+        Ok(())
+    }
+}
+
+enum State {
+    /// Expecting `{hex size}[;chunk-signature=...]`.
+    Header,
+    /// Passing on the data of the current chunk.
+    Data,
+    /// Expecting the CRLF after a chunk's data.
+    DataEnd,
+    /// Skipping trailing headers after the last chunk.
+    Trailer,
+    Done,
+}
+
+struct Decoder {
+    stream: ByteStream,
+    buf: BytesMut,
+    state: State,
+    /// Hash of the current chunk's data so far.
+    chunk: Sha256,
+    signature: Option<String>,
+    remaining: usize,
+    decoded: u64,
+    decoded_length: Option<u64>,
+    signer: Option<ChunkSigner>,
+}
+
+impl Decoder {
+    /// The next piece of payload, `None` once the body is complete.
+    async fn next_data(&mut self) -> io::Result<Option<Bytes>> {
+        loop {
+            match self.state {
+                State::Header => {
+                    let line = self
+                        .read_line()
+                        .await?
+                        .ok_or_else(|| framing_error("body ends before the last chunk"))?;
+                    let (size, extensions) = line.split_once(';').unwrap_or((&line, ""));
+                    self.remaining = usize::from_str_radix(size.trim(), 16)
+                        .map_err(|_| framing_error(format!("bad chunk size `{}`", size)))?;
+                    self.signature = extensions.split(';').find_map(|extension| {
+                        extension
+                            .trim()
+                            .strip_prefix("chunk-signature=")
+                            .map(str::to_string)
+                    });
+                    self.chunk = Sha256::new();
+                    if self.remaining == 0 {
+                        self.verify_chunk()?;
+                        self.state = State::Trailer;
+                    } else {
+                        self.state = State::Data;
+                    }
+                }
+                State::Data => {
+                    if self.buf.is_empty() && !self.fill().await? {
+                        return Err(framing_error("body ends inside a chunk"));
+                    }
+                    let data = self
+                        .buf
+                        .split_to(self.remaining.min(self.buf.len()))
+                        .freeze();
+                    self.chunk.update(&data);
+                    self.remaining -= data.len();
+                    self.decoded += data.len() as u64;
+                    if self.decoded_length.is_some_and(|len| self.decoded > len) {
+                        return Err(framing_error(
+                            "payload is longer than x-amz-decoded-content-length",
+                        ));
+                    }
+                    if self.remaining == 0 {
+                        self.state = State::DataEnd;
+                    }
+                    return Ok(Some(data));
+                }
+                State::DataEnd => {
+                    match self.read_line().await? {
+                        Some(line) if line.is_empty() => {}
+                        _ => return Err(framing_error("chunk data is not followed by CRLF")),
+                    }
+                    self.verify_chunk()?;
+                    self.state = State::Header;
+                }
+                State::Trailer => {
+                    // Trailers end with an empty line, or just the body.
+                    while let Some(line) = self.read_line().await? {
+                        if line.is_empty() {
+                            break;
+                        }
+                    }
+                    self.state = State::Done;
+                }
+                State::Done => {
+                    if self.decoded_length.is_some_and(|len| self.decoded != len) {
+                        return Err(framing_error(
+                            "payload is shorter than x-amz-decoded-content-length",
+                        ));
+                    }
+                    return Ok(None);
+                }
+            }
+        }
+    }
+
+    fn verify_chunk(&mut self) -> io::Result<()> {
+        match self.signer.as_mut() {
+            Some(signer) => {
+                let digest = std::mem::take(&mut self.chunk).finalize();
+                signer.verify(self.signature.as_deref(), &digest)
+            }
+            None => Ok(()),
+        }
+    }
+
+    /// Read more of the body into `buf`; `false` at its end.
+    async fn fill(&mut self) -> io::Result<bool> {
+        loop {
+            match self.stream.next().await {
+                Some(Ok(data)) if data.is_empty() => continue,
+                Some(Ok(data)) => {
+                    self.buf.extend_from_slice(&data);
+                    return Ok(true);
+                }
+                Some(Err(err)) => return Err(err),
+                None => return Ok(false),
+            }
+        }
+    }
+
+    /// The next CRLF-terminated line, `None` at the end of the body.
+    async fn read_line(&mut self) -> io::Result<Option<String>> {
+        loop {
+            if let Some(end) = self.buf.windows(2).position(|pair| pair == b"\r\n") {
+                let line = self.buf.split_to(end + 2);
+                return String::from_utf8(line[..end].to_vec())
+                    .map(Some)
+                    .map_err(|_| framing_error("chunk header is not UTF-8"));
+            }
+            if self.buf.len() > MAX_LINE {
+                return Err(framing_error("chunk header too long"));
+            }
+            if !self.fill().await? {
+                return match self.buf.is_empty() {
+                    true => Ok(None),
+                    false => Err(framing_error("body ends inside a chunk header")),
+                };
+            }
+        }
+    }
+}
+
+pub(crate) fn hex(bytes: &[u8]) -> String {
+    bytes.iter().map(|byte| format!("{:02x}", byte)).collect()
+}
+
+pub(crate) fn unhex(text: &str) -> Option<Vec<u8>> {
+    if !text.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..text.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(text.get(i..i + 2)?, 16).ok())
+        .collect()
+}
@@ -0,0 +1,200 @@
+//! Multi-object delete (S3 `DeleteObjects`).
+//!
+//! Up to `MAX_DELETE_KEYS` keys are deleted per call and each gets its own
+//! result, so one bad key does not fail the batch. Plain soft-deletes are
+//! applied in a single SQLite transaction and their payloads removed after it
+//! commits; keys that keep version history get delete markers (and
+//! `VersionId` targets are removed) one at a time through the versioning
+//! paths, since those also move payload files.
+//!
+//! As in S3, deleting a key or version that does not exist succeeds.
+
+use crate::services::{
+    changes::{NewChange, insert_change},
+    events::EventKind,
+    keys::key_violation,
+    storage_service::{StorageError, StorageResult, StorageService},
+};
+use chrono::Utc;
+use tracing::warn;
+use uuid::Uuid;
+
+/// Keys accepted by one `DeleteObjects` call.
+pub const MAX_DELETE_KEYS: usize = 1000;
+
+/// One key (optionally one version of it) to delete.
+#[derive(Debug, Clone)]
+pub struct DeleteTarget {
+    pub key: String,
+    pub version_id: Option<String>,
+}
+
+/// Per-key result of a batch delete.
+#[derive(Debug, Clone)]
+pub enum DeleteOutcome {
+    Deleted {
+        key: String,
+        version_id: Option<String>,
+        /// The deletion created a delete marker, or removed one.
+        delete_marker: bool,
+        /// Version id of the marker created or removed.
+        delete_marker_version_id: Option<String>,
+    },
+    Failed {
+        key: String,
+        version_id: Option<String>,
+        code: &'static str,
+        message: String,
+    },
+}
+
+impl DeleteOutcome {
+    fn deleted(target: &DeleteTarget) -> Self {
+        DeleteOutcome::Deleted {
+            key: target.key.clone(),
+            version_id: target.version_id.clone(),
+            delete_marker: false,
+            delete_marker_version_id: None,
+        }
+    }
+
+    fn failed(target: &DeleteTarget, code: &'static str, message: impl Into<String>) -> Self {
+        DeleteOutcome::Failed {
+            key: target.key.clone(),
+            version_id: target.version_id.clone(),
+            code,
+            message: message.into(),
+        }
+    }
+}
+
+impl StorageService {
+    /// Delete every target in `bucket`, returning one outcome per target in
+    /// request order. Only bucket-level problems (missing, read-only, too
+    /// many keys) fail the whole call.
+    pub async fn delete_objects(
+        &self,
+        bucket: &str,
+        targets: &[DeleteTarget],
+    ) -> StorageResult<Vec<DeleteOutcome>> {
+        if targets.len() > MAX_DELETE_KEYS {
+            return Err(StorageError::InvalidContent(format!(
+                "at most {} keys can be deleted per request",
+                MAX_DELETE_KEYS
+            )));
+        }
+        let bucket_rec = self.fetch_writable_bucket(bucket).await?;
+
+        let mut outcomes: Vec<Option<DeleteOutcome>> = vec![None; targets.len()];
+        let mut needs_marker = Vec::new();
+        let mut removed = Vec::new();
+
+        // Keys deleted in the transaction below stay locked until their
+        // payloads are discarded.
+        let key_guards = if bucket_rec.versioning_enabled {
+            Vec::new()
+        } else {
+            let keys = targets
+                .iter()
+                .filter(|t| t.version_id.is_none() && key_violation(&t.key).is_none())
+                .map(|t| t.key.as_str());
+            self.key_locks.lock_all(bucket_rec.id, keys).await
+        };
+        let mut tx = self.db.begin().await?;
+        for (i, target) in targets.iter().enumerate() {
+            if let Some(reason) = key_violation(&target.key) {
+                outcomes[i] = Some(DeleteOutcome::failed(
+                    target,
+                    "InvalidArgument",
+                    format!("invalid key: {}", reason),
+                ));
+                continue;
+            }
+            if target.version_id.is_some() || bucket_rec.versioning_enabled {
+                needs_marker.push(i);
+                continue;
+            }
+            let live: Option<(Uuid, Option<String>)> = sqlx::query_as(
+                "SELECT id, version_id FROM objects
+                 WHERE bucket_id = ? AND key = ? AND is_deleted = 0",
+            )
+            .bind(bucket_rec.id)
+            .bind(&target.key)
+            .fetch_optional(&mut *tx)
+            .await?;
+            match live {
+                // Written while versioning was on: keep that history.
+                Some((_, Some(_))) => needs_marker.push(i),
+                Some((id, None)) => {
+                    sqlx::query(
+                        "UPDATE objects SET is_deleted = 1, deleted_at = ?
+                         WHERE bucket_id = ? AND key = ? AND is_deleted = 0",
+                    )
+                    .bind(Utc::now())
+                    .bind(bucket_rec.id)
+                    .bind(&target.key)
+                    .execute(&mut *tx)
+                    .await?;
+                    insert_change(
+                        &mut *tx,
+                        bucket_rec.id,
+                        &NewChange::deleted(&target.key, None),
+                    )
+                    .await?;
+                    removed.push((i, id));
+                    outcomes[i] = Some(DeleteOutcome::deleted(target));
+                }
+                None => outcomes[i] = Some(DeleteOutcome::deleted(target)),
+            }
+        }
+        tx.commit().await?;
+
+        for &(i, id) in &removed {
+            let key = &targets[i].key;
+            if let Err(err) = self
+                .discard_deleted_payload(&bucket_rec.name, id, key)
+                .await
+            {
+                warn!("failed to remove payload of {}: {}", key, err);
+            }
+            self.events
+                .object(EventKind::ObjectDeleted, &bucket_rec.name, key, None, None);
+        }
+        drop(key_guards);
+
+        for i in needs_marker {
+            let target = &targets[i];
+            let outcome = match target.version_id.as_deref() {
+                Some(version_id) => {
+                    match self
+                        .delete_object_version(&bucket_rec.name, &target.key, version_id)
+                        .await
+                    {
+                        Ok(deleted) => DeleteOutcome::Deleted {
+                            key: target.key.clone(),
+                            version_id: target.version_id.clone(),
+                            delete_marker: deleted.delete_marker,
+                            delete_marker_version_id: deleted
+                                .delete_marker
+                                .then_some(deleted.version_id),
+                        },
+                        Err(StorageError::NoSuchVersion { .. }) => DeleteOutcome::deleted(target),
+                        Err(err) => DeleteOutcome::failed(target, "InternalError", err.to_string()),
+                    }
+                }
+                None => match self.locked_delete_marker(&bucket_rec, &target.key).await {
+                    Ok(marker) => DeleteOutcome::Deleted {
+                        key: target.key.clone(),
+                        version_id: None,
+                        delete_marker: marker.version_id.is_some(),
+                        delete_marker_version_id: marker.version_id,
+                    },
+                    Err(err) => DeleteOutcome::failed(target, "InternalError", err.to_string()),
+                },
+            };
+            outcomes[i] = Some(outcome);
+        }
+
+        Ok(outcomes.into_iter().flatten().collect())
+    }
+}
@@ -0,0 +1,408 @@
+//! Payload stores besides the local volume.
+//!
+//! A `BlobStore` holds payloads under opaque keys chosen by the caller. The
+//! only implementation is `RemoteS3Store`, the `remote-s3` tier that
+//! lifecycle transitions move cold payloads to (see `tiering`): any S3 API
+//! (AWS, MinIO, another object-store), addressed path-style as
+//! `{url}/{key}` and signed with AWS Signature Version 4. Replication (see
+//! `replication`) writes to its destination bucket through one as well.
+
+use crate::services::content_encoding::ByteStream;
+use chrono::{DateTime, Utc};
+use futures::{StreamExt, TryStreamExt, future::BoxFuture};
+use hmac::{Hmac, Mac};
+use percent_encoding::{AsciiSet, NON_ALPHANUMERIC, utf8_percent_encode};
+use reqwest::{
+    Client, Method, StatusCode, Url,
+    header::{HeaderMap, HeaderName},
+};
+use serde::Deserialize;
+use sha2::{Digest, Sha256};
+use std::{fmt, io, path::Path, str::FromStr};
+use tokio::{fs::File, io::AsyncWriteExt};
+use tokio_util::io::ReaderStream;
+
+/// Characters escaped in keys put into request paths: all but the
+/// unreserved ones and `/`, as SigV4 canonical URIs expect.
+pub(crate) const KEY_ENCODE: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~')
+    .remove(b'/');
+
+/// Characters escaped in query parameter names and values: all but the
+/// unreserved ones.
+pub(crate) const QUERY_ENCODE: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~');
+
+/// An object listed by `RemoteS3Store::list`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct RemoteObject {
+    pub key: String,
+    /// Without quotes.
+    pub etag: String,
+    pub size: u64,
+}
+
+/// The parts of a ListObjectsV2 response `RemoteS3Store::list` reads.
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ListBucketResult {
+    #[serde(default)]
+    contents: Vec<ListEntry>,
+    #[serde(default)]
+    is_truncated: bool,
+    next_continuation_token: Option<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(rename_all = "PascalCase")]
+struct ListEntry {
+    key: String,
+    #[serde(rename = "ETag", default)]
+    etag: String,
+    #[serde(default)]
+    size: u64,
+}
+
+/// Somewhere payloads can be copied to and read back from.
+pub trait BlobStore: Send + Sync + fmt::Debug {
+    /// Tier name recorded with the payloads stored here.
+    fn tier(&self) -> &'static str;
+
+    /// Upload the file at `path` as `key`.
+    fn put<'a>(&'a self, key: &'a str, path: &'a Path) -> BoxFuture<'a, io::Result<()>>;
+
+    /// Stream the payload stored as `key`.
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, io::Result<ByteStream>>;
+
+    /// Remove `key`; removing a missing key succeeds.
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, io::Result<()>>;
+}
+
+/// Access key pair for the remote tier, given as `ACCESS_KEY:SECRET_KEY`.
+#[derive(Clone)]
+pub struct S3Credentials {
+    access_key: String,
+    secret_key: String,
+}
+
+impl S3Credentials {
+    pub(crate) fn access_key(&self) -> &str {
+        &self.access_key
+    }
+
+    pub(crate) fn secret_key(&self) -> &str {
+        &self.secret_key
+    }
+}
+
+impl fmt::Debug for S3Credentials {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "S3Credentials({}:<redacted>)", self.access_key)
+    }
+}
+
+impl FromStr for S3Credentials {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_once(':') {
+            Some((access_key, secret_key)) if !access_key.is_empty() && !secret_key.is_empty() => {
+                Ok(S3Credentials {
+                    access_key: access_key.to_string(),
+                    secret_key: secret_key.to_string(),
+                })
+            }
+            _ => Err("credentials must be ACCESS_KEY:SECRET_KEY".into()),
+        }
+    }
+}
+
+/// A bucket (and optional key prefix) of a remote S3 endpoint.
+#[derive(Clone)]
+pub struct RemoteS3Store {
+    client: Client,
+    /// `scheme://host[:port]/bucket[/prefix]`, without a trailing slash.
+    base: Url,
+    region: String,
+    credentials: S3Credentials,
+}
+
+impl fmt::Debug for RemoteS3Store {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("RemoteS3Store")
+            .field("base", &self.base.as_str())
+            .field("region", &self.region)
+            .finish_non_exhaustive()
+    }
+}
+
+impl RemoteS3Store {
+    pub fn new(
+        client: Client,
+        url: Url,
+        region: String,
+        credentials: S3Credentials,
+    ) -> Result<Self, String> {
+        if url.host_str().is_none() || url.path().trim_matches('/').is_empty() {
+            return Err(format!("remote tier URL `{}` must name a bucket", url));
+        }
+        let mut base = url;
+        base.set_query(None);
+        base.set_fragment(None);
+        let path = base.path().trim_end_matches('/').to_string();
+        base.set_path(&path);
+        Ok(RemoteS3Store {
+            client,
+            base,
+            region,
+            credentials,
+        })
+    }
+
+    fn url(&self, key: &str) -> io::Result<Url> {
+        let key = utf8_percent_encode(key, KEY_ENCODE).to_string();
+        Url::parse(&format!("{}/{}", self.base, key)).map_err(io::Error::other)
+    }
+
+    /// Send a signed request to `url`, whose query (if any) must already be
+    /// in canonical form: sorted, and encoded with `QUERY_ENCODE`. The
+    /// payload is never signed (`UNSIGNED-PAYLOAD`), so bodies can be
+    /// streamed. `headers` are sent and signed along.
+    async fn send(
+        &self,
+        method: Method,
+        url: Url,
+        headers: &[(String, String)],
+        body: Option<(reqwest::Body, u64)>,
+    ) -> io::Result<reqwest::Response> {
+        let signed = self.sign(&method, &url, headers, Utc::now())?;
+        let mut request = self.client.request(method, url).headers(signed);
+        if let Some((body, len)) = body {
+            request = request
+                .header(reqwest::header::CONTENT_LENGTH, len)
+                .body(body);
+        }
+        request.send().await.map_err(io::Error::other)
+    }
+
+    /// Upload `file` as `key` with `headers` (lowercase names, e.g.
+    /// `content-type` or `x-amz-meta-*`).
+    pub async fn put_file(
+        &self,
+        key: &str,
+        file: File,
+        headers: &[(String, String)],
+    ) -> io::Result<()> {
+        let len = file.metadata().await?.len();
+        let body = reqwest::Body::wrap_stream(ReaderStream::new(file));
+        let response = self
+            .send(Method::PUT, self.url(key)?, headers, Some((body, len)))
+            .await?;
+        if !response.status().is_success() {
+            return Err(remote_error(&response, key));
+        }
+        Ok(())
+    }
+
+    /// Keys below `prefix` (relative to this store, like the keys it is
+    /// given) with their ETag and size, following continuation tokens.
+    pub async fn list(&self, prefix: &str) -> io::Result<Vec<RemoteObject>> {
+        let path = self.base.path().trim_start_matches('/');
+        let (bucket, base_prefix) = match path.split_once('/') {
+            Some((bucket, rest)) => (bucket, format!("{}/", rest)),
+            None => (path, String::new()),
+        };
+        let mut url = self.base.clone();
+        url.set_path(&format!("/{}", bucket));
+        let full_prefix = format!("{}{}", base_prefix, prefix);
+        let mut objects = Vec::new();
+        let mut token: Option<String> = None;
+        loop {
+            let mut query = vec![("list-type", "2".to_string())];
+            if let Some(token) = &token {
+                query.push(("continuation-token", token.clone()));
+            }
+            query.push(("prefix", full_prefix.clone()));
+            let query: Vec<String> = query
+                .iter()
+                .map(|(name, value)| {
+                    format!("{}={}", name, utf8_percent_encode(value, QUERY_ENCODE))
+                })
+                .collect();
+            url.set_query(Some(&query.join("&")));
+            let response = self.send(Method::GET, url.clone(), &[], None).await?;
+            if !response.status().is_success() {
+                return Err(remote_error(&response, &full_prefix));
+            }
+            let text = response.text().await.map_err(io::Error::other)?;
+            let page: ListBucketResult = quick_xml::de::from_str(&text)
+                .map_err(|err| io::Error::new(io::ErrorKind::InvalidData, err))?;
+            objects.extend(page.contents.into_iter().filter_map(|entry| {
+                Some(RemoteObject {
+                    key: entry.key.strip_prefix(&base_prefix)?.to_string(),
+                    etag: entry.etag.trim_matches('"').to_string(),
+                    size: entry.size,
+                })
+            }));
+            match page.next_continuation_token {
+                Some(next) if page.is_truncated => token = Some(next),
+                _ => return Ok(objects),
+            }
+        }
+    }
+
+    /// SigV4 headers for a request to `url`; see [`sigv4_headers`].
+    fn sign(
+        &self,
+        method: &Method,
+        url: &Url,
+        extra: &[(String, String)],
+        now: DateTime<Utc>,
+    ) -> io::Result<HeaderMap> {
+        sigv4_headers(&self.credentials, &self.region, method, url, extra, now)
+    }
+}
+
+/// SigV4 headers (`host`, `x-amz-date`, `x-amz-content-sha256`,
+/// `authorization`, plus `extra`) for an `UNSIGNED-PAYLOAD` request to `url`,
+/// whose query must already be in canonical form.
+pub(crate) fn sigv4_headers(
+    credentials: &S3Credentials,
+    region: &str,
+    method: &Method,
+    url: &Url,
+    extra: &[(String, String)],
+    now: DateTime<Utc>,
+) -> io::Result<HeaderMap> {
+    const PAYLOAD: &str = "UNSIGNED-PAYLOAD";
+    let host = match url.port() {
+        Some(port) => format!("{}:{}", url.host_str().unwrap_or_default(), port),
+        None => url.host_str().unwrap_or_default().to_string(),
+    };
+    let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let date = now.format("%Y%m%d").to_string();
+    let scope = format!("{}/{}/s3/aws4_request", date, region);
+    let mut signed: Vec<(String, String)> = vec![
+        ("host".into(), host),
+        ("x-amz-content-sha256".into(), PAYLOAD.into()),
+        ("x-amz-date".into(), amz_date.clone()),
+    ];
+    signed.extend(
+        extra
+            .iter()
+            .map(|(name, value)| (name.to_ascii_lowercase(), value.trim().to_string())),
+    );
+    signed.sort();
+    let canonical_headers: String = signed
+        .iter()
+        .map(|(name, value)| format!("{}:{}\n", name, value))
+        .collect();
+    let signed_headers = signed
+        .iter()
+        .map(|(name, _)| name.as_str())
+        .collect::<Vec<_>>()
+        .join(";");
+    let canonical_request = format!(
+        "{}\n{}\n{}\n{}\n{}\n{}",
+        method.as_str(),
+        url.path(),
+        url.query().unwrap_or_default(),
+        canonical_headers,
+        signed_headers,
+        PAYLOAD
+    );
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{:x}",
+        amz_date,
+        scope,
+        Sha256::digest(canonical_request.as_bytes())
+    );
+    let mut key = format!("AWS4{}", credentials.secret_key).into_bytes();
+    for part in [date.as_str(), region, "s3", "aws4_request"] {
+        key = hmac_sha256(&key, part.as_bytes());
+    }
+    let signature: String = hmac_sha256(&key, string_to_sign.as_bytes())
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+
+    let mut headers = HeaderMap::new();
+    let authorization = format!(
+        "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders={}, Signature={}",
+        credentials.access_key, scope, signed_headers, signature
+    );
+    for (name, value) in signed
+        .into_iter()
+        .chain([("authorization".into(), authorization)])
+    {
+        let name = HeaderName::from_bytes(name.as_bytes()).map_err(io::Error::other)?;
+        headers.insert(name, value.parse().map_err(io::Error::other)?);
+    }
+    Ok(headers)
+}
+
+pub(crate) fn hmac_sha256(key: &[u8], data: &[u8]) -> Vec<u8> {
+    let mut mac = Hmac::<Sha256>::new_from_slice(key).expect("HMAC accepts any key length");
+    mac.update(data);
+    mac.finalize().into_bytes().to_vec()
+}
+
+/// `Err` describing a failed response.
+fn remote_error(response: &reqwest::Response, key: &str) -> io::Error {
+    let kind = match response.status() {
+        StatusCode::NOT_FOUND => io::ErrorKind::NotFound,
+        _ => io::ErrorKind::Other,
+    };
+    io::Error::new(
+        kind,
+        format!("remote tier answered {} for `{}`", response.status(), key),
+    )
+}
+
+impl BlobStore for RemoteS3Store {
+    fn tier(&self) -> &'static str {
+        "remote-s3"
+    }
+
+    fn put<'a>(&'a self, key: &'a str, path: &'a Path) -> BoxFuture<'a, io::Result<()>> {
+        Box::pin(async move {
+            let file = File::open(path).await?;
+            self.put_file(key, file, &[]).await
+        })
+    }
+
+    fn get<'a>(&'a self, key: &'a str) -> BoxFuture<'a, io::Result<ByteStream>> {
+        Box::pin(async move {
+            let response = self.send(Method::GET, self.url(key)?, &[], None).await?;
+            if !response.status().is_success() {
+                return Err(remote_error(&response, key));
+            }
+            let stream: ByteStream = Box::pin(response.bytes_stream().map_err(io::Error::other));
+            Ok(stream)
+        })
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, io::Result<()>> {
+        Box::pin(async move {
+            let response = self.send(Method::DELETE, self.url(key)?, &[], None).await?;
+            if !response.status().is_success() && response.status() != StatusCode::NOT_FOUND {
+                return Err(remote_error(&response, key));
+            }
+            Ok(())
+        })
+    }
+}
+
+/// Drain `stream` into `file`.
+pub(crate) async fn write_stream(mut stream: ByteStream, file: &mut File) -> io::Result<()> {
+    while let Some(chunk) = stream.next().await {
+        file.write_all(&chunk?).await?;
+    }
+    file.flush().await
+}
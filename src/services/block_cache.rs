@@ -0,0 +1,159 @@
+//! In-memory block cache for range reads of hot objects.
+//!
+//! Workloads such as Parquet or ZIP readers fetch many small ranges of the
+//! same large object (footer, index, then scattered column chunks). With
+//! `StorageOptions::range_cache_bytes` set, range GETs are assembled from
+//! fixed-size, aligned blocks kept in an LRU cache, so repeated reads of the
+//! same region are served from memory instead of seeking the file again.
+//!
+//! Blocks are keyed by object id *and* last-modified time: any write to a key
+//! changes the latter, so stale blocks are never served and simply age out.
+//! Whole-object GETs and ranges longer than `MAX_CACHED_RANGE_BLOCKS` blocks
+//! bypass the cache; they would only evict the hot blocks.
+
+use crate::{
+    models::object::Object,
+    services::storage_service::{StorageResult, StorageService},
+};
+use bytes::{Bytes, BytesMut};
+use std::{
+    collections::{BTreeMap, HashMap},
+    io::SeekFrom,
+    sync::{Arc, Mutex},
+};
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncSeekExt},
+};
+use uuid::Uuid;
+
+/// Size of a cached block; ranges are widened to block boundaries.
+pub const CACHE_BLOCK_SIZE: u64 = 256 * 1024;
+
+/// Longest range (in blocks) that goes through the cache.
+pub const MAX_CACHED_RANGE_BLOCKS: u64 = 16;
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+struct BlockKey {
+    object_id: Uuid,
+    /// `last_modified` in microseconds; changes on every write to the key.
+    version: i64,
+    index: u64,
+}
+
+#[derive(Debug, Default)]
+struct CacheState {
+    blocks: HashMap<BlockKey, (Bytes, u64)>,
+    /// Recency order: access tick -> block.
+    lru: BTreeMap<u64, BlockKey>,
+    tick: u64,
+    bytes: u64,
+}
+
+/// Shared LRU of object blocks. The capacity is passed on insert so it
+/// follows `StorageOptions` without rebuilding the cache.
+#[derive(Debug, Clone, Default)]
+pub struct BlockCache {
+    state: Arc<Mutex<CacheState>>,
+}
+
+impl BlockCache {
+    fn get(&self, key: &BlockKey) -> Option<Bytes> {
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        state.tick += 1;
+        let tick = state.tick;
+        let (data, old_tick) = state.blocks.get_mut(key)?;
+        let data = data.clone();
+        let old_tick = std::mem::replace(old_tick, tick);
+        state.lru.remove(&old_tick);
+        state.lru.insert(tick, *key);
+        Some(data)
+    }
+
+    fn insert(&self, key: BlockKey, data: Bytes, capacity: u64) {
+        let size = data.len() as u64;
+        if size > capacity {
+            return;
+        }
+        let mut state = self.state.lock().unwrap_or_else(|e| e.into_inner());
+        while state.bytes + size > capacity {
+            let Some((_, oldest)) = state.lru.pop_first() else {
+                break;
+            };
+            if let Some((evicted, _)) = state.blocks.remove(&oldest) {
+                state.bytes -= evicted.len() as u64;
+            }
+        }
+        state.tick += 1;
+        let tick = state.tick;
+        if let Some((previous, old_tick)) = state.blocks.insert(key, (data, tick)) {
+            state.bytes -= previous.len() as u64;
+            state.lru.remove(&old_tick);
+        }
+        state.lru.insert(tick, key);
+        state.bytes += size;
+    }
+
+    /// Bytes currently held.
+    pub fn size_bytes(&self) -> u64 {
+        self.state.lock().unwrap_or_else(|e| e.into_inner()).bytes
+    }
+}
+
+impl StorageService {
+    /// Read bytes `start..=end` of `meta` through the block cache, loading
+    /// missing blocks from `file`. Returns `None` when the cache is disabled
+    /// or the range is too long to be worth caching; the caller then streams
+    /// from `file` as usual.
+    pub async fn cached_range(
+        &self,
+        meta: &Object,
+        file: &mut File,
+        start: u64,
+        end: u64,
+    ) -> StorageResult<Option<Bytes>> {
+        let Some(capacity) = self.options.range_cache_bytes else {
+            return Ok(None);
+        };
+        let size = meta.size_bytes.max(0) as u64;
+        let (first, last) = (start / CACHE_BLOCK_SIZE, end / CACHE_BLOCK_SIZE);
+        if end >= size || last - first + 1 > MAX_CACHED_RANGE_BLOCKS {
+            return Ok(None);
+        }
+
+        let version = meta.last_modified.timestamp_micros();
+        let mut blocks = Vec::with_capacity((last - first + 1) as usize);
+        for index in first..=last {
+            let key = BlockKey {
+                object_id: meta.id,
+                version,
+                index,
+            };
+            let block = match self.block_cache.get(&key) {
+                Some(block) => block,
+                None => {
+                    let offset = index * CACHE_BLOCK_SIZE;
+                    let len = CACHE_BLOCK_SIZE.min(size - offset) as usize;
+                    let mut buf = vec![0u8; len];
+                    file.seek(SeekFrom::Start(offset)).await?;
+                    file.read_exact(&mut buf).await?;
+                    let block = Bytes::from(buf);
+                    self.block_cache.insert(key, block.clone(), capacity);
+                    block
+                }
+            };
+            blocks.push(block);
+        }
+
+        let from = (start - first * CACHE_BLOCK_SIZE) as usize;
+        let len = (end - start + 1) as usize;
+        if let [block] = blocks.as_slice() {
+            return Ok(Some(block.slice(from..from + len)));
+        }
+        let mut joined = BytesMut::with_capacity(blocks.len() * CACHE_BLOCK_SIZE as usize);
+        for block in &blocks {
+            joined.extend_from_slice(block);
+        }
+        Ok(Some(joined.freeze().slice(from..from + len)))
+    }
+}
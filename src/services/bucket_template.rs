@@ -0,0 +1,224 @@
+//! Canned bucket configurations.
+//!
+//! Platform teams that need every new bucket to meet a policy (versioned,
+//! expiring old data, locked for compliance, cacheable with a given header)
+//! define named templates in a JSON file (`OBJECT_STORE_BUCKET_TEMPLATES`):
+//!
+//! ```json
+//! {
+//!   "logs": {
+//!     "versioning": true,
+//!     "lifecycle": [{"id": "expire", "enabled": true, "prefix": "tmp/", "expiration_days": 30}],
+//!     "default_acl": "private"
+//!   }
+//! }
+//! ```
+//!
+//! `PUT /{bucket}` with `{"Template": "logs"}` creates the bucket with all of
+//! the template's settings in one transaction: either the bucket exists with
+//! every setting applied or it does not exist. Templates are validated when
+//! the file is loaded, so a bad template stops the server from starting
+//! rather than failing bucket creation later. Fields the store has no
+//! setting for are rejected.
+
+use crate::{
+    models::{bucket::Bucket, lifecycle::LifecycleRule, object_lock::ObjectLockConfiguration},
+    services::{
+        acl::CannedAcl,
+        auto_tagging::{AutoTagging, store_auto_tagging},
+        events::EventKind,
+        lifecycle,
+        naming_policy::{NamingPolicy, store_naming_policy},
+        object_lock,
+        scanner::ScanMode,
+        storage_service::{StorageError, StorageResult, StorageService, is_unique_violation},
+    },
+};
+use chrono::Utc;
+use serde::{Deserialize, Serialize};
+use std::{collections::BTreeMap, sync::Arc};
+use tokio::fs;
+use tracing::info;
+use uuid::Uuid;
+
+/// Settings a bucket is created with.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct BucketTemplate {
+    #[serde(default)]
+    pub versioning: bool,
+    /// Enables Object Lock (requires `versioning`).
+    pub object_lock: Option<ObjectLockConfiguration>,
+    #[serde(default)]
+    pub lifecycle: Vec<LifecycleRule>,
+    pub cache_control: Option<String>,
+    pub expires_secs: Option<u32>,
+    pub default_acl: Option<String>,
+    #[serde(default)]
+    pub enforce_bucket_owner_full_control: bool,
+    /// `inline` or `async` content scanning of uploads (see `scanner`).
+    pub scan_uploads: Option<String>,
+    /// Constraints on the keys written (see `naming_policy`).
+    pub naming_policy: Option<NamingPolicy>,
+    /// Rules tagging uploads (see `auto_tagging`).
+    pub auto_tagging: Option<AutoTagging>,
+}
+
+impl BucketTemplate {
+    /// Check the settings the way the matching bucket operations would.
+    pub fn validate(&self) -> StorageResult<()> {
+        if !self.lifecycle.is_empty() {
+            lifecycle::validate_rules(&self.lifecycle)?;
+        }
+        if let Some(config) = &self.object_lock {
+            if !self.versioning {
+                return Err(StorageError::InvalidObjectLock(
+                    "Object Lock requires versioning".into(),
+                ));
+            }
+            if let Some(default) = &config.default_retention {
+                object_lock::validate_default_retention(default)?;
+            }
+        }
+        if let Some(acl) = self.default_acl.as_deref() {
+            CannedAcl::parse(acl)?;
+        }
+        if let Some(mode) = self.scan_uploads.as_deref() {
+            ScanMode::parse(mode)?;
+        }
+        if let Some(policy) = &self.naming_policy {
+            policy.clone().validate()?;
+        }
+        if let Some(rules) = &self.auto_tagging {
+            rules.clone().validate()?;
+        }
+        Ok(())
+    }
+}
+
+/// Templates by name.
+#[derive(Debug, Clone, Default)]
+pub struct BucketTemplates(Arc<BTreeMap<String, BucketTemplate>>);
+
+impl BucketTemplates {
+    /// Parse and validate a JSON object of templates.
+    pub fn from_json(text: &str) -> Result<Self, String> {
+        let templates: BTreeMap<String, BucketTemplate> =
+            serde_json::from_str(text).map_err(|err| err.to_string())?;
+        for (name, template) in &templates {
+            template
+                .validate()
+                .map_err(|err| format!("template `{}`: {}", name, err))?;
+        }
+        Ok(BucketTemplates(Arc::new(templates)))
+    }
+
+    pub fn get(&self, name: &str) -> Option<&BucketTemplate> {
+        self.0.get(name)
+    }
+
+    pub fn all(&self) -> &BTreeMap<String, BucketTemplate> {
+        &self.0
+    }
+}
+
+impl StorageService {
+    /// Create a bucket with the settings of the template `template`.
+    pub async fn create_bucket_from_template(
+        &self,
+        name: &str,
+        region: String,
+        template: &str,
+    ) -> StorageResult<Bucket> {
+        let settings = self
+            .options
+            .bucket_templates
+            .get(template)
+            .ok_or_else(|| {
+                StorageError::InvalidContent(format!("no bucket template named `{}`", template))
+            })?
+            .clone();
+        self.ensure_bucket_name_safe(name)?;
+        let region = region.to_lowercase();
+        self.ensure_region_valid(&region)?;
+        self.ensure_transitions_supported(&settings.lifecycle)?;
+        if settings.scan_uploads.is_some() {
+            self.ensure_scanner_configured()?;
+        }
+        for prefix in settings
+            .lifecycle
+            .iter()
+            .filter_map(|r| r.prefix.as_deref())
+        {
+            if !prefix.is_empty() {
+                self.ensure_key_safe(prefix)?;
+            }
+        }
+        fs::create_dir_all(self.bucket_root(name)).await?;
+
+        let bucket = Bucket {
+            id: Uuid::new_v4(),
+            name: name.to_string(),
+            owner_id: Uuid::new_v4(),
+            region,
+            created_at: Utc::now(),
+            versioning_enabled: settings.versioning,
+            read_only: false,
+            cache_control: settings.cache_control.clone(),
+            expires_secs: settings
+                .expires_secs
+                .filter(|&secs| secs > 0)
+                .map(i64::from),
+            default_acl: settings.default_acl.clone(),
+            enforce_bucket_owner_full_control: settings.enforce_bucket_owner_full_control,
+            scan_uploads: settings.scan_uploads.clone(),
+            encrypt_metadata: false,
+        };
+        let mut tx = self.db.begin().await?;
+        let inserted = sqlx::query(
+            "INSERT INTO buckets (
+                 id, name, owner_id, region, created_at, versioning_enabled, cache_control,
+                 expires_secs, default_acl, enforce_bucket_owner_full_control, scan_uploads
+             ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(bucket.id)
+        .bind(&bucket.name)
+        .bind(bucket.owner_id)
+        .bind(&bucket.region)
+        .bind(bucket.created_at)
+        .bind(bucket.versioning_enabled)
+        .bind(&bucket.cache_control)
+        .bind(bucket.expires_secs)
+        .bind(&bucket.default_acl)
+        .bind(bucket.enforce_bucket_owner_full_control)
+        .bind(&bucket.scan_uploads)
+        .execute(&mut *tx)
+        .await;
+        match inserted {
+            Ok(_) => {}
+            Err(err) if is_unique_violation(&err) => {
+                return Err(StorageError::BucketAlreadyExists(name.to_string()));
+            }
+            Err(err) => return Err(err.into()),
+        }
+        lifecycle::insert_rules(&mut tx, bucket.id, &settings.lifecycle).await?;
+        if let Some(config) = &settings.object_lock {
+            object_lock::store_configuration(&mut tx, bucket.id, config).await?;
+        }
+        if let Some(policy) = settings.naming_policy {
+            store_naming_policy(&mut tx, bucket.id, &policy.validate()?).await?;
+        }
+        if let Some(rules) = settings.auto_tagging {
+            store_auto_tagging(&mut tx, bucket.id, &rules.validate()?).await?;
+        }
+        tx.commit().await?;
+
+        info!("created bucket `{}` from template `{}`", name, template);
+        self.events.bucket(
+            EventKind::BucketCreated,
+            name,
+            Some(format!("template={}", template)),
+        );
+        Ok(bucket)
+    }
+}
@@ -0,0 +1,300 @@
+//! Per-bucket change feed.
+//!
+//! Every write and delete of an object appends a row to `bucket_changes`,
+//! so indexers and caches can follow a bucket with
+//! `GET /{bucket}?changes&since=<cursor>` instead of listing it over and
+//! over; `prefix` limits the feed to keys under it. Each change carries a cursor (its position in the feed); passing
+//! the last one seen as `since` returns what happened after it, oldest
+//! first. Without `since` the feed starts at the oldest change kept.
+//!
+//! - `created`: a key without a live object got one (PUT, POST, copy,
+//!   completed multipart upload, undelete, recovery, snapshot restore);
+//! - `updated`: a live object was replaced, or its metadata was;
+//! - `deleted`: a key was deleted (a delete marker, when `version_id` is
+//!   set on a versioned key), or one version of it was removed.
+//!
+//! Writes record their change in the transaction that makes them visible
+//! where they have one; the others record it right after and only log a
+//! failure, since the object has changed either way.
+//!
+//! Changes older than the retention (`--changes-retention-secs`) are pruned
+//! by `spawn_change_pruner`. A cursor from before the pruned part of a
+//! bucket's feed is refused with `ChangeCursorExpired` (410): the client may
+//! have missed changes and has to resync with a full listing.
+
+use crate::{
+    models::{bucket::Bucket, object::Object},
+    services::storage_service::{StorageError, StorageResult, StorageService},
+};
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use serde::Serialize;
+use sqlx::{Executor, Sqlite};
+use std::time::Duration;
+use tokio::task::JoinHandle;
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+/// How often the pruner drops changes past the retention.
+pub const CHANGES_PRUNE_TICK: Duration = Duration::from_secs(600);
+
+/// Most changes returned per request.
+pub const MAX_CHANGES: usize = 1000;
+
+/// What happened to a key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ChangeKind {
+    Created,
+    Updated,
+    Deleted,
+}
+
+impl ChangeKind {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ChangeKind::Created => "created",
+            ChangeKind::Updated => "updated",
+            ChangeKind::Deleted => "deleted",
+        }
+    }
+}
+
+/// A change about to be recorded.
+#[derive(Debug, Clone)]
+pub(crate) struct NewChange<'a> {
+    pub kind: ChangeKind,
+    pub key: &'a str,
+    pub version_id: Option<&'a str>,
+    pub etag: Option<&'a str>,
+    pub size_bytes: Option<i64>,
+}
+
+impl<'a> NewChange<'a> {
+    /// `object` was written (or, for a delete marker, deleted).
+    pub fn of(kind: ChangeKind, object: &'a Object) -> Self {
+        Self {
+            kind,
+            key: &object.key,
+            version_id: object.version_id.as_deref(),
+            etag: object.etag.as_deref(),
+            size_bytes: (!object.is_deleted).then_some(object.size_bytes),
+        }
+    }
+
+    /// `key` (or the given version of it) was deleted.
+    pub fn deleted(key: &'a str, version_id: Option<&'a str>) -> Self {
+        Self {
+            kind: ChangeKind::Deleted,
+            key,
+            version_id,
+            etag: None,
+            size_bytes: None,
+        }
+    }
+}
+
+/// One entry of the feed.
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct Change {
+    /// Pass as `since` to get the changes after this one.
+    #[serde(serialize_with = "as_cursor")]
+    #[sqlx(rename = "seq")]
+    pub cursor: i64,
+    pub event: String,
+    pub key: String,
+    pub version_id: Option<String>,
+    pub etag: Option<String>,
+    pub size_bytes: Option<i64>,
+    pub occurred_at: DateTime<Utc>,
+}
+
+/// Body of `GET /{bucket}?changes`.
+#[derive(Debug, Clone, Serialize)]
+pub struct ChangesPage {
+    pub bucket: String,
+    #[serde(serialize_with = "as_optional_cursor")]
+    pub since: Option<i64>,
+    pub changes: Vec<Change>,
+    pub is_truncated: bool,
+    /// Pass as `since` on the next request: the last change returned, or
+    /// `since` again when there was none.
+    #[serde(serialize_with = "as_optional_cursor")]
+    pub next_cursor: Option<i64>,
+}
+
+/// Cursors are opaque strings to clients.
+fn as_cursor<S: serde::Serializer>(seq: &i64, serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_str(seq)
+}
+
+fn as_optional_cursor<S: serde::Serializer>(
+    seq: &Option<i64>,
+    serializer: S,
+) -> Result<S::Ok, S::Error> {
+    match seq {
+        Some(seq) => serializer.collect_str(seq),
+        None => serializer.serialize_none(),
+    }
+}
+
+/// Parse a `since` cursor.
+pub fn parse_cursor(value: &str) -> StorageResult<i64> {
+    value
+        .parse::<i64>()
+        .ok()
+        .filter(|seq| *seq >= 0)
+        .ok_or_else(|| StorageError::InvalidChangeCursor(value.to_string()))
+}
+
+/// Append `change` to the feed of bucket `bucket_id` through `executor`
+/// (the transaction of the write, where there is one).
+pub(crate) async fn insert_change<'e, E>(
+    executor: E,
+    bucket_id: Uuid,
+    change: &NewChange<'_>,
+) -> Result<(), sqlx::Error>
+where
+    E: Executor<'e, Database = Sqlite>,
+{
+    sqlx::query(
+        "INSERT INTO bucket_changes
+            (bucket_id, key, event, version_id, etag, size_bytes, occurred_at)
+         VALUES (?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(bucket_id)
+    .bind(change.key)
+    .bind(change.kind.as_str())
+    .bind(change.version_id)
+    .bind(change.etag)
+    .bind(change.size_bytes)
+    .bind(Utc::now())
+    .execute(executor)
+    .await?;
+    Ok(())
+}
+
+impl StorageService {
+    /// Record a change of a write that is already committed; a failure is
+    /// only logged.
+    pub(crate) async fn record_change(&self, bucket_id: Uuid, change: NewChange<'_>) {
+        if let Err(err) = insert_change(&*self.db, bucket_id, &change).await {
+            warn!(
+                "could not record {} change of {}: {}",
+                change.kind.as_str(),
+                change.key,
+                err
+            );
+        }
+    }
+
+    /// Whether writing `key` now creates it or updates a live object. The
+    /// caller holds the key's lock.
+    pub(crate) async fn write_kind(&self, bucket: &Bucket, key: &str) -> StorageResult<ChangeKind> {
+        let live: Option<(i64,)> = sqlx::query_as(
+            "SELECT 1 FROM objects WHERE bucket_id = ? AND key = ? AND is_deleted = 0",
+        )
+        .bind(bucket.id)
+        .bind(key)
+        .fetch_optional(&*self.db)
+        .await?;
+        Ok(match live {
+            Some(_) => ChangeKind::Updated,
+            None => ChangeKind::Created,
+        })
+    }
+
+    /// Changes of `bucket` to keys under `prefix` after cursor `since`
+    /// (from the oldest kept without one), at most `max` of them.
+    pub async fn list_changes(
+        &self,
+        bucket: &str,
+        prefix: Option<&str>,
+        since: Option<i64>,
+        max: usize,
+    ) -> StorageResult<ChangesPage> {
+        let bucket_rec = self.fetch_bucket(bucket).await?;
+        let max = max.clamp(1, MAX_CHANGES);
+        if let Some(since) = since {
+            let pruned: Option<(i64,)> = sqlx::query_as(
+                "SELECT pruned_through FROM bucket_changes_pruned WHERE bucket_id = ?",
+            )
+            .bind(bucket_rec.id)
+            .fetch_optional(&*self.db)
+            .await?;
+            if let Some((pruned_through,)) = pruned
+                && since < pruned_through
+            {
+                return Err(StorageError::ChangeCursorExpired {
+                    bucket: bucket_rec.name,
+                    cursor: since.to_string(),
+                });
+            }
+        }
+        let mut changes = sqlx::query_as::<_, Change>(
+            "SELECT seq, event, key, version_id, etag, size_bytes, occurred_at
+             FROM bucket_changes WHERE bucket_id = ? AND seq > ?
+               AND substr(key, 1, length(?)) = ?
+             ORDER BY seq LIMIT ?",
+        )
+        .bind(bucket_rec.id)
+        .bind(since.unwrap_or(0))
+        .bind(prefix.unwrap_or_default())
+        .bind(prefix.unwrap_or_default())
+        .bind(max as i64 + 1)
+        .fetch_all(&*self.db)
+        .await?;
+        let is_truncated = changes.len() > max;
+        changes.truncate(max);
+        Ok(ChangesPage {
+            bucket: bucket_rec.name,
+            since,
+            next_cursor: changes.last().map(|change| change.cursor).or(since),
+            changes,
+            is_truncated,
+        })
+    }
+
+    /// Drop changes recorded more than `retention` ago, remembering how far
+    /// each bucket's feed was pruned. Returns the number dropped.
+    pub async fn prune_changes(&self, retention: Duration) -> StorageResult<u64> {
+        let cutoff =
+            Utc::now() - ChronoDuration::from_std(retention).unwrap_or(ChronoDuration::MAX);
+        let mut tx = self.db.begin().await?;
+        sqlx::query(
+            "INSERT INTO bucket_changes_pruned (bucket_id, pruned_through)
+             SELECT bucket_id, MAX(seq) FROM bucket_changes
+             WHERE occurred_at < ? GROUP BY bucket_id
+             ON CONFLICT(bucket_id) DO UPDATE SET
+                pruned_through = MAX(pruned_through, excluded.pruned_through)",
+        )
+        .bind(cutoff)
+        .execute(&mut *tx)
+        .await?;
+        let removed = sqlx::query("DELETE FROM bucket_changes WHERE occurred_at < ?")
+            .bind(cutoff)
+            .execute(&mut *tx)
+            .await?
+            .rows_affected();
+        tx.commit().await?;
+        if removed > 0 {
+            debug!("pruned {} changes older than {}", removed, cutoff);
+        }
+        Ok(removed)
+    }
+}
+
+/// Prune changes older than `retention` every `period`.
+pub fn spawn_change_pruner(
+    service: StorageService,
+    retention: Duration,
+    period: Duration,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(period);
+        loop {
+            ticker.tick().await;
+            if let Err(err) = service.prune_changes(retention).await {
+                warn!("change feed pruning failed: {}", err);
+            }
+        }
+    })
+}
@@ -0,0 +1,237 @@
+//! Integrity checks on upload bodies.
+//!
+//! A PUT (or UploadPart) may carry `Content-MD5`, `x-amz-checksum-sha256`
+//! and `x-amz-checksum-crc32c`, each the base64 digest of the body as sent.
+//! `verify_stream` hashes the body on its way to disk, before any
+//! Content-Encoding decoding, and fails the stream at its end when a digest
+//! differs, so the staged file is discarded and nothing is committed.
+//! A SigV4 `x-amz-content-sha256` that is a digest (not `UNSIGNED-PAYLOAD`
+//! or a `STREAMING-*` variant) is checked the same way, as hex, and a
+//! mismatch is refused as `XAmzContentSHA256Mismatch`.
+//!
+//! Verified SHA-256 and CRC32C values are stored on the object when they
+//! describe the stored bytes, i.e. unless the body was decoded.
+//!
+//! Downloads can be checked against those stored values the same way
+//! (`x-amz-checksum-mode: ENABLED`, or every download with
+//! `StorageOptions::verify_read_checksums`): a payload mapped into memory is
+//! checked before the response starts and refused with 500, a streamed one
+//! fails at its end, which aborts the response so the client never takes
+//! corrupt bytes for a complete object.
+
+use crate::{
+    models::object::Object,
+    services::{
+        aws_chunked::hex,
+        content_encoding::ByteStream,
+        storage_service::{StorageError, StorageResult},
+    },
+};
+use base64::{Engine as _, engine::general_purpose};
+use bytes::Bytes;
+use futures::{Stream, StreamExt, stream};
+use sha2::{Digest, Sha256};
+use std::{fmt, io};
+
+/// Digests the client sent with an upload, base64-encoded.
+#[derive(Clone, Debug, Default)]
+pub struct ExpectedChecksums {
+    /// `Content-MD5`.
+    pub md5: Option<String>,
+    /// `x-amz-checksum-sha256`.
+    pub sha256: Option<String>,
+    /// `x-amz-checksum-crc32c`.
+    pub crc32c: Option<String>,
+    /// `x-amz-content-sha256` when it is a digest, hex-encoded.
+    pub content_sha256: Option<String>,
+}
+
+impl ExpectedChecksums {
+    pub fn is_empty(&self) -> bool {
+        self.md5.is_none()
+            && self.sha256.is_none()
+            && self.crc32c.is_none()
+            && self.content_sha256.is_none()
+    }
+
+    /// Reject digests that are not base64 of the algorithm's length.
+    pub fn validate(&self) -> StorageResult<()> {
+        for (name, value, len) in [
+            ("Content-MD5", &self.md5, 16),
+            ("x-amz-checksum-sha256", &self.sha256, 32),
+            ("x-amz-checksum-crc32c", &self.crc32c, 4),
+        ] {
+            if let Some(value) = value
+                && general_purpose::STANDARD
+                    .decode(value)
+                    .map_or(true, |digest| digest.len() != len)
+            {
+                return Err(StorageError::InvalidDigest(format!(
+                    "{} `{}` is not a base64 {}-byte digest",
+                    name, value, len
+                )));
+            }
+        }
+        if let Some(value) = &self.content_sha256
+            && (value.len() != 64 || !value.bytes().all(|b| b.is_ascii_hexdigit()))
+        {
+            return Err(StorageError::InvalidDigest(format!(
+                "x-amz-content-sha256 `{}` is not a hex SHA-256 digest",
+                value
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// A body digest that differs from the one the client sent.
+#[derive(Debug)]
+pub struct ChecksumMismatch {
+    algorithm: &'static str,
+    expected: String,
+    computed: String,
+}
+
+impl fmt::Display for ChecksumMismatch {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(
+            f,
+            "{} of the body is {}, but {} was expected",
+            self.algorithm, self.computed, self.expected
+        )
+    }
+}
+
+impl std::error::Error for ChecksumMismatch {}
+
+impl ChecksumMismatch {
+    /// Whether the digest that differs is the SigV4 `x-amz-content-sha256`.
+    pub(crate) fn is_content_sha256(&self) -> bool {
+        self.algorithm == CONTENT_SHA256
+    }
+}
+
+/// Name of the SigV4 payload digest in a `ChecksumMismatch`.
+const CONTENT_SHA256: &str = "x-amz-content-sha256";
+
+/// Stored digests of `object`'s payload, to check a download against.
+pub(crate) fn stored_checksums(object: &Object) -> ExpectedChecksums {
+    ExpectedChecksums {
+        md5: None,
+        sha256: object.checksum_sha256.clone(),
+        crc32c: object.checksum_crc32c.clone(),
+        content_sha256: None,
+    }
+}
+
+/// Check a payload held in memory against `expected`.
+pub(crate) fn verify_bytes(
+    bytes: &[u8],
+    expected: ExpectedChecksums,
+) -> Result<(), ChecksumMismatch> {
+    if expected.is_empty() {
+        return Ok(());
+    }
+    let mut digests = Digests::new(expected);
+    digests.update(bytes);
+    digests.verify()
+}
+
+/// The mismatch carried by an error from a `verify_stream` stream, if any.
+pub(crate) fn mismatch(err: &io::Error) -> Option<&ChecksumMismatch> {
+    err.get_ref()?.downcast_ref()
+}
+
+/// Running digests for the checksums that were sent.
+struct Digests {
+    expected: ExpectedChecksums,
+    md5: Option<md5::Context>,
+    sha256: Option<Sha256>,
+    crc32c: Option<u32>,
+}
+
+impl Digests {
+    fn new(expected: ExpectedChecksums) -> Self {
+        Self {
+            md5: expected.md5.is_some().then(md5::Context::new),
+            sha256: (expected.sha256.is_some() || expected.content_sha256.is_some())
+                .then(Sha256::new),
+            crc32c: expected.crc32c.is_some().then_some(0),
+            expected,
+        }
+    }
+
+    fn update(&mut self, chunk: &[u8]) {
+        if let Some(md5) = self.md5.as_mut() {
+            md5.consume(chunk);
+        }
+        if let Some(sha256) = self.sha256.as_mut() {
+            sha256.update(chunk);
+        }
+        if let Some(crc) = self.crc32c.as_mut() {
+            *crc = crc32c::crc32c_append(*crc, chunk);
+        }
+    }
+
+    fn verify(self) -> Result<(), ChecksumMismatch> {
+        let computed = [
+            ("Content-MD5", self.md5.map(|md5| md5.compute().0.to_vec())),
+            ("SHA-256", self.sha256.map(|sha| sha.finalize().to_vec())),
+            ("CRC32C", self.crc32c.map(|crc| crc.to_be_bytes().to_vec())),
+        ];
+        if let (Some(computed), Some(expected)) = (&computed[1].1, self.expected.content_sha256) {
+            let computed = hex(computed);
+            if !computed.eq_ignore_ascii_case(&expected) {
+                return Err(ChecksumMismatch {
+                    algorithm: CONTENT_SHA256,
+                    expected,
+                    computed,
+                });
+            }
+        }
+        let expected = [
+            self.expected.md5,
+            self.expected.sha256,
+            self.expected.crc32c,
+        ];
+        for ((algorithm, computed), expected) in computed.into_iter().zip(expected) {
+            if let (Some(computed), Some(expected)) = (computed, expected) {
+                let computed = general_purpose::STANDARD.encode(computed);
+                if computed != expected {
+                    return Err(ChecksumMismatch {
+                        algorithm,
+                        expected,
+                        computed,
+                    });
+                }
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Pass `stream` through unchanged, failing it at the end when a digest in
+/// `expected` does not match what went by.
+pub(crate) fn verify_stream<S>(stream: S, expected: ExpectedChecksums) -> ByteStream
+where
+    S: Stream<Item = io::Result<Bytes>> + Send + 'static,
+{
+    if expected.is_empty() {
+        return Box::pin(stream);
+    }
+    let state = (Box::pin(stream), Some(Digests::new(expected)));
+    Box::pin(stream::unfold(state, |(mut stream, digests)| async move {
+        let mut digests = digests?;
+        match stream.next().await {
+            Some(Ok(chunk)) => {
+                digests.update(&chunk);
+                Some((Ok(chunk), (stream, Some(digests))))
+            }
+            Some(Err(err)) => Some((Err(err), (stream, None))),
+            None => match digests.verify() {
+                Ok(()) => None,
+                Err(mismatch) => Some((Err(io::Error::other(mismatch)), (stream, None))),
+            },
+        }
+    }))
+}
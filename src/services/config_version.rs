@@ -0,0 +1,157 @@
+//! Versions of bucket configuration resources.
+//!
+//! Every configuration document of a bucket (lifecycle, notification,
+//! Object Lock, versioning and the admin policies) has a version that
+//! each successful update bumps. Its ETag is derived from the bucket id,
+//! the resource and the version, so it changes with every update, differs
+//! between a bucket and a re-created one of the same name, and is the
+//! same on every read in between. `middleware::config_etag` serves it and
+//! checks `If-Match` against it, holding the resource's lock from the
+//! check until the version is bumped, so two admins updating from the
+//! same read cannot both succeed.
+//!
+//! A resource never changed since its bucket was created is at version 0,
+//! including configuration a bucket template set.
+
+use crate::services::{
+    key_lock::KeyGuard,
+    storage_service::{StorageResult, StorageService},
+};
+use std::fmt;
+use uuid::Uuid;
+
+/// A versioned configuration resource of a bucket.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+pub enum ConfigResource {
+    Lifecycle,
+    Notification,
+    ObjectLock,
+    Versioning,
+    Quota,
+    NamingPolicy,
+    AutoTagging,
+    Placement,
+    Publishers,
+    Replication,
+    SnapshotPolicy,
+}
+
+impl ConfigResource {
+    /// Resources addressed by a flag on the bucket route (`/{bucket}?lifecycle`).
+    pub const BUCKET_FLAGS: [ConfigResource; 4] = [
+        ConfigResource::Lifecycle,
+        ConfigResource::Notification,
+        ConfigResource::ObjectLock,
+        ConfigResource::Versioning,
+    ];
+
+    /// Resources below `/admin/buckets/{bucket}/`.
+    pub const ADMIN: [ConfigResource; 7] = [
+        ConfigResource::Quota,
+        ConfigResource::NamingPolicy,
+        ConfigResource::AutoTagging,
+        ConfigResource::Placement,
+        ConfigResource::Publishers,
+        ConfigResource::Replication,
+        ConfigResource::SnapshotPolicy,
+    ];
+
+    /// The query flag or admin path segment naming the resource.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ConfigResource::Lifecycle => "lifecycle",
+            ConfigResource::Notification => "notification",
+            ConfigResource::ObjectLock => "object-lock",
+            ConfigResource::Versioning => "versioning",
+            ConfigResource::Quota => "quota",
+            ConfigResource::NamingPolicy => "naming-policy",
+            ConfigResource::AutoTagging => "auto-tagging",
+            ConfigResource::Placement => "placement",
+            ConfigResource::Publishers => "publishers",
+            ConfigResource::Replication => "replication",
+            ConfigResource::SnapshotPolicy => "snapshot-policy",
+        }
+    }
+}
+
+impl fmt::Display for ConfigResource {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(self.as_str())
+    }
+}
+
+/// ETag (unquoted) of `version` of `resource` of the bucket `bucket_id`.
+pub fn config_etag(bucket_id: Uuid, resource: ConfigResource, version: i64) -> String {
+    format!(
+        "{:x}",
+        md5::compute(format!("{}/{}/{}", bucket_id, resource, version))
+    )
+}
+
+/// Exclusive access to a configuration resource while it is updated.
+pub struct ConfigUpdate {
+    _guard: KeyGuard,
+    bucket_id: Uuid,
+    resource: ConfigResource,
+    /// ETag of the version being replaced.
+    pub etag: String,
+}
+
+impl StorageService {
+    /// Current version of `resource` of the bucket `bucket_id`.
+    pub async fn config_version(
+        &self,
+        bucket_id: Uuid,
+        resource: ConfigResource,
+    ) -> StorageResult<i64> {
+        Ok(sqlx::query_scalar(
+            "SELECT version FROM bucket_config_versions WHERE bucket_id = ? AND resource = ?",
+        )
+        .bind(bucket_id)
+        .bind(resource.as_str())
+        .fetch_optional(&*self.db)
+        .await?
+        .unwrap_or(0))
+    }
+
+    /// ETag of the current version of `resource` of the bucket `bucket_id`.
+    pub async fn current_config_etag(
+        &self,
+        bucket_id: Uuid,
+        resource: ConfigResource,
+    ) -> StorageResult<String> {
+        let version = self.config_version(bucket_id, resource).await?;
+        Ok(config_etag(bucket_id, resource, version))
+    }
+
+    /// Lock `resource` of the bucket `bucket_id` for an update; the lock is
+    /// held until the returned update is committed or dropped.
+    pub async fn begin_config_update(
+        &self,
+        bucket_id: Uuid,
+        resource: ConfigResource,
+    ) -> StorageResult<ConfigUpdate> {
+        let guard = self.config_locks.lock(bucket_id, resource.as_str()).await;
+        let etag = self.current_config_etag(bucket_id, resource).await?;
+        Ok(ConfigUpdate {
+            _guard: guard,
+            bucket_id,
+            resource,
+            etag,
+        })
+    }
+
+    /// Record that `update` changed its resource; returns the new ETag.
+    pub async fn commit_config_update(&self, update: ConfigUpdate) -> StorageResult<String> {
+        let version: i64 = sqlx::query_scalar(
+            "INSERT INTO bucket_config_versions (bucket_id, resource, version) VALUES (?, ?, 1)
+             ON CONFLICT(bucket_id, resource) DO UPDATE SET version = version + 1
+             RETURNING version",
+        )
+        .bind(update.bucket_id)
+        .bind(update.resource.as_str())
+        .fetch_one(&*self.db)
+        .await?;
+        Ok(config_etag(update.bucket_id, update.resource, version))
+    }
+}
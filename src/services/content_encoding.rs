@@ -0,0 +1,76 @@
+//! Content-Encoding handling for uploads.
+//!
+//! By default the body is stored exactly as sent and the (normalised)
+//! `Content-Encoding` is recorded so GET/HEAD can replay it. With
+//! `StorageOptions::decode_content_encoding`, `gzip` and `deflate` bodies are
+//! decoded to identity while streaming, so the stored payload, its size, ETag
+//! and any byte ranges refer to the plain content.
+
+use async_compression::tokio::bufread::{GzipDecoder, ZlibDecoder};
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+use std::{io, pin::Pin};
+use tokio_util::io::{ReaderStream, StreamReader};
+
+/// Boxed byte stream as consumed by the upload path.
+pub type ByteStream = Pin<Box<dyn Stream<Item = io::Result<Bytes>> + Send>>;
+
+/// Codings that describe message framing rather than the stored
+/// representation, and must never be persisted.
+const TRANSPORT_CODINGS: [&str; 2] = ["identity", "aws-chunked"];
+
+/// Split a `Content-Encoding` header into lowercase codings, in the order
+/// they were applied, dropping transport-only codings.
+pub fn parse_codings(header: Option<&str>) -> Vec<String> {
+    header
+        .unwrap_or("")
+        .split(',')
+        .map(|c| c.trim().to_ascii_lowercase())
+        .filter(|c| !c.is_empty() && !TRANSPORT_CODINGS.contains(&c.as_str()))
+        .collect()
+}
+
+/// Wrap `stream` according to the upload's `Content-Encoding`.
+///
+/// Returns the stream to persist and the encoding to record on the object
+/// (`None` when the stored bytes are identity-encoded).
+pub fn prepare_upload_stream<S>(
+    stream: S,
+    header: Option<&str>,
+    decode: bool,
+) -> (ByteStream, Option<String>)
+where
+    S: Stream<Item = io::Result<Bytes>> + Send + 'static,
+{
+    let codings = parse_codings(header);
+    if codings.is_empty() {
+        return (Box::pin(stream), None);
+    }
+
+    let decodable = codings
+        .iter()
+        .all(|c| matches!(c.as_str(), "gzip" | "x-gzip" | "deflate"));
+    if !decode || !decodable {
+        return (Box::pin(stream), Some(codings.join(", ")));
+    }
+
+    // Codings are listed in the order applied, so undo them right-to-left.
+    let mut decoded: ByteStream = Box::pin(stream);
+    for coding in codings.iter().rev() {
+        let reader = StreamReader::new(decoded);
+        decoded = match coding.as_str() {
+            "deflate" => Box::pin(ReaderStream::new(ZlibDecoder::new(reader))),
+            _ => Box::pin(ReaderStream::new(GzipDecoder::new(reader))),
+        };
+    }
+    let decoded = decoded.map(|chunk| {
+        chunk.map_err(|err| match err.kind() {
+            io::ErrorKind::InvalidData | io::ErrorKind::UnexpectedEof => io::Error::new(
+                io::ErrorKind::InvalidData,
+                format!("could not decode request body: {}", err),
+            ),
+            _ => err,
+        })
+    });
+    (Box::pin(decoded), None)
+}
@@ -0,0 +1,206 @@
+//! Content-addressed payload deduplication.
+//!
+//! With `dedup` on, a committed payload is stored once per distinct content
+//! under `base_path/.cas/{aa}/{bb}/{sha256}` and every key holding that
+//! content gets a hard link to it at its usual payload path. Everything that
+//! reads, archives, recycles, trashes or snapshots payloads keeps working on
+//! those paths unchanged: payloads are never modified in place (uploads
+//! always write a new file and rename it over the old one), so sharing an
+//! inode is safe.
+//!
+//! `content_blobs` counts the links to each blob besides its own. Removing a
+//! key only unlinks its path; the collector (`gc_content_blobs`, run every
+//! `DEDUP_GC_TICK`) refreshes the counts from the filesystem and deletes the
+//! blobs no key, version, recycled or trashed copy or snapshot links to any
+//! more. Link counts are only available on Unix; elsewhere blobs are kept.
+//!
+//! A blob that cannot be linked (e.g. `.cas` on another device) is copied,
+//! which stores that key's payload without deduplication.
+
+use crate::services::{
+    snapshot::link_or_copy,
+    staging::StagingFile,
+    storage_service::{StorageResult, StorageService, side_dirs},
+};
+use chrono::Utc;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::{
+    fs::Metadata,
+    io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use tokio::{fs, io::AsyncReadExt, task::JoinHandle};
+use tracing::{debug, info, warn};
+
+/// Directory (below `base_path`) holding shared blobs.
+const CAS_DIR: &str = side_dirs::CAS;
+
+/// How often `main` collects unreferenced blobs.
+pub const DEDUP_GC_TICK: Duration = Duration::from_secs(600);
+
+/// Outcome of one `gc_content_blobs` pass.
+#[derive(Debug, Default, Clone, Copy, Serialize)]
+pub struct DedupGcSummary {
+    pub blobs_removed: u64,
+    /// Bytes of blobs removed with them.
+    pub freed_bytes: u64,
+}
+
+impl StorageService {
+    fn blob_path(&self, digest: &str) -> PathBuf {
+        let mut path = self.base_path.join(CAS_DIR);
+        path.push(&digest[..2]);
+        path.push(&digest[2..4]);
+        path.push(digest);
+        path
+    }
+
+    /// Move a staged payload to `dest`. With a `digest` (dedup on), `dest`
+    /// becomes a link to the blob of that content, which the staged file
+    /// becomes if no such blob exists yet.
+    pub(crate) async fn persist_payload(
+        &self,
+        staged: StagingFile,
+        digest: Option<&str>,
+        dest: &Path,
+    ) -> io::Result<()> {
+        let Some(digest) = digest else {
+            return staged.persist(dest).await;
+        };
+        let blob = self.blob_path(digest);
+        let dir = dest
+            .parent()
+            .ok_or_else(|| io::Error::other("payload path missing parent directory"))?;
+        let link = self.temp_path(dir);
+        match link_or_copy(&blob, &link).await {
+            Ok(()) => staged.discard().await?,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                if let Some(parent) = blob.parent() {
+                    fs::create_dir_all(parent).await?;
+                }
+                staged.persist(&blob).await?;
+                link_or_copy(&blob, &link).await?;
+            }
+            Err(err) => return Err(err),
+        }
+        if let Err(err) = fs::rename(&link, dest).await {
+            let _ = fs::remove_file(&link).await;
+            return Err(err);
+        }
+        if let Err(err) = self.count_blob_reference(digest, &blob).await {
+            debug!("could not count reference to blob {}: {}", digest, err);
+        }
+        Ok(())
+    }
+
+    /// Hex SHA-256 of the payload at `path`, naming its blob, for payloads
+    /// persisted from a file rather than staged from a request.
+    pub(crate) async fn content_digest(&self, path: &Path) -> io::Result<String> {
+        let mut file = fs::File::open(path).await?;
+        let mut sha256 = Sha256::new();
+        let mut buf = vec![0u8; 64 * 1024];
+        loop {
+            let read = file.read(&mut buf).await?;
+            if read == 0 {
+                break;
+            }
+            sha256.update(&buf[..read]);
+        }
+        Ok(format!("{:x}", sha256.finalize()))
+    }
+
+    /// Record the blob and its current reference count.
+    async fn count_blob_reference(&self, digest: &str, blob: &Path) -> StorageResult<()> {
+        let metadata = fs::metadata(blob).await?;
+        sqlx::query(
+            "INSERT INTO content_blobs (sha256, size_bytes, refcount, created_at)
+             VALUES (?, ?, ?, ?)
+             ON CONFLICT(sha256) DO UPDATE SET refcount = excluded.refcount",
+        )
+        .bind(digest)
+        .bind(metadata.len() as i64)
+        .bind(references(&metadata).unwrap_or(1) as i64)
+        .bind(Utc::now())
+        .execute(&*self.db)
+        .await?;
+        Ok(())
+    }
+
+    /// Refresh blob reference counts and delete blobs nothing links to.
+    pub async fn gc_content_blobs(&self) -> StorageResult<DedupGcSummary> {
+        let blobs: Vec<(String,)> = sqlx::query_as("SELECT sha256 FROM content_blobs")
+            .fetch_all(&*self.db)
+            .await?;
+        let mut summary = DedupGcSummary::default();
+        for (digest,) in blobs {
+            let blob = self.blob_path(&digest);
+            let metadata = match fs::metadata(&blob).await {
+                Ok(metadata) => Some(metadata),
+                Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+                Err(err) => return Err(err.into()),
+            };
+            let refs = match metadata.as_ref().map(references) {
+                // Link counts unknown: keep the blob.
+                Some(None) => continue,
+                Some(Some(refs)) => refs,
+                None => 0,
+            };
+            if refs > 0 {
+                sqlx::query("UPDATE content_blobs SET refcount = ? WHERE sha256 = ?")
+                    .bind(refs as i64)
+                    .bind(&digest)
+                    .execute(&*self.db)
+                    .await?;
+                continue;
+            }
+            if let Some(metadata) = metadata {
+                match fs::remove_file(&blob).await {
+                    Ok(()) => {
+                        summary.blobs_removed += 1;
+                        summary.freed_bytes += metadata.len();
+                    }
+                    Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                    Err(err) => return Err(err.into()),
+                }
+            }
+            sqlx::query("DELETE FROM content_blobs WHERE sha256 = ?")
+                .bind(&digest)
+                .execute(&*self.db)
+                .await?;
+        }
+        if summary.blobs_removed > 0 {
+            info!(
+                "removed {} unreferenced blobs ({} bytes)",
+                summary.blobs_removed, summary.freed_bytes
+            );
+        }
+        Ok(summary)
+    }
+}
+
+/// Links to a blob besides its own `.cas` entry, where link counts exist.
+#[cfg(unix)]
+fn references(metadata: &Metadata) -> Option<u64> {
+    use std::os::unix::fs::MetadataExt;
+    Some(metadata.nlink().saturating_sub(1))
+}
+
+#[cfg(not(unix))]
+fn references(_metadata: &Metadata) -> Option<u64> {
+    None
+}
+
+/// Spawn a background task that collects unreferenced blobs every `period`.
+pub fn spawn_dedup_gc(service: StorageService, period: Duration) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(period);
+        loop {
+            ticker.tick().await;
+            if let Err(err) = service.gc_content_blobs().await {
+                warn!("blob collector failed: {}", err);
+            }
+        }
+    })
+}
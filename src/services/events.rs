@@ -0,0 +1,135 @@
+//! Bucket activity notifications.
+//!
+//! Service operations that create, delete or reconfigure something publish a
+//! `BucketEvent` on an in-process broadcast queue. Subscribers (the
+//! `/admin/events` SSE stream) receive every event published after they
+//! subscribe; nothing is persisted, and a subscriber that falls more than
+//! `EVENT_BUFFER` events behind skips ahead and is told how many it missed.
+
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::sync::{
+    Arc,
+    atomic::{AtomicU64, Ordering},
+};
+use tokio::sync::broadcast;
+
+/// Events buffered per subscriber before it starts missing some.
+const EVENT_BUFFER: usize = 1024;
+
+/// What happened.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "kebab-case")]
+pub enum EventKind {
+    BucketCreated,
+    BucketDeleted,
+    /// A bucket setting or policy changed; `detail` says which.
+    BucketConfigChanged,
+    /// A key received new content (upload, multipart completion, recovery,
+    /// snapshot restore).
+    ObjectCreated,
+    ObjectDeleted,
+    /// The content scanner flagged a stored version (see `scanner`).
+    ObjectQuarantined,
+}
+
+impl EventKind {
+    /// Name used as the SSE `event:` field.
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            EventKind::BucketCreated => "bucket-created",
+            EventKind::BucketDeleted => "bucket-deleted",
+            EventKind::BucketConfigChanged => "bucket-config-changed",
+            EventKind::ObjectCreated => "object-created",
+            EventKind::ObjectDeleted => "object-deleted",
+            EventKind::ObjectQuarantined => "object-quarantined",
+        }
+    }
+}
+
+/// One notification.
+#[derive(Debug, Clone, Serialize)]
+pub struct BucketEvent {
+    /// Monotonic sequence number, unique for the life of the process.
+    pub seq: u64,
+    pub kind: EventKind,
+    pub bucket: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub size_bytes: Option<i64>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub etag: Option<String>,
+    /// Free-form description, e.g. the setting that changed.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub detail: Option<String>,
+    pub time: DateTime<Utc>,
+}
+
+/// Broadcast queue of bucket events.
+#[derive(Debug, Clone)]
+pub struct EventBus {
+    tx: broadcast::Sender<BucketEvent>,
+    seq: Arc<AtomicU64>,
+}
+
+impl Default for EventBus {
+    fn default() -> Self {
+        let (tx, _) = broadcast::channel(EVENT_BUFFER);
+        Self {
+            tx,
+            seq: Arc::new(AtomicU64::new(0)),
+        }
+    }
+}
+
+impl EventBus {
+    /// Receive every event published from now on.
+    pub fn subscribe(&self) -> broadcast::Receiver<BucketEvent> {
+        self.tx.subscribe()
+    }
+
+    /// Publish a bucket-level event.
+    pub(crate) fn bucket(&self, kind: EventKind, bucket: &str, detail: Option<String>) {
+        self.publish(kind, bucket, None, None, None, detail);
+    }
+
+    /// Publish an object-level event.
+    pub(crate) fn object(
+        &self,
+        kind: EventKind,
+        bucket: &str,
+        key: &str,
+        size_bytes: Option<i64>,
+        etag: Option<String>,
+    ) {
+        self.publish(kind, bucket, Some(key.to_string()), size_bytes, etag, None);
+    }
+
+    fn publish(
+        &self,
+        kind: EventKind,
+        bucket: &str,
+        key: Option<String>,
+        size_bytes: Option<i64>,
+        etag: Option<String>,
+        detail: Option<String>,
+    ) {
+        // Without subscribers there is nobody to tell; skip the allocation.
+        if self.tx.receiver_count() == 0 {
+            return;
+        }
+        let event = BucketEvent {
+            seq: self.seq.fetch_add(1, Ordering::Relaxed) + 1,
+            kind,
+            bucket: bucket.to_string(),
+            key,
+            size_bytes,
+            etag,
+            detail,
+            time: Utc::now(),
+        };
+        // Only fails when every subscriber went away in the meantime.
+        let _ = self.tx.send(event);
+    }
+}
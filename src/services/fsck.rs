@@ -0,0 +1,304 @@
+//! Reconciling the payload tree with the metadata database.
+//!
+//! A crash between staging a payload and upserting its row leaves `.tmp-*`
+//! files next to payloads, and a crash in a delete or a lost disk can leave
+//! rows whose file is gone or files no row points at. `fsck` walks
+//! `base_path` (and the bucket directories of other volumes, see
+//! `placement`) once and:
+//! - removes `.tmp-*` files older than a minimum age (anything younger may
+//!   belong to an upload still in flight), in the staging directory too when
+//!   one is configured;
+//! - reports live objects and noncurrent versions whose payload is missing;
+//! - reports payload files under bucket and version directories that no row
+//!   describes.
+//!
+//! Only temp files are deleted. Missing and orphaned payloads are logged and
+//! returned for an operator to look at. `main` runs a pass with no minimum age
+//! before serving, and `--fsck` runs one (keeping the last hour of temp files)
+//! and exits.
+
+use crate::services::{
+    staging::TEMP_PREFIX,
+    storage_service::{StorageResult, StorageService},
+    versioning::VERSIONS_DIR,
+};
+use serde::Serialize;
+use std::{
+    collections::{HashMap, HashSet},
+    io,
+    path::{Path, PathBuf},
+    time::{Duration, SystemTime},
+};
+use tokio::fs;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// Temp files `--fsck` leaves alone because a running server may still be
+/// writing them.
+pub const FSCK_TEMP_MIN_AGE: Duration = Duration::from_secs(3600);
+
+/// Outcome of one `fsck` pass.
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct FsckReport {
+    pub temp_files_removed: u64,
+    /// Bytes of temp files removed with them.
+    pub temp_bytes_removed: u64,
+    /// Rows whose payload file does not exist.
+    pub missing_payloads: Vec<MissingPayload>,
+    /// Payload files no row describes.
+    pub orphaned_payloads: Vec<PathBuf>,
+}
+
+/// A live object or noncurrent version without a payload.
+#[derive(Debug, Clone, Serialize)]
+pub struct MissingPayload {
+    pub bucket: String,
+    pub key: String,
+    /// Set for noncurrent versions.
+    pub version_id: Option<String>,
+}
+
+impl FsckReport {
+    /// True when the pass found nothing to report.
+    pub fn is_clean(&self) -> bool {
+        self.missing_payloads.is_empty() && self.orphaned_payloads.is_empty()
+    }
+}
+
+impl StorageService {
+    /// Remove stale temp files and report payloads and rows that do not
+    /// match up. Temp files modified less than `temp_min_age` ago are kept.
+    pub async fn fsck(&self, temp_min_age: Duration) -> StorageResult<FsckReport> {
+        let mut report = FsckReport::default();
+        let buckets: Vec<(Uuid, String)> = sqlx::query_as("SELECT id, name FROM buckets")
+            .fetch_all(&*self.db)
+            .await?;
+        let bucket_names: HashSet<&str> = buckets.iter().map(|(_, name)| name.as_str()).collect();
+
+        for (bucket_id, bucket_name) in &buckets {
+            self.fsck_objects(*bucket_id, bucket_name, temp_min_age, &mut report)
+                .await?;
+            self.fsck_versions(*bucket_id, bucket_name, temp_min_age, &mut report)
+                .await?;
+        }
+
+        let staging_dir = self.options.staging_dir.as_deref();
+        if let Some(staging_dir) = staging_dir {
+            for file in walk_files(staging_dir).await? {
+                if is_temp(&file) {
+                    sweep_temp(&file, temp_min_age, &mut report).await;
+                }
+            }
+        }
+
+        // Side areas only get their temp files swept; directories of buckets
+        // that no longer exist hold nothing but orphans.
+        let mut entries = match fs::read_dir(&self.base_path).await {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(report),
+            Err(err) => return Err(err.into()),
+        };
+        while let Some(entry) = entries.next_entry().await? {
+            if !entry.file_type().await?.is_dir() {
+                continue;
+            }
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if name == VERSIONS_DIR
+                || bucket_names.contains(name.as_str())
+                || staging_dir == Some(entry.path().as_path())
+            {
+                continue;
+            }
+            let side_area = name.starts_with('.');
+            for file in walk_files(&entry.path()).await? {
+                if is_temp(&file) {
+                    sweep_temp(&file, temp_min_age, &mut report).await;
+                } else if !side_area {
+                    report.orphaned_payloads.push(file);
+                }
+            }
+        }
+
+        for missing in &report.missing_payloads {
+            warn!(
+                "payload missing for {}/{}{}",
+                missing.bucket,
+                missing.key,
+                missing
+                    .version_id
+                    .as_deref()
+                    .map(|id| format!(" (version {})", id))
+                    .unwrap_or_default()
+            );
+        }
+        for orphan in &report.orphaned_payloads {
+            warn!("payload file without metadata: {}", orphan.display());
+        }
+        if report.temp_files_removed > 0 {
+            info!(
+                "removed {} stale temp files ({} bytes)",
+                report.temp_files_removed, report.temp_bytes_removed
+            );
+        }
+        Ok(report)
+    }
+
+    /// Check live objects of one bucket against its payload tree.
+    async fn fsck_objects(
+        &self,
+        bucket_id: Uuid,
+        bucket_name: &str,
+        temp_min_age: Duration,
+        report: &mut FsckReport,
+    ) -> StorageResult<()> {
+        let keys: Vec<(String, Option<String>)> = sqlx::query_as(
+            "SELECT key, volume FROM objects WHERE bucket_id = ? AND is_deleted = 0",
+        )
+        .bind(bucket_id)
+        .fetch_all(&*self.db)
+        .await?;
+        let mut expected: HashMap<PathBuf, String> = keys
+            .into_iter()
+            .map(|(key, volume)| {
+                (
+                    self.object_path_on(volume.as_deref(), bucket_name, &key),
+                    key,
+                )
+            })
+            .collect();
+
+        let roots = std::iter::once(self.bucket_root(bucket_name)).chain(
+            self.options
+                .volumes
+                .iter()
+                .map(|spec| spec.path.join(bucket_name)),
+        );
+        let mut files = Vec::new();
+        for root in roots {
+            files.extend(walk_files(&root).await?);
+        }
+        for file in files {
+            if expected.remove(&file).is_some() {
+                continue;
+            }
+            if is_temp(&file) {
+                sweep_temp(&file, temp_min_age, report).await;
+            } else {
+                report.orphaned_payloads.push(file);
+            }
+        }
+
+        let mut missing: Vec<String> = expected.into_values().collect();
+        missing.sort();
+        report
+            .missing_payloads
+            .extend(missing.into_iter().map(|key| MissingPayload {
+                bucket: bucket_name.to_string(),
+                key,
+                version_id: None,
+            }));
+        Ok(())
+    }
+
+    /// Check noncurrent versions of one bucket against `.versions/{bucket}`.
+    async fn fsck_versions(
+        &self,
+        bucket_id: Uuid,
+        bucket_name: &str,
+        temp_min_age: Duration,
+        report: &mut FsckReport,
+    ) -> StorageResult<()> {
+        let versions: Vec<(Uuid, String, String)> = sqlx::query_as(
+            "SELECT id, key, version_id FROM object_versions
+             WHERE bucket_id = ? AND is_delete_marker = 0
+             ORDER BY key, last_modified",
+        )
+        .bind(bucket_id)
+        .fetch_all(&*self.db)
+        .await?;
+        let mut expected: HashMap<PathBuf, usize> = versions
+            .iter()
+            .enumerate()
+            .map(|(index, (id, _, _))| (self.version_path(bucket_name, *id), index))
+            .collect();
+
+        for file in walk_files(&self.versions_root(bucket_name)).await? {
+            if expected.remove(&file).is_some() {
+                continue;
+            }
+            if is_temp(&file) {
+                sweep_temp(&file, temp_min_age, report).await;
+            } else {
+                report.orphaned_payloads.push(file);
+            }
+        }
+
+        let mut missing: Vec<usize> = expected.into_values().collect();
+        missing.sort_unstable();
+        report
+            .missing_payloads
+            .extend(missing.into_iter().map(|index| {
+                let (_, key, version_id) = &versions[index];
+                MissingPayload {
+                    bucket: bucket_name.to_string(),
+                    key: key.clone(),
+                    version_id: Some(version_id.clone()),
+                }
+            }));
+        Ok(())
+    }
+}
+
+/// Every regular file below `root`, without following symlinks. A missing
+/// root has no files.
+async fn walk_files(root: &Path) -> io::Result<Vec<PathBuf>> {
+    let mut files = Vec::new();
+    let mut dirs = vec![root.to_path_buf()];
+    while let Some(dir) = dirs.pop() {
+        let mut entries = match fs::read_dir(&dir).await {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => continue,
+            Err(err) => return Err(err),
+        };
+        while let Some(entry) = entries.next_entry().await? {
+            let file_type = entry.file_type().await?;
+            if file_type.is_dir() {
+                dirs.push(entry.path());
+            } else if file_type.is_file() {
+                files.push(entry.path());
+            }
+        }
+    }
+    files.sort();
+    Ok(files)
+}
+
+fn is_temp(path: &Path) -> bool {
+    path.file_name()
+        .and_then(|name| name.to_str())
+        .is_some_and(|name| name.starts_with(TEMP_PREFIX))
+}
+
+/// Remove a temp file unless it was modified within `min_age`.
+async fn sweep_temp(path: &Path, min_age: Duration, report: &mut FsckReport) {
+    let metadata = match fs::symlink_metadata(path).await {
+        Ok(metadata) => metadata,
+        Err(_) => return,
+    };
+    let age = metadata
+        .modified()
+        .ok()
+        .and_then(|modified| SystemTime::now().duration_since(modified).ok())
+        .unwrap_or_default();
+    if age < min_age {
+        return;
+    }
+    match fs::remove_file(path).await {
+        Ok(()) => {
+            report.temp_files_removed += 1;
+            report.temp_bytes_removed += metadata.len();
+        }
+        Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+        Err(err) => warn!("could not remove temp file {}: {}", path.display(), err),
+    }
+}
@@ -0,0 +1,301 @@
+//! External identity providers for the admin API.
+//!
+//! Two backends are supported, and both may be enabled at once:
+//!
+//! - **OIDC**: bearer tokens are validated against the issuer's JWKS
+//!   (discovered from `/.well-known/openid-configuration` unless an explicit
+//!   JWKS URL is given). Group membership comes from a configurable claim.
+//! - **LDAP**: HTTP basic credentials are checked with a simple bind using a
+//!   DN template; group membership comes from an attribute of the user entry
+//!   (`memberOf` by default).
+//!
+//! Neither backend decides what a user may do; that is the role mapping in
+//! `middleware::admin_auth`.
+
+use crate::services::outbound::OutboundHttp;
+use jsonwebtoken::{
+    Algorithm, DecodingKey, Validation, decode, decode_header,
+    jwk::{Jwk, JwkSet},
+};
+use ldap3::{LdapConnAsync, LdapConnSettings, Scope, SearchEntry, dn_escape};
+use reqwest::{Client, Url};
+use serde::Deserialize;
+use serde_json::Value;
+use std::time::{Duration, Instant};
+use thiserror::Error;
+use tokio::sync::RwLock;
+
+/// How long fetched signing keys are trusted before being refreshed.
+const JWKS_MAX_AGE: Duration = Duration::from_secs(600);
+
+/// Minimum gap between refetches triggered by an unknown `kid`.
+const JWKS_MIN_REFRESH: Duration = Duration::from_secs(30);
+
+/// Timeout for calls to the identity provider.
+const PROVIDER_TIMEOUT: Duration = Duration::from_secs(5);
+
+#[derive(Debug, Error)]
+pub enum IdentityError {
+    /// The credentials were checked and rejected.
+    #[error("invalid credentials: {0}")]
+    Rejected(String),
+
+    /// The provider could not be reached or answered nonsense.
+    #[error("identity provider unavailable: {0}")]
+    Unavailable(String),
+}
+
+/// A user the provider vouched for.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VerifiedUser {
+    pub subject: String,
+    pub groups: Vec<String>,
+}
+
+/// OIDC settings as configured.
+#[derive(Debug, Clone)]
+pub struct OidcSettings {
+    pub issuer: Url,
+    pub audience: String,
+    /// Explicit JWKS endpoint; discovered from the issuer when `None`.
+    pub jwks_url: Option<Url>,
+    /// Claim that lists the user's groups.
+    pub groups_claim: String,
+}
+
+#[derive(Debug, Default)]
+struct JwksCache {
+    keys: Option<JwkSet>,
+    fetched_at: Option<Instant>,
+    jwks_url: Option<Url>,
+}
+
+/// Validates OIDC bearer tokens.
+#[derive(Debug)]
+pub struct OidcValidator {
+    settings: OidcSettings,
+    client: Client,
+    cache: RwLock<JwksCache>,
+}
+
+#[derive(Deserialize)]
+struct DiscoveryDocument {
+    jwks_uri: Url,
+}
+
+impl OidcValidator {
+    pub fn new(settings: OidcSettings, outbound: &OutboundHttp) -> reqwest::Result<Self> {
+        let client = outbound
+            .client_builder()
+            .timeout(PROVIDER_TIMEOUT)
+            .build()?;
+        Ok(Self {
+            settings,
+            client,
+            cache: RwLock::new(JwksCache::default()),
+        })
+    }
+
+    /// Verify signature, issuer, audience and expiry of `token`.
+    pub async fn validate(&self, token: &str) -> Result<VerifiedUser, IdentityError> {
+        let header = decode_header(token)
+            .map_err(|err| IdentityError::Rejected(format!("malformed token: {}", err)))?;
+        if matches!(
+            header.alg,
+            Algorithm::HS256 | Algorithm::HS384 | Algorithm::HS512
+        ) {
+            return Err(IdentityError::Rejected(
+                "symmetric token algorithms are not accepted".into(),
+            ));
+        }
+
+        let jwk = self.signing_key(header.kid.as_deref()).await?;
+        let key = DecodingKey::from_jwk(&jwk)
+            .map_err(|err| IdentityError::Unavailable(format!("unusable signing key: {}", err)))?;
+
+        let mut validation = Validation::new(header.alg);
+        // Providers disagree on the trailing slash; accept either spelling.
+        let issuer = self.settings.issuer.as_str().trim_end_matches('/');
+        validation.set_issuer(&[issuer.to_string(), format!("{}/", issuer)]);
+        validation.set_audience(&[&self.settings.audience]);
+        validation.set_required_spec_claims(&["exp", "iss", "aud", "sub"]);
+
+        let claims = decode::<serde_json::Map<String, Value>>(token, &key, &validation)
+            .map_err(|err| IdentityError::Rejected(err.to_string()))?
+            .claims;
+
+        let subject = claims
+            .get("sub")
+            .and_then(Value::as_str)
+            .unwrap_or_default()
+            .to_string();
+        let groups = match claims.get(&self.settings.groups_claim) {
+            Some(Value::Array(items)) => items
+                .iter()
+                .filter_map(Value::as_str)
+                .map(str::to_string)
+                .collect(),
+            Some(Value::String(single)) => vec![single.clone()],
+            _ => Vec::new(),
+        };
+        Ok(VerifiedUser { subject, groups })
+    }
+
+    /// Find the key for `kid`, refreshing the JWKS when stale or when the
+    /// key is unknown (provider rotated keys).
+    async fn signing_key(&self, kid: Option<&str>) -> Result<Jwk, IdentityError> {
+        {
+            let cache = self.cache.read().await;
+            let fresh = cache
+                .fetched_at
+                .is_some_and(|at| at.elapsed() < JWKS_MAX_AGE);
+            if fresh && let Some(jwk) = cache.keys.as_ref().and_then(|keys| pick_key(keys, kid)) {
+                return Ok(jwk);
+            }
+        }
+
+        let mut cache = self.cache.write().await;
+        let recently_fetched = cache
+            .fetched_at
+            .is_some_and(|at| at.elapsed() < JWKS_MIN_REFRESH);
+        if !recently_fetched {
+            let jwks_url = match (&self.settings.jwks_url, &cache.jwks_url) {
+                (Some(url), _) | (None, Some(url)) => url.clone(),
+                (None, None) => self.discover_jwks_url().await?,
+            };
+            let keys = self
+                .client
+                .get(jwks_url.clone())
+                .send()
+                .await
+                .and_then(|r| r.error_for_status())
+                .map_err(|err| IdentityError::Unavailable(format!("fetching JWKS: {}", err)))?
+                .json::<JwkSet>()
+                .await
+                .map_err(|err| IdentityError::Unavailable(format!("parsing JWKS: {}", err)))?;
+            cache.keys = Some(keys);
+            cache.fetched_at = Some(Instant::now());
+            cache.jwks_url = Some(jwks_url);
+        }
+
+        cache
+            .keys
+            .as_ref()
+            .and_then(|keys| pick_key(keys, kid))
+            .ok_or_else(|| IdentityError::Rejected("token signed by an unknown key".into()))
+    }
+
+    async fn discover_jwks_url(&self) -> Result<Url, IdentityError> {
+        let discovery = format!(
+            "{}/.well-known/openid-configuration",
+            self.settings.issuer.as_str().trim_end_matches('/')
+        );
+        self.client
+            .get(&discovery)
+            .send()
+            .await
+            .and_then(|r| r.error_for_status())
+            .map_err(|err| IdentityError::Unavailable(format!("OIDC discovery: {}", err)))?
+            .json::<DiscoveryDocument>()
+            .await
+            .map(|doc| doc.jwks_uri)
+            .map_err(|err| IdentityError::Unavailable(format!("OIDC discovery: {}", err)))
+    }
+}
+
+/// With a `kid`, the matching key; without one, the only key in the set.
+fn pick_key(keys: &JwkSet, kid: Option<&str>) -> Option<Jwk> {
+    match kid {
+        Some(kid) => keys.find(kid).cloned(),
+        None if keys.keys.len() == 1 => keys.keys.first().cloned(),
+        None => None,
+    }
+}
+
+/// LDAP settings as configured.
+#[derive(Debug, Clone)]
+pub struct LdapSettings {
+    /// `ldap://` or `ldaps://` server URL.
+    pub url: String,
+    /// Bind DN with a `{user}` placeholder, e.g.
+    /// `uid={user},ou=people,dc=example,dc=com`.
+    pub user_dn_template: String,
+    /// Attribute on the user entry that lists group DNs.
+    pub group_attribute: String,
+}
+
+/// Authenticates users with an LDAP simple bind.
+#[derive(Debug)]
+pub struct LdapAuthenticator {
+    settings: LdapSettings,
+}
+
+impl LdapAuthenticator {
+    pub fn new(settings: LdapSettings) -> Self {
+        Self { settings }
+    }
+
+    pub fn url(&self) -> &str {
+        &self.settings.url
+    }
+
+    /// Bind as `user` and read their group memberships.
+    pub async fn authenticate(
+        &self,
+        user: &str,
+        password: &str,
+    ) -> Result<VerifiedUser, IdentityError> {
+        // An empty password is an unauthenticated bind in LDAP and would
+        // "succeed" for any DN.
+        if user.is_empty() || password.is_empty() {
+            return Err(IdentityError::Rejected(
+                "username and password required".into(),
+            ));
+        }
+        let dn = self
+            .settings
+            .user_dn_template
+            .replace("{user}", &dn_escape(user));
+
+        let unavailable = |err: ldap3::LdapError| IdentityError::Unavailable(err.to_string());
+        let settings = LdapConnSettings::new().set_conn_timeout(PROVIDER_TIMEOUT);
+        let (conn, mut ldap) = LdapConnAsync::with_settings(settings, &self.settings.url)
+            .await
+            .map_err(unavailable)?;
+        ldap3::drive!(conn);
+
+        let bind = ldap.simple_bind(&dn, password).await.map_err(unavailable)?;
+        if bind.rc != 0 {
+            let _ = ldap.unbind().await;
+            return Err(IdentityError::Rejected(format!("bind failed for {}", dn)));
+        }
+
+        let (entries, _) = ldap
+            .search(
+                &dn,
+                Scope::Base,
+                "(objectClass=*)",
+                vec![self.settings.group_attribute.as_str()],
+            )
+            .await
+            .and_then(|result| result.success())
+            .map_err(unavailable)?;
+        let _ = ldap.unbind().await;
+
+        let groups = entries
+            .into_iter()
+            .map(SearchEntry::construct)
+            .flat_map(|entry| {
+                entry
+                    .attrs
+                    .into_iter()
+                    .filter(|(name, _)| name.eq_ignore_ascii_case(&self.settings.group_attribute))
+                    .flat_map(|(_, values)| values)
+            })
+            .collect();
+        Ok(VerifiedUser {
+            subject: user.to_string(),
+            groups,
+        })
+    }
+}
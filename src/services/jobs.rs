@@ -0,0 +1,264 @@
+//! Background jobs.
+//!
+//! Work too large for a single request (deleting millions of keys under a
+//! prefix, analysing a bucket's access patterns, reconciling a replica,
+//! moving payloads to a new shard layout) is recorded in the `jobs` table and
+//! handed back to the client as a job id. A runner task executes
+//! queued jobs one at a time, in creation order, and records progress as it
+//! goes so `GET /admin/jobs/{id}` can report it. Jobs survive restarts:
+//! anything still `running` at startup is requeued, which is safe because
+//! every job step is idempotent.
+
+use crate::{
+    models::{bucket::Bucket, job::Job},
+    services::{
+        changes::{NewChange, insert_change},
+        events::EventKind,
+        storage_service::{StorageError, StorageResult, StorageService},
+    },
+};
+use chrono::Utc;
+use std::{sync::Arc, time::Duration};
+use tokio::{sync::Notify, task::JoinHandle};
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+/// Keys soft-deleted per transaction by a prefix delete.
+const DELETE_BATCH: i64 = 500;
+
+/// Most recent jobs returned by `list_jobs`.
+const LIST_LIMIT: i64 = 100;
+
+/// How often the runner looks for queued jobs when not woken explicitly.
+pub const JOB_POLL: Duration = Duration::from_secs(30);
+
+pub(crate) const JOB_COLUMNS: &str = "id, kind, bucket_id, prefix, destination, status, processed, error, \
+     report, created_at, started_at, finished_at";
+
+/// Wakes the job runner when work is enqueued.
+#[derive(Debug, Clone, Default)]
+pub struct JobQueue {
+    wake: Arc<Notify>,
+}
+
+impl JobQueue {
+    pub(crate) fn notify(&self) {
+        self.wake.notify_one();
+    }
+}
+
+impl StorageService {
+    /// Enqueue deletion of every live key under `prefix` in `bucket`.
+    ///
+    /// The bucket must be writable now and stay writable while the job runs;
+    /// a bucket flagged read-only mid-way fails the job, leaving the keys not
+    /// yet reached in place.
+    pub async fn enqueue_prefix_delete(&self, bucket: &str, prefix: &str) -> StorageResult<Job> {
+        // An empty prefix would empty the whole bucket; that is what deleting
+        // the bucket is for.
+        self.ensure_key_safe(prefix)?;
+        let bucket_rec = self.fetch_writable_bucket(bucket).await?;
+        let job = sqlx::query_as::<_, Job>(&format!(
+            "INSERT INTO jobs (id, kind, bucket_id, prefix, status, created_at)
+             VALUES (?, 'delete-prefix', ?, ?, 'queued', ?)
+             RETURNING {JOB_COLUMNS}"
+        ))
+        .bind(Uuid::new_v4())
+        .bind(bucket_rec.id)
+        .bind(prefix)
+        .bind(Utc::now())
+        .fetch_one(&*self.db)
+        .await?;
+        self.jobs.notify();
+        info!(
+            "queued job {}: delete `{}` under prefix `{}`",
+            job.id, bucket_rec.name, prefix
+        );
+        Ok(job)
+    }
+
+    /// Look up a job by id.
+    pub async fn get_job(&self, id: Uuid) -> StorageResult<Job> {
+        sqlx::query_as::<_, Job>(&format!("SELECT {JOB_COLUMNS} FROM jobs WHERE id = ?"))
+            .bind(id)
+            .fetch_optional(&*self.db)
+            .await?
+            .ok_or(StorageError::JobNotFound(id))
+    }
+
+    /// The most recent jobs, newest first.
+    pub async fn list_jobs(&self) -> StorageResult<Vec<Job>> {
+        let jobs = sqlx::query_as::<_, Job>(&format!(
+            "SELECT {JOB_COLUMNS} FROM jobs ORDER BY created_at DESC LIMIT ?"
+        ))
+        .bind(LIST_LIMIT)
+        .fetch_all(&*self.db)
+        .await?;
+        Ok(jobs)
+    }
+
+    /// Put jobs interrupted by a shutdown back in the queue.
+    pub async fn requeue_interrupted_jobs(&self) -> StorageResult<u64> {
+        let result = sqlx::query("UPDATE jobs SET status = 'queued' WHERE status = 'running'")
+            .execute(&*self.db)
+            .await?;
+        Ok(result.rows_affected())
+    }
+
+    /// Claim and run the oldest queued job. Returns `false` when the queue is
+    /// empty.
+    pub async fn run_next_job(&self) -> StorageResult<bool> {
+        let Some(job) = sqlx::query_as::<_, Job>(&format!(
+            "UPDATE jobs SET status = 'running', started_at = ?
+             WHERE id = (
+                SELECT id FROM jobs WHERE status = 'queued' ORDER BY created_at LIMIT 1
+             )
+             RETURNING {JOB_COLUMNS}"
+        ))
+        .bind(Utc::now())
+        .fetch_optional(&*self.db)
+        .await?
+        else {
+            return Ok(false);
+        };
+
+        let outcome = match job.kind.as_str() {
+            "delete-prefix" => self.run_prefix_delete(&job).await,
+            "storage-class-analysis" => self.run_storage_class_analysis(&job).await,
+            "replication-reconcile" => self.run_replication_reconcile(&job).await,
+            "shard-migration" => self.run_shard_migration(&job).await,
+            other => Err(StorageError::InvalidContent(format!(
+                "unknown job kind `{}`",
+                other
+            ))),
+        };
+        let (status, error) = match &outcome {
+            Ok(()) => ("succeeded", None),
+            Err(err) => ("failed", Some(err.to_string())),
+        };
+        sqlx::query("UPDATE jobs SET status = ?, error = ?, finished_at = ? WHERE id = ?")
+            .bind(status)
+            .bind(&error)
+            .bind(Utc::now())
+            .bind(job.id)
+            .execute(&*self.db)
+            .await?;
+        match error {
+            None => info!("job {} ({}) succeeded", job.id, job.kind),
+            Some(err) => warn!("job {} ({}) failed: {}", job.id, job.kind, err),
+        }
+        Ok(true)
+    }
+
+    /// Soft-delete every live key under the job's prefix, batch by batch,
+    /// removing payloads as it goes.
+    async fn run_prefix_delete(&self, job: &Job) -> StorageResult<()> {
+        let prefix = job.prefix.clone().unwrap_or_default();
+        loop {
+            // Re-read the bucket each batch so a read-only flag stops the job.
+            let bucket = self.writable_bucket_by_id(job.bucket_id).await?;
+
+            // Keys sharing a prefix are contiguous in key order, so a range
+            // scan on the (bucket_id, key) index finds them without LIKE
+            // (whose wildcards could match unrelated keys).
+            let candidates: Vec<(Uuid, String, Option<String>)> = sqlx::query_as(
+                "SELECT id, key, version_id FROM objects
+                 WHERE bucket_id = ? AND is_deleted = 0 AND key >= ?
+                 ORDER BY key ASC LIMIT ?",
+            )
+            .bind(bucket.id)
+            .bind(&prefix)
+            .bind(DELETE_BATCH)
+            .fetch_all(&*self.db)
+            .await?;
+            let (versioned, batch): (Vec<_>, Vec<_>) = candidates
+                .into_iter()
+                .take_while(|(_, key, _)| key.starts_with(&prefix))
+                .partition(|(_, _, version_id)| bucket.versioning_enabled || version_id.is_some());
+            if versioned.is_empty() && batch.is_empty() {
+                return Ok(());
+            }
+
+            // Keys with version history keep it behind a delete marker.
+            for (_, key, _) in &versioned {
+                self.locked_delete_marker(&bucket, key).await?;
+                sqlx::query("UPDATE jobs SET processed = processed + 1 WHERE id = ?")
+                    .bind(job.id)
+                    .execute(&*self.db)
+                    .await?;
+            }
+            if batch.is_empty() {
+                continue;
+            }
+
+            let key_guards = self
+                .key_locks
+                .lock_all(bucket.id, batch.iter().map(|(_, key, _)| key.as_str()))
+                .await;
+            let mut tx = self.db.begin().await?;
+            for (id, key, _) in &batch {
+                sqlx::query("UPDATE objects SET is_deleted = 1, deleted_at = ? WHERE id = ?")
+                    .bind(Utc::now())
+                    .bind(id)
+                    .execute(&mut *tx)
+                    .await?;
+                insert_change(&mut *tx, bucket.id, &NewChange::deleted(key, None)).await?;
+            }
+            sqlx::query("UPDATE jobs SET processed = processed + ? WHERE id = ?")
+                .bind(batch.len() as i64)
+                .bind(job.id)
+                .execute(&mut *tx)
+                .await?;
+            tx.commit().await?;
+
+            for (id, key, _) in &batch {
+                if let Err(err) = self.discard_deleted_payload(&bucket.name, *id, key).await {
+                    warn!("failed to remove payload of {}: {}", key, err);
+                }
+                self.events
+                    .object(EventKind::ObjectDeleted, &bucket.name, key, None, None);
+            }
+            drop(key_guards);
+            debug!("job {}: deleted {} keys", job.id, batch.len());
+        }
+    }
+
+    pub(crate) async fn writable_bucket_by_id(&self, id: Uuid) -> StorageResult<Bucket> {
+        let name: Option<String> = sqlx::query_scalar("SELECT name FROM buckets WHERE id = ?")
+            .bind(id)
+            .fetch_optional(&*self.db)
+            .await?;
+        match name {
+            Some(name) => self.fetch_writable_bucket(&name).await,
+            None => Err(StorageError::BucketNotFound(id.to_string())),
+        }
+    }
+}
+
+/// Spawn the task that runs queued jobs. It wakes when a job is enqueued and
+/// otherwise polls every `poll`.
+pub fn spawn_job_runner(service: StorageService, poll: Duration) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        match service.requeue_interrupted_jobs().await {
+            Ok(0) => {}
+            Ok(n) => info!("requeued {} interrupted jobs", n),
+            Err(err) => warn!("could not requeue interrupted jobs: {}", err),
+        }
+        loop {
+            loop {
+                match service.run_next_job().await {
+                    Ok(true) => continue,
+                    Ok(false) => break,
+                    Err(err) => {
+                        warn!("job runner failed: {}", err);
+                        break;
+                    }
+                }
+            }
+            tokio::select! {
+                _ = service.jobs.wake.notified() => {}
+                _ = tokio::time::sleep(poll) => {}
+            }
+        }
+    })
+}
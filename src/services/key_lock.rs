@@ -0,0 +1,114 @@
+//! Per-key write serialization.
+//!
+//! A write to a key moves files around (archiving or recycling the old
+//! payload, renaming the staged one into place) and then upserts the row.
+//! Two writers interleaving those steps could leave the row describing one
+//! payload while the file on disk is the other's, so every write or delete
+//! of a key holds that key's lock across its file and row changes.
+//!
+//! Locks live in a sharded map keyed by bucket id and key, created on first
+//! use and dropped with their last guard. A caller holding a key lock must
+//! not wait for another one, except through `lock_all`, which takes a batch
+//! in key order so two batches cannot deadlock.
+
+use std::{
+    collections::{BTreeSet, HashMap},
+    hash::{BuildHasher, RandomState},
+    sync::{Arc, Mutex},
+};
+use tokio::sync::{Mutex as AsyncMutex, OwnedMutexGuard};
+use uuid::Uuid;
+
+/// Shards of the lock map; writers to different shards never contend on it.
+const SHARDS: usize = 16;
+
+type LockId = (Uuid, String);
+type Shard = Mutex<HashMap<LockId, Arc<AsyncMutex<()>>>>;
+
+struct LockTable {
+    hasher: RandomState,
+    shards: Vec<Shard>,
+}
+
+/// Locks serializing writes to individual keys.
+#[derive(Clone)]
+pub struct KeyLocks {
+    table: Arc<LockTable>,
+}
+
+impl Default for KeyLocks {
+    fn default() -> Self {
+        Self {
+            table: Arc::new(LockTable {
+                hasher: RandomState::new(),
+                shards: (0..SHARDS).map(|_| Mutex::default()).collect(),
+            }),
+        }
+    }
+}
+
+/// Holds a key's lock until dropped.
+pub struct KeyGuard {
+    guard: Option<OwnedMutexGuard<()>>,
+    table: Arc<LockTable>,
+    id: LockId,
+}
+
+impl KeyLocks {
+    /// Wait for exclusive access to `key` in the bucket `bucket_id`.
+    pub async fn lock(&self, bucket_id: Uuid, key: &str) -> KeyGuard {
+        let id = (bucket_id, key.to_string());
+        let mutex = {
+            let mut shard = self
+                .table
+                .shard(&id)
+                .lock()
+                .unwrap_or_else(|e| e.into_inner());
+            shard.entry(id.clone()).or_default().clone()
+        };
+        KeyGuard {
+            guard: Some(mutex.lock_owned().await),
+            table: self.table.clone(),
+            id,
+        }
+    }
+
+    /// Lock several keys of one bucket, in key order.
+    pub async fn lock_all<'a>(
+        &self,
+        bucket_id: Uuid,
+        keys: impl IntoIterator<Item = &'a str>,
+    ) -> Vec<KeyGuard> {
+        let keys: BTreeSet<&str> = keys.into_iter().collect();
+        let mut guards = Vec::with_capacity(keys.len());
+        for key in keys {
+            guards.push(self.lock(bucket_id, key).await);
+        }
+        guards
+    }
+}
+
+impl LockTable {
+    fn shard(&self, id: &LockId) -> &Shard {
+        &self.shards[self.hasher.hash_one(id) as usize % SHARDS]
+    }
+}
+
+impl Drop for KeyGuard {
+    fn drop(&mut self) {
+        drop(self.guard.take());
+        let mut shard = self
+            .table
+            .shard(&self.id)
+            .lock()
+            .unwrap_or_else(|e| e.into_inner());
+        // Handles are only cloned under the shard lock, so a count of one
+        // means nobody holds or awaits this lock any more.
+        if shard
+            .get(&self.id)
+            .is_some_and(|mutex| Arc::strong_count(mutex) == 1)
+        {
+            shard.remove(&self.id);
+        }
+    }
+}
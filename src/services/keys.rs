@@ -0,0 +1,101 @@
+//! Object key policy.
+//!
+//! Keys are stored byte-for-byte as received: they are valid UTF-8 (the
+//! router rejects anything else) and are not Unicode-normalised, so `é` as
+//! one code point and as `e` plus a combining accent are different keys, as
+//! in S3. On top of that a key must be 1-1024 bytes, must not begin with `/`
+//! or contain `..` (payloads live at `{bucket}/{key}` on disk), and must not
+//! contain control characters or backslashes.
+//!
+//! Upload, get, delete and copy check keys with `ensure_key_safe`; listings
+//! check their prefix with `ensure_prefix_safe`, which applies the same
+//! character rules, since a prefix no key can start with is a client bug
+//! and control characters cannot be returned in XML anyway. Prefixes are
+//! matched in SQL with `GLOB` on an escaped pattern rather than `LIKE`,
+//! which treats `%` and `_` as wildcards and ignores ASCII case.
+
+use crate::services::storage_service::{StorageError, StorageResult, StorageService};
+
+/// Longest accepted key, in bytes.
+pub(crate) const MAX_OBJECT_KEY_LEN: usize = 1024;
+
+/// Why `key` is not acceptable as an object key, if it is not.
+pub(crate) fn key_violation(key: &str) -> Option<&'static str> {
+    if key.is_empty() {
+        return Some("must not be empty");
+    }
+    if key.len() > MAX_OBJECT_KEY_LEN {
+        return Some("must be at most 1024 bytes");
+    }
+    if key.starts_with('/') {
+        return Some("must not begin with `/`");
+    }
+    if key.contains("..") {
+        return Some("must not contain `..`");
+    }
+    character_violation(key)
+}
+
+/// Why no key could start with `prefix`, judged by the character rules
+/// alone: a prefix may be empty or end half-way through a `..`.
+pub(crate) fn prefix_violation(prefix: &str) -> Option<&'static str> {
+    if prefix.len() > MAX_OBJECT_KEY_LEN {
+        return Some("must be at most 1024 bytes");
+    }
+    character_violation(prefix)
+}
+
+fn character_violation(text: &str) -> Option<&'static str> {
+    if text.bytes().any(|b| b.is_ascii_control() || b == b'\\') {
+        return Some("must not contain control characters or backslashes");
+    }
+    None
+}
+
+/// `GLOB` pattern matching every key that starts with `prefix`.
+///
+/// `*`, `?` and `[` are wrapped in brackets so they match themselves; `]` is
+/// literal outside a bracket expression.
+pub(crate) fn prefix_glob(prefix: &str) -> String {
+    let mut pattern = String::with_capacity(prefix.len() + 1);
+    for c in prefix.chars() {
+        match c {
+            '*' | '?' | '[' => {
+                pattern.push('[');
+                pattern.push(c);
+                pattern.push(']');
+            }
+            _ => pattern.push(c),
+        }
+    }
+    pattern.push('*');
+    pattern
+}
+
+/// The smallest string ordered (bytewise) after every key that starts with
+/// `prefix`, for seeking past a common prefix; `None` when bumping its last
+/// byte leaves no valid UTF-8.
+pub(crate) fn prefix_successor(prefix: &str) -> Option<String> {
+    let mut bytes = prefix.as_bytes().to_vec();
+    let last = bytes.last_mut()?;
+    *last = last.checked_add(1)?;
+    String::from_utf8(bytes).ok()
+}
+
+impl StorageService {
+    /// Reject keys that break the policy above with `InvalidObjectKey`.
+    pub(crate) fn ensure_key_safe(&self, key: &str) -> StorageResult<()> {
+        match key_violation(key) {
+            Some(_) => Err(StorageError::InvalidObjectKey),
+            None => Ok(()),
+        }
+    }
+
+    /// Reject listing prefixes no valid key could start with.
+    pub(crate) fn ensure_prefix_safe(&self, prefix: &str) -> StorageResult<()> {
+        match prefix_violation(prefix) {
+            Some(reason) => Err(StorageError::InvalidPrefix(reason.to_string())),
+            None => Ok(()),
+        }
+    }
+}
@@ -0,0 +1,496 @@
+//! Bucket lifecycle rules.
+//!
+//! A bucket's lifecycle configuration (S3 `?lifecycle`) is a list of rules,
+//! each with a filter (key prefix and/or tags) and at least one action:
+//! expire current objects some days after their last modification, move
+//! them to a remote tier once untouched for some days (see `tiering`), or
+//! abort multipart uploads some days after they were initiated. Rules live in
+//! `lifecycle_rules`; a PUT replaces the whole configuration.
+//!
+//! `spawn_lifecycle_worker` applies the enabled rules of every bucket
+//! periodically. Expiration goes through `delete_object`, so a versioned
+//! bucket gets a delete marker and keeps the history, as in S3. Each pass
+//! also collects the remote blobs of tiered versions that are gone.
+
+use crate::{
+    models::{
+        bucket::Bucket,
+        lifecycle::{LifecycleRule, LifecycleTransition},
+        object_tag::ObjectTag,
+    },
+    services::{
+        metadata_encryption::MetadataSealer,
+        storage_service::{StorageError, StorageResult, StorageService},
+        tagging::validate_tags,
+        tiering::TRANSITION_STORAGE_CLASSES,
+    },
+};
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use serde::Serialize;
+use sqlx::{QueryBuilder, Sqlite, Transaction};
+use std::{collections::HashSet, time::Duration};
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+/// How often the worker applies lifecycle rules.
+pub const LIFECYCLE_TICK: Duration = Duration::from_secs(3600);
+
+/// Most rules a configuration may hold, as in S3.
+pub const MAX_LIFECYCLE_RULES: usize = 1000;
+
+/// Longest accepted rule `ID`.
+const MAX_RULE_ID_LEN: usize = 255;
+
+/// Keys fetched per query while expiring objects.
+const EXPIRE_PAGE: i64 = 500;
+
+/// What one lifecycle pass did.
+#[derive(Debug, Default, Clone, Copy, Serialize)]
+pub struct LifecycleSummary {
+    /// Objects expired (deleted, or given a delete marker).
+    pub expired: u64,
+    /// Objects moved to the remote tier (or to another class on it).
+    pub transitioned: u64,
+    /// Remote blobs of versions that no longer exist removed.
+    pub collected_blobs: u64,
+    /// Multipart uploads aborted.
+    pub aborted_uploads: u64,
+}
+
+/// A rule as stored, with its tag filter still JSON.
+type RuleRow = (
+    String,
+    bool,
+    Option<String>,
+    Option<String>,
+    Option<i64>,
+    Option<i64>,
+    Option<i64>,
+    Option<String>,
+);
+
+impl StorageService {
+    /// Replace the lifecycle configuration of `bucket`.
+    pub async fn put_bucket_lifecycle(
+        &self,
+        bucket: &str,
+        rules: &[LifecycleRule],
+    ) -> StorageResult<()> {
+        self.validate_lifecycle(rules)?;
+        let bucket_rec = self.fetch_writable_bucket(bucket).await?;
+        let mut tx = self.db.begin().await?;
+        sqlx::query("DELETE FROM lifecycle_rules WHERE bucket_id = ?")
+            .bind(bucket_rec.id)
+            .execute(&mut *tx)
+            .await?;
+        insert_rules(&mut tx, bucket_rec.id, rules).await?;
+        tx.commit().await?;
+        info!(
+            "set {} lifecycle rule(s) on bucket `{}`",
+            rules.len(),
+            bucket_rec.name
+        );
+        Ok(())
+    }
+
+    /// The lifecycle rules of `bucket`, in configuration order.
+    pub async fn get_bucket_lifecycle(&self, bucket: &str) -> StorageResult<Vec<LifecycleRule>> {
+        let bucket_rec = self.fetch_bucket(bucket).await?;
+        let rules = self.lifecycle_rules(bucket_rec.id).await?;
+        if rules.is_empty() {
+            return Err(StorageError::NoSuchLifecycleConfiguration(bucket_rec.name));
+        }
+        Ok(rules)
+    }
+
+    /// Remove the lifecycle configuration of `bucket`.
+    pub async fn delete_bucket_lifecycle(&self, bucket: &str) -> StorageResult<()> {
+        let bucket_rec = self.fetch_writable_bucket(bucket).await?;
+        sqlx::query("DELETE FROM lifecycle_rules WHERE bucket_id = ?")
+            .bind(bucket_rec.id)
+            .execute(&*self.db)
+            .await?;
+        Ok(())
+    }
+
+    /// Apply the enabled lifecycle rules of every bucket once.
+    pub async fn run_lifecycle(&self) -> StorageResult<LifecycleSummary> {
+        let bucket_ids: Vec<Uuid> =
+            sqlx::query_scalar("SELECT DISTINCT bucket_id FROM lifecycle_rules WHERE enabled = 1")
+                .fetch_all(&*self.db)
+                .await?;
+        let now = Utc::now();
+        let mut summary = LifecycleSummary::default();
+        for bucket_id in bucket_ids {
+            let bucket = match self.writable_bucket_by_id(bucket_id).await {
+                Ok(bucket) => bucket,
+                Err(StorageError::BucketReadOnly(name)) => {
+                    debug!("skipping lifecycle of read-only bucket `{}`", name);
+                    continue;
+                }
+                Err(StorageError::BucketNotFound(_)) => continue,
+                Err(err) => return Err(err),
+            };
+            for rule in self.lifecycle_rules(bucket_id).await? {
+                if !rule.enabled {
+                    continue;
+                }
+                if let Some(days) = rule.expiration_days {
+                    summary.expired += self
+                        .expire_objects(&bucket, &rule, now - ChronoDuration::days(days))
+                        .await?;
+                }
+                if let Some(transition) = &rule.transition {
+                    summary.transitioned += self
+                        .transition_objects(
+                            &bucket,
+                            &rule,
+                            now - ChronoDuration::days(transition.days),
+                            &transition.storage_class,
+                        )
+                        .await?;
+                }
+                if let Some(days) = rule.abort_incomplete_days {
+                    summary.aborted_uploads += self
+                        .abort_stale_uploads(&bucket, &rule, now - ChronoDuration::days(days))
+                        .await?;
+                }
+            }
+        }
+        summary.collected_blobs = self.collect_tier_orphans().await?;
+        if summary.expired > 0
+            || summary.transitioned > 0
+            || summary.aborted_uploads > 0
+            || summary.collected_blobs > 0
+        {
+            info!(
+                "lifecycle expired {} objects, transitioned {}, aborted {} multipart uploads \
+                 and removed {} remote blobs",
+                summary.expired,
+                summary.transitioned,
+                summary.aborted_uploads,
+                summary.collected_blobs
+            );
+        }
+        Ok(summary)
+    }
+
+    /// Check a whole configuration as `put_bucket_lifecycle` would store it.
+    pub(crate) fn validate_lifecycle(&self, rules: &[LifecycleRule]) -> StorageResult<()> {
+        validate_rules(rules)?;
+        self.ensure_transitions_supported(rules)?;
+        for prefix in rules.iter().filter_map(|rule| rule.prefix.as_deref()) {
+            if !prefix.is_empty() {
+                self.ensure_key_safe(prefix)?;
+            }
+        }
+        Ok(())
+    }
+
+    /// Refuse `Transition` actions when there is no remote tier to move
+    /// objects to.
+    pub(crate) fn ensure_transitions_supported(
+        &self,
+        rules: &[LifecycleRule],
+    ) -> StorageResult<()> {
+        match rules.iter().find(|rule| rule.transition.is_some()) {
+            Some(rule) if self.options.remote_tier.is_none() => {
+                Err(StorageError::InvalidLifecycle(format!(
+                    "rule `{}`: Transition needs a remote tier, which is not configured",
+                    rule.id
+                )))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    async fn lifecycle_rules(&self, bucket_id: Uuid) -> StorageResult<Vec<LifecycleRule>> {
+        let rows: Vec<RuleRow> = sqlx::query_as(
+            "SELECT rule_id, enabled, prefix, tags, expiration_days, abort_incomplete_days,
+                    transition_days, transition_storage_class
+             FROM lifecycle_rules WHERE bucket_id = ? ORDER BY position",
+        )
+        .bind(bucket_id)
+        .fetch_all(&*self.db)
+        .await?;
+        rows.into_iter()
+            .map(
+                |(
+                    id,
+                    enabled,
+                    prefix,
+                    tags,
+                    expiration_days,
+                    abort_incomplete_days,
+                    transition_days,
+                    transition_storage_class,
+                )| {
+                    let tags: Vec<ObjectTag> = match tags {
+                        Some(json) => serde_json::from_str(&json)
+                            .map_err(|err| StorageError::InvalidLifecycle(err.to_string()))?,
+                        None => Vec::new(),
+                    };
+                    Ok(LifecycleRule {
+                        id,
+                        enabled,
+                        prefix,
+                        tags,
+                        expiration_days,
+                        abort_incomplete_days,
+                        transition: transition_days.zip(transition_storage_class).map(
+                            |(days, storage_class)| LifecycleTransition {
+                                days,
+                                storage_class,
+                            },
+                        ),
+                    })
+                },
+            )
+            .collect()
+    }
+
+    /// Delete the live objects of `bucket` matching `rule` that were last
+    /// modified before `cutoff`.
+    async fn expire_objects(
+        &self,
+        bucket: &Bucket,
+        rule: &LifecycleRule,
+        cutoff: DateTime<Utc>,
+    ) -> StorageResult<u64> {
+        let prefix = rule.prefix.as_deref().unwrap_or_default();
+        let sealer = self.metadata_sealer(bucket)?;
+        let mut expired = 0;
+        let mut after: Option<String> = None;
+        loop {
+            // Range scan rather than LIKE, as in the prefix-delete job.
+            let mut query =
+                QueryBuilder::<Sqlite>::new("SELECT o.key FROM objects o WHERE o.bucket_id = ");
+            query.push_bind(bucket.id);
+            query.push(" AND o.is_deleted = 0 AND o.key >= ");
+            query.push_bind(prefix);
+            query.push(" AND o.last_modified < ");
+            query.push_bind(cutoff);
+            if let Some(after) = &after {
+                query.push(" AND o.key > ");
+                query.push_bind(after.clone());
+            }
+            push_tag_filter(&mut query, sealer.as_ref(), &rule.tags);
+            query.push(" ORDER BY o.key ASC LIMIT ");
+            query.push_bind(EXPIRE_PAGE);
+            let page: Vec<String> = query.build_query_scalar().fetch_all(&*self.db).await?;
+            let fetched = page.len();
+            let matching: Vec<String> = page
+                .into_iter()
+                .take_while(|key| key.starts_with(prefix))
+                .collect();
+            for key in &matching {
+                match self.delete_object(&bucket.name, key).await {
+                    Ok(_) => expired += 1,
+                    Err(StorageError::ObjectNotFound { .. }) => {}
+                    Err(err) => {
+                        warn!(
+                            "lifecycle rule `{}` could not expire {}/{}: {}",
+                            rule.id, bucket.name, key, err
+                        );
+                    }
+                }
+            }
+            if (fetched as i64) < EXPIRE_PAGE || matching.len() < fetched {
+                return Ok(expired);
+            }
+            after = matching.last().cloned();
+        }
+    }
+
+    /// Abort the multipart uploads of `bucket` under `rule`'s prefix that were
+    /// initiated before `cutoff`.
+    async fn abort_stale_uploads(
+        &self,
+        bucket: &Bucket,
+        rule: &LifecycleRule,
+        cutoff: DateTime<Utc>,
+    ) -> StorageResult<u64> {
+        let prefix = rule.prefix.as_deref().unwrap_or_default();
+        let uploads: Vec<(Uuid, String)> = sqlx::query_as(
+            "SELECT id, key FROM multipart_uploads
+             WHERE bucket_id = ? AND key >= ? AND initiated_at < ?
+             ORDER BY key ASC",
+        )
+        .bind(bucket.id)
+        .bind(prefix)
+        .bind(cutoff)
+        .fetch_all(&*self.db)
+        .await?;
+        let mut aborted = 0;
+        for (id, key) in uploads
+            .iter()
+            .take_while(|(_, key)| key.starts_with(prefix))
+        {
+            match self.abort_multipart_upload(&bucket.name, key, *id).await {
+                Ok(()) => aborted += 1,
+                Err(StorageError::NoSuchUpload(_)) => {}
+                Err(err) => warn!(
+                    "lifecycle rule `{}` could not abort upload {} of {}/{}: {}",
+                    rule.id, id, bucket.name, key, err
+                ),
+            }
+        }
+        Ok(aborted)
+    }
+}
+
+/// Restrict a query over `objects o` to objects carrying every one of `tags`.
+/// With the bucket's `sealer`, sealed values are matched by their hash.
+pub(crate) fn push_tag_filter(
+    query: &mut QueryBuilder<'_, Sqlite>,
+    sealer: Option<&MetadataSealer>,
+    tags: &[ObjectTag],
+) {
+    for tag in tags {
+        query.push(
+            " AND EXISTS (SELECT 1 FROM object_tags t
+               WHERE t.bucket_id = o.bucket_id AND t.key = o.key AND t.tag_key = ",
+        );
+        query.push_bind(tag.key.clone());
+        if let Some(sealer) = sealer {
+            query.push(" AND (t.value_hash = ");
+            query.push_bind(sealer.hash(&tag.key, &tag.value));
+            query.push(" OR t.value_hash IS NULL AND t.tag_value = ");
+            query.push_bind(tag.value.clone());
+            query.push(")");
+        } else {
+            query.push(" AND t.tag_value = ");
+            query.push_bind(tag.value.clone());
+        }
+        query.push(")");
+    }
+}
+
+/// Store `rules` for a bucket that has none.
+pub(crate) async fn insert_rules(
+    tx: &mut Transaction<'_, Sqlite>,
+    bucket_id: Uuid,
+    rules: &[LifecycleRule],
+) -> StorageResult<()> {
+    for (position, rule) in rules.iter().enumerate() {
+        let tags = (!rule.tags.is_empty())
+            .then(|| serde_json::to_string(&rule.tags))
+            .transpose()
+            .map_err(|err| StorageError::InvalidLifecycle(err.to_string()))?;
+        sqlx::query(
+            "INSERT INTO lifecycle_rules (
+                 bucket_id, rule_id, position, enabled, prefix, tags,
+                 expiration_days, abort_incomplete_days, transition_days,
+                 transition_storage_class
+             ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(bucket_id)
+        .bind(&rule.id)
+        .bind(position as i64)
+        .bind(rule.enabled)
+        .bind(&rule.prefix)
+        .bind(tags)
+        .bind(rule.expiration_days)
+        .bind(rule.abort_incomplete_days)
+        .bind(rule.transition.as_ref().map(|transition| transition.days))
+        .bind(
+            rule.transition
+                .as_ref()
+                .map(|transition| &transition.storage_class),
+        )
+        .execute(&mut **tx)
+        .await?;
+    }
+    Ok(())
+}
+
+pub(crate) fn validate_rules(rules: &[LifecycleRule]) -> StorageResult<()> {
+    if rules.is_empty() {
+        return Err(StorageError::InvalidLifecycle(
+            "at least one rule is required".into(),
+        ));
+    }
+    if rules.len() > MAX_LIFECYCLE_RULES {
+        return Err(StorageError::InvalidLifecycle(format!(
+            "at most {} rules are allowed",
+            MAX_LIFECYCLE_RULES
+        )));
+    }
+    let mut seen = HashSet::new();
+    for rule in rules {
+        if rule.id.is_empty() || rule.id.chars().count() > MAX_RULE_ID_LEN {
+            return Err(StorageError::InvalidLifecycle(format!(
+                "rule IDs must be 1 to {} characters",
+                MAX_RULE_ID_LEN
+            )));
+        }
+        if !seen.insert(rule.id.as_str()) {
+            return Err(StorageError::InvalidLifecycle(format!(
+                "rule ID `{}` is repeated",
+                rule.id
+            )));
+        }
+        if rule.expiration_days.is_none()
+            && rule.abort_incomplete_days.is_none()
+            && rule.transition.is_none()
+        {
+            return Err(StorageError::InvalidLifecycle(format!(
+                "rule `{}` has no action",
+                rule.id
+            )));
+        }
+        let transition_days = rule.transition.as_ref().map(|transition| transition.days);
+        if [
+            rule.expiration_days,
+            rule.abort_incomplete_days,
+            transition_days,
+        ]
+        .into_iter()
+        .flatten()
+        .any(|days| days < 1)
+        {
+            return Err(StorageError::InvalidLifecycle(format!(
+                "rule `{}`: days must be a positive integer",
+                rule.id
+            )));
+        }
+        if let Some(transition) = &rule.transition
+            && !TRANSITION_STORAGE_CLASSES.contains(&transition.storage_class.as_str())
+        {
+            return Err(StorageError::InvalidLifecycle(format!(
+                "rule `{}`: cannot transition to storage class `{}`",
+                rule.id, transition.storage_class
+            )));
+        }
+        if let (Some(expiration), Some(transition)) = (rule.expiration_days, transition_days)
+            && transition >= expiration
+        {
+            return Err(StorageError::InvalidLifecycle(format!(
+                "rule `{}`: Transition days must be fewer than Expiration days",
+                rule.id
+            )));
+        }
+        if !rule.tags.is_empty() && rule.abort_incomplete_days.is_some() {
+            return Err(StorageError::InvalidLifecycle(format!(
+                "rule `{}`: AbortIncompleteMultipartUpload cannot be combined with a tag filter",
+                rule.id
+            )));
+        }
+        validate_tags(&rule.tags)?;
+    }
+    Ok(())
+}
+
+/// Spawn a background task that applies lifecycle rules every `period`.
+pub fn spawn_lifecycle_worker(service: StorageService, period: Duration) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(period);
+        loop {
+            ticker.tick().await;
+            if let Err(err) = service.run_lifecycle().await {
+                warn!("lifecycle worker failed: {}", err);
+            }
+        }
+    })
+}
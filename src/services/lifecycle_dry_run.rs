@@ -0,0 +1,206 @@
+//! Lifecycle dry runs (`POST /{bucket}?lifecycle-dry-run`).
+//!
+//! Expiration rules are destructive, and a mistyped prefix or `Days` is
+//! only noticed once objects are gone. A dry run takes a candidate
+//! configuration, validated as `PUT ?lifecycle` would, and reports per rule
+//! what the lifecycle worker would do to the bucket if it ran now: how many
+//! objects (and bytes) would expire or move to the remote tier and how many
+//! multipart uploads would be aborted, with the first few keys of each.
+//! Nothing is stored or changed. A `prefix` narrows the run to keys under
+//! it on top of each rule's own filter, so a caller confined to a prefix
+//! learns nothing about keys outside it.
+//!
+//! Counts and samples come from aggregate queries with the worker's own
+//! conditions, so they use the same indexes as listings. Each rule is
+//! evaluated on its own: an object two rules match is counted under both.
+
+use crate::{
+    models::{bucket::Bucket, lifecycle::LifecycleRule},
+    services::{
+        keys,
+        lifecycle::push_tag_filter,
+        storage_service::{StorageResult, StorageService},
+        tiering::push_transition_filter,
+    },
+};
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use serde::Serialize;
+use sqlx::{QueryBuilder, Sqlite};
+
+/// Keys listed per action of a dry run.
+pub const DRY_RUN_SAMPLES: i64 = 10;
+
+/// What a candidate lifecycle configuration would do to a bucket now.
+#[derive(Debug, Serialize)]
+pub struct LifecycleDryRun {
+    pub bucket: String,
+    pub evaluated_at: DateTime<Utc>,
+    /// The worker skips read-only buckets, so nothing happens while set.
+    pub read_only: bool,
+    pub rules: Vec<RuleDryRun>,
+}
+
+/// One rule of a dry run. Disabled rules and actions the rule does not
+/// have are left out (`null`).
+#[derive(Debug, Serialize)]
+pub struct RuleDryRun {
+    pub id: String,
+    pub enabled: bool,
+    pub expire: Option<ActionDryRun>,
+    pub transition: Option<ActionDryRun>,
+    pub abort_incomplete_uploads: Option<ActionDryRun>,
+}
+
+/// Objects (or uploads) one action would apply to.
+#[derive(Debug, Default, Serialize)]
+pub struct ActionDryRun {
+    pub count: u64,
+    pub bytes: u64,
+    /// The first `DRY_RUN_SAMPLES` keys, in key order.
+    pub samples: Vec<String>,
+}
+
+impl StorageService {
+    /// Evaluate `rules` against the keys of `bucket` under `scope` (all of
+    /// them without one) without applying or storing the rules.
+    pub async fn lifecycle_dry_run(
+        &self,
+        bucket: &str,
+        scope: Option<&str>,
+        rules: &[LifecycleRule],
+    ) -> StorageResult<LifecycleDryRun> {
+        self.validate_lifecycle(rules)?;
+        let scope = keys::prefix_glob(scope.unwrap_or_default());
+        let bucket_rec = self.fetch_bucket(bucket).await?;
+        let now = Utc::now();
+        let mut report = Vec::with_capacity(rules.len());
+        for rule in rules {
+            let mut dry_run = RuleDryRun {
+                id: rule.id.clone(),
+                enabled: rule.enabled,
+                expire: None,
+                transition: None,
+                abort_incomplete_uploads: None,
+            };
+            if rule.enabled {
+                if let Some(days) = rule.expiration_days {
+                    let cutoff = now - ChronoDuration::days(days);
+                    dry_run.expire = Some(
+                        self.matching_objects(&bucket_rec, &scope, rule, |query| {
+                            query.push(" AND o.last_modified < ");
+                            query.push_bind(cutoff);
+                        })
+                        .await?,
+                    );
+                }
+                if let Some(transition) = &rule.transition {
+                    let cutoff = now - ChronoDuration::days(transition.days);
+                    dry_run.transition = Some(
+                        self.matching_objects(&bucket_rec, &scope, rule, |query| {
+                            push_transition_filter(query, cutoff, &transition.storage_class)
+                        })
+                        .await?,
+                    );
+                }
+                if let Some(days) = rule.abort_incomplete_days {
+                    let cutoff = now - ChronoDuration::days(days);
+                    dry_run.abort_incomplete_uploads = Some(
+                        self.stale_uploads(&bucket_rec, &scope, rule, cutoff)
+                            .await?,
+                    );
+                }
+            }
+            report.push(dry_run);
+        }
+        Ok(LifecycleDryRun {
+            bucket: bucket_rec.name,
+            evaluated_at: now,
+            read_only: bucket_rec.read_only,
+            rules: report,
+        })
+    }
+
+    /// Live objects of `bucket` matching the `scope` glob and `rule`'s
+    /// filter that `condition` also holds for.
+    async fn matching_objects(
+        &self,
+        bucket: &Bucket,
+        scope: &str,
+        rule: &LifecycleRule,
+        condition: impl Fn(&mut QueryBuilder<'_, Sqlite>),
+    ) -> StorageResult<ActionDryRun> {
+        let sealer = self.metadata_sealer(bucket)?;
+        let filtered = |select: &str| {
+            let mut query = QueryBuilder::<Sqlite>::new(select);
+            query.push(" FROM objects o WHERE o.bucket_id = ");
+            query.push_bind(bucket.id);
+            query.push(" AND o.is_deleted = 0 AND o.key GLOB ");
+            query.push_bind(scope);
+            if let Some(prefix) = rule.prefix.as_deref() {
+                query.push(" AND o.key GLOB ");
+                query.push_bind(keys::prefix_glob(prefix));
+            }
+            push_tag_filter(&mut query, sealer.as_ref(), &rule.tags);
+            condition(&mut query);
+            query
+        };
+        let (count, bytes): (i64, i64) =
+            filtered("SELECT COUNT(*), COALESCE(SUM(o.size_bytes), 0)")
+                .build_query_as()
+                .fetch_one(&*self.db)
+                .await?;
+        let mut query = filtered("SELECT o.key");
+        query.push(" ORDER BY o.key ASC LIMIT ");
+        query.push_bind(DRY_RUN_SAMPLES);
+        let samples = query.build_query_scalar().fetch_all(&*self.db).await?;
+        Ok(ActionDryRun {
+            count: count.max(0) as u64,
+            bytes: bytes.max(0) as u64,
+            samples,
+        })
+    }
+
+    /// Multipart uploads of `bucket` matching the `scope` glob under
+    /// `rule`'s prefix initiated before `cutoff`, with the bytes of their
+    /// uploaded parts.
+    async fn stale_uploads(
+        &self,
+        bucket: &Bucket,
+        scope: &str,
+        rule: &LifecycleRule,
+        cutoff: DateTime<Utc>,
+    ) -> StorageResult<ActionDryRun> {
+        let glob = keys::prefix_glob(rule.prefix.as_deref().unwrap_or_default());
+        let (count, bytes): (i64, i64) = sqlx::query_as(
+            "SELECT COUNT(*), COALESCE(SUM(
+                 (SELECT COALESCE(SUM(p.size_bytes), 0) FROM multipart_parts p
+                  WHERE p.upload_id = u.id)), 0)
+             FROM multipart_uploads u
+             WHERE u.bucket_id = ? AND u.key GLOB ? AND u.key GLOB ?
+               AND u.initiated_at < ?",
+        )
+        .bind(bucket.id)
+        .bind(scope)
+        .bind(&glob)
+        .bind(cutoff)
+        .fetch_one(&*self.db)
+        .await?;
+        let samples = sqlx::query_scalar(
+            "SELECT key FROM multipart_uploads
+             WHERE bucket_id = ? AND key GLOB ? AND key GLOB ? AND initiated_at < ?
+             ORDER BY key ASC LIMIT ?",
+        )
+        .bind(bucket.id)
+        .bind(scope)
+        .bind(&glob)
+        .bind(cutoff)
+        .bind(DRY_RUN_SAMPLES)
+        .fetch_all(&*self.db)
+        .await?;
+        Ok(ActionDryRun {
+            count: count.max(0) as u64,
+            bytes: bytes.max(0) as u64,
+            samples,
+        })
+    }
+}
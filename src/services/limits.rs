@@ -0,0 +1,158 @@
+//! Effective server limits and capabilities.
+//!
+//! Clients and test harnesses otherwise hard-code assumptions (part sizes,
+//! list page sizes, which checksums come back) that differ between this
+//! server and S3, or between two deployments with different options. The
+//! report is built from the same constants and `StorageOptions` the request
+//! paths enforce, so it cannot drift from actual behaviour.
+//!
+//! `GET /limits` serves the [`ServerLimits`] every client may see;
+//! `GET /admin/limits` adds the operator tunables in [`AdminLimits`].
+
+use crate::services::{
+    batch_delete::MAX_DELETE_KEYS,
+    block_cache::CACHE_BLOCK_SIZE,
+    keys::MAX_OBJECT_KEY_LEN,
+    multipart::{DEFAULT_MAX_PARTS, MAX_PART_NUMBER, MIN_PART_SIZE},
+    partition::MAX_LIST_PARTITIONS,
+    quota::Quota,
+    storage_service::{
+        BUCKET_NAME_MAX_LEN, BUCKET_NAME_MIN_LEN, MAX_LIST_KEYS, SUPPORTED_REGIONS, StorageService,
+    },
+    versioning::DEFAULT_MAX_VERSION_KEYS,
+};
+use serde::Serialize;
+
+/// Limits and capabilities relevant to any client.
+#[derive(Debug, Clone, Serialize)]
+pub struct ServerLimits {
+    /// Largest single object in bytes; `null` when only disk space limits it.
+    pub max_object_size: Option<u64>,
+    /// Largest request body in bytes; `null` when unlimited.
+    pub max_request_body: Option<u64>,
+    pub max_key_length: usize,
+    pub bucket_name_length: LengthBounds,
+    pub max_keys_per_list: usize,
+    pub max_keys_per_version_list: usize,
+    pub max_keys_per_delete: usize,
+    pub max_list_partitions: usize,
+    pub multipart: MultipartLimits,
+    /// Checksums returned on stored objects (`MD5` is the ETag of
+    /// single-part uploads).
+    pub checksum_algorithms: Vec<&'static str>,
+    pub regions: Vec<&'static str>,
+    /// Optional behaviour switched on for this deployment.
+    pub features: Vec<&'static str>,
+    /// API groups refused with 403 (`OBJECT_STORE_DISABLED_APIS`).
+    pub disabled_apis: Vec<&'static str>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct LengthBounds {
+    pub min: usize,
+    pub max: usize,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct MultipartLimits {
+    /// Smallest size of every part but the last.
+    pub min_part_size: u64,
+    /// Largest part; `null` when unbounded.
+    pub max_part_size: Option<u64>,
+    pub max_part_number: u32,
+    pub max_parts_per_list: usize,
+}
+
+/// `ServerLimits` plus operator tunables.
+#[derive(Debug, Clone, Serialize)]
+pub struct AdminLimits {
+    #[serde(flatten)]
+    pub limits: ServerLimits,
+    pub overwrite_retention_secs: Option<u64>,
+    pub mmap_read_threshold: Option<u64>,
+    pub range_cache_bytes: Option<u64>,
+    pub range_cache_block_size: u64,
+    pub volume_failure_threshold: Option<u64>,
+    /// Store-wide quota (see `quota`).
+    pub global_quota: Quota,
+}
+
+impl StorageService {
+    /// Limits in effect for this service; `disabled_apis` comes from the
+    /// feature-flag layer, which the service does not know about.
+    pub fn limits(&self, disabled_apis: Vec<&'static str>) -> ServerLimits {
+        let mut checksum_algorithms = vec!["MD5"];
+        if self.options.compute_sha256 {
+            checksum_algorithms.push("SHA256");
+        }
+        let mut features = vec![
+            "versioning",
+            "conditional-requests",
+            "range-requests",
+            "delete-objects",
+        ];
+        if self.options.decode_content_encoding {
+            features.push("decode-content-encoding");
+        }
+        if self.options.overwrite_retention.is_some() {
+            features.push("overwrite-recovery");
+        }
+        if self.options.access_analytics {
+            features.push("storage-class-analysis");
+        }
+        if self.options.verify_read_checksums {
+            features.push("read-checksum-verification");
+        }
+        if self.options.dedup {
+            features.push("dedup");
+        }
+        if self.options.manifest_key.is_some() {
+            features.push("signed-manifests");
+        }
+        if self.options.session_key.is_some() {
+            features.push("session-tokens");
+        }
+        if cfg!(feature = "nats") {
+            features.push("nats-publishers");
+        }
+        if cfg!(feature = "kafka") {
+            features.push("kafka-publishers");
+        }
+        ServerLimits {
+            max_object_size: self.options.max_object_size,
+            max_request_body: self.options.max_request_body,
+            max_key_length: MAX_OBJECT_KEY_LEN,
+            bucket_name_length: LengthBounds {
+                min: BUCKET_NAME_MIN_LEN,
+                max: BUCKET_NAME_MAX_LEN,
+            },
+            max_keys_per_list: MAX_LIST_KEYS,
+            max_keys_per_version_list: DEFAULT_MAX_VERSION_KEYS,
+            max_keys_per_delete: MAX_DELETE_KEYS,
+            max_list_partitions: MAX_LIST_PARTITIONS,
+            multipart: MultipartLimits {
+                min_part_size: MIN_PART_SIZE as u64,
+                max_part_size: self.options.max_object_size,
+                max_part_number: MAX_PART_NUMBER as u32,
+                max_parts_per_list: DEFAULT_MAX_PARTS,
+            },
+            checksum_algorithms,
+            regions: SUPPORTED_REGIONS.to_vec(),
+            features,
+            disabled_apis,
+        }
+    }
+
+    /// Limits plus the tunables behind them.
+    pub fn admin_limits(&self, disabled_apis: Vec<&'static str>) -> AdminLimits {
+        AdminLimits {
+            limits: self.limits(disabled_apis),
+            overwrite_retention_secs: self.options.overwrite_retention.map(|d| d.as_secs()),
+            mmap_read_threshold: self.options.mmap_read_threshold,
+            range_cache_bytes: self.options.range_cache_bytes,
+            range_cache_block_size: CACHE_BLOCK_SIZE,
+            volume_failure_threshold: self.options.volume_failure_threshold,
+            global_quota: self.options.global_quota,
+        }
+    }
+}
@@ -0,0 +1,92 @@
+//! Unpaginated listings streamed as NDJSON (`GET /{bucket}?list-stream`).
+//!
+//! Backup and sync tools that enumerate a whole bucket spend most of their
+//! time on round trips when every page holds 1000 keys. `list_stream` walks
+//! the matching keys in `LIST_STREAM_PAGE`-sized pages itself, keeping each
+//! page's continuation token as the cursor for the next page, and yields one
+//! `ListedObject` per key. The cursor lives in the stream, so it lasts as
+//! long as the response and nothing is kept once the client disconnects.
+//!
+//! The first page is read before the stream is returned, so a missing bucket
+//! or an invalid filter is still reported with a status code. A failure after
+//! that can only end the body early.
+
+use crate::{
+    models::object::Object,
+    services::storage_service::{ListObjectsParams, StorageError, StorageResult, StorageService},
+};
+use chrono::{DateTime, Utc};
+use futures::{Stream, TryStreamExt, stream};
+use serde::Serialize;
+
+/// Keys fetched from the database per page of a streamed listing.
+pub const LIST_STREAM_PAGE: usize = 1000;
+
+/// One line of a streamed listing.
+#[derive(Debug, Clone, Serialize)]
+pub struct ListedObject {
+    pub key: String,
+    pub size: u64,
+    pub etag: Option<String>,
+    pub last_modified: DateTime<Utc>,
+    pub storage_class: String,
+}
+
+impl From<Object> for ListedObject {
+    fn from(object: Object) -> Self {
+        ListedObject {
+            key: object.key,
+            size: object.size_bytes.max(0) as u64,
+            etag: object.etag,
+            last_modified: object.last_modified,
+            storage_class: object.storage_class,
+        }
+    }
+}
+
+impl StorageService {
+    /// Every object of `bucket` matching `params` (prefix, start-after,
+    /// end-key and the size/storage-class filters), in key order. Delimiters
+    /// are not supported; `max_keys` and `continuation_token` are ignored.
+    pub async fn list_stream(
+        &self,
+        bucket: &str,
+        mut params: ListObjectsParams,
+    ) -> StorageResult<impl Stream<Item = StorageResult<ListedObject>> + Send + 'static> {
+        if params.delimiter.is_some() {
+            return Err(StorageError::InvalidContent(
+                "list-stream does not support delimiter".to_string(),
+            ));
+        }
+        params.continuation_token = None;
+        params.max_keys = LIST_STREAM_PAGE;
+        let first = self.list_objects_v2(bucket, params.clone()).await?;
+
+        let service = self.clone();
+        let bucket = bucket.to_string();
+        let pages = stream::try_unfold((Some(first), Some(params)), move |(page, params)| {
+            let service = service.clone();
+            let bucket = bucket.clone();
+            async move {
+                let Some(mut params) = params else {
+                    return Ok(None);
+                };
+                let page = match page {
+                    Some(page) => page,
+                    None => service.list_objects_v2(&bucket, params.clone()).await?,
+                };
+                let next = match page.next_continuation_token {
+                    Some(token) if page.is_truncated => {
+                        params.continuation_token = Some(token);
+                        Some(params)
+                    }
+                    _ => None,
+                };
+                Ok::<_, StorageError>(Some((page.objects, (None, next))))
+            }
+        });
+        Ok(pages
+            .map_ok(|objects| stream::iter(objects.into_iter().map(ListedObject::from).map(Ok)))
+            .try_flatten())
+    }
+}
@@ -0,0 +1,44 @@
+//! Runtime control of the tracing filter.
+//!
+//! `main` installs the `RUST_LOG` / `OBJECT_STORE_LOG` filter behind a
+//! `tracing_subscriber::reload` layer and hands its handle to the router, so
+//! `PUT /admin/log-level` can swap in another filter (for instance
+//! `info,object_store::services::storage_service=debug` while diagnosing an
+//! incident) without a restart. The change lasts until the process exits.
+
+use crate::services::storage_service::{StorageError, StorageResult};
+use std::{fmt, io};
+use tracing::info;
+use tracing_subscriber::{EnvFilter, Registry, reload};
+
+/// Handle to the process-wide log filter.
+#[derive(Clone)]
+pub struct LogFilter(reload::Handle<EnvFilter, Registry>);
+
+impl fmt::Debug for LogFilter {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_tuple("LogFilter").field(&self.current()).finish()
+    }
+}
+
+impl LogFilter {
+    pub fn new(handle: reload::Handle<EnvFilter, Registry>) -> Self {
+        LogFilter(handle)
+    }
+
+    /// The active filter directives, `None` once the subscriber is gone.
+    pub fn current(&self) -> Option<String> {
+        self.0.with_current(|filter| filter.to_string()).ok()
+    }
+
+    /// Replace the active filter with `directives` (`EnvFilter` syntax).
+    pub fn set(&self, directives: &str) -> StorageResult<String> {
+        let filter = EnvFilter::try_new(directives)
+            .map_err(|err| StorageError::InvalidContent(format!("invalid log filter: {}", err)))?;
+        let previous = self.current().unwrap_or_default();
+        self.0.reload(filter).map_err(io::Error::other)?;
+        let current = self.current().unwrap_or_default();
+        info!("log filter changed from `{}` to `{}`", previous, current);
+        Ok(current)
+    }
+}
@@ -0,0 +1,286 @@
+//! Signed content manifests for integrity attestation.
+//!
+//! A manifest lists every live object of a bucket (or of a prefix in it)
+//! with its size, version and a SHA-256 computed from the stored bytes, and
+//! carries an HMAC-SHA256 signature made with the server's manifest key. A
+//! release pipeline exports one after publishing artifacts and later hands
+//! it back to `verify_manifest`, which checks the signature and then compares
+//! the bucket's current contents entry by entry.
+//!
+//! The signature covers the compact JSON of the manifest without its
+//! `signature` field, fields in the order they are declared below.
+
+use crate::services::storage_service::{StorageError, StorageResult, StorageService};
+use base64::{Engine as _, engine::general_purpose};
+use chrono::{DateTime, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use std::{collections::BTreeMap, fmt, io, path::Path, str::FromStr};
+use tokio::{fs::File, io::AsyncReadExt};
+use uuid::Uuid;
+
+/// Keys fetched per query while walking a bucket.
+const MANIFEST_PAGE: i64 = 1000;
+
+/// Signature scheme recorded in every manifest.
+pub const MANIFEST_ALGORITHM: &str = "HMAC-SHA256";
+
+/// Secret used to sign and verify manifests. `Debug` never prints it.
+#[derive(Clone)]
+pub struct ManifestKey(Vec<u8>);
+
+impl fmt::Debug for ManifestKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("ManifestKey(<redacted>)")
+    }
+}
+
+impl FromStr for ManifestKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() < 16 {
+            return Err("manifest signing key must be at least 16 bytes".into());
+        }
+        Ok(Self(s.as_bytes().to_vec()))
+    }
+}
+
+impl ManifestKey {
+    fn mac(&self) -> Hmac<Sha256> {
+        Hmac::<Sha256>::new_from_slice(&self.0).expect("HMAC accepts keys of any length")
+    }
+}
+
+/// One object as recorded in a manifest.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ManifestEntry {
+    pub key: String,
+    pub size: i64,
+    /// Base64 SHA-256 of the stored payload.
+    pub sha256: String,
+    pub version_id: Option<String>,
+}
+
+/// The signed part of a manifest.
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct ManifestBody {
+    pub bucket: String,
+    pub prefix: Option<String>,
+    pub generated_at: DateTime<Utc>,
+    pub algorithm: String,
+    pub entries: Vec<ManifestEntry>,
+}
+
+/// A manifest and its signature (base64).
+#[derive(Debug, Clone, Serialize, Deserialize)]
+pub struct Manifest {
+    #[serde(flatten)]
+    pub body: ManifestBody,
+    pub signature: String,
+}
+
+/// Why a manifest entry no longer describes the bucket.
+#[derive(Debug, Clone, Serialize)]
+pub struct ManifestMismatch {
+    pub key: String,
+    pub reason: String,
+}
+
+/// Outcome of checking a manifest against the bucket.
+#[derive(Debug, Clone, Serialize)]
+pub struct ManifestVerification {
+    /// Signature valid and every entry matched, with nothing extra.
+    pub ok: bool,
+    pub signature_valid: bool,
+    pub checked: usize,
+    pub matched: usize,
+    /// Keys whose size, version or content changed.
+    pub mismatched: Vec<ManifestMismatch>,
+    /// Keys in the manifest that no longer exist.
+    pub missing: Vec<String>,
+    /// Keys under the manifest's prefix that it does not list.
+    pub unexpected: Vec<String>,
+}
+
+impl StorageService {
+    /// Build and sign a manifest of the live objects in `bucket` under
+    /// `prefix`, hashing every payload from disk.
+    pub async fn export_manifest(
+        &self,
+        bucket: &str,
+        prefix: Option<&str>,
+    ) -> StorageResult<Manifest> {
+        let key = self.manifest_key()?;
+        let bucket_rec = self.fetch_bucket(bucket).await?;
+        let mut entries = Vec::new();
+        for (object_key, size, version_id) in self.live_keys(bucket_rec.id, prefix).await? {
+            let path = self.live_path(&bucket_rec, &object_key).await?;
+            entries.push(ManifestEntry {
+                sha256: sha256_file(&path).await?,
+                key: object_key,
+                size,
+                version_id,
+            });
+        }
+        let body = ManifestBody {
+            bucket: bucket_rec.name,
+            prefix: prefix.map(str::to_string),
+            generated_at: Utc::now(),
+            algorithm: MANIFEST_ALGORITHM.to_string(),
+            entries,
+        };
+        let signature = sign(key, &body)?;
+        Ok(Manifest { body, signature })
+    }
+
+    /// Check `manifest`'s signature and compare its entries with the current
+    /// contents of `bucket`.
+    pub async fn verify_manifest(
+        &self,
+        bucket: &str,
+        manifest: &Manifest,
+    ) -> StorageResult<ManifestVerification> {
+        let key = self.manifest_key()?;
+        let bucket_rec = self.fetch_bucket(bucket).await?;
+        if manifest.body.bucket != bucket_rec.name {
+            return Err(StorageError::InvalidContent(format!(
+                "manifest is for bucket `{}`",
+                manifest.body.bucket
+            )));
+        }
+        if manifest.body.algorithm != MANIFEST_ALGORITHM {
+            return Err(StorageError::InvalidContent(format!(
+                "unsupported manifest algorithm `{}`",
+                manifest.body.algorithm
+            )));
+        }
+        let signature_valid = general_purpose::STANDARD
+            .decode(&manifest.signature)
+            .is_ok_and(|signature| {
+                let mut mac = key.mac();
+                mac.update(&canonical_bytes(&manifest.body).unwrap_or_default());
+                mac.verify_slice(&signature).is_ok()
+            });
+
+        let mut current: BTreeMap<String, (i64, Option<String>)> = self
+            .live_keys(bucket_rec.id, manifest.body.prefix.as_deref())
+            .await?
+            .into_iter()
+            .map(|(key, size, version_id)| (key, (size, version_id)))
+            .collect();
+        let mut report = ManifestVerification {
+            ok: false,
+            signature_valid,
+            checked: manifest.body.entries.len(),
+            matched: 0,
+            mismatched: Vec::new(),
+            missing: Vec::new(),
+            unexpected: Vec::new(),
+        };
+        for entry in &manifest.body.entries {
+            let Some((size, version_id)) = current.remove(&entry.key) else {
+                report.missing.push(entry.key.clone());
+                continue;
+            };
+            let reason = if size != entry.size {
+                Some(format!("size is {}, manifest says {}", size, entry.size))
+            } else if version_id != entry.version_id {
+                Some(format!(
+                    "version is {}, manifest says {}",
+                    version_id.as_deref().unwrap_or("null"),
+                    entry.version_id.as_deref().unwrap_or("null")
+                ))
+            } else {
+                let path = self.live_path(&bucket_rec, &entry.key).await?;
+                let sha256 = sha256_file(&path).await?;
+                (sha256 != entry.sha256)
+                    .then(|| format!("SHA-256 is {}, manifest says {}", sha256, entry.sha256))
+            };
+            match reason {
+                Some(reason) => report.mismatched.push(ManifestMismatch {
+                    key: entry.key.clone(),
+                    reason,
+                }),
+                None => report.matched += 1,
+            }
+        }
+        report.unexpected = current.into_keys().collect();
+        report.ok = report.signature_valid
+            && report.mismatched.is_empty()
+            && report.missing.is_empty()
+            && report.unexpected.is_empty();
+        Ok(report)
+    }
+
+    fn manifest_key(&self) -> StorageResult<&ManifestKey> {
+        self.options.manifest_key.as_ref().ok_or_else(|| {
+            StorageError::InvalidContent("no manifest signing key is configured".into())
+        })
+    }
+
+    /// Live keys of a bucket under `prefix`, in key order, with size and
+    /// version.
+    async fn live_keys(
+        &self,
+        bucket_id: Uuid,
+        prefix: Option<&str>,
+    ) -> StorageResult<Vec<(String, i64, Option<String>)>> {
+        let prefix = prefix.unwrap_or_default();
+        let mut keys = Vec::new();
+        let mut after: Option<String> = None;
+        loop {
+            // Range scan rather than LIKE, as in the prefix-delete job.
+            let page: Vec<(String, i64, Option<String>)> = sqlx::query_as(
+                "SELECT key, size_bytes, version_id FROM objects
+                 WHERE bucket_id = ? AND is_deleted = 0 AND key >= ?
+                   AND (? IS NULL OR key > ?)
+                 ORDER BY key ASC LIMIT ?",
+            )
+            .bind(bucket_id)
+            .bind(prefix)
+            .bind(&after)
+            .bind(&after)
+            .bind(MANIFEST_PAGE)
+            .fetch_all(&*self.db)
+            .await?;
+            let fetched = page.len();
+            let matching: Vec<_> = page
+                .into_iter()
+                .take_while(|(key, _, _)| key.starts_with(prefix))
+                .collect();
+            let done = (fetched as i64) < MANIFEST_PAGE || matching.len() < fetched;
+            after = matching.last().map(|(key, _, _)| key.clone());
+            keys.extend(matching);
+            if done {
+                return Ok(keys);
+            }
+        }
+    }
+}
+
+fn canonical_bytes(body: &ManifestBody) -> StorageResult<Vec<u8>> {
+    serde_json::to_vec(body).map_err(|err| StorageError::Io(io::Error::from(err)))
+}
+
+fn sign(key: &ManifestKey, body: &ManifestBody) -> StorageResult<String> {
+    let mut mac = key.mac();
+    mac.update(&canonical_bytes(body)?);
+    Ok(general_purpose::STANDARD.encode(mac.finalize().into_bytes()))
+}
+
+/// Base64 SHA-256 of the file at `path`.
+async fn sha256_file(path: &Path) -> StorageResult<String> {
+    let mut file = File::open(path).await?;
+    let mut hasher = Sha256::new();
+    let mut buf = vec![0u8; 64 * 1024];
+    loop {
+        let read = file.read(&mut buf).await?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buf[..read]);
+    }
+    Ok(general_purpose::STANDARD.encode(hasher.finalize()))
+}
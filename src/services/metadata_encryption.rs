@@ -0,0 +1,365 @@
+//! Encryption at rest of user metadata and tag values.
+//!
+//! A bucket with `encrypt_metadata` set (`PATCH /admin/buckets/{bucket}`)
+//! keeps the values of its `x-amz-meta-*` entries and tags in the database
+//! only as AES-256-GCM ciphertext, so a dump of the database does not reveal
+//! them. Metadata names and tag keys stay readable.
+//!
+//! Keys form a two-level hierarchy: the server's
+//! `OBJECT_STORE_METADATA_ENCRYPTION_KEY` derives, per bucket, a cipher key
+//! and a hash key (HMAC-SHA256 over a label and the bucket id); neither is
+//! stored. Each value is sealed with a random nonce and bound to its object
+//! key and name, so ciphertext moved to another row does not open. Next to
+//! it, `value_hash` holds an HMAC of name and value under the bucket's hash
+//! key: lifecycle and tiering tag filters match on it without decrypting.
+//! The hash is deterministic, so equal values in one bucket are recognisable
+//! as equal; that is the price of filtering on them.
+//!
+//! Rows with a `value_hash` are sealed, whatever the bucket's current
+//! setting, and turning the setting on or off converts the bucket's rows.
+//! Tagging and metadata of pending multipart uploads are sealed as a whole,
+//! marked by `SEALED_PREFIX`. Starting without the key while a bucket is
+//! encrypted is refused; with a different key, reads of sealed values fail.
+
+use crate::{
+    models::bucket::Bucket,
+    services::{
+        aws_chunked::hex,
+        events::EventKind,
+        storage_service::{BUCKET_COLUMNS, StorageError, StorageResult, StorageService},
+    },
+};
+use aes_gcm::{
+    Aes256Gcm, KeyInit, Nonce,
+    aead::{Aead, AeadCore, OsRng, Payload},
+};
+use base64::{Engine as _, engine::general_purpose::STANDARD};
+use hmac::{Hmac, Mac};
+use sha2::Sha256;
+use sqlx::{Sqlite, Transaction};
+use std::{fmt, str::FromStr};
+use tracing::info;
+use uuid::Uuid;
+
+/// Marks a sealed string stored where a plaintext one could be. Neither the
+/// `x-amz-tagging` form (percent-encoded `k=v` pairs) nor JSON starts with it.
+pub(crate) const SEALED_PREFIX: &str = "sealed:";
+
+const NONCE_LEN: usize = 12;
+
+/// Master secret the per-bucket metadata keys are derived from. `Debug`
+/// never prints it.
+#[derive(Clone)]
+pub struct MetadataKey(Vec<u8>);
+
+impl fmt::Debug for MetadataKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("MetadataKey(<redacted>)")
+    }
+}
+
+impl FromStr for MetadataKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() < 16 {
+            return Err("metadata encryption key must be at least 16 bytes".into());
+        }
+        Ok(Self(s.as_bytes().to_vec()))
+    }
+}
+
+impl MetadataKey {
+    /// The keys of one bucket.
+    pub(crate) fn for_bucket(&self, bucket_id: Uuid) -> MetadataSealer {
+        let derive = |label: &str| -> [u8; 32] {
+            let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&self.0)
+                .expect("HMAC accepts keys of any length");
+            mac.update(label.as_bytes());
+            mac.update(bucket_id.as_bytes());
+            mac.finalize().into_bytes().into()
+        };
+        MetadataSealer {
+            cipher: Aes256Gcm::new(&derive("metadata-cipher\0").into()),
+            hash_key: derive("metadata-hash\0"),
+        }
+    }
+}
+
+/// Seals, opens and hashes the metadata values of one bucket.
+pub(crate) struct MetadataSealer {
+    cipher: Aes256Gcm,
+    hash_key: [u8; 32],
+}
+
+impl MetadataSealer {
+    /// Encrypt `value` for the row `context` names.
+    pub(crate) fn seal(&self, context: &str, value: &str) -> String {
+        let nonce = Aes256Gcm::generate_nonce(&mut OsRng);
+        let sealed = self
+            .cipher
+            .encrypt(
+                &nonce,
+                Payload {
+                    msg: value.as_bytes(),
+                    aad: context.as_bytes(),
+                },
+            )
+            .expect("AES-GCM encrypts values of any metadata size");
+        let mut bytes = nonce.to_vec();
+        bytes.extend_from_slice(&sealed);
+        STANDARD.encode(bytes)
+    }
+
+    /// Decrypt what `seal` produced for the same `context`.
+    pub(crate) fn open(&self, context: &str, sealed: &str) -> StorageResult<String> {
+        let unreadable = || StorageError::SealedMetadata(context.replace('\0', "/"));
+        let bytes = STANDARD.decode(sealed).map_err(|_| unreadable())?;
+        if bytes.len() < NONCE_LEN {
+            return Err(unreadable());
+        }
+        let (nonce, ciphertext) = bytes.split_at(NONCE_LEN);
+        let nonce: [u8; NONCE_LEN] = nonce.try_into().map_err(|_| unreadable())?;
+        let plain = self
+            .cipher
+            .decrypt(
+                &Nonce::from(nonce),
+                Payload {
+                    msg: ciphertext,
+                    aad: context.as_bytes(),
+                },
+            )
+            .map_err(|_| unreadable())?;
+        String::from_utf8(plain).map_err(|_| unreadable())
+    }
+
+    /// Deterministic hash of a name/value pair, for equality filters.
+    pub(crate) fn hash(&self, name: &str, value: &str) -> String {
+        let mut mac = <Hmac<Sha256> as Mac>::new_from_slice(&self.hash_key)
+            .expect("HMAC accepts keys of any length");
+        mac.update(name.as_bytes());
+        mac.update(b"\0");
+        mac.update(value.as_bytes());
+        hex(&mac.finalize().into_bytes())
+    }
+}
+
+/// Context binding a tag value to its row.
+pub(crate) fn tag_context(key: &str, tag_key: &str) -> String {
+    format!("tag\0{}\0{}", key, tag_key)
+}
+
+/// Context binding a user metadata value to its row.
+pub(crate) fn metadata_context(key: &str, name: &str) -> String {
+    format!("meta\0{}\0{}", key, name)
+}
+
+/// Context binding a pending multipart upload's field to the upload.
+pub(crate) fn upload_context(upload_id: Uuid, field: &str) -> String {
+    format!("upload\0{}\0{}", upload_id, field)
+}
+
+/// Builds the context of a row from its object key and name.
+type RowContext = fn(&str, &str) -> String;
+
+/// Table, name column and value column of each sealed row kind, with the
+/// context of a row.
+const SEALED_TABLES: [(&str, &str, &str, RowContext); 2] = [
+    ("object_tags", "tag_key", "tag_value", tag_context),
+    ("object_metadata", "name", "value", metadata_context),
+];
+
+impl StorageService {
+    /// The sealer for writes to `bucket`, `None` when it keeps metadata in
+    /// the clear.
+    pub(crate) fn metadata_sealer(&self, bucket: &Bucket) -> StorageResult<Option<MetadataSealer>> {
+        if !bucket.encrypt_metadata {
+            return Ok(None);
+        }
+        self.bucket_sealer(bucket.id).map(Some)
+    }
+
+    fn bucket_sealer(&self, bucket_id: Uuid) -> StorageResult<MetadataSealer> {
+        self.options
+            .metadata_key
+            .as_ref()
+            .map(|key| key.for_bucket(bucket_id))
+            .ok_or_else(|| {
+                StorageError::SealedMetadata("no metadata encryption key is configured".into())
+            })
+    }
+
+    /// Plaintext of a stored value; rows with a `value_hash` are sealed.
+    pub(crate) fn open_value(
+        &self,
+        bucket_id: Uuid,
+        context: &str,
+        value: String,
+        value_hash: Option<&str>,
+    ) -> StorageResult<String> {
+        match value_hash {
+            None => Ok(value),
+            Some(_) => self.bucket_sealer(bucket_id)?.open(context, &value),
+        }
+    }
+
+    /// Plaintext of a pending multipart upload field stored by
+    /// `create_multipart_upload`.
+    pub(crate) fn open_upload_field(
+        &self,
+        bucket_id: Uuid,
+        upload_id: Uuid,
+        field: &str,
+        stored: &str,
+    ) -> StorageResult<String> {
+        match stored.strip_prefix(SEALED_PREFIX) {
+            None => Ok(stored.to_string()),
+            Some(sealed) => self
+                .bucket_sealer(bucket_id)?
+                .open(&upload_context(upload_id, field), sealed),
+        }
+    }
+
+    /// Refuse to start when a bucket's metadata is sealed but no key is
+    /// configured to open it.
+    pub async fn check_metadata_key(&self) -> StorageResult<()> {
+        if self.options.metadata_key.is_some() {
+            return Ok(());
+        }
+        let encrypted: Vec<String> =
+            sqlx::query_scalar("SELECT name FROM buckets WHERE encrypt_metadata = 1 ORDER BY name")
+                .fetch_all(&*self.db)
+                .await?;
+        if encrypted.is_empty() {
+            return Ok(());
+        }
+        Err(StorageError::SealedMetadata(format!(
+            "buckets {} encrypt their metadata but no metadata encryption key is configured",
+            encrypted.join(", ")
+        )))
+    }
+
+    /// Turn encryption of a bucket's user metadata and tag values on or off,
+    /// converting the values already stored.
+    pub async fn set_bucket_encrypt_metadata(
+        &self,
+        name: &str,
+        enabled: bool,
+    ) -> StorageResult<Bucket> {
+        self.ensure_bucket_name_safe(name)?;
+        if enabled && self.options.metadata_key.is_none() {
+            return Err(StorageError::InvalidBucketState(
+                "metadata cannot be encrypted: no metadata encryption key is configured".into(),
+            ));
+        }
+        let mut tx = self.db.begin().await?;
+        let updated = sqlx::query_as::<_, Bucket>(&format!(
+            "UPDATE buckets SET encrypt_metadata = ? WHERE name = ? RETURNING {BUCKET_COLUMNS}"
+        ))
+        .bind(enabled)
+        .bind(name)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| StorageError::BucketNotFound(name.to_string()))?;
+        let converted = match &self.options.metadata_key {
+            Some(key) => {
+                reseal_bucket(&mut tx, &key.for_bucket(updated.id), updated.id, enabled).await?
+            }
+            None => 0,
+        };
+        tx.commit().await?;
+        if converted > 0 {
+            info!(
+                "{} {} metadata values of bucket `{}`",
+                if enabled { "sealed" } else { "opened" },
+                converted,
+                name
+            );
+        }
+        self.events.bucket(
+            EventKind::BucketConfigChanged,
+            name,
+            Some(format!("encrypt_metadata={}", enabled)),
+        );
+        Ok(updated)
+    }
+}
+
+/// Seal (`seal` true) or open every value of a bucket not already in that
+/// form, including pending multipart uploads. Returns the values converted.
+async fn reseal_bucket(
+    tx: &mut Transaction<'_, Sqlite>,
+    sealer: &MetadataSealer,
+    bucket_id: Uuid,
+    seal: bool,
+) -> StorageResult<u64> {
+    let mut converted = 0;
+    for (table, name_column, value_column, context) in SEALED_TABLES {
+        let rows: Vec<(String, String, String)> = sqlx::query_as(&format!(
+            "SELECT key, {name_column}, {value_column} FROM {table}
+             WHERE bucket_id = ? AND value_hash IS {}",
+            if seal { "NULL" } else { "NOT NULL" }
+        ))
+        .bind(bucket_id)
+        .fetch_all(&mut **tx)
+        .await?;
+        for (key, name, value) in rows {
+            let context = context(&key, &name);
+            let (value, hash) = if seal {
+                (
+                    sealer.seal(&context, &value),
+                    Some(sealer.hash(&name, &value)),
+                )
+            } else {
+                (sealer.open(&context, &value)?, None)
+            };
+            sqlx::query(&format!(
+                "UPDATE {table} SET {value_column} = ?, value_hash = ?
+                 WHERE bucket_id = ? AND key = ? AND {name_column} = ?"
+            ))
+            .bind(value)
+            .bind(hash)
+            .bind(bucket_id)
+            .bind(&key)
+            .bind(&name)
+            .execute(&mut **tx)
+            .await?;
+            converted += 1;
+        }
+    }
+
+    let uploads: Vec<(Uuid, Option<String>, Option<String>)> = sqlx::query_as(
+        "SELECT id, tagging, user_metadata FROM multipart_uploads WHERE bucket_id = ?",
+    )
+    .bind(bucket_id)
+    .fetch_all(&mut **tx)
+    .await?;
+    for (id, tagging, user_metadata) in uploads {
+        let mut convert = |field: &str, stored: Option<String>| -> StorageResult<Option<String>> {
+            let Some(stored) = stored else {
+                return Ok(None);
+            };
+            let context = upload_context(id, field);
+            Ok(Some(match (seal, stored.strip_prefix(SEALED_PREFIX)) {
+                (true, None) => {
+                    converted += 1;
+                    format!("{}{}", SEALED_PREFIX, sealer.seal(&context, &stored))
+                }
+                (false, Some(sealed)) => {
+                    converted += 1;
+                    sealer.open(&context, sealed)?
+                }
+                _ => stored,
+            }))
+        };
+        let tagging = convert("tagging", tagging)?;
+        let user_metadata = convert("user_metadata", user_metadata)?;
+        sqlx::query("UPDATE multipart_uploads SET tagging = ?, user_metadata = ? WHERE id = ?")
+            .bind(tagging)
+            .bind(user_metadata)
+            .bind(id)
+            .execute(&mut **tx)
+            .await?;
+    }
+    Ok(converted)
+}
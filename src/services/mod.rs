@@ -1 +1,65 @@
+pub mod acl;
+pub mod admin_tokens;
+pub mod alerts;
+pub mod analytics;
+pub mod auto_tagging;
+pub mod aws_chunked;
+pub mod batch_delete;
+pub mod blob_store;
+pub mod block_cache;
+pub mod bucket_template;
+pub mod changes;
+pub mod checksum;
+pub mod config_version;
+pub mod content_encoding;
+pub mod dedup;
+pub mod events;
+pub mod fsck;
+pub mod identity;
+pub mod jobs;
+pub mod key_lock;
+pub mod keys;
+pub mod lifecycle;
+pub mod lifecycle_dry_run;
+pub mod limits;
+pub mod list_stream;
+pub mod log_filter;
+pub mod manifest;
+pub mod mapped_read;
+pub mod metadata_encryption;
+pub mod metadata_io;
+pub mod multipart;
+pub mod multipart_assembly;
+pub mod naming_policy;
+pub mod notifications;
+pub mod object_lock;
+pub mod object_search;
+pub mod outbound;
+pub mod partition;
+pub mod placement;
+pub mod prefix_usage;
+pub mod preflight;
+pub mod publishers;
+pub mod purge;
+pub mod quota;
+pub mod reclaim;
+pub mod recycle;
+pub mod replication;
+pub mod scanner;
+pub mod self_test;
+pub mod session;
+pub mod shard_layout;
+pub mod sigv4;
+pub mod size_limit;
+pub mod snapshot;
+pub mod sse_c;
+pub mod staging;
 pub mod storage_service;
+pub mod tagging;
+pub mod tiering;
+pub mod tls;
+pub mod trash;
+pub mod upload_progress;
+pub mod user_metadata;
+pub mod versioning;
+pub mod volume;
@@ -0,0 +1,646 @@
+//! S3 multipart uploads.
+//!
+//! A multipart upload is initiated for a key, receives parts (1-10000) in any
+//! order and possibly in parallel, and is then completed with the list of
+//! parts to keep, in ascending order. Each part is staged and stored as its own
+//! file under `base_path/.multipart/{bucket}/{upload_id}/`. Completing
+//! concatenates the chosen parts (restartably, see `multipart_assembly`) and
+//! commits the result like a regular upload (recycling the previous payload,
+//! upserting metadata), then drops the upload. The object's ETag follows S3: the MD5 of the
+//! concatenated binary part MD5s, suffixed with `-{part count}`. The part
+//! numbers, sizes, MD5s and checksums are kept with the object for
+//! GetObjectAttributes, which pages through them like ListParts.
+//!
+//! Uploads that are never completed or aborted would keep their parts
+//! forever; `spawn_multipart_reaper` (or a one-off `--gc-multipart` run)
+//! aborts those initiated longer ago than the configured TTL.
+//!
+//! Parts are stored as sent; `Content-Encoding` given at initiation is
+//! recorded on the final object but never decoded.
+
+use crate::{
+    models::{
+        bucket::Bucket,
+        multipart::{MultipartUpload, ObjectPart, UploadPart},
+        object::Object,
+    },
+    services::{
+        checksum::{self, ExpectedChecksums},
+        metadata_encryption::{SEALED_PREFIX, upload_context},
+        storage_service::{
+            ObjectAttributes, PutObjectParams, StorageError, StorageResult, StorageService,
+            side_dirs,
+        },
+        tagging, user_metadata,
+    },
+};
+use bytes::Bytes;
+use chrono::{Duration as ChronoDuration, Utc};
+use futures::Stream;
+use serde::{Deserialize, Serialize};
+use sqlx::{Sqlite, Transaction};
+use std::{collections::HashMap, io, path::PathBuf, time::Duration};
+use tokio::{fs, task::JoinHandle};
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+/// Directory (below `base_path`) holding uploaded parts.
+const MULTIPART_DIR: &str = side_dirs::MULTIPART;
+
+/// Highest part number S3 accepts.
+pub const MAX_PART_NUMBER: i64 = 10_000;
+
+/// Minimum size of every part but the last, as enforced by S3.
+pub const MIN_PART_SIZE: i64 = 5 * 1024 * 1024;
+
+/// Parts returned by `ListParts` when the client sets no limit.
+pub const DEFAULT_MAX_PARTS: usize = 1000;
+
+/// How often the reaper looks for abandoned uploads.
+pub const MULTIPART_GC_TICK: Duration = Duration::from_secs(3600);
+
+/// A part named in a `CompleteMultipartUpload` request.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CompletedPart {
+    pub part_number: i64,
+    pub etag: String,
+}
+
+/// What completing an upload with a list of parts produces.
+#[derive(Debug, Clone)]
+pub(crate) struct AssemblyPlan {
+    pub etag: String,
+    /// Part payloads, in the order they are concatenated.
+    pub paths: Vec<PathBuf>,
+    pub parts: Vec<ObjectPart>,
+}
+
+/// Outcome of one `gc_multipart_uploads` pass.
+#[derive(Debug, Default, Clone, Copy, Serialize)]
+pub struct MultipartGcSummary {
+    pub aborted_uploads: u64,
+    /// Bytes of parts removed with them.
+    pub freed_bytes: u64,
+}
+
+/// One page of the parts of a completed object (GetObjectAttributes).
+#[derive(Debug)]
+pub struct ObjectPartsPage {
+    /// Parts of the object in all pages.
+    pub total_parts: i64,
+    pub parts: Vec<ObjectPart>,
+    /// The page size used, after clamping.
+    pub max_parts: usize,
+    pub is_truncated: bool,
+    pub next_part_number_marker: Option<i64>,
+}
+
+/// One page of `ListParts`.
+#[derive(Debug)]
+pub struct PartListing {
+    pub upload: MultipartUpload,
+    pub parts: Vec<UploadPart>,
+    pub is_truncated: bool,
+    pub next_part_number_marker: Option<i64>,
+}
+
+impl StorageService {
+    /// Root of the multipart staging area for a bucket.
+    pub(crate) fn multipart_root(&self, bucket_name: &str) -> PathBuf {
+        self.base_path.join(MULTIPART_DIR).join(bucket_name)
+    }
+
+    pub(crate) fn upload_dir(&self, bucket_name: &str, upload_id: Uuid) -> PathBuf {
+        self.multipart_root(bucket_name).join(upload_id.to_string())
+    }
+
+    fn part_path(&self, bucket_name: &str, upload_id: Uuid, part_number: i64) -> PathBuf {
+        self.upload_dir(bucket_name, upload_id)
+            .join(part_number.to_string())
+    }
+
+    /// Initiate a multipart upload for `key`.
+    pub async fn create_multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        params: PutObjectParams,
+    ) -> StorageResult<MultipartUpload> {
+        self.ensure_key_safe(key)?;
+        tagging::validate_tags(&params.tags)?;
+        user_metadata::validate_user_metadata(&params.user_metadata)?;
+        if params.retention.is_some() {
+            return Err(StorageError::InvalidObjectLock(
+                "retention headers are not supported on multipart uploads; \
+                 the bucket's default retention applies"
+                    .into(),
+            ));
+        }
+        let tagging = (!params.tags.is_empty()).then(|| tagging::encode_tags(&params.tags));
+        let user_metadata = (!params.user_metadata.is_empty())
+            .then(|| serde_json::to_string(&params.user_metadata))
+            .transpose()
+            .map_err(|err| StorageError::InvalidMetadata(err.to_string()))?;
+        let bucket_rec = self.fetch_writable_bucket(bucket).await?;
+        self.ensure_key_allowed(&bucket_rec, key).await?;
+        let acl = self.resolve_object_acl(&bucket_rec, params.acl.as_deref())?;
+        let upload_id = Uuid::new_v4();
+        let (tagging, user_metadata) = match self.metadata_sealer(&bucket_rec)? {
+            Some(sealer) => {
+                let seal = |field: &str, value: Option<String>| {
+                    value.map(|value| {
+                        let sealed = sealer.seal(&upload_context(upload_id, field), &value);
+                        format!("{}{}", SEALED_PREFIX, sealed)
+                    })
+                };
+                (
+                    seal("tagging", tagging),
+                    seal("user_metadata", user_metadata),
+                )
+            }
+            None => (tagging, user_metadata),
+        };
+        let upload = sqlx::query_as::<_, MultipartUpload>(
+            "INSERT INTO multipart_uploads (
+                id, bucket_id, key, content_type, content_encoding, tagging, user_metadata,
+                acl, cache_control, content_disposition, expires, uploader, initiated_at
+             ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+             RETURNING id, bucket_id, key, content_type, content_encoding, tagging,
+                       user_metadata, acl, cache_control, content_disposition, expires,
+                       uploader, initiated_at",
+        )
+        .bind(upload_id)
+        .bind(bucket_rec.id)
+        .bind(key)
+        .bind(&params.content_type)
+        .bind(&params.content_encoding)
+        .bind(&tagging)
+        .bind(&user_metadata)
+        .bind(&acl)
+        .bind(&params.cache_control)
+        .bind(&params.content_disposition)
+        .bind(&params.expires)
+        .bind(&params.uploader)
+        .bind(Utc::now())
+        .fetch_one(&*self.db)
+        .await?;
+        fs::create_dir_all(self.upload_dir(&bucket_rec.name, upload.id)).await?;
+        debug!(
+            "initiated multipart upload {} for {}/{}",
+            upload.id, bucket_rec.name, key
+        );
+        Ok(upload)
+    }
+
+    /// Look up an upload, checking it belongs to `bucket`/`key`.
+    pub(crate) async fn fetch_upload(
+        &self,
+        bucket: &Bucket,
+        key: &str,
+        upload_id: Uuid,
+    ) -> StorageResult<MultipartUpload> {
+        sqlx::query_as::<_, MultipartUpload>(
+            "SELECT id, bucket_id, key, content_type, content_encoding, tagging, user_metadata,
+                    acl, cache_control, content_disposition, expires, uploader, initiated_at
+             FROM multipart_uploads WHERE id = ? AND bucket_id = ? AND key = ?",
+        )
+        .bind(upload_id)
+        .bind(bucket.id)
+        .bind(key)
+        .fetch_optional(&*self.db)
+        .await?
+        .ok_or(StorageError::NoSuchUpload(upload_id))
+    }
+
+    /// Store one part. Re-uploading a part number replaces it.
+    ///
+    /// The part is checked against `checksums`; the SHA-256 and CRC32C that
+    /// matched are kept with it and reported for the completed object.
+    #[allow(clippy::too_many_arguments)]
+    pub async fn upload_part<S>(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: Uuid,
+        part_number: i64,
+        content_length: Option<u64>,
+        checksums: ExpectedChecksums,
+        stream: S,
+    ) -> StorageResult<UploadPart>
+    where
+        S: Stream<Item = Result<Bytes, io::Error>> + Send + 'static,
+    {
+        if !(1..=MAX_PART_NUMBER).contains(&part_number) {
+            return Err(StorageError::InvalidPart(format!(
+                "part number must be between 1 and {}",
+                MAX_PART_NUMBER
+            )));
+        }
+        self.ensure_key_safe(key)?;
+        checksums.validate()?;
+        let bucket_rec = self.fetch_writable_bucket(bucket).await?;
+        let upload = self.fetch_upload(&bucket_rec, key, upload_id).await?;
+        self.ensure_not_assembling(upload.id).await?;
+
+        let (_progress, stream) = self
+            .uploads
+            .track(&bucket_rec.name, key, content_length, stream);
+        let stream = checksum::verify_stream(stream, checksums.clone());
+        // No part can be larger than the object it is part of.
+        let stream = self.limit_object_stream(key, content_length, Box::pin(stream))?;
+        let dir = self.upload_dir(&bucket_rec.name, upload.id);
+        let staged = self.stage_payload(&dir, stream, None).await?;
+        let part_path = self.part_path(&bucket_rec.name, upload.id, part_number);
+        let (size_bytes, md5) = (staged.size_bytes, staged.md5);
+        let sha256 = staged.sha256.or(checksums.sha256);
+        self.track_write(staged.file.persist(&part_path).await)?;
+
+        let part = sqlx::query_as::<_, UploadPart>(
+            "INSERT INTO multipart_parts (upload_id, part_number, size_bytes, etag,
+                                          checksum_sha256, checksum_crc32c, last_modified)
+             VALUES (?, ?, ?, ?, ?, ?, ?)
+             ON CONFLICT(upload_id, part_number) DO UPDATE SET
+                size_bytes = excluded.size_bytes,
+                etag = excluded.etag,
+                checksum_sha256 = excluded.checksum_sha256,
+                checksum_crc32c = excluded.checksum_crc32c,
+                last_modified = excluded.last_modified
+             RETURNING upload_id, part_number, size_bytes, etag, checksum_sha256,
+                       checksum_crc32c, last_modified",
+        )
+        .bind(upload.id)
+        .bind(part_number)
+        .bind(size_bytes)
+        .bind(format!("{:x}", md5))
+        .bind(sha256)
+        .bind(checksums.crc32c)
+        .bind(Utc::now())
+        .fetch_one(&*self.db)
+        .await?;
+        if let Err(err) = self
+            .record_prefix_transfer(bucket_rec.id, key, size_bytes.max(0) as u64, 0)
+            .await
+        {
+            debug!(
+                "failed to record part upload of `{}/{}`: {}",
+                bucket, key, err
+            );
+        }
+        Ok(part)
+    }
+
+    /// List uploaded parts in part-number order, starting after
+    /// `part_number_marker`.
+    pub async fn list_parts(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: Uuid,
+        part_number_marker: Option<i64>,
+        max_parts: usize,
+    ) -> StorageResult<PartListing> {
+        self.ensure_key_safe(key)?;
+        let bucket_rec = self.fetch_bucket(bucket).await?;
+        let upload = self.fetch_upload(&bucket_rec, key, upload_id).await?;
+        let max_parts = max_parts.clamp(1, DEFAULT_MAX_PARTS);
+
+        let mut parts = sqlx::query_as::<_, UploadPart>(
+            "SELECT upload_id, part_number, size_bytes, etag, checksum_sha256, checksum_crc32c,
+                    last_modified
+             FROM multipart_parts WHERE upload_id = ? AND part_number > ?
+             ORDER BY part_number ASC LIMIT ?",
+        )
+        .bind(upload.id)
+        .bind(part_number_marker.unwrap_or(0))
+        .bind(max_parts as i64 + 1)
+        .fetch_all(&*self.db)
+        .await?;
+
+        let is_truncated = parts.len() > max_parts;
+        parts.truncate(max_parts);
+        let next_part_number_marker = parts.last().map(|p| p.part_number);
+        Ok(PartListing {
+            upload,
+            parts,
+            is_truncated,
+            next_part_number_marker,
+        })
+    }
+
+    /// Assemble the listed parts into the object and drop the upload.
+    ///
+    /// Parts must be listed in ascending order, exist with matching ETags,
+    /// and all but the last must be at least `MIN_PART_SIZE`. Uploaded parts
+    /// that are not listed are discarded. Assembly runs in the background
+    /// (see `multipart_assembly`), so it finishes even if the caller goes
+    /// away; a repeated request for the same parts waits for it, or returns
+    /// the object once it is done.
+    pub async fn complete_multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: Uuid,
+        requested: &[CompletedPart],
+    ) -> StorageResult<Object> {
+        self.ensure_key_safe(key)?;
+        let bucket_rec = self.fetch_writable_bucket(bucket).await?;
+        if requested.is_empty() {
+            return Err(StorageError::InvalidPart(
+                "at least one part must be specified".into(),
+            ));
+        }
+        if requested
+            .windows(2)
+            .any(|pair| pair[0].part_number >= pair[1].part_number)
+        {
+            return Err(StorageError::InvalidPart(
+                "parts must be listed in ascending order".into(),
+            ));
+        }
+        let requested: Vec<CompletedPart> = requested
+            .iter()
+            .map(|part| CompletedPart {
+                part_number: part.part_number,
+                etag: part.etag.trim().trim_matches('"').to_lowercase(),
+            })
+            .collect();
+        self.assemble_upload(bucket_rec, key, upload_id, requested)
+            .await
+    }
+
+    /// Check the listed parts against the stored ones and work out what
+    /// completing the upload with them produces.
+    pub(crate) async fn plan_assembly(
+        &self,
+        bucket: &Bucket,
+        upload: &MultipartUpload,
+        requested: &[CompletedPart],
+    ) -> StorageResult<AssemblyPlan> {
+        let stored: HashMap<i64, UploadPart> = sqlx::query_as::<_, UploadPart>(
+            "SELECT upload_id, part_number, size_bytes, etag, checksum_sha256, checksum_crc32c,
+                    last_modified
+             FROM multipart_parts WHERE upload_id = ?",
+        )
+        .bind(upload.id)
+        .fetch_all(&*self.db)
+        .await?
+        .into_iter()
+        .map(|part| (part.part_number, part))
+        .collect();
+
+        let mut md5s = Vec::with_capacity(requested.len() * 16);
+        let mut paths = Vec::with_capacity(requested.len());
+        let mut parts = Vec::with_capacity(requested.len());
+        for (index, wanted) in requested.iter().enumerate() {
+            let part = stored
+                .get(&wanted.part_number)
+                .filter(|part| part.etag.eq_ignore_ascii_case(&wanted.etag))
+                .ok_or_else(|| {
+                    StorageError::InvalidPart(format!(
+                        "part {} was not uploaded or its ETag does not match",
+                        wanted.part_number
+                    ))
+                })?;
+            let is_last = index + 1 == requested.len();
+            if !is_last && part.size_bytes < MIN_PART_SIZE {
+                return Err(StorageError::InvalidPart(format!(
+                    "part {} is smaller than the {} byte minimum",
+                    part.part_number, MIN_PART_SIZE
+                )));
+            }
+            md5s.extend(decode_hex(&part.etag).ok_or_else(|| {
+                StorageError::InvalidPart(format!("part {} has a corrupt ETag", part.part_number))
+            })?);
+            paths.push(self.part_path(&bucket.name, upload.id, part.part_number));
+            parts.push(ObjectPart {
+                part_number: part.part_number,
+                size_bytes: part.size_bytes,
+                etag: part.etag.clone(),
+                checksum_sha256: part.checksum_sha256.clone(),
+                checksum_crc32c: part.checksum_crc32c.clone(),
+            });
+        }
+        let size: i64 = parts.iter().map(|part| part.size_bytes).sum();
+        self.check_object_size(&upload.key, size.max(0) as u64)?;
+        Ok(AssemblyPlan {
+            etag: format!("{:x}-{}", md5::compute(&md5s), requested.len()),
+            paths,
+            parts,
+        })
+    }
+
+    /// The attributes given at initiation, applied to the completed object.
+    pub(crate) fn upload_attributes(
+        &self,
+        upload: &MultipartUpload,
+        parts: Vec<ObjectPart>,
+    ) -> StorageResult<ObjectAttributes> {
+        let open = |field: &str, stored: &str| {
+            self.open_upload_field(upload.bucket_id, upload.id, field, stored)
+        };
+        let tags = match upload.tagging.as_deref() {
+            Some(tagging) => tagging::parse_tagging_header(&open("tagging", tagging)?)?,
+            None => Vec::new(),
+        };
+        let user_metadata = match upload.user_metadata.as_deref() {
+            Some(json) => serde_json::from_str(&open("user_metadata", json)?)
+                .map_err(|err| StorageError::InvalidMetadata(err.to_string()))?,
+            None => Vec::new(),
+        };
+        Ok(ObjectAttributes {
+            content_type: upload.content_type.clone(),
+            content_encoding: upload.content_encoding.clone(),
+            tags,
+            user_metadata,
+            acl: upload.acl.clone(),
+            cache_control: upload.cache_control.clone(),
+            content_disposition: upload.content_disposition.clone(),
+            expires: upload.expires.clone(),
+            parts,
+            retention: None,
+            encryption: None,
+            uploader: upload.uploader.clone(),
+        })
+    }
+
+    /// Up to `max_parts` of the parts `object` was assembled from, in part
+    /// number order, starting after `part_number_marker`; none unless it is
+    /// the current version of a key completed by a multipart upload.
+    pub async fn object_parts(
+        &self,
+        object: &Object,
+        part_number_marker: Option<i64>,
+        max_parts: usize,
+    ) -> StorageResult<ObjectPartsPage> {
+        let max_parts = max_parts.clamp(1, DEFAULT_MAX_PARTS);
+        let total_parts: i64 =
+            sqlx::query_scalar("SELECT COUNT(*) FROM object_parts WHERE bucket_id = ? AND key = ?")
+                .bind(object.bucket_id)
+                .bind(&object.key)
+                .fetch_one(&*self.db)
+                .await?;
+        let mut parts: Vec<ObjectPart> = sqlx::query_as(
+            "SELECT part_number, size_bytes, etag, checksum_sha256, checksum_crc32c
+             FROM object_parts WHERE bucket_id = ? AND key = ? AND part_number > ?
+             ORDER BY part_number LIMIT ?",
+        )
+        .bind(object.bucket_id)
+        .bind(&object.key)
+        .bind(part_number_marker.unwrap_or(0))
+        .bind(max_parts as i64 + 1)
+        .fetch_all(&*self.db)
+        .await?;
+        let is_truncated = parts.len() > max_parts;
+        parts.truncate(max_parts);
+        let next_part_number_marker = parts.last().map(|part| part.part_number);
+        Ok(ObjectPartsPage {
+            total_parts,
+            parts,
+            max_parts,
+            is_truncated,
+            next_part_number_marker,
+        })
+    }
+
+    /// Abort an upload and delete its parts.
+    pub async fn abort_multipart_upload(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: Uuid,
+    ) -> StorageResult<()> {
+        self.ensure_key_safe(key)?;
+        let bucket_rec = self.fetch_bucket(bucket).await?;
+        let upload = self.fetch_upload(&bucket_rec, key, upload_id).await?;
+        self.ensure_not_assembling(upload.id).await?;
+        self.discard_upload(&bucket_rec.name, upload.id).await?;
+        self.forget_assembly(upload.id).await?;
+        debug!(
+            "aborted multipart upload {} for {}/{}",
+            upload.id, bucket_rec.name, key
+        );
+        Ok(())
+    }
+
+    /// Abort every upload initiated more than `ttl` ago, in any bucket,
+    /// unless it is being completed, and forget completions that finished
+    /// that long ago.
+    pub async fn gc_multipart_uploads(&self, ttl: Duration) -> StorageResult<MultipartGcSummary> {
+        let cutoff = Utc::now() - ChronoDuration::from_std(ttl).unwrap_or(ChronoDuration::MAX);
+        let stale: Vec<(Uuid, String, String, i64)> = sqlx::query_as(
+            "SELECT u.id, u.key, b.name,
+                    COALESCE((SELECT SUM(p.size_bytes) FROM multipart_parts p
+                              WHERE p.upload_id = u.id), 0)
+             FROM multipart_uploads u JOIN buckets b ON b.id = u.bucket_id
+             WHERE u.initiated_at < ?
+               AND NOT EXISTS (SELECT 1 FROM multipart_completions c
+                               WHERE c.upload_id = u.id AND c.state = 'assembling')
+             ORDER BY u.initiated_at ASC",
+        )
+        .bind(cutoff)
+        .fetch_all(&*self.db)
+        .await?;
+        let mut summary = MultipartGcSummary::default();
+        for (upload_id, key, bucket_name, bytes) in stale {
+            self.discard_upload(&bucket_name, upload_id).await?;
+            self.forget_assembly(upload_id).await?;
+            debug!(
+                "reaped abandoned multipart upload {} for {}/{}",
+                upload_id, bucket_name, key
+            );
+            summary.aborted_uploads += 1;
+            summary.freed_bytes += bytes.max(0) as u64;
+        }
+        self.prune_assemblies(cutoff).await?;
+        if summary.aborted_uploads > 0 {
+            info!(
+                "reaped {} abandoned multipart uploads ({} bytes of parts)",
+                summary.aborted_uploads, summary.freed_bytes
+            );
+        }
+        Ok(summary)
+    }
+
+    /// Drop an upload's row (its parts cascade) and its part payloads.
+    pub(crate) async fn discard_upload(
+        &self,
+        bucket_name: &str,
+        upload_id: Uuid,
+    ) -> StorageResult<()> {
+        sqlx::query("DELETE FROM multipart_uploads WHERE id = ?")
+            .bind(upload_id)
+            .execute(&*self.db)
+            .await?;
+        let dir = self.upload_dir(bucket_name, upload_id);
+        if let Err(err) = fs::remove_dir_all(&dir).await
+            && err.kind() != io::ErrorKind::NotFound
+        {
+            warn!(
+                "failed to remove multipart directory {}: {}",
+                dir.display(),
+                err
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Spawn a background task that aborts uploads older than `ttl`, checking
+/// every `period`.
+pub fn spawn_multipart_reaper(
+    service: StorageService,
+    ttl: Duration,
+    period: Duration,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(period);
+        loop {
+            ticker.tick().await;
+            if let Err(err) = service.gc_multipart_uploads(ttl).await {
+                warn!("multipart reaper failed: {}", err);
+            }
+        }
+    })
+}
+
+/// Replace the recorded parts of `key` inside `tx`.
+pub(crate) async fn replace_parts(
+    tx: &mut Transaction<'_, Sqlite>,
+    bucket_id: Uuid,
+    key: &str,
+    parts: &[ObjectPart],
+) -> sqlx::Result<()> {
+    sqlx::query("DELETE FROM object_parts WHERE bucket_id = ? AND key = ?")
+        .bind(bucket_id)
+        .bind(key)
+        .execute(&mut **tx)
+        .await?;
+    for part in parts {
+        sqlx::query(
+            "INSERT INTO object_parts (bucket_id, key, part_number, size_bytes, etag,
+                                       checksum_sha256, checksum_crc32c)
+             VALUES (?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(bucket_id)
+        .bind(key)
+        .bind(part.part_number)
+        .bind(part.size_bytes)
+        .bind(&part.etag)
+        .bind(&part.checksum_sha256)
+        .bind(&part.checksum_crc32c)
+        .execute(&mut **tx)
+        .await?;
+    }
+    Ok(())
+}
+
+/// Decode a lowercase or uppercase hex string.
+fn decode_hex(hex: &str) -> Option<Vec<u8>> {
+    if !hex.len().is_multiple_of(2) {
+        return None;
+    }
+    (0..hex.len())
+        .step_by(2)
+        .map(|i| u8::from_str_radix(hex.get(i..i + 2)?, 16).ok())
+        .collect()
+}
@@ -0,0 +1,487 @@
+//! Restartable completion of multipart uploads.
+//!
+//! Concatenating the parts of a large upload can take minutes. Rather than
+//! doing it inside the `CompleteMultipartUpload` request, where a client
+//! timeout or a crash would throw the work away, assembly runs as a
+//! background task that appends the parts, in order, to
+//! `.multipart/{bucket}/{upload_id}/.assembly`. After each part the file is
+//! synced and the progress is recorded in `multipart_completions`, so:
+//!
+//! - a client that disconnects does not stop the assembly; repeating the
+//!   request with the same parts waits for it (or returns the object once it
+//!   is done), and `GET /{bucket}/{key}?uploadId=U&completion` reports its
+//!   progress as JSON;
+//! - after a crash, `resume_multipart_assemblies` (run at startup) carries on
+//!   from the last recorded part instead of starting over; the bytes already
+//!   assembled are only read back to recompute the payload's digests.
+//!
+//! A completed assembly is kept in `multipart_completions` (its `etag` and
+//! `version_id`) after the upload is dropped, so a late retry still gets the
+//! object, until the multipart reaper forgets it. A failed one records the
+//! error; completing the upload again starts over. An upload being assembled
+//! cannot receive parts or be aborted.
+//!
+//! A crash after the object is committed but before the upload is dropped
+//! commits the same payload again on resume.
+
+use crate::{
+    models::{bucket::Bucket, multipart::MultipartUpload, object::Object},
+    services::{
+        multipart::{AssemblyPlan, CompletedPart},
+        staging::StagingFile,
+        storage_service::{StagedPayload, StorageError, StorageResult, StorageService},
+    },
+};
+use base64::{Engine as _, engine::general_purpose};
+use chrono::{DateTime, Utc};
+use md5::Context;
+use serde::Serialize;
+use sha2::{Digest, Sha256};
+use std::{collections::HashMap, io::SeekFrom, sync::Arc};
+use tokio::{
+    fs::OpenOptions,
+    io::{AsyncReadExt, AsyncSeekExt, AsyncWriteExt},
+    sync::{Mutex, watch},
+};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// Name of the assembled payload in an upload's directory; part files are
+/// named by number, so this never collides with one.
+const ASSEMBLY_FILE: &str = ".assembly";
+
+/// Bytes copied per read while assembling.
+const COPY_CHUNK: usize = 1024 * 1024;
+
+const ASSEMBLING: &str = "assembling";
+const COMPLETED: &str = "completed";
+const FAILED: &str = "failed";
+
+/// Assemblies running in this process, each with a channel that turns
+/// `true` once its outcome is recorded.
+#[derive(Clone, Default)]
+pub struct AssemblyRegistry {
+    running: Arc<Mutex<HashMap<Uuid, watch::Receiver<bool>>>>,
+}
+
+/// Body of `GET /{bucket}/{key}?uploadId=U&completion`.
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct AssemblyStatus {
+    pub upload_id: Uuid,
+    #[serde(skip)]
+    pub bucket_id: Uuid,
+    pub key: String,
+    /// `assembling`, `completed` or `failed`.
+    pub state: String,
+    pub parts_total: i64,
+    pub parts_assembled: i64,
+    pub bytes_total: i64,
+    pub bytes_assembled: i64,
+    pub started_at: DateTime<Utc>,
+    pub updated_at: DateTime<Utc>,
+    /// ETag and version of the completed object.
+    pub etag: Option<String>,
+    pub version_id: Option<String>,
+    /// Why the assembly failed.
+    pub error: Option<String>,
+    /// The requested parts (JSON).
+    #[serde(skip)]
+    pub parts: String,
+}
+
+const STATUS_COLUMNS: &str = "upload_id, bucket_id, key, state, parts_total, parts_assembled, \
+     bytes_total, bytes_assembled, started_at, updated_at, etag, version_id, error, parts";
+
+impl StorageService {
+    /// Progress of the completion of `upload_id`; NoSuchUpload when it was
+    /// never requested (or has been forgotten).
+    pub async fn multipart_completion_status(
+        &self,
+        bucket: &str,
+        key: &str,
+        upload_id: Uuid,
+    ) -> StorageResult<AssemblyStatus> {
+        self.ensure_key_safe(key)?;
+        let bucket_rec = self.fetch_bucket(bucket).await?;
+        self.assembly_status(&bucket_rec, key, upload_id)
+            .await?
+            .ok_or(StorageError::NoSuchUpload(upload_id))
+    }
+
+    /// Complete `upload_id` with `requested` (normalized) parts: start an
+    /// assembly, resume an interrupted one, or join the one running.
+    pub(crate) async fn assemble_upload(
+        &self,
+        bucket: Bucket,
+        key: &str,
+        upload_id: Uuid,
+        requested: Vec<CompletedPart>,
+    ) -> StorageResult<Object> {
+        let parts_json = serde_json::to_string(&requested)
+            .map_err(|err| StorageError::InvalidPart(err.to_string()))?;
+        let mut running = self.assemblies.running.lock().await;
+        let status = self.assembly_status(&bucket, key, upload_id).await?;
+        let same_parts = status
+            .as_ref()
+            .is_some_and(|status| status.parts == parts_json);
+        if let Some(done) = running.get(&upload_id).cloned() {
+            drop(running);
+            if !same_parts {
+                return Err(StorageError::InvalidPart(format!(
+                    "upload {} is already being completed with different parts",
+                    upload_id
+                )));
+            }
+            return self.join_assembly(&bucket, key, upload_id, done).await;
+        }
+        if let Some(status) = status.as_ref().filter(|status| status.state == COMPLETED) {
+            drop(running);
+            if !same_parts {
+                return Err(StorageError::NoSuchUpload(upload_id));
+            }
+            return self.completed_object(&bucket, status).await;
+        }
+
+        let upload = self.fetch_upload(&bucket, key, upload_id).await?;
+        let plan = self.plan_assembly(&bucket, &upload, &requested).await?;
+        let resume = match &status {
+            Some(status) if status.state == ASSEMBLING && same_parts => {
+                info!(
+                    "resuming assembly of upload {} at part {} of {}",
+                    upload_id, status.parts_assembled, status.parts_total
+                );
+                (status.parts_assembled, status.bytes_assembled)
+            }
+            _ => (0, 0),
+        };
+        sqlx::query(
+            "INSERT INTO multipart_completions (
+                upload_id, bucket_id, key, parts, state, parts_total, parts_assembled,
+                bytes_total, bytes_assembled, started_at, updated_at
+             ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)
+             ON CONFLICT(upload_id) DO UPDATE SET
+                parts = excluded.parts,
+                state = excluded.state,
+                parts_total = excluded.parts_total,
+                parts_assembled = excluded.parts_assembled,
+                bytes_total = excluded.bytes_total,
+                bytes_assembled = excluded.bytes_assembled,
+                started_at = CASE WHEN excluded.parts_assembled = 0
+                                  THEN excluded.started_at ELSE started_at END,
+                updated_at = excluded.updated_at,
+                etag = NULL,
+                version_id = NULL,
+                error = NULL",
+        )
+        .bind(upload_id)
+        .bind(bucket.id)
+        .bind(key)
+        .bind(&parts_json)
+        .bind(ASSEMBLING)
+        .bind(plan.paths.len() as i64)
+        .bind(resume.0)
+        .bind(plan.parts.iter().map(|part| part.size_bytes).sum::<i64>())
+        .bind(resume.1)
+        .bind(Utc::now())
+        .bind(Utc::now())
+        .execute(&*self.db)
+        .await?;
+
+        let (done, receiver) = watch::channel(false);
+        running.insert(upload_id, receiver);
+        drop(running);
+        let service = self.clone();
+        tokio::spawn(async move {
+            service
+                .run_assembly(bucket, upload, plan, resume, done)
+                .await
+        })
+        .await
+        .map_err(|err| StorageError::Io(std::io::Error::other(err)))?
+    }
+
+    /// Restart the assemblies a previous run left unfinished, in the
+    /// background. Returns how many were restarted.
+    pub async fn resume_multipart_assemblies(&self) -> StorageResult<usize> {
+        let pending: Vec<(Uuid, String, String, String)> = sqlx::query_as(
+            "SELECT c.upload_id, b.name, c.key, c.parts
+             FROM multipart_completions c JOIN buckets b ON b.id = c.bucket_id
+             WHERE c.state = ?",
+        )
+        .bind(ASSEMBLING)
+        .fetch_all(&*self.db)
+        .await?;
+        let count = pending.len();
+        for (upload_id, bucket, key, parts) in pending {
+            let service = self.clone();
+            tokio::spawn(async move {
+                let result = async {
+                    let requested: Vec<CompletedPart> = serde_json::from_str(&parts)
+                        .map_err(|err| StorageError::InvalidPart(err.to_string()))?;
+                    let bucket_rec = service.fetch_bucket(&bucket).await?;
+                    service
+                        .assemble_upload(bucket_rec, &key, upload_id, requested)
+                        .await
+                }
+                .await;
+                if let Err(err) = result {
+                    warn!("resumed assembly of upload {} failed: {}", upload_id, err);
+                }
+            });
+        }
+        Ok(count)
+    }
+
+    /// Refuse changing an upload while it is being assembled.
+    pub(crate) async fn ensure_not_assembling(&self, upload_id: Uuid) -> StorageResult<()> {
+        if self
+            .assemblies
+            .running
+            .lock()
+            .await
+            .contains_key(&upload_id)
+        {
+            return Err(StorageError::InvalidPart(format!(
+                "upload {} is being completed",
+                upload_id
+            )));
+        }
+        Ok(())
+    }
+
+    /// Forget the completion state of an aborted upload.
+    pub(crate) async fn forget_assembly(&self, upload_id: Uuid) -> StorageResult<()> {
+        sqlx::query("DELETE FROM multipart_completions WHERE upload_id = ?")
+            .bind(upload_id)
+            .execute(&*self.db)
+            .await?;
+        Ok(())
+    }
+
+    /// Forget finished (completed or failed) assemblies last updated before
+    /// `cutoff`.
+    pub(crate) async fn prune_assemblies(&self, cutoff: DateTime<Utc>) -> StorageResult<()> {
+        sqlx::query("DELETE FROM multipart_completions WHERE state != ? AND updated_at < ?")
+            .bind(ASSEMBLING)
+            .bind(cutoff)
+            .execute(&*self.db)
+            .await?;
+        Ok(())
+    }
+
+    async fn assembly_status(
+        &self,
+        bucket: &Bucket,
+        key: &str,
+        upload_id: Uuid,
+    ) -> StorageResult<Option<AssemblyStatus>> {
+        Ok(sqlx::query_as::<_, AssemblyStatus>(&format!(
+            "SELECT {STATUS_COLUMNS} FROM multipart_completions
+             WHERE upload_id = ? AND bucket_id = ? AND key = ?"
+        ))
+        .bind(upload_id)
+        .bind(bucket.id)
+        .bind(key)
+        .fetch_optional(&*self.db)
+        .await?)
+    }
+
+    /// Wait for the assembly running for `upload_id` and return its outcome.
+    async fn join_assembly(
+        &self,
+        bucket: &Bucket,
+        key: &str,
+        upload_id: Uuid,
+        mut done: watch::Receiver<bool>,
+    ) -> StorageResult<Object> {
+        // An error means the task went away without recording an outcome,
+        // which the status below reports.
+        let _ = done.wait_for(|done| *done).await;
+        let status = self
+            .assembly_status(bucket, key, upload_id)
+            .await?
+            .ok_or(StorageError::NoSuchUpload(upload_id))?;
+        match status.state.as_str() {
+            COMPLETED => self.completed_object(bucket, &status).await,
+            _ => Err(StorageError::AssemblyFailed {
+                upload_id,
+                reason: status
+                    .error
+                    .unwrap_or_else(|| "the assembly was interrupted".into()),
+            }),
+        }
+    }
+
+    /// The object a completed assembly produced, while it is still the
+    /// current version of its key.
+    async fn completed_object(
+        &self,
+        bucket: &Bucket,
+        status: &AssemblyStatus,
+    ) -> StorageResult<Object> {
+        match self.fetch_object(bucket, &status.key).await {
+            Ok(object) if object.etag == status.etag && object.version_id == status.version_id => {
+                Ok(object)
+            }
+            Ok(_) | Err(StorageError::ObjectNotFound { .. }) => {
+                Err(StorageError::NoSuchUpload(status.upload_id))
+            }
+            Err(err) => Err(err),
+        }
+    }
+
+    /// Assemble and commit the object, record the outcome and wake anyone
+    /// waiting for it.
+    async fn run_assembly(
+        &self,
+        bucket: Bucket,
+        upload: MultipartUpload,
+        plan: AssemblyPlan,
+        resume: (i64, i64),
+        done: watch::Sender<bool>,
+    ) -> StorageResult<Object> {
+        let parts = plan.parts.len();
+        let result = self.assemble(&bucket, &upload, plan, resume).await;
+        let recorded = match &result {
+            Ok(object) => {
+                if let Err(err) = self.discard_upload(&bucket.name, upload.id).await {
+                    warn!("could not drop completed upload {}: {}", upload.id, err);
+                }
+                info!(
+                    "completed multipart upload {} into {}/{} ({} parts, {} bytes)",
+                    upload.id, bucket.name, upload.key, parts, object.size_bytes
+                );
+                sqlx::query(
+                    "UPDATE multipart_completions
+                     SET state = ?, etag = ?, version_id = ?, updated_at = ?
+                     WHERE upload_id = ?",
+                )
+                .bind(COMPLETED)
+                .bind(&object.etag)
+                .bind(&object.version_id)
+                .bind(Utc::now())
+                .bind(upload.id)
+                .execute(&*self.db)
+                .await
+            }
+            Err(err) => {
+                warn!("assembly of upload {} failed: {}", upload.id, err);
+                sqlx::query(
+                    "UPDATE multipart_completions
+                     SET state = ?, error = ?, parts_assembled = 0, bytes_assembled = 0,
+                         updated_at = ?
+                     WHERE upload_id = ?",
+                )
+                .bind(FAILED)
+                .bind(err.to_string())
+                .bind(Utc::now())
+                .bind(upload.id)
+                .execute(&*self.db)
+                .await
+            }
+        };
+        if let Err(err) = recorded {
+            warn!(
+                "could not record the outcome of assembling upload {}: {}",
+                upload.id, err
+            );
+        }
+        self.assemblies.running.lock().await.remove(&upload.id);
+        let _ = done.send(true);
+        result
+    }
+
+    /// Append the parts not assembled yet to the assembly file, then commit
+    /// it as the object.
+    async fn assemble(
+        &self,
+        bucket: &Bucket,
+        upload: &MultipartUpload,
+        plan: AssemblyPlan,
+        (mut parts_done, mut bytes_done): (i64, i64),
+    ) -> StorageResult<Object> {
+        let path = self.upload_dir(&bucket.name, upload.id).join(ASSEMBLY_FILE);
+        let mut file = self.track_write(
+            OpenOptions::new()
+                .create(true)
+                .truncate(false)
+                .read(true)
+                .write(true)
+                .open(&path)
+                .await,
+        )?;
+        let on_disk = file.metadata().await?.len();
+        if !(0..=plan.paths.len() as i64).contains(&parts_done)
+            || !(0..=on_disk as i64).contains(&bytes_done)
+        {
+            (parts_done, bytes_done) = (0, 0);
+        }
+        self.track_write(file.set_len(bytes_done as u64).await)?;
+
+        let dedup = self.options.dedup;
+        let mut md5 = Context::new();
+        let mut sha256 = (self.options.compute_sha256 || dedup).then(Sha256::new);
+        let mut buf = vec![0u8; COPY_CHUNK];
+        let mut digest = |chunk: &[u8]| {
+            md5.consume(chunk);
+            if let Some(sha256) = sha256.as_mut() {
+                sha256.update(chunk);
+            }
+        };
+        // Digests cover the whole payload, so re-read what was assembled.
+        file.seek(SeekFrom::Start(0)).await?;
+        let mut prefix = (&mut file).take(bytes_done as u64);
+        loop {
+            let n = prefix.read(&mut buf).await?;
+            if n == 0 {
+                break;
+            }
+            digest(&buf[..n]);
+        }
+        file.seek(SeekFrom::Start(bytes_done as u64)).await?;
+
+        for part_path in &plan.paths[parts_done as usize..] {
+            let mut part = tokio::fs::File::open(part_path).await?;
+            loop {
+                let n = part.read(&mut buf).await?;
+                if n == 0 {
+                    break;
+                }
+                digest(&buf[..n]);
+                self.track_write(file.write_all(&buf[..n]).await)?;
+                bytes_done += n as i64;
+            }
+            self.track_write(file.sync_data().await)?;
+            parts_done += 1;
+            sqlx::query(
+                "UPDATE multipart_completions
+                 SET parts_assembled = ?, bytes_assembled = ?, updated_at = ?
+                 WHERE upload_id = ?",
+            )
+            .bind(parts_done)
+            .bind(bytes_done)
+            .bind(Utc::now())
+            .bind(upload.id)
+            .execute(&*self.db)
+            .await?;
+        }
+        self.track_write(file.flush().await)?;
+        drop(file);
+        self.volume.record_write_ok();
+
+        let sha256 = sha256.map(|h| h.finalize());
+        let staged = StagedPayload {
+            file: StagingFile::new(path, self.uploads.clone()),
+            size_bytes: bytes_done,
+            md5: md5.compute(),
+            sha256: sha256
+                .filter(|_| self.options.compute_sha256)
+                .map(|h| general_purpose::STANDARD.encode(h)),
+            crc32c: None,
+            content_digest: sha256.filter(|_| dedup).map(|h| format!("{:x}", h)),
+        };
+        let attrs = self.upload_attributes(upload, plan.parts)?;
+        self.commit_payload(bucket, &upload.key, staged, plan.etag, attrs)
+            .await
+    }
+}
@@ -0,0 +1,229 @@
+//! Object naming policies.
+//!
+//! Platform owners sharing a bucket between teams can make it refuse keys
+//! that break their conventions instead of relying on everyone to follow
+//! them (`PUT /admin/buckets/{bucket}/naming-policy`, or a bucket template):
+//!
+//! ```json
+//! {
+//!   "allow": ["[a-z0-9][a-z0-9._/-]*"],
+//!   "required_prefix": "(team-a|team-b)/",
+//!   "max_depth": 4,
+//!   "forbidden_extensions": ["exe", "tar.gz"]
+//! }
+//! ```
+//!
+//! - `allow`: a key must match one of these regular expressions in full;
+//! - `required_prefix`: a key must start with a match of this one;
+//! - `max_depth`: most `/`-separated segments a key may have (a trailing
+//!   `/` of a folder marker does not count);
+//! - `forbidden_extensions`: suffixes after a `.` of the key's last segment
+//!   that are refused, compared without regard to case.
+//!
+//! Policies apply to keys written from then on: PUT, POST, CopyObject
+//! destinations and CreateMultipartUpload of a key that breaks one answer
+//! `KeyNotAllowed` (400) saying which rule. Existing keys are left alone.
+
+use crate::{
+    models::bucket::Bucket,
+    services::storage_service::{StorageError, StorageResult, StorageService},
+};
+use regex::{Regex, RegexBuilder};
+use serde::{Deserialize, Serialize};
+use sqlx::{Sqlite, Transaction};
+use uuid::Uuid;
+
+/// Compiled size limit for policy patterns, so a policy cannot make every
+/// write expensive.
+const PATTERN_SIZE_LIMIT: usize = 1 << 20;
+
+/// Constraints on the keys written to a bucket; empty fields do not apply.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct NamingPolicy {
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub allow: Vec<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub required_prefix: Option<String>,
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub max_depth: Option<u32>,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub forbidden_extensions: Vec<String>,
+}
+
+impl NamingPolicy {
+    pub fn is_empty(&self) -> bool {
+        self.allow.is_empty()
+            && self.required_prefix.is_none()
+            && self.max_depth.is_none()
+            && self.forbidden_extensions.is_empty()
+    }
+
+    /// Check the patterns compile and the limits make sense, and return the
+    /// policy with extensions normalized (lowercase, no leading dot).
+    pub fn validate(mut self) -> StorageResult<Self> {
+        self.compile()?;
+        if self.max_depth == Some(0) {
+            return Err(StorageError::InvalidNamingPolicy(
+                "max_depth must be at least 1".into(),
+            ));
+        }
+        for ext in &mut self.forbidden_extensions {
+            *ext = ext.trim().trim_start_matches('.').to_lowercase();
+            if ext.is_empty() {
+                return Err(StorageError::InvalidNamingPolicy(
+                    "forbidden_extensions may not contain empty entries".into(),
+                ));
+            }
+        }
+        Ok(self)
+    }
+
+    fn compile(&self) -> StorageResult<CompiledPolicy<'_>> {
+        let compile = |pattern: String, source: &str| {
+            RegexBuilder::new(&pattern)
+                .size_limit(PATTERN_SIZE_LIMIT)
+                .build()
+                .map_err(|err| {
+                    StorageError::InvalidNamingPolicy(format!("pattern `{}`: {}", source, err))
+                })
+        };
+        Ok(CompiledPolicy {
+            policy: self,
+            allow: self
+                .allow
+                .iter()
+                .map(|p| compile(format!("^(?:{})$", p), p))
+                .collect::<StorageResult<_>>()?,
+            required_prefix: self
+                .required_prefix
+                .as_deref()
+                .map(|p| compile(format!("^(?:{})", p), p))
+                .transpose()?,
+        })
+    }
+
+    /// The first rule `key` breaks, if any.
+    pub fn violation(&self, key: &str) -> StorageResult<Option<String>> {
+        Ok(self.compile()?.violation(key))
+    }
+}
+
+struct CompiledPolicy<'a> {
+    policy: &'a NamingPolicy,
+    allow: Vec<Regex>,
+    required_prefix: Option<Regex>,
+}
+
+impl CompiledPolicy<'_> {
+    fn violation(&self, key: &str) -> Option<String> {
+        if !self.allow.is_empty() && !self.allow.iter().any(|re| re.is_match(key)) {
+            return Some(format!(
+                "it matches none of the allowed patterns {:?}",
+                self.policy.allow
+            ));
+        }
+        if let (Some(re), Some(pattern)) = (&self.required_prefix, &self.policy.required_prefix)
+            && !re.is_match(key)
+        {
+            return Some(format!("it does not start with a match of `{}`", pattern));
+        }
+        if let Some(max_depth) = self.policy.max_depth {
+            let depth = key.trim_end_matches('/').split('/').count();
+            if depth > max_depth as usize {
+                return Some(format!(
+                    "it is {} levels deep, more than the {} allowed",
+                    depth, max_depth
+                ));
+            }
+        }
+        let name = key.rsplit('/').next().unwrap_or(key).to_lowercase();
+        self.policy
+            .forbidden_extensions
+            .iter()
+            .find(|ext| {
+                name.strip_suffix(ext.as_str())
+                    .is_some_and(|stem| stem.len() > 1 && stem.ends_with('.'))
+            })
+            .map(|ext| format!("extension `.{}` is not allowed", ext))
+    }
+}
+
+impl StorageService {
+    /// The naming policy of `bucket`; empty when it has none.
+    pub async fn get_naming_policy(&self, bucket: &str) -> StorageResult<NamingPolicy> {
+        let bucket_rec = self.fetch_bucket(bucket).await?;
+        self.naming_policy(bucket_rec.id).await
+    }
+
+    /// Replace the naming policy of `bucket`; an empty policy removes it.
+    pub async fn set_naming_policy(
+        &self,
+        bucket: &str,
+        policy: NamingPolicy,
+    ) -> StorageResult<NamingPolicy> {
+        let bucket_rec = self.fetch_bucket(bucket).await?;
+        let policy = policy.validate()?;
+        let mut tx = self.db.begin().await?;
+        store_naming_policy(&mut tx, bucket_rec.id, &policy).await?;
+        tx.commit().await?;
+        Ok(policy)
+    }
+
+    /// Refuse writing `key` to `bucket` when it breaks the bucket's naming
+    /// policy.
+    pub(crate) async fn ensure_key_allowed(&self, bucket: &Bucket, key: &str) -> StorageResult<()> {
+        let policy = self.naming_policy(bucket.id).await?;
+        if policy.is_empty() {
+            return Ok(());
+        }
+        match policy.violation(key)? {
+            Some(reason) => Err(StorageError::KeyNotAllowed {
+                key: key.to_string(),
+                bucket: bucket.name.clone(),
+                reason,
+            }),
+            None => Ok(()),
+        }
+    }
+
+    async fn naming_policy(&self, bucket_id: Uuid) -> StorageResult<NamingPolicy> {
+        let row: Option<(String,)> =
+            sqlx::query_as("SELECT policy FROM bucket_naming_policies WHERE bucket_id = ?")
+                .bind(bucket_id)
+                .fetch_optional(&*self.db)
+                .await?;
+        match row {
+            Some((json,)) => serde_json::from_str(&json).map_err(|err| {
+                StorageError::InvalidNamingPolicy(format!("stored policy is unreadable: {}", err))
+            }),
+            None => Ok(NamingPolicy::default()),
+        }
+    }
+}
+
+/// Store (or, when empty, remove) the naming policy of a bucket in `tx`.
+pub(crate) async fn store_naming_policy(
+    tx: &mut Transaction<'_, Sqlite>,
+    bucket_id: Uuid,
+    policy: &NamingPolicy,
+) -> StorageResult<()> {
+    if policy.is_empty() {
+        sqlx::query("DELETE FROM bucket_naming_policies WHERE bucket_id = ?")
+            .bind(bucket_id)
+            .execute(&mut **tx)
+            .await?;
+        return Ok(());
+    }
+    let json = serde_json::to_string(policy)
+        .map_err(|err| StorageError::InvalidNamingPolicy(err.to_string()))?;
+    sqlx::query(
+        "INSERT INTO bucket_naming_policies (bucket_id, policy) VALUES (?, ?)
+         ON CONFLICT(bucket_id) DO UPDATE SET policy = excluded.policy",
+    )
+    .bind(bucket_id)
+    .bind(json)
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
@@ -0,0 +1,555 @@
+//! Bucket event notifications to webhooks.
+//!
+//! A bucket's notification configuration (S3 `?notification`) lists webhook
+//! targets, each with the events it wants and an optional key prefix and
+//! suffix filter. Events come from the change feed (see `changes`), which
+//! every object write and delete already records in its own transaction:
+//!
+//! - `created` and `updated` changes are `ObjectCreated:Put` events;
+//! - `deleted` changes are `ObjectRemoved:Delete` events.
+//!
+//! Only these two, and the `s3:ObjectCreated:*` / `s3:ObjectRemoved:*`
+//! wildcards, can be configured; other event names are refused rather than
+//! stored and never fired.
+//!
+//! The dispatcher (`spawn_notification_dispatcher`) first fans new changes
+//! out into `notification_deliveries`, one row per matching target holding
+//! the S3 event JSON, advancing a persisted cursor in the same transaction.
+//! It then POSTs due deliveries. A delivery is removed once its endpoint
+//! answers `2xx`; otherwise it is retried with exponential backoff, up to
+//! `MAX_DELIVERY_ATTEMPTS`, after which it is marked `failed` and kept for
+//! `FAILED_DELIVERY_RETENTION`. Queued deliveries survive restarts; events
+//! may arrive more than once and out of order (use `sequencer`).
+
+use crate::{
+    models::notification::NotificationTarget,
+    services::{
+        outbound::OutboundHttp,
+        storage_service::{StorageError, StorageResult, StorageService},
+    },
+};
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use futures::{StreamExt, stream};
+use percent_encoding::{AsciiSet, NON_ALPHANUMERIC, utf8_percent_encode};
+use reqwest::{Client, Url};
+use serde::Serialize;
+use serde_json::json;
+use sqlx::{Sqlite, Transaction};
+use std::{collections::HashMap, time::Duration};
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+/// How often the dispatcher looks for new changes and due deliveries.
+pub const NOTIFICATION_TICK: Duration = Duration::from_secs(1);
+
+/// Attempts before a delivery is given up and marked `failed`.
+pub const MAX_DELIVERY_ATTEMPTS: i64 = 10;
+
+/// Wait after the first failed attempt; doubled after each further one.
+const RETRY_BASE: Duration = Duration::from_secs(5);
+
+/// Longest wait between two attempts.
+const RETRY_MAX: Duration = Duration::from_secs(3600);
+
+/// How long failed deliveries are kept for inspection.
+pub const FAILED_DELIVERY_RETENTION: Duration = Duration::from_secs(7 * 24 * 3600);
+
+const DELIVERY_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Changes fanned out, and deliveries attempted, per pass.
+const BATCH: i64 = 500;
+
+/// Deliveries in flight at once.
+const CONCURRENT_DELIVERIES: usize = 8;
+
+/// Event names a target may subscribe to.
+pub const SUPPORTED_EVENTS: [&str; 4] = [
+    "s3:ObjectCreated:*",
+    "s3:ObjectCreated:Put",
+    "s3:ObjectRemoved:*",
+    "s3:ObjectRemoved:Delete",
+];
+
+/// Characters escaped in the event's object key, as S3 does.
+const KEY_ENCODE: &AsciiSet = &NON_ALPHANUMERIC
+    .remove(b'-')
+    .remove(b'_')
+    .remove(b'.')
+    .remove(b'~')
+    .remove(b'/');
+
+/// What one dispatcher pass did.
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct DispatchSummary {
+    /// Deliveries queued from new changes.
+    pub queued: u64,
+    pub delivered: u64,
+    /// Failed attempts that will be retried.
+    pub retried: u64,
+    /// Deliveries given up on.
+    pub failed: u64,
+}
+
+type TargetRow = (String, String, String, Option<String>, Option<String>);
+
+type BucketTargetRow = (
+    Uuid,
+    String,
+    String,
+    String,
+    Option<String>,
+    Option<String>,
+    i64,
+);
+
+type ChangeRow = (
+    i64,
+    Uuid,
+    String,
+    String,
+    String,
+    String,
+    Option<String>,
+    Option<String>,
+    Option<i64>,
+    DateTime<Utc>,
+);
+
+type DeliveryRow = (i64, String, String, String, i64);
+
+impl StorageService {
+    /// Replace the notification configuration of `bucket`; an empty list
+    /// removes it. Deliveries still queued for dropped targets are dropped
+    /// with them.
+    pub async fn put_bucket_notification(
+        &self,
+        bucket: &str,
+        targets: &[NotificationTarget],
+    ) -> StorageResult<()> {
+        validate_targets(targets)?;
+        let bucket_rec = self.fetch_writable_bucket(bucket).await?;
+        let mut tx = self.db.begin().await?;
+        let since: (i64,) = sqlx::query_as("SELECT COALESCE(MAX(seq), 0) FROM bucket_changes")
+            .fetch_one(&mut *tx)
+            .await?;
+        let kept: HashMap<&str, &str> = targets
+            .iter()
+            .map(|target| (target.id.as_str(), target.endpoint.as_str()))
+            .collect();
+        let existing: Vec<(String, String, i64)> = sqlx::query_as(
+            "SELECT target_id, endpoint, since_seq FROM notification_targets WHERE bucket_id = ?",
+        )
+        .bind(bucket_rec.id)
+        .fetch_all(&mut *tx)
+        .await?;
+        sqlx::query("DELETE FROM notification_targets WHERE bucket_id = ?")
+            .bind(bucket_rec.id)
+            .execute(&mut *tx)
+            .await?;
+        for (id, endpoint, _) in &existing {
+            if kept.get(id.as_str()) != Some(&endpoint.as_str()) {
+                sqlx::query(
+                    "DELETE FROM notification_deliveries
+                     WHERE bucket_id = ? AND target_id = ? AND state = 'pending'",
+                )
+                .bind(bucket_rec.id)
+                .bind(id)
+                .execute(&mut *tx)
+                .await?;
+            }
+        }
+        for (position, target) in targets.iter().enumerate() {
+            // A target that is only reconfigured keeps its place in the feed.
+            let since_seq = existing
+                .iter()
+                .find(|(id, endpoint, _)| *id == target.id && *endpoint == target.endpoint)
+                .map(|(_, _, since_seq)| *since_seq)
+                .unwrap_or(since.0);
+            insert_target(&mut tx, bucket_rec.id, position, target, since_seq).await?;
+        }
+        tx.commit().await?;
+        info!(
+            "set {} notification target(s) on bucket `{}`",
+            targets.len(),
+            bucket_rec.name
+        );
+        Ok(())
+    }
+
+    /// The notification targets of `bucket`, in configuration order.
+    pub async fn get_bucket_notification(
+        &self,
+        bucket: &str,
+    ) -> StorageResult<Vec<NotificationTarget>> {
+        let bucket_rec = self.fetch_bucket(bucket).await?;
+        let rows: Vec<TargetRow> = sqlx::query_as(
+            "SELECT target_id, endpoint, events, prefix, suffix
+             FROM notification_targets WHERE bucket_id = ? ORDER BY position",
+        )
+        .bind(bucket_rec.id)
+        .fetch_all(&*self.db)
+        .await?;
+        rows.into_iter().map(target_from_row).collect()
+    }
+
+    /// Queue deliveries for the changes recorded since the last pass.
+    /// Returns the number queued.
+    pub async fn fan_out_notifications(&self) -> StorageResult<u64> {
+        let mut tx = self.db.begin().await?;
+        let (cursor,): (i64,) =
+            sqlx::query_as("SELECT last_seq FROM notification_cursor WHERE id = 1")
+                .fetch_optional(&mut *tx)
+                .await?
+                .unwrap_or((0,));
+        let changes: Vec<ChangeRow> = sqlx::query_as(
+            "SELECT c.seq, c.bucket_id, b.name, b.region, c.key, c.event, c.version_id,
+                    c.etag, c.size_bytes, c.occurred_at
+             FROM bucket_changes c JOIN buckets b ON b.id = c.bucket_id
+             WHERE c.seq > ? ORDER BY c.seq LIMIT ?",
+        )
+        .bind(cursor)
+        .bind(BATCH)
+        .fetch_all(&mut *tx)
+        .await?;
+        let Some(last) = changes.last().map(|change| change.0) else {
+            return Ok(0);
+        };
+
+        let mut targets: HashMap<Uuid, Vec<(NotificationTarget, i64)>> = HashMap::new();
+        let rows: Vec<BucketTargetRow> = sqlx::query_as(
+            "SELECT bucket_id, target_id, endpoint, events, prefix, suffix, since_seq
+             FROM notification_targets ORDER BY position",
+        )
+        .fetch_all(&mut *tx)
+        .await?;
+        for (bucket_id, id, endpoint, events, prefix, suffix, since_seq) in rows {
+            let target = target_from_row((id, endpoint, events, prefix, suffix))?;
+            targets
+                .entry(bucket_id)
+                .or_default()
+                .push((target, since_seq));
+        }
+
+        let now = Utc::now();
+        let mut queued = 0;
+        for change in &changes {
+            let (seq, bucket_id, _, _, key, event, ..) = change;
+            let event_name = event_name(event);
+            for (target, since_seq) in targets.get(bucket_id).into_iter().flatten() {
+                if seq <= since_seq || !target.matches(event_name, key) {
+                    continue;
+                }
+                let payload = event_payload(change, event_name, &target.id);
+                sqlx::query(
+                    "INSERT INTO notification_deliveries
+                        (bucket_id, target_id, endpoint, event_name, payload,
+                         next_attempt_at, created_at)
+                     VALUES (?, ?, ?, ?, ?, ?, ?)",
+                )
+                .bind(bucket_id)
+                .bind(&target.id)
+                .bind(&target.endpoint)
+                .bind(format!("s3:{}", event_name))
+                .bind(payload.to_string())
+                .bind(now)
+                .bind(now)
+                .execute(&mut *tx)
+                .await?;
+                queued += 1;
+            }
+        }
+        sqlx::query(
+            "INSERT INTO notification_cursor (id, last_seq) VALUES (1, ?)
+             ON CONFLICT(id) DO UPDATE SET last_seq = excluded.last_seq",
+        )
+        .bind(last)
+        .execute(&mut *tx)
+        .await?;
+        tx.commit().await?;
+        if queued > 0 {
+            debug!("queued {} event notification(s)", queued);
+        }
+        Ok(queued)
+    }
+}
+
+/// Fans changes out to webhook deliveries and sends them.
+#[derive(Clone)]
+pub struct NotificationDispatcher {
+    service: StorageService,
+    client: Client,
+}
+
+impl NotificationDispatcher {
+    /// Build a dispatcher whose webhook calls go through the shared
+    /// outbound client factory.
+    pub fn new(service: StorageService, outbound: &OutboundHttp) -> reqwest::Result<Self> {
+        let client = outbound
+            .client_builder()
+            .timeout(DELIVERY_TIMEOUT)
+            .build()?;
+        Ok(Self { service, client })
+    }
+
+    /// Queue deliveries for new changes, then attempt every due one.
+    pub async fn run_once(&self) -> StorageResult<DispatchSummary> {
+        let mut summary = DispatchSummary {
+            queued: self.service.fan_out_notifications().await?,
+            ..DispatchSummary::default()
+        };
+        let now = Utc::now();
+        let due: Vec<DeliveryRow> = sqlx::query_as(
+            "SELECT id, endpoint, event_name, payload, attempts
+             FROM notification_deliveries
+             WHERE state = 'pending' AND next_attempt_at <= ?
+             ORDER BY id LIMIT ?",
+        )
+        .bind(now)
+        .bind(BATCH)
+        .fetch_all(&*self.service.db)
+        .await?;
+        let outcomes: Vec<(DeliveryRow, Result<(), String>)> = stream::iter(due)
+            .map(|delivery| async move {
+                let result = self.send(&delivery.1, &delivery.3).await;
+                (delivery, result)
+            })
+            .buffer_unordered(CONCURRENT_DELIVERIES)
+            .collect()
+            .await;
+        for ((id, endpoint, event_name, _, attempts), result) in outcomes {
+            let attempts = attempts + 1;
+            match result {
+                Ok(()) => {
+                    sqlx::query("DELETE FROM notification_deliveries WHERE id = ?")
+                        .bind(id)
+                        .execute(&*self.service.db)
+                        .await?;
+                    summary.delivered += 1;
+                }
+                Err(err) if attempts >= MAX_DELIVERY_ATTEMPTS => {
+                    warn!(
+                        "giving up on {} notification to {} after {} attempts: {}",
+                        event_name, endpoint, attempts, err
+                    );
+                    self.record_failure(id, attempts, "failed", now, &err)
+                        .await?;
+                    summary.failed += 1;
+                }
+                Err(err) => {
+                    debug!(
+                        "{} notification to {} failed (attempt {}): {}",
+                        event_name, endpoint, attempts, err
+                    );
+                    let next = now
+                        + ChronoDuration::from_std(retry_delay(attempts))
+                            .unwrap_or(ChronoDuration::MAX);
+                    self.record_failure(id, attempts, "pending", next, &err)
+                        .await?;
+                    summary.retried += 1;
+                }
+            }
+        }
+
+        let cutoff = now
+            - ChronoDuration::from_std(FAILED_DELIVERY_RETENTION).unwrap_or(ChronoDuration::MAX);
+        sqlx::query(
+            "DELETE FROM notification_deliveries WHERE state = 'failed' AND next_attempt_at < ?",
+        )
+        .bind(cutoff)
+        .execute(&*self.service.db)
+        .await?;
+        Ok(summary)
+    }
+
+    async fn send(&self, endpoint: &str, payload: &str) -> Result<(), String> {
+        let url = Url::parse(endpoint).map_err(|err| err.to_string())?;
+        let response = self
+            .client
+            .post(url)
+            .header(reqwest::header::CONTENT_TYPE, "application/json")
+            .body(payload.to_string())
+            .send()
+            .await
+            .map_err(|err| err.to_string())?;
+        if response.status().is_success() {
+            Ok(())
+        } else {
+            Err(format!("endpoint answered {}", response.status()))
+        }
+    }
+
+    /// Record a failed attempt; for `failed` deliveries `next_attempt_at`
+    /// is when they were given up.
+    async fn record_failure(
+        &self,
+        id: i64,
+        attempts: i64,
+        state: &str,
+        next_attempt_at: DateTime<Utc>,
+        error: &str,
+    ) -> StorageResult<()> {
+        sqlx::query(
+            "UPDATE notification_deliveries
+             SET attempts = ?, state = ?, next_attempt_at = ?, last_error = ?
+             WHERE id = ?",
+        )
+        .bind(attempts)
+        .bind(state)
+        .bind(next_attempt_at)
+        .bind(error)
+        .bind(id)
+        .execute(&*self.service.db)
+        .await?;
+        Ok(())
+    }
+}
+
+/// Run the dispatcher every `period`.
+pub fn spawn_notification_dispatcher(
+    dispatcher: NotificationDispatcher,
+    period: Duration,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(period);
+        loop {
+            ticker.tick().await;
+            if let Err(err) = dispatcher.run_once().await {
+                warn!("event notification pass failed: {}", err);
+            }
+        }
+    })
+}
+
+/// Wait before attempt `attempts + 1`.
+fn retry_delay(attempts: i64) -> Duration {
+    let doublings = attempts.clamp(1, 32) as u32 - 1;
+    RETRY_BASE
+        .checked_mul(1 << doublings)
+        .unwrap_or(RETRY_MAX)
+        .min(RETRY_MAX)
+}
+
+/// S3 event name of a change feed event.
+fn event_name(event: &str) -> &'static str {
+    match event {
+        "deleted" => "ObjectRemoved:Delete",
+        _ => "ObjectCreated:Put",
+    }
+}
+
+/// The S3 event JSON of one change for target `configuration_id`.
+fn event_payload(
+    change: &ChangeRow,
+    event_name: &str,
+    configuration_id: &str,
+) -> serde_json::Value {
+    let (seq, _, bucket, region, key, _, version_id, etag, size_bytes, occurred_at) = change;
+    let mut object = json!({
+        "key": utf8_percent_encode(key, KEY_ENCODE).to_string(),
+        "sequencer": format!("{:016X}", seq),
+    });
+    if let Some(size) = size_bytes {
+        object["size"] = json!(size);
+    }
+    if let Some(etag) = etag {
+        object["eTag"] = json!(etag.trim_matches('"'));
+    }
+    if let Some(version_id) = version_id {
+        object["versionId"] = json!(version_id);
+    }
+    json!({
+        "Records": [{
+            "eventVersion": "2.1",
+            "eventSource": "aws:s3",
+            "awsRegion": region,
+            "eventTime": occurred_at.to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+            "eventName": event_name,
+            "s3": {
+                "s3SchemaVersion": "1.0",
+                "configurationId": configuration_id,
+                "bucket": {
+                    "name": bucket,
+                    "arn": format!("arn:aws:s3:::{}", bucket),
+                },
+                "object": object,
+            },
+        }],
+    })
+}
+
+fn target_from_row(row: TargetRow) -> StorageResult<NotificationTarget> {
+    let (id, endpoint, events, prefix, suffix) = row;
+    let events = serde_json::from_str(&events)
+        .map_err(|err| StorageError::InvalidNotification(err.to_string()))?;
+    Ok(NotificationTarget {
+        id,
+        endpoint,
+        events,
+        prefix,
+        suffix,
+    })
+}
+
+async fn insert_target(
+    tx: &mut Transaction<'_, Sqlite>,
+    bucket_id: Uuid,
+    position: usize,
+    target: &NotificationTarget,
+    since_seq: i64,
+) -> StorageResult<()> {
+    let events = serde_json::to_string(&target.events)
+        .map_err(|err| StorageError::InvalidNotification(err.to_string()))?;
+    sqlx::query(
+        "INSERT INTO notification_targets
+            (bucket_id, target_id, position, endpoint, events, prefix, suffix, since_seq)
+         VALUES (?, ?, ?, ?, ?, ?, ?, ?)",
+    )
+    .bind(bucket_id)
+    .bind(&target.id)
+    .bind(position as i64)
+    .bind(&target.endpoint)
+    .bind(events)
+    .bind(&target.prefix)
+    .bind(&target.suffix)
+    .bind(since_seq)
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+pub(crate) fn validate_targets(targets: &[NotificationTarget]) -> StorageResult<()> {
+    let mut ids = std::collections::HashSet::new();
+    for target in targets {
+        let invalid = |reason: String| {
+            StorageError::InvalidNotification(format!("target `{}`: {}", target.id, reason))
+        };
+        if target.id.is_empty() || target.id.len() > 255 {
+            return Err(StorageError::InvalidNotification(
+                "target ids must be 1 to 255 characters".into(),
+            ));
+        }
+        if !ids.insert(target.id.as_str()) {
+            return Err(invalid("duplicate id".into()));
+        }
+        match Url::parse(&target.endpoint) {
+            Ok(url) if matches!(url.scheme(), "http" | "https") && url.has_host() => {}
+            _ => return Err(invalid("Endpoint must be an http or https URL".into())),
+        }
+        if target.events.is_empty() {
+            return Err(invalid("at least one Event is required".into()));
+        }
+        if let Some(event) = target
+            .events
+            .iter()
+            .find(|event| !SUPPORTED_EVENTS.contains(&event.as_str()))
+        {
+            return Err(invalid(format!(
+                "event `{}` is not supported (supported: {})",
+                event,
+                SUPPORTED_EVENTS.join(", ")
+            )));
+        }
+    }
+    Ok(())
+}
@@ -0,0 +1,291 @@
+//! Object Lock.
+//!
+//! A bucket's Object Lock configuration (S3 `?object-lock`) can only be put
+//! on a bucket with versioning enabled; once put, Object Lock stays enabled
+//! and versioning can no longer be suspended. The configuration may carry a
+//! default retention (mode plus days or years) that every new version gets
+//! unless the upload names its own with `x-amz-object-lock-mode` and
+//! `x-amz-object-lock-retain-until-date`, so compliance buckets do not
+//! depend on every client sending those headers.
+//!
+//! Retention is recorded per version in `object_retention`. A version still
+//! under retention cannot be deleted (`DELETE ?versionId=`), nor can a bucket
+//! holding one; deleting a key without a version id only adds a delete
+//! marker and is always allowed, as in S3.
+
+use crate::{
+    models::{
+        bucket::Bucket,
+        object_lock::{DefaultRetention, ObjectLockConfiguration, ObjectRetention, RetentionMode},
+    },
+    services::{
+        events::EventKind,
+        storage_service::{StorageError, StorageResult, StorageService},
+    },
+};
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use sqlx::{Sqlite, Transaction};
+use std::str::FromStr;
+use tracing::info;
+use uuid::Uuid;
+
+/// Longest default retention accepted, as in S3 (100 years).
+pub const MAX_RETENTION_DAYS: i64 = 36_500;
+
+/// A stored lock configuration row.
+type LockRow = (Option<String>, Option<i64>, Option<i64>);
+
+impl StorageService {
+    /// Enable Object Lock on `bucket` (if it is not yet) and replace its
+    /// default retention.
+    pub async fn put_object_lock_configuration(
+        &self,
+        bucket: &str,
+        config: ObjectLockConfiguration,
+    ) -> StorageResult<()> {
+        if let Some(default) = config.default_retention {
+            validate_default_retention(&default)?;
+        }
+        let bucket_rec = self.fetch_writable_bucket(bucket).await?;
+        if !bucket_rec.versioning_enabled {
+            return Err(StorageError::InvalidBucketState(format!(
+                "versioning must be enabled on `{}` before Object Lock",
+                bucket_rec.name
+            )));
+        }
+        let default = config.default_retention;
+        let mut tx = self.db.begin().await?;
+        store_configuration(&mut tx, bucket_rec.id, &config).await?;
+        tx.commit().await?;
+        info!(
+            "object lock configured on bucket `{}` (default retention: {:?})",
+            bucket_rec.name, default
+        );
+        self.events.bucket(
+            EventKind::BucketConfigChanged,
+            &bucket_rec.name,
+            Some("object-lock".into()),
+        );
+        Ok(())
+    }
+
+    /// The Object Lock configuration of `bucket`.
+    pub async fn get_object_lock_configuration(
+        &self,
+        bucket: &str,
+    ) -> StorageResult<ObjectLockConfiguration> {
+        let bucket_rec = self.fetch_bucket(bucket).await?;
+        self.object_lock_configuration(&bucket_rec)
+            .await?
+            .ok_or(StorageError::NoSuchObjectLockConfiguration(bucket_rec.name))
+    }
+
+    /// `None` when Object Lock is not enabled on `bucket`.
+    pub(crate) async fn object_lock_configuration(
+        &self,
+        bucket: &Bucket,
+    ) -> StorageResult<Option<ObjectLockConfiguration>> {
+        let row: Option<LockRow> = sqlx::query_as(
+            "SELECT default_mode, default_days, default_years
+             FROM bucket_object_lock WHERE bucket_id = ?",
+        )
+        .bind(bucket.id)
+        .fetch_optional(&*self.db)
+        .await?;
+        let Some((mode, days, years)) = row else {
+            return Ok(None);
+        };
+        let default_retention = mode
+            .map(|mode| {
+                RetentionMode::from_str(&mode).map(|mode| DefaultRetention { mode, days, years })
+            })
+            .transpose()
+            .map_err(StorageError::InvalidObjectLock)?;
+        Ok(Some(ObjectLockConfiguration { default_retention }))
+    }
+
+    /// Retention for a new version of a key in `bucket`: `requested` (only
+    /// allowed with Object Lock enabled), else the bucket's default.
+    pub(crate) async fn resolve_retention(
+        &self,
+        bucket: &Bucket,
+        requested: Option<ObjectRetention>,
+    ) -> StorageResult<Option<ObjectRetention>> {
+        let config = self.object_lock_configuration(bucket).await?;
+        let now = Utc::now();
+        match (config, requested) {
+            (None, None) => Ok(None),
+            (None, Some(_)) => Err(StorageError::InvalidObjectLock(format!(
+                "bucket `{}` does not have Object Lock enabled",
+                bucket.name
+            ))),
+            (Some(_), Some(retention)) if retention.retain_until <= now => Err(
+                StorageError::InvalidObjectLock("retain-until date must be in the future".into()),
+            ),
+            (Some(_), Some(retention)) => Ok(Some(retention)),
+            (Some(config), None) => Ok(config.default_retention.map(|default| {
+                let days = default
+                    .days
+                    .unwrap_or_else(|| default.years.unwrap_or(0) * 365);
+                ObjectRetention {
+                    mode: default.mode,
+                    retain_until: now + ChronoDuration::days(days),
+                }
+            })),
+        }
+    }
+
+    /// Retention of one version of a key, if it has any.
+    pub async fn object_retention(
+        &self,
+        bucket_id: Uuid,
+        key: &str,
+        version_id: &str,
+    ) -> StorageResult<Option<ObjectRetention>> {
+        let row: Option<(String, DateTime<Utc>)> = sqlx::query_as(
+            "SELECT mode, retain_until FROM object_retention
+             WHERE bucket_id = ? AND key = ? AND version_id = ?",
+        )
+        .bind(bucket_id)
+        .bind(key)
+        .bind(version_id)
+        .fetch_optional(&*self.db)
+        .await?;
+        row.map(|(mode, retain_until)| {
+            Ok(ObjectRetention {
+                mode: RetentionMode::from_str(&mode).map_err(StorageError::InvalidObjectLock)?,
+                retain_until,
+            })
+        })
+        .transpose()
+    }
+
+    /// Refuse to delete a version that is still under retention.
+    pub(crate) async fn ensure_version_unlocked(
+        &self,
+        bucket: &Bucket,
+        key: &str,
+        version_id: &str,
+    ) -> StorageResult<()> {
+        match self.object_retention(bucket.id, key, version_id).await? {
+            Some(retention) if retention.retain_until > Utc::now() => {
+                Err(StorageError::ObjectLocked {
+                    key: key.to_string(),
+                    version_id: version_id.to_string(),
+                    retain_until: retention.retain_until,
+                })
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Refuse to delete a bucket holding versions under retention.
+    pub(crate) async fn ensure_bucket_unlocked(&self, bucket: &Bucket) -> StorageResult<()> {
+        let locked: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM object_retention WHERE bucket_id = ? AND retain_until > ?",
+        )
+        .bind(bucket.id)
+        .bind(Utc::now())
+        .fetch_one(&*self.db)
+        .await?;
+        if locked > 0 {
+            return Err(StorageError::InvalidBucketState(format!(
+                "bucket `{}` holds {} object version(s) under retention",
+                bucket.name, locked
+            )));
+        }
+        Ok(())
+    }
+}
+
+/// Record the retention of a version written in `tx`.
+pub(crate) async fn insert_retention(
+    tx: &mut Transaction<'_, Sqlite>,
+    bucket_id: Uuid,
+    key: &str,
+    version_id: &str,
+    retention: &ObjectRetention,
+) -> Result<(), sqlx::Error> {
+    sqlx::query(
+        "INSERT OR REPLACE INTO object_retention (bucket_id, key, version_id, mode, retain_until)
+         VALUES (?, ?, ?, ?, ?)",
+    )
+    .bind(bucket_id)
+    .bind(key)
+    .bind(version_id)
+    .bind(retention.mode.as_str())
+    .bind(retention.retain_until)
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+/// Parse `x-amz-object-lock-mode` and `x-amz-object-lock-retain-until-date`,
+/// which must come together.
+pub fn parse_requested_retention(
+    mode: Option<&str>,
+    retain_until: Option<&str>,
+) -> StorageResult<Option<ObjectRetention>> {
+    match (mode, retain_until) {
+        (None, None) => Ok(None),
+        (Some(mode), Some(retain_until)) => {
+            let mode = RetentionMode::from_str(mode).map_err(StorageError::InvalidObjectLock)?;
+            let retain_until = DateTime::parse_from_rfc3339(retain_until)
+                .map_err(|err| {
+                    StorageError::InvalidObjectLock(format!(
+                        "x-amz-object-lock-retain-until-date is not an ISO 8601 date: {}",
+                        err
+                    ))
+                })?
+                .with_timezone(&Utc);
+            Ok(Some(ObjectRetention { mode, retain_until }))
+        }
+        _ => Err(StorageError::InvalidObjectLock(
+            "x-amz-object-lock-mode and x-amz-object-lock-retain-until-date must be sent together"
+                .into(),
+        )),
+    }
+}
+
+/// Enable Object Lock on a bucket, or replace its default retention.
+pub(crate) async fn store_configuration(
+    tx: &mut Transaction<'_, Sqlite>,
+    bucket_id: Uuid,
+    config: &ObjectLockConfiguration,
+) -> StorageResult<()> {
+    let default = config.default_retention;
+    sqlx::query(
+        "INSERT INTO bucket_object_lock (bucket_id, default_mode, default_days, default_years)
+         VALUES (?, ?, ?, ?)
+         ON CONFLICT(bucket_id) DO UPDATE SET
+             default_mode = excluded.default_mode,
+             default_days = excluded.default_days,
+             default_years = excluded.default_years",
+    )
+    .bind(bucket_id)
+    .bind(default.map(|d| d.mode.as_str()))
+    .bind(default.and_then(|d| d.days))
+    .bind(default.and_then(|d| d.years))
+    .execute(&mut **tx)
+    .await?;
+    Ok(())
+}
+
+pub(crate) fn validate_default_retention(default: &DefaultRetention) -> StorageResult<()> {
+    let days = match (default.days, default.years) {
+        (Some(days), None) => days,
+        (None, Some(years)) => years.saturating_mul(365),
+        _ => {
+            return Err(StorageError::InvalidObjectLock(
+                "DefaultRetention takes exactly one of Days or Years".into(),
+            ));
+        }
+    };
+    if !(1..=MAX_RETENTION_DAYS).contains(&days) {
+        return Err(StorageError::InvalidObjectLock(format!(
+            "default retention must be between 1 day and {} days",
+            MAX_RETENTION_DAYS
+        )));
+    }
+    Ok(())
+}
@@ -0,0 +1,173 @@
+//! Object search across buckets (`GET /admin/objects`).
+//!
+//! Cleanup and audit jobs ("everything over 1 GiB older than a year",
+//! "every `*.tmp` key") otherwise have to list each bucket in turn. A search
+//! walks the live objects of every bucket — or of one owner's buckets — in
+//! (bucket name, key) order, filtered by key prefix or `GLOB` pattern, size
+//! and age.
+//!
+//! Pages resume after the last object returned: the continuation token is
+//! that object's bucket and key, so keys written behind the cursor while a
+//! search is paged through are skipped, like in a bucket listing.
+
+use crate::services::{
+    keys::{self, MAX_OBJECT_KEY_LEN},
+    storage_service::{StorageError, StorageResult, StorageService},
+};
+use base64::{Engine as _, engine::general_purpose};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{FromRow, QueryBuilder, Sqlite};
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Most objects returned per page of a search.
+pub const MAX_SEARCH_KEYS: usize = 1000;
+
+/// Filters of an object search; all of them are optional.
+#[derive(Debug, Clone, Default)]
+pub struct ObjectSearch {
+    /// Only buckets with this `owner_id`.
+    pub owner: Option<Uuid>,
+    /// Only keys starting with this prefix.
+    pub prefix: Option<String>,
+    /// Only keys matching this `GLOB` pattern (`*`, `?`, `[...]`), e.g.
+    /// `*.tmp` or `logs/*/2024-*`.
+    pub pattern: Option<String>,
+    /// Only objects of at least this many bytes.
+    pub min_size: Option<u64>,
+    /// Only objects of at most this many bytes.
+    pub max_size: Option<u64>,
+    /// Only objects last modified more than this long ago.
+    pub older_than: Option<Duration>,
+    /// Only objects last modified at most this long ago.
+    pub newer_than: Option<Duration>,
+    /// Page size, clamped to `1..=MAX_SEARCH_KEYS`.
+    pub max_keys: usize,
+    /// `next_continuation_token` of the previous page.
+    pub continuation_token: Option<String>,
+}
+
+/// One object found by a search.
+#[derive(Debug, Clone, Serialize, FromRow)]
+pub struct FoundObject {
+    pub bucket: String,
+    pub owner_id: Uuid,
+    pub key: String,
+    pub size: i64,
+    pub etag: Option<String>,
+    pub last_modified: DateTime<Utc>,
+    pub storage_class: String,
+}
+
+/// A page of search results.
+#[derive(Debug, Serialize)]
+pub struct ObjectSearchPage {
+    pub objects: Vec<FoundObject>,
+    pub is_truncated: bool,
+    pub next_continuation_token: Option<String>,
+}
+
+impl StorageService {
+    /// One page of the live objects matching `search`, across buckets.
+    pub async fn search_objects(&self, search: ObjectSearch) -> StorageResult<ObjectSearchPage> {
+        if let Some(prefix) = &search.prefix {
+            self.ensure_prefix_safe(prefix)?;
+        }
+        if search
+            .pattern
+            .as_ref()
+            .is_some_and(|pattern| pattern.is_empty() || pattern.len() > MAX_OBJECT_KEY_LEN)
+        {
+            return Err(StorageError::InvalidSearch(
+                "pattern must be 1 to 1024 bytes".to_string(),
+            ));
+        }
+        let after = search
+            .continuation_token
+            .as_deref()
+            .map(decode_token)
+            .transpose()?;
+        let max_keys = search.max_keys.clamp(1, MAX_SEARCH_KEYS);
+        let now = Utc::now();
+
+        let mut query = QueryBuilder::<Sqlite>::new(
+            "SELECT b.name AS bucket, b.owner_id, o.key, o.size_bytes AS size, o.etag,
+                    o.last_modified, o.storage_class
+             FROM objects o JOIN buckets b ON b.id = o.bucket_id
+             WHERE o.is_deleted = 0",
+        );
+        if let Some(owner) = search.owner {
+            query.push(" AND b.owner_id = ");
+            query.push_bind(owner);
+        }
+        if let Some(prefix) = &search.prefix {
+            query.push(" AND o.key GLOB ");
+            query.push_bind(keys::prefix_glob(prefix));
+        }
+        if let Some(pattern) = &search.pattern {
+            query.push(" AND o.key GLOB ");
+            query.push_bind(pattern.clone());
+        }
+        if let Some(min_size) = search.min_size {
+            query.push(" AND o.size_bytes >= ");
+            query.push_bind(i64::try_from(min_size).unwrap_or(i64::MAX));
+        }
+        if let Some(max_size) = search.max_size {
+            query.push(" AND o.size_bytes <= ");
+            query.push_bind(i64::try_from(max_size).unwrap_or(i64::MAX));
+        }
+        if let Some(age) = search.older_than {
+            query.push(" AND o.last_modified < ");
+            query.push_bind(cutoff(now, age));
+        }
+        if let Some(age) = search.newer_than {
+            query.push(" AND o.last_modified >= ");
+            query.push_bind(cutoff(now, age));
+        }
+        if let Some((bucket, key)) = after {
+            query.push(" AND (b.name, o.key) > (");
+            query.push_bind(bucket);
+            query.push(", ");
+            query.push_bind(key);
+            query.push(")");
+        }
+        query.push(" ORDER BY b.name ASC, o.key ASC LIMIT ");
+        query.push_bind(max_keys as i64 + 1);
+
+        let mut objects: Vec<FoundObject> = query.build_query_as().fetch_all(&*self.db).await?;
+        let is_truncated = objects.len() > max_keys;
+        objects.truncate(max_keys);
+        let next_continuation_token = match objects.last() {
+            Some(last) if is_truncated => Some(encode_token(&last.bucket, &last.key)),
+            _ => None,
+        };
+        Ok(ObjectSearchPage {
+            objects,
+            is_truncated,
+            next_continuation_token,
+        })
+    }
+}
+
+fn cutoff(now: DateTime<Utc>, age: Duration) -> DateTime<Utc> {
+    chrono::Duration::from_std(age)
+        .ok()
+        .and_then(|age| now.checked_sub_signed(age))
+        .unwrap_or(DateTime::<Utc>::MIN_UTC)
+}
+
+/// Bucket names never contain `/`, so the first one separates the key.
+fn encode_token(bucket: &str, key: &str) -> String {
+    general_purpose::URL_SAFE_NO_PAD.encode(format!("{bucket}/{key}"))
+}
+
+fn decode_token(token: &str) -> StorageResult<(String, String)> {
+    let invalid = || StorageError::InvalidSearch(format!("invalid continuation token `{token}`"));
+    let bytes = general_purpose::URL_SAFE_NO_PAD
+        .decode(token)
+        .map_err(|_| invalid())?;
+    let text = String::from_utf8(bytes).map_err(|_| invalid())?;
+    let (bucket, key) = text.split_once('/').ok_or_else(invalid)?;
+    Ok((bucket.to_string(), key.to_string()))
+}
@@ -0,0 +1,216 @@
+//! Outbound HTTP for background workers.
+//!
+//! Anything that calls external endpoints (replication targets, webhooks,
+//! authorizer or scanner callouts) must build its client through
+//! [`OutboundHttp`], so egress honours the standard proxy environment
+//! (`HTTPS_PROXY`, `HTTP_PROXY`, `ALL_PROXY`, `NO_PROXY`) plus per-target
+//! overrides from `OBJECT_STORE_OUTBOUND_PROXY_RULES`.
+//!
+//! Rules are evaluated in order before the environment; the first whose host
+//! pattern matches wins:
+//!
+//! ```text
+//! OBJECT_STORE_OUTBOUND_PROXY_RULES="replica.example.com=http://dr-proxy:3128,*.corp.internal=direct"
+//! ```
+
+use crate::middleware::client_info::IpNetwork;
+use reqwest::{Client, ClientBuilder, Proxy, Url};
+use std::{env, fmt, net::IpAddr, str::FromStr, sync::Arc};
+
+/// Where traffic for a matching host goes.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ProxyTarget {
+    /// Connect directly, bypassing any environment proxy.
+    Direct,
+    /// Tunnel through the given proxy.
+    Via(Url),
+}
+
+/// A per-target proxy override: `host=proxy-url`, `*.suffix=proxy-url`, or
+/// `host=direct`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ProxyRule {
+    pattern: String,
+    target: ProxyTarget,
+}
+
+impl ProxyRule {
+    fn matches(&self, host: &str) -> bool {
+        match self.pattern.strip_prefix("*.") {
+            Some(suffix) => host == suffix || host.ends_with(&format!(".{}", suffix)),
+            None => self.pattern == "*" || host.eq_ignore_ascii_case(&self.pattern),
+        }
+    }
+}
+
+impl FromStr for ProxyRule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (pattern, target) = s
+            .trim()
+            .split_once('=')
+            .ok_or_else(|| format!("proxy rule `{}` must look like host=proxy-url", s))?;
+        let pattern = pattern.trim().to_ascii_lowercase();
+        if pattern.is_empty() {
+            return Err(format!("proxy rule `{}` has an empty host pattern", s));
+        }
+        let target = match target.trim() {
+            t if t.eq_ignore_ascii_case("direct") => ProxyTarget::Direct,
+            t => ProxyTarget::Via(
+                Url::parse(t).map_err(|err| format!("proxy rule `{}`: {}", s, err))?,
+            ),
+        };
+        Ok(Self { pattern, target })
+    }
+}
+
+impl fmt::Display for ProxyRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.target {
+            ProxyTarget::Direct => write!(f, "{}=direct", self.pattern),
+            ProxyTarget::Via(url) => write!(f, "{}={}", self.pattern, url),
+        }
+    }
+}
+
+/// One `NO_PROXY` entry.
+#[derive(Debug, Clone)]
+enum NoProxyEntry {
+    All,
+    Network(IpNetwork),
+    Domain(String),
+}
+
+impl NoProxyEntry {
+    fn parse(entry: &str) -> Option<Self> {
+        let entry = entry.trim();
+        if entry.is_empty() {
+            return None;
+        }
+        if entry == "*" {
+            return Some(NoProxyEntry::All);
+        }
+        if let Ok(net) = entry.parse::<IpNetwork>() {
+            return Some(NoProxyEntry::Network(net));
+        }
+        let host = entry.split(':').next().unwrap_or(entry);
+        Some(NoProxyEntry::Domain(
+            host.trim_start_matches("*.")
+                .trim_start_matches('.')
+                .to_ascii_lowercase(),
+        ))
+    }
+
+    fn matches(&self, host: &str) -> bool {
+        match self {
+            NoProxyEntry::All => true,
+            NoProxyEntry::Network(net) => host
+                .trim_matches(|c| c == '[' || c == ']')
+                .parse::<IpAddr>()
+                .is_ok_and(|ip| net.contains(ip)),
+            NoProxyEntry::Domain(domain) => {
+                host == domain || host.ends_with(&format!(".{}", domain))
+            }
+        }
+    }
+}
+
+/// Proxy-aware factory for outbound HTTP clients.
+#[derive(Debug, Clone, Default)]
+pub struct OutboundHttp {
+    rules: Arc<Vec<ProxyRule>>,
+    http_proxy: Option<Url>,
+    https_proxy: Option<Url>,
+    no_proxy: Arc<Vec<NoProxyEntry>>,
+}
+
+impl OutboundHttp {
+    /// Combine per-target `rules` with the process proxy environment.
+    pub fn from_env(rules: Vec<ProxyRule>) -> Result<Self, String> {
+        let all_proxy = proxy_env(&["ALL_PROXY", "all_proxy"])?;
+        let http_proxy = proxy_env(&["HTTP_PROXY", "http_proxy"])?.or(all_proxy.clone());
+        let https_proxy = proxy_env(&["HTTPS_PROXY", "https_proxy"])?.or(all_proxy);
+        let no_proxy = ["NO_PROXY", "no_proxy"]
+            .iter()
+            .find_map(|name| env::var(name).ok())
+            .map(|value| value.split(',').filter_map(NoProxyEntry::parse).collect())
+            .unwrap_or_default();
+
+        Ok(Self {
+            rules: Arc::new(rules),
+            http_proxy,
+            https_proxy,
+            no_proxy: Arc::new(no_proxy),
+        })
+    }
+
+    /// The proxy that should carry a request to `url`, if any.
+    pub fn proxy_for(&self, url: &Url) -> Option<Url> {
+        let host = url.host_str()?.to_ascii_lowercase();
+        if let Some(rule) = self.rules.iter().find(|rule| rule.matches(&host)) {
+            return match &rule.target {
+                ProxyTarget::Direct => None,
+                ProxyTarget::Via(proxy) => Some(proxy.clone()),
+            };
+        }
+        if self.no_proxy.iter().any(|entry| entry.matches(&host)) {
+            return None;
+        }
+        match url.scheme() {
+            "https" => self.https_proxy.clone(),
+            "http" => self.http_proxy.clone(),
+            _ => None,
+        }
+    }
+
+    /// A client builder with proxy selection wired in; callers add their own
+    /// timeouts and headers.
+    pub fn client_builder(&self) -> ClientBuilder {
+        let resolver = self.clone();
+        Client::builder()
+            .no_proxy()
+            .proxy(Proxy::custom(move |url| resolver.proxy_for(url)))
+    }
+
+    /// Build a client with default settings.
+    pub fn client(&self) -> reqwest::Result<Client> {
+        self.client_builder().build()
+    }
+
+    /// Human-readable summary for startup logs (credentials redacted).
+    pub fn describe(&self) -> String {
+        let redact = |url: &Option<Url>| {
+            url.as_ref()
+                .map(|u| format!("{}://{}", u.scheme(), u.host_str().unwrap_or("?")))
+                .unwrap_or_else(|| "none".into())
+        };
+        format!(
+            "http={}, https={}, no_proxy entries={}, rules={}",
+            redact(&self.http_proxy),
+            redact(&self.https_proxy),
+            self.no_proxy.len(),
+            self.rules.len()
+        )
+    }
+}
+
+fn proxy_env(names: &[&str]) -> Result<Option<Url>, String> {
+    let Some((name, value)) = names
+        .iter()
+        .find_map(|name| env::var(name).ok().map(|v| (*name, v)))
+        .filter(|(_, v)| !v.trim().is_empty())
+    else {
+        return Ok(None);
+    };
+    let value = value.trim();
+    // Bare `host:port` is common in proxy env vars; assume http.
+    let candidate = if value.contains("://") {
+        value.to_string()
+    } else {
+        format!("http://{}", value)
+    };
+    Url::parse(&candidate)
+        .map(Some)
+        .map_err(|err| format!("invalid {} `{}`: {}", name, value, err))
+}
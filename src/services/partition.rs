@@ -0,0 +1,92 @@
+//! Key-range partitioning for parallel enumeration of very large buckets.
+//!
+//! Sync tools that walk millions of keys sequentially are bound by one
+//! paginated cursor. `list_partitions` splits the (optionally prefixed) key
+//! space into contiguous ranges of roughly equal size so clients can run one
+//! ListObjectsV2 cursor per range concurrently, bounding each with
+//! `start-after` / `end-key`.
+
+use crate::services::{
+    keys,
+    storage_service::{StorageResult, StorageService},
+};
+use serde::Serialize;
+
+/// Upper bound on partitions handed out for a single bucket listing.
+pub const MAX_LIST_PARTITIONS: usize = 64;
+
+/// A contiguous slice of the key space: keys `> start_after` and `<= end_key`.
+/// `None` means the range is open on that side.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct KeyPartition {
+    pub start_after: Option<String>,
+    pub end_key: Option<String>,
+    pub key_count: i64,
+}
+
+impl StorageService {
+    /// Split the live keys of `bucket` (under `prefix`) into at most `count`
+    /// partitions of roughly equal size.
+    ///
+    /// Boundaries are read from the `(bucket_id, key)` index, so this does not
+    /// materialise the listing. Returns fewer partitions than requested when
+    /// the bucket holds fewer keys.
+    pub async fn list_partitions(
+        &self,
+        bucket: &str,
+        prefix: Option<&str>,
+        count: usize,
+    ) -> StorageResult<Vec<KeyPartition>> {
+        let prefix = prefix.unwrap_or("");
+        self.ensure_prefix_safe(prefix)?;
+        let bucket_rec = self.fetch_bucket(bucket).await?;
+        let pattern = keys::prefix_glob(prefix);
+
+        let total: i64 = sqlx::query_scalar(
+            "SELECT COUNT(*) FROM objects
+             WHERE bucket_id = ? AND is_deleted = 0 AND key GLOB ?",
+        )
+        .bind(bucket_rec.id)
+        .bind(&pattern)
+        .fetch_one(&*self.db)
+        .await?;
+
+        let count = (count.clamp(1, MAX_LIST_PARTITIONS) as i64).min(total.max(1));
+        let mut partitions = Vec::with_capacity(count as usize);
+        let mut start_after: Option<String> = None;
+        let mut consumed = 0i64;
+        for i in 1..=count {
+            if i == count {
+                partitions.push(KeyPartition {
+                    start_after,
+                    end_key: None,
+                    key_count: total - consumed,
+                });
+                break;
+            }
+
+            let boundary_offset = total * i / count - 1;
+            let end_key: String = sqlx::query_scalar(
+                "SELECT key FROM objects
+                 WHERE bucket_id = ? AND is_deleted = 0 AND key GLOB ?
+                 ORDER BY key ASC LIMIT 1 OFFSET ?",
+            )
+            .bind(bucket_rec.id)
+            .bind(&pattern)
+            .bind(boundary_offset)
+            .fetch_one(&*self.db)
+            .await?;
+
+            let key_count = boundary_offset + 1 - consumed;
+            consumed = boundary_offset + 1;
+            partitions.push(KeyPartition {
+                start_after: start_after.replace(end_key.clone()),
+                end_key: Some(end_key),
+                key_count,
+            });
+        }
+
+        Ok(partitions)
+    }
+}
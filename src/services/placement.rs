@@ -0,0 +1,306 @@
+//! Placement of payloads on volumes by key prefix.
+//!
+//! Besides the default volume (`base_path`), further volumes can be
+//! configured by name (`--volumes cold=/mnt/hdd,fast=/mnt/nvme`). A bucket's
+//! placement policy (`PUT /admin/buckets/{bucket}/placement`) maps key
+//! prefixes to them:
+//!
+//! ```json
+//! {
+//!   "rules": [
+//!     { "prefix": "raw/", "volume": "cold" },
+//!     { "prefix": "thumbnails/", "volume": "fast" }
+//!   ]
+//! }
+//! ```
+//!
+//! The longest matching prefix wins; keys no rule matches go to the default
+//! volume. Rules are evaluated when a payload is written and the chosen
+//! volume is recorded on the object (`objects.volume`), so changing them
+//! only affects later writes: existing payloads stay where they are and
+//! keep being found there. Every volume mirrors the layout of the default
+//! one (`{bucket}/{shard}/{shard}/{key}`).
+//!
+//! Only live payloads are placed. Archived versions, recycled and trashed
+//! payloads, snapshots and deduplicated blobs stay on the default volume;
+//! moving a placed payload there (or back) copies it when the volumes are
+//! different filesystems. Deduplication is skipped for placed payloads, so
+//! they are not turned into links to a blob on the default volume.
+
+use crate::{
+    models::bucket::Bucket,
+    services::{
+        staging::TEMP_PREFIX,
+        storage_service::{StorageError, StorageResult, StorageService},
+        volume::DEFAULT_VOLUME,
+    },
+};
+use serde::{Deserialize, Serialize};
+use std::{
+    fmt, io,
+    path::{Path, PathBuf},
+    str::FromStr,
+};
+use tokio::fs::{self, File};
+use uuid::Uuid;
+
+/// Most rules a placement policy may have.
+pub const MAX_PLACEMENT_RULES: usize = 100;
+
+/// A named volume besides the default one, as configured with `name=path`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct VolumeSpec {
+    pub name: String,
+    pub path: PathBuf,
+}
+
+impl FromStr for VolumeSpec {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let (name, path) = s
+            .trim()
+            .split_once('=')
+            .ok_or_else(|| format!("volume `{}` must look like name=path", s))?;
+        let name = name.trim();
+        let valid = !name.is_empty()
+            && name
+                .bytes()
+                .all(|b| b.is_ascii_lowercase() || b.is_ascii_digit() || b == b'-' || b == b'_');
+        if !valid {
+            return Err(format!(
+                "volume name `{}` may only contain a-z, 0-9, `-` and `_`",
+                name
+            ));
+        }
+        if name == DEFAULT_VOLUME {
+            return Err(format!(
+                "volume name `{}` is reserved for the storage directory",
+                DEFAULT_VOLUME
+            ));
+        }
+        let path = path.trim();
+        if path.is_empty() {
+            return Err(format!("volume `{}` has an empty path", name));
+        }
+        Ok(Self {
+            name: name.to_string(),
+            path: PathBuf::from(path),
+        })
+    }
+}
+
+impl fmt::Display for VolumeSpec {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}={}", self.name, self.path.display())
+    }
+}
+
+/// Volumes for key prefixes of a bucket; no rules is everything on the
+/// default volume.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PlacementPolicy {
+    #[serde(default)]
+    pub rules: Vec<PlacementRule>,
+}
+
+/// Keys starting with `prefix` are placed on `volume`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PlacementRule {
+    pub prefix: String,
+    pub volume: String,
+}
+
+impl PlacementPolicy {
+    /// The volume the longest rule matching `key` places it on; `None` is
+    /// the default volume.
+    pub fn volume_for(&self, key: &str) -> Option<&str> {
+        self.rules
+            .iter()
+            .filter(|rule| key.starts_with(&rule.prefix))
+            .max_by_key(|rule| rule.prefix.len())
+            .map(|rule| rule.volume.as_str())
+            .filter(|volume| *volume != DEFAULT_VOLUME)
+    }
+}
+
+impl StorageService {
+    /// Root directory of `volume`; `None` (and unknown names, which
+    /// `check_placed_volumes` rules out at startup) is `base_path`.
+    pub(crate) fn volume_root(&self, volume: Option<&str>) -> &Path {
+        volume
+            .and_then(|name| self.options.volumes.iter().find(|spec| spec.name == name))
+            .map_or(self.base_path.as_path(), |spec| spec.path.as_path())
+    }
+
+    /// Path of the live payload of `key`, on the volume recorded for it.
+    pub(crate) async fn live_path(&self, bucket: &Bucket, key: &str) -> StorageResult<PathBuf> {
+        let volume: Option<(Option<String>,)> =
+            sqlx::query_as("SELECT volume FROM objects WHERE bucket_id = ? AND key = ?")
+                .bind(bucket.id)
+                .bind(key)
+                .fetch_optional(&*self.db)
+                .await?;
+        let volume = volume.and_then(|(volume,)| volume);
+        Ok(self.object_path_on(volume.as_deref(), &bucket.name, key))
+    }
+
+    /// The directory of `bucket_name` on whichever volume holds `path`, above
+    /// which empty directories are not pruned.
+    pub(crate) fn payload_bucket_root(&self, bucket_name: &str, path: &Path) -> PathBuf {
+        self.options
+            .volumes
+            .iter()
+            .map(|spec| spec.path.join(bucket_name))
+            .find(|root| path.starts_with(root))
+            .unwrap_or_else(|| self.bucket_root(bucket_name))
+    }
+
+    /// The volume a payload written to `key` goes to; `None` is the default
+    /// volume.
+    pub(crate) async fn place_payload(
+        &self,
+        bucket: &Bucket,
+        key: &str,
+    ) -> StorageResult<Option<String>> {
+        if self.options.volumes.is_empty() {
+            return Ok(None);
+        }
+        let policy = self.placement_policy(bucket.id).await?;
+        Ok(policy.volume_for(key).map(str::to_string))
+    }
+
+    /// Where a payload written to `key` now goes, for staging it next to
+    /// that location.
+    pub(crate) async fn placed_path(&self, bucket: &Bucket, key: &str) -> StorageResult<PathBuf> {
+        let volume = self.place_payload(bucket, key).await?;
+        Ok(self.layout_path_on(volume.as_deref(), &bucket.name, key))
+    }
+
+    /// The placement policy of `bucket`.
+    pub async fn get_placement_policy(&self, bucket: &str) -> StorageResult<PlacementPolicy> {
+        let bucket_rec = self.fetch_bucket(bucket).await?;
+        self.placement_policy(bucket_rec.id).await
+    }
+
+    /// Replace the placement policy of `bucket`; no rules removes it.
+    pub async fn set_placement_policy(
+        &self,
+        bucket: &str,
+        policy: PlacementPolicy,
+    ) -> StorageResult<PlacementPolicy> {
+        let bucket_rec = self.fetch_bucket(bucket).await?;
+        self.validate_placement(&policy)?;
+        if policy.rules.is_empty() {
+            sqlx::query("DELETE FROM bucket_placement_policies WHERE bucket_id = ?")
+                .bind(bucket_rec.id)
+                .execute(&*self.db)
+                .await?;
+            return Ok(policy);
+        }
+        let json = serde_json::to_string(&policy)
+            .map_err(|err| StorageError::InvalidPlacement(err.to_string()))?;
+        sqlx::query(
+            "INSERT INTO bucket_placement_policies (bucket_id, policy) VALUES (?, ?)
+             ON CONFLICT(bucket_id) DO UPDATE SET policy = excluded.policy",
+        )
+        .bind(bucket_rec.id)
+        .bind(json)
+        .execute(&*self.db)
+        .await?;
+        Ok(policy)
+    }
+
+    /// Refuse to start when objects are recorded on volumes that are no
+    /// longer configured; their payloads could not be found.
+    pub async fn check_placed_volumes(&self) -> StorageResult<()> {
+        let placed: Vec<(String,)> =
+            sqlx::query_as("SELECT DISTINCT volume FROM objects WHERE volume IS NOT NULL")
+                .fetch_all(&*self.db)
+                .await?;
+        let missing: Vec<String> = placed
+            .into_iter()
+            .map(|(name,)| name)
+            .filter(|name| !self.options.volumes.iter().any(|spec| spec.name == *name))
+            .collect();
+        if missing.is_empty() {
+            return Ok(());
+        }
+        Err(StorageError::InvalidPlacement(format!(
+            "objects are stored on volume(s) that are not configured: {}",
+            missing.join(", ")
+        )))
+    }
+
+    fn validate_placement(&self, policy: &PlacementPolicy) -> StorageResult<()> {
+        if policy.rules.len() > MAX_PLACEMENT_RULES {
+            return Err(StorageError::InvalidPlacement(format!(
+                "at most {} rules are allowed",
+                MAX_PLACEMENT_RULES
+            )));
+        }
+        for (idx, rule) in policy.rules.iter().enumerate() {
+            if policy.rules[..idx]
+                .iter()
+                .any(|other| other.prefix == rule.prefix)
+            {
+                return Err(StorageError::InvalidPlacement(format!(
+                    "prefix `{}` has more than one rule",
+                    rule.prefix
+                )));
+            }
+            let known = rule.volume == DEFAULT_VOLUME
+                || self
+                    .options
+                    .volumes
+                    .iter()
+                    .any(|spec| spec.name == rule.volume);
+            if !known {
+                return Err(StorageError::InvalidPlacement(format!(
+                    "unknown volume `{}`",
+                    rule.volume
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    async fn placement_policy(&self, bucket_id: Uuid) -> StorageResult<PlacementPolicy> {
+        let row: Option<(String,)> =
+            sqlx::query_as("SELECT policy FROM bucket_placement_policies WHERE bucket_id = ?")
+                .bind(bucket_id)
+                .fetch_optional(&*self.db)
+                .await?;
+        match row {
+            Some((json,)) => serde_json::from_str(&json).map_err(|err| {
+                StorageError::InvalidPlacement(format!("stored policy is unreadable: {}", err))
+            }),
+            None => Ok(PlacementPolicy::default()),
+        }
+    }
+}
+
+/// Rename `from` to `to`, or copy it when they are on different
+/// filesystems. The copy is written under a temp name and synced before it
+/// replaces `to`, so `to` is never seen half-written.
+pub(crate) async fn move_file(from: &Path, to: &Path) -> io::Result<()> {
+    match fs::rename(from, to).await {
+        Err(err) if err.kind() == io::ErrorKind::CrossesDevices => {
+            let tmp = to.with_file_name(format!("{}{}", TEMP_PREFIX, Uuid::new_v4()));
+            let copied = async {
+                fs::copy(from, &tmp).await?;
+                File::open(&tmp).await?.sync_all().await?;
+                fs::rename(&tmp, to).await
+            }
+            .await;
+            if let Err(err) = copied {
+                let _ = fs::remove_file(&tmp).await;
+                return Err(err);
+            }
+            fs::remove_file(from).await
+        }
+        result => result,
+    }
+}
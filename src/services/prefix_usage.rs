@@ -0,0 +1,168 @@
+//! Usage per key prefix, for charging teams that share a bucket.
+//!
+//! With `usage_prefix_depth` set, every upload (including multipart parts)
+//! and every GET adds its bytes to `prefix_transfers` under the key's first
+//! `depth` `/`-separated segments: `team-a/logs/2024/x.gz` counts under
+//! `team-a/logs/` at depth 2 and `team-a/` at depth 1. Keys with fewer
+//! segments count under the part up to their last `/` (the empty prefix for
+//! top-level keys).
+//!
+//! `bucket_usage` reports, per prefix, the live objects and the bytes stored
+//! (noncurrent versions included, as they occupy disk too) next to the bytes
+//! transferred. It may group at any depth up to the recorded one; transfers
+//! recorded deeper are rolled up.
+
+use crate::services::{
+    replication::ReplicationLag,
+    storage_service::{StorageError, StorageResult, StorageService},
+};
+use futures::TryStreamExt;
+use serde::Serialize;
+use std::collections::BTreeMap;
+use uuid::Uuid;
+
+/// Deepest prefix `bucket_usage` groups by when transfers are not tracked.
+pub const MAX_USAGE_PREFIX_DEPTH: usize = 16;
+
+/// Storage and transfer of one prefix.
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct PrefixUsage {
+    pub prefix: String,
+    /// Live objects under the prefix.
+    pub objects: u64,
+    /// Bytes of live objects and noncurrent versions.
+    pub bytes_stored: u64,
+    /// Bytes uploaded since tracking began.
+    pub bytes_in: u64,
+    /// Bytes downloaded since tracking began.
+    pub bytes_out: u64,
+}
+
+/// Body of `GET /admin/buckets/{bucket}/stats`.
+#[derive(Debug, Clone, Serialize)]
+pub struct BucketUsage {
+    pub bucket: String,
+    pub depth: usize,
+    /// Whether `bytes_in`/`bytes_out` are being recorded.
+    pub transfers_tracked: bool,
+    pub prefixes: Vec<PrefixUsage>,
+    /// Set when the bucket is replicated.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub replication: Option<ReplicationLag>,
+}
+
+impl StorageService {
+    /// Add a transfer of `key` to its prefix's counters. Does nothing unless
+    /// `usage_prefix_depth` is set.
+    pub async fn record_prefix_transfer(
+        &self,
+        bucket_id: Uuid,
+        key: &str,
+        bytes_in: u64,
+        bytes_out: u64,
+    ) -> StorageResult<()> {
+        let Some(depth) = self.options.usage_prefix_depth else {
+            return Ok(());
+        };
+        sqlx::query(
+            "INSERT INTO prefix_transfers (bucket_id, prefix, bytes_in, bytes_out)
+             VALUES (?, ?, ?, ?)
+             ON CONFLICT(bucket_id, prefix) DO UPDATE SET
+                 bytes_in = bytes_in + excluded.bytes_in,
+                 bytes_out = bytes_out + excluded.bytes_out",
+        )
+        .bind(bucket_id)
+        .bind(usage_prefix(key, depth))
+        .bind(i64::try_from(bytes_in).unwrap_or(i64::MAX))
+        .bind(i64::try_from(bytes_out).unwrap_or(i64::MAX))
+        .execute(&*self.db)
+        .await?;
+        Ok(())
+    }
+
+    /// Objects, bytes stored and bytes transferred per prefix of `bucket`,
+    /// grouped at `depth` (the recorded depth, or 1, by default).
+    pub async fn bucket_usage(
+        &self,
+        bucket: &str,
+        depth: Option<usize>,
+    ) -> StorageResult<BucketUsage> {
+        let recorded = self.options.usage_prefix_depth;
+        let max_depth = recorded.unwrap_or(MAX_USAGE_PREFIX_DEPTH);
+        let depth = depth.unwrap_or(recorded.unwrap_or(1));
+        if !(1..=max_depth).contains(&depth) {
+            return Err(StorageError::InvalidContent(format!(
+                "depth must be between 1 and {}",
+                max_depth
+            )));
+        }
+        let bucket_rec = self.fetch_bucket(bucket).await?;
+        let mut prefixes: BTreeMap<String, PrefixUsage> = BTreeMap::new();
+
+        let mut live = sqlx::query_as::<_, (String, i64)>(
+            "SELECT key, size_bytes FROM objects WHERE bucket_id = ? AND is_deleted = 0",
+        )
+        .bind(bucket_rec.id)
+        .fetch(&*self.db);
+        while let Some((key, size)) = live.try_next().await? {
+            let usage = entry(&mut prefixes, usage_prefix(&key, depth));
+            usage.objects += 1;
+            usage.bytes_stored += size.max(0) as u64;
+        }
+        drop(live);
+
+        let mut versions = sqlx::query_as::<_, (String, i64)>(
+            "SELECT key, size_bytes FROM object_versions
+             WHERE bucket_id = ? AND is_delete_marker = 0",
+        )
+        .bind(bucket_rec.id)
+        .fetch(&*self.db);
+        while let Some((key, size)) = versions.try_next().await? {
+            entry(&mut prefixes, usage_prefix(&key, depth)).bytes_stored += size.max(0) as u64;
+        }
+        drop(versions);
+
+        let mut transfers = sqlx::query_as::<_, (String, i64, i64)>(
+            "SELECT prefix, bytes_in, bytes_out FROM prefix_transfers WHERE bucket_id = ?",
+        )
+        .bind(bucket_rec.id)
+        .fetch(&*self.db);
+        while let Some((prefix, bytes_in, bytes_out)) = transfers.try_next().await? {
+            let usage = entry(&mut prefixes, usage_prefix(&prefix, depth));
+            usage.bytes_in += bytes_in.max(0) as u64;
+            usage.bytes_out += bytes_out.max(0) as u64;
+        }
+        drop(transfers);
+
+        let replication = self.replication_lag(bucket_rec.id).await?;
+        Ok(BucketUsage {
+            bucket: bucket_rec.name,
+            depth,
+            transfers_tracked: recorded.is_some(),
+            prefixes: prefixes.into_values().collect(),
+            replication,
+        })
+    }
+}
+
+fn entry<'a>(prefixes: &'a mut BTreeMap<String, PrefixUsage>, prefix: &str) -> &'a mut PrefixUsage {
+    prefixes
+        .entry(prefix.to_string())
+        .or_insert_with(|| PrefixUsage {
+            prefix: prefix.to_string(),
+            ..Default::default()
+        })
+}
+
+/// `key` up to and including its `depth`-th `/`, or its last `/` when it
+/// has fewer.
+fn usage_prefix(key: &str, depth: usize) -> &str {
+    let mut end = 0;
+    for (seen, (pos, _)) in key.match_indices('/').enumerate() {
+        if seen == depth {
+            break;
+        }
+        end = pos + 1;
+    }
+    &key[..end]
+}
@@ -0,0 +1,102 @@
+//! Side-effect-free validation of proposed bucket names and object keys.
+//!
+//! Clients about to create a bucket or start a large upload can ask whether
+//! the server would accept it, and get every reason it would not, instead of
+//! finding out from the first failed request. The checks are the ones the
+//! write paths apply; nothing is created or locked, so the answer is advisory
+//! and can change before the real request arrives.
+
+use crate::services::{
+    keys::key_violation,
+    storage_service::{StorageError, StorageResult, StorageService},
+};
+use serde::Serialize;
+
+/// Which part of the proposal a problem refers to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize)]
+#[serde(rename_all = "lowercase")]
+pub enum ValidationField {
+    Bucket,
+    Key,
+}
+
+/// One reason the proposal would be rejected.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ValidationProblem {
+    pub field: ValidationField,
+    pub reason: String,
+}
+
+/// Outcome of a pre-flight check.
+#[derive(Debug, Clone, Serialize)]
+#[serde(rename_all = "PascalCase")]
+pub struct ValidationReport {
+    pub bucket: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub key: Option<String>,
+    pub valid: bool,
+    pub bucket_exists: bool,
+    pub problems: Vec<ValidationProblem>,
+}
+
+impl StorageService {
+    /// Check whether `bucket` could be created (no `key`) or whether `key`
+    /// could be uploaded into `bucket`.
+    ///
+    /// Never fails on a bad proposal; problems are collected in the report.
+    pub async fn validate_proposal(
+        &self,
+        bucket: &str,
+        key: Option<&str>,
+    ) -> StorageResult<ValidationReport> {
+        let mut problems = Vec::new();
+        let mut bucket_problem = |reason: String| {
+            problems.push(ValidationProblem {
+                field: ValidationField::Bucket,
+                reason,
+            })
+        };
+
+        let name_ok = match self.ensure_bucket_name_safe(bucket) {
+            Ok(()) => true,
+            Err(StorageError::InvalidBucketName { reason, .. }) => {
+                bucket_problem(reason);
+                false
+            }
+            Err(err) => return Err(err),
+        };
+        let existing = if name_ok {
+            match self.fetch_bucket(bucket).await {
+                Ok(bucket_rec) => Some(bucket_rec),
+                Err(StorageError::BucketNotFound(_)) => None,
+                Err(err) => return Err(err),
+            }
+        } else {
+            None
+        };
+
+        match (key, &existing) {
+            (None, Some(_)) => bucket_problem("already exists".into()),
+            (Some(_), None) if name_ok => bucket_problem("does not exist".into()),
+            (Some(_), Some(bucket_rec)) if bucket_rec.read_only => {
+                bucket_problem("is read-only".into())
+            }
+            _ => {}
+        }
+        if let Some(reason) = key.and_then(key_violation) {
+            problems.push(ValidationProblem {
+                field: ValidationField::Key,
+                reason: reason.to_string(),
+            });
+        }
+
+        Ok(ValidationReport {
+            bucket: bucket.to_string(),
+            key: key.map(str::to_string),
+            valid: problems.is_empty(),
+            bucket_exists: existing.is_some(),
+            problems,
+        })
+    }
+}
@@ -0,0 +1,413 @@
+//! Publishing object events to NATS and Kafka.
+//!
+//! A bucket can have its object events published to message brokers so
+//! downstream pipelines react to uploads without polling listings
+//! (`PUT /admin/buckets/{bucket}/publishers`):
+//!
+//! ```json
+//! {
+//!   "publishers": [
+//!     { "id": "ingest", "kind": "nats", "url": "nats://nats:4222",
+//!       "subject": "uploads.photos", "events": ["object-created"], "prefix": "raw/" },
+//!     { "id": "audit", "kind": "kafka", "url": "kafka-1:9092,kafka-2:9092",
+//!       "subject": "object-events" }
+//!   ]
+//! }
+//! ```
+//!
+//! Every `object-created` / `object-deleted` event on the event bus (see
+//! `events`) whose key starts with `prefix` is sent as its JSON record to
+//! the NATS subject, or the Kafka topic keyed by the object key. `events`
+//! defaults to both kinds; `url` is the NATS server URL or the Kafka
+//! bootstrap brokers.
+//!
+//! Support for each broker is compiled in with the `nats` and `kafka` cargo
+//! features; configuring a kind the build lacks is refused. Publishing is
+//! best effort: events come from the in-process bus, so events of writes
+//! made while no publisher task runs, or that fail to send, are logged and
+//! lost. Webhook notifications (see `notifications`) are retried from the
+//! persisted change feed instead.
+
+use crate::services::{
+    events::{BucketEvent, EventKind},
+    storage_service::{StorageError, StorageResult, StorageService},
+};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, sync::Arc};
+use tokio::{sync::broadcast::error::RecvError, task::JoinHandle};
+use tracing::{debug, warn};
+
+/// Most publishers a bucket may have.
+pub const MAX_PUBLISHERS: usize = 10;
+
+/// Event kinds publishers can be configured for.
+const PUBLISHED_EVENTS: [EventKind; 2] = [EventKind::ObjectCreated, EventKind::ObjectDeleted];
+
+/// Broker a publisher sends to.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(rename_all = "lowercase")]
+pub enum PublisherKind {
+    Nats,
+    Kafka,
+}
+
+impl PublisherKind {
+    /// Whether this build can publish to the broker.
+    pub fn available(&self) -> bool {
+        match self {
+            PublisherKind::Nats => cfg!(feature = "nats"),
+            PublisherKind::Kafka => cfg!(feature = "kafka"),
+        }
+    }
+
+    fn feature(&self) -> &'static str {
+        match self {
+            PublisherKind::Nats => "nats",
+            PublisherKind::Kafka => "kafka",
+        }
+    }
+}
+
+/// One destination of a bucket's object events.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct EventPublisher {
+    /// Identifier, unique within the bucket; used in logs.
+    pub id: String,
+    pub kind: PublisherKind,
+    /// NATS server URL, or comma-separated Kafka bootstrap brokers.
+    pub url: String,
+    /// NATS subject or Kafka topic.
+    pub subject: String,
+    /// Event kinds (`object-created`, `object-deleted`); empty is both.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub events: Vec<String>,
+    /// Only keys starting with this prefix.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prefix: Option<String>,
+}
+
+impl EventPublisher {
+    /// Whether an event of `kind` on `key` goes to this publisher.
+    pub fn matches(&self, kind: EventKind, key: &str) -> bool {
+        self.prefix.as_deref().is_none_or(|p| key.starts_with(p))
+            && (self.events.is_empty() || self.events.iter().any(|e| e == kind.as_str()))
+    }
+}
+
+/// The publishers of a bucket; none means its events are not published.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PublisherConfig {
+    #[serde(default)]
+    pub publishers: Vec<EventPublisher>,
+}
+
+impl PublisherConfig {
+    fn validate(&self) -> StorageResult<()> {
+        let invalid = |reason: String| Err(StorageError::InvalidPublisher(reason));
+        if self.publishers.len() > MAX_PUBLISHERS {
+            return invalid(format!("at most {} publishers are allowed", MAX_PUBLISHERS));
+        }
+        for (idx, publisher) in self.publishers.iter().enumerate() {
+            if publisher.id.is_empty() || publisher.id.len() > 64 {
+                return invalid("publisher ids must have 1 to 64 characters".into());
+            }
+            if self.publishers[..idx]
+                .iter()
+                .any(|other| other.id == publisher.id)
+            {
+                return invalid(format!("publisher id `{}` is used twice", publisher.id));
+            }
+            if !publisher.kind.available() {
+                return invalid(format!(
+                    "publisher `{}`: this server was built without the `{}` feature",
+                    publisher.id,
+                    publisher.kind.feature()
+                ));
+            }
+            if publisher.url.trim().is_empty() {
+                return invalid(format!("publisher `{}` has no url", publisher.id));
+            }
+            if publisher.subject.is_empty() || publisher.subject.chars().any(char::is_whitespace) {
+                return invalid(format!(
+                    "publisher `{}` needs a subject without whitespace",
+                    publisher.id
+                ));
+            }
+            if let Some(event) = publisher
+                .events
+                .iter()
+                .find(|e| !PUBLISHED_EVENTS.iter().any(|kind| kind.as_str() == *e))
+            {
+                return invalid(format!(
+                    "publisher `{}`: event `{}` is not one of object-created, object-deleted",
+                    publisher.id, event
+                ));
+            }
+        }
+        Ok(())
+    }
+}
+
+impl StorageService {
+    /// The event publishers of `bucket`.
+    pub async fn get_event_publishers(&self, bucket: &str) -> StorageResult<PublisherConfig> {
+        let bucket_rec = self.fetch_bucket(bucket).await?;
+        let row: Option<(String,)> =
+            sqlx::query_as("SELECT config FROM bucket_event_publishers WHERE bucket_id = ?")
+                .bind(bucket_rec.id)
+                .fetch_optional(&*self.db)
+                .await?;
+        parse_config(row)
+    }
+
+    /// Replace the event publishers of `bucket`; none removes them.
+    pub async fn set_event_publishers(
+        &self,
+        bucket: &str,
+        config: PublisherConfig,
+    ) -> StorageResult<PublisherConfig> {
+        let bucket_rec = self.fetch_bucket(bucket).await?;
+        config.validate()?;
+        if config.publishers.is_empty() {
+            sqlx::query("DELETE FROM bucket_event_publishers WHERE bucket_id = ?")
+                .bind(bucket_rec.id)
+                .execute(&*self.db)
+                .await?;
+        } else {
+            let json = serde_json::to_string(&config)
+                .map_err(|err| StorageError::InvalidPublisher(err.to_string()))?;
+            sqlx::query(
+                "INSERT INTO bucket_event_publishers (bucket_id, config) VALUES (?, ?)
+                 ON CONFLICT(bucket_id) DO UPDATE SET config = excluded.config",
+            )
+            .bind(bucket_rec.id)
+            .bind(json)
+            .execute(&*self.db)
+            .await?;
+        }
+        // Also tells the publisher task to reload the bucket's publishers.
+        self.events.bucket(
+            EventKind::BucketConfigChanged,
+            &bucket_rec.name,
+            Some(format!("publishers={}", config.publishers.len())),
+        );
+        Ok(config)
+    }
+
+    async fn publishers_of(&self, bucket_name: &str) -> StorageResult<PublisherConfig> {
+        let row: Option<(String,)> = sqlx::query_as(
+            "SELECT p.config FROM bucket_event_publishers p
+             JOIN buckets b ON b.id = p.bucket_id
+             WHERE b.name = ?",
+        )
+        .bind(bucket_name)
+        .fetch_optional(&*self.db)
+        .await?;
+        parse_config(row)
+    }
+}
+
+fn parse_config(row: Option<(String,)>) -> StorageResult<PublisherConfig> {
+    match row {
+        Some((json,)) => serde_json::from_str(&json).map_err(|err| {
+            StorageError::InvalidPublisher(format!("stored configuration is unreadable: {}", err))
+        }),
+        None => Ok(PublisherConfig::default()),
+    }
+}
+
+/// Publish object events of buckets with publishers until the event bus
+/// closes. Subscribes before returning, so no event published afterwards
+/// is missed.
+pub fn spawn_event_publishers(service: StorageService) -> JoinHandle<()> {
+    let mut rx = service.events.subscribe();
+    tokio::spawn(async move {
+        let mut worker = PublisherWorker {
+            service,
+            configs: HashMap::new(),
+            sinks: Sinks::default(),
+        };
+        loop {
+            match rx.recv().await {
+                Ok(event) => worker.handle(&event).await,
+                Err(RecvError::Lagged(missed)) => {
+                    warn!("event publishers fell behind and skipped {} events", missed)
+                }
+                Err(RecvError::Closed) => break,
+            }
+        }
+    })
+}
+
+struct PublisherWorker {
+    service: StorageService,
+    /// Publishers per bucket name, dropped when the bucket changes.
+    configs: HashMap<String, Arc<Vec<EventPublisher>>>,
+    sinks: Sinks,
+}
+
+impl PublisherWorker {
+    async fn handle(&mut self, event: &BucketEvent) {
+        match event.kind {
+            EventKind::BucketConfigChanged | EventKind::BucketDeleted => {
+                self.configs.remove(&event.bucket);
+                return;
+            }
+            kind if PUBLISHED_EVENTS.contains(&kind) => {}
+            _ => return,
+        }
+        let Some(key) = event.key.as_deref() else {
+            return;
+        };
+        let publishers = match self.configs.get(&event.bucket) {
+            Some(publishers) => publishers.clone(),
+            None => match self.service.publishers_of(&event.bucket).await {
+                Ok(config) => {
+                    let publishers = Arc::new(config.publishers);
+                    self.configs
+                        .insert(event.bucket.clone(), publishers.clone());
+                    publishers
+                }
+                Err(err) => {
+                    warn!(
+                        "could not load event publishers of `{}`: {}",
+                        event.bucket, err
+                    );
+                    return;
+                }
+            },
+        };
+        let mut payload = None;
+        for publisher in publishers.iter().filter(|p| p.matches(event.kind, key)) {
+            let payload = payload
+                .get_or_insert_with(|| serde_json::to_vec(event).unwrap_or_default())
+                .as_slice();
+            match self.sinks.send(publisher, key, payload).await {
+                Ok(()) => debug!(
+                    "published {} of {}/{} via `{}`",
+                    event.kind.as_str(),
+                    event.bucket,
+                    key,
+                    publisher.id
+                ),
+                Err(err) => warn!(
+                    "publisher `{}` of `{}` dropped {} of `{}`: {}",
+                    publisher.id,
+                    event.bucket,
+                    event.kind.as_str(),
+                    key,
+                    err
+                ),
+            }
+        }
+    }
+}
+
+/// Broker connections, opened on first use and kept per URL.
+#[derive(Default)]
+struct Sinks {
+    #[cfg(feature = "nats")]
+    nats: HashMap<String, async_nats::Client>,
+    #[cfg(feature = "kafka")]
+    kafka: HashMap<String, rdkafka::producer::FutureProducer>,
+}
+
+impl Sinks {
+    async fn send(
+        &mut self,
+        publisher: &EventPublisher,
+        key: &str,
+        payload: &[u8],
+    ) -> Result<(), String> {
+        match publisher.kind {
+            PublisherKind::Nats => self.send_nats(publisher, payload).await,
+            PublisherKind::Kafka => self.send_kafka(publisher, key, payload).await,
+        }
+    }
+
+    #[cfg(feature = "nats")]
+    async fn send_nats(
+        &mut self,
+        publisher: &EventPublisher,
+        payload: &[u8],
+    ) -> Result<(), String> {
+        let client = match self.nats.get(&publisher.url) {
+            Some(client) => client.clone(),
+            None => {
+                let client = async_nats::connect(publisher.url.as_str())
+                    .await
+                    .map_err(|err| format!("connecting to {}: {}", publisher.url, err))?;
+                tracing::info!("connected to NATS at {}", publisher.url);
+                self.nats.insert(publisher.url.clone(), client.clone());
+                client
+            }
+        };
+        client
+            .publish(
+                publisher.subject.clone(),
+                bytes::Bytes::copy_from_slice(payload),
+            )
+            .await
+            .map_err(|err| err.to_string())?;
+        client.flush().await.map_err(|err| err.to_string())
+    }
+
+    #[cfg(not(feature = "nats"))]
+    async fn send_nats(
+        &mut self,
+        _publisher: &EventPublisher,
+        _payload: &[u8],
+    ) -> Result<(), String> {
+        Err("built without the `nats` feature".into())
+    }
+
+    #[cfg(feature = "kafka")]
+    async fn send_kafka(
+        &mut self,
+        publisher: &EventPublisher,
+        key: &str,
+        payload: &[u8],
+    ) -> Result<(), String> {
+        use rdkafka::{
+            ClientConfig,
+            producer::{FutureProducer, FutureRecord},
+        };
+        use std::time::Duration;
+
+        let producer = match self.kafka.get(&publisher.url) {
+            Some(producer) => producer.clone(),
+            None => {
+                let producer: FutureProducer = ClientConfig::new()
+                    .set("bootstrap.servers", &publisher.url)
+                    .set("message.timeout.ms", "10000")
+                    .create()
+                    .map_err(|err| format!("creating producer for {}: {}", publisher.url, err))?;
+                tracing::info!("created Kafka producer for {}", publisher.url);
+                self.kafka.insert(publisher.url.clone(), producer.clone());
+                producer
+            }
+        };
+        producer
+            .send(
+                FutureRecord::to(&publisher.subject)
+                    .key(key)
+                    .payload(payload),
+                Duration::from_secs(5),
+            )
+            .await
+            .map(|_| ())
+            .map_err(|(err, _)| err.to_string())
+    }
+
+    #[cfg(not(feature = "kafka"))]
+    async fn send_kafka(
+        &mut self,
+        _publisher: &EventPublisher,
+        _key: &str,
+        _payload: &[u8],
+    ) -> Result<(), String> {
+        Err("built without the `kafka` feature".into())
+    }
+}
@@ -0,0 +1,167 @@
+//! Permanent removal of soft-deleted objects.
+//!
+//! Deleting a key outside a versioned history only marks its row
+//! `is_deleted = 1` (stamping `deleted_at`) and sets the payload aside, so
+//! rows would otherwise pile up forever. Once a deleted row is older than the
+//! retention (`StorageOptions::deleted_retention`), `spawn_deleted_purger`
+//! removes it for good together with the key's tags, user metadata and
+//! recorded parts; `POST /admin/purge-deleted` runs the same pass on demand.
+//!
+//! Delete markers (deleted rows with a `version_id`) are part of a key's
+//! version history and are never purged here.
+//!
+//! The payload kept in the trash for undeleting (see `trash`) goes with the
+//! row, and so does a payload still in the bucket directory (a removal that
+//! failed, or a crash before it ran). Each row is purged under its key's
+//! lock (see `key_lock`), which writes hold across their file and row
+//! changes, and only after checking that the key has no live row again, so
+//! neither the state nor the payload of a newer write can be taken along.
+
+use crate::services::storage_service::{StorageResult, StorageService};
+use chrono::{Duration as ChronoDuration, Utc};
+use serde::Serialize;
+use std::{io, time::Duration};
+use tokio::{fs, task::JoinHandle};
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// How often the purger looks for expired deleted rows.
+pub const PURGE_TICK: Duration = Duration::from_secs(3600);
+
+/// Rows purged per query.
+const PURGE_PAGE: i64 = 500;
+
+/// Tables holding per-key state of the current object.
+const KEY_TABLES: [&str; 3] = ["object_tags", "object_metadata", "object_parts"];
+
+/// Outcome of one `purge_deleted_objects` pass.
+#[derive(Debug, Default, Clone, Copy, Serialize)]
+pub struct PurgeSummary {
+    pub purged_rows: u64,
+    /// Trashed or lingering payload files removed with purged rows.
+    pub removed_payloads: u64,
+}
+
+/// A deleted row selected for purging.
+#[derive(Debug, sqlx::FromRow)]
+struct DeletedRow {
+    id: Uuid,
+    bucket_id: Uuid,
+    bucket_name: String,
+    key: String,
+    volume: Option<String>,
+}
+
+impl StorageService {
+    /// Permanently remove rows deleted more than `older_than` ago.
+    pub async fn purge_deleted_objects(&self, older_than: Duration) -> StorageResult<PurgeSummary> {
+        let cutoff =
+            Utc::now() - ChronoDuration::from_std(older_than).unwrap_or(ChronoDuration::MAX);
+        let mut summary = PurgeSummary::default();
+        loop {
+            let rows: Vec<DeletedRow> = sqlx::query_as(
+                "SELECT o.id, o.bucket_id, b.name AS bucket_name, o.key, o.volume
+                 FROM objects o JOIN buckets b ON b.id = o.bucket_id
+                 WHERE o.is_deleted = 1 AND o.version_id IS NULL
+                   AND COALESCE(o.deleted_at, o.last_modified) < ?
+                 LIMIT ?",
+            )
+            .bind(cutoff)
+            .bind(PURGE_PAGE)
+            .fetch_all(&*self.db)
+            .await?;
+            let page = rows.len();
+            for row in rows {
+                let _key_guard = self.key_locks.lock(row.bucket_id, &row.key).await;
+                if !self.purge_row(&row).await? {
+                    continue;
+                }
+                summary.purged_rows += 1;
+                if self.remove_trashed(&row.bucket_name, row.id).await? {
+                    summary.removed_payloads += 1;
+                }
+                if self.remove_lingering_payload(&row).await? {
+                    summary.removed_payloads += 1;
+                }
+            }
+            if page < PURGE_PAGE as usize {
+                break;
+            }
+        }
+        if summary.purged_rows > 0 {
+            info!(
+                "purged {} deleted objects ({} lingering payloads)",
+                summary.purged_rows, summary.removed_payloads
+            );
+        }
+        Ok(summary)
+    }
+
+    /// Delete `row` and its per-key state, unless the key has a live row
+    /// again (written since the row was selected). The caller holds the
+    /// key's lock, so no write can land until its payload is gone too.
+    async fn purge_row(&self, row: &DeletedRow) -> StorageResult<bool> {
+        let mut tx = self.db.begin().await?;
+        let live: Option<i64> = sqlx::query_scalar(
+            "SELECT 1 FROM objects WHERE bucket_id = ? AND key = ? AND is_deleted = 0 LIMIT 1",
+        )
+        .bind(row.bucket_id)
+        .bind(&row.key)
+        .fetch_optional(&mut *tx)
+        .await?;
+        if live.is_some() {
+            return Ok(false);
+        }
+        let deleted = sqlx::query(
+            "DELETE FROM objects WHERE id = ? AND is_deleted = 1 AND version_id IS NULL",
+        )
+        .bind(row.id)
+        .execute(&mut *tx)
+        .await?;
+        if deleted.rows_affected() == 0 {
+            return Ok(false);
+        }
+        for table in KEY_TABLES {
+            sqlx::query(&format!(
+                "DELETE FROM {table} WHERE bucket_id = ? AND key = ?"
+            ))
+            .bind(row.bucket_id)
+            .bind(&row.key)
+            .execute(&mut *tx)
+            .await?;
+        }
+        tx.commit().await?;
+        Ok(true)
+    }
+
+    /// Remove the payload of a purged key if it is still on disk. The caller
+    /// holds the key's lock.
+    async fn remove_lingering_payload(&self, row: &DeletedRow) -> StorageResult<bool> {
+        let path = self.object_path_on(row.volume.as_deref(), &row.bucket_name, &row.key);
+        match fs::metadata(&path).await {
+            Ok(_) => {}
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(false),
+            Err(err) => return Err(err.into()),
+        }
+        self.remove_payload(&row.bucket_name, &path).await?;
+        Ok(true)
+    }
+}
+
+/// Spawn a background task that purges rows deleted more than `retention`
+/// ago, every `period`.
+pub fn spawn_deleted_purger(
+    service: StorageService,
+    retention: Duration,
+    period: Duration,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(period);
+        loop {
+            ticker.tick().await;
+            if let Err(err) = service.purge_deleted_objects(retention).await {
+                warn!("deleted object purge failed: {}", err);
+            }
+        }
+    })
+}
@@ -0,0 +1,302 @@
+//! Storage quotas.
+//!
+//! A quota caps the bytes stored and/or the live objects held, either per
+//! bucket (`PUT /admin/buckets/{bucket}/quota`) or across all buckets
+//! (`StorageOptions::global_quota`). Usage comes from `bucket_usage`, which
+//! triggers keep current on every insert, update and delete of `objects` and
+//! `object_versions`: bytes include noncurrent versions, objects count only
+//! live current keys.
+//!
+//! Writes that would exceed a quota are refused with `QuotaExceeded` (403):
+//! `upload_object_stream` checks the declared length up front and stops the
+//! body as soon as it outgrows what is left, and `commit_payload` checks the
+//! final size of every write (PUT, copy, completed multipart upload) under
+//! the key's lock. Replacing an unversioned key only counts the growth.
+//! Concurrent writes to different keys are checked independently, so they
+//! can overshoot a limit by at most their combined size.
+
+use crate::{
+    models::bucket::Bucket,
+    services::storage_service::{StorageError, StorageResult, StorageService},
+};
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+use serde::{Deserialize, Serialize};
+use std::{fmt, io};
+use uuid::Uuid;
+
+/// Limits of a bucket or of the whole store; `None` is unlimited.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct Quota {
+    pub max_bytes: Option<u64>,
+    pub max_objects: Option<u64>,
+}
+
+impl Quota {
+    pub fn is_unlimited(&self) -> bool {
+        self.max_bytes.is_none() && self.max_objects.is_none()
+    }
+}
+
+/// Objects and bytes counted against a quota.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize, sqlx::FromRow)]
+pub struct Usage {
+    pub objects: i64,
+    pub bytes: i64,
+}
+
+/// Body of `GET /admin/buckets/{bucket}/quota`.
+#[derive(Debug, Clone, Serialize)]
+pub struct BucketQuota {
+    pub bucket: String,
+    pub quota: Quota,
+    pub usage: Usage,
+    /// The store-wide quota and usage, when a global quota is set.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub global: Option<GlobalQuota>,
+}
+
+#[derive(Debug, Clone, Serialize)]
+pub struct GlobalQuota {
+    pub quota: Quota,
+    pub usage: Usage,
+}
+
+/// Carried by the error of a `limit_stream` stream that outgrew its limit.
+#[derive(Debug)]
+struct QuotaOverflow(String);
+
+impl fmt::Display for QuotaOverflow {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl std::error::Error for QuotaOverflow {}
+
+/// The quota error carried by an error from a `limit_stream` stream, if any.
+pub(crate) fn overflow(err: &io::Error) -> Option<StorageError> {
+    err.get_ref()?
+        .downcast_ref::<QuotaOverflow>()
+        .map(|overflow| StorageError::QuotaExceeded(overflow.0.clone()))
+}
+
+/// Fail `stream` once it yields more than `limit` bytes.
+pub(crate) fn limit_stream<S>(
+    stream: S,
+    limit: u64,
+    reason: String,
+) -> impl Stream<Item = io::Result<Bytes>> + Send + 'static
+where
+    S: Stream<Item = io::Result<Bytes>> + Send + 'static,
+{
+    let mut seen: u64 = 0;
+    stream.map(move |chunk| {
+        let chunk = chunk?;
+        seen += chunk.len() as u64;
+        if seen > limit {
+            return Err(io::Error::other(QuotaOverflow(reason.clone())));
+        }
+        Ok(chunk)
+    })
+}
+
+impl StorageService {
+    /// Quota and usage of `bucket`.
+    pub async fn get_bucket_quota(&self, bucket: &str) -> StorageResult<BucketQuota> {
+        let bucket_rec = self.fetch_bucket(bucket).await?;
+        let quota = self.bucket_quota(bucket_rec.id).await?;
+        let usage = self.bucket_usage_totals(bucket_rec.id).await?;
+        let global = match self.options.global_quota {
+            quota if quota.is_unlimited() => None,
+            quota => Some(GlobalQuota {
+                quota,
+                usage: self.global_usage().await?,
+            }),
+        };
+        Ok(BucketQuota {
+            bucket: bucket_rec.name,
+            quota,
+            usage,
+            global,
+        })
+    }
+
+    /// Replace the quota of `bucket`; an unlimited quota removes it.
+    pub async fn set_bucket_quota(&self, bucket: &str, quota: Quota) -> StorageResult<()> {
+        let bucket_rec = self.fetch_bucket(bucket).await?;
+        let to_i64 = |value: Option<u64>| value.map(|v| i64::try_from(v).unwrap_or(i64::MAX));
+        if quota.is_unlimited() {
+            sqlx::query("DELETE FROM bucket_quotas WHERE bucket_id = ?")
+                .bind(bucket_rec.id)
+                .execute(&*self.db)
+                .await?;
+        } else {
+            sqlx::query(
+                "INSERT INTO bucket_quotas (bucket_id, max_bytes, max_objects) VALUES (?, ?, ?)
+                 ON CONFLICT(bucket_id) DO UPDATE SET
+                     max_bytes = excluded.max_bytes, max_objects = excluded.max_objects",
+            )
+            .bind(bucket_rec.id)
+            .bind(to_i64(quota.max_bytes))
+            .bind(to_i64(quota.max_objects))
+            .execute(&*self.db)
+            .await?;
+        }
+        Ok(())
+    }
+
+    /// Bytes the payload of `key` may have under the bucket's and the global
+    /// quota, `None` when unlimited. Fails when the key cannot be written at
+    /// all (no bytes or objects left).
+    pub(crate) async fn quota_headroom(
+        &self,
+        bucket: &Bucket,
+        key: &str,
+    ) -> StorageResult<Option<u64>> {
+        let quotas = self.applicable_quotas(bucket).await?;
+        if quotas.is_empty() {
+            return Ok(None);
+        }
+        let (new_objects, freed_bytes) = self.replaced_usage(bucket, key).await?;
+        let mut headroom: Option<u64> = None;
+        for (scope, quota, usage) in quotas {
+            check_objects(&scope, quota, usage, new_objects)?;
+            if let Some(max_bytes) = quota.max_bytes {
+                let left = (max_bytes as i128 - usage.bytes as i128 + freed_bytes as i128).max(0);
+                if left == 0 {
+                    return Err(StorageError::QuotaExceeded(format!(
+                        "{} byte quota of {} is used up",
+                        scope, max_bytes
+                    )));
+                }
+                let left = u64::try_from(left).unwrap_or(u64::MAX);
+                headroom = Some(headroom.map_or(left, |current| current.min(left)));
+            }
+        }
+        Ok(headroom)
+    }
+
+    /// Refuse storing `size` bytes as `key` when that would exceed the
+    /// bucket's or the global quota. The caller holds the key's lock.
+    pub(crate) async fn check_quota(
+        &self,
+        bucket: &Bucket,
+        key: &str,
+        size: i64,
+    ) -> StorageResult<()> {
+        let quotas = self.applicable_quotas(bucket).await?;
+        if quotas.is_empty() {
+            return Ok(());
+        }
+        let (new_objects, freed_bytes) = self.replaced_usage(bucket, key).await?;
+        for (scope, quota, usage) in quotas {
+            check_objects(&scope, quota, usage, new_objects)?;
+            if let Some(max_bytes) = quota.max_bytes
+                && size > freed_bytes
+                && usage.bytes - freed_bytes + size > i64::try_from(max_bytes).unwrap_or(i64::MAX)
+            {
+                return Err(StorageError::QuotaExceeded(format!(
+                    "storing {} bytes would exceed the {} byte quota of {} ({} used)",
+                    size, scope, max_bytes, usage.bytes
+                )));
+            }
+        }
+        Ok(())
+    }
+
+    /// Objects added and bytes freed by writing a new current version of
+    /// `key`: an unversioned current object is replaced in place; anything
+    /// else stays in the history and keeps counting.
+    async fn replaced_usage(&self, bucket: &Bucket, key: &str) -> StorageResult<(i64, i64)> {
+        let current: Option<(i64, Option<String>)> = sqlx::query_as(
+            "SELECT size_bytes, version_id FROM objects
+             WHERE bucket_id = ? AND key = ? AND is_deleted = 0",
+        )
+        .bind(bucket.id)
+        .bind(key)
+        .fetch_optional(&*self.db)
+        .await?;
+        Ok(match current {
+            Some((size, None)) if !bucket.versioning_enabled => (0, size),
+            Some(_) => (0, 0),
+            None => (1, 0),
+        })
+    }
+
+    /// The quotas `bucket` is subject to, with their usage and a label for
+    /// error messages.
+    async fn applicable_quotas(
+        &self,
+        bucket: &Bucket,
+    ) -> StorageResult<Vec<(String, Quota, Usage)>> {
+        let mut quotas = Vec::new();
+        let own = self.bucket_quota(bucket.id).await?;
+        if !own.is_unlimited() {
+            quotas.push((
+                format!("bucket `{}`'s", bucket.name),
+                own,
+                self.bucket_usage_totals(bucket.id).await?,
+            ));
+        }
+        let global = self.options.global_quota;
+        if !global.is_unlimited() {
+            quotas.push((
+                "the store's".to_string(),
+                global,
+                self.global_usage().await?,
+            ));
+        }
+        Ok(quotas)
+    }
+
+    async fn bucket_quota(&self, bucket_id: Uuid) -> StorageResult<Quota> {
+        let row: Option<(Option<i64>, Option<i64>)> =
+            sqlx::query_as("SELECT max_bytes, max_objects FROM bucket_quotas WHERE bucket_id = ?")
+                .bind(bucket_id)
+                .fetch_optional(&*self.db)
+                .await?;
+        let to_u64 = |value: Option<i64>| value.map(|v| v.max(0) as u64);
+        Ok(row
+            .map(|(max_bytes, max_objects)| Quota {
+                max_bytes: to_u64(max_bytes),
+                max_objects: to_u64(max_objects),
+            })
+            .unwrap_or_default())
+    }
+
+    async fn bucket_usage_totals(&self, bucket_id: Uuid) -> StorageResult<Usage> {
+        Ok(sqlx::query_as::<_, Usage>(
+            "SELECT objects, bytes FROM bucket_usage WHERE bucket_id = ?",
+        )
+        .bind(bucket_id)
+        .fetch_optional(&*self.db)
+        .await?
+        .unwrap_or_default())
+    }
+
+    async fn global_usage(&self) -> StorageResult<Usage> {
+        Ok(sqlx::query_as::<_, Usage>(
+            "SELECT COALESCE(SUM(objects), 0) AS objects, COALESCE(SUM(bytes), 0) AS bytes
+             FROM bucket_usage",
+        )
+        .fetch_one(&*self.db)
+        .await?)
+    }
+}
+
+/// Refuse `new_objects` more objects when `quota`'s count is reached.
+fn check_objects(scope: &str, quota: Quota, usage: Usage, new_objects: i64) -> StorageResult<()> {
+    match quota.max_objects {
+        Some(max_objects)
+            if new_objects > 0
+                && usage.objects + new_objects > i64::try_from(max_objects).unwrap_or(i64::MAX) =>
+        {
+            Err(StorageError::QuotaExceeded(format!(
+                "{} object quota of {} is used up",
+                scope, max_objects
+            )))
+        }
+        _ => Ok(()),
+    }
+}
@@ -0,0 +1,211 @@
+//! Background removal of deleted payloads.
+//!
+//! Unlinking a payload and pruning the shard directories it leaves empty is
+//! the slow part of a delete. Once `spawn_reclaimers` has started the worker
+//! pool, deletes only rename the payload into `base_path/.reclaim/` — which
+//! frees the key's path for the next upload straight away — and queue it.
+//! Workers drain the queue in batches, unlink the files and prune each
+//! affected directory once per batch, deepest first.
+//!
+//! The queue is bounded. When it is full, or no workers are running (as in
+//! tests and one-shot commands), the caller does the work inline as before.
+//! Files still in `.reclaim` after a shutdown are removed at the next start.
+
+use crate::services::storage_service::{StorageService, side_dirs};
+use std::{
+    collections::BTreeSet,
+    io,
+    path::{Path, PathBuf},
+    sync::{Arc, OnceLock},
+};
+use tokio::{
+    fs,
+    sync::{Mutex, mpsc},
+    task::JoinHandle,
+};
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+/// Directory (below `base_path`) holding payloads waiting to be unlinked.
+const RECLAIM_DIR: &str = side_dirs::RECLAIM;
+
+/// Workers started by `main`.
+pub const RECLAIM_WORKERS: usize = 4;
+
+/// Removals that may wait in the queue before deletes fall back to inline.
+const RECLAIM_QUEUE: usize = 4096;
+
+/// Removals a worker takes from the queue at once.
+const RECLAIM_BATCH: usize = 128;
+
+/// A file to unlink and/or a directory to prune up to `stop`.
+#[derive(Debug)]
+struct Reclaim {
+    file: Option<PathBuf>,
+    prune: Option<(PathBuf, PathBuf)>,
+}
+
+/// Hands removals to the worker pool once it is running.
+#[derive(Debug, Clone, Default)]
+pub struct ReclaimQueue {
+    sender: Arc<OnceLock<mpsc::Sender<Reclaim>>>,
+}
+
+impl StorageService {
+    fn reclaim_root(&self) -> PathBuf {
+        self.base_path.join(RECLAIM_DIR)
+    }
+
+    /// Remove the payload of a key at `path` and prune the directories it
+    /// leaves empty in `bucket_name`.
+    ///
+    /// A missing file is not an error. With workers running, the payload is
+    /// moved aside here and unlinked later.
+    pub(crate) async fn remove_payload(&self, bucket_name: &str, path: &Path) -> io::Result<()> {
+        let prune = path.parent().map(|parent| {
+            (
+                parent.to_path_buf(),
+                self.payload_bucket_root(bucket_name, path),
+            )
+        });
+        if self.reclaim.sender.get().is_none() {
+            remove_if_present(path).await?;
+            if let Some((dir, stop)) = prune {
+                self.prune_empty_dirs(&dir, &stop).await;
+            }
+            return Ok(());
+        }
+
+        let aside = self.reclaim_root().join(Uuid::new_v4().to_string());
+        let file = match fs::rename(path, &aside).await {
+            Ok(()) => Some(aside),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => None,
+            Err(err) => {
+                debug!("could not move {} aside: {}", path.display(), err);
+                remove_if_present(path).await?;
+                None
+            }
+        };
+        self.enqueue_reclaim(Reclaim { file, prune }).await;
+        Ok(())
+    }
+
+    /// Unlink a file nothing else can reach any more (an archived version),
+    /// in the background when workers are running.
+    pub(crate) async fn discard_file(&self, path: PathBuf) {
+        self.enqueue_reclaim(Reclaim {
+            file: Some(path),
+            prune: None,
+        })
+        .await;
+    }
+
+    /// Prune the directories above `path` in `bucket_name` that are empty,
+    /// in the background when workers are running.
+    pub(crate) async fn prune_payload_dirs(&self, bucket_name: &str, path: &Path) {
+        if let Some(parent) = path.parent() {
+            self.enqueue_reclaim(Reclaim {
+                file: None,
+                prune: Some((
+                    parent.to_path_buf(),
+                    self.payload_bucket_root(bucket_name, path),
+                )),
+            })
+            .await;
+        }
+    }
+
+    async fn enqueue_reclaim(&self, item: Reclaim) {
+        let item = match self.reclaim.sender.get() {
+            Some(sender) => match sender.try_send(item) {
+                Ok(()) => return,
+                Err(mpsc::error::TrySendError::Full(item))
+                | Err(mpsc::error::TrySendError::Closed(item)) => item,
+            },
+            None => item,
+        };
+        self.run_reclaims(vec![item]).await;
+    }
+
+    /// Unlink every file in `batch`, then prune each distinct directory once.
+    async fn run_reclaims(&self, batch: Vec<Reclaim>) {
+        let mut dirs = BTreeSet::new();
+        for item in batch {
+            if let Some(file) = item.file
+                && let Err(err) = remove_if_present(&file).await
+            {
+                warn!("failed to remove payload {}: {}", file.display(), err);
+            }
+            if let Some(prune) = item.prune {
+                dirs.insert(prune);
+            }
+        }
+        // Children sort after their parents; prune them first so a parent
+        // emptied by its child's removal goes too.
+        for (dir, stop) in dirs.into_iter().rev() {
+            self.prune_empty_dirs(&dir, &stop).await;
+        }
+    }
+
+    /// Remove payloads left in the reclaim area by an earlier run.
+    async fn sweep_reclaim_dir(&self) -> io::Result<u64> {
+        let mut entries = match fs::read_dir(self.reclaim_root()).await {
+            Ok(entries) => entries,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(0),
+            Err(err) => return Err(err),
+        };
+        let mut removed = 0;
+        while let Some(entry) = entries.next_entry().await? {
+            remove_if_present(&entry.path()).await?;
+            removed += 1;
+        }
+        Ok(removed)
+    }
+}
+
+async fn remove_if_present(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path).await {
+        Ok(()) => Ok(()),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+        Err(err) => Err(err),
+    }
+}
+
+/// Start `workers` tasks removing payloads in the background. Deletes keep
+/// removing payloads inline until this has been called.
+pub async fn spawn_reclaimers(service: &StorageService, workers: usize) -> Vec<JoinHandle<()>> {
+    match service.sweep_reclaim_dir().await {
+        Ok(0) => {}
+        Ok(n) => info!("removed {} payloads left from an earlier run", n),
+        Err(err) => warn!("could not sweep reclaim directory: {}", err),
+    }
+    if let Err(err) = fs::create_dir_all(service.reclaim_root()).await {
+        warn!("payload removal stays inline: {}", err);
+        return Vec::new();
+    }
+    let (sender, receiver) = mpsc::channel(RECLAIM_QUEUE);
+    if service.reclaim.sender.set(sender).is_err() {
+        return Vec::new();
+    }
+    let receiver = Arc::new(Mutex::new(receiver));
+    (0..workers.max(1))
+        .map(|_| {
+            let service = service.clone();
+            let receiver = receiver.clone();
+            tokio::spawn(async move {
+                let mut batch = Vec::with_capacity(RECLAIM_BATCH);
+                loop {
+                    let received = receiver
+                        .lock()
+                        .await
+                        .recv_many(&mut batch, RECLAIM_BATCH)
+                        .await;
+                    if received == 0 {
+                        break;
+                    }
+                    service.run_reclaims(std::mem::take(&mut batch)).await;
+                }
+            })
+        })
+        .collect()
+}
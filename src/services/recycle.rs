@@ -0,0 +1,347 @@
+//! Recycle area for payloads displaced by overwrites.
+//!
+//! In non-versioned buckets an upload replaces the previous payload in place.
+//! When `StorageOptions::overwrite_retention` is set, the previous payload is
+//! moved to `base_path/.recycle/{bucket}/{id}` instead and tracked in the
+//! `recycled_objects` table until its grace period expires, so an accidental
+//! overwrite can be undone.
+
+use crate::{
+    models::{bucket::Bucket, object::Object, recycled_object::RecycledObject},
+    services::{
+        changes::{NewChange, insert_change},
+        events::EventKind,
+        placement::move_file,
+        storage_service::{OBJECT_COLUMNS, StorageError, StorageResult, StorageService, side_dirs},
+    },
+};
+use chrono::Utc;
+use std::{
+    io,
+    path::{Path, PathBuf},
+    time::Duration,
+};
+use tokio::{fs, task::JoinHandle};
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+/// Directory (below `base_path`) holding recycled payloads.
+const RECYCLE_DIR: &str = side_dirs::RECYCLE;
+
+impl StorageService {
+    /// Root of the recycle area for a bucket.
+    pub(crate) fn recycle_root(&self, bucket_name: &str) -> PathBuf {
+        self.base_path.join(RECYCLE_DIR).join(bucket_name)
+    }
+
+    fn recycle_path(&self, bucket_name: &str, id: Uuid) -> PathBuf {
+        self.recycle_root(bucket_name).join(id.to_string())
+    }
+
+    /// Move the current payload of `key` (if any) into the recycle area.
+    ///
+    /// Called by the upload path right before the new payload is renamed into
+    /// place. Returns `None` when retention is disabled or there is nothing to
+    /// keep.
+    pub(crate) async fn recycle_previous_payload(
+        &self,
+        bucket: &Bucket,
+        key: &str,
+    ) -> StorageResult<Option<RecycledObject>> {
+        let Some(retention) = self.options.overwrite_retention else {
+            return Ok(None);
+        };
+
+        let previous = match self.fetch_object(bucket, key).await {
+            Ok(obj) => obj,
+            Err(StorageError::ObjectNotFound { .. }) => return Ok(None),
+            Err(err) => return Err(err),
+        };
+        // Restoring would serve the payload without its key record.
+        if self.object_encryption(&previous).await?.is_some() {
+            return Ok(None);
+        }
+
+        let live_path = self.object_path_on(previous.volume.as_deref(), &bucket.name, key);
+        let recycled_at = Utc::now();
+        let recycled = RecycledObject {
+            id: Uuid::new_v4(),
+            bucket_id: bucket.id,
+            key: key.to_string(),
+            content_type: previous.content_type,
+            content_encoding: previous.content_encoding,
+            size_bytes: previous.size_bytes,
+            etag: previous.etag,
+            checksum_sha256: previous.checksum_sha256,
+            checksum_crc32c: previous.checksum_crc32c,
+            last_modified: previous.last_modified,
+            recycled_at,
+            expires_at: recycled_at
+                + chrono::Duration::from_std(retention).unwrap_or(chrono::Duration::MAX),
+            acl: previous.acl,
+            cache_control: previous.cache_control,
+            content_disposition: previous.content_disposition,
+            expires: previous.expires,
+        };
+
+        let target = self.recycle_path(&bucket.name, recycled.id);
+        fs::create_dir_all(self.recycle_root(&bucket.name)).await?;
+        match move_file(&live_path, &target).await {
+            Ok(_) => {}
+            Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+            Err(err) => return Err(StorageError::Io(err)),
+        }
+
+        let insert = sqlx::query(
+            "INSERT INTO recycled_objects (
+                id, bucket_id, key, content_type, content_encoding, size_bytes, etag,
+                checksum_sha256, checksum_crc32c, last_modified, recycled_at, expires_at, acl,
+                cache_control, content_disposition, expires
+             ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(recycled.id)
+        .bind(recycled.bucket_id)
+        .bind(&recycled.key)
+        .bind(&recycled.content_type)
+        .bind(&recycled.content_encoding)
+        .bind(recycled.size_bytes)
+        .bind(&recycled.etag)
+        .bind(&recycled.checksum_sha256)
+        .bind(&recycled.checksum_crc32c)
+        .bind(recycled.last_modified)
+        .bind(recycled.recycled_at)
+        .bind(recycled.expires_at)
+        .bind(&recycled.acl)
+        .bind(&recycled.cache_control)
+        .bind(&recycled.content_disposition)
+        .bind(&recycled.expires)
+        .execute(&*self.db)
+        .await;
+
+        if let Err(err) = insert {
+            let _ = move_file(&target, &live_path).await;
+            return Err(StorageError::Sqlx(err));
+        }
+
+        debug!(
+            "recycled previous payload of {}/{} as {}",
+            bucket.name, key, recycled.id
+        );
+        Ok(Some(recycled))
+    }
+
+    /// Undo `recycle_previous_payload` after a failed upload (best-effort).
+    pub(crate) async fn unrecycle_payload(
+        &self,
+        bucket: &Bucket,
+        recycled: &RecycledObject,
+        live_path: &Path,
+    ) {
+        let source = self.recycle_path(&bucket.name, recycled.id);
+        if let Err(err) = move_file(&source, live_path).await {
+            warn!(
+                "could not move recycled payload {} back to {}: {}",
+                recycled.id,
+                live_path.display(),
+                err
+            );
+            return;
+        }
+        let _ = sqlx::query("DELETE FROM recycled_objects WHERE id = ?")
+            .bind(recycled.id)
+            .execute(&*self.db)
+            .await;
+    }
+
+    /// List recycled payloads for a key, newest first. Expired entries that
+    /// have not been purged yet are skipped.
+    pub async fn list_recycled(
+        &self,
+        bucket: &str,
+        key: &str,
+    ) -> StorageResult<Vec<RecycledObject>> {
+        self.ensure_key_safe(key)?;
+        let bucket_rec = self.fetch_bucket(bucket).await?;
+        let rows = sqlx::query_as::<_, RecycledObject>(
+            "SELECT id, bucket_id, key, content_type, content_encoding, size_bytes, etag,
+                    checksum_sha256, checksum_crc32c, last_modified, recycled_at, expires_at, acl,
+                    cache_control, content_disposition, expires
+             FROM recycled_objects
+             WHERE bucket_id = ? AND key = ? AND expires_at > ?
+             ORDER BY recycled_at DESC",
+        )
+        .bind(bucket_rec.id)
+        .bind(key)
+        .bind(Utc::now())
+        .fetch_all(&*self.db)
+        .await?;
+        Ok(rows)
+    }
+
+    /// Put the most recently recycled payload of `key` back in place.
+    ///
+    /// The payload being replaced is itself recycled (when retention is on),
+    /// so a recovery can be undone the same way. Returns ObjectNotFound when
+    /// there is nothing to recover.
+    pub async fn recover_overwritten(&self, bucket: &str, key: &str) -> StorageResult<Object> {
+        let bucket_rec = self.fetch_writable_bucket(bucket).await?;
+        let _key_guard = self.key_locks.lock(bucket_rec.id, key).await;
+        let candidate = self
+            .list_recycled(bucket, key)
+            .await?
+            .into_iter()
+            .next()
+            .ok_or_else(|| StorageError::ObjectNotFound {
+                bucket: bucket.to_string(),
+                key: key.to_string(),
+            })?;
+
+        let source = self.recycle_path(&bucket_rec.name, candidate.id);
+        let live_path = self.live_path(&bucket_rec, key).await?;
+        if let Some(parent) = live_path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+
+        let change_kind = self.write_kind(&bucket_rec, key).await?;
+        let displaced = self.recycle_previous_payload(&bucket_rec, key).await?;
+        if let Err(err) = move_file(&source, &live_path).await {
+            if let Some(displaced) = displaced {
+                self.unrecycle_payload(&bucket_rec, &displaced, &live_path)
+                    .await;
+            }
+            return Err(if err.kind() == io::ErrorKind::NotFound {
+                StorageError::ObjectNotFound {
+                    bucket: bucket.to_string(),
+                    key: key.to_string(),
+                }
+            } else {
+                StorageError::Io(err)
+            });
+        }
+
+        let filename = key.split('/').next_back().unwrap_or(key).to_string();
+        let mut tx = self.db.begin().await?;
+        let object = sqlx::query_as::<_, Object>(&format!(
+            r#"
+            INSERT INTO objects (
+                id, bucket_id, key, filename, content_type, content_encoding, size_bytes,
+                etag, checksum_sha256, checksum_crc32c, storage_class, last_modified, version_id,
+                is_deleted, acl, cache_control, content_disposition, expires
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, 0, ?, ?, ?, ?)
+            ON CONFLICT(bucket_id, key) DO UPDATE SET
+                filename = excluded.filename,
+                content_type = excluded.content_type,
+                content_encoding = excluded.content_encoding,
+                size_bytes = excluded.size_bytes,
+                etag = excluded.etag,
+                checksum_sha256 = excluded.checksum_sha256,
+                checksum_crc32c = excluded.checksum_crc32c,
+                last_modified = excluded.last_modified,
+                is_deleted = 0,
+                acl = excluded.acl,
+                cache_control = excluded.cache_control,
+                content_disposition = excluded.content_disposition,
+                expires = excluded.expires
+            RETURNING {OBJECT_COLUMNS}
+            "#
+        ))
+        .bind(Uuid::new_v4())
+        .bind(bucket_rec.id)
+        .bind(key)
+        .bind(&filename)
+        .bind(&candidate.content_type)
+        .bind(&candidate.content_encoding)
+        .bind(candidate.size_bytes)
+        .bind(&candidate.etag)
+        .bind(&candidate.checksum_sha256)
+        .bind(&candidate.checksum_crc32c)
+        .bind("STANDARD")
+        .bind(Utc::now())
+        .bind::<Option<String>>(None)
+        .bind(&candidate.acl)
+        .bind(&candidate.cache_control)
+        .bind(&candidate.content_disposition)
+        .bind(&candidate.expires)
+        .fetch_one(&mut *tx)
+        .await?;
+        sqlx::query("DELETE FROM recycled_objects WHERE id = ?")
+            .bind(candidate.id)
+            .execute(&mut *tx)
+            .await?;
+        insert_change(
+            &mut *tx,
+            bucket_rec.id,
+            &NewChange::of(change_kind, &object),
+        )
+        .await?;
+        tx.commit().await?;
+
+        info!(
+            "recovered {}/{} from recycled payload {}",
+            bucket, key, candidate.id
+        );
+        self.events.object(
+            EventKind::ObjectCreated,
+            &bucket_rec.name,
+            key,
+            Some(object.size_bytes),
+            object.etag.clone(),
+        );
+        Ok(object)
+    }
+
+    /// Permanently remove recycled payloads whose grace period has elapsed.
+    ///
+    /// Returns the number of entries purged.
+    pub async fn purge_expired_recycled(&self) -> StorageResult<u64> {
+        let expired: Vec<(Uuid, String)> = sqlx::query_as(
+            "SELECT r.id, b.name
+             FROM recycled_objects r JOIN buckets b ON b.id = r.bucket_id
+             WHERE r.expires_at <= ?",
+        )
+        .bind(Utc::now())
+        .fetch_all(&*self.db)
+        .await?;
+
+        let mut purged = 0;
+        for (id, bucket_name) in expired {
+            let path = self.recycle_path(&bucket_name, id);
+            match fs::remove_file(&path).await {
+                Ok(_) => {}
+                Err(err) if err.kind() == io::ErrorKind::NotFound => {}
+                Err(err) => {
+                    warn!(
+                        "failed to purge recycled payload {}: {}",
+                        path.display(),
+                        err
+                    );
+                    continue;
+                }
+            }
+            sqlx::query("DELETE FROM recycled_objects WHERE id = ?")
+                .bind(id)
+                .execute(&*self.db)
+                .await?;
+            purged += 1;
+        }
+
+        if purged > 0 {
+            debug!("purged {} expired recycled payloads", purged);
+        }
+        Ok(purged)
+    }
+}
+
+/// Spawn a background task that purges expired recycled payloads every
+/// `period`.
+pub fn spawn_recycle_purger(service: StorageService, period: Duration) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(period);
+        loop {
+            ticker.tick().await;
+            if let Err(err) = service.purge_expired_recycled().await {
+                warn!("recycle purge failed: {}", err);
+            }
+        }
+    })
+}
@@ -0,0 +1,769 @@
+//! Asynchronous replication of a bucket to another S3 endpoint.
+//!
+//! For single-node deployments this is the disaster-recovery copy: a
+//! bucket's replication configuration
+//! (`PUT /admin/buckets/{bucket}/replication`) names a destination bucket
+//! on any S3 API (another object-store, MinIO, AWS) and the credentials to
+//! write to it:
+//!
+//! ```json
+//! {
+//!   "destination": "https://backup.example.com/photos-replica",
+//!   "region": "eu-west-1",
+//!   "access_key": "AKIA...",
+//!   "secret_key": "...",
+//!   "prefix": "raw/",
+//!   "replicate_deletes": true
+//! }
+//! ```
+//!
+//! Keys keep their name at the destination (below any prefix in
+//! `destination`). Only keys starting with `prefix` are replicated, and
+//! deletes are skipped when `replicate_deletes` is `false`. The secret key is
+//! stored with the bucket and never returned; a PUT that omits it keeps the
+//! stored one when the access key is unchanged.
+//!
+//! Replication follows the change feed (see `changes`) from the moment it
+//! is configured; existing objects are not copied. The worker
+//! (`spawn_replicator`) queues every changed key in `object_replication` as
+//! `pending`, then brings the destination to the key's current state: the
+//! live payload is PUT with its content headers and user metadata, a key
+//! without one is DELETEd. A failed attempt is retried with exponential
+//! backoff, up to `MAX_REPLICATION_ATTEMPTS`, after which the key is marked
+//! `failed` until it changes again. Objects encrypted with a customer key
+//! (SSE-C) cannot be read without it and are marked `failed` right away.
+//!
+//! GET and HEAD of a key replicated this way carry its state as
+//! `x-amz-replication-status` (`PENDING`, `COMPLETED` or `FAILED`). How far
+//! behind the destination is shows in the bucket's stats
+//! (`GET /admin/buckets/{bucket}/stats`), see `ReplicationLag`.
+//!
+//! A reconciliation job (`POST /admin/buckets/{bucket}/replication/reconcile`)
+//! lists the destination and compares it with the bucket by ETag, then
+//! queues every missing or differing object, and with `replicate_deletes`
+//! every key only the destination has, for replication again. Its counts
+//! are the job's `report`.
+
+use crate::{
+    models::job::Job,
+    services::{
+        blob_store::{BlobStore, RemoteObject, RemoteS3Store, S3Credentials},
+        jobs::JOB_COLUMNS,
+        outbound::OutboundHttp,
+        storage_service::{StorageError, StorageResult, StorageService},
+        versioning::NULL_VERSION,
+    },
+};
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use futures::{StreamExt, stream};
+use reqwest::{Client, Url};
+use serde::{Deserialize, Serialize};
+use std::{collections::HashMap, io, time::Duration};
+use tokio::task::JoinHandle;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+/// How often the worker looks for new changes and due keys.
+pub const REPLICATION_TICK: Duration = Duration::from_secs(1);
+
+/// Attempts before a key is given up and marked `failed`.
+pub const MAX_REPLICATION_ATTEMPTS: i64 = 10;
+
+/// Wait after the first failed attempt; doubled after each further one.
+const RETRY_BASE: Duration = Duration::from_secs(5);
+
+/// Longest wait between two attempts.
+const RETRY_MAX: Duration = Duration::from_secs(3600);
+
+/// Changes queued per bucket, and keys attempted, per pass.
+const BATCH: i64 = 500;
+
+/// Keys replicated at once.
+const CONCURRENT_REPLICATIONS: usize = 4;
+
+/// Region signed for when the configuration names none.
+const DEFAULT_REGION: &str = "us-east-1";
+
+/// Where a bucket is replicated to.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ReplicationConfig {
+    /// `scheme://host[:port]/bucket[/prefix]` of the destination.
+    pub destination: String,
+    #[serde(default = "default_region")]
+    pub region: String,
+    pub access_key: String,
+    /// Never returned.
+    #[serde(default, skip_serializing)]
+    pub secret_key: String,
+    /// Only keys starting with this are replicated.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prefix: Option<String>,
+    #[serde(default = "replicate_deletes_default")]
+    pub replicate_deletes: bool,
+}
+
+fn default_region() -> String {
+    DEFAULT_REGION.to_string()
+}
+
+fn replicate_deletes_default() -> bool {
+    true
+}
+
+impl ReplicationConfig {
+    /// Whether changes of `key` are replicated.
+    pub fn matches(&self, key: &str) -> bool {
+        self.prefix
+            .as_deref()
+            .is_none_or(|prefix| key.starts_with(prefix))
+    }
+
+    /// A client for the destination bucket.
+    fn store(&self, client: Client) -> Result<RemoteS3Store, String> {
+        let url = Url::parse(&self.destination)
+            .map_err(|err| format!("destination `{}`: {}", self.destination, err))?;
+        if !matches!(url.scheme(), "http" | "https") {
+            return Err(format!(
+                "destination `{}` must be an http(s) URL",
+                self.destination
+            ));
+        }
+        let credentials: S3Credentials = format!("{}:{}", self.access_key, self.secret_key)
+            .parse()
+            .map_err(|_| "access_key and secret_key must be set".to_string())?;
+        RemoteS3Store::new(client, url, self.region.clone(), credentials)
+    }
+}
+
+/// Replication state of a key.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ReplicationState {
+    Pending,
+    Completed,
+    Failed,
+}
+
+impl ReplicationState {
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ReplicationState::Pending => "pending",
+            ReplicationState::Completed => "completed",
+            ReplicationState::Failed => "failed",
+        }
+    }
+
+    /// Value of the `x-amz-replication-status` header.
+    pub fn header_value(&self) -> &'static str {
+        match self {
+            ReplicationState::Pending => "PENDING",
+            ReplicationState::Completed => "COMPLETED",
+            ReplicationState::Failed => "FAILED",
+        }
+    }
+
+    fn parse(state: &str) -> Option<Self> {
+        match state {
+            "pending" => Some(ReplicationState::Pending),
+            "completed" => Some(ReplicationState::Completed),
+            "failed" => Some(ReplicationState::Failed),
+            _ => None,
+        }
+    }
+}
+
+/// What a reconciliation job found, stored as its report.
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct ReconcileReport {
+    /// Live objects compared.
+    pub checked: u64,
+    pub in_sync: u64,
+    /// Objects the destination lacks.
+    pub missing: u64,
+    /// Objects whose copy has another ETag (or size, for multipart ETags).
+    pub differing: u64,
+    /// Keys only the destination has.
+    pub extra: u64,
+    /// Objects encrypted with a customer key, which are never replicated.
+    pub skipped: u64,
+    /// Keys queued for replication again.
+    pub requeued: u64,
+}
+
+/// How far a bucket's destination is behind, part of its stats.
+#[derive(Debug, Clone, Serialize)]
+pub struct ReplicationLag {
+    pub destination: String,
+    /// Keys whose latest change is not replicated yet.
+    pub pending: u64,
+    /// Keys given up on until they change again.
+    pub failed: u64,
+    pub completed: u64,
+    /// Age of the oldest change not replicated (pending or failed); 0 when
+    /// the destination is up to date.
+    pub lag_secs: u64,
+    pub oldest_unreplicated_at: Option<DateTime<Utc>>,
+    pub last_replicated_at: Option<DateTime<Utc>>,
+}
+
+/// What one replication pass did.
+#[derive(Debug, Default, Clone, Serialize)]
+pub struct ReplicationSummary {
+    /// Keys queued from new changes.
+    pub queued: u64,
+    pub replicated: u64,
+    /// Failed attempts that will be retried.
+    pub retried: u64,
+    /// Keys given up on.
+    pub failed: u64,
+}
+
+type ChangeRow = (i64, String, String, DateTime<Utc>);
+
+type DueRow = (Uuid, String, String, i64, i64);
+
+type LocalRow = (String, Option<String>, i64, bool);
+
+type LagRow = (i64, i64, i64, Option<DateTime<Utc>>, Option<DateTime<Utc>>);
+
+impl StorageService {
+    /// The replication configuration of `bucket`, without its secret key.
+    pub async fn get_replication(&self, bucket: &str) -> StorageResult<ReplicationConfig> {
+        let bucket_rec = self.fetch_bucket(bucket).await?;
+        self.replication_config(bucket_rec.id)
+            .await?
+            .ok_or_else(|| StorageError::NoSuchReplicationConfiguration(bucket_rec.name))
+    }
+
+    /// Replicate `bucket` as `config` says from now on. Reconfiguring keeps
+    /// the bucket's place in the change feed and the keys still pending.
+    pub async fn set_replication(
+        &self,
+        bucket: &str,
+        mut config: ReplicationConfig,
+    ) -> StorageResult<ReplicationConfig> {
+        let bucket_rec = self.fetch_bucket(bucket).await?;
+        let existing = self.replication_config(bucket_rec.id).await?;
+        if config.secret_key.is_empty()
+            && let Some(existing) = existing.filter(|e| e.access_key == config.access_key)
+        {
+            config.secret_key = existing.secret_key;
+        }
+        if config.region.trim().is_empty() {
+            return Err(StorageError::InvalidReplication(
+                "region must not be empty".into(),
+            ));
+        }
+        config
+            .store(Client::new())
+            .map_err(StorageError::InvalidReplication)?;
+        let json = serde_json::to_string(&StoredConfig(&config))
+            .map_err(|err| StorageError::InvalidReplication(err.to_string()))?;
+        sqlx::query(
+            "INSERT INTO bucket_replication (bucket_id, config, last_seq)
+             VALUES (?, ?, (SELECT COALESCE(MAX(seq), 0) FROM bucket_changes))
+             ON CONFLICT(bucket_id) DO UPDATE SET config = excluded.config",
+        )
+        .bind(bucket_rec.id)
+        .bind(json)
+        .execute(&*self.db)
+        .await?;
+        info!(
+            "bucket `{}` replicates to {}",
+            bucket_rec.name, config.destination
+        );
+        Ok(config)
+    }
+
+    /// Stop replicating `bucket` and forget the status of its keys.
+    pub async fn delete_replication(&self, bucket: &str) -> StorageResult<()> {
+        let bucket_rec = self.fetch_bucket(bucket).await?;
+        let mut tx = self.db.begin().await?;
+        for table in ["bucket_replication", "object_replication"] {
+            sqlx::query(&format!("DELETE FROM {table} WHERE bucket_id = ?"))
+                .bind(bucket_rec.id)
+                .execute(&mut *tx)
+                .await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// How far the destination of the bucket with `bucket_id` is behind;
+    /// `None` when it is not replicated.
+    pub async fn replication_lag(&self, bucket_id: Uuid) -> StorageResult<Option<ReplicationLag>> {
+        let Some(config) = self.replication_config(bucket_id).await? else {
+            return Ok(None);
+        };
+        let (pending, failed, completed, oldest, last): LagRow = sqlx::query_as(
+            "SELECT
+                 COALESCE(SUM(state = 'pending'), 0),
+                 COALESCE(SUM(state = 'failed'), 0),
+                 COALESCE(SUM(state = 'completed'), 0),
+                 MIN(CASE WHEN state != 'completed' THEN queued_at END),
+                 MAX(replicated_at)
+             FROM object_replication WHERE bucket_id = ?",
+        )
+        .bind(bucket_id)
+        .fetch_one(&*self.db)
+        .await?;
+        let lag_secs = oldest
+            .map(|oldest| (Utc::now() - oldest).num_seconds().max(0) as u64)
+            .unwrap_or(0);
+        Ok(Some(ReplicationLag {
+            destination: config.destination,
+            pending: pending.max(0) as u64,
+            failed: failed.max(0) as u64,
+            completed: completed.max(0) as u64,
+            lag_secs,
+            oldest_unreplicated_at: oldest,
+            last_replicated_at: last,
+        }))
+    }
+
+    /// Queue the keys changed since the last pass of every replicated
+    /// bucket. Returns the number of changes queued.
+    pub async fn queue_replication(&self) -> StorageResult<u64> {
+        let buckets: Vec<(Uuid, String, i64)> =
+            sqlx::query_as("SELECT bucket_id, config, last_seq FROM bucket_replication")
+                .fetch_all(&*self.db)
+                .await?;
+        let mut queued = 0;
+        for (bucket_id, json, last_seq) in buckets {
+            let config = parse_config(&json)?;
+            let mut tx = self.db.begin().await?;
+            let changes: Vec<ChangeRow> = sqlx::query_as(
+                "SELECT seq, key, event, occurred_at FROM bucket_changes
+                 WHERE bucket_id = ? AND seq > ? ORDER BY seq LIMIT ?",
+            )
+            .bind(bucket_id)
+            .bind(last_seq)
+            .bind(BATCH)
+            .fetch_all(&mut *tx)
+            .await?;
+            let Some(last) = changes.last().map(|change| change.0) else {
+                continue;
+            };
+            let now = Utc::now();
+            for (seq, key, event, occurred_at) in &changes {
+                let skipped = event == "deleted" && !config.replicate_deletes;
+                if skipped || !config.matches(key) {
+                    continue;
+                }
+                sqlx::query(
+                    "INSERT INTO object_replication
+                        (bucket_id, key, change_seq, state, next_attempt_at, queued_at)
+                     VALUES (?, ?, ?, 'pending', ?, ?)
+                     ON CONFLICT(bucket_id, key) DO UPDATE SET
+                         change_seq = excluded.change_seq,
+                         state = 'pending',
+                         attempts = 0,
+                         next_attempt_at = excluded.next_attempt_at,
+                         last_error = NULL,
+                         queued_at = CASE WHEN object_replication.state = 'completed'
+                             THEN excluded.queued_at ELSE object_replication.queued_at END",
+                )
+                .bind(bucket_id)
+                .bind(key)
+                .bind(seq)
+                .bind(now)
+                .bind(occurred_at)
+                .execute(&mut *tx)
+                .await?;
+                queued += 1;
+            }
+            sqlx::query("UPDATE bucket_replication SET last_seq = ? WHERE bucket_id = ?")
+                .bind(last)
+                .bind(bucket_id)
+                .execute(&mut *tx)
+                .await?;
+            tx.commit().await?;
+        }
+        if queued > 0 {
+            debug!("queued {} change(s) for replication", queued);
+        }
+        Ok(queued)
+    }
+
+    /// Replication state of the live object at `key`; `None` when the bucket
+    /// is not replicated or the key has not changed since it is.
+    pub async fn replication_state(
+        &self,
+        bucket_id: Uuid,
+        key: &str,
+    ) -> StorageResult<Option<ReplicationState>> {
+        let state: Option<(String,)> =
+            sqlx::query_as("SELECT state FROM object_replication WHERE bucket_id = ? AND key = ?")
+                .bind(bucket_id)
+                .bind(key)
+                .fetch_optional(&*self.db)
+                .await?;
+        Ok(state.and_then(|(state,)| ReplicationState::parse(&state)))
+    }
+
+    /// Queue a job comparing `bucket` with its destination.
+    pub async fn enqueue_replication_reconcile(&self, bucket: &str) -> StorageResult<Job> {
+        let bucket_rec = self.fetch_bucket(bucket).await?;
+        let config = self
+            .replication_config(bucket_rec.id)
+            .await?
+            .ok_or_else(|| StorageError::NoSuchReplicationConfiguration(bucket_rec.name.clone()))?;
+        let job = sqlx::query_as::<_, Job>(&format!(
+            "INSERT INTO jobs (id, kind, bucket_id, prefix, destination, status, created_at)
+             VALUES (?, 'replication-reconcile', ?, ?, ?, 'queued', ?)
+             RETURNING {JOB_COLUMNS}"
+        ))
+        .bind(Uuid::new_v4())
+        .bind(bucket_rec.id)
+        .bind(&config.prefix)
+        .bind(&config.destination)
+        .bind(Utc::now())
+        .fetch_one(&*self.db)
+        .await?;
+        self.jobs.notify();
+        info!(
+            "queued job {}: reconcile `{}` with {}",
+            job.id, bucket_rec.name, config.destination
+        );
+        Ok(job)
+    }
+
+    /// Compare the bucket of `job` with its destination by ETag and queue
+    /// the keys that differ for replication again.
+    pub(crate) async fn run_replication_reconcile(&self, job: &Job) -> StorageResult<()> {
+        let config = self
+            .replication_config(job.bucket_id)
+            .await?
+            .ok_or_else(|| {
+                StorageError::NoSuchReplicationConfiguration(job.bucket_id.to_string())
+            })?;
+        let client = self
+            .options
+            .outbound
+            .client_builder()
+            .build()
+            .map_err(io::Error::other)?;
+        let store = config
+            .store(client)
+            .map_err(StorageError::InvalidReplication)?;
+        let prefix = config.prefix.clone().unwrap_or_default();
+        let mut remote: HashMap<String, RemoteObject> = store
+            .list(&prefix)
+            .await?
+            .into_iter()
+            .map(|object| (object.key.clone(), object))
+            .collect();
+
+        // Keys sharing a prefix are contiguous in key order; a range scan
+        // avoids LIKE wildcards matching unrelated keys.
+        let local: Vec<LocalRow> = sqlx::query_as(
+            "SELECT o.key, o.etag, o.size_bytes, EXISTS(
+                 SELECT 1 FROM object_encryption e
+                 WHERE e.bucket_id = o.bucket_id AND e.key = o.key
+                   AND e.version_id = COALESCE(o.version_id, ?))
+             FROM objects o
+             WHERE o.bucket_id = ? AND o.is_deleted = 0 AND o.key >= ?
+             ORDER BY o.key ASC",
+        )
+        .bind(NULL_VERSION)
+        .bind(job.bucket_id)
+        .bind(&prefix)
+        .fetch_all(&*self.db)
+        .await?;
+        let mut report = ReconcileReport::default();
+        let mut divergent = Vec::new();
+        for (key, etag, size, encrypted) in local {
+            if !key.starts_with(&prefix) {
+                break;
+            }
+            report.checked += 1;
+            let copy = remote.remove(&key);
+            if encrypted {
+                report.skipped += 1;
+                continue;
+            }
+            match copy {
+                None => report.missing += 1,
+                Some(copy) if in_sync(etag.as_deref(), size, &copy) => {
+                    report.in_sync += 1;
+                    continue;
+                }
+                Some(_) => report.differing += 1,
+            }
+            divergent.push(key);
+        }
+        report.extra = remote.len() as u64;
+        if config.replicate_deletes {
+            divergent.extend(remote.into_keys());
+        }
+
+        let now = Utc::now();
+        for key in &divergent {
+            sqlx::query(
+                "INSERT INTO object_replication
+                    (bucket_id, key, change_seq, state, next_attempt_at, queued_at)
+                 VALUES (?, ?, 0, 'pending', ?, ?)
+                 ON CONFLICT(bucket_id, key) DO UPDATE SET
+                     state = 'pending',
+                     attempts = 0,
+                     next_attempt_at = excluded.next_attempt_at,
+                     last_error = NULL,
+                     queued_at = CASE WHEN object_replication.state = 'completed'
+                         THEN excluded.queued_at ELSE object_replication.queued_at END",
+            )
+            .bind(job.bucket_id)
+            .bind(key)
+            .bind(now)
+            .bind(now)
+            .execute(&*self.db)
+            .await?;
+        }
+        report.requeued = divergent.len() as u64;
+        let json = serde_json::to_string(&report)
+            .map_err(|err| StorageError::InvalidContent(err.to_string()))?;
+        sqlx::query("UPDATE jobs SET processed = ?, report = ? WHERE id = ?")
+            .bind(report.checked as i64)
+            .bind(json)
+            .bind(job.id)
+            .execute(&*self.db)
+            .await?;
+        info!(
+            "job {}: {} of {} object(s) in sync with {}, {} key(s) requeued",
+            job.id, report.in_sync, report.checked, config.destination, report.requeued
+        );
+        Ok(())
+    }
+
+    async fn replication_config(
+        &self,
+        bucket_id: Uuid,
+    ) -> StorageResult<Option<ReplicationConfig>> {
+        let row: Option<(String,)> =
+            sqlx::query_as("SELECT config FROM bucket_replication WHERE bucket_id = ?")
+                .bind(bucket_id)
+                .fetch_optional(&*self.db)
+                .await?;
+        row.map(|(json,)| parse_config(&json)).transpose()
+    }
+}
+
+/// Serializes a configuration with its secret key, for storage.
+struct StoredConfig<'a>(&'a ReplicationConfig);
+
+impl Serialize for StoredConfig<'_> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        let mut value = serde_json::to_value(self.0).map_err(serde::ser::Error::custom)?;
+        value["secret_key"] = self.0.secret_key.clone().into();
+        value.serialize(serializer)
+    }
+}
+
+/// Whether `copy` holds the payload of a local object with `etag` and
+/// `size`. Multipart ETags (`md5-N`) depend on the part sizes, which a
+/// single PUT to the destination does not keep, so those are compared by
+/// size.
+fn in_sync(etag: Option<&str>, size: i64, copy: &RemoteObject) -> bool {
+    match etag {
+        Some(etag) if !etag.contains('-') => etag == copy.etag,
+        _ => size.max(0) as u64 == copy.size,
+    }
+}
+
+fn parse_config(json: &str) -> StorageResult<ReplicationConfig> {
+    serde_json::from_str(json).map_err(|err| {
+        StorageError::InvalidReplication(format!("stored configuration is unreadable: {}", err))
+    })
+}
+
+/// Queues changed keys and replicates them.
+#[derive(Clone)]
+pub struct Replicator {
+    service: StorageService,
+    client: Client,
+}
+
+impl Replicator {
+    /// Build a replicator whose requests go through the shared outbound
+    /// client factory.
+    pub fn new(service: StorageService, outbound: &OutboundHttp) -> reqwest::Result<Self> {
+        let client = outbound.client_builder().build()?;
+        Ok(Self { service, client })
+    }
+
+    /// Queue new changes, then replicate every due key.
+    pub async fn run_once(&self) -> StorageResult<ReplicationSummary> {
+        let mut summary = ReplicationSummary {
+            queued: self.service.queue_replication().await?,
+            ..ReplicationSummary::default()
+        };
+        let now = Utc::now();
+        let due: Vec<DueRow> = sqlx::query_as(
+            "SELECT r.bucket_id, b.name, r.key, r.change_seq, r.attempts
+             FROM object_replication r JOIN buckets b ON b.id = r.bucket_id
+             WHERE r.state = 'pending' AND r.next_attempt_at <= ?
+             ORDER BY r.next_attempt_at LIMIT ?",
+        )
+        .bind(now)
+        .bind(BATCH)
+        .fetch_all(&*self.service.db)
+        .await?;
+        if due.is_empty() {
+            return Ok(summary);
+        }
+
+        let mut stores: HashMap<Uuid, Result<RemoteS3Store, String>> = HashMap::new();
+        for (bucket_id, ..) in &due {
+            if !stores.contains_key(bucket_id) {
+                let store = match self.service.replication_config(*bucket_id).await? {
+                    Some(config) => config.store(self.client.clone()),
+                    None => Err("replication is not configured".into()),
+                };
+                stores.insert(*bucket_id, store);
+            }
+        }
+        let outcomes: Vec<(DueRow, Result<(), Failure>)> = stream::iter(due)
+            .map(|row| {
+                let store = &stores[&row.0];
+                async move {
+                    let result = match store {
+                        Ok(store) => self.replicate(store, &row.1, &row.2).await,
+                        Err(err) => Err(Failure::Retry(err.clone())),
+                    };
+                    (row, result)
+                }
+            })
+            .buffer_unordered(CONCURRENT_REPLICATIONS)
+            .collect()
+            .await;
+
+        for ((bucket_id, bucket, key, change_seq, attempts), result) in outcomes {
+            let attempts = attempts + 1;
+            let (state, next, error) = match result {
+                Ok(()) => {
+                    summary.replicated += 1;
+                    (ReplicationState::Completed, now, None)
+                }
+                Err(Failure::Retry(err)) if attempts < MAX_REPLICATION_ATTEMPTS => {
+                    debug!(
+                        "replicating {}/{} failed (attempt {}): {}",
+                        bucket, key, attempts, err
+                    );
+                    summary.retried += 1;
+                    let delay = ChronoDuration::from_std(retry_delay(attempts))
+                        .unwrap_or(ChronoDuration::MAX);
+                    (ReplicationState::Pending, now + delay, Some(err))
+                }
+                Err(Failure::Retry(err) | Failure::Permanent(err)) => {
+                    warn!(
+                        "giving up replicating {}/{} after {} attempt(s): {}",
+                        bucket, key, attempts, err
+                    );
+                    summary.failed += 1;
+                    (ReplicationState::Failed, now, Some(err))
+                }
+            };
+            // A key changed meanwhile has been requeued and keeps its state.
+            sqlx::query(
+                "UPDATE object_replication
+                 SET state = ?, attempts = ?, next_attempt_at = ?, last_error = ?,
+                     replicated_at = CASE WHEN ? THEN ? ELSE replicated_at END
+                 WHERE bucket_id = ? AND key = ? AND change_seq = ?",
+            )
+            .bind(state.as_str())
+            .bind(attempts)
+            .bind(next)
+            .bind(error)
+            .bind(state == ReplicationState::Completed)
+            .bind(now)
+            .bind(bucket_id)
+            .bind(&key)
+            .bind(change_seq)
+            .execute(&*self.service.db)
+            .await?;
+        }
+        Ok(summary)
+    }
+
+    /// Bring `key` at the destination to its current state here.
+    async fn replicate(
+        &self,
+        store: &RemoteS3Store,
+        bucket: &str,
+        key: &str,
+    ) -> Result<(), Failure> {
+        let (object, file) = match self.service.get_object_reader(bucket, key).await {
+            Ok(found) => found,
+            Err(StorageError::ObjectNotFound { .. }) => {
+                return store
+                    .delete(key)
+                    .await
+                    .map_err(|err| Failure::Retry(err.to_string()));
+            }
+            Err(err) => return Err(Failure::Retry(err.to_string())),
+        };
+        let encrypted = self
+            .service
+            .object_encryption(&object)
+            .await
+            .map_err(|err| Failure::Retry(err.to_string()))?;
+        if encrypted.is_some() {
+            return Err(Failure::Permanent(
+                "objects encrypted with a customer key are not replicated".into(),
+            ));
+        }
+        let mut headers: Vec<(String, String)> = [
+            ("content-type", &object.content_type),
+            ("content-encoding", &object.content_encoding),
+            ("cache-control", &object.cache_control),
+            ("content-disposition", &object.content_disposition),
+            ("expires", &object.expires),
+        ]
+        .into_iter()
+        .filter_map(|(name, value)| Some((name.to_string(), value.clone()?)))
+        .collect();
+        let metadata = self
+            .service
+            .user_metadata(&object)
+            .await
+            .map_err(|err| Failure::Retry(err.to_string()))?;
+        headers.extend(
+            metadata
+                .into_iter()
+                .map(|entry| (format!("x-amz-meta-{}", entry.name), entry.value)),
+        );
+        store
+            .put_file(key, file, &headers)
+            .await
+            .map_err(|err| Failure::Retry(err.to_string()))
+    }
+}
+
+/// Why a key could not be replicated.
+enum Failure {
+    /// Worth another attempt.
+    Retry(String),
+    /// Will fail until the key changes.
+    Permanent(String),
+}
+
+/// Run the replicator every `period`.
+pub fn spawn_replicator(replicator: Replicator, period: Duration) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(period);
+        loop {
+            ticker.tick().await;
+            if let Err(err) = replicator.run_once().await {
+                warn!("replication pass failed: {}", err);
+            }
+        }
+    })
+}
+
+/// Wait before attempt `attempts + 1`.
+fn retry_delay(attempts: i64) -> Duration {
+    let doublings = attempts.clamp(1, 32) as u32 - 1;
+    RETRY_BASE
+        .checked_mul(1 << doublings)
+        .unwrap_or(RETRY_MAX)
+        .min(RETRY_MAX)
+}
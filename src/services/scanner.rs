@@ -0,0 +1,637 @@
+//! Content scanning of uploads.
+//!
+//! Deployments accepting untrusted uploads can have payloads checked by an
+//! external scanner (`StorageOptions::scanner`, `OBJECT_STORE_SCANNER`):
+//!
+//! - `clamd:HOST:PORT` streams the payload to a ClamAV daemon (`INSTREAM`);
+//! - `http:URL` (or a bare `http(s)://` URL) POSTs it to a service that
+//!   answers `{"infected": bool, "signature": "..."}`. ICAP servers are
+//!   reached through such an adapter.
+//!
+//! Each bucket picks how its uploads are scanned with the `scan_uploads`
+//! setting (`PATCH /admin/buckets/{bucket}` or a bucket template):
+//!
+//! - `inline`: `commit_payload` scans the staged payload before it becomes
+//!   visible. A positive refuses the write with `ContentRejected` (403); a
+//!   scanner that cannot be reached fails the write rather than letting it
+//!   through.
+//! - `async`: the write succeeds at once and the new version is queued in
+//!   `object_scans`. `spawn_scan_worker` scans queued versions and
+//!   quarantines positives: GET (and copies) of a quarantined version answer
+//!   `ObjectQuarantined` (403), while HEAD and listings still show it.
+//!   Versions are readable while they wait; failed scans are retried.
+//!
+//! Payloads encrypted with a customer key cannot be scanned, so SSE-C writes
+//! to a scanning bucket are refused. `GET /admin/buckets/{bucket}/quarantine`
+//! lists quarantined versions and `DELETE` on it releases one after review.
+
+use crate::{
+    models::{bucket::Bucket, object::Object},
+    services::{
+        events::EventKind,
+        outbound::OutboundHttp,
+        storage_service::{BUCKET_COLUMNS, StorageError, StorageResult, StorageService},
+        versioning::NULL_VERSION,
+    },
+};
+use chrono::{DateTime, Utc};
+use futures::future::BoxFuture;
+use reqwest::{Client, Url, header};
+use serde::{Deserialize, Serialize};
+use sqlx::{Sqlite, Transaction};
+use std::{fmt, io, path::Path, str::FromStr, sync::Arc, time::Duration};
+use tokio::{
+    fs::File,
+    io::{AsyncReadExt, AsyncWriteExt},
+    net::TcpStream,
+    task::JoinHandle,
+};
+use tokio_util::io::ReaderStream;
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+/// How often `spawn_scan_worker` looks for queued versions.
+pub const SCAN_POLL: Duration = Duration::from_secs(5);
+
+/// Queued versions scanned per pass.
+const SCAN_BATCH: i64 = 64;
+
+/// Bytes sent per `INSTREAM` chunk.
+const CLAMD_CHUNK: usize = 64 * 1024;
+
+/// When a bucket's uploads are scanned.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum ScanMode {
+    /// Before the write completes; positives are refused.
+    Inline,
+    /// After the write; positives are quarantined.
+    Async,
+}
+
+impl ScanMode {
+    pub fn parse(value: &str) -> StorageResult<Self> {
+        match value {
+            "inline" => Ok(ScanMode::Inline),
+            "async" => Ok(ScanMode::Async),
+            other => Err(StorageError::InvalidContent(format!(
+                "unknown scan mode `{}` (expected `inline` or `async`)",
+                other
+            ))),
+        }
+    }
+
+    pub fn as_str(&self) -> &'static str {
+        match self {
+            ScanMode::Inline => "inline",
+            ScanMode::Async => "async",
+        }
+    }
+
+    /// The mode `bucket` scans uploads with. An unknown stored value scans
+    /// inline, the strictest mode.
+    pub fn of(bucket: &Bucket) -> Option<Self> {
+        bucket
+            .scan_uploads
+            .as_deref()
+            .map(|mode| ScanMode::parse(mode).unwrap_or(ScanMode::Inline))
+    }
+}
+
+/// What the scanner made of a payload.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScanVerdict {
+    Clean,
+    /// Flagged, with the signature (or reason) the scanner reported.
+    Infected(String),
+}
+
+/// Something payloads can be checked by.
+pub trait ContentScanner: Send + Sync + fmt::Debug {
+    /// Scan the payload read from `payload`, from its current position.
+    fn scan(&self, payload: File) -> BoxFuture<'_, io::Result<ScanVerdict>>;
+}
+
+/// `clamd:HOST:PORT` or `http:URL` as given on the command line or in the
+/// environment.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum ScannerEndpoint {
+    Clamd(String),
+    Http(Url),
+}
+
+impl FromStr for ScannerEndpoint {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let s = s.trim();
+        match s.split_once(':') {
+            Some((kind, addr)) if kind.eq_ignore_ascii_case("clamd") => {
+                match addr.rsplit_once(':') {
+                    Some((host, port)) if !host.is_empty() && port.parse::<u16>().is_ok() => {
+                        Ok(ScannerEndpoint::Clamd(addr.to_string()))
+                    }
+                    _ => Err(format!("scanner `{}` must look like clamd:HOST:PORT", s)),
+                }
+            }
+            Some((kind, rest)) => {
+                // `http:URL`; a bare URL is an HTTP scanner too.
+                let url = if kind.eq_ignore_ascii_case("http") && !rest.starts_with("//") {
+                    rest
+                } else {
+                    s
+                };
+                let url = Url::parse(url).map_err(|err| format!("scanner `{}`: {}", s, err))?;
+                if !matches!(url.scheme(), "http" | "https") {
+                    return Err(format!(
+                        "scanner `{}` must be clamd:HOST:PORT or http:URL",
+                        s
+                    ));
+                }
+                Ok(ScannerEndpoint::Http(url))
+            }
+            None => Err(format!(
+                "scanner `{}` must be clamd:HOST:PORT or http:URL",
+                s
+            )),
+        }
+    }
+}
+
+impl fmt::Display for ScannerEndpoint {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            ScannerEndpoint::Clamd(addr) => write!(f, "clamd:{}", addr),
+            ScannerEndpoint::Http(url) => write!(f, "http:{}", url),
+        }
+    }
+}
+
+impl ScannerEndpoint {
+    /// Build the scanner for this endpoint; each scan may take `timeout`.
+    pub fn connect(
+        &self,
+        outbound: &OutboundHttp,
+        timeout: Duration,
+    ) -> reqwest::Result<Arc<dyn ContentScanner>> {
+        Ok(match self {
+            ScannerEndpoint::Clamd(addr) => Arc::new(ClamdScanner {
+                addr: addr.clone(),
+                timeout,
+            }),
+            ScannerEndpoint::Http(url) => Arc::new(HttpScanner {
+                client: outbound.client_builder().timeout(timeout).build()?,
+                url: url.clone(),
+            }),
+        })
+    }
+}
+
+/// A ClamAV daemon reached over TCP.
+#[derive(Debug)]
+pub struct ClamdScanner {
+    addr: String,
+    timeout: Duration,
+}
+
+impl ClamdScanner {
+    async fn instream(&self, mut payload: File) -> io::Result<ScanVerdict> {
+        let mut conn = TcpStream::connect(&self.addr).await?;
+        conn.write_all(b"zINSTREAM\0").await?;
+        let mut buf = vec![0u8; CLAMD_CHUNK];
+        loop {
+            let n = payload.read(&mut buf).await?;
+            if n == 0 {
+                break;
+            }
+            conn.write_all(&(n as u32).to_be_bytes()).await?;
+            conn.write_all(&buf[..n]).await?;
+        }
+        conn.write_all(&0u32.to_be_bytes()).await?;
+        let mut reply = Vec::new();
+        conn.read_to_end(&mut reply).await?;
+        parse_clamd_reply(&reply)
+    }
+}
+
+impl ContentScanner for ClamdScanner {
+    fn scan(&self, payload: File) -> BoxFuture<'_, io::Result<ScanVerdict>> {
+        Box::pin(async move {
+            tokio::time::timeout(self.timeout, self.instream(payload))
+                .await
+                .map_err(|_| {
+                    io::Error::new(
+                        io::ErrorKind::TimedOut,
+                        format!("clamd at {} did not answer in time", self.addr),
+                    )
+                })?
+        })
+    }
+}
+
+/// `stream: OK`, `stream: <signature> FOUND` or an error message.
+fn parse_clamd_reply(reply: &[u8]) -> io::Result<ScanVerdict> {
+    let reply = String::from_utf8_lossy(reply);
+    let reply = reply.trim_end_matches(['\0', '\n']).trim();
+    let result = reply.strip_prefix("stream:").map_or(reply, str::trim);
+    if result == "OK" {
+        return Ok(ScanVerdict::Clean);
+    }
+    match result.strip_suffix(" FOUND") {
+        Some(signature) => Ok(ScanVerdict::Infected(signature.trim().to_string())),
+        None => Err(io::Error::other(format!("clamd: {}", reply))),
+    }
+}
+
+/// A scanning service reached over HTTP.
+pub struct HttpScanner {
+    client: Client,
+    url: Url,
+}
+
+impl fmt::Debug for HttpScanner {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("HttpScanner")
+            .field("host", &self.url.host_str().unwrap_or("?"))
+            .finish_non_exhaustive()
+    }
+}
+
+/// Reply of an HTTP scanner.
+#[derive(Debug, Deserialize)]
+struct HttpVerdict {
+    infected: bool,
+    #[serde(default)]
+    signature: Option<String>,
+}
+
+impl ContentScanner for HttpScanner {
+    fn scan(&self, payload: File) -> BoxFuture<'_, io::Result<ScanVerdict>> {
+        Box::pin(async move {
+            let len = payload.metadata().await?.len();
+            let response = self
+                .client
+                .post(self.url.clone())
+                .header(header::CONTENT_TYPE, "application/octet-stream")
+                .header(header::CONTENT_LENGTH, len)
+                .body(reqwest::Body::wrap_stream(ReaderStream::new(payload)))
+                .send()
+                .await
+                .map_err(io::Error::other)?;
+            if !response.status().is_success() {
+                return Err(io::Error::other(format!(
+                    "scanner answered {}",
+                    response.status()
+                )));
+            }
+            let verdict: HttpVerdict = response.json().await.map_err(io::Error::other)?;
+            Ok(match verdict {
+                HttpVerdict {
+                    infected: true,
+                    signature,
+                } => ScanVerdict::Infected(signature.unwrap_or_else(|| "unknown".into())),
+                HttpVerdict {
+                    infected: false, ..
+                } => ScanVerdict::Clean,
+            })
+        })
+    }
+}
+
+/// A version the scanner flagged.
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct QuarantinedObject {
+    pub key: String,
+    pub version_id: String,
+    pub signature: Option<String>,
+    pub scanned_at: DateTime<Utc>,
+}
+
+/// Outcome of one `run_pending_scans` pass.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq, Serialize)]
+pub struct ScanSummary {
+    pub scanned: u64,
+    pub quarantined: u64,
+    pub failed: u64,
+}
+
+#[derive(Debug, sqlx::FromRow)]
+struct PendingScan {
+    bucket: String,
+    key: String,
+    version_id: String,
+    scan_id: String,
+}
+
+impl StorageService {
+    /// Set how uploads to bucket `name` are scanned; `None` stops scanning.
+    /// Versions already quarantined stay quarantined.
+    pub async fn set_bucket_scan_uploads(
+        &self,
+        name: &str,
+        mode: Option<ScanMode>,
+    ) -> StorageResult<Bucket> {
+        self.ensure_bucket_name_safe(name)?;
+        if mode.is_some() {
+            self.ensure_scanner_configured()?;
+        }
+        let updated = sqlx::query_as::<_, Bucket>(&format!(
+            "UPDATE buckets SET scan_uploads = ? WHERE name = ? RETURNING {BUCKET_COLUMNS}"
+        ))
+        .bind(mode.map(|mode| mode.as_str()))
+        .bind(name)
+        .fetch_one(&*self.db)
+        .await
+        .map_err(|err| match err {
+            sqlx::Error::RowNotFound => StorageError::BucketNotFound(name.to_string()),
+            other => StorageError::Sqlx(other),
+        })?;
+        self.events.bucket(
+            EventKind::BucketConfigChanged,
+            name,
+            Some(format!(
+                "scan_uploads={}",
+                mode.map_or("", |mode| mode.as_str())
+            )),
+        );
+        Ok(updated)
+    }
+
+    /// Refuse to scan uploads when no scanner is configured.
+    pub(crate) fn ensure_scanner_configured(&self) -> StorageResult<()> {
+        if self.options.scanner.is_none() {
+            return Err(StorageError::InvalidBucketState(
+                "uploads cannot be scanned: no content scanner is configured".into(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// Scan the staged payload at `path` of a write to `key` when `bucket`
+    /// scans inline, and return the bucket's scan mode. `encrypted` payloads
+    /// are refused by scanning buckets.
+    pub(crate) async fn screen_staged(
+        &self,
+        bucket: &Bucket,
+        key: &str,
+        path: &Path,
+        encrypted: bool,
+    ) -> StorageResult<Option<ScanMode>> {
+        let Some(mode) = ScanMode::of(bucket) else {
+            return Ok(None);
+        };
+        if encrypted {
+            return Err(StorageError::InvalidBucketState(format!(
+                "bucket `{}` scans uploads, which rules out customer-key encryption",
+                bucket.name
+            )));
+        }
+        if mode == ScanMode::Inline {
+            let verdict = self.scan_file(File::open(path).await?).await?;
+            if let ScanVerdict::Infected(signature) = verdict {
+                warn!(
+                    "rejected upload of `{}/{}`: {}",
+                    bucket.name, key, signature
+                );
+                return Err(StorageError::ContentRejected {
+                    key: key.to_string(),
+                    signature,
+                });
+            }
+        }
+        Ok(Some(mode))
+    }
+
+    async fn scan_file(&self, payload: File) -> StorageResult<ScanVerdict> {
+        let scanner = self.options.scanner.as_ref().ok_or_else(|| {
+            StorageError::Io(io::Error::other("no content scanner is configured"))
+        })?;
+        scanner.scan(payload).await.map_err(|err| {
+            StorageError::Io(io::Error::new(
+                err.kind(),
+                format!("content scanner: {}", err),
+            ))
+        })
+    }
+
+    /// Refuse reading `object` while it is quarantined.
+    pub(crate) async fn ensure_not_quarantined(&self, object: &Object) -> StorageResult<()> {
+        let version_id = object.version_id.as_deref().unwrap_or(NULL_VERSION);
+        let quarantined: Option<(i64,)> = sqlx::query_as(
+            "SELECT 1 FROM object_scans
+             WHERE bucket_id = ? AND key = ? AND version_id = ? AND quarantined = 1",
+        )
+        .bind(object.bucket_id)
+        .bind(&object.key)
+        .bind(version_id)
+        .fetch_optional(&*self.db)
+        .await?;
+        match quarantined {
+            Some(_) => Err(StorageError::ObjectQuarantined {
+                key: object.key.clone(),
+                version_id: version_id.to_string(),
+            }),
+            None => Ok(()),
+        }
+    }
+
+    /// Scan versions queued by `async` buckets, oldest first, and
+    /// quarantine the ones flagged. Versions that are gone are dropped from
+    /// the queue; failures stay queued for the next pass.
+    pub async fn run_pending_scans(&self) -> StorageResult<ScanSummary> {
+        let mut summary = ScanSummary::default();
+        if self.options.scanner.is_none() {
+            return Ok(summary);
+        }
+        let pending = sqlx::query_as::<_, PendingScan>(
+            "SELECT b.name AS bucket, s.key, s.version_id, s.scan_id
+             FROM object_scans s JOIN buckets b ON b.id = s.bucket_id
+             WHERE s.scanned_at IS NULL
+             ORDER BY s.attempts, s.queued_at LIMIT ?",
+        )
+        .bind(SCAN_BATCH)
+        .fetch_all(&*self.db)
+        .await?;
+        for scan in pending {
+            let opened = self
+                .get_object_version_reader(&scan.bucket, &scan.key, &scan.version_id)
+                .await;
+            let verdict = match opened {
+                Ok((_, file)) => self.scan_file(file).await,
+                Err(
+                    StorageError::BucketNotFound(_)
+                    | StorageError::ObjectNotFound { .. }
+                    | StorageError::NoSuchVersion { .. }
+                    | StorageError::VersionIsDeleteMarker { .. },
+                ) => {
+                    sqlx::query("DELETE FROM object_scans WHERE scan_id = ?")
+                        .bind(&scan.scan_id)
+                        .execute(&*self.db)
+                        .await?;
+                    continue;
+                }
+                Err(err) => Err(err),
+            };
+            let verdict = match verdict {
+                Ok(verdict) => verdict,
+                Err(err) => {
+                    warn!(
+                        "scan of `{}/{}` ({}) failed: {}",
+                        scan.bucket, scan.key, scan.version_id, err
+                    );
+                    sqlx::query(
+                        "UPDATE object_scans SET attempts = attempts + 1 WHERE scan_id = ?",
+                    )
+                    .bind(&scan.scan_id)
+                    .execute(&*self.db)
+                    .await?;
+                    summary.failed += 1;
+                    continue;
+                }
+            };
+            let signature = match &verdict {
+                ScanVerdict::Clean => None,
+                ScanVerdict::Infected(signature) => Some(signature.as_str()),
+            };
+            // A rewrite of the version while it was scanned replaced the
+            // row; its new payload is scanned on the next pass.
+            let recorded = sqlx::query(
+                "UPDATE object_scans SET scanned_at = ?, quarantined = ?, signature = ?
+                 WHERE scan_id = ?",
+            )
+            .bind(Utc::now())
+            .bind(signature.is_some())
+            .bind(signature)
+            .bind(&scan.scan_id)
+            .execute(&*self.db)
+            .await?
+            .rows_affected()
+                > 0;
+            summary.scanned += 1;
+            match signature {
+                Some(signature) if recorded => {
+                    warn!(
+                        "quarantined `{}/{}` ({}): {}",
+                        scan.bucket, scan.key, scan.version_id, signature
+                    );
+                    self.events.object(
+                        EventKind::ObjectQuarantined,
+                        &scan.bucket,
+                        &scan.key,
+                        None,
+                        None,
+                    );
+                    summary.quarantined += 1;
+                }
+                _ => debug!(
+                    "scanned `{}/{}` ({}): clean",
+                    scan.bucket, scan.key, scan.version_id
+                ),
+            }
+        }
+        Ok(summary)
+    }
+
+    /// Quarantined versions of `bucket`, by key.
+    pub async fn list_quarantined(&self, bucket: &str) -> StorageResult<Vec<QuarantinedObject>> {
+        let bucket_rec = self.fetch_bucket(bucket).await?;
+        Ok(sqlx::query_as::<_, QuarantinedObject>(
+            "SELECT key, version_id, signature, scanned_at FROM object_scans
+             WHERE bucket_id = ? AND quarantined = 1
+             ORDER BY key, scanned_at",
+        )
+        .bind(bucket_rec.id)
+        .fetch_all(&*self.db)
+        .await?)
+    }
+
+    /// Make a quarantined version of `key` (the current one when
+    /// `version_id` is `None`) readable again.
+    pub async fn release_quarantine(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: Option<&str>,
+    ) -> StorageResult<()> {
+        self.ensure_key_safe(key)?;
+        let bucket_rec = self.fetch_bucket(bucket).await?;
+        let version_id = match version_id {
+            Some(version_id) => version_id.to_string(),
+            None => self
+                .fetch_object(&bucket_rec, key)
+                .await?
+                .version_id
+                .unwrap_or_else(|| NULL_VERSION.to_string()),
+        };
+        let released = sqlx::query(
+            "UPDATE object_scans SET quarantined = 0
+             WHERE bucket_id = ? AND key = ? AND version_id = ? AND quarantined = 1",
+        )
+        .bind(bucket_rec.id)
+        .bind(key)
+        .bind(&version_id)
+        .execute(&*self.db)
+        .await?
+        .rows_affected();
+        if released == 0 {
+            return Err(StorageError::NoSuchVersion {
+                key: key.to_string(),
+                version_id,
+            });
+        }
+        info!(
+            "released `{}/{}` ({}) from quarantine",
+            bucket, key, version_id
+        );
+        Ok(())
+    }
+}
+
+/// Record the scan state of a version written in `tx`: queued when
+/// `queue`, otherwise none (which also clears an earlier quarantine of a
+/// rewritten version).
+pub(crate) async fn replace_scan(
+    tx: &mut Transaction<'_, Sqlite>,
+    bucket_id: Uuid,
+    key: &str,
+    version_id: &str,
+    queue: bool,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM object_scans WHERE bucket_id = ? AND key = ? AND version_id = ?")
+        .bind(bucket_id)
+        .bind(key)
+        .bind(version_id)
+        .execute(&mut **tx)
+        .await?;
+    if queue {
+        sqlx::query(
+            "INSERT INTO object_scans (bucket_id, key, version_id, scan_id, queued_at)
+             VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(bucket_id)
+        .bind(key)
+        .bind(version_id)
+        .bind(Uuid::new_v4().to_string())
+        .bind(Utc::now())
+        .execute(&mut **tx)
+        .await?;
+    }
+    Ok(())
+}
+
+/// Spawn a background task that scans queued versions every `period`.
+pub fn spawn_scan_worker(service: StorageService, period: Duration) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(period);
+        loop {
+            ticker.tick().await;
+            match service.run_pending_scans().await {
+                Ok(summary) if summary.quarantined > 0 || summary.failed > 0 => info!(
+                    "scanned {} uploads: {} quarantined, {} failed",
+                    summary.scanned, summary.quarantined, summary.failed
+                ),
+                Ok(_) => {}
+                Err(err) => warn!("scan worker failed: {}", err),
+            }
+        }
+    })
+}
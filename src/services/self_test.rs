@@ -0,0 +1,214 @@
+//! End-to-end smoke test of a running server (`--self-test`).
+//!
+//! `/readyz` shows the process is up; it does not show that a request can
+//! make it through the middleware stack, reach the database and the payload
+//! directory and come back. With `--self-test`, `main` runs `run_self_test`
+//! against the bound listener before settling into serving, and exits
+//! non-zero if any step fails, so a deployment pipeline can fail fast.
+//!
+//! The test creates a bucket named `self-test-{random}`, writes, reads back
+//! and deletes an object in it, checks the object is gone and deletes the
+//! bucket (forcibly, if a step failed half way). When `--access-keys` has
+//! a secret for `SELF_TEST_PRINCIPAL`, requests are SigV4-signed with it,
+//! so a delegated authorizer can allow that principal for those buckets;
+//! otherwise they reach it as `anonymous`. When the admin API requires an
+//! identity, the test also checks that an anonymous admin request is
+//! refused.
+
+use crate::services::{
+    aws_chunked::AccessKeys,
+    blob_store::{S3Credentials, sigv4_headers},
+};
+use chrono::Utc;
+use reqwest::{Client, Method, StatusCode, Url};
+use std::time::Duration;
+use uuid::Uuid;
+
+/// Access key the self-test's requests are made as.
+pub const SELF_TEST_PRINCIPAL: &str = "object-store-self-test";
+
+/// Time allowed for each self-test request.
+const SELF_TEST_TIMEOUT: Duration = Duration::from_secs(10);
+
+/// Run the smoke test against the server at `endpoint` (e.g.
+/// `http://127.0.0.1:3000` or `https://...`). `admin_auth` says whether the
+/// admin API should refuse anonymous callers; requests are signed when
+/// `keys` has `SELF_TEST_PRINCIPAL`. The error names the failed step.
+pub async fn run_self_test(
+    endpoint: &str,
+    admin_auth: bool,
+    keys: &AccessKeys,
+) -> Result<(), String> {
+    let test = SelfTest {
+        http: Client::builder()
+            .no_proxy()
+            // The server's own certificate need not name the loopback
+            // address it is reached on.
+            .danger_accept_invalid_certs(true)
+            .timeout(SELF_TEST_TIMEOUT)
+            .build()
+            .map_err(|err| format!("building HTTP client: {}", err))?,
+        endpoint: endpoint.trim_end_matches('/').to_string(),
+        bucket: format!("self-test-{}", &Uuid::new_v4().simple().to_string()[..12]),
+        credentials: keys.secret(SELF_TEST_PRINCIPAL).map(|secret| {
+            format!("{}:{}", SELF_TEST_PRINCIPAL, secret)
+                .parse()
+                .expect("configured access keys are valid credentials")
+        }),
+    };
+    let result = test.run(admin_auth).await;
+    if result.is_err() {
+        // Best effort; the original failure is what matters.
+        let _ = test
+            .request(
+                Method::DELETE,
+                &format!("/{}?force=true", test.bucket),
+                None,
+            )
+            .await;
+    }
+    result
+}
+
+struct SelfTest {
+    http: Client,
+    endpoint: String,
+    bucket: String,
+    credentials: Option<S3Credentials>,
+}
+
+impl SelfTest {
+    async fn run(&self, admin_auth: bool) -> Result<(), String> {
+        let bucket = format!("/{}", self.bucket);
+        let object = format!("{}/probe", bucket);
+        let payload = Uuid::new_v4().to_string().into_bytes();
+
+        self.expect(
+            "create bucket",
+            Method::PUT,
+            &bucket,
+            Some(("application/json", b"null".to_vec())),
+            StatusCode::OK,
+        )
+        .await?;
+        self.expect(
+            "put object",
+            Method::PUT,
+            &object,
+            Some(("application/octet-stream", payload.clone())),
+            StatusCode::OK,
+        )
+        .await?;
+        let read = self
+            .expect("get object", Method::GET, &object, None, StatusCode::OK)
+            .await?;
+        if read != payload {
+            return Err(format!(
+                "get object: read back {} bytes that differ from the {} written",
+                read.len(),
+                payload.len()
+            ));
+        }
+        self.expect(
+            "delete object",
+            Method::DELETE,
+            &object,
+            None,
+            StatusCode::NO_CONTENT,
+        )
+        .await?;
+        self.expect(
+            "get deleted object",
+            Method::GET,
+            &object,
+            None,
+            StatusCode::NOT_FOUND,
+        )
+        .await?;
+        self.expect(
+            "delete bucket",
+            Method::DELETE,
+            &bucket,
+            None,
+            StatusCode::NO_CONTENT,
+        )
+        .await?;
+
+        if admin_auth {
+            let status = self
+                .http
+                .get(format!("{}/admin/whoami", self.endpoint))
+                .send()
+                .await
+                .map_err(|err| format!("anonymous admin request: {}", err))?
+                .status();
+            if status != StatusCode::UNAUTHORIZED && status != StatusCode::FORBIDDEN {
+                return Err(format!(
+                    "anonymous admin request: expected 401 or 403, got {}",
+                    status
+                ));
+            }
+        }
+        Ok(())
+    }
+
+    /// Send a request and check its status, returning the body.
+    async fn expect(
+        &self,
+        step: &str,
+        method: Method,
+        path: &str,
+        body: Option<(&str, Vec<u8>)>,
+        expected: StatusCode,
+    ) -> Result<Vec<u8>, String> {
+        let response = self
+            .request(method, path, body)
+            .await
+            .map_err(|err| format!("{}: {}", step, err))?;
+        let status = response.status();
+        let body = response
+            .bytes()
+            .await
+            .map_err(|err| format!("{}: reading response: {}", step, err))?;
+        if status != expected {
+            return Err(format!(
+                "{}: expected {}, got {}: {}",
+                step,
+                expected,
+                status,
+                String::from_utf8_lossy(&body)
+            ));
+        }
+        Ok(body.to_vec())
+    }
+
+    /// Send a request with an optional `(content type, body)`.
+    async fn request(
+        &self,
+        method: Method,
+        path: &str,
+        body: Option<(&str, Vec<u8>)>,
+    ) -> reqwest::Result<reqwest::Response> {
+        let url = format!("{}{}", self.endpoint, path);
+        let signed = match (&self.credentials, Url::parse(&url)) {
+            (Some(credentials), Ok(url)) => {
+                sigv4_headers(credentials, "us-east-1", &method, &url, &[], Utc::now()).ok()
+            }
+            _ => None,
+        };
+        let mut request = self.http.request(method, url);
+        request = match signed {
+            Some(headers) => request.headers(headers),
+            None => request.header(
+                reqwest::header::AUTHORIZATION,
+                format!("AWS {}:self-test", SELF_TEST_PRINCIPAL),
+            ),
+        };
+        if let Some((content_type, body)) = body {
+            request = request
+                .header(reqwest::header::CONTENT_TYPE, content_type)
+                .body(body);
+        }
+        request.send().await
+    }
+}
@@ -0,0 +1,289 @@
+//! Scoped session tokens.
+//!
+//! Like the temporary credentials STS hands out with a session policy, a
+//! session token lets an application give a client access to one bucket,
+//! optionally only under a key prefix (`uploads/{user}/`), for a limited
+//! time. Tokens are issued by `POST /admin/sessions` and sent as
+//! `x-amz-security-token` (or `X-Amz-Security-Token` in a presigned URL).
+//!
+//! A token is `{policy}.{signature}`: the compact JSON of its
+//! [`SessionPolicy`] and an HMAC-SHA256 of that JSON made with the server's
+//! session key, both base64url without padding. Nothing is stored
+//! server-side, so a token stays valid until it expires.
+//!
+//! The authorizer enforces the policy (see `middleware::authorizer`): the
+//! token's principal replaces the one claimed in `Authorization`, requests
+//! outside the scope are refused before the policy engine is asked, and
+//! the engine still decides about the rest.
+
+use crate::{
+    middleware::{authorizer::query_param, feature_flags::ApiGroup},
+    services::storage_service::{StorageError, StorageResult, StorageService},
+};
+use base64::{Engine as _, engine::general_purpose::URL_SAFE_NO_PAD};
+use chrono::{DateTime, Duration as ChronoDuration, Utc};
+use hmac::{Hmac, Mac};
+use serde::{Deserialize, Serialize};
+use sha2::Sha256;
+use std::{fmt, str::FromStr};
+
+/// Lifetime of a token when the request names none.
+pub const DEFAULT_SESSION_SECS: u64 = 3600;
+
+/// Shortest and longest token lifetime, as for STS sessions.
+pub const MIN_SESSION_SECS: u64 = 900;
+pub const MAX_SESSION_SECS: u64 = 12 * 3600;
+
+/// Actions granted when the request names none.
+const DEFAULT_ACTIONS: [ApiGroup; 4] = [
+    ApiGroup::BucketList,
+    ApiGroup::ObjectRead,
+    ApiGroup::ObjectWrite,
+    ApiGroup::ObjectDelete,
+];
+
+/// Query parameters of the bucket-level requests known to confine what
+/// they return or remove to `prefix`: ListObjects (v1 and v2, with its
+/// extensions), `?versions`, `?deleted`, `?list-partitions`, `?list-stream`,
+/// `?changes`, `?lifecycle-dry-run` and prefix deletes. Prefix-scoped
+/// sessions are refused any other bucket-level request, since it may
+/// reveal or touch keys outside the scope.
+const PREFIX_SCOPED_PARAMS: [&str; 22] = [
+    "prefix",
+    "list-type",
+    "delimiter",
+    "max-keys",
+    "continuation-token",
+    "start-after",
+    "marker",
+    "end-key",
+    "storage-class",
+    "min-size",
+    "max-size",
+    "encoding-type",
+    "fetch-owner",
+    "versions",
+    "key-marker",
+    "version-id-marker",
+    "deleted",
+    "list-partitions",
+    "list-stream",
+    "changes",
+    "since",
+    "lifecycle-dry-run",
+];
+
+/// Whether `name` is a query parameter of request signing rather than of
+/// the operation (presigned URLs, the SDKs' `x-id`).
+fn is_signing_param(name: &str) -> bool {
+    name.len() >= 6 && name[..6].eq_ignore_ascii_case("x-amz-") || name == "x-id"
+}
+
+/// Secret used to sign and verify session tokens. `Debug` never prints it.
+#[derive(Clone)]
+pub struct SessionKey(Vec<u8>);
+
+impl fmt::Debug for SessionKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str("SessionKey(<redacted>)")
+    }
+}
+
+impl FromStr for SessionKey {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        if s.len() < 16 {
+            return Err("session token key must be at least 16 bytes".into());
+        }
+        Ok(Self(s.as_bytes().to_vec()))
+    }
+}
+
+impl SessionKey {
+    fn mac(&self) -> Hmac<Sha256> {
+        Hmac::<Sha256>::new_from_slice(&self.0).expect("HMAC accepts keys of any length")
+    }
+
+    /// Sign `policy` into a token.
+    pub fn issue(&self, policy: &SessionPolicy) -> String {
+        let json = serde_json::to_vec(policy).expect("session policy serializes");
+        let mut mac = self.mac();
+        mac.update(&json);
+        format!(
+            "{}.{}",
+            URL_SAFE_NO_PAD.encode(&json),
+            URL_SAFE_NO_PAD.encode(mac.finalize().into_bytes())
+        )
+    }
+
+    /// Check a token's signature and expiry and return its policy.
+    pub fn verify(&self, token: &str, now: DateTime<Utc>) -> Result<SessionPolicy, String> {
+        let malformed = || "malformed session token".to_string();
+        let (payload, signature) = token.split_once('.').ok_or_else(malformed)?;
+        let json = URL_SAFE_NO_PAD.decode(payload).map_err(|_| malformed())?;
+        let signature = URL_SAFE_NO_PAD.decode(signature).map_err(|_| malformed())?;
+        let mut mac = self.mac();
+        mac.update(&json);
+        mac.verify_slice(&signature)
+            .map_err(|_| "session token signature mismatch".to_string())?;
+        let policy: SessionPolicy = serde_json::from_slice(&json).map_err(|_| malformed())?;
+        if policy.expires_at <= now {
+            return Err(format!("session token expired at {}", policy.expires_at));
+        }
+        Ok(policy)
+    }
+}
+
+/// What a session token grants.
+#[derive(Debug, Clone, PartialEq, Eq, Hash, Serialize, Deserialize)]
+pub struct SessionPolicy {
+    /// Principal requests made with the token act as.
+    pub principal: String,
+    pub bucket: String,
+    /// Only keys under this prefix; the whole bucket when absent.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub prefix: Option<String>,
+    /// API groups allowed (`bucket-list`, `object-read`, ...).
+    pub actions: Vec<String>,
+    pub expires_at: DateTime<Utc>,
+}
+
+impl SessionPolicy {
+    /// Whether a request of `group` on `bucket`/`key` is in scope.
+    ///
+    /// `query` is the request's query string. With a scoped prefix,
+    /// bucket-level requests must be one of the operations in
+    /// `PREFIX_SCOPED_PARAMS` with a `prefix` under the scope; everything
+    /// else, e.g. `DeleteObjects` whose keys are only known from the body,
+    /// is refused. `copy_source` is the `bucket/key` read by a CopyObject.
+    pub fn permits(
+        &self,
+        group: ApiGroup,
+        bucket: Option<&str>,
+        key: Option<&str>,
+        query: Option<&str>,
+        copy_source: Option<(&str, &str)>,
+    ) -> Result<(), String> {
+        if !self.actions.iter().any(|action| action == group.as_str()) {
+            return Err(format!("session does not allow `{}`", group));
+        }
+        if bucket != Some(self.bucket.as_str()) {
+            return Err(format!("session is limited to bucket `{}`", self.bucket));
+        }
+        let scope = self.prefix.as_deref().unwrap_or_default();
+        if key.is_none()
+            && !scope.is_empty()
+            && let Some(param) = query
+                .unwrap_or_default()
+                .split('&')
+                .map(|pair| pair.split('=').next().unwrap_or_default())
+                .find(|name| {
+                    !name.is_empty()
+                        && !PREFIX_SCOPED_PARAMS.contains(name)
+                        && !is_signing_param(name)
+                })
+        {
+            return Err(format!(
+                "`?{}` is not available to prefix-scoped sessions",
+                param
+            ));
+        }
+        let list_prefix = query_param(query, "prefix");
+        let target = key.or(list_prefix.as_deref()).unwrap_or_default();
+        if !target.starts_with(scope) {
+            return Err(format!("session is limited to keys under `{}`", scope));
+        }
+        if let Some((source_bucket, source_key)) = copy_source
+            && (source_bucket != self.bucket || !source_key.starts_with(scope))
+        {
+            return Err(format!(
+                "copy source is outside bucket `{}` under `{}`",
+                self.bucket, scope
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Body of `POST /admin/sessions`.
+#[derive(Debug, Clone, Deserialize)]
+pub struct SessionRequest {
+    pub principal: String,
+    pub bucket: String,
+    pub prefix: Option<String>,
+    /// Defaults to listing, reading, writing and deleting objects.
+    pub actions: Option<Vec<String>>,
+    /// Defaults to `DEFAULT_SESSION_SECS`.
+    pub duration_secs: Option<u64>,
+}
+
+/// A freshly issued token and what it grants.
+#[derive(Debug, Clone, Serialize)]
+pub struct SessionToken {
+    pub session_token: String,
+    #[serde(flatten)]
+    pub policy: SessionPolicy,
+}
+
+impl StorageService {
+    /// Issue a session token for an existing bucket.
+    pub async fn issue_session_token(&self, req: SessionRequest) -> StorageResult<SessionToken> {
+        let key = self.options.session_key.as_ref().ok_or_else(|| {
+            StorageError::InvalidContent("no session token key is configured".into())
+        })?;
+        if req.principal.trim().is_empty() {
+            return Err(StorageError::InvalidContent(
+                "session principal must not be empty".into(),
+            ));
+        }
+        let duration = req.duration_secs.unwrap_or(DEFAULT_SESSION_SECS);
+        if !(MIN_SESSION_SECS..=MAX_SESSION_SECS).contains(&duration) {
+            return Err(StorageError::InvalidContent(format!(
+                "session duration must be between {} and {} seconds",
+                MIN_SESSION_SECS, MAX_SESSION_SECS
+            )));
+        }
+        let prefix = req.prefix.filter(|prefix| !prefix.is_empty());
+        if let Some(prefix) = &prefix {
+            self.ensure_prefix_safe(prefix)?;
+        }
+        let actions = match req.actions {
+            Some(actions) => actions
+                .iter()
+                .map(|action| ApiGroup::from_str(action).map_err(StorageError::InvalidContent))
+                .collect::<StorageResult<Vec<_>>>()?,
+            None => DEFAULT_ACTIONS.to_vec(),
+        };
+        if let Some(group) = actions.iter().find(|group| {
+            matches!(
+                group,
+                ApiGroup::Admin | ApiGroup::BucketCreate | ApiGroup::BucketDelete
+            )
+        }) {
+            return Err(StorageError::InvalidContent(format!(
+                "`{}` cannot be granted to a session",
+                group
+            )));
+        }
+        let bucket = self.fetch_bucket(&req.bucket).await?;
+
+        let mut names: Vec<String> = Vec::new();
+        for group in actions {
+            if !names.iter().any(|name| name == group.as_str()) {
+                names.push(group.as_str().to_string());
+            }
+        }
+        let policy = SessionPolicy {
+            principal: req.principal,
+            bucket: bucket.name,
+            prefix,
+            actions: names,
+            expires_at: Utc::now() + ChronoDuration::seconds(duration as i64),
+        };
+        Ok(SessionToken {
+            session_token: key.issue(&policy),
+            policy,
+        })
+    }
+}
@@ -0,0 +1,437 @@
+//! Shard layout of live payloads, and moving payloads between layouts.
+//!
+//! A live payload sits `depth` directories below its bucket, each named
+//! after one byte of MD5(bucket/key) in hex: `{bucket}/{ab}/{cd}/{key}` at
+//! the default depth of 2. `--shard-depth` picks another depth, and the
+//! layouts payloads may be in are recorded in `shard_layouts`.
+//!
+//! The `/`-separated segments of the key are used as file and directory
+//! names as they are, unless the layout has portable names
+//! (`--portable-filenames`, the default on Windows). Then each segment is
+//! escaped into a name NTFS accepts too: `%`, `<>:"|?*` and a trailing dot
+//! or space become `%XX`, device names (`CON`, `aux.txt`, `LPT1`) get their
+//! first letter escaped, an empty segment (`a//b`, `dir/`) is `%`, and a
+//! segment longer than 200 bytes is cut short and suffixed with `%~` and
+//! its MD5, so no key hits the 255-character name limit. Paths longer than
+//! Windows' 260 characters work because the storage directory is made
+//! absolute at startup, which the standard library extends to long paths.
+//!
+//! When the server starts with a layout other than the recorded one, the new
+//! layout becomes current and a `shard-migration` job is queued per bucket;
+//! each moves the bucket's payloads over one key at a time under the key's
+//! lock. Meanwhile payloads are written in the current layout and looked up
+//! there first, then in the previous layouts, so reads keep working without
+//! an export/import. Once every migration job has succeeded the previous
+//! layouts are forgotten. Failed jobs are queued again at the next start.
+
+use crate::{
+    models::job::Job,
+    services::{
+        jobs::JOB_COLUMNS,
+        storage_service::{StorageResult, StorageService},
+    },
+};
+use chrono::Utc;
+use std::{
+    fmt, io,
+    path::{Path, PathBuf},
+    sync::{Arc, RwLock},
+};
+use tokio::fs::{self, File};
+use tracing::{debug, info};
+use uuid::Uuid;
+
+/// Directory levels of the layout payloads were always written in.
+pub const DEFAULT_SHARD_DEPTH: usize = 2;
+
+/// Deepest layout `--shard-depth` accepts.
+pub const MAX_SHARD_DEPTH: usize = 4;
+
+/// Keys moved per batch by a migration job.
+const MIGRATE_BATCH: i64 = 500;
+
+/// Longest portable name kept as is; longer ones are shortened and hashed.
+const MAX_PORTABLE_NAME: usize = 200;
+
+/// How live payload paths are formed.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct Layout {
+    /// Shard directory levels below the bucket.
+    pub depth: usize,
+    /// Key segments escaped into names every filesystem accepts.
+    pub portable: bool,
+}
+
+impl Default for Layout {
+    fn default() -> Self {
+        Self {
+            depth: DEFAULT_SHARD_DEPTH,
+            portable: false,
+        }
+    }
+}
+
+impl fmt::Display for Layout {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "depth {}", self.depth)?;
+        if self.portable {
+            f.write_str(" with portable names")?;
+        }
+        Ok(())
+    }
+}
+
+#[derive(Debug, Default)]
+struct LayoutState {
+    current: Layout,
+    previous: Vec<Layout>,
+}
+
+/// The layout payloads are written in and those still being migrated from.
+#[derive(Debug, Clone, Default)]
+pub struct ShardLayout {
+    state: Arc<RwLock<LayoutState>>,
+}
+
+impl ShardLayout {
+    /// The layout payloads are written in.
+    pub fn current(&self) -> Layout {
+        self.state.read().unwrap().current
+    }
+
+    /// The layouts payloads are still being moved out of.
+    pub fn previous(&self) -> Vec<Layout> {
+        self.state.read().unwrap().previous.clone()
+    }
+
+    /// Whether payloads may still be in another layout.
+    pub fn is_migrating(&self) -> bool {
+        !self.state.read().unwrap().previous.is_empty()
+    }
+
+    fn set(&self, current: Layout, previous: Vec<Layout>) {
+        *self.state.write().unwrap() = LayoutState { current, previous };
+    }
+}
+
+impl StorageService {
+    /// Path of the payload of `key` in `layout`.
+    pub(crate) fn object_path_at(
+        &self,
+        volume: Option<&str>,
+        bucket_name: &str,
+        key: &str,
+        layout: Layout,
+    ) -> PathBuf {
+        let digest = md5::compute(format!("{}/{}", bucket_name, key));
+        let mut path = self.volume_root(volume).join(bucket_name);
+        for byte in &digest.0[..layout.depth] {
+            path.push(format!("{:02x}", byte));
+        }
+        // Segment by segment: `/` is not a separator in Windows' long
+        // (`\\?\`) paths.
+        for segment in key.split('/') {
+            match layout.portable {
+                true => path.push(portable_name(segment)),
+                false => path.push(segment),
+            }
+        }
+        path
+    }
+
+    /// Where a payload written to `key` now goes: always the current layout.
+    pub(crate) fn layout_path_on(
+        &self,
+        volume: Option<&str>,
+        bucket_name: &str,
+        key: &str,
+    ) -> PathBuf {
+        self.object_path_at(volume, bucket_name, key, self.shard_layout.current())
+    }
+
+    /// Open the live payload of `key`. A migration job may move it between
+    /// looking it up and opening it, so a miss is looked up once more.
+    pub(crate) async fn open_live_payload(
+        &self,
+        volume: Option<&str>,
+        bucket_name: &str,
+        key: &str,
+    ) -> io::Result<File> {
+        let path = self.object_path_on(volume, bucket_name, key);
+        match File::open(&path).await {
+            Err(err)
+                if err.kind() == io::ErrorKind::NotFound && self.shard_layout.is_migrating() =>
+            {
+                let moved = self.object_path_on(volume, bucket_name, key);
+                if moved == path {
+                    return Err(err);
+                }
+                File::open(&moved).await
+            }
+            opened => opened,
+        }
+    }
+
+    /// Make `layout` current, as `--shard-depth` and `--portable-filenames`
+    /// ask at startup, and queue a migration job per bucket when it changed.
+    /// Returns the number of jobs queued.
+    pub async fn adopt_shard_layout(&self, layout: Layout) -> StorageResult<u64> {
+        let layouts: Vec<(i64, bool, bool)> = sqlx::query_as(
+            "SELECT depth, portable, current FROM shard_layouts ORDER BY depth, portable",
+        )
+        .fetch_all(&*self.db)
+        .await?;
+        let layouts: Vec<(Layout, bool)> = layouts
+            .into_iter()
+            .map(|(depth, portable, current)| {
+                let depth = depth as usize;
+                (Layout { depth, portable }, current)
+            })
+            .collect();
+        let recorded = layouts
+            .iter()
+            .find(|(_, current)| *current)
+            .map_or(Layout::default(), |(layout, _)| *layout);
+        let previous: Vec<Layout> = layouts
+            .iter()
+            .map(|(other, _)| *other)
+            .filter(|other| *other != layout)
+            .collect();
+        self.shard_layout.set(layout, previous.clone());
+
+        let mut queued = 0;
+        if layout != recorded {
+            let now = Utc::now();
+            let mut tx = self.db.begin().await?;
+            sqlx::query("UPDATE shard_layouts SET current = 0")
+                .execute(&mut *tx)
+                .await?;
+            sqlx::query(
+                "INSERT INTO shard_layouts (depth, portable, current, adopted_at)
+                 VALUES (?, ?, 1, ?)
+                 ON CONFLICT(depth, portable) DO UPDATE
+                 SET current = 1, adopted_at = excluded.adopted_at",
+            )
+            .bind(layout.depth as i64)
+            .bind(layout.portable)
+            .bind(now)
+            .execute(&mut *tx)
+            .await?;
+            tx.commit().await?;
+            info!(
+                "payload layout changed from {} to {}; migrating payloads in the background",
+                recorded, layout
+            );
+            queued = self.queue_shard_migrations().await?;
+        } else if !previous.is_empty() {
+            let retried = sqlx::query(
+                "UPDATE jobs SET status = 'queued', error = NULL
+                 WHERE kind = 'shard-migration' AND status = 'failed'
+                   AND created_at >= (SELECT adopted_at FROM shard_layouts WHERE current = 1)",
+            )
+            .execute(&*self.db)
+            .await?;
+            queued = retried.rows_affected();
+            if queued > 0 {
+                self.jobs.notify();
+            }
+        }
+        self.retire_shard_layouts(None).await?;
+        Ok(queued)
+    }
+
+    /// Queue a migration job for every bucket.
+    async fn queue_shard_migrations(&self) -> StorageResult<u64> {
+        let buckets: Vec<(Uuid, String)> =
+            sqlx::query_as("SELECT id, name FROM buckets ORDER BY name")
+                .fetch_all(&*self.db)
+                .await?;
+        for (bucket_id, bucket_name) in &buckets {
+            let job = sqlx::query_as::<_, Job>(&format!(
+                "INSERT INTO jobs (id, kind, bucket_id, status, created_at)
+                 VALUES (?, 'shard-migration', ?, 'queued', ?)
+                 RETURNING {JOB_COLUMNS}"
+            ))
+            .bind(Uuid::new_v4())
+            .bind(bucket_id)
+            .bind(Utc::now())
+            .fetch_one(&*self.db)
+            .await?;
+            info!(
+                "queued job {}: migrate payloads of `{}`",
+                job.id, bucket_name
+            );
+        }
+        if !buckets.is_empty() {
+            self.jobs.notify();
+        }
+        Ok(buckets.len() as u64)
+    }
+
+    /// Forget the previous layouts once every migration job since the switch
+    /// (other than `running_job`, which is finishing) has succeeded.
+    async fn retire_shard_layouts(&self, running_job: Option<Uuid>) -> StorageResult<()> {
+        if !self.shard_layout.is_migrating() {
+            return Ok(());
+        }
+        let (unfinished,): (i64,) = sqlx::query_as(
+            "SELECT COUNT(*) FROM jobs j, shard_layouts l
+             WHERE j.kind = 'shard-migration' AND l.current = 1
+               AND j.created_at >= l.adopted_at AND j.status != 'succeeded'
+               AND (? IS NULL OR j.id != ?)",
+        )
+        .bind(running_job)
+        .bind(running_job)
+        .fetch_one(&*self.db)
+        .await?;
+        if unfinished > 0 {
+            return Ok(());
+        }
+        sqlx::query("DELETE FROM shard_layouts WHERE current = 0")
+            .execute(&*self.db)
+            .await?;
+        let layout = self.shard_layout.current();
+        self.shard_layout.set(layout, Vec::new());
+        info!("payloads migrated to the layout of {}", layout);
+        Ok(())
+    }
+
+    /// Move every live payload of the job's bucket into the current layout,
+    /// batch by batch, counting the payloads moved.
+    pub(crate) async fn run_shard_migration(&self, job: &Job) -> StorageResult<()> {
+        let bucket_name: Option<(String,)> =
+            sqlx::query_as("SELECT name FROM buckets WHERE id = ?")
+                .bind(job.bucket_id)
+                .fetch_optional(&*self.db)
+                .await?;
+        let previous = self.shard_layout.previous();
+        if let (Some((bucket_name,)), false) = (bucket_name, previous.is_empty()) {
+            let mut after = String::new();
+            loop {
+                let rows: Vec<(String, Option<String>)> = sqlx::query_as(
+                    "SELECT key, volume FROM objects
+                     WHERE bucket_id = ? AND is_deleted = 0 AND key > ?
+                     ORDER BY key LIMIT ?",
+                )
+                .bind(job.bucket_id)
+                .bind(&after)
+                .bind(MIGRATE_BATCH)
+                .fetch_all(&*self.db)
+                .await?;
+                let Some((last, _)) = rows.last() else {
+                    break;
+                };
+                after = last.clone();
+
+                let mut moved = 0i64;
+                for (key, volume) in &rows {
+                    let _key_guard = self.key_locks.lock(job.bucket_id, key).await;
+                    if self
+                        .migrate_payload(volume.as_deref(), &bucket_name, key, &previous)
+                        .await?
+                    {
+                        moved += 1;
+                    }
+                }
+                sqlx::query("UPDATE jobs SET processed = processed + ? WHERE id = ?")
+                    .bind(moved)
+                    .bind(job.id)
+                    .execute(&*self.db)
+                    .await?;
+            }
+        }
+        self.retire_shard_layouts(Some(job.id)).await
+    }
+
+    /// Move the payload of `key` out of the `previous` layouts. A copy in a
+    /// previous layout next to one in the current layout is stale (the key
+    /// was written since the switch) and removed. Returns whether it moved.
+    async fn migrate_payload(
+        &self,
+        volume: Option<&str>,
+        bucket_name: &str,
+        key: &str,
+        previous: &[Layout],
+    ) -> StorageResult<bool> {
+        let target = self.layout_path_on(volume, bucket_name, key);
+        let mut moved = false;
+        for &layout in previous {
+            let source = self.object_path_at(volume, bucket_name, key, layout);
+            // Layouts can differ only in names, so `source` may be a
+            // directory of the current one (`dir/` next to `dir/%`).
+            if source == target || !is_file(&source).await? {
+                continue;
+            }
+            if moved || is_file(&target).await? {
+                remove_stale(&source).await?;
+            } else {
+                if let Some(parent) = target.parent() {
+                    fs::create_dir_all(parent).await?;
+                }
+                fs::rename(&source, &target).await?;
+                moved = true;
+            }
+            debug!("migrated {}/{} out of {}", bucket_name, key, layout);
+            self.prune_payload_dirs(bucket_name, &source).await;
+        }
+        Ok(moved)
+    }
+}
+
+async fn is_file(path: &Path) -> io::Result<bool> {
+    match fs::metadata(path).await {
+        Ok(metadata) => Ok(metadata.is_file()),
+        Err(err)
+            if matches!(
+                err.kind(),
+                io::ErrorKind::NotFound | io::ErrorKind::NotADirectory
+            ) =>
+        {
+            Ok(false)
+        }
+        Err(err) => Err(err),
+    }
+}
+
+async fn remove_stale(path: &Path) -> io::Result<()> {
+    match fs::remove_file(path).await {
+        Err(err) if err.kind() != io::ErrorKind::NotFound => Err(err),
+        _ => Ok(()),
+    }
+}
+
+/// `segment` as a name every filesystem accepts (see the module docs).
+fn portable_name(segment: &str) -> String {
+    if segment.is_empty() {
+        return "%".to_string();
+    }
+    let stem = segment.split('.').next().unwrap_or_default();
+    let reserved = ["CON", "PRN", "AUX", "NUL"]
+        .iter()
+        .any(|name| stem.eq_ignore_ascii_case(name))
+        || ["COM", "LPT"].iter().any(|prefix| {
+            stem.len() == 4
+                && stem[..3].eq_ignore_ascii_case(prefix)
+                && matches!(stem.as_bytes()[3], b'1'..=b'9')
+        });
+    let last = segment.len() - 1;
+    let mut name = String::with_capacity(segment.len());
+    for (at, c) in segment.char_indices() {
+        let escape = matches!(c, '%' | '<' | '>' | ':' | '"' | '|' | '?' | '*' | '\\')
+            || c.is_ascii_control()
+            || (at == 0 && reserved)
+            || (at == last && matches!(c, '.' | ' '));
+        match escape {
+            true => name.push_str(&format!("%{:02X}", c as u32)),
+            false => name.push(c),
+        }
+    }
+    if name.len() > MAX_PORTABLE_NAME {
+        let mut cut = MAX_PORTABLE_NAME - 34;
+        while !name.is_char_boundary(cut) {
+            cut -= 1;
+        }
+        name = format!("{}%~{:x}", &name[..cut], md5::compute(segment));
+    }
+    name
+}
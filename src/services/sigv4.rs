@@ -0,0 +1,312 @@
+//! AWS Signature Version 4 request verification.
+//!
+//! A request signed with `AWS4-HMAC-SHA256`, in its `Authorization` header
+//! or as a presigned URL (`X-Amz-Credential`, `X-Amz-Signature`, ...), is
+//! checked against the secret `--access-keys` configures for the access key
+//! it names: the canonical request is rebuilt from the method, path, query,
+//! signed headers and `x-amz-content-sha256` (`UNSIGNED-PAYLOAD` for
+//! presigned URLs), and its signature computed with the key derived for the
+//! credential scope. Header-signed requests must be dated within 15 minutes
+//! of the server clock; presigned URLs until their `X-Amz-Expires` (at most
+//! seven days).
+//!
+//! Only the signature is checked here; uploads check that the body hashes
+//! to the `x-amz-content-sha256` it covers (see `checksum`). SigV2 (`AWS key:signature`) and SigV4a
+//! cannot be verified and count as unsigned.
+
+use crate::services::{
+    aws_chunked::{AccessKeys, hex, unhex},
+    blob_store::{KEY_ENCODE, QUERY_ENCODE, hmac_sha256},
+};
+use axum::http::{HeaderMap, Method, Uri};
+use chrono::{DateTime, Duration, NaiveDateTime, Utc};
+use hmac::{Hmac, Mac};
+use percent_encoding::{percent_decode_str, utf8_percent_encode};
+use sha2::{Digest, Sha256};
+use std::fmt;
+
+const ALGORITHM: &str = "AWS4-HMAC-SHA256";
+
+/// Largest difference between a header-signed request's date and the clock.
+const MAX_CLOCK_SKEW: Duration = Duration::minutes(15);
+
+/// Longest `X-Amz-Expires` of a presigned URL.
+const MAX_PRESIGNED_EXPIRY_SECS: i64 = 7 * 24 * 3600;
+
+/// Why a signed request was not verified.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum SignatureError {
+    /// The signature's parameters are missing or malformed.
+    Malformed(String),
+    /// The access key has no configured secret.
+    UnknownAccessKey(String),
+    /// Signed too far from now, or a presigned URL past its expiry.
+    Expired(String),
+    /// The signature is not the one the server computes.
+    Mismatch {
+        access_key: String,
+        canonical_request: String,
+        string_to_sign: String,
+    },
+}
+
+impl fmt::Display for SignatureError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            SignatureError::Malformed(message) => write!(f, "malformed signature: {}", message),
+            SignatureError::UnknownAccessKey(key) => write!(f, "unknown access key `{}`", key),
+            SignatureError::Expired(message) => f.write_str(message),
+            SignatureError::Mismatch { access_key, .. } => write!(
+                f,
+                "the request signature of access key `{}` does not match",
+                access_key
+            ),
+        }
+    }
+}
+
+/// The SigV4 parameters a request carries.
+struct Signature {
+    access_key: String,
+    /// `date/region/service/aws4_request`.
+    scope: String,
+    amz_date: String,
+    signed_headers: Vec<String>,
+    signature: String,
+    /// `X-Amz-Expires` of a presigned URL; `None` for header signatures.
+    expires: Option<i64>,
+}
+
+/// Check the SigV4 signature of a request against `keys`. Returns the
+/// verified access key, or `None` when the request carries no SigV4
+/// signature.
+pub fn verify_request(
+    keys: &AccessKeys,
+    method: &Method,
+    uri: &Uri,
+    headers: &HeaderMap,
+    now: DateTime<Utc>,
+) -> Result<Option<String>, SignatureError> {
+    let Some(signature) = parse(headers, uri.query())? else {
+        return Ok(None);
+    };
+    let secret = keys
+        .secret(&signature.access_key)
+        .ok_or_else(|| SignatureError::UnknownAccessKey(signature.access_key.clone()))?;
+    check_date(&signature, now)?;
+
+    let canonical_request = canonical_request(method, uri, headers, &signature)?;
+    let string_to_sign = format!(
+        "{}\n{}\n{}\n{}",
+        ALGORITHM,
+        signature.amz_date,
+        signature.scope,
+        hex(&Sha256::digest(canonical_request.as_bytes()))
+    );
+    let mut key = format!("AWS4{}", secret).into_bytes();
+    for part in signature.scope.split('/') {
+        key = hmac_sha256(&key, part.as_bytes());
+    }
+    let mut mac = Hmac::<Sha256>::new_from_slice(&key).expect("HMAC accepts any key length");
+    mac.update(string_to_sign.as_bytes());
+    let matches = unhex(&signature.signature).is_some_and(|sig| mac.verify_slice(&sig).is_ok());
+    if !matches {
+        return Err(SignatureError::Mismatch {
+            access_key: signature.access_key,
+            canonical_request,
+            string_to_sign,
+        });
+    }
+    Ok(Some(signature.access_key))
+}
+
+fn parse(headers: &HeaderMap, query: Option<&str>) -> Result<Option<Signature>, SignatureError> {
+    let malformed = |message: &str| SignatureError::Malformed(message.to_string());
+    let authorization = headers
+        .get("authorization")
+        .and_then(|value| value.to_str().ok())
+        .and_then(|value| value.strip_prefix(ALGORITHM));
+    let params: Vec<(String, String)> = query_pairs(query).collect();
+    let param = |name: &str| {
+        params
+            .iter()
+            .find(|(key, _)| key == name)
+            .map(|(_, value)| value.clone())
+    };
+
+    let (credential, signed_headers, signature, amz_date, expires) =
+        if let Some(authorization) = authorization {
+            let mut credential = None;
+            let mut signed_headers = None;
+            let mut signature = None;
+            for part in authorization.split(',') {
+                let Some((name, value)) = part.trim().split_once('=') else {
+                    continue;
+                };
+                match name {
+                    "Credential" => credential = Some(value.to_string()),
+                    "SignedHeaders" => signed_headers = Some(value.to_string()),
+                    "Signature" => signature = Some(value.to_string()),
+                    _ => {}
+                }
+            }
+            let amz_date = headers
+                .get("x-amz-date")
+                .and_then(|value| value.to_str().ok())
+                .map(str::to_string);
+            (credential, signed_headers, signature, amz_date, None)
+        } else if param("X-Amz-Algorithm").as_deref() == Some(ALGORITHM) {
+            let expires = param("X-Amz-Expires")
+                .ok_or_else(|| malformed("presigned URL without X-Amz-Expires"))?
+                .parse::<i64>()
+                .ok()
+                .filter(|secs| (1..=MAX_PRESIGNED_EXPIRY_SECS).contains(secs))
+                .ok_or_else(|| malformed("X-Amz-Expires must be 1 to 604800 seconds"))?;
+            (
+                param("X-Amz-Credential"),
+                param("X-Amz-SignedHeaders"),
+                param("X-Amz-Signature"),
+                param("X-Amz-Date"),
+                Some(expires),
+            )
+        } else {
+            return Ok(None);
+        };
+
+    let credential = credential.ok_or_else(|| malformed("no Credential"))?;
+    let (access_key, scope) = credential
+        .split_once('/')
+        .ok_or_else(|| malformed("Credential is not `key/date/region/service/aws4_request`"))?;
+    let amz_date = amz_date.ok_or_else(|| malformed("no x-amz-date"))?;
+    let scope_parts: Vec<&str> = scope.split('/').collect();
+    match scope_parts[..] {
+        [date, region, service, "aws4_request"]
+            if !region.is_empty() && !service.is_empty() && amz_date.starts_with(date) => {}
+        _ => return Err(malformed("credential scope does not fit the request date")),
+    }
+    let signed_headers: Vec<String> = signed_headers
+        .ok_or_else(|| malformed("no SignedHeaders"))?
+        .split(';')
+        .map(str::to_ascii_lowercase)
+        .collect();
+    if !signed_headers.iter().any(|name| name == "host") {
+        return Err(malformed("the host header must be signed"));
+    }
+    Ok(Some(Signature {
+        access_key: access_key.to_string(),
+        scope: scope.to_string(),
+        amz_date,
+        signed_headers,
+        signature: signature.ok_or_else(|| malformed("no Signature"))?,
+        expires,
+    }))
+}
+
+fn check_date(signature: &Signature, now: DateTime<Utc>) -> Result<(), SignatureError> {
+    let signed_at = NaiveDateTime::parse_from_str(&signature.amz_date, "%Y%m%dT%H%M%SZ")
+        .map_err(|_| SignatureError::Malformed("x-amz-date is not `YYYYMMDDTHHMMSSZ`".into()))?
+        .and_utc();
+    if signed_at - now > MAX_CLOCK_SKEW {
+        return Err(SignatureError::Expired(
+            "the request is dated in the future".into(),
+        ));
+    }
+    match signature.expires {
+        Some(expires) if now > signed_at + Duration::seconds(expires) => Err(
+            SignatureError::Expired("the presigned URL has expired".into()),
+        ),
+        None if now - signed_at > MAX_CLOCK_SKEW => Err(SignatureError::Expired(
+            "the difference between the request time and the server's time is too large".into(),
+        )),
+        _ => Ok(()),
+    }
+}
+
+/// The canonical request the client should have signed.
+fn canonical_request(
+    method: &Method,
+    uri: &Uri,
+    headers: &HeaderMap,
+    signature: &Signature,
+) -> Result<String, SignatureError> {
+    let path = uri.path();
+    let canonical_uri = utf8_percent_encode(
+        &percent_decode_str(if path.is_empty() { "/" } else { path }).decode_utf8_lossy(),
+        KEY_ENCODE,
+    )
+    .to_string();
+
+    let presigned = signature.expires.is_some();
+    let mut query: Vec<(String, String)> = query_pairs(uri.query())
+        .filter(|(name, _)| !(presigned && name == "X-Amz-Signature"))
+        .map(|(name, value)| {
+            (
+                utf8_percent_encode(&name, QUERY_ENCODE).to_string(),
+                utf8_percent_encode(&value, QUERY_ENCODE).to_string(),
+            )
+        })
+        .collect();
+    query.sort();
+    let canonical_query = query
+        .iter()
+        .map(|(name, value)| format!("{}={}", name, value))
+        .collect::<Vec<_>>()
+        .join("&");
+
+    let mut canonical_headers = String::new();
+    for name in &signature.signed_headers {
+        let mut values: Vec<String> = headers
+            .get_all(name.as_str())
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .map(|value| value.split_whitespace().collect::<Vec<_>>().join(" "))
+            .collect();
+        if values.is_empty() && name == "host" {
+            values.extend(uri.authority().map(|authority| authority.to_string()));
+        }
+        if values.is_empty() {
+            return Err(SignatureError::Malformed(format!(
+                "signed header `{}` is missing",
+                name
+            )));
+        }
+        canonical_headers.push_str(&format!("{}:{}\n", name, values.join(",")));
+    }
+
+    let payload = if presigned {
+        query_pairs(uri.query())
+            .find(|(name, _)| name == "X-Amz-Content-Sha256")
+            .map(|(_, value)| value)
+            .unwrap_or_else(|| "UNSIGNED-PAYLOAD".into())
+    } else {
+        headers
+            .get("x-amz-content-sha256")
+            .and_then(|value| value.to_str().ok())
+            .map(str::to_string)
+            .ok_or_else(|| SignatureError::Malformed("no x-amz-content-sha256".into()))?
+    };
+
+    Ok(format!(
+        "{}\n{}\n{}\n{}\n{}\n{}",
+        method.as_str(),
+        canonical_uri,
+        canonical_query,
+        canonical_headers,
+        signature.signed_headers.join(";"),
+        payload
+    ))
+}
+
+/// Percent-decoded `name=value` pairs of a query; a bare `name` has an empty
+/// value.
+fn query_pairs(query: Option<&str>) -> impl Iterator<Item = (String, String)> + '_ {
+    query
+        .unwrap_or_default()
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (name, value) = pair.split_once('=').unwrap_or((pair, ""));
+            let decode = |s: &str| percent_decode_str(s).decode_utf8_lossy().into_owned();
+            (decode(name), decode(value))
+        })
+}
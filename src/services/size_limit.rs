@@ -0,0 +1,118 @@
+//! Object size and request body limits.
+//!
+//! `StorageOptions::max_object_size` caps the bytes a single object may hold
+//! and `max_request_body` the bytes any one request may send. Both are
+//! enforced while the body streams in rather than after it is on disk: a
+//! declared length over the limit is refused before anything is read, and a
+//! body that grows past it fails the stream, so `stage_payload` removes the
+//! temp file. Either way the request is answered with `EntityTooLarge`
+//! (413).
+//!
+//! The object limit applies to the stored bytes of a PUT (after aws-chunked
+//! and content decoding), to each upload part, to copies and to completed
+//! multipart uploads. The body limit applies to every request as received
+//! and is installed by `middleware::body_limit`.
+
+use crate::services::{
+    content_encoding::ByteStream,
+    storage_service::{StorageError, StorageResult, StorageService},
+};
+use bytes::Bytes;
+use futures::{Stream, StreamExt};
+use std::{error::Error, fmt, io};
+
+/// Carried by the error of a `limit_stream` stream that outgrew its limit.
+#[derive(Debug)]
+struct Oversize(String);
+
+impl fmt::Display for Oversize {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&self.0)
+    }
+}
+
+impl Error for Oversize {}
+
+/// The size error carried by an error from a `limit_stream` stream, if
+/// any. Request bodies reach the upload path wrapped in `axum::Error`, so
+/// the whole chain is searched.
+pub(crate) fn overflow(err: &io::Error) -> Option<StorageError> {
+    let mut current: Option<&(dyn Error + 'static)> = Some(err);
+    while let Some(err) = current {
+        if let Some(oversize) = err.downcast_ref::<Oversize>() {
+            return Some(StorageError::EntityTooLarge(oversize.0.clone()));
+        }
+        // `io::Error::source` skips the error it wraps.
+        current = match err.downcast_ref::<io::Error>() {
+            Some(err) => err.get_ref().map(|inner| inner as &(dyn Error + 'static)),
+            None => err.source(),
+        };
+    }
+    None
+}
+
+/// Fail `stream` once it yields more than `limit` bytes.
+pub(crate) fn limit_stream<S>(
+    stream: S,
+    limit: u64,
+    reason: String,
+) -> impl Stream<Item = io::Result<Bytes>> + Send + 'static
+where
+    S: Stream<Item = io::Result<Bytes>> + Send + 'static,
+{
+    let mut seen: u64 = 0;
+    stream.map(move |chunk| {
+        let chunk = chunk?;
+        seen += chunk.len() as u64;
+        if seen > limit {
+            return Err(io::Error::other(Oversize(reason.clone())));
+        }
+        Ok(chunk)
+    })
+}
+
+/// Why a request body of more than `limit` bytes is refused.
+pub(crate) fn body_too_large(limit: u64) -> String {
+    format!("the request body exceeds the maximum of {} bytes", limit)
+}
+
+fn object_too_large(key: &str, limit: u64) -> String {
+    format!(
+        "`{}` exceeds the maximum object size of {} bytes",
+        key, limit
+    )
+}
+
+impl StorageService {
+    /// Refuse `size` bytes for `key` when they exceed the maximum object
+    /// size.
+    pub(crate) fn check_object_size(&self, key: &str, size: u64) -> StorageResult<()> {
+        match self.options.max_object_size {
+            Some(limit) if size > limit => {
+                Err(StorageError::EntityTooLarge(object_too_large(key, limit)))
+            }
+            _ => Ok(()),
+        }
+    }
+
+    /// Refuse a declared `content_length` over the maximum object size up
+    /// front, and cut `stream` off as soon as it outgrows it.
+    pub(crate) fn limit_object_stream(
+        &self,
+        key: &str,
+        content_length: Option<u64>,
+        stream: ByteStream,
+    ) -> StorageResult<ByteStream> {
+        let Some(limit) = self.options.max_object_size else {
+            return Ok(stream);
+        };
+        if let Some(length) = content_length {
+            self.check_object_size(key, length)?;
+        }
+        Ok(Box::pin(limit_stream(
+            stream,
+            limit,
+            object_too_large(key, limit),
+        )))
+    }
+}
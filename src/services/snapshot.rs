@@ -0,0 +1,978 @@
+//! Point-in-time bucket snapshots with scheduled retention.
+//!
+//! A snapshot records every live object of a bucket. Payloads are hard-linked
+//! (copied when linking is not possible) into
+//! `base_path/.snapshots/{bucket}/{snapshot_id}/`, which is cheap because
+//! uploads always write a new file and rename it into place: a snapshot keeps
+//! the old inode while the live key moves on.
+//!
+//! Each bucket can carry one policy: a cron schedule plus retention counts
+//! ("keep 7 daily, 4 weekly"). The scheduler task takes due snapshots and then
+//! prunes scheduled snapshots that fall outside the retention window. Manual
+//! and pre-restore snapshots are kept until deleted explicitly.
+//!
+//! Each object is captured under its key's lock, together with what reads
+//! of it depend on besides the row: the SSE-C encryption of its payload and
+//! its tags, user metadata and multipart parts (values copied as stored, so
+//! sealed ones stay sealed).
+//!
+//! Restoring a snapshot makes the bucket match it exactly: captured keys get
+//! their captured payload, metadata and per-key state back, written like an
+//! upload (placed on a volume and, with `dedup` on, linked to its blob), and
+//! keys created since are deleted. A `pre-restore` snapshot is taken first
+//! so a restore can itself be undone.
+
+use crate::{
+    models::{
+        bucket::Bucket,
+        multipart::ObjectPart,
+        snapshot::{BucketSnapshot, SnapshotPolicy},
+    },
+    services::{
+        changes::NewChange,
+        events::EventKind,
+        multipart::replace_parts,
+        scanner::replace_scan,
+        sse_c::{ObjectEncryption, replace_encryption},
+        staging::StagingFile,
+        storage_service::{BUCKET_COLUMNS, StorageError, StorageResult, StorageService, side_dirs},
+        versioning::{NULL_VERSION, new_version_id},
+    },
+};
+use base64::{Engine as _, engine::general_purpose};
+use chrono::{DateTime, Datelike, Utc};
+use cron::Schedule;
+use serde::Serialize;
+use sqlx::{FromRow, Sqlite, Transaction};
+use std::{
+    collections::HashSet,
+    io,
+    path::{Path, PathBuf},
+    str::FromStr,
+    time::Duration,
+};
+use tokio::{fs, task::JoinHandle};
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+/// Directory (below `base_path`) holding snapshot payloads.
+const SNAPSHOT_DIR: &str = side_dirs::SNAPSHOTS;
+
+/// Objects read per page while capturing a snapshot.
+const CAPTURE_PAGE: i64 = 1000;
+
+/// How often the scheduler checks for due snapshots.
+pub const SNAPSHOT_TICK: Duration = Duration::from_secs(60);
+
+/// What caused a snapshot to be taken.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SnapshotTrigger {
+    Scheduled,
+    Manual,
+    PreRestore,
+}
+
+impl SnapshotTrigger {
+    fn as_str(self) -> &'static str {
+        match self {
+            SnapshotTrigger::Scheduled => "scheduled",
+            SnapshotTrigger::Manual => "manual",
+            SnapshotTrigger::PreRestore => "pre-restore",
+        }
+    }
+}
+
+/// Outcome of restoring a snapshot.
+#[derive(Debug, Clone, Serialize)]
+pub struct RestoreSummary {
+    pub snapshot_id: Uuid,
+    /// Snapshot of the bucket as it was just before the restore.
+    pub pre_restore_snapshot_id: Uuid,
+    /// Keys put back from the snapshot.
+    pub restored: u64,
+    /// Keys deleted because they did not exist when the snapshot was taken.
+    pub removed: u64,
+}
+
+/// One captured object, as stored in `snapshot_objects`.
+#[derive(Debug, Clone, FromRow)]
+struct SnapshotEntry {
+    key: String,
+    payload_id: Uuid,
+    content_type: Option<String>,
+    content_encoding: Option<String>,
+    size_bytes: i64,
+    etag: Option<String>,
+    checksum_sha256: Option<String>,
+    checksum_crc32c: Option<String>,
+    storage_class: String,
+    last_modified: DateTime<Utc>,
+    acl: Option<String>,
+    cache_control: Option<String>,
+    content_disposition: Option<String>,
+    expires: Option<String>,
+    /// `object_encryption` of the captured payload, as stored.
+    sse_key_md5: Option<String>,
+    sse_iv: Option<String>,
+    #[sqlx(skip)]
+    state: KeyState,
+}
+
+/// Per-key state of a captured object besides its row.
+#[derive(Debug, Clone, Default)]
+struct KeyState {
+    tags: Vec<StoredValue>,
+    metadata: Vec<StoredValue>,
+    parts: Vec<ObjectPart>,
+}
+
+/// A tag or user metadata entry as stored: the value is sealed when
+/// `value_hash` is set (see `metadata_encryption`).
+#[derive(Debug, Clone, FromRow)]
+struct StoredValue {
+    name: String,
+    value: String,
+    value_hash: Option<String>,
+}
+
+/// Tables holding tag and metadata values: live table, its snapshot copy,
+/// name column and value column.
+const VALUE_TABLES: [(&str, &str, &str, &str); 2] = [
+    (
+        "object_tags",
+        "snapshot_object_tags",
+        "tag_key",
+        "tag_value",
+    ),
+    (
+        "object_metadata",
+        "snapshot_object_metadata",
+        "name",
+        "value",
+    ),
+];
+
+impl SnapshotEntry {
+    /// The encryption of the captured payload, if it was encrypted.
+    fn encryption(&self) -> StorageResult<Option<ObjectEncryption>> {
+        let (Some(key_md5), Some(iv)) = (&self.sse_key_md5, &self.sse_iv) else {
+            return Ok(None);
+        };
+        let iv = general_purpose::STANDARD
+            .decode(iv)
+            .ok()
+            .and_then(|iv| iv.try_into().ok())
+            .ok_or_else(|| StorageError::CorruptPayload {
+                key: self.key.clone(),
+                reason: "invalid SSE-C initial counter block in snapshot".into(),
+            })?;
+        Ok(Some(ObjectEncryption {
+            key_md5: key_md5.clone(),
+            iv,
+        }))
+    }
+}
+
+/// Record the per-key state captured for `key` in snapshot `snapshot_id`.
+async fn insert_captured_state(
+    tx: &mut Transaction<'_, Sqlite>,
+    snapshot_id: Uuid,
+    key: &str,
+    state: &KeyState,
+) -> Result<(), sqlx::Error> {
+    for ((_, table, name_column, value_column), values) in
+        VALUE_TABLES.into_iter().zip([&state.tags, &state.metadata])
+    {
+        for value in values {
+            sqlx::query(&format!(
+                "INSERT INTO {table} (snapshot_id, key, {name_column}, {value_column}, value_hash)
+                 VALUES (?, ?, ?, ?, ?)"
+            ))
+            .bind(snapshot_id)
+            .bind(key)
+            .bind(&value.name)
+            .bind(&value.value)
+            .bind(&value.value_hash)
+            .execute(&mut **tx)
+            .await?;
+        }
+    }
+    for part in &state.parts {
+        sqlx::query(
+            "INSERT INTO snapshot_object_parts (
+                snapshot_id, key, part_number, size_bytes, etag, checksum_sha256, checksum_crc32c
+             ) VALUES (?, ?, ?, ?, ?, ?, ?)",
+        )
+        .bind(snapshot_id)
+        .bind(key)
+        .bind(part.part_number)
+        .bind(part.size_bytes)
+        .bind(&part.etag)
+        .bind(&part.checksum_sha256)
+        .bind(&part.checksum_crc32c)
+        .execute(&mut **tx)
+        .await?;
+    }
+    Ok(())
+}
+
+/// Parse a policy schedule. Accepts standard five-field cron (`min hour dom
+/// month dow`), the six/seven-field form with seconds, and shorthands such as
+/// `@daily` or `@weekly`.
+pub fn parse_schedule(expr: &str) -> Result<Schedule, String> {
+    let expr = expr.trim();
+    let normalized = if !expr.starts_with('@') && expr.split_whitespace().count() == 5 {
+        format!("0 {}", expr)
+    } else {
+        expr.to_string()
+    };
+    Schedule::from_str(&normalized).map_err(|err| {
+        // The parser's message draws a caret diagram; keep only its summary.
+        let err = err.to_string();
+        let summary = err.lines().last().unwrap_or_default().to_string();
+        format!("invalid schedule `{}`: {}", expr, summary)
+    })
+}
+
+impl StorageService {
+    /// Root of the snapshot area for a bucket.
+    pub(crate) fn snapshot_root(&self, bucket_name: &str) -> PathBuf {
+        self.base_path.join(SNAPSHOT_DIR).join(bucket_name)
+    }
+
+    fn snapshot_dir(&self, bucket_name: &str, id: Uuid) -> PathBuf {
+        self.snapshot_root(bucket_name).join(id.to_string())
+    }
+
+    /// Create or replace the snapshot policy of a bucket. Replacing a policy
+    /// restarts its schedule from now.
+    pub async fn set_snapshot_policy(
+        &self,
+        bucket: &str,
+        schedule: &str,
+        keep_daily: u32,
+        keep_weekly: u32,
+    ) -> StorageResult<SnapshotPolicy> {
+        parse_schedule(schedule).map_err(StorageError::InvalidSnapshotPolicy)?;
+        if keep_daily == 0 && keep_weekly == 0 {
+            return Err(StorageError::InvalidSnapshotPolicy(
+                "keep_daily and keep_weekly cannot both be zero".into(),
+            ));
+        }
+        let bucket_rec = self.fetch_bucket(bucket).await?;
+        let policy = sqlx::query_as::<_, SnapshotPolicy>(
+            "INSERT INTO snapshot_policies (
+                bucket_id, schedule, keep_daily, keep_weekly, created_at, last_run_at
+             ) VALUES (?, ?, ?, ?, ?, NULL)
+             ON CONFLICT(bucket_id) DO UPDATE SET
+                schedule = excluded.schedule,
+                keep_daily = excluded.keep_daily,
+                keep_weekly = excluded.keep_weekly,
+                created_at = excluded.created_at,
+                last_run_at = NULL
+             RETURNING bucket_id, schedule, keep_daily, keep_weekly, created_at, last_run_at",
+        )
+        .bind(bucket_rec.id)
+        .bind(schedule.trim())
+        .bind(keep_daily as i64)
+        .bind(keep_weekly as i64)
+        .bind(Utc::now())
+        .fetch_one(&*self.db)
+        .await?;
+        self.events.bucket(
+            EventKind::BucketConfigChanged,
+            &bucket_rec.name,
+            Some(format!("snapshot_policy={}", policy.schedule)),
+        );
+        Ok(policy)
+    }
+
+    /// The snapshot policy of a bucket, if one is set.
+    pub async fn get_snapshot_policy(&self, bucket: &str) -> StorageResult<Option<SnapshotPolicy>> {
+        let bucket_rec = self.fetch_bucket(bucket).await?;
+        let policy = sqlx::query_as::<_, SnapshotPolicy>(
+            "SELECT bucket_id, schedule, keep_daily, keep_weekly, created_at, last_run_at
+             FROM snapshot_policies WHERE bucket_id = ?",
+        )
+        .bind(bucket_rec.id)
+        .fetch_optional(&*self.db)
+        .await?;
+        Ok(policy)
+    }
+
+    /// Remove the snapshot policy of a bucket. Existing snapshots are kept.
+    /// Returns whether a policy was set.
+    pub async fn delete_snapshot_policy(&self, bucket: &str) -> StorageResult<bool> {
+        let bucket_rec = self.fetch_bucket(bucket).await?;
+        let result = sqlx::query("DELETE FROM snapshot_policies WHERE bucket_id = ?")
+            .bind(bucket_rec.id)
+            .execute(&*self.db)
+            .await?;
+        let removed = result.rows_affected() > 0;
+        if removed {
+            self.events.bucket(
+                EventKind::BucketConfigChanged,
+                &bucket_rec.name,
+                Some("snapshot_policy=none".into()),
+            );
+        }
+        Ok(removed)
+    }
+
+    /// List a bucket's snapshots, newest first.
+    pub async fn list_snapshots(&self, bucket: &str) -> StorageResult<Vec<BucketSnapshot>> {
+        let bucket_rec = self.fetch_bucket(bucket).await?;
+        self.snapshots_of(&bucket_rec).await
+    }
+
+    async fn snapshots_of(&self, bucket: &Bucket) -> StorageResult<Vec<BucketSnapshot>> {
+        let rows = sqlx::query_as::<_, BucketSnapshot>(
+            "SELECT id, bucket_id, created_at, trigger, object_count, total_bytes
+             FROM bucket_snapshots WHERE bucket_id = ?
+             ORDER BY created_at DESC",
+        )
+        .bind(bucket.id)
+        .fetch_all(&*self.db)
+        .await?;
+        Ok(rows)
+    }
+
+    async fn fetch_snapshot(&self, bucket: &Bucket, id: Uuid) -> StorageResult<BucketSnapshot> {
+        sqlx::query_as::<_, BucketSnapshot>(
+            "SELECT id, bucket_id, created_at, trigger, object_count, total_bytes
+             FROM bucket_snapshots WHERE id = ? AND bucket_id = ?",
+        )
+        .bind(id)
+        .bind(bucket.id)
+        .fetch_optional(&*self.db)
+        .await?
+        .ok_or_else(|| StorageError::SnapshotNotFound {
+            bucket: bucket.name.clone(),
+            id,
+        })
+    }
+
+    /// Take a snapshot of a bucket now. Read-only buckets can be snapshotted.
+    pub async fn create_snapshot(
+        &self,
+        bucket: &str,
+        trigger: SnapshotTrigger,
+    ) -> StorageResult<BucketSnapshot> {
+        let bucket_rec = self.fetch_bucket(bucket).await?;
+        self.capture_snapshot(&bucket_rec, trigger).await
+    }
+
+    async fn capture_snapshot(
+        &self,
+        bucket: &Bucket,
+        trigger: SnapshotTrigger,
+    ) -> StorageResult<BucketSnapshot> {
+        let id = Uuid::new_v4();
+        let dir = self.snapshot_dir(&bucket.name, id);
+        fs::create_dir_all(&dir).await?;
+
+        let entries = match self.link_live_objects(bucket, &dir).await {
+            Ok(entries) => entries,
+            Err(err) => {
+                let _ = fs::remove_dir_all(&dir).await;
+                return Err(err);
+            }
+        };
+
+        let snapshot = BucketSnapshot {
+            id,
+            bucket_id: bucket.id,
+            created_at: Utc::now(),
+            trigger: trigger.as_str().to_string(),
+            object_count: entries.len() as i64,
+            total_bytes: entries.iter().map(|e| e.size_bytes).sum(),
+        };
+        if let Err(err) = self.insert_snapshot(&snapshot, &entries).await {
+            let _ = fs::remove_dir_all(&dir).await;
+            return Err(err);
+        }
+
+        info!(
+            "snapshot {} of bucket `{}` ({}): {} objects, {} bytes",
+            id, bucket.name, snapshot.trigger, snapshot.object_count, snapshot.total_bytes
+        );
+        Ok(snapshot)
+    }
+
+    /// Link the payload of every live object into `dir`, page by page.
+    ///
+    /// Each object is re-read under its key's lock, so the recorded row and
+    /// state match the linked payload; one deleted in between is left out.
+    async fn link_live_objects(
+        &self,
+        bucket: &Bucket,
+        dir: &Path,
+    ) -> StorageResult<Vec<SnapshotEntry>> {
+        let mut entries = Vec::new();
+        let mut after = String::new();
+        loop {
+            let keys: Vec<String> = sqlx::query_scalar(
+                "SELECT key FROM objects
+                 WHERE bucket_id = ? AND is_deleted = 0 AND key > ?
+                 ORDER BY key ASC LIMIT ?",
+            )
+            .bind(bucket.id)
+            .bind(&after)
+            .bind(CAPTURE_PAGE)
+            .fetch_all(&*self.db)
+            .await?;
+            let Some(last) = keys.last() else {
+                break;
+            };
+            after = last.clone();
+
+            for key in keys {
+                let _key_guard = self.key_locks.lock(bucket.id, &key).await;
+                let object = match self.fetch_object(bucket, &key).await {
+                    Ok(object) => object,
+                    Err(StorageError::ObjectNotFound { .. }) => continue,
+                    Err(err) => return Err(err),
+                };
+                let payload_id = Uuid::new_v4();
+                let target = dir.join(payload_id.to_string());
+                let live = self.object_path_on(object.volume.as_deref(), &bucket.name, &object.key);
+                match link_or_copy(&live, &target).await {
+                    Ok(()) => {}
+                    Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                        debug!(
+                            "skipping {}/{} in snapshot: no payload on disk",
+                            bucket.name, object.key
+                        );
+                        continue;
+                    }
+                    Err(err) => return Err(StorageError::Io(err)),
+                }
+                let encryption = self.object_encryption(&object).await?;
+                let state = self.key_state(bucket.id, &object.key, false).await?;
+                entries.push(SnapshotEntry {
+                    key: object.key,
+                    payload_id,
+                    content_type: object.content_type,
+                    content_encoding: object.content_encoding,
+                    size_bytes: object.size_bytes,
+                    etag: object.etag,
+                    checksum_sha256: object.checksum_sha256,
+                    checksum_crc32c: object.checksum_crc32c,
+                    storage_class: object.storage_class,
+                    last_modified: object.last_modified,
+                    acl: object.acl,
+                    cache_control: object.cache_control,
+                    content_disposition: object.content_disposition,
+                    expires: object.expires,
+                    sse_key_md5: encryption.as_ref().map(|e| e.key_md5.clone()),
+                    sse_iv: encryption.map(|e| general_purpose::STANDARD.encode(e.iv)),
+                    state,
+                });
+            }
+        }
+        Ok(entries)
+    }
+
+    /// Tags, user metadata and parts of `key`: the live ones in bucket
+    /// `owner` or, with `captured`, those recorded in snapshot `owner`.
+    async fn key_state(&self, owner: Uuid, key: &str, captured: bool) -> StorageResult<KeyState> {
+        let owner_column = if captured { "snapshot_id" } else { "bucket_id" };
+        let mut values = Vec::with_capacity(VALUE_TABLES.len());
+        for (live, snapshot, name_column, value_column) in VALUE_TABLES {
+            let table = if captured { snapshot } else { live };
+            let rows = sqlx::query_as::<_, StoredValue>(&format!(
+                "SELECT {name_column} AS name, {value_column} AS value, value_hash
+                 FROM {table} WHERE {owner_column} = ? AND key = ?"
+            ))
+            .bind(owner)
+            .bind(key)
+            .fetch_all(&*self.db)
+            .await?;
+            values.push(rows);
+        }
+        let metadata = values.pop().unwrap_or_default();
+        let tags = values.pop().unwrap_or_default();
+        let parts_table = if captured {
+            "snapshot_object_parts"
+        } else {
+            "object_parts"
+        };
+        let parts = sqlx::query_as::<_, ObjectPart>(&format!(
+            "SELECT part_number, size_bytes, etag, checksum_sha256, checksum_crc32c
+             FROM {parts_table} WHERE {owner_column} = ? AND key = ?
+             ORDER BY part_number"
+        ))
+        .bind(owner)
+        .bind(key)
+        .fetch_all(&*self.db)
+        .await?;
+        Ok(KeyState {
+            tags,
+            metadata,
+            parts,
+        })
+    }
+
+    async fn insert_snapshot(
+        &self,
+        snapshot: &BucketSnapshot,
+        entries: &[SnapshotEntry],
+    ) -> StorageResult<()> {
+        let mut tx = self.db.begin().await?;
+        sqlx::query(
+            "INSERT INTO bucket_snapshots (
+                id, bucket_id, created_at, trigger, object_count, total_bytes
+             ) VALUES (?, ?, ?, ?, ?, ?)",
+        )
+        .bind(snapshot.id)
+        .bind(snapshot.bucket_id)
+        .bind(snapshot.created_at)
+        .bind(&snapshot.trigger)
+        .bind(snapshot.object_count)
+        .bind(snapshot.total_bytes)
+        .execute(&mut *tx)
+        .await?;
+        for entry in entries {
+            sqlx::query(
+                "INSERT INTO snapshot_objects (
+                    snapshot_id, key, payload_id, content_type, content_encoding,
+                    size_bytes, etag, checksum_sha256, checksum_crc32c, storage_class,
+                    last_modified, acl, cache_control, content_disposition, expires,
+                    sse_key_md5, sse_iv
+                 ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)",
+            )
+            .bind(snapshot.id)
+            .bind(&entry.key)
+            .bind(entry.payload_id)
+            .bind(&entry.content_type)
+            .bind(&entry.content_encoding)
+            .bind(entry.size_bytes)
+            .bind(&entry.etag)
+            .bind(&entry.checksum_sha256)
+            .bind(&entry.checksum_crc32c)
+            .bind(&entry.storage_class)
+            .bind(entry.last_modified)
+            .bind(&entry.acl)
+            .bind(&entry.cache_control)
+            .bind(&entry.content_disposition)
+            .bind(&entry.expires)
+            .bind(&entry.sse_key_md5)
+            .bind(&entry.sse_iv)
+            .execute(&mut *tx)
+            .await?;
+            insert_captured_state(&mut tx, snapshot.id, &entry.key, &entry.state).await?;
+        }
+        tx.commit().await?;
+        Ok(())
+    }
+
+    /// Delete a snapshot and its payloads.
+    pub async fn delete_snapshot(&self, bucket: &str, id: Uuid) -> StorageResult<()> {
+        let bucket_rec = self.fetch_bucket(bucket).await?;
+        self.remove_snapshot(&bucket_rec, id).await
+    }
+
+    async fn remove_snapshot(&self, bucket: &Bucket, id: Uuid) -> StorageResult<()> {
+        let result = sqlx::query("DELETE FROM bucket_snapshots WHERE id = ? AND bucket_id = ?")
+            .bind(id)
+            .bind(bucket.id)
+            .execute(&*self.db)
+            .await?;
+        if result.rows_affected() == 0 {
+            return Err(StorageError::SnapshotNotFound {
+                bucket: bucket.name.clone(),
+                id,
+            });
+        }
+        let dir = self.snapshot_dir(&bucket.name, id);
+        if let Err(err) = fs::remove_dir_all(&dir).await
+            && err.kind() != io::ErrorKind::NotFound
+        {
+            warn!(
+                "failed to remove snapshot directory {}: {}",
+                dir.display(),
+                err
+            );
+        }
+        Ok(())
+    }
+
+    /// Make the bucket match snapshot `id`.
+    ///
+    /// Captured keys get their captured payload, metadata, encryption, tags
+    /// and parts back; keys that did not exist at snapshot time are deleted. The current state is saved
+    /// as a `pre-restore` snapshot first.
+    pub async fn restore_snapshot(&self, bucket: &str, id: Uuid) -> StorageResult<RestoreSummary> {
+        let bucket_rec = self.fetch_writable_bucket(bucket).await?;
+        self.fetch_snapshot(&bucket_rec, id).await?;
+        let pre_restore = self
+            .capture_snapshot(&bucket_rec, SnapshotTrigger::PreRestore)
+            .await?;
+
+        let entries = sqlx::query_as::<_, SnapshotEntry>(
+            "SELECT key, payload_id, content_type, content_encoding, size_bytes, etag,
+                    checksum_sha256, checksum_crc32c, storage_class, last_modified, acl,
+                    cache_control, content_disposition, expires, sse_key_md5, sse_iv
+             FROM snapshot_objects WHERE snapshot_id = ?",
+        )
+        .bind(id)
+        .fetch_all(&*self.db)
+        .await?;
+
+        let dir = self.snapshot_dir(&bucket_rec.name, id);
+        let mut restored = 0;
+        for mut entry in entries {
+            entry.state = self.key_state(id, &entry.key, true).await?;
+            self.restore_entry(&bucket_rec, &dir, &entry).await?;
+            restored += 1;
+        }
+
+        let stale: Vec<String> = sqlx::query_scalar(
+            "SELECT key FROM objects
+             WHERE bucket_id = ? AND is_deleted = 0
+               AND key NOT IN (SELECT key FROM snapshot_objects WHERE snapshot_id = ?)",
+        )
+        .bind(bucket_rec.id)
+        .bind(id)
+        .fetch_all(&*self.db)
+        .await?;
+        let mut removed = 0;
+        for key in stale {
+            match self.delete_object(&bucket_rec.name, &key).await {
+                Ok(_) => removed += 1,
+                Err(StorageError::ObjectNotFound { .. }) => {}
+                Err(err) => return Err(err),
+            }
+        }
+
+        info!(
+            "restored bucket `{}` from snapshot {}: {} restored, {} removed (undo with {})",
+            bucket_rec.name, id, restored, removed, pre_restore.id
+        );
+        Ok(RestoreSummary {
+            snapshot_id: id,
+            pre_restore_snapshot_id: pre_restore.id,
+            restored,
+            removed,
+        })
+    }
+
+    /// Write one captured object back as the live version of its key, the
+    /// way an upload commits a payload.
+    async fn restore_entry(
+        &self,
+        bucket: &Bucket,
+        dir: &Path,
+        entry: &SnapshotEntry,
+    ) -> StorageResult<()> {
+        let key = entry.key.as_str();
+        let encryption = entry.encryption()?;
+        let _key_guard = self.key_locks.lock(bucket.id, key).await;
+        let volume = self.place_payload(bucket, key).await?;
+        let file_path = self.layout_path_on(volume.as_deref(), &bucket.name, key);
+        let previous_path = self.live_path(bucket, key).await?;
+        let parent = file_path.parent().map(Path::to_path_buf).ok_or_else(|| {
+            StorageError::Io(io::Error::other("object path missing parent directory"))
+        })?;
+        fs::create_dir_all(&parent).await?;
+        // Stage a link to the snapshot's payload and persist it like an
+        // upload, so the snapshot's inode is never the one later uploads
+        // replace in place and a deduplicated payload counts its blob link.
+        let tmp = self.temp_path(&parent);
+        link_or_copy(&dir.join(entry.payload_id.to_string()), &tmp).await?;
+        let staged = StagingFile::new(tmp, self.uploads.clone());
+        // Placed and encrypted payloads are not linked to blobs.
+        let digest = if self.options.dedup && encryption.is_none() && volume.is_none() {
+            Some(self.content_digest(staged.path()).await?)
+        } else {
+            None
+        };
+        let change_kind = self.write_kind(bucket, key).await?;
+        // In versioned buckets the restore is a new version on top of the
+        // current one, which stays in the history.
+        let archived = self.archive_current_version(bucket, key).await?;
+        if let Err(err) = self
+            .persist_payload(staged, digest.as_deref(), &file_path)
+            .await
+        {
+            if let Some(archived) = archived {
+                self.unarchive_version(bucket, &archived, &previous_path)
+                    .await;
+            }
+            return Err(StorageError::Io(err));
+        }
+
+        let version_id = bucket.versioning_enabled.then(new_version_id);
+        let result = self
+            .insert_restored(
+                bucket,
+                entry,
+                volume.as_deref(),
+                version_id.as_deref(),
+                encryption.as_ref(),
+            )
+            .await;
+        let object_id = match result {
+            Ok(object_id) => object_id,
+            Err(err) => {
+                let _ = fs::remove_file(&file_path).await;
+                if let Some(archived) = archived {
+                    self.unarchive_version(bucket, &archived, &previous_path)
+                        .await;
+                }
+                return Err(StorageError::Sqlx(err));
+            }
+        };
+        // A previous payload on another volume was not replaced by the
+        // rename.
+        if previous_path != file_path
+            && archived.is_none()
+            && let Err(err) = self.remove_payload(&bucket.name, &previous_path).await
+        {
+            debug!("could not remove previous payload of {}: {}", key, err);
+        }
+        // The key may have been deleted with its payload still in the trash;
+        // it is superseded now.
+        if self.options.deleted_retention.is_some()
+            && let Err(err) = self.remove_trashed(&bucket.name, object_id).await
+        {
+            debug!("could not remove trashed payload of {}: {}", key, err);
+        }
+        self.record_change(
+            bucket.id,
+            NewChange {
+                kind: change_kind,
+                key,
+                version_id: version_id.as_deref(),
+                etag: entry.etag.as_deref(),
+                size_bytes: Some(entry.size_bytes),
+            },
+        )
+        .await;
+        self.events.object(
+            EventKind::ObjectCreated,
+            &bucket.name,
+            key,
+            Some(entry.size_bytes),
+            entry.etag.clone(),
+        );
+        Ok(())
+    }
+
+    /// Upsert the row of a restored object and replace its per-key state,
+    /// in one transaction. Returns the row id.
+    async fn insert_restored(
+        &self,
+        bucket: &Bucket,
+        entry: &SnapshotEntry,
+        volume: Option<&str>,
+        version_id: Option<&str>,
+        encryption: Option<&ObjectEncryption>,
+    ) -> Result<Uuid, sqlx::Error> {
+        let key = entry.key.as_str();
+        let filename = key.split('/').next_back().unwrap_or(key).to_string();
+        let mut tx = self.db.begin().await?;
+        let object_id: Uuid = sqlx::query_scalar(
+            "INSERT INTO objects (
+                id, bucket_id, key, filename, content_type, content_encoding, size_bytes,
+                etag, checksum_sha256, checksum_crc32c, storage_class, last_modified, version_id,
+                is_deleted, acl, cache_control, content_disposition, expires, volume
+             ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, 0, ?, ?, ?, ?, ?)
+             ON CONFLICT(bucket_id, key) DO UPDATE SET
+                filename = excluded.filename,
+                content_type = excluded.content_type,
+                content_encoding = excluded.content_encoding,
+                size_bytes = excluded.size_bytes,
+                etag = excluded.etag,
+                checksum_sha256 = excluded.checksum_sha256,
+                checksum_crc32c = excluded.checksum_crc32c,
+                storage_class = excluded.storage_class,
+                last_modified = excluded.last_modified,
+                version_id = excluded.version_id,
+                is_deleted = 0,
+                acl = excluded.acl,
+                cache_control = excluded.cache_control,
+                content_disposition = excluded.content_disposition,
+                expires = excluded.expires,
+                volume = excluded.volume
+             RETURNING id",
+        )
+        .bind(Uuid::new_v4())
+        .bind(bucket.id)
+        .bind(key)
+        .bind(&filename)
+        .bind(&entry.content_type)
+        .bind(&entry.content_encoding)
+        .bind(entry.size_bytes)
+        .bind(&entry.etag)
+        .bind(&entry.checksum_sha256)
+        .bind(&entry.checksum_crc32c)
+        .bind(&entry.storage_class)
+        .bind(entry.last_modified)
+        .bind(version_id)
+        .bind(&entry.acl)
+        .bind(&entry.cache_control)
+        .bind(&entry.content_disposition)
+        .bind(&entry.expires)
+        .bind(volume)
+        .fetch_one(&mut *tx)
+        .await?;
+        for ((table, _, name_column, value_column), values) in VALUE_TABLES
+            .into_iter()
+            .zip([&entry.state.tags, &entry.state.metadata])
+        {
+            sqlx::query(&format!(
+                "DELETE FROM {table} WHERE bucket_id = ? AND key = ?"
+            ))
+            .bind(bucket.id)
+            .bind(key)
+            .execute(&mut *tx)
+            .await?;
+            for value in values {
+                sqlx::query(&format!(
+                    "INSERT INTO {table} (bucket_id, key, {name_column}, {value_column}, value_hash)
+                     VALUES (?, ?, ?, ?, ?)"
+                ))
+                .bind(bucket.id)
+                .bind(key)
+                .bind(&value.name)
+                .bind(&value.value)
+                .bind(&value.value_hash)
+                .execute(&mut *tx)
+                .await?;
+            }
+        }
+        replace_parts(&mut tx, bucket.id, key, &entry.state.parts).await?;
+        let version = version_id.unwrap_or(NULL_VERSION);
+        replace_encryption(&mut tx, bucket.id, key, version, encryption).await?;
+        // The captured payload was screened when it was uploaded.
+        replace_scan(&mut tx, bucket.id, key, version, false).await?;
+        tx.commit().await?;
+        Ok(object_id)
+    }
+
+    /// Take every scheduled snapshot that is due and apply retention.
+    ///
+    /// A schedule that fired several times while the server was down runs
+    /// once. Returns the number of snapshots taken.
+    pub async fn run_due_snapshots(&self) -> StorageResult<usize> {
+        let policies = sqlx::query_as::<_, SnapshotPolicy>(
+            "SELECT bucket_id, schedule, keep_daily, keep_weekly, created_at, last_run_at
+             FROM snapshot_policies",
+        )
+        .fetch_all(&*self.db)
+        .await?;
+
+        let now = Utc::now();
+        let mut taken = 0;
+        for policy in policies {
+            let schedule = match parse_schedule(&policy.schedule) {
+                Ok(schedule) => schedule,
+                Err(err) => {
+                    warn!("skipping snapshot policy of {}: {}", policy.bucket_id, err);
+                    continue;
+                }
+            };
+            let anchor = policy.last_run_at.unwrap_or(policy.created_at);
+            let due = schedule
+                .after(&anchor)
+                .next()
+                .is_some_and(|next| next <= now);
+            if !due {
+                continue;
+            }
+
+            let Some(bucket) = self.bucket_by_id(policy.bucket_id).await? else {
+                continue;
+            };
+            sqlx::query("UPDATE snapshot_policies SET last_run_at = ? WHERE bucket_id = ?")
+                .bind(now)
+                .bind(policy.bucket_id)
+                .execute(&*self.db)
+                .await?;
+            match self
+                .capture_snapshot(&bucket, SnapshotTrigger::Scheduled)
+                .await
+            {
+                Ok(_) => taken += 1,
+                Err(err) => {
+                    warn!("scheduled snapshot of `{}` failed: {}", bucket.name, err);
+                    continue;
+                }
+            }
+            self.apply_retention(&bucket, &policy).await?;
+        }
+        Ok(taken)
+    }
+
+    async fn bucket_by_id(&self, id: Uuid) -> StorageResult<Option<Bucket>> {
+        let bucket = sqlx::query_as::<_, Bucket>(&format!(
+            "SELECT {BUCKET_COLUMNS} FROM buckets WHERE id = ?"
+        ))
+        .bind(id)
+        .fetch_optional(&*self.db)
+        .await?;
+        Ok(bucket)
+    }
+
+    /// Delete scheduled snapshots outside the policy's retention window.
+    async fn apply_retention(&self, bucket: &Bucket, policy: &SnapshotPolicy) -> StorageResult<()> {
+        let scheduled: Vec<BucketSnapshot> = self
+            .snapshots_of(bucket)
+            .await?
+            .into_iter()
+            .filter(|s| s.trigger == SnapshotTrigger::Scheduled.as_str())
+            .collect();
+        let keep = retained(&scheduled, policy.keep_daily, policy.keep_weekly);
+        for snapshot in scheduled.iter().filter(|s| !keep.contains(&s.id)) {
+            self.remove_snapshot(bucket, snapshot.id).await?;
+            debug!(
+                "pruned snapshot {} of bucket `{}` (taken {})",
+                snapshot.id, bucket.name, snapshot.created_at
+            );
+        }
+        Ok(())
+    }
+}
+
+/// Grandfather-father-son selection over snapshots sorted newest first: the
+/// newest snapshot of each of the `keep_daily` most recent days and of each
+/// of the `keep_weekly` most recent ISO weeks is kept.
+fn retained(snapshots: &[BucketSnapshot], keep_daily: i64, keep_weekly: i64) -> HashSet<Uuid> {
+    let mut keep = HashSet::new();
+    let mut days = HashSet::new();
+    let mut weeks = HashSet::new();
+    for snapshot in snapshots {
+        let day = snapshot.created_at.date_naive();
+        if (days.len() as i64) < keep_daily && days.insert(day) {
+            keep.insert(snapshot.id);
+        }
+        let week = day.iso_week();
+        if (weeks.len() as i64) < keep_weekly && weeks.insert((week.year(), week.week())) {
+            keep.insert(snapshot.id);
+        }
+    }
+    keep
+}
+
+/// Hard-link `src` to `dst`, falling back to a copy (e.g. across devices).
+pub(crate) async fn link_or_copy(src: &Path, dst: &Path) -> io::Result<()> {
+    match fs::hard_link(src, dst).await {
+        Ok(()) => Ok(()),
+        Err(err) if err.kind() == io::ErrorKind::NotFound => Err(err),
+        Err(_) => fs::copy(src, dst).await.map(|_| ()),
+    }
+}
+
+/// Spawn a background task that takes due snapshots every `period`.
+pub fn spawn_snapshot_scheduler(service: StorageService, period: Duration) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut ticker = tokio::time::interval(period);
+        loop {
+            ticker.tick().await;
+            if let Err(err) = service.run_due_snapshots().await {
+                warn!("snapshot scheduler failed: {}", err);
+            }
+        }
+    })
+}
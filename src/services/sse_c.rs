@@ -0,0 +1,201 @@
+//! Server-side encryption with customer-provided keys (SSE-C).
+//!
+//! A PUT carrying `x-amz-server-side-encryption-customer-algorithm: AES256`,
+//! `-customer-key` and `-customer-key-MD5` is encrypted with the caller's
+//! key as it is staged. The key is never stored: the object keeps only the
+//! key's MD5 and a random initial counter block in `object_encryption`.
+//! GET and HEAD of such an object must send the same key; a missing key is
+//! refused with `400` and a different one with `403`, as is a key sent for an
+//! object that is not encrypted.
+//!
+//! Payloads are encrypted with AES-256 in CTR mode, so ciphertext and
+//! plaintext have the same length and any byte range can be decrypted on its
+//! own: ranged reads and the block cache work on the stored bytes unchanged.
+//! The ETag and checksums describe the plaintext, as they do in S3.
+//!
+//! Encrypted payloads are never deduplicated or kept in the recycle area,
+//! and cannot be the source of a CopyObject. Multipart uploads do not accept
+//! a customer key.
+
+use crate::{
+    models::object::Object,
+    services::{
+        content_encoding::ByteStream,
+        storage_service::{StorageError, StorageResult, StorageService},
+        versioning::NULL_VERSION,
+    },
+};
+use aes::Aes256;
+use base64::{Engine as _, engine::general_purpose};
+use bytes::Bytes;
+use ctr::cipher::{KeyIvInit, StreamCipher, StreamCipherSeek};
+use futures::{Stream, StreamExt};
+use sqlx::{Sqlite, Transaction};
+use std::{fmt, io};
+use uuid::Uuid;
+
+/// Length of an SSE-C key (AES-256).
+pub const SSE_CUSTOMER_KEY_LEN: usize = 32;
+
+type Aes256Ctr = ctr::Ctr128BE<Aes256>;
+
+/// An SSE-C key whose `x-amz-server-side-encryption-customer-key-MD5` was
+/// checked against it.
+#[derive(Clone)]
+pub struct SseCustomerKey {
+    pub key: [u8; SSE_CUSTOMER_KEY_LEN],
+    /// Base64 MD5 of `key`.
+    pub key_md5: String,
+}
+
+impl fmt::Debug for SseCustomerKey {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.debug_struct("SseCustomerKey")
+            .field("key_md5", &self.key_md5)
+            .finish_non_exhaustive()
+    }
+}
+
+/// What is recorded about an encrypted payload.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ObjectEncryption {
+    /// Base64 MD5 of the customer key.
+    pub key_md5: String,
+    pub iv: [u8; 16],
+}
+
+/// The keystream of one payload.
+#[derive(Clone)]
+pub struct ObjectCipher {
+    key: [u8; SSE_CUSTOMER_KEY_LEN],
+    iv: [u8; 16],
+}
+
+impl ObjectCipher {
+    /// A cipher with a fresh initial counter block for a new payload.
+    pub fn for_new_payload(key: &SseCustomerKey) -> (Self, ObjectEncryption) {
+        let iv = *Uuid::new_v4().as_bytes();
+        let cipher = ObjectCipher { key: key.key, iv };
+        let encryption = ObjectEncryption {
+            key_md5: key.key_md5.clone(),
+            iv,
+        };
+        (cipher, encryption)
+    }
+
+    /// The keystream positioned at byte `offset` of the payload.
+    pub(crate) fn keystream_at(&self, offset: u64) -> Aes256Ctr {
+        let mut ctr = Aes256Ctr::new(&self.key.into(), &self.iv.into());
+        ctr.seek(offset);
+        ctr
+    }
+
+    /// Decrypt `bytes` read from `offset` of the stored payload.
+    pub fn decrypt_bytes(&self, bytes: &[u8], offset: u64) -> Bytes {
+        let mut plain = bytes.to_vec();
+        self.keystream_at(offset).apply_keystream(&mut plain);
+        Bytes::from(plain)
+    }
+
+    /// Decrypt a stream of stored bytes starting at `offset`.
+    pub fn decrypt_stream<S>(&self, stream: S, offset: u64) -> ByteStream
+    where
+        S: Stream<Item = io::Result<Bytes>> + Send + 'static,
+    {
+        let mut ctr = self.keystream_at(offset);
+        Box::pin(stream.map(move |chunk| {
+            chunk.map(|chunk| {
+                let mut plain = chunk.to_vec();
+                ctr.apply_keystream(&mut plain);
+                Bytes::from(plain)
+            })
+        }))
+    }
+}
+
+impl StorageService {
+    /// How the payload of `object` is encrypted, if it is.
+    pub async fn object_encryption(
+        &self,
+        object: &Object,
+    ) -> StorageResult<Option<ObjectEncryption>> {
+        let row: Option<(String, String)> = sqlx::query_as(
+            "SELECT key_md5, iv FROM object_encryption
+             WHERE bucket_id = ? AND key = ? AND version_id = ?",
+        )
+        .bind(object.bucket_id)
+        .bind(&object.key)
+        .bind(object.version_id.as_deref().unwrap_or(NULL_VERSION))
+        .fetch_optional(&*self.db)
+        .await?;
+        row.map(|(key_md5, iv)| {
+            let iv = general_purpose::STANDARD
+                .decode(&iv)
+                .ok()
+                .and_then(|iv| iv.try_into().ok())
+                .ok_or_else(|| StorageError::CorruptPayload {
+                    key: object.key.clone(),
+                    reason: "invalid SSE-C initial counter block".into(),
+                })?;
+            Ok(ObjectEncryption { key_md5, iv })
+        })
+        .transpose()
+    }
+
+    /// Check the key sent to read `object` and return the cipher to decrypt
+    /// its payload with (`None` when it is stored in the clear).
+    pub async fn payload_cipher(
+        &self,
+        object: &Object,
+        key: Option<&SseCustomerKey>,
+    ) -> StorageResult<Option<ObjectCipher>> {
+        match (self.object_encryption(object).await?, key) {
+            (None, None) => Ok(None),
+            (None, Some(_)) => Err(StorageError::InvalidCustomerKey(format!(
+                "`{}` is not encrypted with a customer key",
+                object.key
+            ))),
+            (Some(_), None) => Err(StorageError::InvalidCustomerKey(format!(
+                "`{}` is encrypted with a customer key, which the request must send",
+                object.key
+            ))),
+            (Some(encryption), Some(key)) if encryption.key_md5 != key.key_md5 => {
+                Err(StorageError::CustomerKeyMismatch(object.key.clone()))
+            }
+            (Some(encryption), Some(key)) => Ok(Some(ObjectCipher {
+                key: key.key,
+                iv: encryption.iv,
+            })),
+        }
+    }
+}
+
+/// Record (or clear) the encryption of a version written in `tx`.
+pub(crate) async fn replace_encryption(
+    tx: &mut Transaction<'_, Sqlite>,
+    bucket_id: Uuid,
+    key: &str,
+    version_id: &str,
+    encryption: Option<&ObjectEncryption>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query("DELETE FROM object_encryption WHERE bucket_id = ? AND key = ? AND version_id = ?")
+        .bind(bucket_id)
+        .bind(key)
+        .bind(version_id)
+        .execute(&mut **tx)
+        .await?;
+    if let Some(encryption) = encryption {
+        sqlx::query(
+            "INSERT INTO object_encryption (bucket_id, key, version_id, key_md5, iv)
+             VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(bucket_id)
+        .bind(key)
+        .bind(version_id)
+        .bind(&encryption.key_md5)
+        .bind(general_purpose::STANDARD.encode(encryption.iv))
+        .execute(&mut **tx)
+        .await?;
+    }
+    Ok(())
+}
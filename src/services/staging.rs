@@ -0,0 +1,158 @@
+//! Temporary files of payloads being written.
+//!
+//! Every upload is first streamed into a `.tmp-*` file, next to its final
+//! location or, with `StorageOptions::staging_dir`, in that directory. It
+//! has to be on the volume's filesystem so that moving the finished file
+//! into place stays an atomic rename; `prepare_staging_dir` checks this at
+//! startup. Payloads placed on other volumes (see `placement`) are always
+//! staged next to their location. A separate directory keeps partial data
+//! out of bucket trees (and backups of them) and in one place for cleanup.
+//!
+//! When the client disconnects mid-body, the handler's future is simply
+//! dropped, so no error branch runs; a `StagingFile` therefore owns the temp
+//! file and removes it when dropped unless it was persisted. Each abandoned
+//! file is counted in the upload registry (`aborted_uploads`,
+//! reported by `GET /admin/uploads`) together with the bytes written to it.
+
+use crate::services::{
+    placement::move_file, storage_service::StorageService, upload_progress::UploadRegistry,
+};
+use std::{
+    fs as std_fs,
+    io::{self, ErrorKind},
+    path::{Path, PathBuf},
+};
+use tokio::fs;
+use tracing::{info, warn};
+use uuid::Uuid;
+
+/// Prefix of temp files (see `fsck`).
+pub(crate) const TEMP_PREFIX: &str = ".tmp-";
+
+impl StorageService {
+    /// A fresh temp file path for a file that will end up in `dir`. The
+    /// staging directory only serves the default volume; files for other
+    /// volumes (see `placement`) are staged next to their destination.
+    pub(crate) fn temp_path(&self, dir: &Path) -> PathBuf {
+        self.options
+            .staging_dir
+            .as_deref()
+            .filter(|_| dir.starts_with(&self.base_path))
+            .unwrap_or(dir)
+            .join(format!("{}{}", TEMP_PREFIX, Uuid::new_v4()))
+    }
+
+    /// Create the staging directory, if one is configured, and make sure it
+    /// is on the same filesystem as `base_path`.
+    pub async fn prepare_staging_dir(&self) -> io::Result<()> {
+        let Some(staging) = &self.options.staging_dir else {
+            return Ok(());
+        };
+        fs::create_dir_all(staging).await?;
+        fs::create_dir_all(&self.base_path).await?;
+        if same_filesystem(staging, &self.base_path).await? == Some(false) {
+            return Err(io::Error::new(
+                ErrorKind::InvalidInput,
+                format!(
+                    "staging directory {} is not on the filesystem of {}; temp files could not be renamed into place",
+                    staging.display(),
+                    self.base_path.display()
+                ),
+            ));
+        }
+        Ok(())
+    }
+}
+
+/// Whether `a` and `b` are on the same filesystem; `None` where this is not
+/// supported.
+#[cfg(unix)]
+async fn same_filesystem(a: &Path, b: &Path) -> io::Result<Option<bool>> {
+    use std::os::unix::fs::MetadataExt;
+
+    Ok(Some(
+        fs::metadata(a).await?.dev() == fs::metadata(b).await?.dev(),
+    ))
+}
+
+#[cfg(not(unix))]
+async fn same_filesystem(_a: &Path, _b: &Path) -> io::Result<Option<bool>> {
+    Ok(None)
+}
+
+/// A temp file that is deleted on drop unless `persist` moved it into place.
+#[derive(Debug)]
+pub(crate) struct StagingFile {
+    path: PathBuf,
+    written: u64,
+    registry: UploadRegistry,
+    persisted: bool,
+}
+
+impl StagingFile {
+    pub(crate) fn new(path: PathBuf, registry: UploadRegistry) -> Self {
+        Self {
+            path,
+            written: 0,
+            registry,
+            persisted: false,
+        }
+    }
+
+    pub(crate) fn path(&self) -> &Path {
+        &self.path
+    }
+
+    /// Count bytes written, for the waste reported if the file is abandoned.
+    pub(crate) fn add_written(&mut self, bytes: usize) {
+        self.written += bytes as u64;
+    }
+
+    /// Move the file to `dest`, replacing whatever is there; it is copied
+    /// when `dest` is on another filesystem.
+    pub(crate) async fn persist(mut self, dest: &Path) -> io::Result<()> {
+        if let Err(err) = move_file(&self.path, dest).await {
+            if err.kind() != ErrorKind::AlreadyExists {
+                return Err(err);
+            }
+            fs::remove_file(dest).await?;
+            move_file(&self.path, dest).await?;
+        }
+        self.persisted = true;
+        Ok(())
+    }
+
+    /// Remove the file because its content is already stored elsewhere;
+    /// unlike dropping it, this does not count as an abandoned upload.
+    pub(crate) async fn discard(mut self) -> io::Result<()> {
+        self.persisted = true;
+        match fs::remove_file(&self.path).await {
+            Err(err) if err.kind() != ErrorKind::NotFound => Err(err),
+            _ => Ok(()),
+        }
+    }
+}
+
+impl Drop for StagingFile {
+    fn drop(&mut self) {
+        if self.persisted {
+            return;
+        }
+        // Drop cannot await; unlinking one file is quick enough to do inline.
+        match std_fs::remove_file(&self.path) {
+            Ok(()) => {}
+            Err(err) if err.kind() == ErrorKind::NotFound => {}
+            Err(err) => warn!(
+                "could not remove abandoned upload {}: {}",
+                self.path.display(),
+                err
+            ),
+        }
+        self.registry.record_aborted(self.written);
+        info!(
+            "discarded unfinished upload {}: {} bytes wasted",
+            self.path.display(),
+            self.written
+        );
+    }
+}
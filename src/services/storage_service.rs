@@ -3,19 +3,63 @@
 //! StorageService — core S3-like operations backed by SQLite for metadata
 //! and local disk for object payloads. This file intentionally does **not**
 //! include any cache or external stores; it focuses on durable metadata
-//! (SQLite) and on-disk object storage sharded beneath `base_path/{bucket}/{shard}/{shard}/{key}`.
+//! (SQLite) and on-disk object storage sharded beneath `base_path/{bucket}/{shard}/{shard}/{key}`
+//! (at the default depth, see `shard_layout`).
 
-use crate::models::{bucket::Bucket, object::Object};
+use crate::{
+    middleware::server_timing::{Phase, record, timed},
+    models::{
+        bucket::Bucket, multipart::ObjectPart, object::Object, object_lock::ObjectRetention,
+        object_metadata::ObjectMetadata, object_tag::ObjectTag,
+    },
+    services::{
+        auto_tagging::UploadFacts,
+        aws_chunked::{self, AccessKeys},
+        blob_store::BlobStore,
+        block_cache::BlockCache,
+        bucket_template::BucketTemplates,
+        changes::{NewChange, insert_change},
+        checksum::{self, ExpectedChecksums},
+        content_encoding,
+        events::{EventBus, EventKind},
+        jobs::JobQueue,
+        key_lock::KeyLocks,
+        keys,
+        manifest::ManifestKey,
+        metadata_encryption::MetadataKey,
+        multipart::replace_parts,
+        multipart_assembly::AssemblyRegistry,
+        object_lock::insert_retention,
+        outbound::OutboundHttp,
+        placement::VolumeSpec,
+        quota::{self, Quota},
+        reclaim::ReclaimQueue,
+        scanner::{ContentScanner, ScanMode, replace_scan},
+        session::SessionKey,
+        shard_layout::ShardLayout,
+        size_limit,
+        sse_c::{ObjectCipher, ObjectEncryption, SseCustomerKey, replace_encryption},
+        staging::StagingFile,
+        tagging::{self, replace_tags},
+        upload_progress::UploadRegistry,
+        user_metadata::{self, replace_user_metadata},
+        versioning::{NULL_VERSION, new_version_id},
+        volume::{DEFAULT_VOLUME, VolumeHealth},
+    },
+};
+use base64::{Engine as _, engine::general_purpose};
 use bytes::Bytes;
 use chrono::Utc;
+use ctr::cipher::StreamCipher;
 use futures::{Stream, StreamExt, pin_mut};
 use md5::Context;
-use sqlx::{QueryBuilder, SqlitePool, sqlite::Sqlite};
+use sha2::{Digest, Sha256};
+use sqlx::{FromRow, QueryBuilder, Row, SqlitePool, sqlite::Sqlite};
 use std::{
-    collections::BTreeSet,
     io::{self, ErrorKind},
     path::{Path, PathBuf},
     sync::Arc,
+    time::{Duration, Instant},
 };
 use thiserror::Error;
 use tokio::{
@@ -25,15 +69,102 @@ use tokio::{
 use tracing::debug;
 use uuid::Uuid;
 
+/// Column list selected for `Bucket` rows; keep in sync with the model.
+pub(crate) const BUCKET_COLUMNS: &str = "id, name, owner_id, region, created_at, versioning_enabled, read_only, cache_control, \
+     expires_secs, default_acl, enforce_bucket_owner_full_control, scan_uploads, encrypt_metadata";
+
+/// Column list selected for `Object` rows; keep in sync with the model.
+pub(crate) const OBJECT_COLUMNS: &str = "id, bucket_id, key, filename, content_type, \
+     content_encoding, size_bytes, etag, checksum_sha256, checksum_crc32c, storage_class, \
+     last_modified, version_id, is_deleted, acl, cache_control, content_disposition, expires, volume";
+
+/// Request-level attributes of an upload.
+#[derive(Clone, Debug, Default)]
+pub struct PutObjectParams {
+    pub content_type: Option<String>,
+    /// Raw `Content-Encoding` header sent by the client.
+    pub content_encoding: Option<String>,
+    /// Declared body size, used for progress reporting.
+    pub content_length: Option<u64>,
+    /// Tag set from `x-amz-tagging`; replaces any tags of the previous
+    /// version.
+    pub tags: Vec<ObjectTag>,
+    /// `x-amz-meta-*` headers; replace any metadata of the previous version.
+    pub user_metadata: Vec<ObjectMetadata>,
+    /// Canned ACL from `x-amz-acl`; the bucket's policy decides when absent.
+    pub acl: Option<String>,
+    /// `Cache-Control`, `Content-Disposition` and `Expires` to replay on
+    /// GET/HEAD.
+    pub cache_control: Option<String>,
+    pub content_disposition: Option<String>,
+    pub expires: Option<String>,
+    /// `Content-MD5` / `x-amz-checksum-*` the body must match.
+    pub checksums: ExpectedChecksums,
+    /// `x-amz-object-lock-mode` / `-retain-until-date`; the bucket's default
+    /// retention applies when absent.
+    pub retention: Option<ObjectRetention>,
+    /// SSE-C key to encrypt the payload with (see `sse_c`).
+    pub customer_key: Option<SseCustomerKey>,
+    /// Access key that signed the request, matched by auto-tagging rules.
+    pub uploader: Option<String>,
+}
+
 #[derive(Clone, Debug)]
 pub struct ListObjectsParams {
     pub prefix: Option<String>,
     pub delimiter: Option<String>,
     pub continuation_token: Option<String>,
     pub start_after: Option<String>,
+    /// Inclusive upper bound on returned keys (extension used by partitioned listings).
+    pub end_key: Option<String>,
+    /// Only objects in this storage class (extension).
+    pub storage_class: Option<String>,
+    /// Only objects of at least this many bytes (extension).
+    pub min_size: Option<u64>,
+    /// Only objects of at most this many bytes (extension).
+    pub max_size: Option<u64>,
     pub max_keys: usize,
 }
 
+/// A payload written to a temporary file but not yet visible under a key.
+#[derive(Debug)]
+pub(crate) struct StagedPayload {
+    /// The temp file; removed if dropped before it is persisted.
+    pub file: StagingFile,
+    pub size_bytes: i64,
+    pub md5: md5::Digest,
+    /// Base64 SHA-256 of the staged bytes, when `compute_sha256` is on.
+    pub sha256: Option<String>,
+    /// Base64 CRC32C of the staged bytes, when the client sent one that
+    /// matched (see `checksum`).
+    pub crc32c: Option<String>,
+    /// Hex SHA-256 of the staged bytes naming their shared blob, when
+    /// `dedup` is on.
+    pub content_digest: Option<String>,
+}
+
+/// Client-supplied attributes stored with a committed payload.
+#[derive(Debug, Default)]
+pub(crate) struct ObjectAttributes {
+    pub content_type: Option<String>,
+    pub content_encoding: Option<String>,
+    pub tags: Vec<ObjectTag>,
+    pub user_metadata: Vec<ObjectMetadata>,
+    /// Canned ACL after applying the bucket's policy.
+    pub acl: Option<String>,
+    pub cache_control: Option<String>,
+    pub content_disposition: Option<String>,
+    pub expires: Option<String>,
+    /// Parts of a completed multipart upload; empty for other payloads.
+    pub parts: Vec<ObjectPart>,
+    /// Retention requested for the new version (see `object_lock`).
+    pub retention: Option<ObjectRetention>,
+    /// Set when the payload was encrypted with a customer key.
+    pub encryption: Option<ObjectEncryption>,
+    /// Access key of the uploader, matched by auto-tagging rules.
+    pub uploader: Option<String>,
+}
+
 #[derive(Debug)]
 pub struct ListObjectsResult {
     pub objects: Vec<Object>,
@@ -43,12 +174,60 @@ pub struct ListObjectsResult {
     pub key_count: usize,
 }
 
+/// Where a listing scan resumes in key order.
+enum ScanStart {
+    Beginning,
+    /// Keys above this one.
+    After(String),
+    /// Keys from this one on: the successor of a common prefix.
+    From(String),
+    /// Keys above this common prefix that do not start with it, when it has
+    /// no successor to seek to.
+    PastGlob(String),
+}
+
+impl ScanStart {
+    /// Resume after every key starting with `common`.
+    fn past_prefix(common: &str) -> Self {
+        match keys::prefix_successor(common) {
+            Some(successor) => ScanStart::From(successor),
+            None => ScanStart::PastGlob(common.to_string()),
+        }
+    }
+
+    fn push_condition(&self, builder: &mut QueryBuilder<'_, Sqlite>) {
+        match self {
+            ScanStart::Beginning => {}
+            ScanStart::After(key) => {
+                builder.push(" AND key > ");
+                builder.push_bind(key.clone());
+            }
+            ScanStart::From(key) => {
+                builder.push(" AND key >= ");
+                builder.push_bind(key.clone());
+            }
+            ScanStart::PastGlob(common) => {
+                builder.push(" AND key > ");
+                builder.push_bind(common.clone());
+                builder.push(" AND key NOT GLOB ");
+                builder.push_bind(keys::prefix_glob(common));
+            }
+        }
+    }
+}
+
 #[derive(Debug, Error)]
 pub enum StorageError {
     #[error("bucket `{0}` not found")]
     BucketNotFound(String),
     #[error("bucket `{0}` already exists")]
     BucketAlreadyExists(String),
+    #[error("bucket `{0}` is not empty")]
+    BucketNotEmpty(String),
+    #[error("bucket `{0}` is read-only")]
+    BucketReadOnly(String),
+    #[error("volume `{0}` is read-only after repeated write failures")]
+    VolumeReadOnly(String),
     #[error("bucket `{name}` invalid: {reason}")]
     InvalidBucketName { name: String, reason: String },
     #[error("region `{0}` is not supported")]
@@ -57,6 +236,104 @@ pub enum StorageError {
     ObjectNotFound { bucket: String, key: String },
     #[error("invalid object key")]
     InvalidObjectKey,
+    #[error("key `{key}` is not allowed in bucket `{bucket}`: {reason}")]
+    KeyNotAllowed {
+        key: String,
+        bucket: String,
+        reason: String,
+    },
+    #[error("invalid naming policy: {0}")]
+    InvalidNamingPolicy(String),
+    #[error("invalid auto-tagging rules: {0}")]
+    InvalidAutoTagging(String),
+    #[error("invalid prefix: {0}")]
+    InvalidPrefix(String),
+    #[error("version `{version_id}` of `{key}` not found")]
+    NoSuchVersion { key: String, version_id: String },
+    #[error("version `{version_id}` of `{key}` is a delete marker")]
+    VersionIsDeleteMarker { key: String, version_id: String },
+    #[error("snapshot `{id}` not found in bucket `{bucket}`")]
+    SnapshotNotFound { bucket: String, id: Uuid },
+    #[error("multipart upload `{0}` not found")]
+    NoSuchUpload(Uuid),
+    #[error("invalid part: {0}")]
+    InvalidPart(String),
+    #[error("job `{0}` not found")]
+    JobNotFound(Uuid),
+    #[error("invalid snapshot policy: {0}")]
+    InvalidSnapshotPolicy(String),
+    #[error("bucket `{0}` has no lifecycle configuration")]
+    NoSuchLifecycleConfiguration(String),
+    #[error("invalid lifecycle configuration: {0}")]
+    InvalidLifecycle(String),
+    #[error("invalid admin token lifetime: {0}")]
+    InvalidTokenTtl(String),
+    #[error("invalid request body: {0}")]
+    InvalidContent(String),
+    #[error("invalid tag: {0}")]
+    InvalidTag(String),
+    #[error("invalid user metadata: {0}")]
+    InvalidMetadata(String),
+    #[error("invalid copy: {0}")]
+    InvalidCopy(String),
+    #[error("unknown canned ACL `{0}`")]
+    InvalidAcl(String),
+    #[error("ACL not allowed: {0}")]
+    AclNotAllowed(String),
+    #[error("invalid digest: {0}")]
+    InvalidDigest(String),
+    #[error("invalid object lock request: {0}")]
+    InvalidObjectLock(String),
+    #[error("bucket `{0}` has no Object Lock configuration")]
+    NoSuchObjectLockConfiguration(String),
+    #[error("version `{version_id}` of `{key}` is retained until {retain_until}")]
+    ObjectLocked {
+        key: String,
+        version_id: String,
+        retain_until: chrono::DateTime<Utc>,
+    },
+    #[error("invalid bucket state: {0}")]
+    InvalidBucketState(String),
+    #[error("bad digest: {0}")]
+    BadDigest(String),
+    #[error("XAmzContentSHA256Mismatch: {0}")]
+    ContentSha256Mismatch(String),
+    #[error("invalid customer key: {0}")]
+    InvalidCustomerKey(String),
+    #[error("the customer key does not match the one `{0}` was encrypted with")]
+    CustomerKeyMismatch(String),
+    #[error("quota exceeded: {0}")]
+    QuotaExceeded(String),
+    #[error("entity too large: {0}")]
+    EntityTooLarge(String),
+    #[error("`{key}` was rejected by the content scanner: {signature}")]
+    ContentRejected { key: String, signature: String },
+    #[error("version `{version_id}` of `{key}` is quarantined by the content scanner")]
+    ObjectQuarantined { key: String, version_id: String },
+    #[error("completing multipart upload {upload_id} failed: {reason}")]
+    AssemblyFailed { upload_id: Uuid, reason: String },
+    #[error("invalid notification configuration: {0}")]
+    InvalidNotification(String),
+    #[error("invalid placement rules: {0}")]
+    InvalidPlacement(String),
+    #[error("invalid event publishers: {0}")]
+    InvalidPublisher(String),
+    #[error("invalid replication configuration: {0}")]
+    InvalidReplication(String),
+    #[error("bucket `{0}` has no replication configuration")]
+    NoSuchReplicationConfiguration(String),
+    #[error("invalid object search: {0}")]
+    InvalidSearch(String),
+    #[error("invalid change cursor `{0}`")]
+    InvalidChangeCursor(String),
+    #[error("change cursor `{cursor}` of bucket `{bucket}` has expired; list the bucket to resync")]
+    ChangeCursorExpired { bucket: String, cursor: String },
+    #[error("signature does not match: {0}")]
+    SignatureDoesNotMatch(String),
+    #[error("stored payload of `{key}` is corrupt: {reason}")]
+    CorruptPayload { key: String, reason: String },
+    #[error("encrypted metadata cannot be read: {0}")]
+    SealedMetadata(String),
     #[error(transparent)]
     Sqlx(#[from] sqlx::Error),
     #[error(transparent)]
@@ -65,6 +342,108 @@ pub enum StorageError {
 
 pub type StorageResult<T> = Result<T, StorageError>;
 
+/// Tunables that change how the service treats payloads on disk.
+#[derive(Clone, Debug, Default)]
+pub struct StorageOptions {
+    /// How long a payload displaced by an overwrite is kept in the recycle
+    /// area. `None` discards the previous payload immediately.
+    pub overwrite_retention: Option<Duration>,
+
+    /// How long soft-deleted object rows are kept before the purger removes
+    /// them (see `purge`). `None` keeps them.
+    pub deleted_retention: Option<Duration>,
+
+    /// Decode `gzip`/`deflate` uploads to identity before storing, so the
+    /// stored bytes (and ranges over them) are the plain payload.
+    pub decode_content_encoding: bool,
+
+    /// Compute a SHA-256 of every payload while it streams in and record it
+    /// on the object, independently of any client-supplied checksum.
+    pub compute_sha256: bool,
+
+    /// Serve objects of at most this many bytes from a memory map instead of
+    /// buffered reads (see `mapped_read`). `None` always streams.
+    pub mmap_read_threshold: Option<u64>,
+
+    /// Memory budget for caching fixed-size blocks of range reads (see
+    /// `block_cache`). `None` reads every range from disk.
+    pub range_cache_bytes: Option<u64>,
+
+    /// Count object reads per age group for storage class analysis (see
+    /// `analytics`).
+    pub access_analytics: bool,
+
+    /// Record bytes transferred per key prefix of this many segments (see
+    /// `prefix_usage`). `None` disables it.
+    pub usage_prefix_depth: Option<usize>,
+
+    /// Store identical payloads once, hard-linked from every key holding
+    /// them (see `dedup`).
+    pub dedup: bool,
+
+    /// Named configurations `PUT /{bucket}` can create buckets with (see
+    /// `bucket_template`).
+    pub bucket_templates: BucketTemplates,
+
+    /// Signs and verifies content manifests (see `manifest`). `None`
+    /// disables them.
+    pub manifest_key: Option<ManifestKey>,
+
+    /// Signs and verifies session tokens (see `session`). `None` disables
+    /// them.
+    pub session_key: Option<SessionKey>,
+
+    /// Derives the keys of buckets that encrypt their user metadata and tag
+    /// values (see `metadata_encryption`). `None` refuses such buckets.
+    pub metadata_key: Option<MetadataKey>,
+
+    /// Consecutive disk write failures after which the volume is marked
+    /// read-only (see `volume`). `None` never marks it.
+    pub volume_failure_threshold: Option<u64>,
+
+    /// Check every full download against the object's stored SHA-256 /
+    /// CRC32C (see `checksum`); otherwise only downloads sent with
+    /// `x-amz-checksum-mode: ENABLED` are checked.
+    pub verify_read_checksums: bool,
+
+    /// Where lifecycle `Transition` rules move cold payloads (see
+    /// `tiering`). `None` refuses such rules.
+    pub remote_tier: Option<Arc<dyn BlobStore>>,
+
+    /// Limits on the bytes and objects stored across all buckets (see
+    /// `quota`); buckets may have their own as well.
+    pub global_quota: Quota,
+
+    /// Checks uploads to buckets that ask for it (see `scanner`). `None`
+    /// refuses such bucket settings.
+    pub scanner: Option<Arc<dyn ContentScanner>>,
+
+    /// Directory for temp files of payloads being written, on the
+    /// filesystem of `base_path` (see `staging`). `None` writes them next
+    /// to their final location.
+    pub staging_dir: Option<PathBuf>,
+
+    /// Volumes besides `base_path` that placement policies can put payloads
+    /// on (see `placement`).
+    pub volumes: Vec<VolumeSpec>,
+
+    /// Client factory for jobs that call other endpoints (replication
+    /// reconciliation, see `replication`).
+    pub outbound: OutboundHttp,
+
+    /// Secrets chunk signatures of streaming uploads are verified with (see
+    /// `aws_chunked`). Empty leaves them unverified.
+    pub access_keys: AccessKeys,
+
+    /// Largest object in bytes that PUT, copies and multipart uploads may
+    /// store (see `size_limit`). `None` leaves only disk space to limit it.
+    pub max_object_size: Option<u64>,
+
+    /// Largest request body in bytes, enforced by `middleware::body_limit`
+    /// (see `size_limit`). `None` is unlimited.
+    pub max_request_body: Option<u64>,
+}
+
 /// StorageService provides basic S3-like operations:
 /// - Upload an object (writes bytes to disk and inserts metadata into SQLite)
 /// - Get object (reads metadata from SQLite and payload from disk)
@@ -81,12 +460,62 @@ pub struct StorageService {
 
     /// Base directory on disk where object payloads are stored.
     pub base_path: PathBuf,
+
+    /// Behavioural options (retention, limits, ...).
+    pub options: StorageOptions,
+
+    /// Uploads currently receiving data (see `upload_progress`).
+    pub uploads: UploadRegistry,
+
+    /// Wakes the background job runner (see `jobs`).
+    pub jobs: JobQueue,
+
+    /// Feeds the payload removal workers (see `reclaim`).
+    pub reclaim: ReclaimQueue,
+
+    /// Bucket activity notifications (see `events`).
+    pub events: EventBus,
+
+    /// Blocks of recently read ranges (see `block_cache`).
+    pub block_cache: BlockCache,
+
+    /// Write failures and read-only state of the volume (see `volume`).
+    pub volume: VolumeHealth,
+
+    /// Serializes writes and deletes of each key (see `key_lock`).
+    pub key_locks: KeyLocks,
+
+    /// Serializes updates of each bucket configuration resource (see
+    /// `config_version`).
+    pub config_locks: KeyLocks,
+
+    /// Multipart uploads being completed (see `multipart_assembly`).
+    pub assemblies: AssemblyRegistry,
+
+    /// Directory layout of live payloads (see `shard_layout`).
+    pub shard_layout: ShardLayout,
 }
 
-const MAX_OBJECT_KEY_LEN: usize = 1024;
-const BUCKET_NAME_MIN_LEN: usize = 3;
-const BUCKET_NAME_MAX_LEN: usize = 63;
-const SUPPORTED_REGIONS: [&str; 16] = [
+pub(crate) const BUCKET_NAME_MIN_LEN: usize = 3;
+pub(crate) const BUCKET_NAME_MAX_LEN: usize = 63;
+/// Keys returned by one ListObjectsV2 page.
+pub const MAX_LIST_KEYS: usize = 1000;
+/// Names that would shadow the server's own top-level routes.
+const RESERVED_BUCKET_NAMES: [&str; 4] = ["admin", "healthz", "limits", "readyz"];
+
+/// Directories the server keeps directly below `base_path`, beside the
+/// bucket directories. Bucket names cannot start with a dot (see
+/// `ensure_bucket_name_safe`), so none of these ever collides with a bucket.
+pub(crate) mod side_dirs {
+    pub const MULTIPART: &str = ".multipart";
+    pub const VERSIONS: &str = ".versions";
+    pub const TRASH: &str = ".trash";
+    pub const RECYCLE: &str = ".recycle";
+    pub const SNAPSHOTS: &str = ".snapshots";
+    pub const RECLAIM: &str = ".reclaim";
+    pub const CAS: &str = ".cas";
+}
+pub(crate) const SUPPORTED_REGIONS: [&str; 16] = [
     "local",
     "us-east-1",
     "us-east-2",
@@ -112,31 +541,24 @@ impl StorageService {
         Self {
             db,
             base_path: base_path.into(),
+            options: StorageOptions::default(),
+            uploads: UploadRegistry::default(),
+            jobs: JobQueue::default(),
+            reclaim: ReclaimQueue::default(),
+            events: EventBus::default(),
+            block_cache: BlockCache::default(),
+            volume: VolumeHealth::default(),
+            key_locks: KeyLocks::default(),
+            config_locks: KeyLocks::default(),
+            assemblies: AssemblyRegistry::default(),
+            shard_layout: ShardLayout::default(),
         }
     }
 
-    /// Basic key validation to avoid trivial path traversal vectors.
-    ///
-    /// Rejects keys that begin with `/` or contain `..`. This is intentionally
-    /// simple — you should replace it with a more robust sanitizer if you
-    /// accept untrusted keys.
-    fn ensure_key_safe(&self, key: &str) -> StorageResult<()> {
-        if key.is_empty() {
-            return Err(StorageError::InvalidObjectKey);
-        }
-        if key.len() > MAX_OBJECT_KEY_LEN {
-            return Err(StorageError::InvalidObjectKey);
-        }
-        if key.starts_with('/') || key.contains("..") {
-            return Err(StorageError::InvalidObjectKey);
-        }
-        if key
-            .bytes()
-            .any(|b| b.is_ascii_control() || b == b'\\' || b == b'\0')
-        {
-            return Err(StorageError::InvalidObjectKey);
-        }
-        Ok(())
+    /// Replace the default options.
+    pub fn with_options(mut self, options: StorageOptions) -> Self {
+        self.options = options;
+        self
     }
 
     /// Validate bucket name format.
@@ -147,9 +569,10 @@ impl StorageService {
     /// - cannot start/end with dot or hyphen
     /// - cannot contain consecutive dots or dot-hyphen patterns
     /// - cannot look like an IPv4 address
+    /// - cannot be one of the server's own route names (`admin`, ...)
     ///
     /// Ensures predictable directory structure and prevents invalid inputs.
-    fn ensure_bucket_name_safe(&self, name: &str) -> StorageResult<()> {
+    pub(crate) fn ensure_bucket_name_safe(&self, name: &str) -> StorageResult<()> {
         let trimmed = name.trim();
         if trimmed != name {
             return Err(StorageError::InvalidBucketName {
@@ -159,7 +582,7 @@ impl StorageService {
         }
 
         let len = name.len();
-        if len < BUCKET_NAME_MIN_LEN || len > BUCKET_NAME_MAX_LEN {
+        if !(BUCKET_NAME_MIN_LEN..=BUCKET_NAME_MAX_LEN).contains(&len) {
             return Err(StorageError::InvalidBucketName {
                 name: name.to_string(),
                 reason: "must be between 3 and 63 characters".into(),
@@ -177,6 +600,7 @@ impl StorageService {
             });
         }
 
+        // A leading dot also keeps buckets clear of the `side_dirs`.
         if name.starts_with('.')
             || name.ends_with('.')
             || name.starts_with('-')
@@ -202,13 +626,20 @@ impl StorageService {
             });
         }
 
+        if RESERVED_BUCKET_NAMES.contains(&name) {
+            return Err(StorageError::InvalidBucketName {
+                name: name.to_string(),
+                reason: "is reserved for server endpoints".into(),
+            });
+        }
+
         Ok(())
     }
 
     /// Validate region string against SUPPORTED_REGIONS.
     ///
     /// Case-insensitive comparison. Returns UnsupportedRegion on mismatch.
-    fn ensure_region_valid(&self, region: &str) -> StorageResult<()> {
+    pub(crate) fn ensure_region_valid(&self, region: &str) -> StorageResult<()> {
         if SUPPORTED_REGIONS
             .iter()
             .any(|candidate| candidate.eq_ignore_ascii_case(region))
@@ -221,75 +652,88 @@ impl StorageService {
     /// Compute the physical base folder path for a bucket.
     ///
     /// This does not check for existence. Used for building object paths.
-    fn bucket_root(&self, bucket_name: &str) -> PathBuf {
+    pub(crate) fn bucket_root(&self, bucket_name: &str) -> PathBuf {
         let mut path = self.base_path.clone();
         path.push(bucket_name);
         path
     }
 
-    /// Generate two-level shard identifiers for an object key.
-    ///
-    /// Uses MD5(bucket/key) and returns the first two bytes as lowercase
-    /// hexadecimal strings (00–ff). Reduces file count per directory.
-    fn object_shards(bucket_name: &str, key: &str) -> (String, String) {
-        let digest = md5::compute(format!("{}/{}", bucket_name, key));
-        (format!("{:02x}", digest[0]), format!("{:02x}", digest[1]))
-    }
-
     /// Construct a fully-qualified object payload path.
     ///
-    /// Combines base_path/bucket/{shard}/{shard}/{key}.
+    /// Combines {volume root}/bucket/{shard}.../{key}, where `None` is the
+    /// default volume (`base_path`, see `placement`) and the shards follow
+    /// the current layout (see `shard_layout`). While payloads are being
+    /// migrated between layouts, this is wherever the payload is found.
     /// Parent directories may not exist yet.
-    fn object_path(&self, bucket_name: &str, key: &str) -> PathBuf {
-        let (shard_a, shard_b) = Self::object_shards(bucket_name, key);
-        let mut path = self.bucket_root(bucket_name);
-        path.push(shard_a);
-        path.push(shard_b);
-        path.push(key);
-        path
+    pub(crate) fn object_path_on(
+        &self,
+        volume: Option<&str>,
+        bucket_name: &str,
+        key: &str,
+    ) -> PathBuf {
+        let current = self.layout_path_on(volume, bucket_name, key);
+        let previous = self.shard_layout.previous();
+        // Only checks the disk while migrating.
+        if previous.is_empty() || current.is_file() {
+            return current;
+        }
+        previous
+            .into_iter()
+            .map(|layout| self.object_path_at(volume, bucket_name, key, layout))
+            .find(|path| path.is_file())
+            .unwrap_or(current)
     }
 
     /// Fetch bucket metadata from SQLite.
     ///
     /// Returns BucketNotFound if missing.
     /// Validates bucket name before querying.
-    async fn fetch_bucket(&self, bucket: &str) -> StorageResult<Bucket> {
+    pub(crate) async fn fetch_bucket(&self, bucket: &str) -> StorageResult<Bucket> {
         self.ensure_bucket_name_safe(bucket)?;
-        sqlx::query_as::<sqlx::sqlite::Sqlite, Bucket>(
-            "SELECT id, name, owner_id, region, created_at, versioning_enabled
-             FROM buckets WHERE name = ?",
-        )
-        .bind(bucket)
-        .fetch_one(&*self.db)
-        .await
-        .map_err(|err| match err {
-            sqlx::Error::RowNotFound => StorageError::BucketNotFound(bucket.to_string()),
-            other => StorageError::Sqlx(other),
-        })
+        let sql = format!("SELECT {BUCKET_COLUMNS} FROM buckets WHERE name = ?");
+        let query = sqlx::query_as::<sqlx::sqlite::Sqlite, Bucket>(&sql).bind(bucket);
+        timed(Phase::Db, query.fetch_one(&*self.db))
+            .await
+            .map_err(|err| match err {
+                sqlx::Error::RowNotFound => StorageError::BucketNotFound(bucket.to_string()),
+                other => StorageError::Sqlx(other),
+            })
+    }
+
+    /// Fetch a bucket that is about to be modified.
+    ///
+    /// Returns BucketReadOnly when the bucket is flagged read-only and
+    /// VolumeReadOnly after repeated disk write failures.
+    pub(crate) async fn fetch_writable_bucket(&self, bucket: &str) -> StorageResult<Bucket> {
+        let bucket_rec = self.fetch_bucket(bucket).await?;
+        if bucket_rec.read_only {
+            return Err(StorageError::BucketReadOnly(bucket_rec.name));
+        }
+        if self.volume.is_read_only() {
+            return Err(StorageError::VolumeReadOnly(DEFAULT_VOLUME.to_string()));
+        }
+        Ok(bucket_rec)
     }
 
     /// Fetch a non-deleted object metadata record.
     ///
     /// Queries SQLite by key and bucket_id.
     /// Returns ObjectNotFound if record missing or marked deleted.
-    async fn fetch_object(&self, bucket: &Bucket, key: &str) -> StorageResult<Object> {
-        sqlx::query_as::<_, Object>(
-            "SELECT id, bucket_id, key, filename, content_type, size_bytes, etag,
-                    storage_class, last_modified, version_id, is_deleted
-             FROM objects
-             WHERE key = ? AND bucket_id = ? AND is_deleted = 0",
-        )
-        .bind(key)
-        .bind(bucket.id)
-        .fetch_one(&*self.db)
-        .await
-        .map_err(|err| match err {
-            sqlx::Error::RowNotFound => StorageError::ObjectNotFound {
-                bucket: bucket.name.clone(),
-                key: key.to_string(),
-            },
-            other => StorageError::Sqlx(other),
-        })
+    pub(crate) async fn fetch_object(&self, bucket: &Bucket, key: &str) -> StorageResult<Object> {
+        let sql = format!(
+            "SELECT {OBJECT_COLUMNS} FROM objects
+             WHERE key = ? AND bucket_id = ? AND is_deleted = 0"
+        );
+        let query = sqlx::query_as::<_, Object>(&sql).bind(key).bind(bucket.id);
+        timed(Phase::Db, query.fetch_one(&*self.db))
+            .await
+            .map_err(|err| match err {
+                sqlx::Error::RowNotFound => StorageError::ObjectNotFound {
+                    bucket: bucket.name.clone(),
+                    key: key.to_string(),
+                },
+                other => StorageError::Sqlx(other),
+            })
     }
 
     /// Stream-upload an object to disk and update metadata.
@@ -298,109 +742,380 @@ impl StorageService {
     /// - Computes MD5/etag and size while streaming.
     /// - Atomically renames into final location.
     /// - Upserts metadata row (S3-like overwrite semantics).
+    /// - Reports progress in `self.uploads` while the body is received.
+    ///
+    /// When `decode_content_encoding` is enabled, gzip/deflate bodies are
+    /// decoded to identity on the way in; otherwise the bytes are stored as
+    /// sent and the encoding is recorded for replay on GET.
     ///
     /// Ensures durable writes (fsync) and cleans up temp files on errors.
     pub async fn upload_object_stream<S>(
         &self,
         bucket: &str,
         key: &str,
-        content_type: Option<String>,
+        params: PutObjectParams,
         stream: S,
     ) -> StorageResult<Object>
     where
         S: Stream<Item = io::Result<Bytes>> + Send + 'static,
     {
         self.ensure_key_safe(key)?;
-        let bucket_rec = self.fetch_bucket(bucket).await?;
+        tagging::validate_tags(&params.tags)?;
+        user_metadata::validate_user_metadata(&params.user_metadata)?;
+        params.checksums.validate()?;
+        let bucket_rec = self.fetch_writable_bucket(bucket).await?;
+        self.ensure_key_allowed(&bucket_rec, key).await?;
+        let acl = self.resolve_object_acl(&bucket_rec, params.acl.as_deref())?;
+        let (_progress, stream) =
+            self.uploads
+                .track(&bucket_rec.name, key, params.content_length, stream);
+        let stream = checksum::verify_stream(stream, params.checksums.clone());
+        let (stream, content_encoding) = content_encoding::prepare_upload_stream(
+            stream,
+            params.content_encoding.as_deref(),
+            self.options.decode_content_encoding,
+        );
+        let stream = self.limit_object_stream(key, params.content_length, stream)?;
+
+        let stream = match self.quota_headroom(&bucket_rec, key).await? {
+            Some(headroom) => {
+                let reason = format!("`{}` may not exceed {} bytes", key, headroom);
+                if params.content_length.is_some_and(|len| len > headroom) {
+                    return Err(StorageError::QuotaExceeded(reason));
+                }
+                Box::pin(quota::limit_stream(stream, headroom, reason))
+                    as content_encoding::ByteStream
+            }
+            None => Box::pin(stream),
+        };
 
-        let file_path = self.object_path(&bucket_rec.name, key);
+        let file_path = self.placed_path(&bucket_rec, key).await?;
         let parent = file_path.parent().map(Path::to_path_buf).ok_or_else(|| {
-            StorageError::Io(io::Error::new(
-                ErrorKind::Other,
-                "object path missing parent directory",
-            ))
+            StorageError::Io(io::Error::other("object path missing parent directory"))
         })?;
-        fs::create_dir_all(&parent).await?;
-        let tmp_path = parent.join(format!(".tmp-{}", Uuid::new_v4()));
-        let mut file = File::create(&tmp_path).await?;
+        let (cipher, encryption) = match params.customer_key.as_ref() {
+            Some(key) => {
+                let (cipher, encryption) = ObjectCipher::for_new_payload(key);
+                (Some(cipher), Some(encryption))
+            }
+            None => (None, None),
+        };
+        let mut staged = self.stage_payload(&parent, stream, cipher.as_ref()).await?;
+        // Client digests describe the body as sent; keep them unless it was
+        // decoded on the way in.
+        let decoded = content_encoding.is_none()
+            && !content_encoding::parse_codings(params.content_encoding.as_deref()).is_empty();
+        if !decoded {
+            staged.sha256 = staged.sha256.or(params.checksums.sha256);
+            staged.crc32c = params.checksums.crc32c;
+        }
+        let etag = format!("{:x}", staged.md5);
+        let attrs = ObjectAttributes {
+            content_type: params.content_type,
+            content_encoding,
+            tags: params.tags,
+            user_metadata: params.user_metadata,
+            acl: Some(acl),
+            cache_control: params.cache_control,
+            content_disposition: params.content_disposition,
+            expires: params.expires,
+            parts: Vec::new(),
+            retention: params.retention,
+            encryption,
+            uploader: params.uploader,
+        };
+        let object = self
+            .commit_payload(&bucket_rec, key, staged, etag, attrs)
+            .await?;
+        if let Err(err) = self
+            .record_prefix_transfer(bucket_rec.id, key, object.size_bytes.max(0) as u64, 0)
+            .await
+        {
+            debug!("failed to record upload of `{}/{}`: {}", bucket, key, err);
+        }
+        Ok(object)
+    }
+
+    /// Write `stream` to a temporary file for `dir` (see `temp_path`), computing size and MD5
+    /// (and SHA-256 when `compute_sha256` is on). With `cipher`, the bytes
+    /// are encrypted on their way to disk; digests describe the plaintext.
+    ///
+    /// The file is synced before returning. It is removed on any error, and
+    /// also when the returned payload (or this future, as when the client
+    /// disconnects) is dropped before the payload is persisted.
+    pub(crate) async fn stage_payload<S>(
+        &self,
+        dir: &Path,
+        stream: S,
+        cipher: Option<&ObjectCipher>,
+    ) -> StorageResult<StagedPayload>
+    where
+        S: Stream<Item = Result<Bytes, io::Error>>,
+    {
+        // `dir` is created even when staging elsewhere, for the rename.
+        if self.options.staging_dir.is_some() {
+            self.track_write(fs::create_dir_all(dir).await)?;
+        }
+        let tmp_path = self.temp_path(dir);
+        let tmp_dir = tmp_path.parent().unwrap_or(dir);
+        let mut file = self.track_write(create_in_dir(tmp_dir, &tmp_path).await)?;
+        let mut staging = StagingFile::new(tmp_path, self.uploads.clone());
 
         let mut size_bytes: i64 = 0;
         let mut digest = Context::new();
+        // Encrypted payloads differ even for equal content, so they are
+        // never deduplicated.
+        let dedup = self.options.dedup && cipher.is_none();
+        let mut sha256 = (self.options.compute_sha256 || dedup).then(Sha256::new);
+        let mut keystream = cipher.map(|cipher| cipher.keystream_at(0));
+        let (mut hashing, mut writing) = (Duration::ZERO, Duration::ZERO);
         pin_mut!(stream);
         while let Some(chunk_res) = stream.next().await {
             let chunk = match chunk_res {
                 Ok(chunk) => chunk,
                 Err(err) => {
-                    let _ = fs::remove_file(&tmp_path).await;
+                    if let Some(mismatch) = checksum::mismatch(&err) {
+                        return Err(if mismatch.is_content_sha256() {
+                            StorageError::ContentSha256Mismatch(mismatch.to_string())
+                        } else {
+                            StorageError::BadDigest(mismatch.to_string())
+                        });
+                    }
+                    if let Some(exceeded) = quota::overflow(&err) {
+                        return Err(exceeded);
+                    }
+                    if let Some(too_large) = size_limit::overflow(&err) {
+                        return Err(too_large);
+                    }
+                    if let Some(mismatch) = aws_chunked::mismatch(&err) {
+                        return Err(mismatch);
+                    }
+                    if err.kind() == ErrorKind::InvalidData {
+                        return Err(StorageError::InvalidContent(err.to_string()));
+                    }
                     return Err(StorageError::Io(err));
                 }
             };
             size_bytes += chunk.len() as i64;
+            let started = Instant::now();
             digest.consume(&chunk);
-            if let Err(err) = file.write_all(&chunk).await {
-                let _ = fs::remove_file(&tmp_path).await;
-                return Err(StorageError::Io(err));
+            if let Some(sha256) = sha256.as_mut() {
+                sha256.update(&chunk);
             }
+            hashing += started.elapsed();
+            let started = Instant::now();
+            match keystream.as_mut() {
+                Some(keystream) => {
+                    let mut encrypted = chunk.to_vec();
+                    keystream.apply_keystream(&mut encrypted);
+                    self.track_write(file.write_all(&encrypted).await)?;
+                }
+                None => self.track_write(file.write_all(&chunk).await)?,
+            }
+            writing += started.elapsed();
+            staging.add_written(chunk.len());
         }
-        if let Err(err) = file.flush().await {
-            let _ = fs::remove_file(&tmp_path).await;
-            return Err(StorageError::Io(err));
-        }
-        if let Err(err) = file.sync_all().await {
-            let _ = fs::remove_file(&tmp_path).await;
-            return Err(StorageError::Io(err));
-        }
+        let started = Instant::now();
+        self.track_write(file.flush().await)?;
+        self.track_write(file.sync_all().await)?;
+        self.volume.record_write_ok();
+        record(Phase::DiskWrite, writing + started.elapsed());
+        record(Phase::Hash, hashing);
 
-        if let Err(err) = fs::rename(&tmp_path, &file_path).await {
-            if err.kind() == ErrorKind::AlreadyExists {
-                fs::remove_file(&file_path).await?;
-                fs::rename(&tmp_path, &file_path).await?;
-            } else {
-                let _ = fs::remove_file(&tmp_path).await;
-                return Err(StorageError::Io(err));
-            }
+        let sha256 = sha256.map(|h| h.finalize());
+        Ok(StagedPayload {
+            file: staging,
+            size_bytes,
+            md5: digest.compute(),
+            sha256: sha256
+                .filter(|_| self.options.compute_sha256)
+                .map(|h| general_purpose::STANDARD.encode(h)),
+            crc32c: None,
+            content_digest: sha256.filter(|_| dedup).map(|h| format!("{:x}", h)),
+        })
+    }
+
+    /// Make a staged payload the live content of `key` and upsert its
+    /// metadata (S3-like overwrite semantics). `tags` replace the key's tag
+    /// set in the same transaction.
+    ///
+    /// In versioned buckets the previous version is archived and the new one
+    /// gets a fresh version id; otherwise the previous payload is recycled
+    /// when retention is on. If the metadata write fails either is put back.
+    pub(crate) async fn commit_payload(
+        &self,
+        bucket_rec: &Bucket,
+        key: &str,
+        staged: StagedPayload,
+        etag: String,
+        attrs: ObjectAttributes,
+    ) -> StorageResult<Object> {
+        let volume = self.place_payload(bucket_rec, key).await?;
+        let file_path = self.layout_path_on(volume.as_deref(), &bucket_rec.name, key);
+        // Errors below drop `staged.file`, which removes the temp file.
+        let scan_mode = self
+            .screen_staged(
+                bucket_rec,
+                key,
+                staged.file.path(),
+                attrs.encryption.is_some(),
+            )
+            .await?;
+        let _key_guard = self.key_locks.lock(bucket_rec.id, key).await;
+        self.check_quota(bucket_rec, key, staged.size_bytes).await?;
+        let retention = self.resolve_retention(bucket_rec, attrs.retention).await?;
+        let sealer = self.metadata_sealer(bucket_rec)?;
+        let upload = UploadFacts {
+            key,
+            content_type: attrs.content_type.as_deref(),
+            size: staged.size_bytes.max(0) as u64,
+            uploader: attrs.uploader.as_deref(),
+        };
+        let tags = self.with_auto_tags(bucket_rec, upload, attrs.tags).await?;
+        let change_kind = self.write_kind(bucket_rec, key).await?;
+        let previous_path = self.live_path(bucket_rec, key).await?;
+        let archived = self.archive_current_version(bucket_rec, key).await?;
+        let recycled = if archived.is_none() && !bucket_rec.versioning_enabled {
+            self.recycle_previous_payload(bucket_rec, key).await?
+        } else {
+            None
+        };
+        let version_id = bucket_rec.versioning_enabled.then(new_version_id);
+
+        if let Some(parent) = file_path.parent() {
+            self.track_write(fs::create_dir_all(parent).await)?;
         }
+        // Placed payloads are not linked to blobs on the default volume.
+        let digest = staged
+            .content_digest
+            .as_deref()
+            .filter(|_| volume.is_none());
+        self.track_write(self.persist_payload(staged.file, digest, &file_path).await)?;
 
-        let filename = key.split('/').last().unwrap_or(key).to_string();
+        let filename = key.split('/').next_back().unwrap_or(key).to_string();
         let last_modified = Utc::now();
-        let etag = format!("{:x}", digest.compute());
 
-        let insert_result = sqlx::query_as::<_, Object>(
-            r#"
+        let insert_result = timed(Phase::Db, async {
+            let mut tx = self.db.begin().await?;
+            let obj = sqlx::query_as::<_, Object>(&format!(
+                r#"
             INSERT INTO objects (
-                id, bucket_id, key, filename, content_type, size_bytes,
-                etag, storage_class, last_modified, version_id, is_deleted
-            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, 0)
+                id, bucket_id, key, filename, content_type, content_encoding, size_bytes,
+                etag, checksum_sha256, checksum_crc32c, storage_class, last_modified, version_id,
+                is_deleted, acl, cache_control, content_disposition, expires, volume
+            ) VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, 0, ?, ?, ?, ?, ?)
             ON CONFLICT(bucket_id, key) DO UPDATE SET
                 filename = excluded.filename,
                 content_type = excluded.content_type,
+                content_encoding = excluded.content_encoding,
                 size_bytes = excluded.size_bytes,
                 etag = excluded.etag,
+                checksum_sha256 = excluded.checksum_sha256,
+                checksum_crc32c = excluded.checksum_crc32c,
                 storage_class = excluded.storage_class,
                 last_modified = excluded.last_modified,
                 version_id = excluded.version_id,
-                is_deleted = 0
-            RETURNING id, bucket_id, key, filename, content_type, size_bytes,
-                      etag, storage_class, last_modified, version_id, is_deleted
-            "#,
-        )
-        .bind(Uuid::new_v4())
-        .bind(bucket_rec.id)
-        .bind(key)
-        .bind(&filename)
-        .bind(content_type.clone())
-        .bind(size_bytes)
-        .bind(&etag)
-        .bind("STANDARD")
-        .bind(last_modified)
-        .bind::<Option<String>>(None)
-        .fetch_one(&*self.db)
+                is_deleted = 0,
+                acl = excluded.acl,
+                cache_control = excluded.cache_control,
+                content_disposition = excluded.content_disposition,
+                expires = excluded.expires,
+                volume = excluded.volume
+            RETURNING {OBJECT_COLUMNS}
+            "#
+            ))
+            .bind(Uuid::new_v4())
+            .bind(bucket_rec.id)
+            .bind(key)
+            .bind(&filename)
+            .bind(&attrs.content_type)
+            .bind(&attrs.content_encoding)
+            .bind(staged.size_bytes)
+            .bind(&etag)
+            .bind(&staged.sha256)
+            .bind(&staged.crc32c)
+            .bind("STANDARD")
+            .bind(last_modified)
+            .bind(&version_id)
+            .bind(&attrs.acl)
+            .bind(&attrs.cache_control)
+            .bind(&attrs.content_disposition)
+            .bind(&attrs.expires)
+            .bind(&volume)
+            .fetch_one(&mut *tx)
+            .await?;
+            replace_tags(&mut tx, sealer.as_ref(), bucket_rec.id, key, &tags).await?;
+            replace_user_metadata(
+                &mut tx,
+                sealer.as_ref(),
+                bucket_rec.id,
+                key,
+                &attrs.user_metadata,
+            )
+            .await?;
+            replace_parts(&mut tx, bucket_rec.id, key, &attrs.parts).await?;
+            if let (Some(version_id), Some(retention)) = (&version_id, &retention) {
+                insert_retention(&mut tx, bucket_rec.id, key, version_id, retention).await?;
+            }
+            replace_encryption(
+                &mut tx,
+                bucket_rec.id,
+                key,
+                version_id.as_deref().unwrap_or(NULL_VERSION),
+                attrs.encryption.as_ref(),
+            )
+            .await?;
+            replace_scan(
+                &mut tx,
+                bucket_rec.id,
+                key,
+                version_id.as_deref().unwrap_or(NULL_VERSION),
+                scan_mode == Some(ScanMode::Async),
+            )
+            .await?;
+            insert_change(&mut *tx, bucket_rec.id, &NewChange::of(change_kind, &obj)).await?;
+            tx.commit().await?;
+            Ok::<_, sqlx::Error>(obj)
+        })
         .await;
 
         match insert_result {
-            Ok(obj) => Ok(obj),
+            Ok(obj) => {
+                // A previous payload on another volume was not replaced by
+                // the rename.
+                if previous_path != file_path
+                    && archived.is_none()
+                    && recycled.is_none()
+                    && let Err(err) = self.remove_payload(&bucket_rec.name, &previous_path).await
+                {
+                    debug!("could not remove previous payload of {}: {}", key, err);
+                }
+                // The key may have been deleted with its payload still in
+                // the trash; it is superseded now.
+                if self.options.deleted_retention.is_some()
+                    && let Err(err) = self.remove_trashed(&bucket_rec.name, obj.id).await
+                {
+                    debug!("could not remove trashed payload of {}: {}", key, err);
+                }
+                self.events.object(
+                    EventKind::ObjectCreated,
+                    &bucket_rec.name,
+                    key,
+                    Some(obj.size_bytes),
+                    obj.etag.clone(),
+                );
+                Ok(obj)
+            }
             Err(err) => {
                 let _ = fs::remove_file(&file_path).await;
+                if let Some(recycled) = recycled {
+                    self.unrecycle_payload(bucket_rec, &recycled, &previous_path)
+                        .await;
+                }
+                if let Some(archived) = archived {
+                    self.unarchive_version(bucket_rec, &archived, &previous_path)
+                        .await;
+                }
                 Err(StorageError::Sqlx(err))
             }
         }
@@ -418,18 +1133,24 @@ impl StorageService {
         self.ensure_key_safe(key)?;
         let bucket_rec = self.fetch_bucket(bucket).await?;
         let object = self.fetch_object(&bucket_rec, key).await?;
+        self.ensure_not_quarantined(&object).await?;
 
-        let file_path = self.object_path(&bucket_rec.name, key);
-        let file = File::open(&file_path).await.map_err(|err| {
-            if err.kind() == io::ErrorKind::NotFound {
-                StorageError::ObjectNotFound {
+        let file = match timed(
+            Phase::DiskRead,
+            self.open_live_payload(object.volume.as_deref(), &bucket_rec.name, key),
+        )
+        .await
+        {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => self
+                .open_tiered(&bucket_rec, &object)
+                .await?
+                .ok_or_else(|| StorageError::ObjectNotFound {
                     bucket: bucket.to_string(),
                     key: key.to_string(),
-                }
-            } else {
-                StorageError::Io(err)
-            }
-        })?;
+                })?,
+            Err(err) => return Err(StorageError::Io(err)),
+        };
 
         Ok((object, file))
     }
@@ -458,65 +1179,133 @@ impl StorageService {
         bucket: &str,
         params: ListObjectsParams,
     ) -> StorageResult<ListObjectsResult> {
+        if let Some(prefix) = &params.prefix {
+            self.ensure_prefix_safe(prefix)?;
+        }
         let bucket_rec = self.fetch_bucket(bucket).await?;
         let max_keys = params.max_keys.clamp(1, 1000);
         let fetch_limit = max_keys + 1;
+        // An empty delimiter groups nothing, as in S3.
+        let delimiter = params
+            .delimiter
+            .as_deref()
+            .filter(|delim| !delim.is_empty());
+        let prefix = params.prefix.as_deref().unwrap_or_default();
 
-        let mut builder = QueryBuilder::<Sqlite>::new(
-            "SELECT id, bucket_id, key, filename, content_type, size_bytes, etag, \
-             storage_class, last_modified, version_id, is_deleted \
-             FROM objects WHERE bucket_id = ",
-        );
-        builder.push_bind(bucket_rec.id);
-        builder.push(" AND is_deleted = 0");
-
-        if let Some(prefix) = &params.prefix {
-            builder.push(" AND key LIKE ");
-            builder.push_bind(format!("{}%", prefix));
-        }
+        // Every condition but the start point, shared by the scans below.
+        let push_filters = |builder: &mut QueryBuilder<'_, Sqlite>| {
+            builder.push(" FROM objects WHERE bucket_id = ");
+            builder.push_bind(bucket_rec.id);
+            builder.push(" AND is_deleted = 0");
+            if let Some(prefix) = &params.prefix {
+                builder.push(" AND key GLOB ");
+                builder.push_bind(keys::prefix_glob(prefix));
+            }
+            if let Some(end_key) = &params.end_key {
+                builder.push(" AND key <= ");
+                builder.push_bind(end_key.clone());
+            }
+            if let Some(class) = &params.storage_class {
+                builder.push(" AND storage_class = ");
+                builder.push_bind(class.to_ascii_uppercase());
+            }
+            if let Some(min_size) = params.min_size {
+                builder.push(" AND size_bytes >= ");
+                builder.push_bind(i64::try_from(min_size).unwrap_or(i64::MAX));
+            }
+            if let Some(max_size) = params.max_size {
+                builder.push(" AND size_bytes <= ");
+                builder.push_bind(i64::try_from(max_size).unwrap_or(i64::MAX));
+            }
+        };
 
-        if let Some(token) = params
+        // Entries of the listing are keys, or with a delimiter the common
+        // prefix a key rolls up into, so that prefixes count toward
+        // `max_keys` however many keys they hold. Keys are read in key
+        // order (BINARY collation, bytes, as S3 orders them), which is also
+        // the order of their entries, and a common prefix is skipped with a
+        // range seek past it, so a page reads about as many rows as it
+        // returns wherever it starts.
+        let mut start = match params
             .continuation_token
             .as_ref()
             .or(params.start_after.as_ref())
         {
-            builder.push(" AND key > ");
-            builder.push_bind(token);
-        }
-
-        builder.push(" ORDER BY key ASC LIMIT ");
-        builder.push_bind(fetch_limit as i64);
-
-        let mut rows: Vec<Object> = builder.build_query_as().fetch_all(&*self.db).await?;
-
-        let mut is_truncated = false;
-        let mut next_continuation_token = None;
-        if rows.len() == fetch_limit {
-            if let Some(last) = rows.pop() {
-                next_continuation_token = Some(last.key.clone());
+            // A continuation token naming a common prefix resumes after
+            // every key grouped under it.
+            Some(token)
+                if params.continuation_token.is_some()
+                    && delimiter.is_some_and(|delim| token.ends_with(delim))
+                    && token.len() > prefix.len() =>
+            {
+                ScanStart::past_prefix(token)
             }
-            is_truncated = true;
-        }
-
+            Some(token) => ScanStart::After(token.clone()),
+            None => ScanStart::Beginning,
+        };
         let mut contents = Vec::new();
-        let mut common_prefixes = BTreeSet::new();
-        for obj in rows.into_iter() {
-            if let Some(delim) = &params.delimiter {
-                if let Some(prefix) =
-                    compute_common_prefix(&obj.key, params.prefix.as_deref(), delim)
+        let mut common_prefixes = Vec::new();
+        let mut entries = 0;
+        // The token names the last entry returned.
+        let mut last_entry = None;
+        let mut is_truncated = false;
+        'scan: loop {
+            let wanted = fetch_limit - entries;
+            let mut builder = QueryBuilder::<Sqlite>::new(format!("SELECT {OBJECT_COLUMNS}"));
+            push_filters(&mut builder);
+            start.push_condition(&mut builder);
+            builder.push(" ORDER BY key ASC LIMIT ");
+            builder.push_bind(wanted as i64);
+            let rows = timed(Phase::Db, builder.build().fetch_all(&*self.db)).await?;
+            let exhausted = rows.len() < wanted;
+            let mut open_prefix: Option<String> = None;
+            for row in &rows {
+                let object = Object::from_row(row)?;
+                if open_prefix
+                    .as_ref()
+                    .is_some_and(|common| object.key.starts_with(common.as_str()))
                 {
-                    common_prefixes.insert(prefix);
                     continue;
                 }
+                open_prefix = None;
+                if entries == max_keys {
+                    is_truncated = true;
+                    break 'scan;
+                }
+                entries += 1;
+                let common = delimiter.and_then(|delim| {
+                    object.key[prefix.len()..]
+                        .find(delim)
+                        .map(|at| object.key[..prefix.len() + at + delim.len()].to_string())
+                });
+                match common {
+                    Some(common) => {
+                        last_entry = Some(common.clone());
+                        common_prefixes.push(common.clone());
+                        open_prefix = Some(common);
+                    }
+                    None => {
+                        last_entry = Some(object.key.clone());
+                        contents.push(object);
+                    }
+                }
+            }
+            if exhausted {
+                break;
             }
-            contents.push(obj);
+            start = match (open_prefix, rows.last()) {
+                (Some(common), _) => ScanStart::past_prefix(&common),
+                (None, Some(row)) => ScanStart::After(row.try_get("key")?),
+                (None, None) => break,
+            };
         }
 
         let key_count = contents.len() + common_prefixes.len();
+        let next_continuation_token = if is_truncated { last_entry } else { None };
 
         Ok(ListObjectsResult {
             objects: contents,
-            common_prefixes: common_prefixes.into_iter().collect(),
+            common_prefixes,
             is_truncated,
             next_continuation_token,
             key_count,
@@ -526,21 +1315,32 @@ impl StorageService {
     /// Soft-delete an object and attempt to remove its payload.
     ///
     /// - Sets `is_deleted = 1`
-    /// - Deletes physical file best-effort
+    /// - Removes the physical file (in the background when `reclaim` workers
+    ///   are running)
     /// - Prunes empty bucket directories
     ///
     /// Idempotent: repeated calls return ObjectNotFound if already deleted.
+    ///
+    /// In versioned buckets (and for keys with version history) the current
+    /// version is kept and a delete marker is returned instead; otherwise the
+    /// removed object is returned with `is_deleted` set.
     pub async fn delete_object(&self, bucket: &str, key: &str) -> StorageResult<Object> {
         self.ensure_key_safe(key)?;
-        let bucket_rec = self.fetch_bucket(bucket).await?;
-        let object = self.fetch_object(&bucket_rec, key).await?;
+        let bucket_rec = self.fetch_writable_bucket(bucket).await?;
+        let _key_guard = self.key_locks.lock(bucket_rec.id, key).await;
+        let mut object = self.fetch_object(&bucket_rec, key).await?;
+        if bucket_rec.versioning_enabled || object.version_id.is_some() {
+            return self.put_delete_marker(&bucket_rec, key).await;
+        }
 
-        let result =
-            sqlx::query("UPDATE objects SET is_deleted = 1 WHERE key = ? AND bucket_id = ?")
-                .bind(key)
-                .bind(bucket_rec.id)
-                .execute(&*self.db)
-                .await?;
+        let result = sqlx::query(
+            "UPDATE objects SET is_deleted = 1, deleted_at = ? WHERE key = ? AND bucket_id = ?",
+        )
+        .bind(Utc::now())
+        .bind(key)
+        .bind(bucket_rec.id)
+        .execute(&*self.db)
+        .await?;
 
         if result.rows_affected() == 0 {
             return Err(StorageError::ObjectNotFound {
@@ -549,20 +1349,14 @@ impl StorageService {
             });
         }
 
-        let file_path = self.object_path(&bucket_rec.name, key);
-        match fs::remove_file(&file_path).await {
-            Ok(_) => debug!("removed physical file {}", file_path.display()),
-            Err(err) if err.kind() == io::ErrorKind::NotFound => {
-                debug!("file {} already missing", file_path.display());
-            }
-            Err(err) => return Err(StorageError::Io(err)),
-        }
-
-        if let Some(parent) = file_path.parent() {
-            let bucket_root = self.bucket_root(&bucket_rec.name);
-            self.prune_empty_dirs(parent, &bucket_root).await;
-        }
+        self.record_change(bucket_rec.id, NewChange::deleted(key, None))
+            .await;
+        self.discard_deleted_payload(&bucket_rec.name, object.id, key)
+            .await?;
 
+        self.events
+            .object(EventKind::ObjectDeleted, &bucket_rec.name, key, None, None);
+        object.is_deleted = true;
         Ok(object)
     }
 
@@ -586,6 +1380,13 @@ impl StorageService {
             region: normalized_region.clone(),
             created_at: Utc::now(),
             versioning_enabled: false,
+            read_only: false,
+            cache_control: None,
+            expires_secs: None,
+            default_acl: None,
+            enforce_bucket_owner_full_control: false,
+            scan_uploads: None,
+            encrypt_metadata: false,
         };
 
         match sqlx::query(
@@ -601,7 +1402,10 @@ impl StorageService {
         .execute(&*self.db)
         .await
         {
-            Ok(_) => Ok(bucket),
+            Ok(_) => {
+                self.events.bucket(EventKind::BucketCreated, name, None);
+                Ok(bucket)
+            }
             Err(err) if is_unique_violation(&err) => {
                 Err(StorageError::BucketAlreadyExists(name.to_string()))
             }
@@ -611,25 +1415,88 @@ impl StorageService {
 
     /// Delete a bucket from metadata and filesystem.
     ///
+    /// - Refuses a bucket that still holds objects, versions or delete
+    ///   markers unless `force` is set, in which case they are removed in the
+    ///   same transaction as the bucket row
     /// - Removes metadata row
-    /// - Attempts to recursively delete bucket directory
+    /// - Attempts to recursively delete bucket, recycle, trash, snapshot,
+    ///   multipart and versions directories
     /// - Ignores missing directory errors
     ///
-    /// Returns BucketNotFound if DB row missing.
-    pub async fn delete_bucket(&self, name: &str) -> StorageResult<()> {
-        self.ensure_bucket_name_safe(name)?;
-        let result = sqlx::query("DELETE FROM buckets WHERE name = ?")
-            .bind(name)
-            .execute(&*self.db)
+    /// Returns BucketNotFound if DB row missing, BucketReadOnly if the bucket
+    /// is flagged read-only, BucketNotEmpty if it holds objects and `force`
+    /// is not set, InvalidBucketState if it holds versions under Object Lock
+    /// retention.
+    pub async fn delete_bucket(&self, name: &str, force: bool) -> StorageResult<()> {
+        let bucket_rec = self.fetch_writable_bucket(name).await?;
+        self.ensure_bucket_unlocked(&bucket_rec).await?;
+        let mut tx = self.db.begin().await?;
+        if force {
+            for table in ["objects", "object_versions"] {
+                sqlx::query(&format!("DELETE FROM {table} WHERE bucket_id = ?"))
+                    .bind(bucket_rec.id)
+                    .execute(&mut *tx)
+                    .await?;
+            }
+        } else {
+            // Soft-deleted keys outside a version history do not count;
+            // delete markers and archived versions do, as in S3.
+            let remaining: i64 = sqlx::query_scalar(
+                "SELECT (SELECT COUNT(*) FROM objects
+                         WHERE bucket_id = ?1 AND (is_deleted = 0 OR version_id IS NOT NULL))
+                      + (SELECT COUNT(*) FROM object_versions WHERE bucket_id = ?1)",
+            )
+            .bind(bucket_rec.id)
+            .fetch_one(&mut *tx)
+            .await?;
+            if remaining > 0 {
+                return Err(StorageError::BucketNotEmpty(bucket_rec.name));
+            }
+        }
+        let result = sqlx::query("DELETE FROM buckets WHERE id = ? AND read_only = 0")
+            .bind(bucket_rec.id)
+            .execute(&mut *tx)
             .await?;
-
         if result.rows_affected() == 0 {
             return Err(StorageError::BucketNotFound(name.to_string()));
         }
+        tx.commit().await?;
+
+        let recycle_path = self.recycle_root(name);
+        if let Err(err) = fs::remove_dir_all(&recycle_path).await
+            && err.kind() != io::ErrorKind::NotFound
+        {
+            debug!(
+                "failed to remove recycle directory {} after delete: {}",
+                recycle_path.display(),
+                err
+            );
+        }
 
-        let bucket_path = self.bucket_root(name);
-        if let Err(err) = fs::remove_dir_all(&bucket_path).await {
-            if err.kind() != io::ErrorKind::NotFound {
+        for (what, path) in [
+            ("trash", self.trash_root(name)),
+            ("snapshot", self.snapshot_root(name)),
+            ("multipart", self.multipart_root(name)),
+            ("versions", self.versions_root(name)),
+        ] {
+            if let Err(err) = fs::remove_dir_all(&path).await
+                && err.kind() != io::ErrorKind::NotFound
+            {
+                debug!(
+                    "failed to remove {} directory {} after delete: {}",
+                    what,
+                    path.display(),
+                    err
+                );
+            }
+        }
+
+        let bucket_paths = std::iter::once(self.bucket_root(name))
+            .chain(self.options.volumes.iter().map(|spec| spec.path.join(name)));
+        for bucket_path in bucket_paths {
+            if let Err(err) = fs::remove_dir_all(&bucket_path).await
+                && err.kind() != io::ErrorKind::NotFound
+            {
                 debug!(
                     "failed to remove bucket directory {} after delete: {}",
                     bucket_path.display(),
@@ -638,9 +1505,91 @@ impl StorageService {
             }
         }
 
+        self.events.bucket(EventKind::BucketDeleted, name, None);
         Ok(())
     }
 
+    /// Set or clear a bucket's read-only flag and return the updated bucket.
+    pub async fn set_bucket_read_only(&self, name: &str, read_only: bool) -> StorageResult<Bucket> {
+        self.ensure_bucket_name_safe(name)?;
+        let updated = sqlx::query_as::<_, Bucket>(&format!(
+            "UPDATE buckets SET read_only = ? WHERE name = ? RETURNING {BUCKET_COLUMNS}"
+        ))
+        .bind(read_only)
+        .bind(name)
+        .fetch_one(&*self.db)
+        .await
+        .map_err(|err| match err {
+            sqlx::Error::RowNotFound => StorageError::BucketNotFound(name.to_string()),
+            other => StorageError::Sqlx(other),
+        })?;
+        self.events.bucket(
+            EventKind::BucketConfigChanged,
+            name,
+            Some(format!("read_only={}", read_only)),
+        );
+        Ok(updated)
+    }
+
+    /// Set or clear (`None`) the `Cache-Control` value sent on GET/HEAD.
+    pub async fn set_bucket_cache_control(
+        &self,
+        name: &str,
+        cache_control: Option<&str>,
+    ) -> StorageResult<Bucket> {
+        self.ensure_bucket_name_safe(name)?;
+        if let Some(value) = cache_control
+            && (value.is_empty() || value.bytes().any(|b| b.is_ascii_control()))
+        {
+            return Err(StorageError::InvalidContent(
+                "cache_control must be a non-empty header value".into(),
+            ));
+        }
+        let updated = sqlx::query_as::<_, Bucket>(&format!(
+            "UPDATE buckets SET cache_control = ? WHERE name = ? RETURNING {BUCKET_COLUMNS}"
+        ))
+        .bind(cache_control)
+        .bind(name)
+        .fetch_one(&*self.db)
+        .await
+        .map_err(|err| match err {
+            sqlx::Error::RowNotFound => StorageError::BucketNotFound(name.to_string()),
+            other => StorageError::Sqlx(other),
+        })?;
+        self.events.bucket(
+            EventKind::BucketConfigChanged,
+            name,
+            Some(format!("cache_control={}", cache_control.unwrap_or(""))),
+        );
+        Ok(updated)
+    }
+
+    /// Set or clear (`None`) the `Expires` offset sent on GET/HEAD.
+    pub async fn set_bucket_expires(
+        &self,
+        name: &str,
+        expires_secs: Option<u32>,
+    ) -> StorageResult<Bucket> {
+        self.ensure_bucket_name_safe(name)?;
+        let updated = sqlx::query_as::<_, Bucket>(&format!(
+            "UPDATE buckets SET expires_secs = ? WHERE name = ? RETURNING {BUCKET_COLUMNS}"
+        ))
+        .bind(expires_secs)
+        .bind(name)
+        .fetch_one(&*self.db)
+        .await
+        .map_err(|err| match err {
+            sqlx::Error::RowNotFound => StorageError::BucketNotFound(name.to_string()),
+            other => StorageError::Sqlx(other),
+        })?;
+        self.events.bucket(
+            EventKind::BucketConfigChanged,
+            name,
+            Some(format!("expires_secs={}", expires_secs.unwrap_or(0))),
+        );
+        Ok(updated)
+    }
+
     /// Recursively remove empty directories up to bucket root.
     ///
     /// Stops when:
@@ -648,7 +1597,7 @@ impl StorageService {
     /// - directory not found
     /// - reached root
     /// - encountered unexpected I/O errors
-    async fn prune_empty_dirs(&self, start: &Path, stop: &Path) {
+    pub(crate) async fn prune_empty_dirs(&self, start: &Path, stop: &Path) {
         let mut current = start.to_path_buf();
         while current.starts_with(stop) && current != stop {
             match fs::remove_dir(&current).await {
@@ -670,45 +1619,28 @@ impl StorageService {
     }
 }
 
+/// Create `path` inside `dir`, creating `dir` first. A reclaim worker may
+/// prune `dir` between the two steps when it has just been emptied by a
+/// delete, so that case is retried.
+async fn create_in_dir(dir: &Path, path: &Path) -> io::Result<File> {
+    let mut attempts = 0;
+    loop {
+        fs::create_dir_all(dir).await?;
+        match File::create(path).await {
+            Err(err) if err.kind() == ErrorKind::NotFound && attempts < 3 => attempts += 1,
+            result => return result,
+        }
+    }
+}
+
 /// Return true if SQLx error indicates a unique constraint violation.
-fn is_unique_violation(err: &sqlx::Error) -> bool {
+pub(crate) fn is_unique_violation(err: &sqlx::Error) -> bool {
     matches!(
         err,
         sqlx::Error::Database(db_err) if db_err.message().to_ascii_lowercase().contains("unique")
     )
 }
 
-/// Compute a synthetic "common prefix" for S3 list semantics.
-///
-/// Used only when a delimiter is provided. Returns Some(prefix) if the key
-/// belongs to a grouped prefix, otherwise None.
-fn compute_common_prefix(
-    key: &str,
-    requested_prefix: Option<&str>,
-    delimiter: &str,
-) -> Option<String> {
-    let after_prefix = if let Some(prefix) = requested_prefix {
-        if key.starts_with(prefix) {
-            &key[prefix.len()..]
-        } else {
-            return None;
-        }
-    } else {
-        key
-    };
-
-    if let Some(pos) = after_prefix.find(delimiter) {
-        let mut combined = String::new();
-        if let Some(prefix) = requested_prefix {
-            combined.push_str(prefix);
-        }
-        combined.push_str(&after_prefix[..pos + delimiter.len()]);
-        Some(combined)
-    } else {
-        None
-    }
-}
-
 /// Check if a string matches IPv4-like dotted decimal form.
 /// Rejects names formatted like `1.2.3.4`.
 fn is_ipv4_like(name: &str) -> bool {
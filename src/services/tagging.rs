@@ -0,0 +1,197 @@
+//! Object tagging (S3 `PutObjectTagging` / `GetObjectTagging` /
+//! `DeleteObjectTagging`, plus `x-amz-tagging` on upload).
+//!
+//! Tags belong to the current version of a key and live in `object_tags`.
+//! Every new payload for a key replaces its tag set (with the tags sent in
+//! `x-amz-tagging`, or none), matching S3 where a PUT creates a new object.
+//! Noncurrent versions do not keep their tags.
+
+use crate::{
+    models::{object::Object, object_tag::ObjectTag},
+    services::{
+        metadata_encryption::{MetadataSealer, tag_context},
+        storage_service::{StorageError, StorageResult, StorageService},
+    },
+};
+use percent_encoding::{NON_ALPHANUMERIC, percent_decode_str, utf8_percent_encode};
+use sqlx::{Sqlite, Transaction};
+use std::collections::HashSet;
+use uuid::Uuid;
+
+/// Tags allowed on one object.
+pub const MAX_TAGS_PER_OBJECT: usize = 10;
+/// Longest tag key, in characters.
+pub const MAX_TAG_KEY_LEN: usize = 128;
+/// Longest tag value, in characters.
+pub const MAX_TAG_VALUE_LEN: usize = 256;
+
+/// Check a tag set against S3's limits: at most `MAX_TAGS_PER_OBJECT` tags,
+/// non-empty keys of bounded length, bounded values, no duplicate keys.
+pub fn validate_tags(tags: &[ObjectTag]) -> StorageResult<()> {
+    if tags.len() > MAX_TAGS_PER_OBJECT {
+        return Err(StorageError::InvalidTag(format!(
+            "at most {} tags are allowed per object",
+            MAX_TAGS_PER_OBJECT
+        )));
+    }
+    let mut seen = HashSet::new();
+    for tag in tags {
+        let key_len = tag.key.chars().count();
+        if key_len == 0 || key_len > MAX_TAG_KEY_LEN {
+            return Err(StorageError::InvalidTag(format!(
+                "tag keys must be 1 to {} characters",
+                MAX_TAG_KEY_LEN
+            )));
+        }
+        if tag.value.chars().count() > MAX_TAG_VALUE_LEN {
+            return Err(StorageError::InvalidTag(format!(
+                "tag `{}` value exceeds {} characters",
+                tag.key, MAX_TAG_VALUE_LEN
+            )));
+        }
+        if !seen.insert(tag.key.as_str()) {
+            return Err(StorageError::InvalidTag(format!(
+                "tag key `{}` is repeated",
+                tag.key
+            )));
+        }
+    }
+    Ok(())
+}
+
+/// Parse an `x-amz-tagging` header: URL query encoding, `k1=v1&k2=v2`.
+pub fn parse_tagging_header(value: &str) -> StorageResult<Vec<ObjectTag>> {
+    let decode = |s: &str| {
+        percent_decode_str(&s.replace('+', " "))
+            .decode_utf8()
+            .map(|s| s.into_owned())
+            .map_err(|_| StorageError::InvalidTag("tags must be UTF-8".into()))
+    };
+    let tags = value
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .map(|pair| {
+            let (key, value) = pair.split_once('=').unwrap_or((pair, ""));
+            Ok(ObjectTag {
+                key: decode(key)?,
+                value: decode(value)?,
+            })
+        })
+        .collect::<StorageResult<Vec<_>>>()?;
+    validate_tags(&tags)?;
+    Ok(tags)
+}
+
+/// Encode tags in `x-amz-tagging` form (inverse of `parse_tagging_header`).
+pub fn encode_tags(tags: &[ObjectTag]) -> String {
+    tags.iter()
+        .map(|tag| {
+            format!(
+                "{}={}",
+                utf8_percent_encode(&tag.key, NON_ALPHANUMERIC),
+                utf8_percent_encode(&tag.value, NON_ALPHANUMERIC)
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("&")
+}
+
+/// Replace the tags of `key` inside an open transaction, sealing the values
+/// when the bucket encrypts its metadata.
+pub(crate) async fn replace_tags(
+    tx: &mut Transaction<'_, Sqlite>,
+    sealer: Option<&MetadataSealer>,
+    bucket_id: Uuid,
+    key: &str,
+    tags: &[ObjectTag],
+) -> sqlx::Result<()> {
+    sqlx::query("DELETE FROM object_tags WHERE bucket_id = ? AND key = ?")
+        .bind(bucket_id)
+        .bind(key)
+        .execute(&mut **tx)
+        .await?;
+    for tag in tags {
+        let (value, value_hash) = match sealer {
+            Some(sealer) => (
+                sealer.seal(&tag_context(key, &tag.key), &tag.value),
+                Some(sealer.hash(&tag.key, &tag.value)),
+            ),
+            None => (tag.value.clone(), None),
+        };
+        sqlx::query(
+            "INSERT INTO object_tags (bucket_id, key, tag_key, tag_value, value_hash)
+             VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(bucket_id)
+        .bind(key)
+        .bind(&tag.key)
+        .bind(value)
+        .bind(value_hash)
+        .execute(&mut **tx)
+        .await?;
+    }
+    Ok(())
+}
+
+impl StorageService {
+    /// Tags of the current version of `key`, sorted by tag key.
+    pub async fn get_object_tags(&self, bucket: &str, key: &str) -> StorageResult<Vec<ObjectTag>> {
+        let object = self.get_object_metadata(bucket, key).await?;
+        let rows: Vec<(String, String, Option<String>)> = sqlx::query_as(
+            "SELECT tag_key, tag_value, value_hash FROM object_tags
+             WHERE bucket_id = ? AND key = ? ORDER BY tag_key",
+        )
+        .bind(object.bucket_id)
+        .bind(&object.key)
+        .fetch_all(&*self.db)
+        .await?;
+        rows.into_iter()
+            .map(|(tag_key, value, value_hash)| {
+                let context = tag_context(&object.key, &tag_key);
+                Ok(ObjectTag {
+                    value: self.open_value(
+                        object.bucket_id,
+                        &context,
+                        value,
+                        value_hash.as_deref(),
+                    )?,
+                    key: tag_key,
+                })
+            })
+            .collect()
+    }
+
+    /// Replace the tag set of `key`. Returns the object it applies to.
+    pub async fn put_object_tags(
+        &self,
+        bucket: &str,
+        key: &str,
+        tags: &[ObjectTag],
+    ) -> StorageResult<Object> {
+        self.ensure_key_safe(key)?;
+        validate_tags(tags)?;
+        let bucket_rec = self.fetch_writable_bucket(bucket).await?;
+        let object = self.fetch_object(&bucket_rec, key).await?;
+        let sealer = self.metadata_sealer(&bucket_rec)?;
+        let mut tx = self.db.begin().await?;
+        replace_tags(&mut tx, sealer.as_ref(), bucket_rec.id, key, tags).await?;
+        tx.commit().await?;
+        Ok(object)
+    }
+
+    /// Remove every tag of `key`. Returns the object it applies to.
+    pub async fn delete_object_tags(&self, bucket: &str, key: &str) -> StorageResult<Object> {
+        self.put_object_tags(bucket, key, &[]).await
+    }
+
+    /// Number of tags on `object`, for `x-amz-tagging-count`.
+    pub async fn object_tag_count(&self, object: &Object) -> StorageResult<i64> {
+        Ok(
+            sqlx::query_scalar("SELECT COUNT(*) FROM object_tags WHERE bucket_id = ? AND key = ?")
+                .bind(object.bucket_id)
+                .bind(&object.key)
+                .fetch_one(&*self.db)
+                .await?,
+        )
+    }
+}
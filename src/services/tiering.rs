@@ -0,0 +1,394 @@
+//! Cold payloads on a remote tier.
+//!
+//! A lifecycle rule with a `Transition` action moves the payloads of current
+//! objects that were neither modified nor (with access analytics on) read
+//! for its number of days to `StorageOptions::remote_tier`, a `BlobStore`
+//! such as the `remote-s3` tier. The object keeps its metadata and reports
+//! the rule's storage class (`x-amz-storage-class`, listings); `object_tiers`
+//! records where the payload went and the local file is removed.
+//!
+//! Reads are transparent: when the local payload of a version is missing and
+//! the version is tiered, `open_tiered` downloads it into an unlinked
+//! temporary file that is served like a local payload, ranges included.
+//! Versions are archived and promoted with their tier row, like their other
+//! per-version state. Remote blobs whose version no longer exists (deleted,
+//! overwritten, bucket removed) are deleted by `collect_tier_orphans`, which
+//! runs with every lifecycle pass.
+
+use crate::{
+    models::{
+        bucket::Bucket, lifecycle::LifecycleRule, object::Object, object_version::ObjectVersion,
+    },
+    services::{
+        blob_store::write_stream,
+        lifecycle::push_tag_filter,
+        storage_service::{StorageError, StorageResult, StorageService},
+        versioning::NULL_VERSION,
+    },
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use sqlx::{QueryBuilder, Sqlite};
+use std::io::{self, SeekFrom};
+use tokio::{
+    fs::{self, File, OpenOptions},
+    io::AsyncSeekExt,
+};
+use tracing::{debug, info, warn};
+use uuid::Uuid;
+
+/// Storage classes a `Transition` may name.
+pub const TRANSITION_STORAGE_CLASSES: [&str; 6] = [
+    "STANDARD_IA",
+    "ONEZONE_IA",
+    "INTELLIGENT_TIERING",
+    "GLACIER_IR",
+    "GLACIER",
+    "DEEP_ARCHIVE",
+];
+
+/// Keys fetched per query while transitioning objects.
+const TRANSITION_PAGE: i64 = 500;
+
+/// Tier rows checked per orphan collection query.
+const ORPHAN_PAGE: i64 = 500;
+
+/// Where a tiered payload lives.
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct TierRecord {
+    pub tier: String,
+    pub remote_key: String,
+    pub transitioned_at: DateTime<Utc>,
+}
+
+/// A tier row whose version is gone.
+#[derive(Debug, sqlx::FromRow)]
+struct OrphanRow {
+    bucket_id: Uuid,
+    key: String,
+    version_id: String,
+    remote_key: String,
+}
+
+impl StorageService {
+    /// Where the payload of `object` was moved, if it is tiered.
+    pub async fn object_tier(&self, object: &Object) -> StorageResult<Option<TierRecord>> {
+        self.tier_record(
+            object.bucket_id,
+            &object.key,
+            object.version_id.as_deref().unwrap_or(NULL_VERSION),
+            object.last_modified,
+        )
+        .await
+    }
+
+    /// Whether the payload of an archived `version` is on a remote tier.
+    pub(crate) async fn version_is_tiered(
+        &self,
+        bucket: &Bucket,
+        version: &ObjectVersion,
+    ) -> StorageResult<bool> {
+        Ok(self
+            .tier_record(
+                bucket.id,
+                &version.key,
+                &version.version_id,
+                version.last_modified,
+            )
+            .await?
+            .is_some())
+    }
+
+    async fn tier_record(
+        &self,
+        bucket_id: Uuid,
+        key: &str,
+        version_id: &str,
+        last_modified: DateTime<Utc>,
+    ) -> StorageResult<Option<TierRecord>> {
+        Ok(sqlx::query_as::<_, TierRecord>(
+            "SELECT tier, remote_key, transitioned_at FROM object_tiers
+             WHERE bucket_id = ? AND key = ? AND version_id = ? AND last_modified = ?",
+        )
+        .bind(bucket_id)
+        .bind(key)
+        .bind(version_id)
+        .bind(last_modified)
+        .fetch_optional(&*self.db)
+        .await?)
+    }
+
+    /// The payload of a tiered `object`, fetched from the remote tier into a
+    /// temporary file that is unlinked once open. `None` when `object` is not
+    /// tiered.
+    pub(crate) async fn open_tiered(
+        &self,
+        bucket: &Bucket,
+        object: &Object,
+    ) -> StorageResult<Option<File>> {
+        let Some(record) = self.object_tier(object).await? else {
+            return Ok(None);
+        };
+        let store = self.options.remote_tier.as_ref().ok_or_else(|| {
+            StorageError::Io(io::Error::other(format!(
+                "`{}` is on the {} tier, which is not configured",
+                object.key, record.tier
+            )))
+        })?;
+        let stream = store.get(&record.remote_key).await?;
+        let path = self.temp_path(&self.bucket_root(&bucket.name));
+        let mut file = OpenOptions::new()
+            .read(true)
+            .write(true)
+            .create_new(true)
+            .open(&path)
+            .await?;
+        let written = write_stream(stream, &mut file).await;
+        // The open handle keeps the data readable on Unix; elsewhere the
+        // file is left for fsck's cleanup of temporary files.
+        let _ = fs::remove_file(&path).await;
+        written?;
+        file.seek(SeekFrom::Start(0)).await?;
+        debug!(
+            "fetched {}/{} from the {} tier",
+            bucket.name, object.key, record.tier
+        );
+        Ok(Some(file))
+    }
+
+    /// Transition the live objects of `bucket` matching `rule` that were
+    /// neither modified nor read since `cutoff` to `storage_class`.
+    pub(crate) async fn transition_objects(
+        &self,
+        bucket: &Bucket,
+        rule: &LifecycleRule,
+        cutoff: DateTime<Utc>,
+        storage_class: &str,
+    ) -> StorageResult<u64> {
+        let prefix = rule.prefix.as_deref().unwrap_or_default();
+        let sealer = self.metadata_sealer(bucket)?;
+        let mut transitioned = 0;
+        let mut after: Option<String> = None;
+        loop {
+            let mut query =
+                QueryBuilder::<Sqlite>::new("SELECT o.key FROM objects o WHERE o.bucket_id = ");
+            query.push_bind(bucket.id);
+            query.push(" AND o.is_deleted = 0 AND o.key >= ");
+            query.push_bind(prefix);
+            push_transition_filter(&mut query, cutoff, storage_class);
+            if let Some(after) = &after {
+                query.push(" AND o.key > ");
+                query.push_bind(after.clone());
+            }
+            push_tag_filter(&mut query, sealer.as_ref(), &rule.tags);
+            query.push(" ORDER BY o.key ASC LIMIT ");
+            query.push_bind(TRANSITION_PAGE);
+            let page: Vec<String> = query.build_query_scalar().fetch_all(&*self.db).await?;
+            let fetched = page.len();
+            let matching: Vec<String> = page
+                .into_iter()
+                .take_while(|key| key.starts_with(prefix))
+                .collect();
+            for key in &matching {
+                match self.transition_object(bucket, key, storage_class).await {
+                    Ok(true) => transitioned += 1,
+                    Ok(false) | Err(StorageError::ObjectNotFound { .. }) => {}
+                    Err(err) => warn!(
+                        "lifecycle rule `{}` could not transition {}/{}: {}",
+                        rule.id, bucket.name, key, err
+                    ),
+                }
+            }
+            if (fetched as i64) < TRANSITION_PAGE || matching.len() < fetched {
+                return Ok(transitioned);
+            }
+            after = matching.last().cloned();
+        }
+    }
+
+    /// Move the payload of the current version of `key` to the remote tier
+    /// and report it as `storage_class`. Returns `false` when the object
+    /// changed meanwhile; an already tiered payload only changes class.
+    pub(crate) async fn transition_object(
+        &self,
+        bucket: &Bucket,
+        key: &str,
+        storage_class: &str,
+    ) -> StorageResult<bool> {
+        let store =
+            self.options.remote_tier.clone().ok_or_else(|| {
+                StorageError::InvalidLifecycle("no remote tier is configured".into())
+            })?;
+        let object = self.fetch_object(bucket, key).await?;
+        let live_path = self.object_path_on(object.volume.as_deref(), &bucket.name, key);
+        let uploaded = match self.object_tier(&object).await? {
+            Some(_) => None,
+            None => {
+                let remote_key = format!("{}/{}", bucket.id, Uuid::new_v4());
+                store.put(&remote_key, &live_path).await?;
+                Some(remote_key)
+            }
+        };
+
+        let _key_guard = self.key_locks.lock(bucket.id, key).await;
+        let version_id = object.version_id.as_deref().unwrap_or(NULL_VERSION);
+        let committed = async {
+            let mut tx = self.db.begin().await?;
+            let updated = sqlx::query(
+                "UPDATE objects SET storage_class = ?
+                 WHERE id = ? AND last_modified = ? AND is_deleted = 0",
+            )
+            .bind(storage_class)
+            .bind(object.id)
+            .bind(object.last_modified)
+            .execute(&mut *tx)
+            .await?;
+            if updated.rows_affected() == 0 {
+                return Ok(None);
+            }
+            let mut replaced = None;
+            if let Some(remote_key) = &uploaded {
+                // A tier row of an earlier payload with the same version ID
+                // (an overwritten `null` version) not collected yet.
+                replaced = sqlx::query_scalar::<_, String>(
+                    "SELECT remote_key FROM object_tiers
+                     WHERE bucket_id = ? AND key = ? AND version_id = ?",
+                )
+                .bind(bucket.id)
+                .bind(key)
+                .bind(version_id)
+                .fetch_optional(&mut *tx)
+                .await?;
+                sqlx::query(
+                    "INSERT OR REPLACE INTO object_tiers (
+                         bucket_id, key, version_id, last_modified, tier, remote_key,
+                         transitioned_at
+                     ) VALUES (?, ?, ?, ?, ?, ?, ?)",
+                )
+                .bind(bucket.id)
+                .bind(key)
+                .bind(version_id)
+                .bind(object.last_modified)
+                .bind(store.tier())
+                .bind(remote_key)
+                .bind(Utc::now())
+                .execute(&mut *tx)
+                .await?;
+            }
+            tx.commit().await?;
+            Ok::<_, sqlx::Error>(Some(replaced))
+        }
+        .await;
+
+        match committed {
+            Ok(Some(replaced)) => {
+                if let Some(replaced) = replaced
+                    && let Err(err) = store.delete(&replaced).await
+                {
+                    warn!("could not remove remote blob {}: {}", replaced, err);
+                }
+                if uploaded.is_some() {
+                    self.remove_payload(&bucket.name, &live_path).await?;
+                    info!(
+                        "moved {}/{} to the {} tier as {}",
+                        bucket.name,
+                        key,
+                        store.tier(),
+                        storage_class
+                    );
+                }
+                Ok(true)
+            }
+            Ok(None) | Err(_) => {
+                if let Some(remote_key) = &uploaded
+                    && let Err(err) = store.delete(remote_key).await
+                {
+                    warn!("could not remove remote blob {}: {}", remote_key, err);
+                }
+                committed.map(|_| false).map_err(StorageError::from)
+            }
+        }
+    }
+
+    /// Delete the remote blobs (and rows) of tiered versions that no longer
+    /// exist. Returns how many were removed.
+    pub async fn collect_tier_orphans(&self) -> StorageResult<u64> {
+        let Some(store) = self.options.remote_tier.clone() else {
+            return Ok(0);
+        };
+        let mut removed = 0;
+        let mut after: Option<(Uuid, String, String)> = None;
+        loop {
+            let (bucket_id, key, version_id) =
+                after
+                    .clone()
+                    .unwrap_or((Uuid::nil(), String::new(), String::new()));
+            let rows: Vec<OrphanRow> = sqlx::query_as(
+                "SELECT t.bucket_id, t.key, t.version_id, t.remote_key FROM object_tiers t
+                 WHERE (t.bucket_id, t.key, t.version_id) > (?, ?, ?)
+                   AND NOT EXISTS (
+                       SELECT 1 FROM objects o
+                       WHERE o.bucket_id = t.bucket_id AND o.key = t.key
+                         AND COALESCE(o.version_id, 'null') = t.version_id
+                         AND o.last_modified = t.last_modified)
+                   AND NOT EXISTS (
+                       SELECT 1 FROM object_versions v
+                       WHERE v.bucket_id = t.bucket_id AND v.key = t.key
+                         AND v.version_id = t.version_id
+                         AND v.last_modified = t.last_modified)
+                 ORDER BY t.bucket_id, t.key, t.version_id
+                 LIMIT ?",
+            )
+            .bind(bucket_id)
+            .bind(key)
+            .bind(version_id)
+            .bind(ORPHAN_PAGE)
+            .fetch_all(&*self.db)
+            .await?;
+            let fetched = rows.len();
+            for row in &rows {
+                if let Err(err) = store.delete(&row.remote_key).await {
+                    warn!("could not remove remote blob {}: {}", row.remote_key, err);
+                    continue;
+                }
+                sqlx::query(
+                    "DELETE FROM object_tiers
+                     WHERE bucket_id = ? AND key = ? AND version_id = ? AND remote_key = ?",
+                )
+                .bind(row.bucket_id)
+                .bind(&row.key)
+                .bind(&row.version_id)
+                .bind(&row.remote_key)
+                .execute(&*self.db)
+                .await?;
+                removed += 1;
+            }
+            if (fetched as i64) < ORPHAN_PAGE {
+                return Ok(removed);
+            }
+            after = rows
+                .last()
+                .map(|row| (row.bucket_id, row.key.clone(), row.version_id.clone()));
+        }
+    }
+}
+
+/// Restrict a query over `objects o` to objects a `Transition` with `cutoff`
+/// would move to `storage_class`: neither modified nor read since `cutoff`,
+/// and not in that class already.
+pub(crate) fn push_transition_filter(
+    query: &mut QueryBuilder<'_, Sqlite>,
+    cutoff: DateTime<Utc>,
+    storage_class: &str,
+) {
+    query.push(" AND o.last_modified < ");
+    query.push_bind(cutoff);
+    query.push(" AND o.storage_class <> ");
+    query.push_bind(storage_class.to_string());
+    query.push(
+        " AND NOT EXISTS (SELECT 1 FROM object_reads r
+           WHERE r.bucket_id = o.bucket_id AND r.key = o.key AND r.last_read_at >= ",
+    );
+    query.push_bind(cutoff);
+    query.push(")");
+}
@@ -0,0 +1,122 @@
+//! Native HTTPS.
+//!
+//! With `OBJECT_STORE_TLS_CERT` and `OBJECT_STORE_TLS_KEY` the server
+//! terminates TLS itself (rustls, ring provider) instead of relying on a
+//! reverse proxy. Both files are PEM: the certificate chain leaf first, and
+//! a PKCS#8, PKCS#1 or SEC1 private key. Startup fails when they cannot be
+//! loaded or do not belong together.
+//!
+//! Certificates rotated in place (e.g. by certbot or cert-manager) are
+//! picked up without a restart when `OBJECT_STORE_TLS_RELOAD_SECS` is set:
+//! the files' modification times are checked that often and, when either
+//! changed, the pair is loaded again. New connections get the new
+//! certificate; a pair that fails to load is logged and the previous one
+//! kept serving.
+
+use anyhow::{Context, Result, anyhow};
+use axum_server::tls_rustls::RustlsConfig;
+use rustls::{
+    ServerConfig,
+    crypto::ring,
+    pki_types::{CertificateDer, PrivateKeyDer, pem::PemObject},
+};
+use std::{
+    path::{Path, PathBuf},
+    sync::Arc,
+    time::{Duration, SystemTime},
+};
+use tokio::task::JoinHandle;
+use tracing::{info, warn};
+
+/// Certificate and key files served.
+#[derive(Debug, Clone)]
+pub struct TlsFiles {
+    pub cert: PathBuf,
+    pub key: PathBuf,
+}
+
+impl TlsFiles {
+    pub fn new(cert: impl Into<PathBuf>, key: impl Into<PathBuf>) -> Self {
+        Self {
+            cert: cert.into(),
+            key: key.into(),
+        }
+    }
+
+    /// Build a rustls server configuration from the files.
+    pub fn server_config(&self) -> Result<ServerConfig> {
+        let chain = CertificateDer::pem_file_iter(&self.cert)
+            .and_then(|certs| certs.collect::<Result<Vec<_>, _>>())
+            .map_err(|err| anyhow!("TLS certificate {}: {}", self.cert.display(), err))?;
+        if chain.is_empty() {
+            return Err(anyhow!(
+                "TLS certificate {}: no certificate found",
+                self.cert.display()
+            ));
+        }
+        let key = PrivateKeyDer::from_pem_file(&self.key)
+            .map_err(|err| anyhow!("TLS key {}: {}", self.key.display(), err))?;
+        let mut config = ServerConfig::builder_with_provider(Arc::new(ring::default_provider()))
+            .with_safe_default_protocol_versions()?
+            .with_no_client_auth()
+            .with_single_cert(chain, key)
+            .with_context(|| {
+                format!(
+                    "TLS key {} does not fit certificate {}",
+                    self.key.display(),
+                    self.cert.display()
+                )
+            })?;
+        config.alpn_protocols = vec![b"h2".to_vec(), b"http/1.1".to_vec()];
+        Ok(config)
+    }
+
+    /// Load the files into a configuration the listener can be reloaded
+    /// through.
+    pub fn load(&self) -> Result<RustlsConfig> {
+        Ok(RustlsConfig::from_config(Arc::new(self.server_config()?)))
+    }
+
+    /// Load the files into `config` again, leaving it as it was on failure.
+    pub fn reload(&self, config: &RustlsConfig) -> Result<()> {
+        config.reload_from_config(Arc::new(self.server_config()?));
+        Ok(())
+    }
+
+    fn modified(&self) -> (Option<SystemTime>, Option<SystemTime>) {
+        (modified(&self.cert), modified(&self.key))
+    }
+}
+
+fn modified(path: &Path) -> Option<SystemTime> {
+    std::fs::metadata(path)
+        .and_then(|meta| meta.modified())
+        .ok()
+}
+
+/// Check `files` every `period` and reload `config` when they changed.
+pub fn spawn_tls_reloader(
+    files: TlsFiles,
+    config: RustlsConfig,
+    period: Duration,
+) -> JoinHandle<()> {
+    tokio::spawn(async move {
+        let mut seen = files.modified();
+        let mut ticker = tokio::time::interval(period);
+        ticker.tick().await;
+        loop {
+            ticker.tick().await;
+            let current = files.modified();
+            if current == seen {
+                continue;
+            }
+            // Remember the attempt either way: a half-written pair is tried
+            // again once the other file changes, not warned about each tick.
+            seen = current;
+            match files.reload(&config) {
+                Ok(()) => info!("reloaded TLS certificate {}", files.cert.display()),
+                Err(err) => warn!("keeping the previous TLS certificate: {:#}", err),
+            }
+        }
+    })
+}
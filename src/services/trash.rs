@@ -0,0 +1,242 @@
+//! Trash for soft-deleted objects.
+//!
+//! Deleting a key outside a versioned history only marks its row deleted.
+//! While deleted rows are retained (`StorageOptions::deleted_retention`),
+//! the payload is moved to `base_path/.trash/{bucket}/{row id}` rather than
+//! removed, so the key can be undeleted until the purger (see `purge`)
+//! drops the row and its trashed payload. Without a retention payloads are
+//! removed right away as before.
+//!
+//! `GET /{bucket}?deleted=true` lists deleted keys and
+//! `POST /{bucket}/{key}?restore` brings one back: the row is marked live
+//! again and the payload is moved back from the trash, or kept if it never
+//! left the bucket directory (a removal that failed). A key whose payload
+//! is gone cannot be restored.
+
+use crate::{
+    models::object::Object,
+    services::{
+        changes::{ChangeKind, NewChange},
+        events::EventKind,
+        keys,
+        placement::move_file,
+        storage_service::{
+            MAX_LIST_KEYS, OBJECT_COLUMNS, StorageError, StorageResult, StorageService, side_dirs,
+        },
+    },
+};
+use chrono::{DateTime, Utc};
+use serde::Serialize;
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+use tokio::fs;
+use tracing::info;
+use uuid::Uuid;
+
+/// Directory (below `base_path`) holding payloads of deleted keys.
+const TRASH_DIR: &str = side_dirs::TRASH;
+
+/// A deleted key as listed by `GET /{bucket}?deleted=true`.
+#[derive(Debug, Clone, Serialize, sqlx::FromRow)]
+pub struct DeletedObject {
+    pub key: String,
+    pub size_bytes: i64,
+    pub etag: Option<String>,
+    pub last_modified: DateTime<Utc>,
+    pub deleted_at: Option<DateTime<Utc>>,
+}
+
+/// One page of deleted keys, in key order.
+#[derive(Debug, Clone, Serialize)]
+pub struct DeletedListing {
+    pub bucket: String,
+    pub prefix: Option<String>,
+    pub deleted: Vec<DeletedObject>,
+    pub is_truncated: bool,
+    /// Pass as `start-after` to get the next page.
+    pub next_start_after: Option<String>,
+}
+
+impl StorageService {
+    /// Root of the trash for a bucket.
+    pub(crate) fn trash_root(&self, bucket_name: &str) -> PathBuf {
+        self.base_path.join(TRASH_DIR).join(bucket_name)
+    }
+
+    pub(crate) fn trash_path(&self, bucket_name: &str, id: Uuid) -> PathBuf {
+        self.trash_root(bucket_name).join(id.to_string())
+    }
+
+    /// Dispose of the payload of the just-deleted row `id` for `key`: into
+    /// the trash while deleted rows are retained, otherwise removed.
+    pub(crate) async fn discard_deleted_payload(
+        &self,
+        bucket_name: &str,
+        id: Uuid,
+        key: &str,
+    ) -> io::Result<()> {
+        let volume: Option<(Option<String>,)> =
+            sqlx::query_as("SELECT volume FROM objects WHERE id = ?")
+                .bind(id)
+                .fetch_optional(&*self.db)
+                .await
+                .map_err(io::Error::other)?;
+        let volume = volume.and_then(|(volume,)| volume);
+        let live_path = self.object_path_on(volume.as_deref(), bucket_name, key);
+        if self.options.deleted_retention.is_none() {
+            return self.remove_payload(bucket_name, &live_path).await;
+        }
+        let trashed = self.trash_path(bucket_name, id);
+        fs::create_dir_all(self.trash_root(bucket_name)).await?;
+        match move_file(&live_path, &trashed).await {
+            Ok(()) => {
+                if let Some(parent) = live_path.parent() {
+                    self.prune_empty_dirs(
+                        parent,
+                        &self.payload_bucket_root(bucket_name, &live_path),
+                    )
+                    .await;
+                }
+                Ok(())
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(()),
+            Err(_) => self.remove_payload(bucket_name, &live_path).await,
+        }
+    }
+
+    /// Remove a trashed payload, if there is one.
+    pub(crate) async fn remove_trashed(&self, bucket_name: &str, id: Uuid) -> io::Result<bool> {
+        match fs::remove_file(self.trash_path(bucket_name, id)).await {
+            Ok(()) => Ok(true),
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+
+    /// List deleted keys (outside a version history) under `prefix`, after
+    /// `start_after`, at most `max_keys` of them.
+    pub async fn list_deleted(
+        &self,
+        bucket: &str,
+        prefix: Option<&str>,
+        start_after: Option<&str>,
+        max_keys: usize,
+    ) -> StorageResult<DeletedListing> {
+        let bucket_rec = self.fetch_bucket(bucket).await?;
+        if let Some(prefix) = prefix {
+            self.ensure_prefix_safe(prefix)?;
+        }
+        let max_keys = max_keys.clamp(1, MAX_LIST_KEYS);
+        let mut deleted = sqlx::query_as::<_, DeletedObject>(
+            "SELECT key, size_bytes, etag, last_modified, deleted_at FROM objects
+             WHERE bucket_id = ? AND is_deleted = 1 AND version_id IS NULL
+               AND key GLOB ? AND key > ?
+             ORDER BY key LIMIT ?",
+        )
+        .bind(bucket_rec.id)
+        .bind(keys::prefix_glob(prefix.unwrap_or_default()))
+        .bind(start_after.unwrap_or_default())
+        .bind(max_keys as i64 + 1)
+        .fetch_all(&*self.db)
+        .await?;
+        let is_truncated = deleted.len() > max_keys;
+        deleted.truncate(max_keys);
+        Ok(DeletedListing {
+            bucket: bucket_rec.name,
+            prefix: prefix.map(str::to_string),
+            next_start_after: is_truncated
+                .then(|| deleted.last().map(|obj| obj.key.clone()))
+                .flatten(),
+            deleted,
+            is_truncated,
+        })
+    }
+
+    /// Undelete `key`, moving its payload back from the trash.
+    ///
+    /// Returns ObjectNotFound when the key is not deleted or its payload is
+    /// gone.
+    pub async fn restore_deleted(&self, bucket: &str, key: &str) -> StorageResult<Object> {
+        self.ensure_key_safe(key)?;
+        let bucket_rec = self.fetch_writable_bucket(bucket).await?;
+        let _key_guard = self.key_locks.lock(bucket_rec.id, key).await;
+        let not_found = || StorageError::ObjectNotFound {
+            bucket: bucket_rec.name.clone(),
+            key: key.to_string(),
+        };
+        let row = sqlx::query_as::<_, Object>(&format!(
+            "SELECT {OBJECT_COLUMNS} FROM objects
+             WHERE bucket_id = ? AND key = ? AND is_deleted = 1 AND version_id IS NULL"
+        ))
+        .bind(bucket_rec.id)
+        .bind(key)
+        .fetch_optional(&*self.db)
+        .await?
+        .ok_or_else(not_found)?;
+
+        let live_path = self.object_path_on(row.volume.as_deref(), &bucket_rec.name, key);
+        if let Some(parent) = live_path.parent() {
+            fs::create_dir_all(parent).await?;
+        }
+        let trashed = self.trash_path(&bucket_rec.name, row.id);
+        let from_trash = match move_file(&trashed, &live_path).await {
+            Ok(()) => true,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => {
+                if !self.payload_intact(&live_path, row.size_bytes).await? {
+                    return Err(not_found());
+                }
+                false
+            }
+            Err(err) => return Err(err.into()),
+        };
+
+        let restored = sqlx::query_as::<_, Object>(&format!(
+            "UPDATE objects SET is_deleted = 0, deleted_at = NULL
+             WHERE id = ? AND is_deleted = 1
+             RETURNING {OBJECT_COLUMNS}"
+        ))
+        .bind(row.id)
+        .fetch_optional(&*self.db)
+        .await;
+        let object = match restored {
+            Ok(Some(object)) => object,
+            result => {
+                if from_trash {
+                    let _ = move_file(&live_path, &trashed).await;
+                }
+                return Err(match result {
+                    Err(err) => err.into(),
+                    _ => not_found(),
+                });
+            }
+        };
+
+        info!("restored deleted object {}/{}", bucket_rec.name, key);
+        self.record_change(bucket_rec.id, NewChange::of(ChangeKind::Created, &object))
+            .await;
+        self.events.object(
+            EventKind::ObjectCreated,
+            &bucket_rec.name,
+            key,
+            Some(object.size_bytes),
+            object.etag.clone(),
+        );
+        Ok(object)
+    }
+
+    /// Whether a payload of `size_bytes` is at `path` (an empty object needs
+    /// none: an empty file is created for it).
+    async fn payload_intact(&self, path: &Path, size_bytes: i64) -> io::Result<bool> {
+        match fs::metadata(path).await {
+            Ok(meta) => Ok(meta.len() as i64 == size_bytes),
+            Err(err) if err.kind() == io::ErrorKind::NotFound && size_bytes == 0 => {
+                fs::write(path, b"").await?;
+                Ok(true)
+            }
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(false),
+            Err(err) => Err(err),
+        }
+    }
+}
@@ -0,0 +1,204 @@
+//! In-flight upload tracking.
+//!
+//! Every streaming PUT registers itself here for as long as its body is being
+//! received, and bumps a byte counter per chunk. The admin API lists the
+//! registry so operators can see what a long-running ingest is doing: bytes
+//! received so far, average rate, ETA when the size is known, and how long
+//! since the last chunk arrived (a stalled client shows up as a growing idle
+//! time). It also counts uploads abandoned before they were committed, and the
+//! bytes written for them (see `staging`).
+
+use bytes::Bytes;
+use chrono::{DateTime, Utc};
+use futures::{Stream, StreamExt};
+use serde::Serialize;
+use std::{
+    collections::HashMap,
+    io,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicU64, Ordering},
+    },
+    time::Instant,
+};
+use uuid::Uuid;
+
+/// Shared registry of uploads currently receiving data.
+#[derive(Debug, Clone, Default)]
+pub struct UploadRegistry {
+    inner: Arc<Mutex<HashMap<Uuid, Arc<UploadState>>>>,
+    aborted: Arc<AbortCounters>,
+}
+
+#[derive(Debug, Default)]
+struct AbortCounters {
+    uploads: AtomicU64,
+    bytes: AtomicU64,
+}
+
+/// Uploads abandoned since the server started: the client disconnected or
+/// the write failed before the payload was committed.
+#[derive(Debug, Clone, Copy, Serialize)]
+pub struct AbortedUploads {
+    pub aborted_uploads: u64,
+    /// Bytes written to temp files that were then thrown away.
+    pub aborted_bytes: u64,
+}
+
+#[derive(Debug)]
+struct UploadState {
+    id: Uuid,
+    bucket: String,
+    key: String,
+    expected_bytes: Option<u64>,
+    started_at: DateTime<Utc>,
+    started: Instant,
+    received: AtomicU64,
+    /// Milliseconds after `started` when the last chunk arrived.
+    last_chunk_ms: AtomicU64,
+}
+
+/// Point-in-time view of one upload, as served by the admin API.
+#[derive(Debug, Clone, Serialize)]
+pub struct UploadProgress {
+    pub id: Uuid,
+    pub bucket: String,
+    pub key: String,
+    pub bytes_received: u64,
+    /// Declared body size (`Content-Length`), when known.
+    pub expected_bytes: Option<u64>,
+    pub started_at: DateTime<Utc>,
+    pub elapsed_secs: f64,
+    /// Average throughput since the upload started.
+    pub bytes_per_sec: f64,
+    /// Remaining time at the average rate; `None` when the size is unknown.
+    pub eta_secs: Option<f64>,
+    /// Seconds since the last chunk was received.
+    pub idle_secs: f64,
+}
+
+/// Removes the upload from the registry when dropped, whether the upload
+/// finished, failed, or the client went away.
+#[derive(Debug)]
+pub struct UploadGuard {
+    registry: UploadRegistry,
+    state: Arc<UploadState>,
+}
+
+impl Drop for UploadGuard {
+    fn drop(&mut self) {
+        self.registry
+            .inner
+            .lock()
+            .expect("upload registry poisoned")
+            .remove(&self.state.id);
+    }
+}
+
+impl UploadRegistry {
+    /// Register an upload and wrap its body so received bytes are counted.
+    /// The upload stays listed until the returned guard is dropped.
+    pub fn track<S>(
+        &self,
+        bucket: &str,
+        key: &str,
+        expected_bytes: Option<u64>,
+        stream: S,
+    ) -> (
+        UploadGuard,
+        impl Stream<Item = io::Result<Bytes>> + Send + 'static,
+    )
+    where
+        S: Stream<Item = io::Result<Bytes>> + Send + 'static,
+    {
+        let state = Arc::new(UploadState {
+            id: Uuid::new_v4(),
+            bucket: bucket.to_string(),
+            key: key.to_string(),
+            expected_bytes,
+            started_at: Utc::now(),
+            started: Instant::now(),
+            received: AtomicU64::new(0),
+            last_chunk_ms: AtomicU64::new(0),
+        });
+        self.inner
+            .lock()
+            .expect("upload registry poisoned")
+            .insert(state.id, state.clone());
+
+        let counter = state.clone();
+        let stream = stream.map(move |chunk| {
+            if let Ok(bytes) = &chunk {
+                counter
+                    .received
+                    .fetch_add(bytes.len() as u64, Ordering::Relaxed);
+                counter.last_chunk_ms.store(
+                    counter.started.elapsed().as_millis() as u64,
+                    Ordering::Relaxed,
+                );
+            }
+            chunk
+        });
+        let guard = UploadGuard {
+            registry: self.clone(),
+            state,
+        };
+        (guard, stream)
+    }
+
+    /// Count an upload whose staged payload was discarded after `bytes`.
+    pub fn record_aborted(&self, bytes: u64) {
+        self.aborted.uploads.fetch_add(1, Ordering::Relaxed);
+        self.aborted.bytes.fetch_add(bytes, Ordering::Relaxed);
+    }
+
+    pub fn aborted(&self) -> AbortedUploads {
+        AbortedUploads {
+            aborted_uploads: self.aborted.uploads.load(Ordering::Relaxed),
+            aborted_bytes: self.aborted.bytes.load(Ordering::Relaxed),
+        }
+    }
+
+    /// Snapshot all in-flight uploads, oldest first.
+    pub fn snapshot(&self) -> Vec<UploadProgress> {
+        let states: Vec<Arc<UploadState>> = self
+            .inner
+            .lock()
+            .expect("upload registry poisoned")
+            .values()
+            .cloned()
+            .collect();
+        let mut uploads: Vec<UploadProgress> = states.iter().map(|s| s.progress()).collect();
+        uploads.sort_by_key(|u| u.started_at);
+        uploads
+    }
+}
+
+impl UploadState {
+    fn progress(&self) -> UploadProgress {
+        let elapsed = self.started.elapsed().as_secs_f64();
+        let received = self.received.load(Ordering::Relaxed);
+        let last_chunk = self.last_chunk_ms.load(Ordering::Relaxed) as f64 / 1000.0;
+        let rate = if elapsed > 0.0 {
+            received as f64 / elapsed
+        } else {
+            0.0
+        };
+        let eta_secs = self
+            .expected_bytes
+            .filter(|_| rate > 0.0)
+            .map(|expected| expected.saturating_sub(received) as f64 / rate);
+        UploadProgress {
+            id: self.id,
+            bucket: self.bucket.clone(),
+            key: self.key.clone(),
+            bytes_received: received,
+            expected_bytes: self.expected_bytes,
+            started_at: self.started_at,
+            elapsed_secs: elapsed,
+            bytes_per_sec: rate,
+            eta_secs,
+            idle_secs: (elapsed - last_chunk).max(0.0),
+        }
+    }
+}
@@ -0,0 +1,335 @@
+//! User-defined object metadata (`x-amz-meta-*`) and CopyObject.
+//!
+//! Metadata belongs to the current version of a key and lives in
+//! `object_metadata`, written in the same transaction as the object row.
+//! Like tags, every new payload replaces it and noncurrent versions do not
+//! keep it.
+//!
+//! CopyObject stages the source payload under the destination key through
+//! the regular upload path. `MetadataDirective` decides where the new
+//! object's content type, content encoding, caching headers and user
+//! metadata come from:
+//! the source (`COPY`, the default) or the request (`REPLACE`). Tags are
+//! always copied from the source; the ACL always comes from the request
+//! and the destination bucket's policy, as in S3.
+//!
+//! Copying the current object onto itself with `REPLACE` is how S3 clients
+//! edit metadata in place. Outside a versioned bucket nothing about the
+//! payload changes then, so the rows are updated without copying a byte and
+//! the ETag stays the same.
+
+use crate::{
+    models::{bucket::Bucket, object::Object, object_metadata::ObjectMetadata},
+    services::{
+        changes::{ChangeKind, NewChange, insert_change},
+        events::EventKind,
+        metadata_encryption::{MetadataSealer, metadata_context},
+        storage_service::{
+            OBJECT_COLUMNS, ObjectAttributes, PutObjectParams, StorageError, StorageResult,
+            StorageService,
+        },
+    },
+};
+use chrono::Utc;
+use sqlx::{Sqlite, Transaction};
+use std::path::Path;
+use tokio_util::io::ReaderStream;
+use uuid::Uuid;
+
+/// Combined size of all names and values, as limited by S3.
+pub const MAX_USER_METADATA_BYTES: usize = 2 * 1024;
+
+/// Where CopyObject takes the destination's metadata from.
+#[derive(Debug, Clone, Copy, Default, PartialEq, Eq)]
+pub enum MetadataDirective {
+    /// Keep the source object's metadata.
+    #[default]
+    Copy,
+    /// Use the metadata sent with the copy request.
+    Replace,
+}
+
+impl MetadataDirective {
+    /// Parse an `x-amz-metadata-directive` header value.
+    pub fn parse(value: &str) -> Option<Self> {
+        match value {
+            "COPY" => Some(Self::Copy),
+            "REPLACE" => Some(Self::Replace),
+            _ => None,
+        }
+    }
+}
+
+/// The object named in `x-amz-copy-source`.
+#[derive(Debug, Clone)]
+pub struct CopySource {
+    pub bucket: String,
+    pub key: String,
+    pub version_id: Option<String>,
+}
+
+/// Check user metadata against S3's limits: non-empty names and at most
+/// `MAX_USER_METADATA_BYTES` in total.
+pub fn validate_user_metadata(entries: &[ObjectMetadata]) -> StorageResult<()> {
+    if entries.iter().any(|entry| entry.name.is_empty()) {
+        return Err(StorageError::InvalidMetadata(
+            "x-amz-meta- header names need a suffix".into(),
+        ));
+    }
+    let total: usize = entries.iter().map(|e| e.name.len() + e.value.len()).sum();
+    if total > MAX_USER_METADATA_BYTES {
+        return Err(StorageError::InvalidMetadata(format!(
+            "user metadata is {} bytes, at most {} are allowed",
+            total, MAX_USER_METADATA_BYTES
+        )));
+    }
+    Ok(())
+}
+
+/// Replace the user metadata of `key` inside an open transaction, sealing
+/// the values when the bucket encrypts its metadata.
+pub(crate) async fn replace_user_metadata(
+    tx: &mut Transaction<'_, Sqlite>,
+    sealer: Option<&MetadataSealer>,
+    bucket_id: Uuid,
+    key: &str,
+    entries: &[ObjectMetadata],
+) -> sqlx::Result<()> {
+    sqlx::query("DELETE FROM object_metadata WHERE bucket_id = ? AND key = ?")
+        .bind(bucket_id)
+        .bind(key)
+        .execute(&mut **tx)
+        .await?;
+    for entry in entries {
+        let (value, value_hash) = match sealer {
+            Some(sealer) => (
+                sealer.seal(&metadata_context(key, &entry.name), &entry.value),
+                Some(sealer.hash(&entry.name, &entry.value)),
+            ),
+            None => (entry.value.clone(), None),
+        };
+        sqlx::query(
+            "INSERT INTO object_metadata (bucket_id, key, name, value, value_hash)
+             VALUES (?, ?, ?, ?, ?)",
+        )
+        .bind(bucket_id)
+        .bind(key)
+        .bind(&entry.name)
+        .bind(value)
+        .bind(value_hash)
+        .execute(&mut **tx)
+        .await?;
+    }
+    Ok(())
+}
+
+impl StorageService {
+    /// User metadata of `object`, sorted by name.
+    pub async fn user_metadata(&self, object: &Object) -> StorageResult<Vec<ObjectMetadata>> {
+        let rows: Vec<(String, String, Option<String>)> = sqlx::query_as(
+            "SELECT name, value, value_hash FROM object_metadata
+             WHERE bucket_id = ? AND key = ? ORDER BY name",
+        )
+        .bind(object.bucket_id)
+        .bind(&object.key)
+        .fetch_all(&*self.db)
+        .await?;
+        rows.into_iter()
+            .map(|(name, value, value_hash)| {
+                let context = metadata_context(&object.key, &name);
+                Ok(ObjectMetadata {
+                    value: self.open_value(
+                        object.bucket_id,
+                        &context,
+                        value,
+                        value_hash.as_deref(),
+                    )?,
+                    name,
+                })
+            })
+            .collect()
+    }
+
+    /// Copy `source` to `key` in `bucket` (CopyObject).
+    ///
+    /// With `MetadataDirective::Replace`, `params` supplies the content type,
+    /// content encoding and user metadata; otherwise they come from the
+    /// source. Copying a key onto itself must replace its metadata, which
+    /// outside a versioned bucket happens in place.
+    pub async fn copy_object(
+        &self,
+        bucket: &str,
+        key: &str,
+        source: &CopySource,
+        directive: MetadataDirective,
+        params: PutObjectParams,
+    ) -> StorageResult<Object> {
+        self.ensure_key_safe(key)?;
+        if source.bucket == bucket
+            && source.key == key
+            && source.version_id.is_none()
+            && directive == MetadataDirective::Copy
+        {
+            return Err(StorageError::InvalidCopy(
+                "copying an object onto itself requires the REPLACE metadata directive".into(),
+            ));
+        }
+        if directive == MetadataDirective::Replace {
+            validate_user_metadata(&params.user_metadata)?;
+        }
+        if params.customer_key.is_some() {
+            return Err(StorageError::InvalidCopy(
+                "copies cannot be encrypted with a customer key".into(),
+            ));
+        }
+        let bucket_rec = self.fetch_writable_bucket(bucket).await?;
+        self.ensure_key_allowed(&bucket_rec, key).await?;
+        let acl = self.resolve_object_acl(&bucket_rec, params.acl.as_deref())?;
+        if source.bucket == bucket
+            && source.key == key
+            && source.version_id.is_none()
+            && !bucket_rec.versioning_enabled
+        {
+            return self
+                .replace_metadata_in_place(&bucket_rec, key, acl, params)
+                .await;
+        }
+
+        let (src, file) = match source.version_id.as_deref() {
+            Some(version_id) => {
+                self.get_object_version_reader(&source.bucket, &source.key, version_id)
+                    .await?
+            }
+            None => self.get_object_reader(&source.bucket, &source.key).await?,
+        };
+        if self.object_encryption(&src).await?.is_some() {
+            return Err(StorageError::InvalidCopy(
+                "objects encrypted with a customer key cannot be copied".into(),
+            ));
+        }
+        self.check_object_size(key, src.size_bytes.max(0) as u64)?;
+        // Tags and user metadata are kept for the current version only.
+        let src_bucket = self.fetch_bucket(&source.bucket).await?;
+        let src_is_current = self
+            .fetch_current_row(&src_bucket, &source.key)
+            .await?
+            .is_some_and(|current| current.id == src.id);
+        let (src_tags, src_metadata) = if src_is_current {
+            (
+                self.get_object_tags(&source.bucket, &source.key).await?,
+                self.user_metadata(&src).await?,
+            )
+        } else {
+            (Vec::new(), Vec::new())
+        };
+
+        let attrs = match directive {
+            MetadataDirective::Copy => ObjectAttributes {
+                content_type: src.content_type.clone(),
+                content_encoding: src.content_encoding.clone(),
+                tags: src_tags,
+                user_metadata: src_metadata,
+                acl: Some(acl),
+                cache_control: src.cache_control.clone(),
+                content_disposition: src.content_disposition.clone(),
+                expires: src.expires.clone(),
+                parts: Vec::new(),
+                retention: params.retention,
+                encryption: None,
+                uploader: params.uploader,
+            },
+            MetadataDirective::Replace => ObjectAttributes {
+                content_type: params.content_type,
+                content_encoding: params.content_encoding,
+                tags: src_tags,
+                user_metadata: params.user_metadata,
+                acl: Some(acl),
+                cache_control: params.cache_control,
+                content_disposition: params.content_disposition,
+                expires: params.expires,
+                parts: Vec::new(),
+                retention: params.retention,
+                encryption: None,
+                uploader: params.uploader,
+            },
+        };
+
+        let file_path = self.placed_path(&bucket_rec, key).await?;
+        let parent = file_path.parent().map(Path::to_path_buf).ok_or_else(|| {
+            StorageError::Io(std::io::Error::other(
+                "object path missing parent directory",
+            ))
+        })?;
+        let mut staged = self
+            .stage_payload(&parent, ReaderStream::new(file), None)
+            .await?;
+        // The bytes are the source's, and so are its checksums.
+        staged.sha256 = staged.sha256.or(src.checksum_sha256.clone());
+        staged.crc32c = src.checksum_crc32c.clone();
+        let etag = format!("{:x}", staged.md5);
+        self.commit_payload(&bucket_rec, key, staged, etag, attrs)
+            .await
+    }
+
+    /// Metadata-only self-copy: update the current row of `key` and its user
+    /// metadata, keeping payload, ETag, checksums and tags.
+    async fn replace_metadata_in_place(
+        &self,
+        bucket_rec: &Bucket,
+        key: &str,
+        acl: String,
+        params: PutObjectParams,
+    ) -> StorageResult<Object> {
+        // Object Lock needs versioning, so this only rejects lock headers.
+        self.resolve_retention(bucket_rec, params.retention).await?;
+        let sealer = self.metadata_sealer(bucket_rec)?;
+        let _key_guard = self.key_locks.lock(bucket_rec.id, key).await;
+        let mut tx = self.db.begin().await?;
+        let object = sqlx::query_as::<_, Object>(&format!(
+            "UPDATE objects SET
+                 content_type = ?, content_encoding = ?, cache_control = ?,
+                 content_disposition = ?, expires = ?, acl = ?, last_modified = ?
+             WHERE bucket_id = ? AND key = ? AND is_deleted = 0
+             RETURNING {OBJECT_COLUMNS}"
+        ))
+        .bind(&params.content_type)
+        .bind(&params.content_encoding)
+        .bind(&params.cache_control)
+        .bind(&params.content_disposition)
+        .bind(&params.expires)
+        .bind(&acl)
+        .bind(Utc::now())
+        .bind(bucket_rec.id)
+        .bind(key)
+        .fetch_optional(&mut *tx)
+        .await?
+        .ok_or_else(|| StorageError::ObjectNotFound {
+            bucket: bucket_rec.name.clone(),
+            key: key.to_string(),
+        })?;
+        replace_user_metadata(
+            &mut tx,
+            sealer.as_ref(),
+            bucket_rec.id,
+            key,
+            &params.user_metadata,
+        )
+        .await?;
+        insert_change(
+            &mut *tx,
+            bucket_rec.id,
+            &NewChange::of(ChangeKind::Updated, &object),
+        )
+        .await?;
+        tx.commit().await?;
+
+        self.events.object(
+            EventKind::ObjectCreated,
+            &bucket_rec.name,
+            key,
+            Some(object.size_bytes),
+            object.etag.clone(),
+        );
+        Ok(object)
+    }
+}
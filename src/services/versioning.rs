@@ -0,0 +1,738 @@
+//! Object versioning.
+//!
+//! The current version of a key (or its current delete marker) is always the
+//! row in `objects`, so reads, listings, snapshots and everything else that
+//! only cares about live data work unchanged. When a versioned bucket's key
+//! is overwritten or deleted, the current version is first archived into
+//! `object_versions` and its payload moved to
+//! `base_path/.versions/{bucket}/{id}`; deleting a key then leaves a delete
+//! marker (`is_deleted = 1` with a `version_id`) as the current row.
+//!
+//! Versioning follows S3's Enabled/Suspended model: once a bucket is
+//! suspended, new writes get the `null` version id and replace any existing
+//! `null` version, while versions with real ids are kept.
+
+use crate::{
+    models::{bucket::Bucket, object::Object, object_version::ObjectVersion},
+    services::{
+        changes::{ChangeKind, NewChange},
+        events::EventKind,
+        placement::move_file,
+        storage_service::{
+            BUCKET_COLUMNS, OBJECT_COLUMNS, StorageError, StorageResult, StorageService, side_dirs,
+        },
+    },
+};
+use chrono::{DateTime, Utc};
+use sqlx::FromRow;
+use std::{
+    io,
+    path::{Path, PathBuf},
+};
+use tokio::fs::{self, File};
+use tracing::{debug, warn};
+use uuid::Uuid;
+
+/// Directory (below `base_path`) holding noncurrent payloads.
+pub(crate) const VERSIONS_DIR: &str = side_dirs::VERSIONS;
+
+/// Version id of objects written while versioning was off.
+pub const NULL_VERSION: &str = "null";
+
+/// Entries returned by `ListObjectVersions` when the client sets no limit.
+pub const DEFAULT_MAX_VERSION_KEYS: usize = 1000;
+
+const VERSION_COLUMNS: &str = "id, bucket_id, key, version_id, content_type, content_encoding, \
+     size_bytes, etag, checksum_sha256, checksum_crc32c, storage_class, last_modified, \
+     is_delete_marker, acl, cache_control, content_disposition, expires";
+
+/// Generate an opaque version id.
+pub(crate) fn new_version_id() -> String {
+    Uuid::new_v4().simple().to_string()
+}
+
+/// Parameters of `ListObjectVersions`.
+#[derive(Debug, Clone, Default)]
+pub struct ListVersionsParams {
+    pub prefix: Option<String>,
+    /// Resume after this key (or within it, with `version_id_marker`).
+    pub key_marker: Option<String>,
+    pub version_id_marker: Option<String>,
+    pub max_keys: usize,
+}
+
+/// One version or delete marker in a listing.
+#[derive(Debug, Clone, FromRow)]
+pub struct VersionEntry {
+    pub key: String,
+    pub version_id: String,
+    pub is_latest: bool,
+    pub is_delete_marker: bool,
+    pub last_modified: DateTime<Utc>,
+    pub etag: Option<String>,
+    pub size_bytes: i64,
+    pub storage_class: String,
+}
+
+/// One page of `ListObjectVersions`: keys ascending, newest version first.
+#[derive(Debug)]
+pub struct ListVersionsResult {
+    pub entries: Vec<VersionEntry>,
+    pub is_truncated: bool,
+    pub next_key_marker: Option<String>,
+    pub next_version_id_marker: Option<String>,
+}
+
+/// What `delete_object_version` removed.
+#[derive(Debug)]
+pub struct DeletedVersion {
+    pub version_id: String,
+    pub delete_marker: bool,
+}
+
+impl StorageService {
+    /// Root of the noncurrent-version area for a bucket.
+    pub(crate) fn versions_root(&self, bucket_name: &str) -> PathBuf {
+        self.base_path.join(VERSIONS_DIR).join(bucket_name)
+    }
+
+    pub(crate) fn version_path(&self, bucket_name: &str, id: Uuid) -> PathBuf {
+        self.versions_root(bucket_name).join(id.to_string())
+    }
+
+    /// Enable or suspend versioning and return the updated bucket.
+    pub async fn set_bucket_versioning(&self, name: &str, enabled: bool) -> StorageResult<Bucket> {
+        self.ensure_bucket_name_safe(name)?;
+        let bucket = self.fetch_writable_bucket(name).await?;
+        if !enabled && self.object_lock_configuration(&bucket).await?.is_some() {
+            return Err(StorageError::InvalidBucketState(format!(
+                "versioning cannot be suspended on `{}`: Object Lock is enabled",
+                bucket.name
+            )));
+        }
+        let updated = sqlx::query_as::<_, Bucket>(&format!(
+            "UPDATE buckets SET versioning_enabled = ? WHERE id = ? RETURNING {BUCKET_COLUMNS}"
+        ))
+        .bind(enabled)
+        .bind(bucket.id)
+        .fetch_one(&*self.db)
+        .await?;
+        self.events.bucket(
+            EventKind::BucketConfigChanged,
+            name,
+            Some(format!(
+                "versioning={}",
+                if enabled { "Enabled" } else { "Suspended" }
+            )),
+        );
+        Ok(updated)
+    }
+
+    /// The current row of `key`, including a current delete marker.
+    pub(crate) async fn fetch_current_row(
+        &self,
+        bucket: &Bucket,
+        key: &str,
+    ) -> StorageResult<Option<Object>> {
+        let row = sqlx::query_as::<_, Object>(&format!(
+            "SELECT {OBJECT_COLUMNS} FROM objects WHERE bucket_id = ? AND key = ?"
+        ))
+        .bind(bucket.id)
+        .bind(key)
+        .fetch_optional(&*self.db)
+        .await?;
+        // A soft-deleted row without a version id is an unversioned delete,
+        // not a delete marker.
+        Ok(row.filter(|obj| !obj.is_deleted || obj.version_id.is_some()))
+    }
+
+    /// Move the current version of `key` (payload or delete marker) into the
+    /// version history, so a new current version can take its place.
+    ///
+    /// Returns `None` when there is nothing to keep: no current version, or
+    /// a `null` version in a suspended bucket (which the caller replaces in
+    /// place, recycling it as usual).
+    pub(crate) async fn archive_current_version(
+        &self,
+        bucket: &Bucket,
+        key: &str,
+    ) -> StorageResult<Option<ObjectVersion>> {
+        if !bucket.versioning_enabled {
+            // The new write becomes the `null` version, replacing any older
+            // one that was kept in the history.
+            self.drop_archived_version(bucket, key, NULL_VERSION)
+                .await?;
+        }
+        let Some(current) = self.fetch_current_row(bucket, key).await? else {
+            return Ok(None);
+        };
+        if current.version_id.is_none() && !bucket.versioning_enabled {
+            return Ok(None);
+        }
+        let tiered = !current.is_deleted && self.object_tier(&current).await?.is_some();
+        let live_path = self.object_path_on(current.volume.as_deref(), &bucket.name, key);
+
+        let version = ObjectVersion {
+            id: Uuid::new_v4(),
+            bucket_id: bucket.id,
+            key: key.to_string(),
+            version_id: current
+                .version_id
+                .clone()
+                .unwrap_or_else(|| NULL_VERSION.to_string()),
+            content_type: current.content_type,
+            content_encoding: current.content_encoding,
+            size_bytes: current.size_bytes,
+            etag: current.etag,
+            checksum_sha256: current.checksum_sha256,
+            checksum_crc32c: current.checksum_crc32c,
+            storage_class: current.storage_class,
+            last_modified: current.last_modified,
+            is_delete_marker: current.is_deleted,
+            acl: current.acl,
+            cache_control: current.cache_control,
+            content_disposition: current.content_disposition,
+            expires: current.expires,
+        };
+        if version.version_id == NULL_VERSION {
+            self.drop_archived_version(bucket, key, NULL_VERSION)
+                .await?;
+        }
+
+        let target = self.version_path(&bucket.name, version.id);
+        if !version.is_delete_marker {
+            fs::create_dir_all(self.versions_root(&bucket.name)).await?;
+            match move_file(&live_path, &target).await {
+                Ok(()) => {}
+                // A tiered payload stays remote; its tier row follows the version.
+                Err(err) if err.kind() == io::ErrorKind::NotFound && tiered => {}
+                Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(None),
+                Err(err) => return Err(StorageError::Io(err)),
+            }
+        }
+
+        let insert = sqlx::query(&format!(
+            "INSERT INTO object_versions ({VERSION_COLUMNS})
+             VALUES (?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?, ?)"
+        ))
+        .bind(version.id)
+        .bind(version.bucket_id)
+        .bind(&version.key)
+        .bind(&version.version_id)
+        .bind(&version.content_type)
+        .bind(&version.content_encoding)
+        .bind(version.size_bytes)
+        .bind(&version.etag)
+        .bind(&version.checksum_sha256)
+        .bind(&version.checksum_crc32c)
+        .bind(&version.storage_class)
+        .bind(version.last_modified)
+        .bind(version.is_delete_marker)
+        .bind(&version.acl)
+        .bind(&version.cache_control)
+        .bind(&version.content_disposition)
+        .bind(&version.expires)
+        .execute(&*self.db)
+        .await;
+        if let Err(err) = insert {
+            if !version.is_delete_marker {
+                let _ = move_file(&target, &live_path).await;
+            }
+            return Err(StorageError::Sqlx(err));
+        }
+
+        debug!(
+            "archived version {} of {}/{}",
+            version.version_id, bucket.name, key
+        );
+        Ok(Some(version))
+    }
+
+    /// Undo `archive_current_version` after a failed write (best-effort).
+    pub(crate) async fn unarchive_version(
+        &self,
+        bucket: &Bucket,
+        version: &ObjectVersion,
+        live_path: &Path,
+    ) {
+        if !version.is_delete_marker {
+            let source = self.version_path(&bucket.name, version.id);
+            if let Err(err) = move_file(&source, live_path).await {
+                warn!(
+                    "failed to restore version {} of {}/{}: {}",
+                    version.version_id, bucket.name, version.key, err
+                );
+                return;
+            }
+        }
+        let _ = sqlx::query("DELETE FROM object_versions WHERE id = ?")
+            .bind(version.id)
+            .execute(&*self.db)
+            .await;
+    }
+
+    /// `put_delete_marker` under the key's lock.
+    pub(crate) async fn locked_delete_marker(
+        &self,
+        bucket: &Bucket,
+        key: &str,
+    ) -> StorageResult<Object> {
+        let _key_guard = self.key_locks.lock(bucket.id, key).await;
+        self.put_delete_marker(bucket, key).await
+    }
+
+    /// Make a delete marker the current version of `key`.
+    ///
+    /// The current version is archived first; in a suspended bucket the
+    /// `null` version is removed instead and the marker gets the `null` id.
+    /// The caller holds the key's lock (see `key_lock`).
+    pub(crate) async fn put_delete_marker(
+        &self,
+        bucket: &Bucket,
+        key: &str,
+    ) -> StorageResult<Object> {
+        let archived = self.archive_current_version(bucket, key).await?;
+        let live_path = self.live_path(bucket, key).await?;
+        if archived.is_none() {
+            self.remove_payload(&bucket.name, &live_path).await?;
+        }
+
+        let version_id = bucket.versioning_enabled.then(new_version_id);
+        let filename = key.split('/').next_back().unwrap_or(key).to_string();
+        let marker = sqlx::query_as::<_, Object>(&format!(
+            "INSERT INTO objects (
+                id, bucket_id, key, filename, content_type, content_encoding, size_bytes,
+                etag, checksum_sha256, storage_class, last_modified, version_id, is_deleted
+             ) VALUES (?, ?, ?, ?, NULL, NULL, 0, NULL, NULL, 'STANDARD', ?, ?, 1)
+             ON CONFLICT(bucket_id, key) DO UPDATE SET
+                content_type = NULL,
+                content_encoding = NULL,
+                size_bytes = 0,
+                etag = NULL,
+                checksum_sha256 = NULL,
+                checksum_crc32c = NULL,
+                last_modified = excluded.last_modified,
+                version_id = excluded.version_id,
+                is_deleted = 1,
+                acl = NULL,
+                cache_control = NULL,
+                content_disposition = NULL,
+                expires = NULL
+             RETURNING {OBJECT_COLUMNS}"
+        ))
+        .bind(Uuid::new_v4())
+        .bind(bucket.id)
+        .bind(key)
+        .bind(&filename)
+        .bind(Utc::now())
+        .bind(&version_id)
+        .fetch_one(&*self.db)
+        .await;
+        let marker = match marker {
+            Ok(marker) => marker,
+            Err(err) => {
+                if let Some(archived) = archived {
+                    self.unarchive_version(bucket, &archived, &live_path).await;
+                }
+                return Err(StorageError::Sqlx(err));
+            }
+        };
+
+        self.prune_payload_dirs(&bucket.name, &live_path).await;
+        self.record_change(bucket.id, NewChange::of(ChangeKind::Deleted, &marker))
+            .await;
+        self.events
+            .object(EventKind::ObjectDeleted, &bucket.name, key, None, None);
+        Ok(marker)
+    }
+
+    /// Open a specific version of `key` for reading.
+    pub async fn get_object_version_reader(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: &str,
+    ) -> StorageResult<(Object, File)> {
+        self.ensure_key_safe(key)?;
+        let bucket_rec = self.fetch_bucket(bucket).await?;
+        let (object, path) = self.resolve_version(&bucket_rec, key, version_id).await?;
+        self.ensure_not_quarantined(&object).await?;
+        let file = match File::open(&path).await {
+            Ok(file) => file,
+            Err(err) if err.kind() == io::ErrorKind::NotFound => self
+                .open_tiered(&bucket_rec, &object)
+                .await?
+                .ok_or_else(|| self.no_such_version(key, version_id))?,
+            Err(err) => return Err(StorageError::Io(err)),
+        };
+        Ok((object, file))
+    }
+
+    /// Metadata of a specific version of `key`.
+    pub async fn get_object_version_metadata(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: &str,
+    ) -> StorageResult<Object> {
+        self.ensure_key_safe(key)?;
+        let bucket_rec = self.fetch_bucket(bucket).await?;
+        let (object, _) = self.resolve_version(&bucket_rec, key, version_id).await?;
+        Ok(object)
+    }
+
+    /// Find a version (current or archived) and the path of its payload.
+    async fn resolve_version(
+        &self,
+        bucket: &Bucket,
+        key: &str,
+        version_id: &str,
+    ) -> StorageResult<(Object, PathBuf)> {
+        if let Some(current) = self.fetch_current_row(bucket, key).await?
+            && current.version_id.as_deref().unwrap_or(NULL_VERSION) == version_id
+        {
+            if current.is_deleted {
+                return Err(StorageError::VersionIsDeleteMarker {
+                    key: key.to_string(),
+                    version_id: version_id.to_string(),
+                });
+            }
+            let path = self.object_path_on(current.volume.as_deref(), &bucket.name, key);
+            return Ok((current, path));
+        }
+
+        let version = self
+            .fetch_archived_version(bucket, key, version_id)
+            .await?
+            .ok_or_else(|| self.no_such_version(key, version_id))?;
+        if version.is_delete_marker {
+            return Err(StorageError::VersionIsDeleteMarker {
+                key: key.to_string(),
+                version_id: version_id.to_string(),
+            });
+        }
+        let path = self.version_path(&bucket.name, version.id);
+        Ok((version_object(version), path))
+    }
+
+    /// Permanently delete one version of `key`. Deleting the current version
+    /// promotes the newest remaining one (which may be a delete marker).
+    pub async fn delete_object_version(
+        &self,
+        bucket: &str,
+        key: &str,
+        version_id: &str,
+    ) -> StorageResult<DeletedVersion> {
+        self.ensure_key_safe(key)?;
+        let bucket_rec = self.fetch_writable_bucket(bucket).await?;
+        let _key_guard = self.key_locks.lock(bucket_rec.id, key).await;
+        self.ensure_version_unlocked(&bucket_rec, key, version_id)
+            .await?;
+        let live_path = self.live_path(&bucket_rec, key).await?;
+
+        let deleted = match self.fetch_current_row(&bucket_rec, key).await? {
+            Some(current)
+                if current.version_id.as_deref().unwrap_or(NULL_VERSION) == version_id =>
+            {
+                if !current.is_deleted {
+                    self.remove_payload(&bucket_rec.name, &live_path).await?;
+                }
+                if !self.promote_latest_version(&bucket_rec, key).await? {
+                    sqlx::query(
+                        "UPDATE objects SET is_deleted = 1, version_id = NULL, deleted_at = ?
+                         WHERE bucket_id = ? AND key = ?",
+                    )
+                    .bind(Utc::now())
+                    .bind(bucket_rec.id)
+                    .bind(key)
+                    .execute(&*self.db)
+                    .await?;
+                }
+                DeletedVersion {
+                    version_id: version_id.to_string(),
+                    delete_marker: current.is_deleted,
+                }
+            }
+            _ => {
+                let version = self
+                    .fetch_archived_version(&bucket_rec, key, version_id)
+                    .await?
+                    .ok_or_else(|| self.no_such_version(key, version_id))?;
+                self.remove_archived(&bucket_rec, &version).await?;
+                DeletedVersion {
+                    version_id: version.version_id,
+                    delete_marker: version.is_delete_marker,
+                }
+            }
+        };
+        sqlx::query(
+            "DELETE FROM object_retention WHERE bucket_id = ? AND key = ? AND version_id = ?",
+        )
+        .bind(bucket_rec.id)
+        .bind(key)
+        .bind(version_id)
+        .execute(&*self.db)
+        .await?;
+        sqlx::query(
+            "DELETE FROM object_encryption WHERE bucket_id = ? AND key = ? AND version_id = ?",
+        )
+        .bind(bucket_rec.id)
+        .bind(key)
+        .bind(version_id)
+        .execute(&*self.db)
+        .await?;
+        sqlx::query("DELETE FROM object_scans WHERE bucket_id = ? AND key = ? AND version_id = ?")
+            .bind(bucket_rec.id)
+            .bind(key)
+            .bind(version_id)
+            .execute(&*self.db)
+            .await?;
+
+        self.prune_payload_dirs(&bucket_rec.name, &live_path).await;
+        self.record_change(
+            bucket_rec.id,
+            NewChange::deleted(key, Some(&deleted.version_id)),
+        )
+        .await;
+        self.events
+            .object(EventKind::ObjectDeleted, &bucket_rec.name, key, None, None);
+        Ok(deleted)
+    }
+
+    /// Make the newest archived version of `key` current again. Returns
+    /// `false` when the history is empty.
+    async fn promote_latest_version(&self, bucket: &Bucket, key: &str) -> StorageResult<bool> {
+        let Some(latest) = sqlx::query_as::<_, ObjectVersion>(&format!(
+            "SELECT {VERSION_COLUMNS} FROM object_versions
+             WHERE bucket_id = ? AND key = ?
+             ORDER BY last_modified DESC LIMIT 1"
+        ))
+        .bind(bucket.id)
+        .bind(key)
+        .fetch_optional(&*self.db)
+        .await?
+        else {
+            return Ok(false);
+        };
+
+        let live_path = self.live_path(bucket, key).await?;
+        if !latest.is_delete_marker {
+            if let Some(parent) = live_path.parent() {
+                fs::create_dir_all(parent).await?;
+            }
+            match move_file(&self.version_path(&bucket.name, latest.id), &live_path).await {
+                Ok(()) => {}
+                Err(err)
+                    if err.kind() == io::ErrorKind::NotFound
+                        && self.version_is_tiered(bucket, &latest).await? => {}
+                Err(err) => return Err(StorageError::Io(err)),
+            }
+        }
+
+        let version_id = Some(latest.version_id.as_str()).filter(|v| *v != NULL_VERSION);
+        let mut tx = self.db.begin().await?;
+        sqlx::query(
+            "UPDATE objects SET
+                content_type = ?, content_encoding = ?, size_bytes = ?, etag = ?,
+                checksum_sha256 = ?, checksum_crc32c = ?, storage_class = ?, last_modified = ?,
+                version_id = ?, is_deleted = ?, acl = ?, cache_control = ?,
+                content_disposition = ?, expires = ?
+             WHERE bucket_id = ? AND key = ?",
+        )
+        .bind(&latest.content_type)
+        .bind(&latest.content_encoding)
+        .bind(latest.size_bytes)
+        .bind(&latest.etag)
+        .bind(&latest.checksum_sha256)
+        .bind(&latest.checksum_crc32c)
+        .bind(&latest.storage_class)
+        .bind(latest.last_modified)
+        .bind(version_id)
+        .bind(latest.is_delete_marker)
+        .bind(&latest.acl)
+        .bind(&latest.cache_control)
+        .bind(&latest.content_disposition)
+        .bind(&latest.expires)
+        .bind(bucket.id)
+        .bind(key)
+        .execute(&mut *tx)
+        .await?;
+        sqlx::query("DELETE FROM object_versions WHERE id = ?")
+            .bind(latest.id)
+            .execute(&mut *tx)
+            .await?;
+        tx.commit().await?;
+
+        debug!(
+            "promoted version {} of {}/{}",
+            latest.version_id, bucket.name, key
+        );
+        Ok(true)
+    }
+
+    async fn fetch_archived_version(
+        &self,
+        bucket: &Bucket,
+        key: &str,
+        version_id: &str,
+    ) -> StorageResult<Option<ObjectVersion>> {
+        let version = sqlx::query_as::<_, ObjectVersion>(&format!(
+            "SELECT {VERSION_COLUMNS} FROM object_versions
+             WHERE bucket_id = ? AND key = ? AND version_id = ?"
+        ))
+        .bind(bucket.id)
+        .bind(key)
+        .bind(version_id)
+        .fetch_optional(&*self.db)
+        .await?;
+        Ok(version)
+    }
+
+    async fn drop_archived_version(
+        &self,
+        bucket: &Bucket,
+        key: &str,
+        version_id: &str,
+    ) -> StorageResult<()> {
+        if let Some(version) = self.fetch_archived_version(bucket, key, version_id).await? {
+            self.remove_archived(bucket, &version).await?;
+        }
+        Ok(())
+    }
+
+    async fn remove_archived(&self, bucket: &Bucket, version: &ObjectVersion) -> StorageResult<()> {
+        sqlx::query("DELETE FROM object_versions WHERE id = ?")
+            .bind(version.id)
+            .execute(&*self.db)
+            .await?;
+        if !version.is_delete_marker {
+            self.discard_file(self.version_path(&bucket.name, version.id))
+                .await;
+        }
+        Ok(())
+    }
+
+    fn no_such_version(&self, key: &str, version_id: &str) -> StorageError {
+        StorageError::NoSuchVersion {
+            key: key.to_string(),
+            version_id: version_id.to_string(),
+        }
+    }
+
+    /// List versions and delete markers, keys ascending and newest version
+    /// first within a key, following S3 ListObjectVersions.
+    pub async fn list_object_versions(
+        &self,
+        bucket: &str,
+        params: ListVersionsParams,
+    ) -> StorageResult<ListVersionsResult> {
+        let prefix = params.prefix.unwrap_or_default();
+        self.ensure_prefix_safe(&prefix)?;
+        let bucket_rec = self.fetch_bucket(bucket).await?;
+        let max_keys = params.max_keys.clamp(1, DEFAULT_MAX_VERSION_KEYS);
+        let mut entries = Vec::new();
+
+        // Remaining versions of the marker key, after the marker version.
+        if let (Some(key_marker), Some(version_marker)) = (
+            params.key_marker.as_deref(),
+            params.version_id_marker.as_deref(),
+        ) && key_marker.starts_with(&prefix)
+        {
+            let rows = self
+                .version_rows(&bucket_rec, "=", key_marker, i64::MAX)
+                .await?;
+            entries.extend(
+                rows.into_iter()
+                    .skip_while(|row| row.version_id != version_marker)
+                    .skip(1),
+            );
+        }
+
+        let (op, lower) = match params.key_marker.as_deref() {
+            Some(marker) if marker >= prefix.as_str() => (">", marker),
+            _ => (">=", prefix.as_str()),
+        };
+        let rows = self
+            .version_rows(&bucket_rec, op, lower, max_keys as i64 + 1)
+            .await?;
+        // Keys sharing a prefix are contiguous in key order.
+        entries.extend(
+            rows.into_iter()
+                .take_while(|row| row.key.starts_with(&prefix)),
+        );
+
+        let is_truncated = entries.len() > max_keys;
+        entries.truncate(max_keys);
+        let (next_key_marker, next_version_id_marker) = match entries.last() {
+            Some(last) if is_truncated => (Some(last.key.clone()), Some(last.version_id.clone())),
+            _ => (None, None),
+        };
+        Ok(ListVersionsResult {
+            entries,
+            is_truncated,
+            next_key_marker,
+            next_version_id_marker,
+        })
+    }
+
+    /// Current and archived versions of keys compared to `bound` with `op`.
+    async fn version_rows(
+        &self,
+        bucket: &Bucket,
+        op: &'static str,
+        bound: &str,
+        limit: i64,
+    ) -> StorageResult<Vec<VersionEntry>> {
+        let rows = sqlx::query_as::<_, VersionEntry>(&format!(
+            "SELECT key, COALESCE(version_id, 'null') AS version_id, 1 AS is_latest,
+                    is_deleted AS is_delete_marker, last_modified, etag, size_bytes, storage_class
+             FROM objects
+             WHERE bucket_id = ?1 AND key {op} ?2
+               AND NOT (is_deleted = 1 AND version_id IS NULL)
+             UNION ALL
+             SELECT key, version_id, 0, is_delete_marker, last_modified, etag, size_bytes,
+                    storage_class
+             FROM object_versions
+             WHERE bucket_id = ?1 AND key {op} ?2
+             ORDER BY key ASC, is_latest DESC, last_modified DESC
+             LIMIT ?3"
+        ))
+        .bind(bucket.id)
+        .bind(bound)
+        .bind(limit)
+        .fetch_all(&*self.db)
+        .await?;
+        Ok(rows)
+    }
+}
+
+/// View an archived version as an `Object` for serving it.
+fn version_object(version: ObjectVersion) -> Object {
+    let filename = version
+        .key
+        .split('/')
+        .next_back()
+        .unwrap_or(&version.key)
+        .to_string();
+    Object {
+        id: version.id,
+        bucket_id: version.bucket_id,
+        key: version.key,
+        filename,
+        content_type: version.content_type,
+        content_encoding: version.content_encoding,
+        size_bytes: version.size_bytes,
+        etag: version.etag,
+        checksum_sha256: version.checksum_sha256,
+        checksum_crc32c: version.checksum_crc32c,
+        storage_class: version.storage_class,
+        last_modified: version.last_modified,
+        version_id: Some(version.version_id),
+        is_deleted: false,
+        acl: version.acl,
+        cache_control: version.cache_control,
+        content_disposition: version.content_disposition,
+        expires: version.expires,
+        volume: None,
+    }
+}
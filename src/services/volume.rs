@@ -0,0 +1,189 @@
+//! Health of the storage volumes.
+//!
+//! Payloads live on the default volume (`base_path`) unless a placement
+//! policy puts them on another configured one (see `placement`); this
+//! module tracks how writes fare so `/readyz` and `GET /admin/volumes` can
+//! report them alongside whether each volume is reachable and how much
+//! space is left. Write failures are counted once for all volumes and
+//! reported on the default one.
+//!
+//! Disk errors while staging or persisting a payload are counted. After
+//! `StorageOptions::volume_failure_threshold` failures in a row the volume
+//! is marked read-only: writes are refused with 503 instead of each one
+//! failing half-way, and reads keep working. A successful write resets the
+//! streak; only an operator clears the read-only mark again
+//! (`POST /admin/volumes/{volume}/reset`), once the disk has been looked at.
+
+use crate::services::storage_service::StorageService;
+use serde::Serialize;
+use std::{
+    io,
+    path::Path,
+    sync::{
+        Arc, Mutex,
+        atomic::{AtomicBool, AtomicU64, Ordering},
+    },
+};
+use tokio::fs;
+use tracing::{error, info};
+
+/// Name of the volume at `base_path`.
+pub const DEFAULT_VOLUME: &str = "default";
+
+#[derive(Debug, Default)]
+struct VolumeState {
+    write_errors: AtomicU64,
+    consecutive_failures: AtomicU64,
+    read_only: AtomicBool,
+    last_error: Mutex<Option<String>>,
+}
+
+/// Shared write error counters of the volume.
+#[derive(Debug, Clone, Default)]
+pub struct VolumeHealth {
+    state: Arc<VolumeState>,
+}
+
+impl VolumeHealth {
+    /// Whether writes are refused after repeated failures.
+    pub fn is_read_only(&self) -> bool {
+        self.state.read_only.load(Ordering::Relaxed)
+    }
+
+    pub(crate) fn record_write_ok(&self) {
+        self.state.consecutive_failures.store(0, Ordering::Relaxed);
+    }
+
+    /// Count a failed write; with `threshold` failures in a row the volume
+    /// becomes read-only.
+    pub(crate) fn record_write_error(&self, err: &io::Error, threshold: Option<u64>) {
+        self.state.write_errors.fetch_add(1, Ordering::Relaxed);
+        let streak = self
+            .state
+            .consecutive_failures
+            .fetch_add(1, Ordering::Relaxed)
+            + 1;
+        *self
+            .state
+            .last_error
+            .lock()
+            .unwrap_or_else(|e| e.into_inner()) = Some(err.to_string());
+        if let Some(threshold) = threshold
+            && streak >= threshold
+            && !self.state.read_only.swap(true, Ordering::Relaxed)
+        {
+            error!(
+                "volume `{}` marked read-only after {} consecutive write failures: {}",
+                DEFAULT_VOLUME, streak, err
+            );
+        }
+    }
+
+    /// Accept writes again and forget the failure streak.
+    pub fn reset(&self) {
+        self.state.consecutive_failures.store(0, Ordering::Relaxed);
+        if self.state.read_only.swap(false, Ordering::Relaxed) {
+            info!("volume `{}` is writable again", DEFAULT_VOLUME);
+        }
+    }
+}
+
+/// State of one volume as reported by `/readyz` and `GET /admin/volumes`.
+#[derive(Debug, Clone, Serialize)]
+pub struct VolumeStatus {
+    pub name: String,
+    pub path: String,
+    /// Where temp files are written, when not next to their payloads.
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub staging_path: Option<String>,
+    /// The directory exists and can be inspected.
+    pub reachable: bool,
+    /// Writes are accepted (not marked read-only).
+    pub writable: bool,
+    /// Bytes available to the server; `None` where unknown.
+    pub free_bytes: Option<u64>,
+    /// Failed writes since the server started.
+    pub write_errors: u64,
+    pub consecutive_write_failures: u64,
+    pub last_error: Option<String>,
+}
+
+impl VolumeStatus {
+    pub fn ok(&self) -> bool {
+        self.reachable && self.writable
+    }
+}
+
+impl StorageService {
+    /// Current state of every volume.
+    pub async fn volume_statuses(&self) -> Vec<VolumeStatus> {
+        let state = &self.volume.state;
+        let reachable = fs::metadata(&self.base_path)
+            .await
+            .is_ok_and(|meta| meta.is_dir());
+        let mut statuses = vec![VolumeStatus {
+            name: DEFAULT_VOLUME.to_string(),
+            path: self.base_path.display().to_string(),
+            staging_path: self
+                .options
+                .staging_dir
+                .as_ref()
+                .map(|dir| dir.display().to_string()),
+            reachable,
+            writable: !self.volume.is_read_only(),
+            free_bytes: disk_free_bytes(&self.base_path).ok().flatten(),
+            write_errors: state.write_errors.load(Ordering::Relaxed),
+            consecutive_write_failures: state.consecutive_failures.load(Ordering::Relaxed),
+            last_error: state
+                .last_error
+                .lock()
+                .unwrap_or_else(|e| e.into_inner())
+                .clone(),
+        }];
+        for spec in &self.options.volumes {
+            statuses.push(VolumeStatus {
+                name: spec.name.clone(),
+                path: spec.path.display().to_string(),
+                staging_path: None,
+                reachable: fs::metadata(&spec.path)
+                    .await
+                    .is_ok_and(|meta| meta.is_dir()),
+                writable: !self.volume.is_read_only(),
+                free_bytes: disk_free_bytes(&spec.path).ok().flatten(),
+                write_errors: 0,
+                consecutive_write_failures: 0,
+                last_error: None,
+            });
+        }
+        statuses
+    }
+
+    /// Pass a disk write result through, counting it against the volume.
+    pub(crate) fn track_write<T>(&self, result: io::Result<T>) -> io::Result<T> {
+        if let Err(err) = &result {
+            self.volume
+                .record_write_error(err, self.options.volume_failure_threshold);
+        }
+        result
+    }
+}
+
+/// Bytes available to unprivileged users on the filesystem holding `path`.
+/// `None` where this is not supported.
+#[cfg(unix)]
+pub(crate) fn disk_free_bytes(path: &Path) -> io::Result<Option<u64>> {
+    use std::{ffi::CString, os::unix::ffi::OsStrExt};
+
+    let c_path = CString::new(path.as_os_str().as_bytes())?;
+    let mut stat: libc::statvfs = unsafe { std::mem::zeroed() };
+    // SAFETY: `c_path` is NUL-terminated and `stat` is a valid out-pointer.
+    if unsafe { libc::statvfs(c_path.as_ptr(), &mut stat) } != 0 {
+        return Err(io::Error::last_os_error());
+    }
+    Ok(Some(stat.f_bavail as u64 * stat.f_frsize as u64))
+}
+
+#[cfg(not(unix))]
+pub(crate) fn disk_free_bytes(_path: &Path) -> io::Result<Option<u64>> {
+    Ok(None)
+}
@@ -0,0 +1,100 @@
+//! Round trips through the typed client against a server on a real socket.
+//! Run with `cargo test --features client`.
+
+#![cfg(feature = "client")]
+
+mod common;
+
+use common::TestApp;
+use futures::TryStreamExt;
+use object_store::client::{Client, ClientError};
+use reqwest::StatusCode;
+
+/// Serve a fresh instance on an ephemeral port; the app must outlive the
+/// client calls.
+async fn serve() -> (TestApp, Client) {
+    let app = TestApp::new().await;
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+        .await
+        .expect("bind");
+    let addr = listener.local_addr().expect("local addr");
+    let router = app.router.clone();
+    tokio::spawn(async move { axum::serve(listener, router).await });
+    (app, Client::new(format!("http://{}", addr)))
+}
+
+#[tokio::test]
+async fn object_round_trip() {
+    let (_app, client) = serve().await;
+    client.create_bucket("photos").await.unwrap();
+
+    let put = client
+        .put_object("photos", "2025/a b.txt", "hello", Some("text/plain"))
+        .await
+        .unwrap();
+    assert_eq!(
+        put.etag.as_deref(),
+        Some("5d41402abc4b2a76b9719d911017c592")
+    );
+
+    let stream = futures::stream::iter(["wor", "ld"].map(Ok::<_, std::io::Error>));
+    client
+        .put_object_stream("photos", "2025/b", stream, Some(5), None)
+        .await
+        .unwrap();
+
+    let get = client.get_object("photos", "2025/a b.txt").await.unwrap();
+    assert_eq!(get.info.content_type.as_deref(), Some("text/plain"));
+    assert_eq!(get.bytes().await.unwrap(), "hello");
+
+    let range = client
+        .get_object_range("photos", "2025/b", 1, Some(3))
+        .await
+        .unwrap();
+    assert_eq!(range.bytes().await.unwrap(), "orl");
+
+    let head = client.head_object("photos", "2025/b").await.unwrap();
+    assert_eq!(head.content_length, Some(5));
+
+    let keys: Vec<String> = client
+        .list_all("photos", Some("2025/"))
+        .map_ok(|object| object.key)
+        .try_collect()
+        .await
+        .unwrap();
+    assert_eq!(keys, ["2025/a b.txt", "2025/b"]);
+
+    client.delete_object("photos", "2025/b").await.unwrap();
+    match client.head_object("photos", "2025/b").await {
+        Err(ClientError::Status { status, .. }) => assert_eq!(status, StatusCode::NOT_FOUND),
+        other => panic!("expected 404, got {:?}", other.map(|_| ())),
+    }
+}
+
+#[tokio::test]
+async fn multipart_helper_splits_reader() {
+    let (_app, client) = serve().await;
+    client.create_bucket("videos").await.unwrap();
+
+    let payload: Vec<u8> = (0..11 * 1024 * 1024).map(|i| (i % 251) as u8).collect();
+    client
+        .upload_multipart(
+            "videos",
+            "clip.bin",
+            payload.as_slice(),
+            5 * 1024 * 1024,
+            None,
+        )
+        .await
+        .unwrap();
+
+    let body = client
+        .get_object("videos", "clip.bin")
+        .await
+        .unwrap()
+        .bytes()
+        .await
+        .unwrap();
+    assert_eq!(body.len(), payload.len());
+    assert!(body == payload.as_slice());
+}
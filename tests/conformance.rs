@@ -0,0 +1,11155 @@
+//! S3 conformance harness.
+//!
+//! Runs a table of S3 behaviours against an in-process server and reports
+//! pass/fail per operation, so compatibility claims stay measurable as
+//! features land. Cases listed as `known_failure` document gaps: the suite
+//! fails if a supported case regresses *or* if a known failure starts passing
+//! (so the table gets updated alongside the feature).
+//!
+//! Set `OBJECT_STORE_CONFORMANCE_REPORT=path.json` to also write the results
+//! as JSON.
+
+mod common;
+
+use axum::{
+    body::Body,
+    http::{Method, Request, StatusCode},
+};
+use common::TestApp;
+use futures::{StreamExt, future::BoxFuture};
+use std::collections::BTreeMap;
+
+type CaseResult = Result<(), String>;
+type CaseFn = for<'a> fn(&'a TestApp) -> BoxFuture<'a, CaseResult>;
+
+struct Case {
+    operation: &'static str,
+    name: &'static str,
+    run: CaseFn,
+    known_failure: bool,
+}
+
+macro_rules! case {
+    ($op:literal, $name:literal, $f:ident) => {
+        Case {
+            operation: $op,
+            name: $name,
+            run: |app| Box::pin($f(app)),
+            known_failure: false,
+        }
+    };
+    ($op:literal, $name:literal, $f:ident, known_failure) => {
+        Case {
+            operation: $op,
+            name: $name,
+            run: |app| Box::pin($f(app)),
+            known_failure: true,
+        }
+    };
+}
+
+macro_rules! ensure {
+    ($cond:expr, $($msg:tt)+) => {
+        if !$cond {
+            return Err(format!($($msg)+));
+        }
+    };
+}
+
+fn cases() -> Vec<Case> {
+    vec![
+        case!("CreateBucket", "create bucket", create_bucket),
+        case!(
+            "CreateBucket",
+            "create bucket without a body",
+            create_bucket_no_body,
+            known_failure
+        ),
+        case!(
+            "CreateBucket",
+            "duplicate bucket is 409",
+            create_bucket_duplicate
+        ),
+        case!(
+            "CreateBucket",
+            "invalid bucket name is 400",
+            create_bucket_invalid_name
+        ),
+        case!("DeleteBucket", "delete bucket", delete_bucket),
+        case!(
+            "DeleteBucket",
+            "refuses a non-empty bucket unless forced",
+            delete_bucket_not_empty
+        ),
+        case!(
+            "DeleteBucket",
+            "?prefix= deletes the keys under it in a background job",
+            delete_prefix_job
+        ),
+        case!(
+            "DeleteBucket",
+            "?prefix= matches literally, not as a LIKE pattern",
+            delete_prefix_literal
+        ),
+        case!(
+            "DeleteBucket",
+            "?prefix= refuses an empty prefix",
+            delete_prefix_empty
+        ),
+        case!(
+            "DeleteBucket",
+            "?prefix= fails when the bucket turns read-only mid-job",
+            delete_prefix_read_only_mid_job
+        ),
+        case!("HeadBucket", "head existing bucket", head_bucket),
+        case!(
+            "HeadBucket",
+            "head missing bucket is 404",
+            head_bucket_missing
+        ),
+        case!(
+            "GetBucketLocation",
+            "location subresource",
+            get_bucket_location
+        ),
+        case!(
+            "GetBucketLocation",
+            "stored region reported",
+            get_bucket_location_region
+        ),
+        case!("PutObject", "etag is md5 of body", put_object_etag),
+        case!("PutObject", "raw body stored verbatim", put_object_raw_body),
+        case!(
+            "PutObject",
+            "aws-chunked bodies are decoded",
+            put_object_aws_chunked
+        ),
+        case!(
+            "PutObject",
+            "aws-chunked chunk signatures verified",
+            put_object_aws_chunked_signed
+        ),
+        case!(
+            "PutObject",
+            "concurrent writes to one key stay consistent",
+            put_object_concurrent_overwrite
+        ),
+        case!(
+            "PutObject",
+            "overwrite replaces payload",
+            put_object_overwrite
+        ),
+        case!(
+            "PutObject",
+            "overwritten payloads are recycled and recoverable",
+            put_object_recycle
+        ),
+        case!(
+            "PutObject",
+            "read-only bucket rejects writes",
+            put_object_read_only_bucket
+        ),
+        case!(
+            "PutObject",
+            "volume turns read-only after write failures",
+            put_object_volume_read_only
+        ),
+        case!(
+            "PutObject",
+            "server-computed sha256 returned",
+            put_object_sha256
+        ),
+        case!(
+            "GetObject",
+            "x-amz-checksum-mode verifies the payload",
+            get_object_checksum_mode
+        ),
+        case!(
+            "PutObject",
+            "Content-MD5 checked against the body",
+            put_object_content_md5
+        ),
+        case!(
+            "PutObject",
+            "x-amz-checksum-crc32c checked and stored",
+            put_object_checksum_crc32c
+        ),
+        case!(
+            "PutObject",
+            "x-amz-content-sha256 digest checked against the body",
+            put_object_content_sha256
+        ),
+        case!("PutObject", "stale if-match is 412", put_object_if_match),
+        case!(
+            "GetObject",
+            "round trip body and headers",
+            get_object_round_trip
+        ),
+        case!("GetObject", "large object streamed whole", get_object_large),
+        case!(
+            "GetObject",
+            "content-encoding replayed",
+            get_object_content_encoding
+        ),
+        case!(
+            "GetObject",
+            "missing key is 404 NoSuchKey",
+            get_object_missing,
+            known_failure
+        ),
+        case!("GetObject", "range request", get_object_range),
+        case!("GetObject", "memory-mapped read and range", get_object_mmap),
+        case!(
+            "GetObject",
+            "ranges served from the block cache",
+            get_object_range_cached
+        ),
+        case!(
+            "GetObject",
+            "unsatisfiable range is 416",
+            get_object_range_unsatisfiable
+        ),
+        case!(
+            "GetObject",
+            "if-range with stale etag returns full body",
+            get_object_if_range
+        ),
+        case!(
+            "GetObject",
+            "bucket cache-control and expires",
+            get_object_cache_headers
+        ),
+        case!(
+            "GetObject",
+            "returns content headers stored at upload",
+            get_object_stored_content_headers
+        ),
+        case!(
+            "CompleteMultipartUpload",
+            "keeps content headers given at initiation",
+            complete_multipart_upload_content_headers
+        ),
+        case!(
+            "GetObject",
+            "if-none-match returns 304",
+            get_object_if_none_match
+        ),
+        case!(
+            "GetObject",
+            "if-modified-since returns 304",
+            get_object_if_modified_since
+        ),
+        case!("HeadObject", "metadata without body", head_object),
+        case!(
+            "HeadObject",
+            "user metadata echoed",
+            head_object_user_metadata
+        ),
+        case!(
+            "GetObject",
+            "returns x-amz-meta-* headers",
+            get_object_user_metadata
+        ),
+        case!(
+            "CopyObject",
+            "COPY directive keeps source metadata",
+            copy_object_metadata_copy
+        ),
+        case!(
+            "CopyObject",
+            "REPLACE directive takes request metadata",
+            copy_object_metadata_replace
+        ),
+        case!(
+            "CopyObject",
+            "copy onto itself without REPLACE rejected",
+            copy_object_onto_itself
+        ),
+        case!(
+            "CopyObject",
+            "REPLACE onto itself updates metadata in place",
+            copy_object_in_place
+        ),
+        case!(
+            "PutObjectTagging",
+            "tags round-trip through GetObjectTagging",
+            put_object_tagging
+        ),
+        case!(
+            "PutObjectTagging",
+            "more than 10 tags rejected",
+            put_object_tagging_too_many
+        ),
+        case!(
+            "PutObject",
+            "x-amz-tagging sets tags and tagging count",
+            put_object_with_tagging_header
+        ),
+        case!(
+            "DeleteObjectTagging",
+            "removes every tag",
+            delete_object_tagging
+        ),
+        case!(
+            "PutObject",
+            "bucket default ACL applies without x-amz-acl",
+            put_object_default_acl
+        ),
+        case!(
+            "PutObject",
+            "bucket-owner-full-control enforced",
+            put_object_owner_full_control
+        ),
+        case!(
+            "PutObject",
+            "dropped connection leaves no temp file",
+            put_object_dropped_connection
+        ),
+        case!(
+            "Fsck",
+            "sweeps temp files and reports payload mismatches",
+            fsck_reconciles_payloads
+        ),
+        case!(
+            "MetadataExport",
+            "a JSONL export imports into an empty store and round-trips",
+            metadata_export_import
+        ),
+        case!(
+            "Staging",
+            "uploads are staged in the configured directory",
+            staging_dir_holds_temp_files
+        ),
+        case!(
+            "ProxyCache",
+            "GET/HEAD headers suit caching proxies",
+            proxy_cache_headers
+        ),
+        case!(
+            "Shadow",
+            "sampled requests are mirrored to the secondary with their bodies",
+            shadow_mirrors_requests
+        ),
+        case!(
+            "AccessLog",
+            "every response carries its x-amz-request-id",
+            access_log_request_ids
+        ),
+        case!(
+            "SloReport",
+            "latency percentiles and error rates per operation",
+            slo_report
+        ),
+        case!(
+            "Alerts",
+            "thresholds fire and resolve once and are posted to the webhook",
+            alerts_fire_and_resolve
+        ),
+        case!(
+            "ServerTiming",
+            "per-phase timing breakdown behind the debug header",
+            server_timing_breakdown
+        ),
+        case!(
+            "BucketStats",
+            "usage and transfers per key prefix",
+            bucket_stats_per_prefix
+        ),
+        case!(
+            "Analytics",
+            "storage class analysis reports reads per prefix and object age as CSV",
+            storage_class_analysis
+        ),
+        case!(
+            "Quota",
+            "bucket byte and object quotas refuse writes",
+            bucket_quota_enforced
+        ),
+        case!(
+            "Quota",
+            "the global quota cuts off an oversized upload",
+            global_quota_enforced
+        ),
+        case!(
+            "Limits",
+            "uploads over the maximum object size answer 413",
+            max_object_size_enforced
+        ),
+        case!(
+            "Limits",
+            "request bodies over the limit answer 413",
+            max_request_body_enforced
+        ),
+        case!(
+            "ClientInfo",
+            "X-Forwarded-For resolves the client behind trusted proxies",
+            client_info_x_forwarded_for
+        ),
+        case!(
+            "ClientInfo",
+            "a client-sent Forwarded header behind an XFF proxy is ignored",
+            client_info_forwarded_spoof
+        ),
+        case!(
+            "ClientInfo",
+            "Forwarded is read when configured and stops at unknown hops",
+            client_info_forwarded
+        ),
+        case!(
+            "Throttling",
+            "clients over the request rate answer 429 SlowDown",
+            rate_limit_per_ip
+        ),
+        case!(
+            "Throttling",
+            "uploads beyond the concurrency cap answer 503 SlowDown",
+            concurrent_uploads_capped
+        ),
+        case!(
+            "NamingPolicy",
+            "keys breaking a bucket's naming policy are refused",
+            naming_policy_enforced
+        ),
+        case!(
+            "AutoTagging",
+            "bucket rules tag uploads by key, content type, size and uploader",
+            auto_tagging_on_upload
+        ),
+        case!(
+            "AutoTagging",
+            "uploader rules match only verified signatures",
+            auto_tagging_verified_uploader
+        ),
+        case!(
+            "ChangeFeed",
+            "writes and deletes are listed in order after a cursor",
+            change_feed
+        ),
+        case!(
+            "AdminEvents",
+            "bucket activity streams as Server-Sent Events, filtered by bucket",
+            admin_events_stream
+        ),
+        case!(
+            "PutBucketNotificationConfiguration",
+            "webhooks receive S3 events and failed deliveries are retried",
+            bucket_notification_webhooks
+        ),
+        case!(
+            "Placement",
+            "key prefixes are placed on their volume and found there",
+            placement_by_prefix
+        ),
+        case!(
+            "ShardLayout",
+            "payloads move to a new shard depth and stay readable meanwhile",
+            shard_layout_migration
+        ),
+        case!(
+            "ShardLayout",
+            "portable names escape what Windows rejects",
+            shard_layout_portable_names
+        ),
+        case!(
+            "EventPublishers",
+            "object events are published to configured NATS subjects",
+            event_publishers
+        ),
+        case!(
+            "Replication",
+            "changes are replicated to an S3 destination and lag is reported",
+            bucket_replication
+        ),
+        case!(
+            "ContentScan",
+            "inline scans refuse and async scans quarantine positives",
+            upload_scanning
+        ),
+        case!(
+            "Dedup",
+            "identical payloads share one blob until the last delete",
+            dedup_shares_blobs
+        ),
+        case!(
+            "Snapshots",
+            "restore brings back captured keys and removes newer ones",
+            snapshot_restore
+        ),
+        case!(
+            "Snapshots",
+            "the pre-restore snapshot undoes a restore",
+            snapshot_pre_restore_undo
+        ),
+        case!(
+            "Snapshots",
+            "restore brings back encryption, tags, metadata and blob links",
+            snapshot_restore_key_state
+        ),
+        case!(
+            "Manifest",
+            "signed manifests verify and report changed, missing and extra keys",
+            manifest_export_verify
+        ),
+        case!(
+            "Snapshots",
+            "retention keeps the newest per day and ISO week",
+            snapshot_retention
+        ),
+        case!(
+            "Snapshots",
+            "read-only buckets refuse restore",
+            snapshot_restore_read_only
+        ),
+        case!(
+            "MetadataEncryption",
+            "metadata and tag values are sealed at rest and read back in the clear",
+            metadata_encryption_round_trip
+        ),
+        case!(
+            "MetadataEncryption",
+            "encrypted buckets need the metadata encryption key",
+            metadata_encryption_needs_key
+        ),
+        case!(
+            "SelfTest",
+            "passes against a live server and cleans up",
+            self_test_round_trip
+        ),
+        case!(
+            "Tls",
+            "serves HTTPS and picks up a rotated certificate",
+            tls_listener_reload
+        ),
+        case!(
+            "BucketTemplate",
+            "creating from a template applies every setting",
+            bucket_template_create
+        ),
+        case!(
+            "S3Headers",
+            "malformed header groups are rejected before the operation",
+            s3_headers_rejected
+        ),
+        case!(
+            "LogLevel",
+            "filter read and replaced at runtime",
+            log_level_runtime
+        ),
+        case!(
+            "AdminAuth",
+            "OIDC and LDAP logins are refused without credentials or an admin group",
+            admin_auth_identity_roles
+        ),
+        case!(
+            "AdminTokens",
+            "scoped admin tokens reach only the endpoints of their scopes",
+            admin_token_scopes
+        ),
+        case!(
+            "AdminTokens",
+            "a lifetime past the timestamp range is refused, not left unbounded",
+            admin_token_ttl_out_of_range
+        ),
+        case!(
+            "Authorizer",
+            "only verified signatures reach the policy engine as their access key",
+            authorizer_verified_principal
+        ),
+        case!(
+            "Authorizer",
+            "decisions are cached for the TTL",
+            authorizer_cache_ttl
+        ),
+        case!(
+            "Authorizer",
+            "callout failures fail closed with 503",
+            authorizer_fail_closed
+        ),
+        case!(
+            "Authorizer",
+            "the debug signature token reveals what the server signed on a mismatch",
+            authorizer_signature_debug
+        ),
+        case!(
+            "DenialLog",
+            "refusals are recorded with who, what and why at /admin/denials",
+            denial_log_records_refusals
+        ),
+        case!(
+            "Sessions",
+            "tokens are refused when forged, tampered with or expired",
+            session_token_verification
+        ),
+        case!(
+            "Sessions",
+            "prefix-scoped tokens reach only keys and listings under the prefix",
+            session_prefix_scope
+        ),
+        case!(
+            "Sessions",
+            "copies read only sources inside the session's scope",
+            session_copy_source
+        ),
+        case!(
+            "SseC",
+            "customer-key objects are encrypted and need the key to read",
+            sse_c_round_trip
+        ),
+        case!(
+            "SseC",
+            "object attributes of a customer-key object need the key",
+            sse_c_attributes
+        ),
+        case!(
+            "ListStream",
+            "streams every matching key as NDJSON across pages",
+            list_stream_ndjson
+        ),
+        case!(
+            "UploadPart",
+            "dropped connection leaves no temp file",
+            upload_part_dropped_connection
+        ),
+        case!("DeleteObject", "delete then get is 404", delete_object),
+        case!(
+            "DeleteObject",
+            "background removal spares a re-upload",
+            delete_object_background_removal
+        ),
+        case!(
+            "DeleteObject",
+            "versioned delete adds a marker",
+            delete_object_versioned
+        ),
+        case!(
+            "DeleteObject",
+            "deleted rows are purged after the retention",
+            purge_deleted_objects
+        ),
+        case!(
+            "DeleteObject",
+            "deleted keys are listed and restored",
+            restore_deleted_object
+        ),
+        case!(
+            "ObjectLock",
+            "default retention applied and enforced",
+            object_lock_default_retention
+        ),
+        case!(
+            "DeleteObjects",
+            "per-key results for a batch",
+            delete_objects
+        ),
+        case!(
+            "DeleteObjects",
+            "quiet mode reports only errors",
+            delete_objects_quiet
+        ),
+        case!(
+            "PutBucketVersioning",
+            "enable and read back",
+            put_bucket_versioning
+        ),
+        case!(
+            "GetObject",
+            "earlier version by versionId",
+            get_object_version
+        ),
+        case!(
+            "GetObject",
+            "response-* parameters override headers",
+            get_object_response_overrides
+        ),
+        case!(
+            "ListObjectVersions",
+            "lists versions and delete markers",
+            list_object_versions
+        ),
+        case!(
+            "ListObjectsV2",
+            "lists keys in order",
+            list_objects_v2_basic
+        ),
+        case!(
+            "ListObjectsV2",
+            "delimiter groups common prefixes",
+            list_objects_v2_delimiter
+        ),
+        case!(
+            "ListObjectsV2",
+            "prefix is matched literally",
+            list_objects_v2_literal_prefix
+        ),
+        case!(
+            "ListObjectsV2",
+            "pagination visits every key",
+            list_objects_v2_pagination
+        ),
+        case!(
+            "ListObjectsV2",
+            "common prefixes count toward max-keys across pages",
+            list_objects_v2_delimiter_pagination
+        ),
+        case!(
+            "ListObjectsV2",
+            "pages skip past common prefixes larger than a page",
+            list_objects_v2_large_common_prefixes
+        ),
+        case!(
+            "ListObjectsV2",
+            "encoding-type=url and fetch-owner",
+            list_objects_v2_encoding_and_owner
+        ),
+        case!(
+            "ObjectKeys",
+            "special and non-ASCII keys round-trip through every API",
+            special_character_keys
+        ),
+        case!(
+            "ListObjects",
+            "marker pagination visits every key and prefix once",
+            list_objects_v1_marker
+        ),
+        case!(
+            "ListAllObjects",
+            "admin search spans buckets and pages through matches",
+            search_objects_across_buckets
+        ),
+        case!(
+            "CreateMultipartUpload",
+            "initiate upload",
+            create_multipart_upload
+        ),
+        case!(
+            "CompleteMultipartUpload",
+            "parts concatenate in order",
+            complete_multipart_upload
+        ),
+        case!(
+            "CompleteMultipartUpload",
+            "an interrupted assembly resumes and reports its progress",
+            complete_multipart_upload_resumes
+        ),
+        case!(
+            "CompleteMultipartUpload",
+            "undersized non-final part rejected",
+            complete_multipart_upload_part_too_small
+        ),
+        case!(
+            "UploadPart",
+            "Content-MD5 mismatch rejected",
+            upload_part_bad_digest
+        ),
+        case!("ListParts", "lists uploaded parts", list_parts),
+        case!(
+            "AbortMultipartUpload",
+            "abort discards the upload",
+            abort_multipart_upload
+        ),
+        case!(
+            "AbortMultipartUpload",
+            "reaper aborts uploads past the TTL",
+            gc_multipart_uploads
+        ),
+        case!(
+            "GetObjectAttributes",
+            "returns only the requested attributes",
+            get_object_attributes
+        ),
+        case!(
+            "GetObjectAttributes",
+            "lists the parts of a multipart object",
+            get_object_attributes_parts
+        ),
+        case!(
+            "GetObjectAttributes",
+            "pages through parts with their checksums",
+            get_object_attributes_parts_paged
+        ),
+        case!(
+            "PutBucketLifecycleConfiguration",
+            "rules round-trip through GetBucketLifecycleConfiguration",
+            put_bucket_lifecycle
+        ),
+        case!(
+            "PutBucketLifecycleConfiguration",
+            "unsupported actions rejected",
+            put_bucket_lifecycle_unsupported
+        ),
+        case!(
+            "PutBucketLifecycleConfiguration",
+            "expiration removes old matching objects",
+            lifecycle_expiration
+        ),
+        case!(
+            "PutBucketLifecycleConfiguration",
+            "dry run reports matches without applying them",
+            lifecycle_dry_run
+        ),
+        case!(
+            "BucketConfiguration",
+            "configuration ETags guard concurrent updates with If-Match",
+            bucket_config_if_match
+        ),
+        case!(
+            "PutBucketLifecycleConfiguration",
+            "stale multipart uploads are aborted",
+            lifecycle_abort_incomplete_uploads
+        ),
+        case!(
+            "PutBucketLifecycleConfiguration",
+            "transition moves cold objects to the remote tier",
+            lifecycle_transition
+        ),
+    ]
+}
+
+#[tokio::test]
+async fn s3_conformance() {
+    let mut per_operation: BTreeMap<&str, (usize, usize)> = BTreeMap::new();
+    let mut unexpected = Vec::new();
+    let mut report = Vec::new();
+
+    for case in cases() {
+        let app = TestApp::new().await;
+        let outcome = (case.run)(&app).await;
+        let passed = outcome.is_ok();
+
+        let entry = per_operation.entry(case.operation).or_default();
+        entry.1 += 1;
+        if passed {
+            entry.0 += 1;
+        }
+
+        match (&outcome, case.known_failure) {
+            (Err(err), false) => unexpected.push(format!(
+                "REGRESSION {} / {}: {}",
+                case.operation, case.name, err
+            )),
+            (Ok(()), true) => unexpected.push(format!(
+                "NOW PASSING {} / {}: remove `known_failure`",
+                case.operation, case.name
+            )),
+            _ => {}
+        }
+
+        report.push(serde_json::json!({
+            "operation": case.operation,
+            "case": case.name,
+            "passed": passed,
+            "known_failure": case.known_failure,
+            "error": outcome.err(),
+        }));
+    }
+
+    println!("{:<24} {:>6} {:>6}", "operation", "passed", "total");
+    for (operation, (passed, total)) in &per_operation {
+        println!("{:<24} {:>6} {:>6}", operation, passed, total);
+    }
+
+    if let Ok(path) = std::env::var("OBJECT_STORE_CONFORMANCE_REPORT") {
+        std::fs::write(&path, serde_json::to_vec_pretty(&report).unwrap())
+            .expect("write conformance report");
+    }
+
+    assert!(unexpected.is_empty(), "{}", unexpected.join("\n"));
+}
+
+fn put_bucket_request(bucket: &str) -> Request<Body> {
+    Request::builder()
+        .method(Method::PUT)
+        .uri(format!("/{}", bucket))
+        .header("content-type", "application/json")
+        .body(Body::from("null"))
+        .unwrap()
+}
+
+async fn create_bucket(app: &TestApp) -> CaseResult {
+    let resp = app.send(put_bucket_request("photos")).await;
+    ensure!(resp.status == StatusCode::OK, "status {}", resp.status);
+    ensure!(
+        resp.text().contains("<Location>/photos</Location>"),
+        "body {}",
+        resp.text()
+    );
+    Ok(())
+}
+
+async fn create_bucket_no_body(app: &TestApp) -> CaseResult {
+    let resp = app.call(Method::PUT, "/photos", Body::empty()).await;
+    ensure!(resp.status == StatusCode::OK, "status {}", resp.status);
+    Ok(())
+}
+
+async fn create_bucket_duplicate(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    let resp = app.send(put_bucket_request("photos")).await;
+    ensure!(
+        resp.status == StatusCode::CONFLICT,
+        "status {}",
+        resp.status
+    );
+    Ok(())
+}
+
+async fn create_bucket_invalid_name(app: &TestApp) -> CaseResult {
+    let resp = app.send(put_bucket_request("Not_Valid")).await;
+    ensure!(
+        resp.status == StatusCode::BAD_REQUEST,
+        "status {}",
+        resp.status
+    );
+    Ok(())
+}
+
+async fn delete_bucket(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    let resp = app.call(Method::DELETE, "/photos", Body::empty()).await;
+    ensure!(
+        resp.status == StatusCode::NO_CONTENT,
+        "status {}",
+        resp.status
+    );
+    let resp = app.call(Method::DELETE, "/photos", Body::empty()).await;
+    ensure!(
+        resp.status == StatusCode::NOT_FOUND,
+        "second delete {}",
+        resp.status
+    );
+    Ok(())
+}
+
+async fn delete_bucket_not_empty(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    app.put_object("photos", "a.txt", b"hello").await;
+    let resp = app.call(Method::DELETE, "/photos", Body::empty()).await;
+    ensure!(
+        resp.status == StatusCode::CONFLICT,
+        "non-empty delete {}",
+        resp.status
+    );
+    let get = app.call(Method::GET, "/photos/a.txt", Body::empty()).await;
+    ensure!(
+        get.body == b"hello",
+        "object after refused delete {}",
+        get.status
+    );
+
+    let resp = app
+        .call(Method::DELETE, "/photos?force=true", Body::empty())
+        .await;
+    ensure!(
+        resp.status == StatusCode::NO_CONTENT,
+        "forced delete {}",
+        resp.status
+    );
+    let objects: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM objects")
+        .fetch_one(&*app.service.db)
+        .await
+        .map_err(|err| err.to_string())?;
+    ensure!(objects == 0, "{} object rows left", objects);
+    Ok(())
+}
+
+/// Run the queued prefix delete and return its job record.
+async fn finish_prefix_delete(app: &TestApp, location: &str) -> Result<serde_json::Value, String> {
+    ensure!(
+        app.service
+            .run_next_job()
+            .await
+            .map_err(|e| e.to_string())?,
+        "no job ran"
+    );
+    let job = app.call(Method::GET, location, Body::empty()).await;
+    serde_json::from_slice(&job.body).map_err(|e| e.to_string())
+}
+
+async fn live_keys(app: &TestApp) -> Result<Vec<String>, String> {
+    sqlx::query_scalar("SELECT key FROM objects WHERE is_deleted = 0 ORDER BY key")
+        .fetch_all(&*app.service.db)
+        .await
+        .map_err(|e| e.to_string())
+}
+
+async fn delete_prefix_job(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    for key in ["logs/a", "logs/b", "logs/deep/c", "logsx", "keep.txt"] {
+        app.put_object("photos", key, b"x").await;
+    }
+    let resp = app
+        .call(Method::DELETE, "/photos?prefix=logs/", Body::empty())
+        .await;
+    ensure!(
+        resp.status == StatusCode::ACCEPTED,
+        "delete {} {}",
+        resp.status,
+        resp.text()
+    );
+    let job: serde_json::Value = serde_json::from_slice(&resp.body).map_err(|e| e.to_string())?;
+    let location = resp.header("location").unwrap_or_default().to_string();
+    ensure!(
+        job["kind"] == "delete-prefix"
+            && job["status"] == "queued"
+            && location == format!("/admin/jobs/{}", job["id"].as_str().unwrap_or_default()),
+        "job {} at {}",
+        job,
+        location
+    );
+    let keys = live_keys(app).await?;
+    ensure!(keys.len() == 5, "deleted before the job ran: {:?}", keys);
+
+    let job = finish_prefix_delete(app, &location).await?;
+    ensure!(
+        job["status"] == "succeeded" && job["processed"] == 3,
+        "finished job {}",
+        job
+    );
+    let keys = live_keys(app).await?;
+    ensure!(keys == ["keep.txt", "logsx"], "left {:?}", keys);
+    let get = app
+        .call(Method::GET, "/photos/logs/deep/c", Body::empty())
+        .await;
+    ensure!(
+        get.status == StatusCode::NOT_FOUND,
+        "deleted key {}",
+        get.status
+    );
+    Ok(())
+}
+
+async fn delete_prefix_literal(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    for key in ["a_1", "ab", "a%", "a%z", "az"] {
+        app.put_object("photos", key, b"x").await;
+    }
+    for (prefix, processed) in [("a_", 1), ("a%25", 2)] {
+        let resp = app
+            .call(
+                Method::DELETE,
+                &format!("/photos?prefix={}", prefix),
+                Body::empty(),
+            )
+            .await;
+        let location = resp.header("location").unwrap_or_default().to_string();
+        let job = finish_prefix_delete(app, &location).await?;
+        ensure!(
+            job["status"] == "succeeded" && job["processed"] == processed,
+            "prefix {} job {}",
+            prefix,
+            job
+        );
+    }
+    let keys = live_keys(app).await?;
+    ensure!(keys == ["ab", "az"], "left {:?}", keys);
+    Ok(())
+}
+
+async fn delete_prefix_empty(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    app.put_object("photos", "a.txt", b"x").await;
+    let resp = app
+        .call(Method::DELETE, "/photos?prefix=", Body::empty())
+        .await;
+    ensure!(
+        resp.status == StatusCode::BAD_REQUEST,
+        "empty prefix {} {}",
+        resp.status,
+        resp.text()
+    );
+    let queued = app
+        .service
+        .run_next_job()
+        .await
+        .map_err(|e| e.to_string())?;
+    ensure!(!queued, "a job was queued");
+    let keys = live_keys(app).await?;
+    ensure!(keys == ["a.txt"], "left {:?}", keys);
+    Ok(())
+}
+
+async fn delete_prefix_read_only_mid_job(app: &TestApp) -> CaseResult {
+    // One more key than a batch, so the job checks the bucket twice.
+    const KEYS: usize = 501;
+    app.create_bucket("photos").await;
+    let keys: Vec<String> = (0..KEYS).map(|i| format!("logs/{:04}", i)).collect();
+    for key in &keys {
+        app.put_object("photos", key, b"x").await;
+    }
+    let resp = app
+        .call(Method::DELETE, "/photos?prefix=logs/", Body::empty())
+        .await;
+    let location = resp.header("location").unwrap_or_default().to_string();
+
+    // Hold the first key so the job stalls inside its first batch, after it
+    // found the bucket writable.
+    let bucket_id: uuid::Uuid = sqlx::query_scalar("SELECT id FROM buckets WHERE name = 'photos'")
+        .fetch_one(&*app.service.db)
+        .await
+        .map_err(|e| e.to_string())?;
+    let guard = app.service.key_locks.lock(bucket_id, &keys[0]).await;
+    let service = app.service.clone();
+    let runner = tokio::spawn(async move { service.run_next_job().await });
+    for _ in 0..100 {
+        let job = app.call(Method::GET, &location, Body::empty()).await;
+        if job.text().contains(r#""status":"running""#) {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+    }
+    tokio::time::sleep(std::time::Duration::from_millis(200)).await;
+
+    let patched = app
+        .send(
+            Request::builder()
+                .method(Method::PATCH)
+                .uri("/admin/buckets/photos")
+                .header("content-type", "application/json")
+                .body(Body::from(r#"{"read_only":true}"#))
+                .unwrap(),
+        )
+        .await;
+    ensure!(patched.status == StatusCode::OK, "patch {}", patched.status);
+    drop(guard);
+    runner
+        .await
+        .map_err(|e| e.to_string())?
+        .map_err(|e| e.to_string())?;
+
+    let job = app.call(Method::GET, &location, Body::empty()).await;
+    let job: serde_json::Value = serde_json::from_slice(&job.body).map_err(|e| e.to_string())?;
+    ensure!(
+        job["status"] == "failed"
+            && job["processed"] == 500
+            && job["error"]
+                .as_str()
+                .is_some_and(|error| error.contains("read-only")),
+        "job {}",
+        job
+    );
+    let left = live_keys(app).await?;
+    ensure!(left == [keys[500].clone()], "left {:?}", left);
+    Ok(())
+}
+
+async fn put_object_concurrent_overwrite(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    let bodies: Vec<Vec<u8>> = (0..16u8)
+        .map(|i| vec![b'a' + i; 1024 * (i as usize + 1)])
+        .collect();
+    let writes = bodies.iter().enumerate().map(|(i, body)| async move {
+        if i % 4 == 3 {
+            app.call(Method::DELETE, "/photos/k", Body::empty()).await
+        } else {
+            app.call(Method::PUT, "/photos/k", body.clone()).await
+        }
+    });
+    futures::future::join_all(writes).await;
+
+    let get = app.call(Method::GET, "/photos/k", Body::empty()).await;
+    if get.status == StatusCode::NOT_FOUND {
+        return Ok(());
+    }
+    let expected = format!("\"{:x}\"", md5::compute(&get.body));
+    ensure!(
+        get.header("etag") == Some(expected.as_str()),
+        "etag {:?} for a body of {} bytes",
+        get.header("etag"),
+        get.body.len()
+    );
+    ensure!(
+        bodies.contains(&get.body),
+        "body of {} bytes is no upload",
+        get.body.len()
+    );
+    Ok(())
+}
+
+async fn head_bucket(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    let resp = app.call(Method::HEAD, "/photos", Body::empty()).await;
+    ensure!(resp.status == StatusCode::OK, "status {}", resp.status);
+    ensure!(resp.body.is_empty(), "body {}", resp.text());
+    ensure!(
+        resp.header("x-amz-bucket-region").is_some(),
+        "missing x-amz-bucket-region"
+    );
+    Ok(())
+}
+
+async fn head_bucket_missing(app: &TestApp) -> CaseResult {
+    let resp = app.call(Method::HEAD, "/nothing-here", Body::empty()).await;
+    ensure!(
+        resp.status == StatusCode::NOT_FOUND,
+        "status {}",
+        resp.status
+    );
+    Ok(())
+}
+
+async fn get_bucket_location(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    let resp = app
+        .call(Method::GET, "/photos?location", Body::empty())
+        .await;
+    ensure!(resp.status == StatusCode::OK, "status {}", resp.status);
+    ensure!(
+        resp.text().contains("<LocationConstraint"),
+        "body {}",
+        resp.text()
+    );
+    Ok(())
+}
+
+async fn get_bucket_location_region(app: &TestApp) -> CaseResult {
+    let resp = app
+        .send(
+            Request::builder()
+                .method(Method::PUT)
+                .uri("/photos")
+                .header("content-type", "application/json")
+                .body(Body::from(r#"{"LocationConstraint":"eu-west-1"}"#))
+                .unwrap(),
+        )
+        .await;
+    ensure!(
+        resp.status == StatusCode::OK,
+        "create status {}",
+        resp.status
+    );
+    let resp = app
+        .call(Method::GET, "/photos?location", Body::empty())
+        .await;
+    ensure!(
+        resp.text().contains(">eu-west-1</LocationConstraint>"),
+        "body {}",
+        resp.text()
+    );
+    let resp = app.call(Method::HEAD, "/photos", Body::empty()).await;
+    ensure!(
+        resp.header("x-amz-bucket-region") == Some("eu-west-1"),
+        "region header {:?}",
+        resp.header("x-amz-bucket-region")
+    );
+    Ok(())
+}
+
+async fn put_object_etag(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    let resp = app.put_object("photos", "hello.txt", b"hello").await;
+    let expected = format!("\"{:x}\"", md5::compute(b"hello"));
+    ensure!(
+        resp.header("etag") == Some(expected.as_str()),
+        "etag {:?}",
+        resp.header("etag")
+    );
+    Ok(())
+}
+
+async fn put_object_read_only_bucket(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    app.put_object("photos", "keep.txt", b"keep").await;
+    let resp = app
+        .send(
+            Request::builder()
+                .method(Method::PATCH)
+                .uri("/admin/buckets/photos")
+                .header("content-type", "application/json")
+                .body(Body::from(r#"{"read_only":true}"#))
+                .unwrap(),
+        )
+        .await;
+    ensure!(resp.status == StatusCode::OK, "patch {}", resp.status);
+
+    let put = app.call(Method::PUT, "/photos/new.txt", "x").await;
+    ensure!(put.status == StatusCode::FORBIDDEN, "put {}", put.status);
+    let delete = app
+        .call(Method::DELETE, "/photos/keep.txt", Body::empty())
+        .await;
+    ensure!(
+        delete.status == StatusCode::FORBIDDEN,
+        "delete {}",
+        delete.status
+    );
+    let get = app
+        .call(Method::GET, "/photos/keep.txt", Body::empty())
+        .await;
+    ensure!(get.status == StatusCode::OK, "get {}", get.status);
+    Ok(())
+}
+
+async fn put_object_volume_read_only(_app: &TestApp) -> CaseResult {
+    let app = TestApp::with_service(|mut service| {
+        service.options.volume_failure_threshold = Some(2);
+        service
+    })
+    .await;
+    app.create_bucket("photos").await;
+    // A file where the bucket directory belongs makes every write fail.
+    let bucket_dir = app.service.base_path.join("photos");
+    let _ = std::fs::remove_dir_all(&bucket_dir);
+    std::fs::write(&bucket_dir, b"not a directory").unwrap();
+    for attempt in 0..2 {
+        let put = app.call(Method::PUT, "/photos/a.txt", "x").await;
+        ensure!(
+            put.status == StatusCode::INTERNAL_SERVER_ERROR,
+            "failing put {} {}",
+            attempt,
+            put.status
+        );
+    }
+    let put = app.call(Method::PUT, "/photos/a.txt", "x").await;
+    ensure!(
+        put.status == StatusCode::SERVICE_UNAVAILABLE,
+        "put {}",
+        put.status
+    );
+    let ready = app.call(Method::GET, "/readyz", Body::empty()).await;
+    ensure!(
+        ready.status == StatusCode::SERVICE_UNAVAILABLE,
+        "readyz {}",
+        ready.status
+    );
+    let volumes: serde_json::Value = serde_json::from_slice(&ready.body).unwrap();
+    ensure!(
+        volumes["volumes"][0]["write_errors"] == 2 && volumes["volumes"][0]["writable"] == false,
+        "volumes {}",
+        volumes["volumes"]
+    );
+
+    std::fs::remove_file(&bucket_dir).unwrap();
+    let reset = app
+        .call(Method::POST, "/admin/volumes/default/reset", Body::empty())
+        .await;
+    ensure!(reset.status == StatusCode::OK, "reset {}", reset.status);
+    let put = app.call(Method::PUT, "/photos/a.txt", "x").await;
+    ensure!(
+        put.status == StatusCode::OK,
+        "put after reset {}",
+        put.status
+    );
+    Ok(())
+}
+
+async fn put_object_sha256(_app: &TestApp) -> CaseResult {
+    let app = TestApp::with_service(|mut service| {
+        service.options.compute_sha256 = true;
+        service
+    })
+    .await;
+    app.create_bucket("photos").await;
+    let expected = Some("uU0nuZNNPgilLlLX2n2r+sSE7+N6U4DukIj3rOLvzek=");
+    let put = app.put_object("photos", "k", b"hello world").await;
+    ensure!(
+        put.header("x-amz-checksum-sha256") == expected,
+        "put checksum {:?}",
+        put.header("x-amz-checksum-sha256")
+    );
+    let head = app.call(Method::HEAD, "/photos/k", Body::empty()).await;
+    ensure!(
+        head.header("x-amz-checksum-sha256") == expected,
+        "head checksum {:?}",
+        head.header("x-amz-checksum-sha256")
+    );
+    Ok(())
+}
+
+async fn get_object_checksum_mode(_app: &TestApp) -> CaseResult {
+    let app = TestApp::with_service(|mut service| {
+        service.options.compute_sha256 = true;
+        service.options.mmap_read_threshold = Some(1024);
+        service
+    })
+    .await;
+    app.create_bucket("photos").await;
+    app.put_object("photos", "k", b"hello world").await;
+    let get = |mode: &'static str| {
+        Request::builder()
+            .method(Method::GET)
+            .uri("/photos/k")
+            .header("x-amz-checksum-mode", mode)
+            .body(Body::empty())
+            .unwrap()
+    };
+    let intact = app.send(get("ENABLED")).await;
+    ensure!(
+        intact.status == StatusCode::OK && intact.header("x-amz-checksum-sha256").is_some(),
+        "intact {} {:?}",
+        intact.status,
+        intact.headers
+    );
+    let bad_mode = app.send(get("yes")).await;
+    ensure!(
+        bad_mode.status == StatusCode::BAD_REQUEST,
+        "bad mode {}",
+        bad_mode.status
+    );
+
+    // Same length, different bytes: only a checksum notices.
+    let payload = find_files(&app.service.base_path.join("photos"), "k");
+    ensure!(payload.len() == 1, "payload files {:?}", payload);
+    std::fs::write(&payload[0], b"hello World").unwrap();
+    let corrupt = app.send(get("ENABLED")).await;
+    ensure!(
+        corrupt.status == StatusCode::INTERNAL_SERVER_ERROR,
+        "corrupt {}",
+        corrupt.status
+    );
+    let unchecked = app.call(Method::GET, "/photos/k", Body::empty()).await;
+    ensure!(
+        unchecked.status == StatusCode::OK,
+        "unchecked {}",
+        unchecked.status
+    );
+    Ok(())
+}
+
+async fn put_object_content_md5(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    let put = |uri: &'static str, md5: &'static str| {
+        Request::builder()
+            .method(Method::PUT)
+            .uri(uri)
+            .header("content-md5", md5)
+            .body(Body::from("hello world"))
+            .unwrap()
+    };
+    let ok = app
+        .send(put("/photos/good", "XrY7u+Ae7tCTyyK7j1rNww=="))
+        .await;
+    ensure!(ok.status == StatusCode::OK, "matching digest {}", ok.status);
+    let bad = app
+        .send(put("/photos/bad", "9Mk4XxkC9zNLALm07NFk3g=="))
+        .await;
+    ensure!(
+        bad.status == StatusCode::BAD_REQUEST && bad.text().contains("bad digest"),
+        "mismatched digest {} {}",
+        bad.status,
+        bad.text()
+    );
+    let malformed = app.send(put("/photos/bad", "not-a-digest")).await;
+    ensure!(
+        malformed.status == StatusCode::BAD_REQUEST && malformed.text().contains("invalid digest"),
+        "malformed digest {} {}",
+        malformed.status,
+        malformed.text()
+    );
+    let get = app.call(Method::GET, "/photos/bad", Body::empty()).await;
+    ensure!(
+        get.status == StatusCode::NOT_FOUND,
+        "rejected upload was stored: {}",
+        get.status
+    );
+    Ok(())
+}
+
+async fn put_object_checksum_crc32c(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    let put = |crc32c: &'static str| {
+        Request::builder()
+            .method(Method::PUT)
+            .uri("/photos/k")
+            .header("x-amz-checksum-crc32c", crc32c)
+            .body(Body::from("hello world"))
+            .unwrap()
+    };
+    let bad = app.send(put("AAAAAA==")).await;
+    ensure!(
+        bad.status == StatusCode::BAD_REQUEST,
+        "mismatched crc32c {}",
+        bad.status
+    );
+    let ok = app.send(put("yZRlqg==")).await;
+    ensure!(ok.status == StatusCode::OK, "matching crc32c {}", ok.status);
+    let head = app.call(Method::HEAD, "/photos/k", Body::empty()).await;
+    ensure!(
+        head.header("x-amz-checksum-crc32c") == Some("yZRlqg=="),
+        "head crc32c {:?}",
+        head.header("x-amz-checksum-crc32c")
+    );
+    Ok(())
+}
+
+async fn put_object_content_sha256(app: &TestApp) -> CaseResult {
+    const HELLO_SHA256: &str = "b94d27b9934d3e08a52e52d7da7dabfac484efe37a5380ee9088f7ace2efcde9";
+    app.create_bucket("photos").await;
+    let put = |uri: &str, sha256: &str| {
+        Request::builder()
+            .method(Method::PUT)
+            .uri(uri)
+            .header("x-amz-content-sha256", sha256)
+            .body(Body::from("hello world"))
+            .unwrap()
+    };
+    let ok = app.send(put("/photos/good", HELLO_SHA256)).await;
+    ensure!(ok.status == StatusCode::OK, "matching digest {}", ok.status);
+    let unsigned = app.send(put("/photos/unsigned", "UNSIGNED-PAYLOAD")).await;
+    ensure!(
+        unsigned.status == StatusCode::OK,
+        "unsigned payload {}",
+        unsigned.status
+    );
+    let other = HELLO_SHA256.replace('b', "c");
+    let bad = app.send(put("/photos/bad", &other)).await;
+    ensure!(
+        bad.status == StatusCode::BAD_REQUEST && bad.text().contains("XAmzContentSHA256Mismatch"),
+        "mismatched digest {} {}",
+        bad.status,
+        bad.text()
+    );
+    let malformed = app.send(put("/photos/bad", "not-a-digest")).await;
+    ensure!(
+        malformed.status == StatusCode::BAD_REQUEST && malformed.text().contains("invalid digest"),
+        "malformed digest {} {}",
+        malformed.status,
+        malformed.text()
+    );
+    let get = app.call(Method::GET, "/photos/bad", Body::empty()).await;
+    ensure!(
+        get.status == StatusCode::NOT_FOUND,
+        "rejected upload was stored: {}",
+        get.status
+    );
+
+    let upload_id = initiate_upload(app, "/photos/big").await?;
+    let part = app
+        .send(put(
+            &format!("/photos/big?partNumber=1&uploadId={}", upload_id),
+            &other,
+        ))
+        .await;
+    ensure!(
+        part.status == StatusCode::BAD_REQUEST && part.text().contains("XAmzContentSHA256Mismatch"),
+        "mismatched part digest {} {}",
+        part.status,
+        part.text()
+    );
+    Ok(())
+}
+
+async fn put_object_raw_body(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    // A body that looks like a form upload must still be stored as sent.
+    let body =
+        "--boundary\r\nContent-Disposition: form-data; name=\"file\"\r\n\r\nx\r\n--boundary--\r\n";
+    let put = app
+        .send(
+            Request::builder()
+                .method(Method::PUT)
+                .uri("/photos/form.txt")
+                .header("content-type", "multipart/form-data; boundary=boundary")
+                .body(Body::from(body))
+                .unwrap(),
+        )
+        .await;
+    ensure!(put.status == StatusCode::OK, "put {}", put.status);
+    let get = app
+        .call(Method::GET, "/photos/form.txt", Body::empty())
+        .await;
+    ensure!(get.body == body.as_bytes(), "body {}", get.text());
+    Ok(())
+}
+
+/// An `aws-chunked` body of `chunks`, each signed in a chain from `seed`
+/// with `key` when given, ending with an empty chunk and `trailer`.
+fn aws_chunked(chunks: &[&[u8]], signing: Option<(&[u8], &str)>, trailer: &str) -> Vec<u8> {
+    use hmac::{Hmac, Mac};
+    use sha2::{Digest, Sha256};
+
+    let hex = |bytes: &[u8]| -> String { bytes.iter().map(|b| format!("{:02x}", b)).collect() };
+    let mut previous = signing.map(|(_, seed)| seed.to_string());
+    let mut body = Vec::new();
+    for chunk in chunks.iter().chain([&&b""[..]]) {
+        body.extend_from_slice(format!("{:x}", chunk.len()).as_bytes());
+        if let (Some((key, _)), Some(prev)) = (signing, previous.as_mut()) {
+            let string_to_sign = format!(
+                "AWS4-HMAC-SHA256-PAYLOAD\n{}\n{}\n{}\n{}\n{}",
+                AMZ_DATE,
+                CHUNK_SCOPE,
+                prev,
+                hex(&Sha256::digest(b"")),
+                hex(&Sha256::digest(chunk))
+            );
+            let mut mac = Hmac::<Sha256>::new_from_slice(key).unwrap();
+            mac.update(string_to_sign.as_bytes());
+            *prev = hex(&mac.finalize().into_bytes());
+            body.extend_from_slice(format!(";chunk-signature={}", prev).as_bytes());
+        }
+        body.extend_from_slice(b"\r\n");
+        if !chunk.is_empty() {
+            body.extend_from_slice(chunk);
+            body.extend_from_slice(b"\r\n");
+        }
+    }
+    body.extend_from_slice(trailer.as_bytes());
+    body.extend_from_slice(b"\r\n");
+    body
+}
+
+const AMZ_DATE: &str = "20260101T000000Z";
+const CHUNK_SCOPE: &str = "20260101/us-east-1/s3/aws4_request";
+
+fn aws_chunked_put(uri: &str, payload: &str, decoded: usize, body: Vec<u8>) -> Request<Body> {
+    Request::builder()
+        .method(Method::PUT)
+        .uri(uri)
+        .header("content-encoding", "aws-chunked")
+        .header("x-amz-content-sha256", payload)
+        .header("x-amz-decoded-content-length", decoded.to_string())
+        .header("x-amz-date", AMZ_DATE)
+        .header(
+            "authorization",
+            format!(
+                "AWS4-HMAC-SHA256 Credential=AKIDCHUNKED/{}, SignedHeaders=host, Signature={}",
+                CHUNK_SCOPE,
+                "ab".repeat(32)
+            ),
+        )
+        .body(Body::from(body))
+        .unwrap()
+}
+
+async fn put_object_aws_chunked(app: &TestApp) -> CaseResult {
+    app.create_bucket("sdk").await;
+    const UNSIGNED: &str = "STREAMING-UNSIGNED-PAYLOAD-TRAILER";
+    let body = aws_chunked(
+        &[b"hello", b" chunked world"],
+        None,
+        "x-amz-checksum-crc32:AAAAAA==\r\n",
+    );
+    let put = app
+        .send(aws_chunked_put("/sdk/greeting.txt", UNSIGNED, 19, body))
+        .await;
+    ensure!(
+        put.status == StatusCode::OK,
+        "put {} {}",
+        put.status,
+        put.text()
+    );
+    let get = app
+        .call(Method::GET, "/sdk/greeting.txt", Body::empty())
+        .await;
+    ensure!(
+        get.body == b"hello chunked world"[..],
+        "stored {:?}",
+        get.text()
+    );
+    ensure!(
+        get.header("content-encoding").is_none() && get.header("content-length") == Some("19"),
+        "replayed {:?} / {:?}",
+        get.header("content-encoding"),
+        get.header("content-length")
+    );
+    ensure!(
+        put.header("etag") == Some(&format!("\"{:x}\"", md5::compute("hello chunked world"))[..]),
+        "etag {:?}",
+        put.header("etag")
+    );
+
+    // Signatures are dropped unverified without access keys.
+    let body = aws_chunked(&[b"signed"], Some((b"any key", "seed")), "");
+    let put = app
+        .send(aws_chunked_put(
+            "/sdk/signed.txt",
+            "STREAMING-AWS4-HMAC-SHA256-PAYLOAD",
+            6,
+            body,
+        ))
+        .await;
+    ensure!(put.status == StatusCode::OK, "signed put {}", put.status);
+
+    // Parts are decoded too.
+    let create = app
+        .call(Method::POST, "/sdk/big.bin?uploads", Body::empty())
+        .await;
+    let upload_id = create
+        .text()
+        .split("<UploadId>")
+        .nth(1)
+        .and_then(|rest| rest.split("</UploadId>").next())
+        .ok_or("no upload id")?
+        .to_string();
+    let part = app
+        .send(aws_chunked_put(
+            &format!("/sdk/big.bin?partNumber=1&uploadId={}", upload_id),
+            UNSIGNED,
+            8,
+            aws_chunked(&[b"part", b" one"], None, ""),
+        ))
+        .await;
+    ensure!(
+        part.header("etag") == Some(&format!("\"{:x}\"", md5::compute("part one"))[..]),
+        "part {} etag {:?}",
+        part.status,
+        part.header("etag")
+    );
+
+    // Bodies that do not add up are refused.
+    for (decoded, body) in [
+        (5, aws_chunked(&[b"too long"], None, "")),
+        (99, aws_chunked(&[b"short"], None, "")),
+        (5, b"5\r\nshort".to_vec()),
+        (5, b"zz\r\nshort\r\n0\r\n\r\n".to_vec()),
+    ] {
+        let put = app
+            .send(aws_chunked_put("/sdk/bad.txt", UNSIGNED, decoded, body))
+            .await;
+        ensure!(
+            put.status == StatusCode::BAD_REQUEST,
+            "malformed body {}",
+            put.status
+        );
+    }
+    let head = app.call(Method::HEAD, "/sdk/bad.txt", Body::empty()).await;
+    ensure!(head.status == StatusCode::NOT_FOUND, "bad body stored");
+    Ok(())
+}
+
+async fn put_object_aws_chunked_signed(_app: &TestApp) -> CaseResult {
+    use object_store::services::aws_chunked::AccessKeys;
+
+    let app = TestApp::with_service(|mut service| {
+        service.options.access_keys =
+            AccessKeys::new(["AKIDCHUNKED:chunk-secret".parse().unwrap()]);
+        service
+    })
+    .await;
+    app.create_bucket("sdk").await;
+    const SIGNED: &str = "STREAMING-AWS4-HMAC-SHA256-PAYLOAD";
+    let mut key = b"AWS4chunk-secret".to_vec();
+    for part in ["20260101", "us-east-1", "s3", "aws4_request"] {
+        use hmac::{Hmac, Mac};
+        let mut mac = Hmac::<sha2::Sha256>::new_from_slice(&key).unwrap();
+        mac.update(part.as_bytes());
+        key = mac.finalize().into_bytes().to_vec();
+    }
+    let seed = "ab".repeat(32);
+
+    let body = aws_chunked(&[b"first ", b"second"], Some((&key, &seed)), "");
+    let put = app
+        .send(aws_chunked_put("/sdk/doc.txt", SIGNED, 12, body))
+        .await;
+    ensure!(
+        put.status == StatusCode::OK,
+        "put {} {}",
+        put.status,
+        put.text()
+    );
+    let get = app.call(Method::GET, "/sdk/doc.txt", Body::empty()).await;
+    ensure!(get.body == b"first second"[..], "stored {:?}", get.text());
+
+    // A chunk altered in flight fails the upload, keeping the old object.
+    let mut body = aws_chunked(&[b"third ", b"fourth"], Some((&key, &seed)), "");
+    let at = body.windows(6).position(|w| w == b"fourth").unwrap();
+    body[at] = b'F';
+    let put = app
+        .send(aws_chunked_put("/sdk/doc.txt", SIGNED, 12, body))
+        .await;
+    ensure!(
+        put.status == StatusCode::FORBIDDEN,
+        "tampered {}",
+        put.status
+    );
+    let get = app.call(Method::GET, "/sdk/doc.txt", Body::empty()).await;
+    ensure!(
+        get.body == b"first second"[..],
+        "overwritten {:?}",
+        get.text()
+    );
+
+    // Signed with a secret the server does not know.
+    let body = aws_chunked(&[b"forged"], Some((b"guess", &seed)), "");
+    let put = app
+        .send(aws_chunked_put("/sdk/forged.txt", SIGNED, 6, body))
+        .await;
+    ensure!(put.status == StatusCode::FORBIDDEN, "forged {}", put.status);
+    let mut request = aws_chunked_put(
+        "/sdk/other.txt",
+        SIGNED,
+        6,
+        aws_chunked(&[b"forged"], Some((&key, &seed)), ""),
+    );
+    request.headers_mut().insert(
+        "authorization",
+        format!(
+            "AWS4-HMAC-SHA256 Credential=AKIDOTHER/{}, SignedHeaders=host, Signature={}",
+            CHUNK_SCOPE, seed
+        )
+        .parse()
+        .unwrap(),
+    );
+    let put = app.send(request).await;
+    ensure!(
+        put.status == StatusCode::FORBIDDEN,
+        "unknown access key {}",
+        put.status
+    );
+    Ok(())
+}
+
+async fn put_object_overwrite(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    app.put_object("photos", "k", b"first").await;
+    app.put_object("photos", "k", b"second").await;
+    let resp = app.call(Method::GET, "/photos/k", Body::empty()).await;
+    ensure!(resp.body == b"second", "body {}", resp.text());
+    Ok(())
+}
+
+async fn put_object_recycle(_app: &TestApp) -> CaseResult {
+    let app = TestApp::with_service(|mut service| {
+        service.options.overwrite_retention = Some(std::time::Duration::from_secs(3600));
+        service
+    })
+    .await;
+    app.create_bucket("photos").await;
+    app.put_object("photos", "k", b"first").await;
+    app.put_object("photos", "k", b"second").await;
+
+    let list = app
+        .call(Method::GET, "/photos/k?recycled", Body::empty())
+        .await;
+    let recycled: serde_json::Value = serde_json::from_slice(&list.body).unwrap_or_default();
+    ensure!(
+        list.status == StatusCode::OK
+            && recycled.as_array().map(Vec::len) == Some(1)
+            && recycled[0]["size_bytes"] == 5,
+        "recycled {} {}",
+        list.status,
+        list.text()
+    );
+
+    let recover = app
+        .call(Method::POST, "/photos/k?recover", Body::empty())
+        .await;
+    ensure!(
+        recover.status == StatusCode::OK,
+        "recover {} {}",
+        recover.status,
+        recover.text()
+    );
+    let get = app.call(Method::GET, "/photos/k", Body::empty()).await;
+    ensure!(get.body == b"first", "recovered body {}", get.text());
+    // The payload the recovery replaced is recycled in turn.
+    let list = app
+        .call(Method::GET, "/photos/k?recycled", Body::empty())
+        .await;
+    let recycled: serde_json::Value = serde_json::from_slice(&list.body).unwrap_or_default();
+    ensure!(
+        recycled.as_array().map(Vec::len) == Some(1) && recycled[0]["size_bytes"] == 6,
+        "recycled after recover {}",
+        list.text()
+    );
+
+    backdate(&app, "UPDATE recycled_objects SET expires_at = ?", 1).await;
+    let purged = app
+        .service
+        .purge_expired_recycled()
+        .await
+        .map_err(|err| err.to_string())?;
+    ensure!(purged == 1, "purged {}", purged);
+    let recover = app
+        .call(Method::POST, "/photos/k?recover", Body::empty())
+        .await;
+    ensure!(
+        recover.status == StatusCode::NOT_FOUND,
+        "recover after purge {}",
+        recover.status
+    );
+    Ok(())
+}
+
+async fn put_object_if_match(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    let first = app.put_object("photos", "k", b"one").await;
+    let etag = first.header("etag").unwrap_or_default().to_string();
+    app.put_object("photos", "k", b"two").await;
+    let put_if_match = |tag: String| {
+        Request::builder()
+            .method(Method::PUT)
+            .uri("/photos/k")
+            .header("if-match", tag)
+            .body(Body::from("three"))
+            .unwrap()
+    };
+    let resp = app.send(put_if_match(etag)).await;
+    ensure!(
+        resp.status == StatusCode::PRECONDITION_FAILED,
+        "status {}",
+        resp.status
+    );
+    let resp = app.call(Method::GET, "/photos/k", Body::empty()).await;
+    ensure!(resp.text() == "two", "body {:?}", resp.text());
+    let current = resp.header("etag").unwrap_or_default().to_string();
+    let resp = app.send(put_if_match(current)).await;
+    ensure!(
+        resp.status == StatusCode::OK,
+        "matching etag {}",
+        resp.status
+    );
+    Ok(())
+}
+
+async fn get_object_round_trip(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    app.send(
+        Request::builder()
+            .method(Method::PUT)
+            .uri("/photos/dir/pic.jpg")
+            .header("content-type", "image/jpeg")
+            .body(Body::from("jpegbytes"))
+            .unwrap(),
+    )
+    .await;
+    let resp = app
+        .call(Method::GET, "/photos/dir/pic.jpg", Body::empty())
+        .await;
+    ensure!(resp.status == StatusCode::OK, "status {}", resp.status);
+    ensure!(resp.body == b"jpegbytes", "body {}", resp.text());
+    ensure!(
+        resp.header("content-type") == Some("image/jpeg"),
+        "content-type {:?}",
+        resp.header("content-type")
+    );
+    ensure!(
+        resp.header("content-length") == Some("9"),
+        "content-length {:?}",
+        resp.header("content-length")
+    );
+    ensure!(
+        resp.header("last-modified").is_some(),
+        "missing last-modified"
+    );
+    Ok(())
+}
+
+async fn get_object_large(app: &TestApp) -> CaseResult {
+    // Several read buffers' worth, not a multiple of any of them.
+    let data: Vec<u8> = (0..(4 << 20) + 3).map(|i: u32| (i % 251) as u8).collect();
+    app.create_bucket("photos").await;
+    app.call(Method::PUT, "/photos/big.bin", Body::from(data.clone()))
+        .await;
+    let resp = app
+        .call(Method::GET, "/photos/big.bin", Body::empty())
+        .await;
+    ensure!(resp.status == StatusCode::OK, "status {}", resp.status);
+    ensure!(
+        resp.header("content-length") == Some(data.len().to_string().as_str()),
+        "content-length {:?}",
+        resp.header("content-length")
+    );
+    ensure!(
+        resp.body == data,
+        "body of {} bytes differs",
+        resp.body.len()
+    );
+    Ok(())
+}
+
+async fn get_object_content_encoding(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    app.send(
+        Request::builder()
+            .method(Method::PUT)
+            .uri("/photos/page.html")
+            .header("content-encoding", "gzip")
+            .body(Body::from("not really gzip"))
+            .unwrap(),
+    )
+    .await;
+    let resp = app
+        .call(Method::GET, "/photos/page.html", Body::empty())
+        .await;
+    ensure!(
+        resp.header("content-encoding") == Some("gzip"),
+        "content-encoding {:?}",
+        resp.header("content-encoding")
+    );
+    ensure!(resp.body == b"not really gzip", "body {}", resp.text());
+    Ok(())
+}
+
+async fn get_object_missing(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    let resp = app.call(Method::GET, "/photos/nope", Body::empty()).await;
+    ensure!(
+        resp.status == StatusCode::NOT_FOUND,
+        "status {}",
+        resp.status
+    );
+    ensure!(
+        resp.text().contains("<Code>NoSuchKey</Code>"),
+        "body {}",
+        resp.text()
+    );
+    Ok(())
+}
+
+async fn get_object_range(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    app.put_object("photos", "k", b"0123456789").await;
+    let resp = app
+        .send(
+            Request::builder()
+                .uri("/photos/k")
+                .header("range", "bytes=2-4")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+    ensure!(
+        resp.status == StatusCode::PARTIAL_CONTENT,
+        "status {}",
+        resp.status
+    );
+    ensure!(resp.body == b"234", "body {}", resp.text());
+    ensure!(
+        resp.header("content-range") == Some("bytes 2-4/10"),
+        "content-range {:?}",
+        resp.header("content-range")
+    );
+    Ok(())
+}
+
+async fn get_object_mmap(_app: &TestApp) -> CaseResult {
+    let app = TestApp::with_service(|mut service| {
+        service.options.mmap_read_threshold = Some(1024);
+        service
+    })
+    .await;
+    app.create_bucket("photos").await;
+    app.put_object("photos", "k", b"0123456789").await;
+    let resp = app.call(Method::GET, "/photos/k", Body::empty()).await;
+    ensure!(resp.body == b"0123456789", "body {}", resp.text());
+    ensure!(
+        resp.header("content-length") == Some("10"),
+        "content-length {:?}",
+        resp.header("content-length")
+    );
+    let resp = app
+        .send(
+            Request::builder()
+                .uri("/photos/k")
+                .header("range", "bytes=7-")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+    ensure!(
+        resp.status == StatusCode::PARTIAL_CONTENT,
+        "status {}",
+        resp.status
+    );
+    ensure!(resp.body == b"789", "range body {}", resp.text());
+    Ok(())
+}
+
+async fn get_object_range_cached(_app: &TestApp) -> CaseResult {
+    let app = TestApp::with_service(|mut service| {
+        service.options.range_cache_bytes = Some(4 * 1024 * 1024);
+        service
+    })
+    .await;
+    app.create_bucket("tables").await;
+    let payload: Vec<u8> = (0..600 * 1024).map(|i| (i % 251) as u8).collect();
+    app.call(Method::PUT, "/tables/data.parquet", payload.clone())
+        .await;
+
+    let get_range = |start: usize, end: usize| {
+        app.send(
+            Request::builder()
+                .uri("/tables/data.parquet")
+                .header("range", format!("bytes={}-{}", start, end))
+                .body(Body::empty())
+                .unwrap(),
+        )
+    };
+    // Spans the first block boundary; read twice to hit the cache.
+    for _ in 0..2 {
+        let resp = get_range(262_000, 263_000).await;
+        ensure!(
+            resp.status == StatusCode::PARTIAL_CONTENT,
+            "status {}",
+            resp.status
+        );
+        ensure!(resp.body == payload[262_000..=263_000], "cached range body");
+    }
+    ensure!(
+        app.service.block_cache.size_bytes() == 2 * 256 * 1024,
+        "cached {} bytes",
+        app.service.block_cache.size_bytes()
+    );
+
+    // An overwrite must not be answered from the old blocks.
+    let replaced = vec![b'x'; 600 * 1024];
+    app.call(Method::PUT, "/tables/data.parquet", replaced.clone())
+        .await;
+    let resp = get_range(262_000, 263_000).await;
+    ensure!(resp.body == replaced[262_000..=263_000], "stale range body");
+    Ok(())
+}
+
+async fn get_object_range_unsatisfiable(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    app.put_object("photos", "k", b"0123456789").await;
+    let resp = app
+        .send(
+            Request::builder()
+                .uri("/photos/k")
+                .header("range", "bytes=20-")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+    ensure!(
+        resp.status == StatusCode::RANGE_NOT_SATISFIABLE,
+        "status {}",
+        resp.status
+    );
+    ensure!(
+        resp.header("content-range") == Some("bytes */10"),
+        "content-range {:?}",
+        resp.header("content-range")
+    );
+    Ok(())
+}
+
+async fn get_object_if_range(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    let put = app.put_object("photos", "k", b"0123456789").await;
+    let etag = put.header("etag").unwrap_or_default().to_string();
+    let ranged = |if_range: String| {
+        Request::builder()
+            .uri("/photos/k")
+            .header("range", "bytes=-3")
+            .header("if-range", if_range)
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    let resp = app.send(ranged(etag.clone())).await;
+    ensure!(
+        resp.status == StatusCode::PARTIAL_CONTENT,
+        "matching status {}",
+        resp.status
+    );
+    ensure!(resp.body == b"789", "matching body {}", resp.text());
+
+    app.put_object("photos", "k", b"abcdefghij").await;
+    let resp = app.send(ranged(etag)).await;
+    ensure!(
+        resp.status == StatusCode::OK,
+        "stale status {}",
+        resp.status
+    );
+    ensure!(resp.body == b"abcdefghij", "stale body {}", resp.text());
+    Ok(())
+}
+
+async fn get_object_cache_headers(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    app.put_object("photos", "k", b"data").await;
+    let resp = app
+        .send(
+            Request::builder()
+                .method(Method::PATCH)
+                .uri("/admin/buckets/photos")
+                .header("content-type", "application/json")
+                .body(Body::from(
+                    r#"{"cache_control":"public, max-age=60","expires_secs":60}"#,
+                ))
+                .unwrap(),
+        )
+        .await;
+    ensure!(resp.status == StatusCode::OK, "patch {}", resp.status);
+
+    for method in [Method::GET, Method::HEAD] {
+        let resp = app.call(method.clone(), "/photos/k", Body::empty()).await;
+        ensure!(
+            resp.header("cache-control") == Some("public, max-age=60"),
+            "{} cache-control {:?}",
+            method,
+            resp.header("cache-control")
+        );
+        ensure!(
+            resp.header("expires").is_some_and(|v| v.ends_with(" GMT")),
+            "{} expires {:?}",
+            method,
+            resp.header("expires")
+        );
+    }
+    Ok(())
+}
+
+async fn get_object_stored_content_headers(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    app.send(
+        Request::builder()
+            .method(Method::PATCH)
+            .uri("/admin/buckets/photos")
+            .header("content-type", "application/json")
+            .body(Body::from(r#"{"cache_control":"public, max-age=60"}"#))
+            .unwrap(),
+    )
+    .await;
+    app.send(
+        Request::builder()
+            .method(Method::PUT)
+            .uri("/photos/k")
+            .header("cache-control", "no-store")
+            .header("content-disposition", "attachment; filename=\"k.txt\"")
+            .header("expires", "Wed, 21 Oct 2037 07:28:00 GMT")
+            .body(Body::from("data"))
+            .unwrap(),
+    )
+    .await;
+    for method in [Method::GET, Method::HEAD] {
+        let resp = app.call(method.clone(), "/photos/k", Body::empty()).await;
+        ensure!(
+            resp.header("cache-control") == Some("no-store"),
+            "{} cache-control {:?}",
+            method,
+            resp.header("cache-control")
+        );
+        ensure!(
+            resp.header("content-disposition") == Some("attachment; filename=\"k.txt\""),
+            "{} content-disposition {:?}",
+            method,
+            resp.header("content-disposition")
+        );
+        ensure!(
+            resp.header("expires") == Some("Wed, 21 Oct 2037 07:28:00 GMT"),
+            "{} expires {:?}",
+            method,
+            resp.header("expires")
+        );
+    }
+    Ok(())
+}
+
+async fn get_object_response_overrides(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    app.send(
+        Request::builder()
+            .method(Method::PUT)
+            .uri("/photos/k")
+            .header("content-type", "text/plain")
+            .body(Body::from("data"))
+            .unwrap(),
+    )
+    .await;
+    let uri = "/photos/k?response-content-type=application%2Fpdf\
+               &response-content-disposition=attachment%3B%20filename%3D%22r.pdf%22\
+               &response-cache-control=no-cache";
+    for method in [Method::GET, Method::HEAD] {
+        let resp = app.call(method.clone(), uri, Body::empty()).await;
+        ensure!(
+            resp.status == StatusCode::OK,
+            "{} status {}",
+            method,
+            resp.status
+        );
+        let content_types: Vec<_> = resp.headers.get_all("content-type").iter().collect();
+        ensure!(
+            content_types == ["application/pdf"],
+            "{} content-type {:?}",
+            method,
+            content_types
+        );
+        ensure!(
+            resp.header("content-disposition") == Some("attachment; filename=\"r.pdf\""),
+            "{} content-disposition {:?}",
+            method,
+            resp.header("content-disposition")
+        );
+        ensure!(
+            resp.header("cache-control") == Some("no-cache"),
+            "{} cache-control {:?}",
+            method,
+            resp.header("cache-control")
+        );
+    }
+    // The stored metadata is untouched.
+    let resp = app.call(Method::GET, "/photos/k", Body::empty()).await;
+    ensure!(
+        resp.header("content-type") == Some("text/plain"),
+        "stored content-type {:?}",
+        resp.header("content-type")
+    );
+    Ok(())
+}
+
+async fn complete_multipart_upload_content_headers(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    let resp = app
+        .send(
+            Request::builder()
+                .method(Method::POST)
+                .uri("/photos/big?uploads")
+                .header("content-disposition", "inline")
+                .header("cache-control", "max-age=5")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+    let text = resp.text();
+    let upload_id = text
+        .split("<UploadId>")
+        .nth(1)
+        .and_then(|rest| rest.split("</UploadId>").next())
+        .ok_or_else(|| format!("no UploadId in {}", text))?
+        .to_string();
+    let resp = app
+        .call(
+            Method::PUT,
+            &format!("/photos/big?partNumber=1&uploadId={}", upload_id),
+            Body::from("only part"),
+        )
+        .await;
+    let etag = resp.header("etag").unwrap_or_default().to_string();
+    let body = format!(
+        "<CompleteMultipartUpload><Part><PartNumber>1</PartNumber><ETag>{}</ETag></Part></CompleteMultipartUpload>",
+        etag
+    );
+    let resp = app
+        .call(
+            Method::POST,
+            &format!("/photos/big?uploadId={}", upload_id),
+            Body::from(body),
+        )
+        .await;
+    ensure!(resp.status == StatusCode::OK, "complete {}", resp.status);
+    let resp = app.call(Method::HEAD, "/photos/big", Body::empty()).await;
+    ensure!(
+        resp.header("content-disposition") == Some("inline")
+            && resp.header("cache-control") == Some("max-age=5"),
+        "headers {:?}",
+        resp.headers
+    );
+    Ok(())
+}
+
+async fn get_object_if_none_match(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    let put = app.put_object("photos", "k", b"data").await;
+    let etag = put.header("etag").unwrap_or_default().to_string();
+    let resp = app
+        .send(
+            Request::builder()
+                .uri("/photos/k")
+                .header("if-none-match", etag)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+    ensure!(
+        resp.status == StatusCode::NOT_MODIFIED,
+        "status {}",
+        resp.status
+    );
+    Ok(())
+}
+
+async fn get_object_if_modified_since(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    app.put_object("photos", "k", b"data").await;
+    let head = app.call(Method::HEAD, "/photos/k", Body::empty()).await;
+    let last_modified = head.header("last-modified").unwrap_or_default().to_string();
+    let resp = app
+        .send(
+            Request::builder()
+                .uri("/photos/k")
+                .header("if-modified-since", &last_modified)
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+    ensure!(
+        resp.status == StatusCode::NOT_MODIFIED,
+        "status {}",
+        resp.status
+    );
+    ensure!(resp.body.is_empty(), "304 carried a body");
+    let resp = app
+        .send(
+            Request::builder()
+                .uri("/photos/k")
+                .header("if-modified-since", "Thu, 01 Jan 1970 00:00:00 GMT")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+    ensure!(resp.status == StatusCode::OK, "stale date {}", resp.status);
+    Ok(())
+}
+
+async fn head_object(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    app.put_object("photos", "k", b"data").await;
+    let resp = app.call(Method::HEAD, "/photos/k", Body::empty()).await;
+    ensure!(resp.status == StatusCode::OK, "status {}", resp.status);
+    ensure!(resp.body.is_empty(), "head returned a body");
+    ensure!(
+        resp.header("content-length") == Some("4"),
+        "content-length {:?}",
+        resp.header("content-length")
+    );
+    Ok(())
+}
+
+async fn head_object_user_metadata(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    app.send(
+        Request::builder()
+            .method(Method::PUT)
+            .uri("/photos/k")
+            .header("x-amz-meta-owner", "alice")
+            .body(Body::from("data"))
+            .unwrap(),
+    )
+    .await;
+    let resp = app.call(Method::HEAD, "/photos/k", Body::empty()).await;
+    ensure!(
+        resp.header("x-amz-meta-owner") == Some("alice"),
+        "x-amz-meta-owner {:?}",
+        resp.header("x-amz-meta-owner")
+    );
+    Ok(())
+}
+
+async fn put_object_tagging(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    app.put_object("photos", "k", b"data").await;
+    let body = concat!(
+        "<Tagging><TagSet>",
+        "<Tag><Key>team</Key><Value>a&amp;b</Value></Tag>",
+        "<Tag><Key>env</Key><Value>prod</Value></Tag>",
+        "</TagSet></Tagging>"
+    );
+    let resp = app
+        .call(Method::PUT, "/photos/k?tagging", Body::from(body))
+        .await;
+    ensure!(resp.status == StatusCode::OK, "put status {}", resp.status);
+    let resp = app
+        .call(Method::GET, "/photos/k?tagging", Body::empty())
+        .await;
+    ensure!(resp.status == StatusCode::OK, "get status {}", resp.status);
+    ensure!(
+        resp.text().contains(concat!(
+            "<TagSet><Tag><Key>env</Key><Value>prod</Value></Tag>",
+            "<Tag><Key>team</Key><Value>a&amp;b</Value></Tag></TagSet>"
+        )),
+        "body {}",
+        resp.text()
+    );
+    Ok(())
+}
+
+async fn put_object_tagging_too_many(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    app.put_object("photos", "k", b"data").await;
+    let tags: String = (0..11)
+        .map(|i| format!("<Tag><Key>k{i}</Key><Value>v</Value></Tag>"))
+        .collect();
+    let body = format!("<Tagging><TagSet>{tags}</TagSet></Tagging>");
+    let resp = app
+        .call(Method::PUT, "/photos/k?tagging", Body::from(body))
+        .await;
+    ensure!(
+        resp.status == StatusCode::BAD_REQUEST,
+        "status {}",
+        resp.status
+    );
+    Ok(())
+}
+
+async fn put_object_with_tagging_header(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    let resp = app
+        .send(
+            Request::builder()
+                .method(Method::PUT)
+                .uri("/photos/k")
+                .header("x-amz-tagging", "team=data%20eng&env=prod")
+                .body(Body::from("data"))
+                .unwrap(),
+        )
+        .await;
+    ensure!(resp.status == StatusCode::OK, "put status {}", resp.status);
+    let resp = app.call(Method::GET, "/photos/k", Body::empty()).await;
+    ensure!(
+        resp.header("x-amz-tagging-count") == Some("2"),
+        "x-amz-tagging-count {:?}",
+        resp.header("x-amz-tagging-count")
+    );
+    let resp = app
+        .call(Method::GET, "/photos/k?tagging", Body::empty())
+        .await;
+    ensure!(
+        resp.text().contains("<Value>data eng</Value>"),
+        "body {}",
+        resp.text()
+    );
+
+    // A plain overwrite is a new object without tags.
+    app.put_object("photos", "k", b"new").await;
+    let resp = app.call(Method::GET, "/photos/k", Body::empty()).await;
+    ensure!(
+        resp.header("x-amz-tagging-count").is_none(),
+        "tags survived overwrite: {:?}",
+        resp.header("x-amz-tagging-count")
+    );
+    Ok(())
+}
+
+async fn delete_object_tagging(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    app.send(
+        Request::builder()
+            .method(Method::PUT)
+            .uri("/photos/k")
+            .header("x-amz-tagging", "team=a")
+            .body(Body::from("data"))
+            .unwrap(),
+    )
+    .await;
+    let resp = app
+        .call(Method::DELETE, "/photos/k?tagging", Body::empty())
+        .await;
+    ensure!(
+        resp.status == StatusCode::NO_CONTENT,
+        "status {}",
+        resp.status
+    );
+    let resp = app
+        .call(Method::GET, "/photos/k?tagging", Body::empty())
+        .await;
+    ensure!(!resp.text().contains("<Tag>"), "body {}", resp.text());
+    let resp = app.call(Method::GET, "/photos/k", Body::empty()).await;
+    ensure!(
+        resp.status == StatusCode::OK,
+        "object gone after untagging: {}",
+        resp.status
+    );
+    Ok(())
+}
+
+async fn get_object_user_metadata(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    app.send(
+        Request::builder()
+            .method(Method::PUT)
+            .uri("/photos/k")
+            .header("x-amz-meta-owner", "alice")
+            .header("x-amz-meta-camera", "x100")
+            .body(Body::from("data"))
+            .unwrap(),
+    )
+    .await;
+    let resp = app.call(Method::GET, "/photos/k", Body::empty()).await;
+    ensure!(
+        resp.header("x-amz-meta-owner") == Some("alice")
+            && resp.header("x-amz-meta-camera") == Some("x100"),
+        "headers {:?}",
+        resp.headers
+    );
+
+    // A new upload replaces the metadata.
+    app.put_object("photos", "k", b"new").await;
+    let resp = app.call(Method::GET, "/photos/k", Body::empty()).await;
+    ensure!(
+        resp.header("x-amz-meta-owner").is_none(),
+        "metadata survived overwrite: {:?}",
+        resp.header("x-amz-meta-owner")
+    );
+    Ok(())
+}
+
+async fn copy_object_metadata_copy(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    app.send(
+        Request::builder()
+            .method(Method::PUT)
+            .uri("/photos/src")
+            .header("content-type", "image/jpeg")
+            .header("x-amz-meta-owner", "alice")
+            .body(Body::from("data"))
+            .unwrap(),
+    )
+    .await;
+    let resp = app
+        .send(
+            Request::builder()
+                .method(Method::PUT)
+                .uri("/photos/dst")
+                .header("x-amz-copy-source", "/photos/src")
+                .header("x-amz-meta-owner", "ignored")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+    ensure!(resp.status == StatusCode::OK, "copy status {}", resp.status);
+    ensure!(
+        resp.text().contains("<CopyObjectResult"),
+        "body {}",
+        resp.text()
+    );
+    let resp = app.call(Method::GET, "/photos/dst", Body::empty()).await;
+    ensure!(resp.text() == "data", "body {}", resp.text());
+    ensure!(
+        resp.header("x-amz-meta-owner") == Some("alice"),
+        "x-amz-meta-owner {:?}",
+        resp.header("x-amz-meta-owner")
+    );
+    ensure!(
+        resp.header("content-type") == Some("image/jpeg"),
+        "content-type {:?}",
+        resp.header("content-type")
+    );
+    Ok(())
+}
+
+async fn copy_object_metadata_replace(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    app.send(
+        Request::builder()
+            .method(Method::PUT)
+            .uri("/photos/src")
+            .header("x-amz-meta-owner", "alice")
+            .body(Body::from("data"))
+            .unwrap(),
+    )
+    .await;
+    let resp = app
+        .send(
+            Request::builder()
+                .method(Method::PUT)
+                .uri("/photos/src")
+                .header("x-amz-copy-source", "photos/src")
+                .header("x-amz-metadata-directive", "REPLACE")
+                .header("x-amz-meta-reviewer", "bob")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+    ensure!(resp.status == StatusCode::OK, "copy status {}", resp.status);
+    let resp = app.call(Method::HEAD, "/photos/src", Body::empty()).await;
+    ensure!(
+        resp.header("x-amz-meta-reviewer") == Some("bob")
+            && resp.header("x-amz-meta-owner").is_none(),
+        "headers {:?}",
+        resp.headers
+    );
+    Ok(())
+}
+
+async fn copy_object_onto_itself(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    app.put_object("photos", "k", b"data").await;
+    let resp = app
+        .send(
+            Request::builder()
+                .method(Method::PUT)
+                .uri("/photos/k")
+                .header("x-amz-copy-source", "/photos/k")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+    ensure!(
+        resp.status == StatusCode::BAD_REQUEST,
+        "status {}",
+        resp.status
+    );
+    Ok(())
+}
+
+async fn copy_object_in_place(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    app.send(
+        Request::builder()
+            .method(Method::PUT)
+            .uri("/photos/k")
+            .header("x-amz-meta-owner", "alice")
+            .header("x-amz-tagging", "team=web")
+            .body(Body::from("data"))
+            .unwrap(),
+    )
+    .await;
+    let before = app
+        .service
+        .get_object_metadata("photos", "k")
+        .await
+        .map_err(|err| err.to_string())?;
+    let resp = app
+        .send(
+            Request::builder()
+                .method(Method::PUT)
+                .uri("/photos/k")
+                .header("x-amz-copy-source", "/photos/k")
+                .header("x-amz-metadata-directive", "REPLACE")
+                .header("content-type", "text/plain")
+                .header("x-amz-meta-owner", "bob")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+    ensure!(resp.status == StatusCode::OK, "status {}", resp.status);
+    let after = app
+        .service
+        .get_object_metadata("photos", "k")
+        .await
+        .map_err(|err| err.to_string())?;
+    ensure!(
+        after.id == before.id && after.etag == before.etag,
+        "row or etag changed: {:?} -> {:?}",
+        before.etag,
+        after.etag
+    );
+    let get = app.call(Method::GET, "/photos/k", Body::empty()).await;
+    ensure!(get.body == b"data", "body {:?}", get.text());
+    ensure!(
+        get.header("content-type") == Some("text/plain")
+            && get.header("x-amz-meta-owner") == Some("bob")
+            && get.header("x-amz-tagging-count") == Some("1"),
+        "headers {:?}",
+        get.headers
+    );
+    Ok(())
+}
+
+async fn put_object_default_acl(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    app.service
+        .set_bucket_default_acl("photos", Some("public-read"))
+        .await
+        .map_err(|err| err.to_string())?;
+    app.put_object("photos", "a", b"data").await;
+    app.send(
+        Request::builder()
+            .method(Method::PUT)
+            .uri("/photos/b")
+            .header("x-amz-acl", "authenticated-read")
+            .body(Body::from("data"))
+            .unwrap(),
+    )
+    .await;
+    for (key, expected) in [("a", "public-read"), ("b", "authenticated-read")] {
+        let object = app
+            .service
+            .get_object_metadata("photos", key)
+            .await
+            .map_err(|err| err.to_string())?;
+        ensure!(
+            object.acl.as_deref() == Some(expected),
+            "{} acl {:?}",
+            key,
+            object.acl
+        );
+    }
+    let resp = app
+        .send(
+            Request::builder()
+                .method(Method::PUT)
+                .uri("/photos/c")
+                .header("x-amz-acl", "world-writable")
+                .body(Body::from("data"))
+                .unwrap(),
+        )
+        .await;
+    ensure!(
+        resp.status == StatusCode::BAD_REQUEST,
+        "unknown ACL status {}",
+        resp.status
+    );
+    Ok(())
+}
+
+async fn put_object_owner_full_control(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    app.service
+        .set_bucket_enforce_owner_full_control("photos", true)
+        .await
+        .map_err(|err| err.to_string())?;
+    let resp = app
+        .send(
+            Request::builder()
+                .method(Method::PUT)
+                .uri("/photos/k")
+                .header("x-amz-acl", "public-read")
+                .body(Body::from("data"))
+                .unwrap(),
+        )
+        .await;
+    ensure!(
+        resp.status == StatusCode::FORBIDDEN,
+        "public-read status {}",
+        resp.status
+    );
+    app.put_object("photos", "k", b"data").await;
+    let object = app
+        .service
+        .get_object_metadata("photos", "k")
+        .await
+        .map_err(|err| err.to_string())?;
+    ensure!(
+        object.acl.as_deref() == Some("bucket-owner-full-control"),
+        "acl {:?}",
+        object.acl
+    );
+    Ok(())
+}
+
+async fn delete_object(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    app.put_object("photos", "k", b"data").await;
+    let resp = app.call(Method::DELETE, "/photos/k", Body::empty()).await;
+    ensure!(
+        resp.status == StatusCode::NO_CONTENT,
+        "status {}",
+        resp.status
+    );
+    let resp = app.call(Method::GET, "/photos/k", Body::empty()).await;
+    ensure!(
+        resp.status == StatusCode::NOT_FOUND,
+        "get after delete {}",
+        resp.status
+    );
+    Ok(())
+}
+
+async fn purge_deleted_objects(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    app.send(
+        Request::builder()
+            .method(Method::PUT)
+            .uri("/photos/old")
+            .header("x-amz-tagging", "team=a")
+            .body(Body::from("data"))
+            .unwrap(),
+    )
+    .await;
+    app.put_object("photos", "recent", b"data").await;
+    app.put_object("photos", "live", b"data").await;
+    app.call(Method::DELETE, "/photos/old", Body::empty()).await;
+    backdate(app, "UPDATE objects SET deleted_at = ?", 10).await;
+    app.call(Method::DELETE, "/photos/recent", Body::empty())
+        .await;
+
+    let summary = app
+        .service
+        .purge_deleted_objects(std::time::Duration::from_secs(7 * 24 * 3600))
+        .await
+        .map_err(|err| err.to_string())?;
+    ensure!(summary.purged_rows == 1, "purged {:?}", summary);
+    let keys: Vec<String> = sqlx::query_scalar("SELECT key FROM objects ORDER BY key")
+        .fetch_all(&*app.service.db)
+        .await
+        .map_err(|err| err.to_string())?;
+    ensure!(keys == ["live", "recent"], "rows left {:?}", keys);
+    let tags: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM object_tags WHERE key = 'old'")
+        .fetch_one(&*app.service.db)
+        .await
+        .map_err(|err| err.to_string())?;
+    ensure!(tags == 0, "{} tags left", tags);
+    let resp = app.call(Method::GET, "/photos/live", Body::empty()).await;
+    ensure!(resp.status == StatusCode::OK, "live object {}", resp.status);
+
+    // Purging waits for the key's lock and spares a key written meanwhile.
+    app.put_object("photos", "again", b"old").await;
+    let payload = find_files(&app.service.base_path.join("photos"), "again");
+    ensure!(payload.len() == 1, "payload files {:?}", payload);
+    let payload = payload[0].clone();
+    app.call(Method::DELETE, "/photos/again", Body::empty())
+        .await;
+    backdate(
+        app,
+        "UPDATE objects SET deleted_at = ? WHERE key = 'again'",
+        10,
+    )
+    .await;
+    let bucket_id: uuid::Uuid = sqlx::query_scalar("SELECT id FROM buckets WHERE name = 'photos'")
+        .fetch_one(&*app.service.db)
+        .await
+        .map_err(|err| err.to_string())?;
+    let guard = app.service.key_locks.lock(bucket_id, "again").await;
+    let service = app.service.clone();
+    let purge = tokio::spawn(async move {
+        service
+            .purge_deleted_objects(std::time::Duration::from_secs(7 * 24 * 3600))
+            .await
+    });
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    ensure!(!purge.is_finished(), "purge ran under a held key lock");
+    // What an upload holding the lock would do: land the payload, then the row.
+    std::fs::create_dir_all(payload.parent().unwrap()).map_err(|err| err.to_string())?;
+    std::fs::write(&payload, b"new").map_err(|err| err.to_string())?;
+    sqlx::query("UPDATE objects SET is_deleted = 0, deleted_at = NULL WHERE key = 'again'")
+        .execute(&*app.service.db)
+        .await
+        .map_err(|err| err.to_string())?;
+    drop(guard);
+    let summary = purge
+        .await
+        .map_err(|err| err.to_string())?
+        .map_err(|err| err.to_string())?;
+    ensure!(summary.purged_rows == 0, "purged {:?}", summary);
+    let resp = app.call(Method::GET, "/photos/again", Body::empty()).await;
+    ensure!(
+        resp.status == StatusCode::OK && resp.body == b"new",
+        "rewritten object {} {:?}",
+        resp.status,
+        resp.text()
+    );
+    Ok(())
+}
+
+async fn restore_deleted_object(_app: &TestApp) -> CaseResult {
+    let app = TestApp::with_service(|mut service| {
+        service.options.deleted_retention = Some(std::time::Duration::from_secs(3600));
+        service
+    })
+    .await;
+    app.create_bucket("photos").await;
+    app.put_object("photos", "docs/a.txt", b"first").await;
+    app.put_object("photos", "docs/b.txt", b"second").await;
+    app.put_object("photos", "other.txt", b"other").await;
+    for key in ["docs/a.txt", "docs/b.txt", "other.txt"] {
+        app.call(Method::DELETE, &format!("/photos/{}", key), Body::empty())
+            .await;
+    }
+
+    let list = app
+        .call(
+            Method::GET,
+            "/photos?deleted=true&prefix=docs/&max-keys=1",
+            Body::empty(),
+        )
+        .await;
+    ensure!(list.status == StatusCode::OK, "list {}", list.status);
+    let listing: serde_json::Value = serde_json::from_slice(&list.body).unwrap();
+    ensure!(
+        listing["deleted"][0]["key"] == "docs/a.txt"
+            && listing["is_truncated"] == true
+            && listing["next_start_after"] == "docs/a.txt",
+        "listing {}",
+        listing
+    );
+
+    let restore = app
+        .call(Method::POST, "/photos/docs/a.txt?restore", Body::empty())
+        .await;
+    ensure!(
+        restore.status == StatusCode::OK,
+        "restore {}",
+        restore.status
+    );
+    let get = app
+        .call(Method::GET, "/photos/docs/a.txt", Body::empty())
+        .await;
+    ensure!(get.body == b"first", "restored body {:?}", get.text());
+    let again = app
+        .call(Method::POST, "/photos/docs/a.txt?restore", Body::empty())
+        .await;
+    ensure!(
+        again.status == StatusCode::NOT_FOUND,
+        "restore of a live key {}",
+        again.status
+    );
+
+    // A purged key has nothing left to restore.
+    app.service
+        .purge_deleted_objects(std::time::Duration::ZERO)
+        .await
+        .map_err(|err| err.to_string())?;
+    let gone = app
+        .call(Method::POST, "/photos/docs/b.txt?restore", Body::empty())
+        .await;
+    ensure!(
+        gone.status == StatusCode::NOT_FOUND,
+        "restore after purge {}",
+        gone.status
+    );
+    let trash = app.service.base_path.join(".trash/photos");
+    let left = std::fs::read_dir(&trash)
+        .map(|dir| dir.count())
+        .unwrap_or(0);
+    ensure!(left == 0, "{} trashed payloads left", left);
+    Ok(())
+}
+
+/// Temp files left anywhere under the storage directory.
+fn leftover_temp_files(dir: &std::path::Path) -> Vec<std::path::PathBuf> {
+    let mut found = Vec::new();
+    for entry in std::fs::read_dir(dir).into_iter().flatten().flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            found.extend(leftover_temp_files(&path));
+        } else if entry.file_name().to_string_lossy().starts_with(".tmp-") {
+            found.push(path);
+        }
+    }
+    found
+}
+
+/// Files named `name` anywhere below `dir`.
+fn find_files(dir: &std::path::Path, name: &str) -> Vec<std::path::PathBuf> {
+    let mut found = Vec::new();
+    for entry in std::fs::read_dir(dir).into_iter().flatten().flatten() {
+        let path = entry.path();
+        if path.is_dir() {
+            found.extend(find_files(&path, name));
+        } else if entry.file_name() == name {
+            found.push(path);
+        }
+    }
+    found
+}
+
+/// Send `uri` a body that delivers one chunk and then, like a client that
+/// went away, either fails or never finishes (the request is then dropped).
+async fn send_interrupted(app: &TestApp, uri: &str, fail: bool) {
+    let first = futures::stream::once(async {
+        Ok::<_, std::io::Error>(axum::body::Bytes::from_static(b"partial body"))
+    });
+    let body = if fail {
+        Body::from_stream(first.chain(futures::stream::once(async {
+            Err(std::io::Error::new(
+                std::io::ErrorKind::ConnectionReset,
+                "client went away",
+            ))
+        })))
+    } else {
+        Body::from_stream(first.chain(futures::stream::pending()))
+    };
+    let request = Request::builder()
+        .method(Method::PUT)
+        .uri(uri)
+        .body(body)
+        .unwrap();
+    let _ = tokio::time::timeout(std::time::Duration::from_millis(200), app.send(request)).await;
+}
+
+async fn aborted_uploads(app: &TestApp) -> Result<u64, String> {
+    let resp = app.call(Method::GET, "/admin/uploads", Body::empty()).await;
+    let json: serde_json::Value = serde_json::from_slice(&resp.body).map_err(|e| e.to_string())?;
+    json["aborted_uploads"]
+        .as_u64()
+        .ok_or_else(|| format!("no aborted_uploads in {}", resp.text()))
+}
+
+async fn put_object_dropped_connection(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    send_interrupted(app, "/photos/a.bin", true).await;
+    send_interrupted(app, "/photos/b.bin", false).await;
+    let leftover = leftover_temp_files(&app.service.base_path);
+    ensure!(leftover.is_empty(), "temp files left: {:?}", leftover);
+    ensure!(aborted_uploads(app).await? == 2, "aborted count");
+    let resp = app.call(Method::HEAD, "/photos/b.bin", Body::empty()).await;
+    ensure!(
+        resp.status == StatusCode::NOT_FOUND,
+        "partial object stored: {}",
+        resp.status
+    );
+    Ok(())
+}
+
+async fn upload_part_dropped_connection(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    let upload_id = initiate_upload(app, "/photos/big.bin").await?;
+    for (part, fail) in [(1, true), (2, false)] {
+        let uri = format!("/photos/big.bin?partNumber={}&uploadId={}", part, upload_id);
+        send_interrupted(app, &uri, fail).await;
+    }
+    let leftover = leftover_temp_files(&app.service.base_path);
+    ensure!(leftover.is_empty(), "temp files left: {:?}", leftover);
+    ensure!(aborted_uploads(app).await? == 2, "aborted count");
+    let resp = app
+        .call(
+            Method::GET,
+            &format!("/photos/big.bin?uploadId={}", upload_id),
+            Body::empty(),
+        )
+        .await;
+    ensure!(
+        extract_all(&resp.text(), "PartNumber").is_empty(),
+        "parts stored: {}",
+        resp.text()
+    );
+    Ok(())
+}
+
+async fn access_log_request_ids(app: &TestApp) -> CaseResult {
+    use object_store::middleware::access_log::{AccessLog, log_access};
+
+    app.create_bucket("logged").await;
+    app.put_object("logged", "a", b"hello").await;
+    let router = app
+        .router
+        .clone()
+        .layer(axum::middleware::from_fn_with_state(
+            AccessLog { enabled: true },
+            log_access,
+        ));
+    let get = |uri: &str| {
+        Request::builder()
+            .method(Method::GET)
+            .uri(uri)
+            .body(Body::empty())
+            .unwrap()
+    };
+
+    let mut ids = Vec::new();
+    for (uri, status) in [
+        ("/logged/a", StatusCode::OK),
+        ("/logged/a", StatusCode::OK),
+        ("/logged/missing", StatusCode::NOT_FOUND),
+        ("/nope", StatusCode::NOT_FOUND),
+    ] {
+        let resp = app.send_via(router.clone(), get(uri)).await;
+        ensure!(resp.status == status, "{} {}", uri, resp.status);
+        let id = resp.header("x-amz-request-id").unwrap_or_default();
+        ensure!(
+            id.len() == 16
+                && id
+                    .chars()
+                    .all(|c| c.is_ascii_digit() || c.is_ascii_uppercase()),
+            "{} request id {:?}",
+            uri,
+            id
+        );
+        ids.push(id.to_string());
+    }
+    let object = app.send_via(router.clone(), get("/logged/a")).await;
+    ensure!(object.body == b"hello", "body {:?}", object.text());
+    ids.sort();
+    ids.dedup();
+    ensure!(ids.len() == 4, "request ids repeat: {:?}", ids);
+    Ok(())
+}
+
+async fn alerts_fire_and_resolve(app: &TestApp) -> CaseResult {
+    use object_store::{
+        middleware::response_stats::{ResponseStats, count_responses},
+        services::{
+            alerts::{AlertMonitor, AlertSettings},
+            outbound::OutboundHttp,
+        },
+    };
+    use std::sync::{Arc, Mutex};
+
+    let posted: Arc<Mutex<Vec<serde_json::Value>>> = Arc::default();
+    let sink = posted.clone();
+    let webhook = axum::Router::new().fallback(move |body: String| {
+        let sink = sink.clone();
+        async move {
+            sink.lock()
+                .unwrap()
+                .push(serde_json::from_str(&body).unwrap_or_default());
+            StatusCode::OK
+        }
+    });
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let url = format!("http://{}/alerts", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, webhook).await });
+
+    let stats = ResponseStats::default();
+    let mut monitor = AlertMonitor::new(
+        app.service.clone(),
+        AlertSettings {
+            bucket_bytes: Some(10),
+            account_bytes: Some(30),
+            max_error_rate: Some(0.25),
+            webhook: Some(url.parse().unwrap()),
+            ..AlertSettings::default()
+        },
+        &OutboundHttp::default(),
+        Some(stats.clone()),
+    )
+    .map_err(|e| e.to_string())?;
+    let mut evaluate = async || {
+        let alerts = monitor.evaluate().await.map_err(|e| e.to_string())?;
+        monitor.deliver(&alerts).await;
+        // `kind:state:subject` per transition.
+        let summary: Vec<String> = alerts
+            .iter()
+            .map(|alert| {
+                let json = serde_json::to_value(alert).unwrap();
+                format!(
+                    "{}:{}:{}",
+                    alert.kind.as_str(),
+                    json["state"].as_str().unwrap_or_default(),
+                    alert.subject.as_deref().unwrap_or("-")
+                )
+            })
+            .collect();
+        Ok::<_, String>(summary)
+    };
+
+    app.create_bucket("photos").await;
+    app.put_object("photos", "small", b"four").await;
+    let quiet = evaluate().await?;
+    ensure!(quiet.is_empty(), "under the thresholds {:?}", quiet);
+
+    app.put_object("photos", "large", b"well over ten bytes")
+        .await;
+    let fired = evaluate().await?;
+    ensure!(
+        fired == ["bucket-bytes:firing:photos"],
+        "over the bucket threshold {:?}",
+        fired
+    );
+    let again = evaluate().await?;
+    ensure!(again.is_empty(), "still over, fired again {:?}", again);
+    let delivered = posted.lock().unwrap().clone();
+    ensure!(
+        delivered.len() == 1
+            && delivered[0]["alerts"][0]["kind"] == "bucket-bytes"
+            && delivered[0]["alerts"][0]["value"] == 23.0
+            && delivered[0]["alerts"][0]["threshold"] == 10.0,
+        "webhook payloads {:?}",
+        delivered
+    );
+
+    // Buckets of one owner add up towards the account threshold.
+    app.create_bucket("logs").await;
+    app.put_object("logs", "day", b"ten bytes!").await;
+    sqlx::query(
+        "UPDATE buckets SET owner_id = (SELECT owner_id FROM buckets WHERE name = 'photos')
+         WHERE name = 'logs'",
+    )
+    .execute(&*app.service.db)
+    .await
+    .map_err(|e| e.to_string())?;
+    let owner: uuid::Uuid = sqlx::query_scalar("SELECT owner_id FROM buckets WHERE name = 'logs'")
+        .fetch_one(&*app.service.db)
+        .await
+        .map_err(|e| e.to_string())?;
+    let account = evaluate().await?;
+    ensure!(
+        account == [format!("account-bytes:firing:{}", owner)],
+        "over the account threshold {:?}",
+        account
+    );
+
+    app.call(Method::DELETE, "/photos/large", Body::empty())
+        .await;
+    let resolved = evaluate().await?;
+    ensure!(
+        resolved
+            == [
+                "bucket-bytes:resolved:photos".to_string(),
+                format!("account-bytes:resolved:{}", owner)
+            ],
+        "back under the threshold {:?}",
+        resolved
+    );
+
+    // Server errors counted by the response layer drive the error rate.
+    let flaky = axum::Router::new()
+        .route("/ok", axum::routing::get(|| async { StatusCode::OK }))
+        .route(
+            "/fail",
+            axum::routing::get(|| async { StatusCode::INTERNAL_SERVER_ERROR }),
+        )
+        .layer(axum::middleware::from_fn_with_state(
+            stats.clone(),
+            count_responses,
+        ));
+    for i in 0..20 {
+        let uri = if i % 2 == 0 { "/ok" } else { "/fail" };
+        app.send_via(
+            flaky.clone(),
+            Request::builder().uri(uri).body(Body::empty()).unwrap(),
+        )
+        .await;
+    }
+    ensure!(
+        stats.snapshot() == (20, 10),
+        "counted {:?}",
+        stats.snapshot()
+    );
+    let failing = evaluate().await?;
+    ensure!(
+        failing == ["error-rate:firing:-"],
+        "half the responses failed {:?}",
+        failing
+    );
+    for _ in 0..20 {
+        app.send_via(
+            flaky.clone(),
+            Request::builder().uri("/ok").body(Body::empty()).unwrap(),
+        )
+        .await;
+    }
+    let healthy = evaluate().await?;
+    ensure!(
+        healthy == ["error-rate:resolved:-"],
+        "no failures since the last sample {:?}",
+        healthy
+    );
+    ensure!(
+        posted.lock().unwrap().len() == 5,
+        "webhook calls {:?}",
+        posted.lock().unwrap()
+    );
+    Ok(())
+}
+
+async fn slo_report(app: &TestApp) -> CaseResult {
+    use object_store::middleware::slo_stats::{SloStats, record_slo_stats};
+
+    let router = app
+        .router
+        .clone()
+        .layer(axum::middleware::from_fn_with_state(
+            SloStats::default(),
+            record_slo_stats,
+        ));
+    let call = |method: Method, uri: &str, body: &'static [u8]| {
+        Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("content-length", body.len())
+            .body(Body::from(body))
+            .unwrap()
+    };
+
+    app.create_bucket("slo").await;
+    for _ in 0..3 {
+        let put = app
+            .send_via(router.clone(), call(Method::PUT, "/slo/a", b"hello"))
+            .await;
+        ensure!(put.status == StatusCode::OK, "put {}", put.status);
+    }
+    let get = app
+        .send_via(router.clone(), call(Method::GET, "/slo/a", b""))
+        .await;
+    ensure!(
+        get.status == StatusCode::OK,
+        "get {} {}",
+        get.status,
+        get.text()
+    );
+    app.send_via(router.clone(), call(Method::GET, "/slo/missing", b""))
+        .await;
+
+    let resp = app
+        .send_via(
+            router.clone(),
+            call(Method::GET, "/admin/slo?minutes=5", b""),
+        )
+        .await;
+    ensure!(resp.status == StatusCode::OK, "slo {}", resp.status);
+    let report: serde_json::Value =
+        serde_json::from_slice(&resp.body).map_err(|e| e.to_string())?;
+    ensure!(report["window_minutes"] == 5, "window {}", report);
+    let writes = &report["operations"]["object-write"];
+    ensure!(
+        writes["requests"] == 3 && writes["request_bytes"] == 15 && writes["error_rate"] == 0.0,
+        "writes {}",
+        writes
+    );
+    ensure!(
+        writes["p50_ms"].as_f64().is_some_and(|p50| p50 > 0.0)
+            && writes["p50_ms"].as_f64() <= writes["p99_ms"].as_f64()
+            && writes["p99_ms"].as_f64() <= writes["max_ms"].as_f64(),
+        "write percentiles {}",
+        writes
+    );
+    let reads = &report["operations"]["object-read"];
+    ensure!(
+        reads["requests"] == 2 && reads["client_errors"] == 1 && reads["client_error_rate"] == 0.5,
+        "reads {}",
+        reads
+    );
+    ensure!(
+        report["overall"]["requests"] == 5,
+        "overall {}",
+        report["overall"]
+    );
+
+    let missing = app.call(Method::GET, "/admin/slo", Body::empty()).await;
+    ensure!(
+        missing.status == StatusCode::NOT_FOUND,
+        "without stats {}",
+        missing.status
+    );
+    Ok(())
+}
+
+async fn server_timing_breakdown(app: &TestApp) -> CaseResult {
+    use object_store::middleware::server_timing::{DebugTiming, server_timing};
+
+    let router = app
+        .router
+        .clone()
+        .layer(axum::middleware::from_fn_with_state(
+            DebugTiming::new("0123456789abcdef"),
+            server_timing,
+        ));
+    let call = |method: Method, uri: &str, body: &'static [u8], token: Option<&str>| {
+        let mut request = Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("content-length", body.len());
+        if let Some(token) = token {
+            request = request.header("x-debug-timing", token);
+        }
+        request.body(Body::from(body)).unwrap()
+    };
+    let token = Some("0123456789abcdef");
+
+    app.create_bucket("timed").await;
+    let put = app
+        .send_via(
+            router.clone(),
+            call(Method::PUT, "/timed/a", b"hello", token),
+        )
+        .await;
+    ensure!(put.status == StatusCode::OK, "put {}", put.status);
+    let timing = put.header("server-timing").unwrap_or_default();
+    for phase in [
+        "auth;dur=",
+        "db;dur=",
+        "disk-write;dur=",
+        "hash;dur=",
+        "total;dur=",
+    ] {
+        ensure!(timing.contains(phase), "put timing {:?}", timing);
+    }
+
+    let get = app
+        .send_via(router.clone(), call(Method::GET, "/timed/a", b"", token))
+        .await;
+    ensure!(get.status == StatusCode::OK, "get {}", get.status);
+    let timing = get.header("server-timing").unwrap_or_default();
+    ensure!(
+        timing.contains("db;dur=") && timing.contains("disk-read;dur="),
+        "get timing {:?}",
+        timing
+    );
+
+    for token in [None, Some("not-the-token")] {
+        let get = app
+            .send_via(router.clone(), call(Method::GET, "/timed/a", b"", token))
+            .await;
+        ensure!(
+            get.status == StatusCode::OK && get.header("server-timing").is_none(),
+            "untimed get {} {:?}",
+            get.status,
+            get.header("server-timing")
+        );
+    }
+    Ok(())
+}
+
+async fn shadow_mirrors_requests(app: &TestApp) -> CaseResult {
+    use object_store::{
+        middleware::shadow::{SHADOW_HEADER, Shadow, ShadowSettings, shadow_requests},
+        services::outbound::OutboundHttp,
+    };
+    use std::sync::{Arc, Mutex};
+
+    // A stub secondary recording every request it receives.
+    type Mirrored = (Method, String, Option<String>, Vec<u8>);
+    let received: Arc<Mutex<Vec<Mirrored>>> = Arc::default();
+    let stub = axum::Router::new().fallback({
+        let received = received.clone();
+        move |request: Request<Body>| async move {
+            let (parts, body) = request.into_parts();
+            let body = axum::body::to_bytes(body, usize::MAX).await.unwrap();
+            let marker = parts
+                .headers
+                .get(SHADOW_HEADER)
+                .and_then(|v| v.to_str().ok())
+                .map(str::to_string);
+            received.lock().unwrap().push((
+                parts.method,
+                parts.uri.to_string(),
+                marker,
+                body.to_vec(),
+            ));
+            StatusCode::OK
+        }
+    });
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let target = format!("http://{}/", listener.local_addr().unwrap());
+    tokio::spawn(async move { axum::serve(listener, stub).await });
+
+    let shadowed = |include_writes: bool| {
+        let shadow = Shadow::new(
+            ShadowSettings {
+                target: target.parse().unwrap(),
+                percent: 100,
+                include_writes,
+            },
+            &OutboundHttp::default(),
+        )
+        .unwrap();
+        app.router
+            .clone()
+            .layer(axum::middleware::from_fn_with_state(
+                shadow,
+                shadow_requests,
+            ))
+    };
+    let wait_for = |count: usize| {
+        let received = received.clone();
+        async move {
+            for _ in 0..100 {
+                if received.lock().unwrap().len() >= count {
+                    break;
+                }
+                tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+            received.lock().unwrap().clone()
+        }
+    };
+
+    app.create_bucket("photos").await;
+    let router = shadowed(true);
+    let put = app
+        .send_via(
+            router.clone(),
+            Request::builder()
+                .method(Method::PUT)
+                .uri("/photos/cat.jpg")
+                .body(Body::from("meow"))
+                .unwrap(),
+        )
+        .await;
+    ensure!(put.status == StatusCode::OK, "primary put {}", put.status);
+    let get = app
+        .send_via(
+            router.clone(),
+            Request::builder()
+                .uri("/photos/cat.jpg?x=1")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+    ensure!(get.body == b"meow", "primary get {}", get.text());
+    // Neither the admin API nor shadow copies are mirrored.
+    app.send_via(
+        router.clone(),
+        Request::builder()
+            .uri("/admin/limits")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await;
+    app.send_via(
+        router,
+        Request::builder()
+            .uri("/photos/cat.jpg")
+            .header(SHADOW_HEADER, "1")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await;
+    let mut seen = wait_for(2).await;
+    seen.sort_by(|a, b| a.1.cmp(&b.1));
+    ensure!(
+        seen == [
+            (
+                Method::PUT,
+                "/photos/cat.jpg".to_string(),
+                Some("1".to_string()),
+                b"meow".to_vec()
+            ),
+            (
+                Method::GET,
+                "/photos/cat.jpg?x=1".to_string(),
+                Some("1".to_string()),
+                Vec::new()
+            ),
+        ],
+        "mirrored {:?}",
+        seen
+    );
+
+    // Without writes enabled only reads reach the secondary.
+    received.lock().unwrap().clear();
+    let router = shadowed(false);
+    app.send_via(
+        router.clone(),
+        Request::builder()
+            .method(Method::PUT)
+            .uri("/photos/dog.jpg")
+            .body(Body::from("woof"))
+            .unwrap(),
+    )
+    .await;
+    app.send_via(
+        router,
+        Request::builder()
+            .method(Method::HEAD)
+            .uri("/photos/dog.jpg")
+            .body(Body::empty())
+            .unwrap(),
+    )
+    .await;
+    let seen = wait_for(1).await;
+    ensure!(
+        seen.len() == 1 && seen[0].0 == Method::HEAD,
+        "read-only mirroring {:?}",
+        seen
+    );
+    Ok(())
+}
+
+async fn proxy_cache_headers(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    app.put_object("photos", "a", b"hello").await;
+    let router = app.router.clone().layer(axum::middleware::from_fn(
+        object_store::middleware::proxy_cache::cache_friendly_headers,
+    ));
+    let get = |uri: &str, if_none_match: Option<&str>| {
+        let mut builder = Request::builder().method(Method::GET).uri(uri);
+        if let Some(etag) = if_none_match {
+            builder = builder.header("if-none-match", etag);
+        }
+        builder.body(Body::empty()).unwrap()
+    };
+
+    let object = app.send_via(router.clone(), get("/photos/a", None)).await;
+    ensure!(object.status == StatusCode::OK, "object {}", object.status);
+    ensure!(
+        object.header("date").is_some_and(|d| d.ends_with(" GMT"))
+            && object.header("age").is_none(),
+        "date/age {:?}",
+        object.headers
+    );
+    ensure!(
+        object
+            .header("last-modified")
+            .is_some_and(|d| d.ends_with(" GMT")),
+        "last-modified {:?}",
+        object.header("last-modified")
+    );
+    ensure!(
+        object
+            .header("vary")
+            .is_some_and(|v| v.contains("authorization")),
+        "vary {:?}",
+        object.header("vary")
+    );
+    let etag = object.header("etag").unwrap_or_default().to_string();
+    let revalidated = app
+        .send_via(router.clone(), get("/photos/a", Some(&etag)))
+        .await;
+    ensure!(
+        revalidated.status == StatusCode::NOT_MODIFIED,
+        "object revalidation {}",
+        revalidated.status
+    );
+
+    let missing = app
+        .send_via(router.clone(), get("/photos/nope", None))
+        .await;
+    ensure!(
+        missing.status == StatusCode::NOT_FOUND
+            && missing.header("cache-control") == Some("no-store"),
+        "missing {} {:?}",
+        missing.status,
+        missing.header("cache-control")
+    );
+
+    let listing = app.send_via(router.clone(), get("/photos", None)).await;
+    let list_etag = listing.header("etag").unwrap_or_default().to_string();
+    ensure!(
+        list_etag.starts_with('"') && !list_etag.starts_with("W/"),
+        "listing etag {:?}",
+        listing.headers
+    );
+    ensure!(
+        listing.header("cache-control") == Some("no-cache"),
+        "listing cache-control {:?}",
+        listing.header("cache-control")
+    );
+    let again = app.send_via(router.clone(), get("/photos", None)).await;
+    ensure!(
+        again.header("etag") == Some(list_etag.as_str()),
+        "listing etag not stable"
+    );
+    let cached = app
+        .send_via(router.clone(), get("/photos", Some(&list_etag)))
+        .await;
+    ensure!(
+        cached.status == StatusCode::NOT_MODIFIED
+            && cached.body.is_empty()
+            && cached.header("etag") == Some(list_etag.as_str()),
+        "listing revalidation {} {:?}",
+        cached.status,
+        cached.headers
+    );
+    app.put_object("photos", "b", b"world").await;
+    let changed = app.send_via(router, get("/photos", Some(&list_etag))).await;
+    ensure!(
+        changed.status == StatusCode::OK && changed.header("etag") != Some(list_etag.as_str()),
+        "changed listing {} {:?}",
+        changed.status,
+        changed.header("etag")
+    );
+    Ok(())
+}
+
+async fn storage_class_analysis(app: &TestApp) -> CaseResult {
+    let analyse = || {
+        Request::builder()
+            .method(Method::POST)
+            .uri("/admin/buckets/data/analytics")
+            .header("content-type", "application/json")
+            .body(Body::from(
+                r#"{"destination": "reports", "prefix": "logs/"}"#,
+            ))
+            .unwrap()
+    };
+    app.create_bucket("data").await;
+    app.create_bucket("reports").await;
+    let disabled = app.send(analyse()).await;
+    ensure!(
+        disabled.status == StatusCode::BAD_REQUEST,
+        "analysis without access analytics {}",
+        disabled.status
+    );
+
+    let app = TestApp::with_service(|mut service| {
+        service.options.access_analytics = true;
+        service
+    })
+    .await;
+    app.create_bucket("data").await;
+    app.create_bucket("reports").await;
+    app.put_object("data", "logs/app/1", b"aaaa").await;
+    app.put_object("data", "logs/app/2", b"bbbb").await;
+    app.put_object("data", "logs/web/old", b"0123456789").await;
+    app.put_object("data", "other/skipped", b"x").await;
+    backdate(
+        &app,
+        "UPDATE objects SET last_modified = ? WHERE key = 'logs/web/old'",
+        100,
+    )
+    .await;
+    for _ in 0..3 {
+        app.call(Method::GET, "/data/logs/app/1", Body::empty())
+            .await;
+    }
+
+    let queued = app.send(analyse()).await;
+    let job: serde_json::Value = serde_json::from_slice(&queued.body).unwrap_or_default();
+    ensure!(
+        queued.status == StatusCode::ACCEPTED && job["kind"] == "storage-class-analysis",
+        "queue analysis {} {}",
+        queued.status,
+        queued.text()
+    );
+    let id = job["id"].as_str().unwrap_or_default().to_string();
+    ensure!(
+        app.service
+            .run_next_job()
+            .await
+            .map_err(|e| e.to_string())?,
+        "no job ran"
+    );
+    let job = app
+        .call(Method::GET, &format!("/admin/jobs/{}", id), Body::empty())
+        .await;
+    let job: serde_json::Value = serde_json::from_slice(&job.body).unwrap_or_default();
+    ensure!(
+        job["status"] == "succeeded" && job["processed"] == 3,
+        "job {}",
+        job
+    );
+
+    let report = app
+        .call(
+            Method::GET,
+            &format!("/reports/storage-class-analysis/data/{}.csv", id),
+            Body::empty(),
+        )
+        .await;
+    ensure!(
+        report.status == StatusCode::OK && report.header("content-type") == Some("text/csv"),
+        "report {} {:?}",
+        report.status,
+        report.header("content-type")
+    );
+    let text = report.text();
+    let mut lines = text.lines();
+    ensure!(
+        lines.next()
+            == Some(
+                "prefix,age_group_days,objects,bytes_stored,reads,bytes_read,retrieval_rate,recommendation"
+            ),
+        "report header {}",
+        text
+    );
+    let rows: Vec<&str> = lines.collect();
+    ensure!(
+        rows.len() == 16
+            && rows.contains(&"logs/app/,0-15,2,8,3,12,1.5000,keep in STANDARD")
+            && rows.contains(
+                &"logs/web/,90-180,1,10,0,0,0.0000,transition to STANDARD_IA after 90 days"
+            )
+            && !rows.iter().any(|row| row.starts_with("other/")),
+        "report rows {}",
+        text
+    );
+    Ok(())
+}
+
+async fn bucket_stats_per_prefix(_app: &TestApp) -> CaseResult {
+    let app = TestApp::with_service(|mut service| {
+        service.options.usage_prefix_depth = Some(2);
+        service
+    })
+    .await;
+    app.create_bucket("shared").await;
+    app.put_object("shared", "team-a/logs/x", b"hello").await;
+    app.put_object("shared", "team-a/img/y", b"abc").await;
+    app.put_object("shared", "team-b/z", b"wxyz").await;
+    app.put_object("shared", "top", b"hi").await;
+    let get = app
+        .call(Method::GET, "/shared/team-a/logs/x", Body::empty())
+        .await;
+    ensure!(get.status == StatusCode::OK, "get {}", get.status);
+
+    let recorded = prefix_stats(&app, "/admin/buckets/shared/stats").await?;
+    let expected = vec![
+        ("".to_string(), 1, 2, 2, 0),
+        ("team-a/img/".to_string(), 1, 3, 3, 0),
+        ("team-a/logs/".to_string(), 1, 5, 5, 5),
+        ("team-b/".to_string(), 1, 4, 4, 0),
+    ];
+    ensure!(recorded == expected, "depth 2 {:?}", recorded);
+    let rolled_up = prefix_stats(&app, "/admin/buckets/shared/stats?depth=1").await?;
+    let expected = vec![
+        ("".to_string(), 1, 2, 2, 0),
+        ("team-a/".to_string(), 2, 8, 8, 5),
+        ("team-b/".to_string(), 1, 4, 4, 0),
+    ];
+    ensure!(rolled_up == expected, "depth 1 {:?}", rolled_up);
+    let too_deep = app
+        .call(
+            Method::GET,
+            "/admin/buckets/shared/stats?depth=3",
+            Body::empty(),
+        )
+        .await;
+    ensure!(
+        too_deep.status == StatusCode::BAD_REQUEST,
+        "deeper than recorded {}",
+        too_deep.status
+    );
+    Ok(())
+}
+
+/// `(prefix, objects, bytes_stored, bytes_in, bytes_out)` rows of a
+/// bucket stats response.
+async fn prefix_stats(
+    app: &TestApp,
+    uri: &str,
+) -> Result<Vec<(String, u64, u64, u64, u64)>, String> {
+    let resp = app.call(Method::GET, uri, Body::empty()).await;
+    let json: serde_json::Value =
+        serde_json::from_slice(&resp.body).map_err(|e| format!("{}: {}", e, resp.text()))?;
+    let rows = json["prefixes"]
+        .as_array()
+        .ok_or_else(|| format!("no prefixes in {}", resp.text()))?
+        .iter()
+        .map(|row| {
+            (
+                row["prefix"].as_str().unwrap_or_default().to_string(),
+                row["objects"].as_u64().unwrap_or_default(),
+                row["bytes_stored"].as_u64().unwrap_or_default(),
+                row["bytes_in"].as_u64().unwrap_or_default(),
+                row["bytes_out"].as_u64().unwrap_or_default(),
+            )
+        })
+        .collect();
+    Ok(rows)
+}
+
+/// `(objects, bytes)` used by `bucket`, from its quota report.
+async fn quota_usage(app: &TestApp, bucket: &str) -> Result<(u64, u64), String> {
+    let resp = app
+        .call(
+            Method::GET,
+            &format!("/admin/buckets/{}/quota", bucket),
+            Body::empty(),
+        )
+        .await;
+    let json: serde_json::Value =
+        serde_json::from_slice(&resp.body).map_err(|e| format!("{}: {}", e, resp.text()))?;
+    Ok((
+        json["usage"]["objects"].as_u64().unwrap_or_default(),
+        json["usage"]["bytes"].as_u64().unwrap_or_default(),
+    ))
+}
+
+async fn put_quota(app: &TestApp, bucket: &str, quota: &'static str) -> common::TestResponse {
+    app.send(
+        Request::builder()
+            .method(Method::PUT)
+            .uri(format!("/admin/buckets/{}/quota", bucket))
+            .header("content-type", "application/json")
+            .body(Body::from(quota))
+            .unwrap(),
+    )
+    .await
+}
+
+async fn bucket_quota_enforced(app: &TestApp) -> CaseResult {
+    app.create_bucket("tenant").await;
+    app.put_object("tenant", "a", b"123456").await;
+    let resp = put_quota(app, "tenant", r#"{"max_bytes": 10, "max_objects": 2}"#).await;
+    ensure!(resp.status == StatusCode::OK, "put quota {}", resp.status);
+
+    let over = app.call(Method::PUT, "/tenant/b", "123456").await;
+    ensure!(
+        over.status == StatusCode::FORBIDDEN,
+        "over byte quota {}",
+        over.status
+    );
+    let missing = app.call(Method::HEAD, "/tenant/b", Body::empty()).await;
+    ensure!(
+        missing.status == StatusCode::NOT_FOUND,
+        "refused upload stored: {}",
+        missing.status
+    );
+    // Replacing a key only counts its growth.
+    app.put_object("tenant", "a", b"12345678").await;
+    app.put_object("tenant", "b", b"12").await;
+    ensure!(
+        quota_usage(app, "tenant").await? == (2, 10),
+        "usage {:?}",
+        quota_usage(app, "tenant").await?
+    );
+    let resp = put_quota(app, "tenant", r#"{"max_objects": 2}"#).await;
+    ensure!(resp.status == StatusCode::OK, "put quota {}", resp.status);
+    let third = app.call(Method::PUT, "/tenant/c", "1").await;
+    ensure!(
+        third.status == StatusCode::FORBIDDEN,
+        "over object quota {}",
+        third.status
+    );
+
+    let deleted = app.call(Method::DELETE, "/tenant/a", Body::empty()).await;
+    ensure!(
+        deleted.status == StatusCode::NO_CONTENT,
+        "delete {}",
+        deleted.status
+    );
+    ensure!(
+        quota_usage(app, "tenant").await? == (1, 2),
+        "usage after delete {:?}",
+        quota_usage(app, "tenant").await?
+    );
+    app.put_object("tenant", "c", b"1").await;
+
+    let removed = app
+        .call(Method::DELETE, "/admin/buckets/tenant/quota", Body::empty())
+        .await;
+    ensure!(
+        removed.status == StatusCode::NO_CONTENT,
+        "delete quota {}",
+        removed.status
+    );
+    app.put_object("tenant", "d", b"no limit any more").await;
+    Ok(())
+}
+
+async fn global_quota_enforced(_app: &TestApp) -> CaseResult {
+    let app = TestApp::with_service(|mut service| {
+        service.options.global_quota.max_bytes = Some(8);
+        service
+    })
+    .await;
+    app.create_bucket("one").await;
+    app.create_bucket("two").await;
+    app.put_object("one", "a", b"12345").await;
+    // Sent without a length, so the body is cut off while it streams in.
+    let chunks = futures::stream::iter(["12", "34"].map(Ok::<_, std::io::Error>));
+    let resp = app
+        .send(
+            Request::builder()
+                .method(Method::PUT)
+                .uri("/two/b")
+                .body(Body::from_stream(chunks))
+                .unwrap(),
+        )
+        .await;
+    ensure!(
+        resp.status == StatusCode::FORBIDDEN,
+        "over global quota {} {}",
+        resp.status,
+        resp.text()
+    );
+    app.put_object("two", "b", b"123").await;
+    let full = app.call(Method::PUT, "/two/c", "1").await;
+    ensure!(
+        full.status == StatusCode::FORBIDDEN,
+        "global quota used up {}",
+        full.status
+    );
+    Ok(())
+}
+
+async fn max_object_size_enforced(_app: &TestApp) -> CaseResult {
+    let app = TestApp::with_service(|mut service| {
+        service.options.max_object_size = Some(4);
+        service
+    })
+    .await;
+    app.create_bucket("small").await;
+    app.put_object("small", "fits", b"1234").await;
+    let declared = app
+        .send(
+            Request::builder()
+                .method(Method::PUT)
+                .uri("/small/declared")
+                .header("content-length", "5")
+                .body(Body::from("12345"))
+                .unwrap(),
+        )
+        .await;
+    ensure!(
+        declared.status == StatusCode::PAYLOAD_TOO_LARGE
+            && declared.text().contains("entity too large"),
+        "declared length {} {}",
+        declared.status,
+        declared.text()
+    );
+    // Sent without a length, so the body is cut off while it streams in.
+    let chunks = futures::stream::iter(["123", "456"].map(Ok::<_, std::io::Error>));
+    let streamed = app
+        .send(
+            Request::builder()
+                .method(Method::PUT)
+                .uri("/small/streamed")
+                .body(Body::from_stream(chunks))
+                .unwrap(),
+        )
+        .await;
+    ensure!(
+        streamed.status == StatusCode::PAYLOAD_TOO_LARGE,
+        "streamed {} {}",
+        streamed.status,
+        streamed.text()
+    );
+    let missing = app
+        .call(Method::HEAD, "/small/streamed", Body::empty())
+        .await;
+    ensure!(
+        missing.status == StatusCode::NOT_FOUND,
+        "refused upload stored: {}",
+        missing.status
+    );
+    let left = leftover_temp_files(&app.service.base_path);
+    ensure!(left.is_empty(), "temp files left: {:?}", left);
+
+    let init = app
+        .call(Method::POST, "/small/big?uploads", Body::empty())
+        .await;
+    let upload_id = extract_all(&init.text(), "UploadId")
+        .pop()
+        .ok_or_else(|| format!("no UploadId in {}", init.text()))?;
+    let part = app
+        .call(
+            Method::PUT,
+            &format!("/small/big?partNumber=1&uploadId={}", upload_id),
+            "123456",
+        )
+        .await;
+    ensure!(
+        part.status == StatusCode::PAYLOAD_TOO_LARGE,
+        "part {} {}",
+        part.status,
+        part.text()
+    );
+
+    let limits = app.call(Method::GET, "/limits", Body::empty()).await;
+    let limits: serde_json::Value = serde_json::from_slice(&limits.body).unwrap_or_default();
+    ensure!(
+        limits["max_object_size"] == 4 && limits["multipart"]["max_part_size"] == 4,
+        "limits {}",
+        limits
+    );
+    Ok(())
+}
+
+async fn max_request_body_enforced(_app: &TestApp) -> CaseResult {
+    use object_store::middleware::body_limit::{BodyLimit, limit_request_body};
+
+    let app = TestApp::with_service(|mut service| {
+        service.options.max_request_body = Some(4);
+        service
+    })
+    .await;
+    let router = app
+        .router
+        .clone()
+        .layer(axum::middleware::from_fn_with_state(
+            BodyLimit { max_bytes: 4 },
+            limit_request_body,
+        ));
+    app.create_bucket("small").await;
+    let put = |uri: &str, body: Body| {
+        Request::builder()
+            .method(Method::PUT)
+            .uri(uri)
+            .body(body)
+            .unwrap()
+    };
+
+    let fits = app
+        .send_via(router.clone(), put("/small/fits", Body::from("1234")))
+        .await;
+    ensure!(fits.status == StatusCode::OK, "fits {}", fits.status);
+    let sized = app
+        .send_via(router.clone(), put("/small/sized", Body::from("12345")))
+        .await;
+    ensure!(
+        sized.status == StatusCode::PAYLOAD_TOO_LARGE,
+        "sized {} {}",
+        sized.status,
+        sized.text()
+    );
+    let chunks = futures::stream::iter(["123", "456"].map(Ok::<_, std::io::Error>));
+    let streamed = app
+        .send_via(
+            router.clone(),
+            put("/small/streamed", Body::from_stream(chunks)),
+        )
+        .await;
+    ensure!(
+        streamed.status == StatusCode::PAYLOAD_TOO_LARGE,
+        "streamed {} {}",
+        streamed.status,
+        streamed.text()
+    );
+    let left = leftover_temp_files(&app.service.base_path);
+    ensure!(left.is_empty(), "temp files left: {:?}", left);
+    let missing = app
+        .send_via(
+            router.clone(),
+            Request::builder()
+                .method(Method::HEAD)
+                .uri("/small/streamed")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+    ensure!(
+        missing.status == StatusCode::NOT_FOUND,
+        "refused upload stored: {}",
+        missing.status
+    );
+    Ok(())
+}
+
+async fn dedup_shares_blobs(_app: &TestApp) -> CaseResult {
+    use std::os::unix::fs::MetadataExt;
+
+    let app = TestApp::with_service(|mut service| {
+        service.options.dedup = true;
+        service
+    })
+    .await;
+    app.create_bucket("photos").await;
+    app.put_object("photos", "a", b"same").await;
+    app.put_object("photos", "b", b"same").await;
+    app.put_object("photos", "c", b"other").await;
+    let bucket_dir = app.service.base_path.join("photos");
+    let inode = |name: &str| {
+        let files = find_files(&bucket_dir, name);
+        files
+            .first()
+            .and_then(|path| std::fs::metadata(path).ok())
+            .map(|metadata| metadata.ino())
+    };
+    ensure!(
+        inode("a").is_some() && inode("a") == inode("b") && inode("a") != inode("c"),
+        "inodes a={:?} b={:?} c={:?}",
+        inode("a"),
+        inode("b"),
+        inode("c")
+    );
+
+    let deleted = app.call(Method::DELETE, "/photos/a", Body::empty()).await;
+    ensure!(
+        deleted.status == StatusCode::NO_CONTENT,
+        "delete a {}",
+        deleted.status
+    );
+    let kept = app
+        .service
+        .gc_content_blobs()
+        .await
+        .map_err(|e| e.to_string())?;
+    ensure!(
+        kept.blobs_removed == 0,
+        "blob still linked from b: {:?}",
+        kept
+    );
+    let b = app.call(Method::GET, "/photos/b", Body::empty()).await;
+    ensure!(b.text() == "same", "b after deleting a: {}", b.text());
+
+    app.call(Method::DELETE, "/photos/b", Body::empty()).await;
+    let collected = app
+        .service
+        .gc_content_blobs()
+        .await
+        .map_err(|e| e.to_string())?;
+    ensure!(
+        collected.blobs_removed == 1 && collected.freed_bytes == 4,
+        "last reference gone: {:?}",
+        collected
+    );
+    let c = app.call(Method::GET, "/photos/c", Body::empty()).await;
+    ensure!(c.text() == "other", "c {}", c.text());
+    Ok(())
+}
+
+async fn snapshot_restore_key_state(_app: &TestApp) -> CaseResult {
+    use base64::{Engine as _, engine::general_purpose::STANDARD};
+    use sha2::{Digest, Sha256};
+    use std::os::unix::fs::MetadataExt;
+
+    let app = TestApp::with_service(|mut service| {
+        service.options.dedup = true;
+        service
+    })
+    .await;
+    let app = &app;
+    app.create_bucket("photos").await;
+    let key = [7u8; 32];
+    let sse_request = |method: Method, uri: &str| {
+        Request::builder()
+            .method(method)
+            .uri(uri)
+            .header("x-amz-server-side-encryption-customer-algorithm", "AES256")
+            .header(
+                "x-amz-server-side-encryption-customer-key",
+                STANDARD.encode(key),
+            )
+            .header(
+                "x-amz-server-side-encryption-customer-key-MD5",
+                STANDARD.encode(md5::compute(key).0),
+            )
+    };
+    let put = app
+        .send(
+            sse_request(Method::PUT, "/photos/secret")
+                .header("x-amz-tagging", "team=a")
+                .header("x-amz-meta-note", "captured")
+                .body(Body::from("the old secret"))
+                .unwrap(),
+        )
+        .await;
+    ensure!(put.status == StatusCode::OK, "sse-c put {}", put.status);
+    app.put_object("photos", "plain", b"shared").await;
+    let id = take_snapshot(app, "photos").await?;
+
+    let newer = app
+        .send(
+            Request::builder()
+                .method(Method::PUT)
+                .uri("/photos/secret")
+                .header("x-amz-tagging", "team=b")
+                .body(Body::from("a newer plaintext"))
+                .unwrap(),
+        )
+        .await;
+    ensure!(newer.status == StatusCode::OK, "newer put {}", newer.status);
+    app.call(Method::DELETE, "/photos/plain", Body::empty())
+        .await;
+    restore(app, "photos", &id).await?;
+
+    let clear = app.call(Method::GET, "/photos/secret", Body::empty()).await;
+    ensure!(
+        clear.status == StatusCode::BAD_REQUEST,
+        "restored SSE-C object read without its key: {} {:?}",
+        clear.status,
+        clear.text()
+    );
+    let get = app
+        .send(
+            sse_request(Method::GET, "/photos/secret")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+    ensure!(
+        get.status == StatusCode::OK && get.body == b"the old secret",
+        "restored SSE-C object {} {:?}",
+        get.status,
+        get.text()
+    );
+    ensure!(
+        get.header("x-amz-meta-note") == Some("captured"),
+        "restored metadata {:?}",
+        get.header("x-amz-meta-note")
+    );
+    let tags = app
+        .call(Method::GET, "/photos/secret?tagging", Body::empty())
+        .await;
+    ensure!(
+        extract_all(&tags.text(), "Value") == ["a"],
+        "restored tags {}",
+        tags.text()
+    );
+
+    let digest = format!("{:x}", Sha256::digest(b"shared"));
+    let blob = find_files(&app.service.base_path.join(".cas"), &digest);
+    let plain = find_files(&app.service.base_path.join("photos"), "plain");
+    let inode = |path: &std::path::Path| std::fs::metadata(path).map(|m| m.ino()).ok();
+    ensure!(
+        blob.len() == 1 && plain.len() == 1 && inode(&blob[0]) == inode(&plain[0]),
+        "restored payload not linked to its blob: {:?} {:?}",
+        blob,
+        plain
+    );
+    let refcount: i64 = sqlx::query_scalar("SELECT refcount FROM content_blobs WHERE sha256 = ?")
+        .bind(&digest)
+        .fetch_one(&*app.service.db)
+        .await
+        .map_err(|e| e.to_string())?;
+    ensure!(refcount == 2, "blob refcount {}", refcount);
+    Ok(())
+}
+
+async fn manifest_export_verify(app: &TestApp) -> CaseResult {
+    use base64::{Engine as _, engine::general_purpose::STANDARD};
+    use sha2::{Digest, Sha256};
+
+    app.create_bucket("releases").await;
+    let disabled = app
+        .call(
+            Method::GET,
+            "/admin/buckets/releases/manifest",
+            Body::empty(),
+        )
+        .await;
+    ensure!(
+        disabled.status == StatusCode::NOT_FOUND,
+        "manifest without a signing key {}",
+        disabled.status
+    );
+
+    let app = TestApp::with_service(|mut service| {
+        service.options.manifest_key = Some("manifest-signing-secret".parse().unwrap());
+        service
+    })
+    .await;
+    let app = &app;
+    app.create_bucket("releases").await;
+    app.put_object("releases", "v1/a.tar", b"alpha").await;
+    app.put_object("releases", "v1/b.tar", b"bravo").await;
+    app.put_object("releases", "notes.txt", b"outside the prefix")
+        .await;
+
+    let export = app
+        .call(
+            Method::GET,
+            "/admin/buckets/releases/manifest?prefix=v1/",
+            Body::empty(),
+        )
+        .await;
+    let manifest: serde_json::Value = serde_json::from_slice(&export.body).unwrap_or_default();
+    ensure!(
+        export.status == StatusCode::OK
+            && manifest["algorithm"] == "HMAC-SHA256"
+            && manifest["entries"].as_array().map(Vec::len) == Some(2)
+            && manifest["entries"][0]["key"] == "v1/a.tar"
+            && manifest["entries"][0]["size"] == 5
+            && manifest["entries"][0]["sha256"] == STANDARD.encode(Sha256::digest(b"alpha"))
+            && manifest["signature"]
+                .as_str()
+                .is_some_and(|s| !s.is_empty()),
+        "manifest {} {}",
+        export.status,
+        export.text()
+    );
+    let verify = |manifest: serde_json::Value| async move {
+        let resp = app
+            .send(
+                Request::builder()
+                    .method(Method::POST)
+                    .uri("/admin/buckets/releases/manifest/verify")
+                    .header("content-type", "application/json")
+                    .body(Body::from(manifest.to_string()))
+                    .unwrap(),
+            )
+            .await;
+        serde_json::from_slice::<serde_json::Value>(&resp.body)
+            .map_err(|_| format!("verify {} {}", resp.status, resp.text()))
+    };
+
+    let report = verify(manifest.clone()).await?;
+    ensure!(
+        report["ok"] == true && report["signature_valid"] == true && report["matched"] == 2,
+        "unchanged bucket {}",
+        report
+    );
+    let mut forged = manifest.clone();
+    forged["entries"][1]["size"] = 6.into();
+    let report = verify(forged).await?;
+    ensure!(
+        report["ok"] == false && report["signature_valid"] == false,
+        "edited manifest {}",
+        report
+    );
+
+    app.put_object("releases", "v1/a.tar", b"ALPHA").await;
+    app.call(Method::DELETE, "/releases/v1/b.tar", Body::empty())
+        .await;
+    app.put_object("releases", "v1/c.tar", b"charlie").await;
+    let report = verify(manifest).await?;
+    ensure!(
+        report["ok"] == false
+            && report["signature_valid"] == true
+            && report["mismatched"][0]["key"] == "v1/a.tar"
+            && report["mismatched"][0]["reason"]
+                .as_str()
+                .is_some_and(|r| r.contains("SHA-256"))
+            && report["missing"] == serde_json::json!(["v1/b.tar"])
+            && report["unexpected"] == serde_json::json!(["v1/c.tar"]),
+        "changed bucket {}",
+        report
+    );
+    Ok(())
+}
+
+async fn take_snapshot(app: &TestApp, bucket: &str) -> Result<String, String> {
+    let resp = app
+        .call(
+            Method::POST,
+            &format!("/admin/buckets/{}/snapshots", bucket),
+            Body::empty(),
+        )
+        .await;
+    ensure!(
+        resp.status == StatusCode::CREATED,
+        "snapshot {} {}",
+        resp.status,
+        resp.text()
+    );
+    let snapshot: serde_json::Value =
+        serde_json::from_slice(&resp.body).map_err(|e| e.to_string())?;
+    Ok(snapshot["id"].as_str().unwrap_or_default().to_string())
+}
+
+async fn restore(app: &TestApp, bucket: &str, id: &str) -> Result<serde_json::Value, String> {
+    let resp = app
+        .call(
+            Method::POST,
+            &format!("/admin/buckets/{}/snapshots/{}/restore", bucket, id),
+            Body::empty(),
+        )
+        .await;
+    ensure!(
+        resp.status == StatusCode::OK,
+        "restore {} {}",
+        resp.status,
+        resp.text()
+    );
+    serde_json::from_slice(&resp.body).map_err(|e| e.to_string())
+}
+
+/// The body of each key, `None` when it is not found.
+async fn object_bodies(app: &TestApp, bucket: &str, keys: &[&str]) -> Vec<Option<String>> {
+    let mut bodies = Vec::new();
+    for key in keys {
+        let resp = app
+            .call(Method::GET, &format!("/{}/{}", bucket, key), Body::empty())
+            .await;
+        bodies.push((resp.status == StatusCode::OK).then(|| resp.text()));
+    }
+    bodies
+}
+
+async fn snapshot_restore(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    app.put_object("photos", "a.txt", b"v1").await;
+    app.put_object("photos", "dir/b.txt", b"b").await;
+    let id = take_snapshot(app, "photos").await?;
+
+    app.put_object("photos", "a.txt", b"v2").await;
+    app.call(Method::DELETE, "/photos/dir/b.txt", Body::empty())
+        .await;
+    app.put_object("photos", "c.txt", b"new").await;
+    app.put_object("photos", "dir/d.txt", b"new").await;
+
+    let summary = restore(app, "photos", &id).await?;
+    ensure!(
+        summary["snapshot_id"] == id.as_str()
+            && summary["restored"] == 2
+            && summary["removed"] == 2,
+        "summary {}",
+        summary
+    );
+    let bodies = object_bodies(app, "photos", &["a.txt", "dir/b.txt", "c.txt", "dir/d.txt"]).await;
+    ensure!(
+        bodies == [Some("v1".to_string()), Some("b".to_string()), None, None],
+        "after restore {:?}",
+        bodies
+    );
+
+    // The snapshot is untouched by writes after the restore.
+    app.put_object("photos", "a.txt", b"v3").await;
+    restore(app, "photos", &id).await?;
+    let bodies = object_bodies(app, "photos", &["a.txt"]).await;
+    ensure!(
+        bodies == [Some("v1".to_string())],
+        "second restore {:?}",
+        bodies
+    );
+    Ok(())
+}
+
+async fn snapshot_pre_restore_undo(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    app.put_object("photos", "a.txt", b"v1").await;
+    let id = take_snapshot(app, "photos").await?;
+    app.put_object("photos", "a.txt", b"v2").await;
+    app.put_object("photos", "b.txt", b"b").await;
+
+    let summary = restore(app, "photos", &id).await?;
+    let pre_restore = summary["pre_restore_snapshot_id"]
+        .as_str()
+        .unwrap_or_default()
+        .to_string();
+    let listed = app
+        .call(
+            Method::GET,
+            "/admin/buckets/photos/snapshots",
+            Body::empty(),
+        )
+        .await;
+    let listed: serde_json::Value =
+        serde_json::from_slice(&listed.body).map_err(|e| e.to_string())?;
+    let entry = listed["snapshots"]
+        .as_array()
+        .and_then(|snapshots| snapshots.iter().find(|s| s["id"] == pre_restore.as_str()))
+        .cloned()
+        .unwrap_or_default();
+    ensure!(
+        entry["trigger"] == "pre-restore" && entry["object_count"] == 2,
+        "pre-restore snapshot {} in {}",
+        pre_restore,
+        listed
+    );
+
+    restore(app, "photos", &pre_restore).await?;
+    let bodies = object_bodies(app, "photos", &["a.txt", "b.txt"]).await;
+    ensure!(
+        bodies == [Some("v2".to_string()), Some("b".to_string())],
+        "after undo {:?}",
+        bodies
+    );
+    Ok(())
+}
+
+async fn snapshot_retention(app: &TestApp) -> CaseResult {
+    use chrono::{TimeZone, Utc};
+
+    app.create_bucket("photos").await;
+    app.put_object("photos", "a.txt", b"a").await;
+    let manual = take_snapshot(app, "photos").await?;
+    sqlx::query("UPDATE bucket_snapshots SET created_at = ?")
+        .bind(Utc.with_ymd_and_hms(2024, 1, 1, 0, 0, 0).unwrap())
+        .execute(&*app.service.db)
+        .await
+        .map_err(|e| e.to_string())?;
+
+    // Generous counts keep every snapshot until the real policy is set.
+    app.service
+        .set_snapshot_policy("photos", "* * * * *", 100, 100)
+        .await
+        .map_err(|e| e.to_string())?;
+    let scheduled = || async {
+        sqlx::query("UPDATE snapshot_policies SET created_at = ?, last_run_at = NULL")
+            .bind(Utc::now() - chrono::Duration::minutes(2))
+            .execute(&*app.service.db)
+            .await
+            .map_err(|e| e.to_string())?;
+        let taken = app
+            .service
+            .run_due_snapshots()
+            .await
+            .map_err(|e| e.to_string())?;
+        ensure!(taken == 1, "scheduled run took {}", taken);
+        sqlx::query_scalar::<_, uuid::Uuid>(
+            "SELECT id FROM bucket_snapshots WHERE trigger = 'scheduled'
+             ORDER BY created_at DESC LIMIT 1",
+        )
+        .fetch_one(&*app.service.db)
+        .await
+        .map_err(|e| e.to_string())
+    };
+    let taken_at = |month: u32, day: u32, hour: u32| async move {
+        let id = scheduled().await?;
+        sqlx::query("UPDATE bucket_snapshots SET created_at = ? WHERE id = ?")
+            .bind(Utc.with_ymd_and_hms(2024, month, day, hour, 30, 0).unwrap())
+            .bind(id)
+            .execute(&*app.service.db)
+            .await
+            .map_err(|e| e.to_string())?;
+        Ok::<_, String>(id)
+    };
+    // 2024-03-11 is a Monday: ISO week 11 starts at its midnight.
+    let monday = taken_at(3, 11, 0).await?;
+    let sunday_late = taken_at(3, 10, 23).await?;
+    let sunday_early = taken_at(3, 10, 8).await?;
+    let saturday = taken_at(3, 9, 12).await?;
+    let previous_week = taken_at(3, 3, 12).await?;
+    let two_weeks_back = taken_at(2, 25, 12).await?;
+
+    // Days: today, 11 and 10 March. Weeks: this one and weeks 11, 10 and 9.
+    app.service
+        .set_snapshot_policy("photos", "* * * * *", 3, 4)
+        .await
+        .map_err(|e| e.to_string())?;
+    let today = scheduled().await?;
+
+    let kept: std::collections::HashSet<uuid::Uuid> = app
+        .service
+        .list_snapshots("photos")
+        .await
+        .map_err(|e| e.to_string())?
+        .into_iter()
+        .map(|snapshot| snapshot.id)
+        .collect();
+    let manual: uuid::Uuid = manual.parse().map_err(|_| "manual id".to_string())?;
+    for (name, id, expected) in [
+        ("today", today, true),
+        ("monday", monday, true),
+        ("sunday late", sunday_late, true),
+        ("sunday early", sunday_early, false),
+        ("saturday", saturday, false),
+        ("previous week", previous_week, true),
+        ("two weeks back", two_weeks_back, false),
+        ("manual", manual, true),
+    ] {
+        ensure!(
+            kept.contains(&id) == expected,
+            "{} snapshot kept={} (expected {})",
+            name,
+            kept.contains(&id),
+            expected
+        );
+    }
+    let pruned_dir = app
+        .service
+        .base_path
+        .join(format!(".snapshots/photos/{}", saturday));
+    ensure!(!pruned_dir.exists(), "pruned payloads left behind");
+    Ok(())
+}
+
+async fn snapshot_restore_read_only(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    app.put_object("photos", "a.txt", b"v1").await;
+    let id = take_snapshot(app, "photos").await?;
+    app.put_object("photos", "a.txt", b"v2").await;
+    let patched = app
+        .send(
+            Request::builder()
+                .method(Method::PATCH)
+                .uri("/admin/buckets/photos")
+                .header("content-type", "application/json")
+                .body(Body::from(r#"{"read_only":true}"#))
+                .unwrap(),
+        )
+        .await;
+    ensure!(patched.status == StatusCode::OK, "patch {}", patched.status);
+
+    let resp = app
+        .call(
+            Method::POST,
+            &format!("/admin/buckets/photos/snapshots/{}/restore", id),
+            Body::empty(),
+        )
+        .await;
+    ensure!(
+        resp.status == StatusCode::FORBIDDEN,
+        "restore {} {}",
+        resp.status,
+        resp.text()
+    );
+    let bodies = object_bodies(app, "photos", &["a.txt"]).await;
+    ensure!(
+        bodies == [Some("v2".to_string())],
+        "after refusal {:?}",
+        bodies
+    );
+    let snapshots = app
+        .service
+        .list_snapshots("photos")
+        .await
+        .map_err(|e| e.to_string())?;
+    ensure!(
+        snapshots.len() == 1,
+        "refused restore took {} snapshots",
+        snapshots.len()
+    );
+    Ok(())
+}
+
+async fn set_encrypt_metadata(app: &TestApp, bucket: &str, enabled: bool) -> common::TestResponse {
+    app.send(
+        Request::builder()
+            .method(Method::PATCH)
+            .uri(format!("/admin/buckets/{}", bucket))
+            .header("content-type", "application/json")
+            .body(Body::from(format!(r#"{{"encrypt_metadata":{}}}"#, enabled)))
+            .unwrap(),
+    )
+    .await
+}
+
+/// Metadata and tag rows whose value is stored in the clear.
+async fn plaintext_values(app: &TestApp) -> Result<Vec<String>, String> {
+    sqlx::query_scalar(
+        "SELECT value FROM object_metadata WHERE value_hash IS NULL
+         UNION ALL SELECT tag_value FROM object_tags WHERE value_hash IS NULL",
+    )
+    .fetch_all(&*app.service.db)
+    .await
+    .map_err(|e| e.to_string())
+}
+
+async fn metadata_encryption_round_trip(_app: &TestApp) -> CaseResult {
+    let app = TestApp::with_service(|mut service| {
+        service.options.metadata_key = Some("metadata-master-secret".parse().unwrap());
+        service
+    })
+    .await;
+    let app = &app;
+    app.create_bucket("vault").await;
+    let put = |key: &'static str, pin: &'static str| {
+        app.send(
+            Request::builder()
+                .method(Method::PUT)
+                .uri(format!("/vault/{}", key))
+                .header("x-amz-meta-pin", pin)
+                .header("x-amz-tagging", "class=secret")
+                .body(Body::from("x"))
+                .unwrap(),
+        )
+    };
+    put("old.txt", "1234").await;
+
+    let patched = set_encrypt_metadata(app, "vault", true).await;
+    ensure!(
+        patched.status == StatusCode::OK && patched.text().contains(r#""encrypt_metadata":true"#),
+        "enable {} {}",
+        patched.status,
+        patched.text()
+    );
+    put("new.txt", "5678").await;
+    let initiated = app
+        .send(
+            Request::builder()
+                .method(Method::POST)
+                .uri("/vault/big.bin?uploads")
+                .header("x-amz-meta-pin", "9999")
+                .header("x-amz-tagging", "class=secret")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+    let upload_id = extract_all(&initiated.text(), "UploadId")
+        .into_iter()
+        .next()
+        .ok_or_else(|| format!("no UploadId in {}", initiated.text()))?;
+    let pending: String = sqlx::query_scalar("SELECT user_metadata FROM multipart_uploads")
+        .fetch_one(&*app.service.db)
+        .await
+        .map_err(|e| e.to_string())?;
+    ensure!(
+        pending.starts_with("sealed:") && !pending.contains("9999"),
+        "pending upload metadata {}",
+        pending
+    );
+    let etag = put_part(app, "/vault/big.bin", &upload_id, 1, b"big".to_vec()).await?;
+    let completed = app
+        .call(
+            Method::POST,
+            &format!("/vault/big.bin?uploadId={}", upload_id),
+            Body::from(complete_body(&[(1, &etag)])),
+        )
+        .await;
+    ensure!(
+        completed.status == StatusCode::OK,
+        "complete {}",
+        completed.status
+    );
+
+    let plaintext = plaintext_values(app).await?;
+    ensure!(plaintext.is_empty(), "stored in the clear: {:?}", plaintext);
+    for (key, pin) in [
+        ("old.txt", "1234"),
+        ("new.txt", "5678"),
+        ("big.bin", "9999"),
+    ] {
+        let head = app
+            .call(Method::HEAD, &format!("/vault/{}", key), Body::empty())
+            .await;
+        ensure!(
+            head.header("x-amz-meta-pin") == Some(pin),
+            "{} pin {:?}",
+            key,
+            head.header("x-amz-meta-pin")
+        );
+        let tags = app
+            .call(
+                Method::GET,
+                &format!("/vault/{}?tagging", key),
+                Body::empty(),
+            )
+            .await;
+        ensure!(
+            tags.text().contains("<Value>secret</Value>"),
+            "{} tags {}",
+            key,
+            tags.text()
+        );
+    }
+
+    // Tag filters match sealed values through their hash.
+    backdate(app, "UPDATE objects SET last_modified = ?", 40).await;
+    let config = concat!(
+        "<LifecycleConfiguration><Rule><ID>secret</ID>",
+        "<Filter><Tag><Key>class</Key><Value>secret</Value></Tag></Filter>",
+        "<Status>Enabled</Status><Expiration><Days>30</Days></Expiration></Rule>",
+        "</LifecycleConfiguration>"
+    );
+    let dry_run = app
+        .call(Method::POST, "/vault?lifecycle-dry-run", Body::from(config))
+        .await;
+    let report: serde_json::Value =
+        serde_json::from_slice(&dry_run.body).map_err(|e| e.to_string())?;
+    ensure!(
+        report["rules"][0]["expire"]["count"] == 3,
+        "dry run {}",
+        report
+    );
+
+    // Sealed values are bound to their row.
+    sqlx::query(
+        "UPDATE object_metadata SET value =
+             (SELECT value FROM object_metadata WHERE key = 'old.txt')
+         WHERE key = 'new.txt'",
+    )
+    .execute(&*app.service.db)
+    .await
+    .map_err(|e| e.to_string())?;
+    let moved = app
+        .call(Method::HEAD, "/vault/new.txt", Body::empty())
+        .await;
+    ensure!(
+        moved.status == StatusCode::INTERNAL_SERVER_ERROR,
+        "moved ciphertext {}",
+        moved.status
+    );
+    put("new.txt", "5678").await;
+
+    let disabled = set_encrypt_metadata(app, "vault", false).await;
+    ensure!(
+        disabled.status == StatusCode::OK,
+        "disable {}",
+        disabled.status
+    );
+    let mut plaintext = plaintext_values(app).await?;
+    plaintext.sort();
+    ensure!(
+        plaintext == ["1234", "5678", "9999", "secret", "secret", "secret"],
+        "after disabling {:?}",
+        plaintext
+    );
+    Ok(())
+}
+
+async fn metadata_encryption_needs_key(app: &TestApp) -> CaseResult {
+    app.create_bucket("vault").await;
+    let refused = set_encrypt_metadata(app, "vault", true).await;
+    ensure!(
+        refused.status == StatusCode::CONFLICT,
+        "enable without a key {} {}",
+        refused.status,
+        refused.text()
+    );
+    app.service
+        .check_metadata_key()
+        .await
+        .map_err(|e| e.to_string())?;
+    sqlx::query("UPDATE buckets SET encrypt_metadata = 1")
+        .execute(&*app.service.db)
+        .await
+        .map_err(|e| e.to_string())?;
+    ensure!(
+        app.service.check_metadata_key().await.is_err(),
+        "started without the key of an encrypted bucket"
+    );
+    Ok(())
+}
+
+async fn bucket_template_create(_app: &TestApp) -> CaseResult {
+    use object_store::services::bucket_template::BucketTemplates;
+
+    ensure!(
+        BucketTemplates::from_json(r#"{"t": {"quota_bytes": 10}}"#).is_err(),
+        "unknown template fields accepted"
+    );
+    ensure!(
+        BucketTemplates::from_json(r#"{"t": {"object_lock": {"default_retention": null}}}"#)
+            .is_err(),
+        "Object Lock without versioning accepted"
+    );
+    let templates = BucketTemplates::from_json(
+        r#"{"compliance": {
+            "versioning": true,
+            "object_lock": {"default_retention": {"mode": "COMPLIANCE", "days": 1}},
+            "lifecycle": [{"id": "tmp", "enabled": true, "prefix": "tmp/", "expiration_days": 7}],
+            "cache_control": "max-age=60",
+            "default_acl": "private"
+        }}"#,
+    )
+    .map_err(|e| e.to_string())?;
+    let app = TestApp::with_service(|mut service| {
+        service.options.bucket_templates = templates;
+        service
+    })
+    .await;
+
+    let create = |bucket: &'static str, template: &'static str| {
+        Request::builder()
+            .method(Method::PUT)
+            .uri(format!("/{}", bucket))
+            .header("content-type", "application/json")
+            .body(Body::from(format!(r#"{{"Template": "{}"}}"#, template)))
+            .unwrap()
+    };
+    let resp = app.send(create("audit", "compliance")).await;
+    ensure!(resp.status == StatusCode::OK, "create {}", resp.text());
+    let versioning = app
+        .call(Method::GET, "/audit?versioning", Body::empty())
+        .await
+        .text();
+    ensure!(versioning.contains("Enabled"), "versioning {}", versioning);
+    let lifecycle = app
+        .call(Method::GET, "/audit?lifecycle", Body::empty())
+        .await
+        .text();
+    ensure!(
+        lifecycle.contains("<ID>tmp</ID>"),
+        "lifecycle {}",
+        lifecycle
+    );
+    let lock = app
+        .call(Method::GET, "/audit?object-lock", Body::empty())
+        .await
+        .text();
+    ensure!(lock.contains("COMPLIANCE"), "object lock {}", lock);
+    let settings: serde_json::Value = serde_json::from_slice(
+        &app.call(Method::GET, "/admin/buckets/audit", Body::empty())
+            .await
+            .body,
+    )
+    .map_err(|e| e.to_string())?;
+    ensure!(
+        settings["cache_control"] == "max-age=60" && settings["default_acl"] == "private",
+        "settings {}",
+        settings
+    );
+
+    let unknown = app.send(create("other", "missing")).await;
+    ensure!(
+        unknown.status == StatusCode::BAD_REQUEST,
+        "unknown template {}",
+        unknown.status
+    );
+    let head = app.call(Method::HEAD, "/other", Body::empty()).await;
+    ensure!(
+        head.status == StatusCode::NOT_FOUND,
+        "bucket created without its template: {}",
+        head.status
+    );
+    let listed = app
+        .call(Method::GET, "/admin/bucket-templates", Body::empty())
+        .await
+        .text();
+    ensure!(listed.contains("\"compliance\""), "templates {}", listed);
+    Ok(())
+}
+
+async fn s3_headers_rejected(app: &TestApp) -> CaseResult {
+    use base64::{Engine as _, engine::general_purpose::STANDARD};
+
+    app.create_bucket("photos").await;
+    let key = [7u8; 32];
+    let key_b64 = STANDARD.encode(key);
+    type HeaderCase<'a> = (&'a str, Vec<(&'a str, &'a [u8])>, StatusCode);
+    let cases: Vec<HeaderCase> = vec![
+        (
+            "copy source without key",
+            vec![("x-amz-copy-source", b"photos")],
+            StatusCode::BAD_REQUEST,
+        ),
+        (
+            "unknown encryption",
+            vec![("x-amz-server-side-encryption", b"DES")],
+            StatusCode::BAD_REQUEST,
+        ),
+        (
+            "partial SSE-C",
+            vec![("x-amz-server-side-encryption-customer-algorithm", b"AES256")],
+            StatusCode::BAD_REQUEST,
+        ),
+        (
+            "SSE-C key MD5 mismatch",
+            vec![
+                ("x-amz-server-side-encryption-customer-algorithm", b"AES256"),
+                (
+                    "x-amz-server-side-encryption-customer-key",
+                    key_b64.as_bytes(),
+                ),
+                (
+                    "x-amz-server-side-encryption-customer-key-MD5",
+                    b"AAAAAAAAAAAAAAAAAAAAAA==",
+                ),
+            ],
+            StatusCode::BAD_REQUEST,
+        ),
+        (
+            "non-ASCII tagging",
+            vec![("x-amz-tagging", b"team=\xe9")],
+            StatusCode::BAD_REQUEST,
+        ),
+        (
+            "non-ASCII If-None-Match",
+            vec![("if-none-match", b"\"\xff\"")],
+            StatusCode::BAD_REQUEST,
+        ),
+    ];
+    for (name, headers, expected) in cases {
+        let mut request = Request::builder().method(Method::PUT).uri("/photos/a");
+        for (header, value) in headers {
+            request = request.header(
+                header,
+                axum::http::HeaderValue::from_bytes(value).map_err(|e| e.to_string())?,
+            );
+        }
+        let resp = app.send(request.body(Body::from("data")).unwrap()).await;
+        ensure!(
+            resp.status == expected,
+            "{}: {} {}",
+            name,
+            resp.status,
+            resp.text()
+        );
+    }
+    let get = app.call(Method::GET, "/photos/a", Body::empty()).await;
+    ensure!(
+        get.status == StatusCode::NOT_FOUND,
+        "rejected uploads stored an object: {}",
+        get.status
+    );
+    Ok(())
+}
+
+/// Sign `request` with SigV4 as `access_key` at `now`, path-style against
+/// host `localhost`, with an unsigned payload.
+fn sigv4_signed(
+    mut request: Request<Body>,
+    access_key: &str,
+    secret: &str,
+    now: chrono::DateTime<chrono::Utc>,
+) -> Request<Body> {
+    use hmac::{Hmac, Mac};
+    use sha2::{Digest, Sha256};
+
+    let hmac = |key: &[u8], data: &str| {
+        let mut mac = Hmac::<Sha256>::new_from_slice(key).unwrap();
+        mac.update(data.as_bytes());
+        mac.finalize().into_bytes().to_vec()
+    };
+    let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let scope = format!("{}/us-east-1/s3/aws4_request", &amz_date[..8]);
+    let headers = request.headers_mut();
+    headers.insert("host", "localhost".parse().unwrap());
+    headers.insert("x-amz-date", amz_date.parse().unwrap());
+    headers.insert("x-amz-content-sha256", "UNSIGNED-PAYLOAD".parse().unwrap());
+    let mut query: Vec<&str> = request
+        .uri()
+        .query()
+        .unwrap_or_default()
+        .split('&')
+        .filter(|pair| !pair.is_empty())
+        .collect();
+    query.sort();
+    let query: Vec<String> = query
+        .iter()
+        .map(|pair| match pair.split_once('=') {
+            Some(_) => pair.to_string(),
+            None => format!("{}=", pair),
+        })
+        .collect();
+    let canonical_request = format!(
+        "{}\n{}\n{}\nhost:localhost\nx-amz-content-sha256:UNSIGNED-PAYLOAD\nx-amz-date:{}\n\nhost;x-amz-content-sha256;x-amz-date\nUNSIGNED-PAYLOAD",
+        request.method(),
+        request.uri().path(),
+        query.join("&"),
+        amz_date
+    );
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{:x}",
+        amz_date,
+        scope,
+        Sha256::digest(canonical_request.as_bytes())
+    );
+    let mut key = format!("AWS4{}", secret).into_bytes();
+    for part in scope.split('/') {
+        key = hmac(&key, part);
+    }
+    let signature: String = hmac(&key, &string_to_sign)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    request.headers_mut().insert(
+        "authorization",
+        format!(
+            "AWS4-HMAC-SHA256 Credential={}/{}, SignedHeaders=host;x-amz-content-sha256;x-amz-date, Signature={}",
+            access_key, scope, signature
+        )
+        .parse()
+        .unwrap(),
+    );
+    request
+}
+
+/// A SigV4 presigned `GET` URI of `path` (no query) for host `localhost`.
+fn sigv4_presigned(
+    path: &str,
+    access_key: &str,
+    secret: &str,
+    now: chrono::DateTime<chrono::Utc>,
+    expires_secs: u64,
+) -> String {
+    use hmac::{Hmac, Mac};
+    use sha2::{Digest, Sha256};
+
+    let hmac = |key: &[u8], data: &str| {
+        let mut mac = Hmac::<Sha256>::new_from_slice(key).unwrap();
+        mac.update(data.as_bytes());
+        mac.finalize().into_bytes().to_vec()
+    };
+    let amz_date = now.format("%Y%m%dT%H%M%SZ").to_string();
+    let scope = format!("{}/us-east-1/s3/aws4_request", &amz_date[..8]);
+    let query = format!(
+        "X-Amz-Algorithm=AWS4-HMAC-SHA256&X-Amz-Credential={}%2F{}&X-Amz-Date={}&X-Amz-Expires={}&X-Amz-SignedHeaders=host",
+        access_key,
+        scope.replace('/', "%2F"),
+        amz_date,
+        expires_secs
+    );
+    let canonical_request = format!(
+        "GET\n{}\n{}\nhost:localhost\n\nhost\nUNSIGNED-PAYLOAD",
+        path, query
+    );
+    let string_to_sign = format!(
+        "AWS4-HMAC-SHA256\n{}\n{}\n{:x}",
+        amz_date,
+        scope,
+        Sha256::digest(canonical_request.as_bytes())
+    );
+    let mut key = format!("AWS4{}", secret).into_bytes();
+    for part in scope.split('/') {
+        key = hmac(&key, part);
+    }
+    let signature: String = hmac(&key, &string_to_sign)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect();
+    format!("{}?{}&X-Amz-Signature={}", path, query, signature)
+}
+
+/// A webhook authorizer allowing the principals in `allowed`, recording the
+/// contexts it is asked about. `status` other than 200 is answered as is;
+/// 299 answers a body that is not a decision.
+async fn denial_log_records_refusals(app: &TestApp) -> CaseResult {
+    use object_store::middleware::{
+        denial_log::{DenialLog, record_denials},
+        feature_flags::{ApiGroup, FeatureFlags, enforce_feature_flags},
+    };
+    use std::sync::{Arc, Mutex, atomic::AtomicU16};
+
+    let disabled = app.call(Method::GET, "/admin/denials", Body::empty()).await;
+    ensure!(
+        disabled.status == StatusCode::NOT_FOUND,
+        "denials without logging {}",
+        disabled.status
+    );
+
+    app.create_bucket("photos").await;
+    app.put_object("photos", "cat.jpg", b"meow").await;
+    let allowed = Arc::new(Mutex::new(vec!["anonymous".to_string()]));
+    let (endpoint, _received) = spawn_fake_authorizer(allowed, Arc::new(AtomicU16::new(200))).await;
+    let router = authorized_router(app, &endpoint, std::time::Duration::ZERO)
+        .layer(axum::middleware::from_fn_with_state(
+            FeatureFlags::new([ApiGroup::ObjectDelete]),
+            enforce_feature_flags,
+        ))
+        .layer(axum::middleware::from_fn_with_state(
+            DenialLog::default(),
+            record_denials,
+        ));
+
+    let delete = app
+        .send_via(
+            router.clone(),
+            Request::builder()
+                .method(Method::DELETE)
+                .uri("/photos/cat.jpg")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+    ensure!(
+        delete.status == StatusCode::FORBIDDEN,
+        "disabled delete {}",
+        delete.status
+    );
+    let read = app
+        .send_via(
+            router.clone(),
+            sigv4_signed(
+                Request::builder()
+                    .uri("/photos/cat.jpg")
+                    .body(Body::empty())
+                    .unwrap(),
+                "writer",
+                "writer-secret",
+                chrono::Utc::now(),
+            ),
+        )
+        .await;
+    ensure!(
+        read.status == StatusCode::FORBIDDEN,
+        "unauthorized read {}",
+        read.status
+    );
+    let allowed = app
+        .send_via(
+            router.clone(),
+            Request::builder()
+                .uri("/photos/cat.jpg")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+    ensure!(
+        allowed.status == StatusCode::OK,
+        "allowed read {}",
+        allowed.status
+    );
+
+    let report = app
+        .send_via(
+            router,
+            Request::builder()
+                .uri("/admin/denials")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+    let report: serde_json::Value = serde_json::from_slice(&report.body).unwrap_or_default();
+    ensure!(
+        report["total"] == 2
+            && report["counts"]["feature-flag"]["object-delete"] == 1
+            && report["counts"]["authorizer"]["object-read"] == 1,
+        "counts {}",
+        report
+    );
+    let recent = &report["recent"];
+    ensure!(
+        recent[0]["source"] == "authorizer"
+            && recent[0]["principal"] == "writer"
+            && recent[0]["method"] == "GET"
+            && recent[0]["bucket"] == "photos"
+            && recent[0]["key"] == "cat.jpg"
+            && recent[0]["status"] == 403
+            && recent[0]["reason"]
+                .as_str()
+                .is_some_and(|r| r.contains("no grant for writer")),
+        "authorizer denial {}",
+        recent[0]
+    );
+    ensure!(
+        recent[1]["source"] == "feature-flag"
+            && recent[1]["principal"] == "anonymous"
+            && recent[1]["method"] == "DELETE"
+            && recent[1]["reason"]
+                .as_str()
+                .is_some_and(|r| r.contains("object-delete")),
+        "feature-flag denial {}",
+        recent[1]
+    );
+    Ok(())
+}
+
+async fn spawn_fake_authorizer(
+    allowed: std::sync::Arc<std::sync::Mutex<Vec<String>>>,
+    status: std::sync::Arc<std::sync::atomic::AtomicU16>,
+) -> (
+    String,
+    std::sync::Arc<std::sync::Mutex<Vec<serde_json::Value>>>,
+) {
+    use axum::response::IntoResponse;
+    use std::sync::{Arc, Mutex, atomic::Ordering};
+
+    let received: Arc<Mutex<Vec<serde_json::Value>>> = Arc::default();
+    let sink = received.clone();
+    let router = axum::Router::new().fallback(move |body: String| {
+        let (sink, allowed) = (sink.clone(), allowed.clone());
+        let status = status.load(Ordering::SeqCst);
+        async move {
+            let context: serde_json::Value = serde_json::from_str(&body).unwrap();
+            sink.lock().unwrap().push(context.clone());
+            match status {
+                200 => {
+                    let principal = context["principal"].as_str().unwrap_or_default();
+                    let allow = allowed.lock().unwrap().iter().any(|p| p == principal);
+                    axum::Json(serde_json::json!({
+                        "allow": allow,
+                        "reason": format!("no grant for {}", principal),
+                    }))
+                    .into_response()
+                }
+                299 => "maybe".into_response(),
+                other => StatusCode::from_u16(other).unwrap().into_response(),
+            }
+        }
+    });
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, router).await });
+    (format!("http:http://{}/check", addr), received)
+}
+
+fn authorized_router(app: &TestApp, endpoint: &str, ttl: std::time::Duration) -> axum::Router {
+    use object_store::{
+        middleware::authorizer::{Authorizer, enforce_authorization},
+        services::{aws_chunked::AccessKeys, outbound::OutboundHttp},
+    };
+
+    let authorizer = Authorizer::new(
+        endpoint.parse().unwrap(),
+        &OutboundHttp::default(),
+        std::time::Duration::from_secs(2),
+        ttl,
+    )
+    .unwrap()
+    .with_access_keys(AccessKeys::new(["writer:writer-secret".parse().unwrap()]))
+    .with_signature_debug(Some("signature-debug-token"));
+    app.router
+        .clone()
+        .layer(axum::middleware::from_fn_with_state(
+            authorizer,
+            enforce_authorization,
+        ))
+}
+
+/// The app behind an authorizer allowing `alice`, accepting session tokens
+/// signed with the returned key.
+async fn session_router(
+    app: &TestApp,
+) -> (axum::Router, object_store::services::session::SessionKey) {
+    use object_store::{
+        middleware::authorizer::{Authorizer, enforce_authorization},
+        services::outbound::OutboundHttp,
+    };
+    use std::sync::{Arc, Mutex, atomic::AtomicU16};
+
+    let allowed = Arc::new(Mutex::new(vec!["alice".to_string()]));
+    let (endpoint, _received) = spawn_fake_authorizer(allowed, Arc::new(AtomicU16::new(200))).await;
+    let key: object_store::services::session::SessionKey =
+        "session-signing-secret".parse().unwrap();
+    let authorizer = Authorizer::new(
+        endpoint.parse().unwrap(),
+        &OutboundHttp::default(),
+        std::time::Duration::from_secs(2),
+        std::time::Duration::ZERO,
+    )
+    .unwrap()
+    .with_session_key(Some(key.clone()));
+    let router = app
+        .router
+        .clone()
+        .layer(axum::middleware::from_fn_with_state(
+            authorizer,
+            enforce_authorization,
+        ));
+    (router, key)
+}
+
+/// A policy for `alice` on `photos`, under `prefix`, valid for an hour.
+fn session_policy(prefix: Option<&str>) -> object_store::services::session::SessionPolicy {
+    object_store::services::session::SessionPolicy {
+        principal: "alice".into(),
+        bucket: "photos".into(),
+        prefix: prefix.map(str::to_string),
+        actions: [
+            "bucket-list",
+            "object-read",
+            "object-write",
+            "object-delete",
+        ]
+        .map(String::from)
+        .to_vec(),
+        expires_at: chrono::Utc::now() + chrono::Duration::hours(1),
+    }
+}
+
+async fn send_with_session(
+    app: &TestApp,
+    router: &axum::Router,
+    token: &str,
+    method: Method,
+    uri: &str,
+    copy_source: Option<&str>,
+) -> common::TestResponse {
+    let mut builder = Request::builder()
+        .method(method)
+        .uri(uri)
+        .header("x-amz-security-token", token);
+    if let Some(source) = copy_source {
+        builder = builder.header("x-amz-copy-source", source);
+    }
+    app.send_via(router.clone(), builder.body(Body::from("x")).unwrap())
+        .await
+}
+
+async fn session_token_verification(app: &TestApp) -> CaseResult {
+    use base64::{Engine as _, engine::general_purpose::URL_SAFE_NO_PAD};
+
+    app.create_bucket("photos").await;
+    let (router, key) = session_router(app).await;
+    let put = |token: String| {
+        let router = router.clone();
+        async move {
+            send_with_session(app, &router, &token, Method::PUT, "/photos/a.jpg", None)
+                .await
+                .status
+        }
+    };
+
+    let policy = session_policy(None);
+    let status = put(key.issue(&policy)).await;
+    ensure!(status == StatusCode::OK, "valid token {}", status);
+
+    let other: object_store::services::session::SessionKey =
+        "another-signing-secret".parse().unwrap();
+    let status = put(other.issue(&policy)).await;
+    ensure!(status == StatusCode::FORBIDDEN, "foreign key {}", status);
+
+    // Widen the signed policy to another bucket, keeping the signature.
+    let token = key.issue(&policy);
+    let (_, signature) = token.split_once('.').unwrap();
+    let mut widened = policy.clone();
+    widened.bucket = "archive".into();
+    let forged = format!(
+        "{}.{}",
+        URL_SAFE_NO_PAD.encode(serde_json::to_vec(&widened).unwrap()),
+        signature
+    );
+    let status = put(forged).await;
+    ensure!(
+        status == StatusCode::FORBIDDEN,
+        "tampered policy {}",
+        status
+    );
+    let status = put("not-a-token".into()).await;
+    ensure!(
+        status == StatusCode::FORBIDDEN,
+        "malformed token {}",
+        status
+    );
+
+    let mut expired = policy.clone();
+    expired.expires_at = chrono::Utc::now() - chrono::Duration::seconds(1);
+    let status = put(key.issue(&expired)).await;
+    ensure!(status == StatusCode::FORBIDDEN, "expired token {}", status);
+
+    let mut read_only = policy;
+    read_only.actions = vec!["object-read".into()];
+    let status = put(key.issue(&read_only)).await;
+    ensure!(
+        status == StatusCode::FORBIDDEN,
+        "write with a read-only token {}",
+        status
+    );
+    Ok(())
+}
+
+async fn session_prefix_scope(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    app.put_object("photos", "alice/a.jpg", b"a").await;
+    app.put_object("photos", "bob/b.jpg", b"b").await;
+    let (router, key) = session_router(app).await;
+    let token = key.issue(&session_policy(Some("alice/")));
+    let send = |method: Method, uri: &'static str| {
+        let (router, token) = (router.clone(), token.clone());
+        async move { send_with_session(app, &router, &token, method, uri, None).await }
+    };
+
+    for (method, uri, allowed) in [
+        (Method::GET, "/photos/alice/a.jpg", true),
+        (Method::PUT, "/photos/alice/c.jpg", true),
+        (Method::GET, "/photos/bob/b.jpg", false),
+        (Method::PUT, "/photos/bob/c.jpg", false),
+        (Method::DELETE, "/photos/bob/b.jpg", false),
+        (Method::GET, "/photos?list-type=2&prefix=alice/", true),
+        (
+            Method::GET,
+            "/photos?prefix=alice/&marker=alice/a.jpg",
+            true,
+        ),
+        (Method::GET, "/photos?versions&prefix=alice/", true),
+        (Method::GET, "/photos?deleted=true&prefix=alice/", true),
+        (Method::GET, "/photos?list-partitions=2&prefix=alice/", true),
+        (Method::GET, "/photos?list-stream&prefix=alice/", true),
+        (Method::GET, "/photos?list-type=2", false),
+        (Method::GET, "/photos?list-type=2&prefix=bob/", false),
+        (Method::GET, "/photos?list-type=2&prefix=", false),
+        (Method::GET, "/photos?changes&prefix=alice/", true),
+        (Method::GET, "/photos?changes", false),
+        (Method::GET, "/photos?changes&since=0", false),
+        (Method::GET, "/photos?uploads&prefix=alice/", false),
+        (Method::GET, "/photos?lifecycle&prefix=alice/", false),
+        (
+            Method::GET,
+            "/photos?validate&key=bob/b.jpg&prefix=alice/",
+            false,
+        ),
+        (Method::POST, "/photos?delete", false),
+        (Method::POST, "/photos?lifecycle-dry-run", false),
+        (Method::DELETE, "/photos?prefix=bob/", false),
+    ] {
+        let resp = send(method.clone(), uri).await;
+        ensure!(
+            (resp.status != StatusCode::FORBIDDEN) == allowed,
+            "{} {}: {} {}",
+            method,
+            uri,
+            resp.status,
+            resp.text()
+        );
+    }
+
+    let listing = send(Method::GET, "/photos?list-type=2&prefix=alice/").await;
+    let keys = extract_all(&listing.text(), "Key");
+    ensure!(
+        keys == ["alice/a.jpg", "alice/c.jpg"],
+        "scoped listing {:?}",
+        keys
+    );
+    let feed = send(Method::GET, "/photos?changes&prefix=alice/").await;
+    let feed: serde_json::Value = serde_json::from_slice(&feed.body).map_err(|e| e.to_string())?;
+    let changed: Vec<_> = feed["changes"]
+        .as_array()
+        .into_iter()
+        .flatten()
+        .filter_map(|change| change["key"].as_str())
+        .collect();
+    ensure!(
+        changed == ["alice/a.jpg", "alice/c.jpg"],
+        "scoped change feed {:?}",
+        changed
+    );
+    backdate(app, "UPDATE objects SET last_modified = ?", 40).await;
+    let config = concat!(
+        "<LifecycleConfiguration><Rule><ID>old</ID><Filter><Prefix></Prefix></Filter>",
+        "<Status>Enabled</Status><Expiration><Days>30</Days></Expiration></Rule>",
+        "</LifecycleConfiguration>"
+    );
+    let dry_run = app
+        .send_via(
+            router.clone(),
+            Request::builder()
+                .method(Method::POST)
+                .uri("/photos?lifecycle-dry-run&prefix=alice/")
+                .header("x-amz-security-token", &token)
+                .body(Body::from(config))
+                .unwrap(),
+        )
+        .await;
+    let report: serde_json::Value =
+        serde_json::from_slice(&dry_run.body).map_err(|e| e.to_string())?;
+    ensure!(
+        report["rules"][0]["expire"]["count"] == 2
+            && report["rules"][0]["expire"]["samples"]
+                == serde_json::json!(["alice/a.jpg", "alice/c.jpg"]),
+        "scoped dry run {} {}",
+        dry_run.status,
+        report
+    );
+    let deleted = send(Method::DELETE, "/photos?prefix=alice/").await;
+    ensure!(
+        deleted.status.is_success(),
+        "prefix delete in scope {}",
+        deleted.status
+    );
+    Ok(())
+}
+
+async fn session_copy_source(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    app.create_bucket("archive").await;
+    app.put_object("photos", "alice/a.jpg", b"a").await;
+    app.put_object("photos", "bob/b.jpg", b"b").await;
+    app.put_object("archive", "alice/old.jpg", b"o").await;
+    let (router, key) = session_router(app).await;
+    let token = key.issue(&session_policy(Some("alice/")));
+
+    for (source, allowed) in [
+        ("/photos/alice/a.jpg", true),
+        ("photos/alice/a.jpg?versionId=null", true),
+        ("/photos/bob/b.jpg", false),
+        ("/photos/%62ob/b.jpg", false),
+        ("/archive/alice/old.jpg", false),
+    ] {
+        let resp = send_with_session(
+            app,
+            &router,
+            &token,
+            Method::PUT,
+            "/photos/alice/copy.jpg",
+            Some(source),
+        )
+        .await;
+        ensure!(
+            (resp.status != StatusCode::FORBIDDEN) == allowed,
+            "copy from {}: {} {}",
+            source,
+            resp.status,
+            resp.text()
+        );
+    }
+    Ok(())
+}
+
+async fn authorizer_signature_debug(app: &TestApp) -> CaseResult {
+    use std::sync::{Arc, Mutex, atomic::AtomicU16};
+
+    let allowed = Arc::new(Mutex::new(vec!["writer".to_string()]));
+    let (endpoint, _received) = spawn_fake_authorizer(allowed, Arc::new(AtomicU16::new(200))).await;
+    let router = authorized_router(app, &endpoint, std::time::Duration::ZERO);
+    app.create_bucket("authz").await;
+    let now = chrono::Utc::now();
+    let send = |secret: &str, debug_token: Option<&str>| {
+        let mut builder = Request::builder()
+            .method(Method::GET)
+            .uri("/authz?list-type=2");
+        if let Some(token) = debug_token {
+            builder = builder.header("x-debug-signature", token);
+        }
+        let request = builder.body(Body::empty()).unwrap();
+        app.send_via(router.clone(), sigv4_signed(request, "writer", secret, now))
+    };
+
+    let listed = send("writer-secret", Some("signature-debug-token")).await;
+    ensure!(
+        listed.status == StatusCode::OK,
+        "matching signature with the debug token {} {}",
+        listed.status,
+        listed.text()
+    );
+
+    let mismatch = send("guessed-secret", Some("signature-debug-token")).await;
+    let body: serde_json::Value =
+        serde_json::from_slice(&mismatch.body).map_err(|e| e.to_string())?;
+    let canonical_request = body["canonical_request"].as_str().unwrap_or_default();
+    let string_to_sign = body["string_to_sign"].as_str().unwrap_or_default();
+    ensure!(
+        mismatch.status == StatusCode::FORBIDDEN && body["code"] == "SignatureDoesNotMatch",
+        "mismatch with the debug token {} {}",
+        mismatch.status,
+        body
+    );
+    ensure!(
+        canonical_request.starts_with("GET\n/authz\nlist-type=2\nhost:localhost\n")
+            && canonical_request.ends_with("\nUNSIGNED-PAYLOAD"),
+        "canonical request {:?}",
+        canonical_request
+    );
+    ensure!(
+        string_to_sign.starts_with(&format!(
+            "AWS4-HMAC-SHA256\n{}\n",
+            now.format("%Y%m%dT%H%M%SZ")
+        )),
+        "string to sign {:?}",
+        string_to_sign
+    );
+
+    for token in [None, Some("not-the-debug-token")] {
+        let anonymous = send("guessed-secret", token).await;
+        ensure!(
+            anonymous.status == StatusCode::FORBIDDEN
+                && !anonymous.text().contains("canonical_request"),
+            "mismatch with debug token {:?}: {} {}",
+            token,
+            anonymous.status,
+            anonymous.text()
+        );
+    }
+    Ok(())
+}
+
+async fn authorizer_verified_principal(app: &TestApp) -> CaseResult {
+    use std::sync::{Arc, Mutex, atomic::AtomicU16};
+
+    let allowed = Arc::new(Mutex::new(vec!["writer".to_string()]));
+    let (endpoint, received) = spawn_fake_authorizer(allowed, Arc::new(AtomicU16::new(200))).await;
+    let router = authorized_router(app, &endpoint, std::time::Duration::ZERO);
+    let put = |path: &str| {
+        Request::builder()
+            .method(Method::PUT)
+            .uri(path)
+            .header("content-type", "application/json")
+            .body(Body::from("null"))
+            .unwrap()
+    };
+    let now = chrono::Utc::now();
+    let last_principal = || {
+        received.lock().unwrap().last().unwrap()["principal"]
+            .as_str()
+            .unwrap_or_default()
+            .to_string()
+    };
+
+    let signed = app
+        .send_via(
+            router.clone(),
+            sigv4_signed(put("/authz"), "writer", "writer-secret", now),
+        )
+        .await;
+    ensure!(
+        signed.status == StatusCode::OK,
+        "signed request {} {}",
+        signed.status,
+        signed.text()
+    );
+    ensure!(
+        last_principal() == "writer",
+        "principal {}",
+        last_principal()
+    );
+    let context = received.lock().unwrap().last().unwrap().clone();
+    ensure!(
+        context["action"] == "bucket-create" && context["bucket"] == "authz",
+        "context {}",
+        context
+    );
+
+    // Claims the engine must not believe: a forged signature, a secret the
+    // server does not know, a stale date and an unverifiable SigV2 header.
+    let forged = sigv4_signed(put("/authz/forged"), "writer", "guessed-secret", now);
+    let stale = sigv4_signed(
+        put("/authz/stale"),
+        "writer",
+        "writer-secret",
+        now - chrono::Duration::hours(1),
+    );
+    let mut tampered = sigv4_signed(put("/authz/one"), "writer", "writer-secret", now);
+    *tampered.uri_mut() = "/authz/other".parse().unwrap();
+    let mut sigv2 = put("/authz/v2");
+    sigv2
+        .headers_mut()
+        .insert("authorization", "AWS writer:c2lnbmF0dXJl".parse().unwrap());
+    for (name, request) in [
+        ("forged", forged),
+        ("stale", stale),
+        ("tampered", tampered),
+        ("sigv2", sigv2),
+        ("unsigned", put("/authz/anon")),
+    ] {
+        let response = app.send_via(router.clone(), request).await;
+        ensure!(
+            response.status == StatusCode::FORBIDDEN,
+            "{} request {}",
+            name,
+            response.status
+        );
+        ensure!(
+            last_principal() == "anonymous",
+            "{} request asked about as {}",
+            name,
+            last_principal()
+        );
+    }
+
+    let presigned = |uri: String| {
+        Request::builder()
+            .uri(uri)
+            .header("host", "localhost")
+            .body(Body::empty())
+            .unwrap()
+    };
+    let fresh = app
+        .send_via(
+            router.clone(),
+            presigned(sigv4_presigned(
+                "/authz",
+                "writer",
+                "writer-secret",
+                now,
+                60,
+            )),
+        )
+        .await;
+    ensure!(
+        fresh.status == StatusCode::OK && last_principal() == "writer",
+        "presigned URL {} as {}",
+        fresh.status,
+        last_principal()
+    );
+    let lapsed = app
+        .send_via(
+            router.clone(),
+            presigned(sigv4_presigned(
+                "/authz",
+                "writer",
+                "writer-secret",
+                now - chrono::Duration::minutes(5),
+                60,
+            )),
+        )
+        .await;
+    ensure!(
+        lapsed.status == StatusCode::FORBIDDEN && last_principal() == "anonymous",
+        "expired presigned URL {} as {}",
+        lapsed.status,
+        last_principal()
+    );
+
+    let unknown = app
+        .send_via(
+            router.clone(),
+            sigv4_signed(put("/authz/x"), "reader", "reader-secret", now),
+        )
+        .await;
+    ensure!(
+        unknown.status == StatusCode::FORBIDDEN && last_principal() == "anonymous",
+        "unknown access key {} as {}",
+        unknown.status,
+        last_principal()
+    );
+    Ok(())
+}
+
+async fn authorizer_cache_ttl(app: &TestApp) -> CaseResult {
+    use std::sync::{Arc, Mutex, atomic::AtomicU16};
+
+    let allowed = Arc::new(Mutex::new(vec!["writer".to_string()]));
+    let (endpoint, received) =
+        spawn_fake_authorizer(allowed.clone(), Arc::new(AtomicU16::new(200))).await;
+    let ttl = std::time::Duration::from_millis(300);
+    let router = authorized_router(app, &endpoint, ttl);
+    app.create_bucket("cached").await;
+    let get = || {
+        sigv4_signed(
+            Request::builder()
+                .uri("/cached")
+                .body(Body::empty())
+                .unwrap(),
+            "writer",
+            "writer-secret",
+            chrono::Utc::now(),
+        )
+    };
+
+    let first = app.send_via(router.clone(), get()).await;
+    ensure!(first.status == StatusCode::OK, "first {}", first.status);
+    allowed.lock().unwrap().clear();
+    let cached = app.send_via(router.clone(), get()).await;
+    ensure!(
+        cached.status == StatusCode::OK && received.lock().unwrap().len() == 1,
+        "within the TTL: {} after {} callouts",
+        cached.status,
+        received.lock().unwrap().len()
+    );
+
+    tokio::time::sleep(ttl + std::time::Duration::from_millis(100)).await;
+    let expired = app.send_via(router.clone(), get()).await;
+    ensure!(
+        expired.status == StatusCode::FORBIDDEN && received.lock().unwrap().len() == 2,
+        "after the TTL: {} after {} callouts",
+        expired.status,
+        received.lock().unwrap().len()
+    );
+
+    // Another key is another decision.
+    let other = app
+        .send_via(
+            router.clone(),
+            Request::builder()
+                .uri("/cached?list-type=2")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+    ensure!(
+        other.status == StatusCode::FORBIDDEN && received.lock().unwrap().len() == 3,
+        "anonymous request {} after {} callouts",
+        other.status,
+        received.lock().unwrap().len()
+    );
+    Ok(())
+}
+
+async fn authorizer_fail_closed(app: &TestApp) -> CaseResult {
+    use std::sync::{
+        Arc, Mutex,
+        atomic::{AtomicU16, Ordering},
+    };
+
+    let allowed = Arc::new(Mutex::new(vec!["anonymous".to_string()]));
+    let status = Arc::new(AtomicU16::new(200));
+    let (endpoint, _received) = spawn_fake_authorizer(allowed, status.clone()).await;
+    let router = authorized_router(app, &endpoint, std::time::Duration::ZERO);
+    app.create_bucket("open").await;
+    let list = || Request::builder().uri("/open").body(Body::empty()).unwrap();
+
+    let ok = app.send_via(router.clone(), list()).await;
+    ensure!(ok.status == StatusCode::OK, "allowed {}", ok.status);
+    for (code, what) in [(500, "an error status"), (299, "an unreadable reply")] {
+        status.store(code, Ordering::SeqCst);
+        let response = app.send_via(router.clone(), list()).await;
+        ensure!(
+            response.status == StatusCode::SERVICE_UNAVAILABLE,
+            "{}: {}",
+            what,
+            response.status
+        );
+    }
+
+    // Nothing listening at all.
+    let closed = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let addr = closed.local_addr().unwrap();
+    drop(closed);
+    let router = authorized_router(
+        app,
+        &format!("http:http://{}/check", addr),
+        std::time::Duration::ZERO,
+    );
+    let response = app.send_via(router.clone(), list()).await;
+    ensure!(
+        response.status == StatusCode::SERVICE_UNAVAILABLE,
+        "unreachable authorizer: {}",
+        response.status
+    );
+    Ok(())
+}
+
+async fn admin_token_ttl_out_of_range(app: &TestApp) -> CaseResult {
+    use object_store::models::admin_token::AdminScope;
+
+    let scopes = &[AdminScope::Metrics];
+    for secs in [u64::MAX, i64::MAX as u64 / 1000] {
+        let created = app
+            .service
+            .create_admin_token(
+                "forever",
+                scopes,
+                Some(std::time::Duration::from_secs(secs)),
+            )
+            .await;
+        ensure!(created.is_err(), "a {}s lifetime was accepted", secs);
+    }
+    let tokens = app
+        .service
+        .list_admin_tokens()
+        .await
+        .map_err(|e| e.to_string())?;
+    ensure!(tokens.is_empty(), "tokens stored: {}", tokens.len());
+    let day = app
+        .service
+        .create_admin_token(
+            "daily",
+            scopes,
+            Some(std::time::Duration::from_secs(86_400)),
+        )
+        .await
+        .map_err(|e| e.to_string())?;
+    ensure!(
+        day.token.expires_at.is_some(),
+        "a day-long token never expires"
+    );
+    Ok(())
+}
+
+async fn admin_auth_identity_roles(app: &TestApp) -> CaseResult {
+    use axum::{Json, response::IntoResponse, routing::get};
+    use base64::{Engine as _, engine::general_purpose::URL_SAFE_NO_PAD};
+    use jsonwebtoken::{Algorithm, EncodingKey, Header, encode};
+    use object_store::{
+        middleware::admin_auth::{AdminAuth, RoleMapping, require_admin_identity},
+        services::{
+            identity::{LdapAuthenticator, LdapSettings, OidcSettings, OidcValidator},
+            outbound::OutboundHttp,
+        },
+    };
+
+    // A stub issuer publishing one P-256 signing key.
+    let signing = rcgen::KeyPair::generate().map_err(|e| e.to_string())?;
+    let point = signing.public_key_raw();
+    let jwks = serde_json::json!({"keys": [{
+        "kty": "EC", "crv": "P-256", "kid": "k1", "alg": "ES256", "use": "sig",
+        "x": URL_SAFE_NO_PAD.encode(&point[1..33]),
+        "y": URL_SAFE_NO_PAD.encode(&point[33..65]),
+    }]});
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let issuer = format!("http://{}", listener.local_addr().unwrap());
+    let discovery = serde_json::json!({"jwks_uri": format!("{}/jwks", issuer)});
+    let stub = axum::Router::new()
+        .route(
+            "/.well-known/openid-configuration",
+            get(move || async move { Json(discovery).into_response() }),
+        )
+        .route(
+            "/jwks",
+            get(move || async move { Json(jwks).into_response() }),
+        );
+    tokio::spawn(async move { axum::serve(listener, stub).await });
+
+    // Nothing listens on the LDAP port: a bind attempt is "unavailable".
+    let closed = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+    let ldap_url = format!("ldap://{}", closed.local_addr().unwrap());
+    drop(closed);
+
+    let oidc = OidcValidator::new(
+        OidcSettings {
+            issuer: issuer.parse().unwrap(),
+            audience: "object-store".into(),
+            jwks_url: None,
+            groups_claim: "groups".into(),
+        },
+        &OutboundHttp::default(),
+    )
+    .map_err(|e| e.to_string())?;
+    let ldap = LdapAuthenticator::new(LdapSettings {
+        url: ldap_url,
+        user_dn_template: "uid={user},ou=people,dc=example,dc=com".into(),
+        group_attribute: "memberOf".into(),
+    });
+    let roles = RoleMapping {
+        admin_groups: vec!["storage-admins".into()],
+        readonly_groups: vec!["storage-viewers".into()],
+    };
+    let router = app
+        .router
+        .clone()
+        .layer(axum::middleware::from_fn_with_state(
+            AdminAuth::new(Some(oidc), Some(ldap), roles),
+            require_admin_identity,
+        ));
+    app.create_bucket("archive").await;
+
+    let key =
+        EncodingKey::from_ec_pem(signing.serialize_pem().as_bytes()).map_err(|e| e.to_string())?;
+    let token = |audience: &str, groups: &[&str]| {
+        let mut header = Header::new(Algorithm::ES256);
+        header.kid = Some("k1".into());
+        let claims = serde_json::json!({
+            "iss": issuer,
+            "aud": audience,
+            "sub": "carol",
+            "exp": chrono::Utc::now().timestamp() + 300,
+            "groups": groups,
+        });
+        encode(&header, &claims, &key).unwrap()
+    };
+    let send = |method: Method, uri: &str, authorization: Option<String>| {
+        let mut builder = Request::builder().method(method).uri(uri);
+        if let Some(authorization) = authorization {
+            builder = builder.header("authorization", authorization);
+        }
+        app.send_via(router.clone(), builder.body(Body::empty()).unwrap())
+    };
+    let bearer = |token: String| Some(format!("Bearer {}", token));
+
+    let anonymous = send(Method::GET, "/admin/whoami", None).await;
+    ensure!(
+        anonymous.status == StatusCode::UNAUTHORIZED
+            && anonymous
+                .header("www-authenticate")
+                .is_some_and(|c| c.contains("Bearer") && c.contains("Basic")),
+        "no credentials {} {:?}",
+        anonymous.status,
+        anonymous.header("www-authenticate")
+    );
+    let wrong_audience = send(
+        Method::GET,
+        "/admin/whoami",
+        bearer(token("someone-else", &["storage-admins"])),
+    )
+    .await;
+    ensure!(
+        wrong_audience.status == StatusCode::UNAUTHORIZED,
+        "token for another audience {}",
+        wrong_audience.status
+    );
+    let no_role = send(
+        Method::GET,
+        "/admin/whoami",
+        bearer(token("object-store", &["developers"])),
+    )
+    .await;
+    ensure!(
+        no_role.status == StatusCode::FORBIDDEN,
+        "token without an admin group {} {}",
+        no_role.status,
+        no_role.text()
+    );
+
+    let viewer = token(
+        "object-store",
+        &["cn=storage-viewers,ou=groups,dc=example,dc=com"],
+    );
+    let read = send(
+        Method::GET,
+        "/admin/buckets/archive",
+        bearer(viewer.clone()),
+    )
+    .await;
+    ensure!(
+        read.status == StatusCode::OK,
+        "read-only GET {}",
+        read.status
+    );
+    let write = send(
+        Method::POST,
+        "/admin/buckets/archive/snapshots",
+        bearer(viewer),
+    )
+    .await;
+    ensure!(
+        write.status == StatusCode::FORBIDDEN,
+        "read-only POST {}",
+        write.status
+    );
+
+    let whoami = send(
+        Method::GET,
+        "/admin/whoami",
+        bearer(token("object-store", &["storage-admins"])),
+    )
+    .await;
+    let identity: serde_json::Value = serde_json::from_slice(&whoami.body).unwrap_or_default();
+    ensure!(
+        whoami.status == StatusCode::OK
+            && identity["subject"] == "carol"
+            && identity["role"] == "admin"
+            && identity["provider"] == "oidc",
+        "admin whoami {} {}",
+        whoami.status,
+        whoami.text()
+    );
+
+    let basic = |credentials: &str| {
+        use base64::engine::general_purpose::STANDARD;
+        Some(format!("Basic {}", STANDARD.encode(credentials)))
+    };
+    let empty_password = send(Method::GET, "/admin/whoami", basic("carol:")).await;
+    ensure!(
+        empty_password.status == StatusCode::UNAUTHORIZED,
+        "LDAP login without a password {}",
+        empty_password.status
+    );
+    let unreachable = send(Method::GET, "/admin/whoami", basic("carol:secret")).await;
+    ensure!(
+        unreachable.status == StatusCode::SERVICE_UNAVAILABLE,
+        "LDAP login with the directory down {}",
+        unreachable.status
+    );
+    Ok(())
+}
+
+async fn admin_token_scopes(app: &TestApp) -> CaseResult {
+    use object_store::{
+        middleware::admin_auth::{AdminAuth, require_admin_identity},
+        models::admin_token::AdminScope,
+    };
+
+    let router = app
+        .router
+        .clone()
+        .layer(axum::middleware::from_fn_with_state(
+            AdminAuth::default().with_tokens(app.service.clone()),
+            require_admin_identity,
+        ));
+    app.create_bucket("archive").await;
+    let create = |name: &'static str, scopes: &'static [AdminScope]| async move {
+        app.service
+            .create_admin_token(name, scopes, None)
+            .await
+            .map(|created| (created.token.id, created.secret))
+            .map_err(|err| err.to_string())
+    };
+    let (_, monitor) = create("monitor", &[AdminScope::Metrics]).await?;
+    let (backup_id, backup) = create("nightly-backup", &[AdminScope::Backup]).await?;
+    let send = |method: Method, uri: &str, token: Option<&str>| {
+        let mut builder = Request::builder().method(method).uri(uri);
+        if let Some(token) = token {
+            builder = builder.header("authorization", format!("Bearer {}", token));
+        }
+        app.send_via(router.clone(), builder.body(Body::empty()).unwrap())
+    };
+
+    let anonymous = send(Method::GET, "/admin/jobs", None).await;
+    ensure!(
+        anonymous.status == StatusCode::UNAUTHORIZED
+            && anonymous.header("www-authenticate").is_some(),
+        "anonymous {}",
+        anonymous.status
+    );
+    let unknown = send(Method::GET, "/admin/jobs", Some("osat_not-a-token")).await;
+    ensure!(
+        unknown.status == StatusCode::UNAUTHORIZED,
+        "unknown token {}",
+        unknown.status
+    );
+
+    for (method, uri, token, expected) in [
+        (Method::GET, "/admin/jobs", &monitor, true),
+        (Method::GET, "/admin/buckets/archive/stats", &monitor, true),
+        (Method::GET, "/admin/buckets/archive/quota", &monitor, false),
+        (
+            Method::POST,
+            "/admin/buckets/archive/snapshots",
+            &monitor,
+            false,
+        ),
+        (Method::POST, "/admin/sessions", &monitor, false),
+        (
+            Method::POST,
+            "/admin/buckets/archive/snapshots",
+            &backup,
+            true,
+        ),
+        (
+            Method::GET,
+            "/admin/buckets/archive/snapshots",
+            &backup,
+            true,
+        ),
+        (
+            Method::DELETE,
+            "/admin/buckets/archive/snapshot-policy",
+            &backup,
+            false,
+        ),
+        (Method::POST, "/admin/purge-deleted", &backup, false),
+    ] {
+        let resp = send(method.clone(), uri, Some(token)).await;
+        let allowed =
+            resp.status != StatusCode::FORBIDDEN && resp.status != StatusCode::UNAUTHORIZED;
+        ensure!(
+            allowed == expected,
+            "{} {} answered {} {}",
+            method,
+            uri,
+            resp.status,
+            resp.text()
+        );
+    }
+
+    let whoami = send(Method::GET, "/admin/whoami", Some(&backup)).await;
+    let identity: serde_json::Value =
+        serde_json::from_slice(&whoami.body).map_err(|e| e.to_string())?;
+    ensure!(
+        identity["subject"] == "token:nightly-backup"
+            && identity["provider"] == "token"
+            && identity["scopes"] == serde_json::json!(["backup"]),
+        "whoami {}",
+        identity
+    );
+    let listed = app
+        .service
+        .list_admin_tokens()
+        .await
+        .map_err(|e| e.to_string())?;
+    ensure!(
+        listed
+            .iter()
+            .any(|token| token.id == backup_id && token.last_used_at.is_some()),
+        "last use not recorded"
+    );
+
+    // Expired and revoked tokens stop working.
+    sqlx::query("UPDATE admin_tokens SET expires_at = ? WHERE name = 'monitor'")
+        .bind(chrono::Utc::now() - chrono::Duration::seconds(1))
+        .execute(&*app.service.db)
+        .await
+        .map_err(|e| e.to_string())?;
+    let expired = send(Method::GET, "/admin/jobs", Some(&monitor)).await;
+    ensure!(
+        expired.status == StatusCode::UNAUTHORIZED,
+        "expired token {}",
+        expired.status
+    );
+    let revoked = app
+        .service
+        .revoke_admin_token(backup_id)
+        .await
+        .map_err(|e| e.to_string())?;
+    let after = send(Method::GET, "/admin/jobs", Some(&backup)).await;
+    ensure!(
+        revoked && after.status == StatusCode::UNAUTHORIZED,
+        "revoked token {}",
+        after.status
+    );
+    Ok(())
+}
+
+async fn log_level_runtime(app: &TestApp) -> CaseResult {
+    use object_store::services::log_filter::LogFilter;
+    use tracing_subscriber::{EnvFilter, Registry, layer::SubscriberExt, reload};
+
+    let missing = app
+        .call(Method::GET, "/admin/log-level", Body::empty())
+        .await;
+    ensure!(
+        missing.status == StatusCode::NOT_FOUND,
+        "without a reloadable filter: {}",
+        missing.status
+    );
+
+    // The subscriber owns the filter; the handle works while it lives.
+    let (layer, handle) = reload::Layer::new(EnvFilter::new("info"));
+    let _subscriber = Registry::default().with(layer);
+    let router = app
+        .router
+        .clone()
+        .layer(axum::Extension(LogFilter::new(handle)));
+    let json = |resp: &common::TestResponse| -> Result<serde_json::Value, String> {
+        serde_json::from_slice(&resp.body).map_err(|e| e.to_string())
+    };
+
+    let get = |router: axum::Router| {
+        app.send_via(
+            router,
+            Request::get("/admin/log-level")
+                .body(Body::empty())
+                .unwrap(),
+        )
+    };
+    let put = |router: axum::Router, body: &'static str| {
+        app.send_via(
+            router,
+            Request::put("/admin/log-level")
+                .header("content-type", "application/json")
+                .body(Body::from(body))
+                .unwrap(),
+        )
+    };
+
+    let current = get(router.clone()).await;
+    ensure!(
+        current.status == StatusCode::OK && json(&current)?["filter"] == "info",
+        "initial filter: {} {}",
+        current.status,
+        current.text()
+    );
+    let changed = put(
+        router.clone(),
+        r#"{"filter": "info,object_store::services::storage_service=debug"}"#,
+    )
+    .await;
+    ensure!(
+        changed.status == StatusCode::OK,
+        "put: {} {}",
+        changed.status,
+        changed.text()
+    );
+    let current = get(router.clone()).await;
+    let filter = json(&current)?["filter"]
+        .as_str()
+        .unwrap_or_default()
+        .to_string();
+    ensure!(
+        filter.contains("object_store::services::storage_service=debug"),
+        "filter not replaced: {}",
+        filter
+    );
+    let invalid = put(router.clone(), r#"{"filter": "info,[=oops"}"#).await;
+    ensure!(
+        invalid.status == StatusCode::BAD_REQUEST,
+        "invalid directives: {} {}",
+        invalid.status,
+        invalid.text()
+    );
+    let current = get(router).await;
+    ensure!(
+        json(&current)?["filter"] == filter.as_str(),
+        "invalid directives changed the filter"
+    );
+    Ok(())
+}
+
+async fn sse_c_attributes(app: &TestApp) -> CaseResult {
+    use base64::{Engine as _, engine::general_purpose::STANDARD};
+
+    app.create_bucket("vault").await;
+    let request = |method: Method, key: Option<[u8; 32]>, body: &'static [u8]| {
+        let uri = match method {
+            Method::PUT => "/vault/secret.txt",
+            _ => "/vault/secret.txt?attributes",
+        };
+        let mut request = Request::builder().method(method).uri(uri);
+        if let Some(key) = key {
+            request = request
+                .header("x-amz-server-side-encryption-customer-algorithm", "AES256")
+                .header(
+                    "x-amz-server-side-encryption-customer-key",
+                    STANDARD.encode(key),
+                )
+                .header(
+                    "x-amz-server-side-encryption-customer-key-MD5",
+                    STANDARD.encode(md5::compute(key).0),
+                );
+        }
+        request.body(Body::from(body)).unwrap()
+    };
+    let key = [42u8; 32];
+    let put = app
+        .send(request(Method::PUT, Some(key), b"attack at dawn"))
+        .await;
+    ensure!(put.status == StatusCode::OK, "put: {}", put.status);
+
+    let missing = app.send(request(Method::GET, None, b"")).await;
+    ensure!(
+        missing.status == StatusCode::BAD_REQUEST,
+        "attributes without key: {} {}",
+        missing.status,
+        missing.text()
+    );
+    let wrong = app.send(request(Method::GET, Some([1u8; 32]), b"")).await;
+    ensure!(
+        wrong.status == StatusCode::FORBIDDEN,
+        "attributes with wrong key: {}",
+        wrong.status
+    );
+    let attributes = app.send(request(Method::GET, Some(key), b"")).await;
+    ensure!(
+        attributes.status == StatusCode::OK,
+        "attributes: {} {}",
+        attributes.status,
+        attributes.text()
+    );
+    ensure!(
+        attributes.text().contains("<ObjectSize>14</ObjectSize>"),
+        "attributes: {}",
+        attributes.text()
+    );
+    Ok(())
+}
+
+async fn sse_c_round_trip(app: &TestApp) -> CaseResult {
+    use base64::{Engine as _, engine::general_purpose::STANDARD};
+
+    app.create_bucket("vault").await;
+    let sse_headers = |key: [u8; 32]| {
+        vec![
+            (
+                "x-amz-server-side-encryption-customer-algorithm",
+                "AES256".to_string(),
+            ),
+            (
+                "x-amz-server-side-encryption-customer-key",
+                STANDARD.encode(key),
+            ),
+            (
+                "x-amz-server-side-encryption-customer-key-MD5",
+                STANDARD.encode(md5::compute(key).0),
+            ),
+        ]
+    };
+    let request = |method: Method, headers: Vec<(&str, String)>, body: &'static [u8]| {
+        let mut request = Request::builder().method(method).uri("/vault/secret.txt");
+        for (name, value) in headers {
+            request = request.header(name, value);
+        }
+        request.body(Body::from(body)).unwrap()
+    };
+    let plaintext: &'static [u8] = b"attack at dawn, bring snacks";
+    let key = [42u8; 32];
+    let key_md5 = STANDARD.encode(md5::compute(key).0);
+
+    let put = app
+        .send(request(Method::PUT, sse_headers(key), plaintext))
+        .await;
+    ensure!(
+        put.status == StatusCode::OK,
+        "put: {} {}",
+        put.status,
+        put.text()
+    );
+    ensure!(
+        put.header("x-amz-server-side-encryption-customer-key-md5") == Some(key_md5.as_str()),
+        "put did not echo the key MD5"
+    );
+    ensure!(
+        put.header("etag") == Some(format!("\"{:x}\"", md5::compute(plaintext)).as_str()),
+        "ETag is not the plaintext MD5: {:?}",
+        put.header("etag")
+    );
+    let (_, mut file) = app
+        .service
+        .get_object_reader("vault", "secret.txt")
+        .await
+        .map_err(|e| e.to_string())?;
+    let mut stored = Vec::new();
+    tokio::io::AsyncReadExt::read_to_end(&mut file, &mut stored)
+        .await
+        .map_err(|e| e.to_string())?;
+    ensure!(
+        stored.len() == plaintext.len() && stored != plaintext,
+        "payload stored in the clear"
+    );
+
+    let missing = app.send(request(Method::GET, Vec::new(), b"")).await;
+    ensure!(
+        missing.status == StatusCode::BAD_REQUEST,
+        "get without key: {}",
+        missing.status
+    );
+    let head = app.send(request(Method::HEAD, Vec::new(), b"")).await;
+    ensure!(
+        head.status == StatusCode::BAD_REQUEST,
+        "head without key: {}",
+        head.status
+    );
+    let wrong = app
+        .send(request(Method::GET, sse_headers([1u8; 32]), b""))
+        .await;
+    ensure!(
+        wrong.status == StatusCode::FORBIDDEN,
+        "get with wrong key: {}",
+        wrong.status
+    );
+
+    let get = app.send(request(Method::GET, sse_headers(key), b"")).await;
+    ensure!(
+        get.status == StatusCode::OK,
+        "get: {} {}",
+        get.status,
+        get.text()
+    );
+    ensure!(get.body == plaintext, "decrypted body differs");
+    ensure!(
+        get.header("x-amz-server-side-encryption-customer-algorithm") == Some("AES256"),
+        "get did not echo the algorithm"
+    );
+    let mut ranged = sse_headers(key);
+    ranged.push(("range", "bytes=7-12".to_string()));
+    let range = app.send(request(Method::GET, ranged, b"")).await;
+    ensure!(
+        range.status == StatusCode::PARTIAL_CONTENT && range.body == plaintext[7..=12],
+        "range: {} {:?}",
+        range.status,
+        range.text()
+    );
+
+    let copy = app
+        .send(request(
+            Method::PUT,
+            vec![("x-amz-copy-source", "vault/secret.txt".to_string())],
+            b"",
+        ))
+        .await;
+    ensure!(
+        copy.status == StatusCode::BAD_REQUEST,
+        "copy of encrypted object: {}",
+        copy.status
+    );
+
+    app.put_object("vault", "secret.txt", b"public").await;
+    let keyed = app.send(request(Method::GET, sse_headers(key), b"")).await;
+    ensure!(
+        keyed.status == StatusCode::BAD_REQUEST,
+        "key sent for a plain object: {}",
+        keyed.status
+    );
+    let plain = app.send(request(Method::GET, Vec::new(), b"")).await;
+    ensure!(
+        plain.status == StatusCode::OK && plain.body == b"public"[..],
+        "overwrite is still encrypted: {}",
+        plain.status
+    );
+    Ok(())
+}
+
+async fn list_stream_ndjson(app: &TestApp) -> CaseResult {
+    app.create_bucket("backup").await;
+    let mut expected = Vec::new();
+    for i in 0..1005 {
+        let key = format!("data/{:04}", i);
+        app.put_object("backup", &key, b"x").await;
+        expected.push(key);
+    }
+    app.put_object("backup", "other", b"x").await;
+
+    let resp = app
+        .call(
+            Method::GET,
+            "/backup?list-stream&prefix=data/",
+            Body::empty(),
+        )
+        .await;
+    ensure!(resp.status == StatusCode::OK, "status {}", resp.status);
+    ensure!(
+        resp.headers["content-type"] == "application/x-ndjson",
+        "content type {:?}",
+        resp.headers.get("content-type")
+    );
+    let mut keys = Vec::new();
+    for line in resp.text().lines() {
+        let object: serde_json::Value =
+            serde_json::from_str(line).map_err(|e| format!("{}: {}", e, line))?;
+        ensure!(object["size"] == 1, "line {}", line);
+        keys.push(object["key"].as_str().unwrap_or_default().to_string());
+    }
+    ensure!(keys == expected, "{} keys streamed", keys.len());
+
+    let grouped = app
+        .call(
+            Method::GET,
+            "/backup?list-stream&delimiter=/",
+            Body::empty(),
+        )
+        .await;
+    ensure!(
+        grouped.status == StatusCode::BAD_REQUEST,
+        "delimiter {}",
+        grouped.status
+    );
+    let missing = app
+        .call(Method::GET, "/nope?list-stream", Body::empty())
+        .await;
+    ensure!(
+        missing.status == StatusCode::NOT_FOUND,
+        "missing bucket {}",
+        missing.status
+    );
+    Ok(())
+}
+
+async fn self_test_round_trip(app: &TestApp) -> CaseResult {
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0")
+        .await
+        .map_err(|e| e.to_string())?;
+    let addr = listener.local_addr().map_err(|e| e.to_string())?;
+    let router = app.router.clone();
+    let server = tokio::spawn(async move { axum::serve(listener, router).await });
+    let result = object_store::services::self_test::run_self_test(
+        &format!("http://{}", addr),
+        false,
+        &Default::default(),
+    )
+    .await;
+    server.abort();
+    result?;
+    let buckets = app.call(Method::GET, "/", Body::empty()).await;
+    ensure!(
+        !buckets.text().contains("self-test-"),
+        "self-test bucket left behind: {}",
+        buckets.text()
+    );
+    Ok(())
+}
+
+/// Write a fresh self-signed `localhost` certificate and its key to `dir`;
+/// returns the certificate PEM.
+fn write_tls_pair(dir: &std::path::Path) -> String {
+    let pair = rcgen::generate_simple_self_signed(vec!["localhost".to_string()]).unwrap();
+    std::fs::write(dir.join("cert.pem"), pair.cert.pem()).unwrap();
+    std::fs::write(dir.join("key.pem"), pair.key_pair.serialize_pem()).unwrap();
+    pair.cert.pem()
+}
+
+/// Whether a client trusting only `cert_pem` completes a request to `addr`.
+async fn trusts(cert_pem: &str, addr: std::net::SocketAddr) -> bool {
+    let client = reqwest::Client::builder()
+        .no_proxy()
+        .add_root_certificate(reqwest::Certificate::from_pem(cert_pem.as_bytes()).unwrap())
+        .resolve("localhost", addr)
+        .build()
+        .unwrap();
+    client
+        .get(format!("https://localhost:{}/healthz", addr.port()))
+        .send()
+        .await
+        .is_ok_and(|response| response.status().is_success())
+}
+
+async fn tls_listener_reload(app: &TestApp) -> CaseResult {
+    use object_store::services::tls::{TlsFiles, spawn_tls_reloader};
+    use std::time::Duration;
+
+    let dir = tempfile::tempdir().map_err(|e| e.to_string())?;
+    let files = TlsFiles::new(dir.path().join("cert.pem"), dir.path().join("key.pem"));
+    let first = write_tls_pair(dir.path());
+    let tls = files.load().map_err(|e| e.to_string())?;
+
+    let other = tempfile::tempdir().map_err(|e| e.to_string())?;
+    write_tls_pair(other.path());
+    let mismatched = TlsFiles::new(dir.path().join("cert.pem"), other.path().join("key.pem"));
+    ensure!(
+        mismatched.load().is_err(),
+        "a key of another certificate was accepted"
+    );
+
+    let listener = std::net::TcpListener::bind("127.0.0.1:0").map_err(|e| e.to_string())?;
+    listener.set_nonblocking(true).map_err(|e| e.to_string())?;
+    let addr = listener.local_addr().map_err(|e| e.to_string())?;
+    let router = app.router.clone();
+    let server = tokio::spawn(
+        axum_server::from_tcp_rustls(listener, tls.clone()).serve(router.into_make_service()),
+    );
+    let reloader = spawn_tls_reloader(files, tls, Duration::from_millis(50));
+
+    let result = async {
+        ensure!(trusts(&first, addr).await, "first certificate not served");
+
+        let second = write_tls_pair(dir.path());
+        let mut rotated = false;
+        for _ in 0..100 {
+            if trusts(&second, addr).await {
+                rotated = true;
+                break;
+            }
+            tokio::time::sleep(Duration::from_millis(50)).await;
+        }
+        ensure!(rotated, "rotated certificate not picked up");
+        ensure!(
+            !trusts(&first, addr).await,
+            "replaced certificate still served"
+        );
+
+        std::fs::write(dir.path().join("cert.pem"), b"not a certificate").unwrap();
+        tokio::time::sleep(Duration::from_millis(300)).await;
+        ensure!(
+            trusts(&second, addr).await,
+            "a broken certificate replaced the working one"
+        );
+        Ok(())
+    }
+    .await;
+    reloader.abort();
+    server.abort();
+    result
+}
+
+fn from_client(ip: &str, mut request: Request<Body>) -> Request<Body> {
+    use object_store::middleware::client_info::ClientInfo;
+
+    request.extensions_mut().insert(ClientInfo {
+        ip: Some(ip.parse().unwrap()),
+        scheme: "http".into(),
+    });
+    request
+}
+
+fn proxy_headers(pairs: &[(&'static str, &str)]) -> axum::http::HeaderMap {
+    let mut headers = axum::http::HeaderMap::new();
+    for (name, value) in pairs {
+        headers.append(*name, value.parse().unwrap());
+    }
+    headers
+}
+
+fn ip(addr: &str) -> Option<std::net::IpAddr> {
+    Some(addr.parse().unwrap())
+}
+
+async fn client_info_x_forwarded_for(_app: &TestApp) -> CaseResult {
+    use object_store::middleware::client_info::TrustedProxies;
+
+    let proxies = TrustedProxies::new(vec![
+        "10.0.0.0/8".parse().unwrap(),
+        "fd00::/8".parse().unwrap(),
+    ]);
+    let headers = proxy_headers(&[
+        ("x-forwarded-for", "203.0.113.9, 198.51.100.7, 10.1.2.3"),
+        ("x-forwarded-proto", "https"),
+    ]);
+
+    let info = proxies.resolve(ip("10.0.0.1"), &headers);
+    ensure!(
+        info.ip == ip("198.51.100.7"),
+        "client behind two proxies {:?}",
+        info.ip
+    );
+    ensure!(info.scheme == "https", "scheme {}", info.scheme);
+
+    let direct = proxies.resolve(ip("192.0.2.1"), &headers);
+    ensure!(
+        direct.ip == ip("192.0.2.1") && direct.scheme == "http",
+        "headers from an untrusted peer believed: {:?}",
+        direct
+    );
+
+    let bare = proxies.resolve(ip("fd00::1"), &proxy_headers(&[]));
+    ensure!(
+        bare.ip == ip("fd00::1"),
+        "proxy without headers {:?}",
+        bare.ip
+    );
+
+    let split = proxy_headers(&[
+        ("x-forwarded-for", "203.0.113.9"),
+        ("x-forwarded-for", "[2001:db8::5]:443"),
+    ]);
+    let info = proxies.resolve(ip("10.0.0.1"), &split);
+    ensure!(
+        info.ip == ip("2001:db8::5"),
+        "repeated header lines {:?}",
+        info.ip
+    );
+
+    // X-Forwarded-Proto lines are read like X-Forwarded-For ones and paired
+    // from the right: what the client sent never sets the scheme.
+    let spoofed = proxy_headers(&[
+        ("x-forwarded-for", "192.0.2.200, 198.51.100.7"),
+        ("x-forwarded-proto", "https"),
+        ("x-forwarded-proto", "http"),
+    ]);
+    let info = proxies.resolve(ip("10.0.0.1"), &spoofed);
+    ensure!(
+        info.ip == ip("198.51.100.7") && info.scheme == "http",
+        "client-sent X-Forwarded-Proto line {:?} {}",
+        info.ip,
+        info.scheme
+    );
+    let left_of_client = proxy_headers(&[
+        ("x-forwarded-for", "192.0.2.200, 198.51.100.7, 10.1.2.3"),
+        ("x-forwarded-proto", "https, http, http"),
+    ]);
+    let info = proxies.resolve(ip("10.0.0.1"), &left_of_client);
+    ensure!(
+        info.scheme == "http",
+        "scheme left of the client believed: {}",
+        info.scheme
+    );
+    Ok(())
+}
+
+async fn client_info_forwarded_spoof(_app: &TestApp) -> CaseResult {
+    use object_store::middleware::client_info::TrustedProxies;
+
+    // nginx-style: the proxy appends the peer to X-Forwarded-For and passes
+    // a client's own `Forwarded` through untouched.
+    let proxies = TrustedProxies::new(vec!["10.0.0.0/8".parse().unwrap()]);
+    let headers = proxy_headers(&[
+        ("forwarded", "for=192.0.2.200;proto=https"),
+        ("x-forwarded-for", "198.51.100.7"),
+    ]);
+    let info = proxies.resolve(ip("10.0.0.1"), &headers);
+    ensure!(
+        info.ip == ip("198.51.100.7"),
+        "spoofed Forwarded chose the client: {:?}",
+        info.ip
+    );
+    ensure!(
+        info.scheme == "http",
+        "spoofed Forwarded chose the scheme: {}",
+        info.scheme
+    );
+
+    let only_forwarded = proxy_headers(&[("forwarded", "for=192.0.2.200")]);
+    let info = proxies.resolve(ip("10.0.0.1"), &only_forwarded);
+    ensure!(
+        info.ip == ip("10.0.0.1"),
+        "Forwarded believed without X-Forwarded-For: {:?}",
+        info.ip
+    );
+    Ok(())
+}
+
+async fn client_info_forwarded(_app: &TestApp) -> CaseResult {
+    use object_store::middleware::client_info::{ForwardedHeader, TrustedProxies};
+
+    let proxies = TrustedProxies::new(vec!["10.0.0.0/8".parse().unwrap()])
+        .with_header(ForwardedHeader::Forwarded);
+    let headers = proxy_headers(&[
+        (
+            "forwarded",
+            "for=198.51.100.7;proto=https, for=\"[2001:db8::5]:4711\"",
+        ),
+        ("x-forwarded-for", "192.0.2.200"),
+    ]);
+    let info = proxies.resolve(ip("10.0.0.1"), &headers);
+    ensure!(
+        info.ip == ip("2001:db8::5"),
+        "Forwarded client {:?}",
+        info.ip
+    );
+    ensure!(
+        info.scheme == "http",
+        "scheme from an element left of the client: {}",
+        info.scheme
+    );
+
+    // Only the client's own element reports its scheme.
+    let spoofed = proxy_headers(&[(
+        "forwarded",
+        "for=198.51.100.7, for=203.0.113.9;proto=https, for=10.2.0.1;proto=http",
+    )]);
+    let info = proxies.resolve(ip("10.0.0.1"), &spoofed);
+    ensure!(
+        info.ip == ip("203.0.113.9") && info.scheme == "https",
+        "client element {:?} {}",
+        info.ip,
+        info.scheme
+    );
+    let spoofed = proxy_headers(&[("forwarded", "for=198.51.100.7;proto=https, for=203.0.113.9")]);
+    let info = proxies.resolve(ip("10.0.0.1"), &spoofed);
+    ensure!(
+        info.scheme == "http",
+        "client-written proto believed: {}",
+        info.scheme
+    );
+
+    // Everything left of an `unknown` hop is the client's to invent; the
+    // proxy that reported it stands in.
+    let unknown = proxy_headers(&[("forwarded", "for=192.0.2.200, for=unknown, for=10.2.0.1")]);
+    let info = proxies.resolve(ip("10.0.0.1"), &unknown);
+    ensure!(
+        info.ip == ip("10.2.0.1"),
+        "walked past an unknown hop: {:?}",
+        info.ip
+    );
+    let obfuscated = proxy_headers(&[("forwarded", "for=192.0.2.200, for=_hidden")]);
+    let info = proxies.resolve(ip("10.0.0.1"), &obfuscated);
+    ensure!(
+        info.ip == ip("10.0.0.1"),
+        "walked past an obfuscated hop: {:?}",
+        info.ip
+    );
+
+    ensure!(
+        "x-forwarded-for".parse::<ForwardedHeader>() == Ok(ForwardedHeader::XForwardedFor)
+            && "Forwarded".parse::<ForwardedHeader>() == Ok(ForwardedHeader::Forwarded)
+            && "x-real-ip".parse::<ForwardedHeader>().is_err(),
+        "header names not parsed"
+    );
+    Ok(())
+}
+
+async fn rate_limit_per_ip(app: &TestApp) -> CaseResult {
+    use object_store::middleware::throttle::{Throttle, ThrottleSettings, throttle_requests};
+
+    let router = app
+        .router
+        .clone()
+        .layer(axum::middleware::from_fn_with_state(
+            Throttle::new(ThrottleSettings {
+                rate_per_ip: 1,
+                burst: 3,
+                max_concurrent_uploads: 0,
+            }),
+            throttle_requests,
+        ));
+    app.create_bucket("photos").await;
+    let get = |ip: &str, uri: &str| {
+        from_client(
+            ip,
+            Request::builder()
+                .method(Method::GET)
+                .uri(uri)
+                .body(Body::empty())
+                .unwrap(),
+        )
+    };
+
+    for attempt in 0..3 {
+        let resp = app
+            .send_via(router.clone(), get("203.0.113.7", "/photos"))
+            .await;
+        ensure!(
+            resp.status == StatusCode::OK,
+            "burst request {} {}",
+            attempt,
+            resp.status
+        );
+    }
+    let throttled = app
+        .send_via(router.clone(), get("203.0.113.7", "/photos"))
+        .await;
+    ensure!(
+        throttled.status == StatusCode::TOO_MANY_REQUESTS
+            && throttled.text().contains("slow down")
+            && throttled.header("retry-after") == Some("1"),
+        "over the rate {} {} {:?}",
+        throttled.status,
+        throttled.text(),
+        throttled.header("retry-after")
+    );
+    let other = app
+        .send_via(router.clone(), get("198.51.100.2", "/photos"))
+        .await;
+    ensure!(
+        other.status == StatusCode::OK,
+        "other client {}",
+        other.status
+    );
+    let probe = app
+        .send_via(router.clone(), get("203.0.113.7", "/healthz"))
+        .await;
+    ensure!(
+        probe.status == StatusCode::OK,
+        "health probe {}",
+        probe.status
+    );
+    Ok(())
+}
+
+async fn concurrent_uploads_capped(app: &TestApp) -> CaseResult {
+    use object_store::middleware::throttle::{Throttle, ThrottleSettings, throttle_requests};
+
+    let router = app
+        .router
+        .clone()
+        .layer(axum::middleware::from_fn_with_state(
+            Throttle::new(ThrottleSettings {
+                max_concurrent_uploads: 1,
+                ..ThrottleSettings::default()
+            }),
+            throttle_requests,
+        ));
+    app.create_bucket("photos").await;
+    let put = |uri: &str, body: Body| {
+        Request::builder()
+            .method(Method::PUT)
+            .uri(uri)
+            .body(body)
+            .unwrap()
+    };
+
+    let (tx, rx) = futures::channel::mpsc::unbounded::<Result<bytes::Bytes, std::io::Error>>();
+    let bucket_dir = app.service.base_path.join("photos");
+    let drive = async {
+        tx.unbounded_send(Ok(bytes::Bytes::from_static(b"slow ")))
+            .unwrap();
+        for _ in 0..200 {
+            if !leftover_temp_files(&bucket_dir).is_empty() {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        let second = app
+            .send_via(router.clone(), put("/photos/fast", Body::from("fast")))
+            .await;
+        let listing = app
+            .send_via(
+                router.clone(),
+                Request::builder()
+                    .method(Method::GET)
+                    .uri("/photos")
+                    .body(Body::empty())
+                    .unwrap(),
+            )
+            .await;
+        tx.unbounded_send(Ok(bytes::Bytes::from_static(b"upload")))
+            .unwrap();
+        drop(tx);
+        (second, listing)
+    };
+    let (first, (second, listing)) = futures::join!(
+        app.send_via(router.clone(), put("/photos/slow", Body::from_stream(rx))),
+        drive
+    );
+    ensure!(
+        first.status == StatusCode::OK,
+        "first upload {}",
+        first.status
+    );
+    ensure!(
+        second.status == StatusCode::SERVICE_UNAVAILABLE && second.text().contains("slow down"),
+        "second upload {} {}",
+        second.status,
+        second.text()
+    );
+    ensure!(
+        listing.status == StatusCode::OK,
+        "listing during an upload {}",
+        listing.status
+    );
+    let after = app
+        .send_via(router.clone(), put("/photos/fast", Body::from("fast")))
+        .await;
+    ensure!(
+        after.status == StatusCode::OK,
+        "upload after {}",
+        after.status
+    );
+    Ok(())
+}
+
+async fn staging_dir_holds_temp_files(_app: &TestApp) -> CaseResult {
+    let app = TestApp::with_service(|mut service| {
+        service.options.staging_dir = Some(service.base_path.with_file_name("staging"));
+        service
+    })
+    .await;
+    app.service
+        .prepare_staging_dir()
+        .await
+        .map_err(|e| e.to_string())?;
+    let staging = app.service.base_path.with_file_name("staging");
+    app.create_bucket("photos").await;
+
+    let (tx, rx) = futures::channel::mpsc::unbounded::<Result<bytes::Bytes, std::io::Error>>();
+    let request = Request::builder()
+        .method(Method::PUT)
+        .uri("/photos/big")
+        .body(Body::from_stream(rx))
+        .unwrap();
+    let bucket_dir = app.service.base_path.join("photos");
+    let temp_files = |dir: &std::path::Path| leftover_temp_files(dir).len();
+    let drive = async {
+        tx.unbounded_send(Ok(bytes::Bytes::from_static(b"hello ")))
+            .unwrap();
+        let mut staged = 0;
+        for _ in 0..200 {
+            staged = temp_files(&staging);
+            if staged > 0 {
+                break;
+            }
+            tokio::time::sleep(std::time::Duration::from_millis(10)).await;
+        }
+        let in_bucket = temp_files(&bucket_dir);
+        tx.unbounded_send(Ok(bytes::Bytes::from_static(b"world")))
+            .unwrap();
+        drop(tx);
+        (staged, in_bucket)
+    };
+    let (put, (staged, in_bucket)) = futures::join!(app.send(request), drive);
+    ensure!(put.status == StatusCode::OK, "put {}", put.status);
+    ensure!(
+        staged == 1 && in_bucket == 0,
+        "temp files: {} staged, {} in the bucket",
+        staged,
+        in_bucket
+    );
+    ensure!(temp_files(&staging) == 0, "temp file left behind");
+    let get = app.call(Method::GET, "/photos/big", Body::empty()).await;
+    ensure!(get.body == b"hello world", "get {:?}", get.text());
+
+    std::fs::write(staging.join(".tmp-crashed"), b"partial").unwrap();
+    let report = app
+        .service
+        .fsck(std::time::Duration::ZERO)
+        .await
+        .map_err(|e| e.to_string())?;
+    ensure!(
+        report.temp_files_removed == 1 && report.orphaned_payloads.is_empty(),
+        "fsck {:?}",
+        report
+    );
+    Ok(())
+}
+
+async fn metadata_export_import(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    app.create_bucket("logs").await;
+    let put = app
+        .send(
+            Request::builder()
+                .method(Method::PUT)
+                .uri("/photos/a/cat.jpg")
+                .header("content-type", "image/jpeg")
+                .header("cache-control", "max-age=60")
+                .body(Body::from("meow"))
+                .unwrap(),
+        )
+        .await;
+    ensure!(put.status == StatusCode::OK, "put {}", put.status);
+    app.put_object("logs", "gone", b"old").await;
+    app.call(Method::DELETE, "/logs/gone", Body::empty()).await;
+
+    let mut dump = Vec::new();
+    let counts = app
+        .service
+        .export_metadata(&mut dump)
+        .await
+        .map_err(|err| err.to_string())?;
+    ensure!(
+        counts.buckets == 2 && counts.objects == 2,
+        "export counts {:?}",
+        counts
+    );
+    let text = String::from_utf8_lossy(&dump).into_owned();
+    ensure!(
+        text.lines()
+            .next()
+            .is_some_and(|line| line.contains(r#""type":"bucket""#)),
+        "export does not start with a bucket {}",
+        text
+    );
+
+    let target = TestApp::new().await;
+    for _ in 0..2 {
+        let counts = target
+            .service
+            .import_metadata(dump.as_slice())
+            .await
+            .map_err(|err| err.to_string())?;
+        ensure!(
+            counts.buckets == 2 && counts.objects == 2,
+            "import counts {:?}",
+            counts
+        );
+    }
+    let mut again = Vec::new();
+    target
+        .service
+        .export_metadata(&mut again)
+        .await
+        .map_err(|err| err.to_string())?;
+    ensure!(
+        again == dump,
+        "re-export differs:\n{}\n{}",
+        text,
+        String::from_utf8_lossy(&again)
+    );
+    let head = target
+        .call(Method::HEAD, "/photos/a/cat.jpg", Body::empty())
+        .await;
+    ensure!(
+        head.status == StatusCode::OK
+            && head.header("content-type") == Some("image/jpeg")
+            && head.header("etag") == put.header("etag"),
+        "imported object {} {:?}",
+        head.status,
+        head.headers
+    );
+    let gone = target.call(Method::GET, "/logs/gone", Body::empty()).await;
+    ensure!(
+        gone.status == StatusCode::NOT_FOUND,
+        "imported deleted object {}",
+        gone.status
+    );
+
+    // A bad line rolls back the whole import.
+    let fresh = TestApp::new().await;
+    let mut broken = dump.clone();
+    broken.extend_from_slice(b"{\"type\":\"object\"}\n");
+    ensure!(
+        fresh
+            .service
+            .import_metadata(broken.as_slice())
+            .await
+            .is_err(),
+        "malformed import accepted"
+    );
+    let buckets: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM buckets")
+        .fetch_one(&*fresh.service.db)
+        .await
+        .map_err(|err| err.to_string())?;
+    ensure!(buckets == 0, "partial import left {} buckets", buckets);
+    Ok(())
+}
+
+async fn fsck_reconciles_payloads(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    app.put_object("photos", "kept", b"hello").await;
+    app.put_object("photos", "lost", b"hello").await;
+    let bucket_dir = app.service.base_path.join("photos");
+    let lost = find_files(&bucket_dir, "lost");
+    ensure!(lost.len() == 1, "payload files {:?}", lost);
+    std::fs::remove_file(&lost[0]).unwrap();
+    let temp = bucket_dir.join(".tmp-crashed");
+    std::fs::write(&temp, b"partial").unwrap();
+    let orphan = bucket_dir.join("00/00/stray");
+    std::fs::create_dir_all(orphan.parent().unwrap()).unwrap();
+    std::fs::write(&orphan, b"stray").unwrap();
+
+    let young = app
+        .service
+        .fsck(std::time::Duration::from_secs(3600))
+        .await
+        .map_err(|e| e.to_string())?;
+    ensure!(
+        young.temp_files_removed == 0 && temp.exists(),
+        "young temp file removed: {:?}",
+        young
+    );
+    let report = app
+        .service
+        .fsck(std::time::Duration::ZERO)
+        .await
+        .map_err(|e| e.to_string())?;
+    ensure!(
+        report.temp_files_removed == 1 && report.temp_bytes_removed == 7 && !temp.exists(),
+        "temp sweep {:?}",
+        report
+    );
+    ensure!(
+        report.missing_payloads.len() == 1 && report.missing_payloads[0].key == "lost",
+        "missing {:?}",
+        report.missing_payloads
+    );
+    ensure!(
+        report.orphaned_payloads == vec![orphan.clone()] && orphan.exists(),
+        "orphans {:?}",
+        report.orphaned_payloads
+    );
+    let kept = app.call(Method::GET, "/photos/kept", Body::empty()).await;
+    ensure!(kept.status == StatusCode::OK, "kept {}", kept.status);
+    Ok(())
+}
+
+async fn delete_object_background_removal(app: &TestApp) -> CaseResult {
+    object_store::services::reclaim::spawn_reclaimers(&app.service, 2).await;
+    app.create_bucket("photos").await;
+    app.put_object("photos", "a/b/k", b"old").await;
+    app.call(Method::DELETE, "/photos/a/b/k", Body::empty())
+        .await;
+    app.put_object("photos", "a/b/k", b"new").await;
+    let resp = app.call(Method::GET, "/photos/a/b/k", Body::empty()).await;
+    ensure!(
+        resp.status == StatusCode::OK && resp.text() == "new",
+        "re-upload after delete: {} {}",
+        resp.status,
+        resp.text()
+    );
+
+    // The removed payload is unlinked by a worker shortly after.
+    let reclaim_dir = app.service.base_path.join(".reclaim");
+    for _ in 0..50 {
+        let pending = std::fs::read_dir(&reclaim_dir)
+            .map_err(|e| e.to_string())?
+            .count();
+        if pending == 0 {
+            return Ok(());
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    Err("removed payload still waiting in .reclaim".into())
+}
+
+async fn delete_objects(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    for key in ["a", "b", "c"] {
+        app.put_object("photos", key, b"x").await;
+    }
+    let body = "<Delete><Object><Key>a</Key></Object><Object><Key>b</Key></Object>\
+                <Object><Key>missing</Key></Object></Delete>";
+    let resp = app.call(Method::POST, "/photos?delete", body).await;
+    ensure!(resp.status == StatusCode::OK, "status {}", resp.status);
+    let text = resp.text();
+    let deleted = extract_all(&text, "Key");
+    ensure!(deleted == ["a", "b", "missing"], "deleted {:?}", deleted);
+    let resp = app
+        .call(Method::GET, "/photos?list-type=2", Body::empty())
+        .await;
+    let keys = extract_all(&resp.text(), "Key");
+    ensure!(keys == ["c"], "remaining {:?}", keys);
+    Ok(())
+}
+
+async fn delete_objects_quiet(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    app.put_object("photos", "a", b"x").await;
+    let body = "<Delete><Quiet>true</Quiet><Object><Key>a</Key></Object>\
+                <Object><Key>/bad</Key></Object></Delete>";
+    let resp = app.call(Method::POST, "/photos?delete", body).await;
+    ensure!(resp.status == StatusCode::OK, "status {}", resp.status);
+    let text = resp.text();
+    ensure!(
+        !text.contains("<Deleted>"),
+        "quiet listed deletions: {}",
+        text
+    );
+    let errors = extract_all(&text, "Key");
+    ensure!(errors == ["/bad"], "errors {:?}", errors);
+    Ok(())
+}
+
+async fn object_lock_default_retention(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    let lock = concat!(
+        "<ObjectLockConfiguration><ObjectLockEnabled>Enabled</ObjectLockEnabled>",
+        "<Rule><DefaultRetention><Mode>COMPLIANCE</Mode><Days>1</Days></DefaultRetention></Rule>",
+        "</ObjectLockConfiguration>"
+    );
+    let unversioned = app.call(Method::PUT, "/photos?object-lock", lock).await;
+    ensure!(
+        unversioned.status == StatusCode::CONFLICT,
+        "lock without versioning {}",
+        unversioned.status
+    );
+    enable_versioning(app, "photos").await;
+    let put_lock = app.call(Method::PUT, "/photos?object-lock", lock).await;
+    ensure!(
+        put_lock.status == StatusCode::OK,
+        "put lock {}: {}",
+        put_lock.status,
+        put_lock.text()
+    );
+    let get_lock = app
+        .call(Method::GET, "/photos?object-lock", Body::empty())
+        .await;
+    ensure!(
+        get_lock
+            .text()
+            .contains("<Mode>COMPLIANCE</Mode><Days>1</Days>"),
+        "get lock {}",
+        get_lock.text()
+    );
+
+    let put = app.call(Method::PUT, "/photos/a.txt", "locked").await;
+    let version_id = put.headers["x-amz-version-id"]
+        .to_str()
+        .unwrap()
+        .to_string();
+    let head = app.call(Method::HEAD, "/photos/a.txt", Body::empty()).await;
+    ensure!(
+        head.headers
+            .get("x-amz-object-lock-mode")
+            .map(|v| v.as_bytes())
+            == Some(b"COMPLIANCE")
+            && head
+                .headers
+                .contains_key("x-amz-object-lock-retain-until-date"),
+        "lock headers {:?}",
+        head.headers
+    );
+    let delete = app
+        .call(
+            Method::DELETE,
+            &format!("/photos/a.txt?versionId={}", version_id),
+            Body::empty(),
+        )
+        .await;
+    ensure!(
+        delete.status == StatusCode::FORBIDDEN,
+        "delete locked version {}",
+        delete.status
+    );
+    let suspend = app
+        .call(
+            Method::PUT,
+            "/photos?versioning",
+            "<VersioningConfiguration><Status>Suspended</Status></VersioningConfiguration>",
+        )
+        .await;
+    ensure!(
+        suspend.status == StatusCode::CONFLICT,
+        "suspend versioning {}",
+        suspend.status
+    );
+    Ok(())
+}
+
+async fn enable_versioning(app: &TestApp, bucket: &str) {
+    let resp = app
+        .call(
+            Method::PUT,
+            &format!("/{}?versioning", bucket),
+            "<VersioningConfiguration><Status>Enabled</Status></VersioningConfiguration>",
+        )
+        .await;
+    assert_eq!(resp.status, StatusCode::OK, "versioning: {}", resp.text());
+}
+
+async fn delete_object_versioned(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    enable_versioning(app, "photos").await;
+    let put = app.put_object("photos", "k", b"data").await;
+    let resp = app.call(Method::DELETE, "/photos/k", Body::empty()).await;
+    ensure!(
+        resp.status == StatusCode::NO_CONTENT,
+        "status {}",
+        resp.status
+    );
+    let marker = resp.header("x-amz-version-id").map(str::to_string);
+    ensure!(marker.is_some(), "no marker version id");
+    let resp = app.call(Method::GET, "/photos/k", Body::empty()).await;
+    ensure!(
+        resp.status == StatusCode::NOT_FOUND,
+        "get after delete {}",
+        resp.status
+    );
+
+    // Removing the marker brings the object back.
+    let uri = format!("/photos/k?versionId={}", marker.unwrap());
+    let resp = app.call(Method::DELETE, &uri, Body::empty()).await;
+    ensure!(
+        resp.header("x-amz-delete-marker") == Some("true"),
+        "marker removal not flagged"
+    );
+    let resp = app.call(Method::GET, "/photos/k", Body::empty()).await;
+    ensure!(resp.text() == "data", "body {:?}", resp.text());
+    ensure!(
+        resp.header("x-amz-version-id") == put.header("x-amz-version-id"),
+        "restored version {:?}",
+        resp.header("x-amz-version-id")
+    );
+    Ok(())
+}
+
+async fn put_bucket_versioning(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    let resp = app
+        .call(Method::GET, "/photos?versioning", Body::empty())
+        .await;
+    ensure!(resp.status == StatusCode::OK, "status {}", resp.status);
+    ensure!(!resp.text().contains("<Status>"), "body {}", resp.text());
+    enable_versioning(app, "photos").await;
+    let resp = app
+        .call(Method::GET, "/photos?versioning", Body::empty())
+        .await;
+    ensure!(
+        resp.text().contains("<Status>Enabled</Status>"),
+        "body {}",
+        resp.text()
+    );
+    Ok(())
+}
+
+async fn get_object_version(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    enable_versioning(app, "photos").await;
+    let first = app.put_object("photos", "k", b"one").await;
+    app.put_object("photos", "k", b"two").await;
+    let version_id = first
+        .header("x-amz-version-id")
+        .ok_or("no x-amz-version-id on PUT")?;
+    let uri = format!("/photos/k?versionId={}", version_id);
+    let resp = app.call(Method::GET, &uri, Body::empty()).await;
+    ensure!(resp.status == StatusCode::OK, "status {}", resp.status);
+    ensure!(resp.text() == "one", "body {:?}", resp.text());
+    let resp = app.call(Method::GET, "/photos/k", Body::empty()).await;
+    ensure!(resp.text() == "two", "current body {:?}", resp.text());
+    let resp = app
+        .call(Method::GET, "/photos/k?versionId=nope", Body::empty())
+        .await;
+    ensure!(
+        resp.status == StatusCode::NOT_FOUND,
+        "unknown version {}",
+        resp.status
+    );
+    Ok(())
+}
+
+async fn list_object_versions(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    enable_versioning(app, "photos").await;
+    app.put_object("photos", "a", b"1").await;
+    app.put_object("photos", "a", b"2").await;
+    app.put_object("photos", "b", b"3").await;
+    app.call(Method::DELETE, "/photos/b", Body::empty()).await;
+    let resp = app
+        .call(Method::GET, "/photos?versions", Body::empty())
+        .await;
+    ensure!(resp.status == StatusCode::OK, "status {}", resp.status);
+    let text = resp.text();
+    let keys = extract_all(&text, "Key");
+    ensure!(keys == ["a", "a", "b", "b"], "keys {:?}", keys);
+    let latest = extract_all(&text, "IsLatest");
+    ensure!(
+        latest == ["true", "false", "true", "false"],
+        "latest {:?}",
+        latest
+    );
+    ensure!(
+        text.matches("<DeleteMarker>").count() == 1,
+        "markers in {}",
+        text
+    );
+    Ok(())
+}
+
+async fn list_objects_v2_basic(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    for key in ["b", "a", "c"] {
+        app.put_object("photos", key, b"x").await;
+    }
+    let resp = app
+        .call(Method::GET, "/photos?list-type=2", Body::empty())
+        .await;
+    ensure!(resp.status == StatusCode::OK, "status {}", resp.status);
+    let keys = extract_all(&resp.text(), "Key");
+    ensure!(keys == ["a", "b", "c"], "keys {:?}", keys);
+    ensure!(
+        resp.text().contains("<KeyCount>3</KeyCount>"),
+        "body {}",
+        resp.text()
+    );
+    Ok(())
+}
+
+async fn list_objects_v2_delimiter(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    for key in ["2024/a.jpg", "2024/b.jpg", "2025/c.jpg", "top.jpg"] {
+        app.put_object("photos", key, b"x").await;
+    }
+    let resp = app
+        .call(
+            Method::GET,
+            "/photos?list-type=2&delimiter=/",
+            Body::empty(),
+        )
+        .await;
+    let text = resp.text();
+    ensure!(extract_all(&text, "Key") == ["top.jpg"], "body {}", text);
+    ensure!(
+        extract_all(&text, "Prefix")
+            .iter()
+            .filter(|p| !p.is_empty())
+            .collect::<Vec<_>>()
+            == ["2024/", "2025/"],
+        "body {}",
+        text
+    );
+    Ok(())
+}
+
+async fn list_objects_v2_literal_prefix(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    for key in ["100%/a", "100x/b", "a_b", "axb", "A_b", "q*", "q[1]", "qz"] {
+        app.put_object("photos", key, b"x").await;
+    }
+    for (prefix, expected) in [
+        ("100%25", vec!["100%/a"]),
+        ("a_", vec!["a_b"]),
+        ("q*", vec!["q*"]),
+        ("q[", vec!["q[1]"]),
+    ] {
+        let resp = app
+            .call(
+                Method::GET,
+                &format!("/photos?list-type=2&prefix={}", prefix),
+                Body::empty(),
+            )
+            .await;
+        let keys = extract_all(&resp.text(), "Key");
+        ensure!(keys == expected, "prefix {}: keys {:?}", prefix, keys);
+    }
+    let resp = app
+        .call(
+            Method::GET,
+            "/photos?list-type=2&prefix=a%01",
+            Body::empty(),
+        )
+        .await;
+    ensure!(
+        resp.status == StatusCode::BAD_REQUEST,
+        "control character prefix {}",
+        resp.status
+    );
+    Ok(())
+}
+
+async fn list_objects_v2_pagination(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    for key in ["k1", "k2", "k3", "k4", "k5"] {
+        app.put_object("photos", key, b"x").await;
+    }
+    let mut seen = Vec::new();
+    let mut token: Option<String> = None;
+    for _ in 0..10 {
+        let uri = match &token {
+            Some(t) => format!(
+                "/photos?list-type=2&max-keys=2&continuation-token={}",
+                t.replace('=', "%3D").replace('+', "%2B")
+            ),
+            None => "/photos?list-type=2&max-keys=2".to_string(),
+        };
+        let text = app.call(Method::GET, &uri, Body::empty()).await.text();
+        seen.extend(extract_all(&text, "Key"));
+        token = extract_all(&text, "NextContinuationToken")
+            .into_iter()
+            .next();
+        if token.is_none() {
+            break;
+        }
+    }
+    ensure!(seen == ["k1", "k2", "k3", "k4", "k5"], "visited {:?}", seen);
+    Ok(())
+}
+
+async fn list_objects_v2_delimiter_pagination(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    for key in ["a/1", "a/2", "a/3", "b", "c/1", "c/2", "d"] {
+        app.put_object("photos", key, b"x").await;
+    }
+    let mut pages = Vec::new();
+    let mut token: Option<String> = None;
+    for _ in 0..10 {
+        let mut uri = "/photos?list-type=2&delimiter=/&max-keys=2".to_string();
+        if let Some(t) = &token {
+            uri.push_str(&format!(
+                "&continuation-token={}",
+                t.replace('=', "%3D")
+                    .replace('+', "%2B")
+                    .replace('/', "%2F")
+            ));
+        }
+        let text = app.call(Method::GET, &uri, Body::empty()).await.text();
+        let mut page = extract_all(&text, "Key");
+        page.extend(
+            extract_all(&text, "Prefix")
+                .into_iter()
+                .filter(|p| !p.is_empty()),
+        );
+        page.sort();
+        ensure!(
+            extract_all(&text, "KeyCount") == [page.len().to_string()],
+            "key count of {}",
+            text
+        );
+        pages.push(page);
+        token = extract_all(&text, "NextContinuationToken")
+            .into_iter()
+            .next();
+        if token.is_none() {
+            ensure!(
+                text.contains("<IsTruncated>false</IsTruncated>"),
+                "last page {}",
+                text
+            );
+            break;
+        }
+        ensure!(
+            text.contains("<IsTruncated>true</IsTruncated>"),
+            "page {}",
+            text
+        );
+    }
+    ensure!(
+        pages == [vec!["a/", "b"], vec!["c/", "d"]],
+        "pages {:?}",
+        pages
+    );
+    Ok(())
+}
+
+async fn list_objects_v2_large_common_prefixes(app: &TestApp) -> CaseResult {
+    let encode = |text: &str| {
+        percent_encoding::utf8_percent_encode(text, percent_encoding::NON_ALPHANUMERIC).to_string()
+    };
+    app.create_bucket("photos").await;
+    // `¿` ends in the byte 0xBF, which has no successor in UTF-8.
+    for delimiter in ["/", "¿"] {
+        let mut expected = Vec::new();
+        for group in ["a", "b", "c"] {
+            for n in 0..7 {
+                let key = format!("p{}{}{}{}", delimiter, group, delimiter, n);
+                app.send(
+                    Request::builder()
+                        .method(Method::PUT)
+                        .uri(format!("/photos/{}", encode(&key)))
+                        .body(Body::from("x"))
+                        .unwrap(),
+                )
+                .await;
+            }
+            expected.push(format!("p{}{}{}", delimiter, group, delimiter));
+            let loose = format!("p{}{}.txt", delimiter, group);
+            app.send(
+                Request::builder()
+                    .method(Method::PUT)
+                    .uri(format!("/photos/{}", encode(&loose)))
+                    .body(Body::from("x"))
+                    .unwrap(),
+            )
+            .await;
+            expected.push(loose);
+        }
+
+        let mut seen = Vec::new();
+        let mut token: Option<String> = None;
+        for _ in 0..10 {
+            let mut uri = format!(
+                "/photos?list-type=2&encoding-type=url&max-keys=2&prefix=p{}&delimiter={}",
+                encode(delimiter),
+                encode(delimiter)
+            );
+            if let Some(t) = &token {
+                uri.push_str(&format!("&continuation-token={}", encode(t)));
+            }
+            let text = app.call(Method::GET, &uri, Body::empty()).await.text();
+            let decode = |value: String| {
+                percent_encoding::percent_decode_str(&value)
+                    .decode_utf8_lossy()
+                    .into_owned()
+            };
+            let mut page: Vec<String> = extract_all(&text, "Key").into_iter().map(decode).collect();
+            page.extend(
+                extract_all(&text, "Prefix")
+                    .into_iter()
+                    .map(decode)
+                    .filter(|p| !p.is_empty() && *p != format!("p{}", delimiter)),
+            );
+            seen.extend(page);
+            token = extract_all(&text, "NextContinuationToken")
+                .into_iter()
+                .next();
+            if token.is_none() {
+                break;
+            }
+        }
+        seen.sort();
+        expected.sort();
+        ensure!(
+            seen == expected,
+            "delimiter {:?}: listed {:?}",
+            delimiter,
+            seen
+        );
+    }
+    Ok(())
+}
+
+async fn list_objects_v2_encoding_and_owner(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    app.put_object("photos", "dir/a+b&c.txt", b"x").await;
+    let text = app
+        .call(
+            Method::GET,
+            "/photos?list-type=2&encoding-type=url&fetch-owner=true",
+            Body::empty(),
+        )
+        .await
+        .text();
+    ensure!(
+        extract_all(&text, "Key") == ["dir/a%2Bb%26c.txt"],
+        "encoded keys in {}",
+        text
+    );
+    ensure!(
+        text.contains("<EncodingType>url</EncodingType>"),
+        "body {}",
+        text
+    );
+    let owner: uuid::Uuid =
+        sqlx::query_scalar("SELECT owner_id FROM buckets WHERE name = 'photos'")
+            .fetch_one(&*app.service.db)
+            .await
+            .map_err(|err| err.to_string())?;
+    ensure!(
+        text.contains(&format!("<Owner><ID>{}</ID>", owner)),
+        "owner in {}",
+        text
+    );
+    let plain = app
+        .call(Method::GET, "/photos?list-type=2", Body::empty())
+        .await
+        .text();
+    ensure!(
+        !plain.contains("<Owner>"),
+        "owner without fetch-owner {}",
+        plain
+    );
+    let resp = app
+        .call(
+            Method::GET,
+            "/photos?list-type=2&encoding-type=base64",
+            Body::empty(),
+        )
+        .await;
+    ensure!(
+        resp.status == StatusCode::BAD_REQUEST,
+        "unknown encoding {}",
+        resp.status
+    );
+    Ok(())
+}
+
+async fn special_character_keys(app: &TestApp) -> CaseResult {
+    // (key, path as an SDK encodes it); sorted by key bytes.
+    const KEYS: [(&str, &str); 6] = [
+        ("100%.txt", "100%25.txt"),
+        ("a b.txt", "a%20b.txt"),
+        ("c+d.txt", "c%2Bd.txt"),
+        ("emoji 😀?&=.txt", "emoji%20%F0%9F%98%80%3F%26%3D.txt"),
+        ("semi;colon#hash.txt", "semi%3Bcolon%23hash.txt"),
+        ("日本/é.txt", "%E6%97%A5%E6%9C%AC/%C3%A9.txt"),
+    ];
+    app.create_bucket("unicode").await;
+    for (key, path) in KEYS {
+        let uri = format!("/unicode/{}", path);
+        let put = app.call(Method::PUT, &uri, key.to_string()).await;
+        ensure!(put.status == StatusCode::OK, "put {} {}", key, put.status);
+        let get = app.call(Method::GET, &uri, Body::empty()).await;
+        ensure!(
+            get.status == StatusCode::OK && get.text() == key,
+            "get {} {} {}",
+            key,
+            get.status,
+            get.text()
+        );
+        let head = app.call(Method::HEAD, &uri, Body::empty()).await;
+        ensure!(
+            head.status == StatusCode::OK,
+            "head {} {}",
+            key,
+            head.status
+        );
+    }
+    let plus = app
+        .call(Method::GET, "/unicode/c+d.txt", Body::empty())
+        .await;
+    ensure!(
+        plus.text() == "c+d.txt",
+        "`+` is not a space {}",
+        plus.text()
+    );
+
+    let encoded = app
+        .call(
+            Method::GET,
+            "/unicode?list-type=2&encoding-type=url",
+            Body::empty(),
+        )
+        .await
+        .text();
+    let paths: Vec<&str> = KEYS.iter().map(|(_, path)| *path).collect();
+    ensure!(
+        extract_all(&encoded, "Key") == paths,
+        "encoded listing {}",
+        encoded
+    );
+    let plain = app
+        .call(Method::GET, "/unicode?list-type=2", Body::empty())
+        .await
+        .text();
+    ensure!(
+        plain.contains("<Key>日本/é.txt</Key>") && plain.contains("<Key>emoji 😀?&amp;=.txt</Key>"),
+        "plain listing {}",
+        plain
+    );
+    let prefixed = app
+        .call(
+            Method::GET,
+            "/unicode?list-type=2&encoding-type=url&prefix=%E6%97%A5&delimiter=/",
+            Body::empty(),
+        )
+        .await
+        .text();
+    ensure!(
+        extract_all(&prefixed, "Prefix") == ["%E6%97%A5", "%E6%97%A5%E6%9C%AC/"],
+        "unicode prefix {}",
+        prefixed
+    );
+    let versions = app
+        .call(
+            Method::GET,
+            "/unicode?versions&encoding-type=url&prefix=c%2B",
+            Body::empty(),
+        )
+        .await
+        .text();
+    ensure!(
+        extract_all(&versions, "Key") == ["c%2Bd.txt"]
+            && versions.contains("<EncodingType>url</EncodingType>"),
+        "versions {}",
+        versions
+    );
+
+    let copy = app
+        .send(
+            Request::builder()
+                .method(Method::PUT)
+                .uri("/unicode/copy%20%C3%A9.txt")
+                .header(
+                    "x-amz-copy-source",
+                    "/unicode/%E6%97%A5%E6%9C%AC/%C3%A9.txt",
+                )
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+    ensure!(copy.status == StatusCode::OK, "copy {}", copy.status);
+    let copied = app
+        .call(Method::GET, "/unicode/copy%20%C3%A9.txt", Body::empty())
+        .await;
+    ensure!(copied.text() == "日本/é.txt", "copied {}", copied.text());
+
+    // A `%` that starts no escape is part of the key.
+    app.call(Method::PUT, "/unicode/50%+off%zz", "sale").await;
+    let literal = app
+        .call(Method::GET, "/unicode/50%25+off%25zz", Body::empty())
+        .await;
+    ensure!(literal.text() == "sale", "literal % {}", literal.status);
+    for uri in ["/unicode/bad%FF.txt", "/unicode/bad%C3%28"] {
+        let resp = app.call(Method::PUT, uri, "x").await;
+        ensure!(
+            resp.status == StatusCode::BAD_REQUEST && resp.text().contains("UTF-8"),
+            "{} {} {}",
+            uri,
+            resp.status,
+            resp.text()
+        );
+    }
+    let bad_copy = app
+        .send(
+            Request::builder()
+                .method(Method::PUT)
+                .uri("/unicode/copy2.txt")
+                .header("x-amz-copy-source", "/unicode/bad%FF.txt")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+    ensure!(
+        bad_copy.status == StatusCode::BAD_REQUEST,
+        "non-UTF-8 copy source {}",
+        bad_copy.status
+    );
+
+    let deleted = app
+        .call(Method::DELETE, "/unicode/100%25.txt", Body::empty())
+        .await;
+    ensure!(
+        deleted.status == StatusCode::NO_CONTENT,
+        "delete {}",
+        deleted.status
+    );
+    let gone = app
+        .call(Method::GET, "/unicode/100%25.txt", Body::empty())
+        .await;
+    ensure!(
+        gone.status == StatusCode::NOT_FOUND,
+        "after delete {}",
+        gone.status
+    );
+    Ok(())
+}
+
+async fn list_objects_v1_marker(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    for key in ["a/1", "a/2", "b", "c", "d/1"] {
+        app.put_object("photos", key, b"x").await;
+    }
+    let mut seen = Vec::new();
+    let mut marker = String::new();
+    for _ in 0..10 {
+        let text = app
+            .call(
+                Method::GET,
+                &format!("/photos?delimiter=/&max-keys=2&marker={}", marker),
+                Body::empty(),
+            )
+            .await
+            .text();
+        ensure!(!text.contains("<KeyCount>"), "V2 response {}", text);
+        ensure!(text.contains("<Owner><ID>"), "no owner in {}", text);
+        ensure!(
+            extract_all(&text, "Marker") == [marker.replace("%2F", "/")],
+            "marker echoed in {}",
+            text
+        );
+        seen.extend(extract_all(&text, "Key"));
+        seen.extend(
+            extract_all(&text, "Prefix")
+                .into_iter()
+                .filter(|prefix| !prefix.is_empty()),
+        );
+        match extract_all(&text, "NextMarker").into_iter().next() {
+            Some(next) => marker = next.replace('/', "%2F"),
+            None => {
+                ensure!(
+                    text.contains("<IsTruncated>false</IsTruncated>"),
+                    "last page {}",
+                    text
+                );
+                break;
+            }
+        }
+    }
+    seen.sort();
+    ensure!(seen == ["a/", "b", "c", "d/"], "visited {:?}", seen);
+
+    let resp = app
+        .call(Method::GET, "/photos?list-type=3", Body::empty())
+        .await;
+    ensure!(
+        resp.status == StatusCode::BAD_REQUEST,
+        "list-type=3 {}",
+        resp.status
+    );
+    Ok(())
+}
+
+async fn create_multipart_upload(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    let resp = app
+        .call(Method::POST, "/photos/big.bin?uploads", Body::empty())
+        .await;
+    ensure!(resp.status == StatusCode::OK, "status {}", resp.status);
+    ensure!(resp.text().contains("<UploadId>"), "body {}", resp.text());
+    Ok(())
+}
+
+/// Initiate a multipart upload and return its id.
+async fn initiate_upload(app: &TestApp, uri: &str) -> Result<String, String> {
+    let resp = app
+        .call(Method::POST, &format!("{}?uploads", uri), Body::empty())
+        .await;
+    ensure!(resp.status == StatusCode::OK, "initiate {}", resp.status);
+    extract_all(&resp.text(), "UploadId")
+        .into_iter()
+        .next()
+        .ok_or_else(|| format!("no UploadId in {}", resp.text()))
+}
+
+/// Upload one part and return its ETag.
+async fn put_part(
+    app: &TestApp,
+    uri: &str,
+    upload_id: &str,
+    part: u32,
+    body: Vec<u8>,
+) -> Result<String, String> {
+    let resp = app
+        .call(
+            Method::PUT,
+            &format!("{}?partNumber={}&uploadId={}", uri, part, upload_id),
+            Body::from(body),
+        )
+        .await;
+    ensure!(
+        resp.status == StatusCode::OK,
+        "part {} {}",
+        part,
+        resp.status
+    );
+    resp.header("etag")
+        .map(str::to_string)
+        .ok_or_else(|| format!("part {} has no ETag", part))
+}
+
+fn complete_body(parts: &[(u32, &str)]) -> String {
+    let mut xml = String::from("<CompleteMultipartUpload>");
+    for (number, etag) in parts {
+        xml.push_str(&format!(
+            "<Part><PartNumber>{}</PartNumber><ETag>{}</ETag></Part>",
+            number, etag
+        ));
+    }
+    xml.push_str("</CompleteMultipartUpload>");
+    xml
+}
+
+async fn complete_multipart_upload(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    let upload_id = initiate_upload(app, "/photos/big.bin").await?;
+    let first = vec![b'a'; 5 * 1024 * 1024];
+    // Parts may arrive out of order; completion order is what counts.
+    let etag2 = put_part(app, "/photos/big.bin", &upload_id, 2, b"tail".to_vec()).await?;
+    let etag1 = put_part(app, "/photos/big.bin", &upload_id, 1, first.clone()).await?;
+
+    let resp = app
+        .call(
+            Method::POST,
+            &format!("/photos/big.bin?uploadId={}", upload_id),
+            Body::from(complete_body(&[(1, &etag1), (2, &etag2)])),
+        )
+        .await;
+    ensure!(
+        resp.status == StatusCode::OK,
+        "complete {} {}",
+        resp.status,
+        resp.text()
+    );
+    let etag = extract_all(&resp.text(), "ETag").join("");
+    ensure!(etag.ends_with("-2&quot;"), "etag {}", etag);
+
+    let resp = app
+        .call(Method::GET, "/photos/big.bin", Body::empty())
+        .await;
+    let mut expected = first;
+    expected.extend_from_slice(b"tail");
+    ensure!(resp.status == StatusCode::OK, "get {}", resp.status);
+    ensure!(
+        resp.body == expected,
+        "body length {} != {}",
+        resp.body.len(),
+        expected.len()
+    );
+    Ok(())
+}
+
+async fn complete_multipart_upload_resumes(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    let upload_id = initiate_upload(app, "/photos/big.bin").await?;
+    let first = vec![b'a'; 5 * 1024 * 1024];
+    let etag1 = put_part(app, "/photos/big.bin", &upload_id, 1, first.clone()).await?;
+    let etag2 = put_part(app, "/photos/big.bin", &upload_id, 2, b"tail".to_vec()).await?;
+
+    // A crash after the first part was assembled leaves this behind.
+    let upload_dir = app
+        .service
+        .base_path
+        .join(".multipart/photos")
+        .join(&upload_id);
+    tokio::fs::write(upload_dir.join(".assembly"), &first)
+        .await
+        .map_err(|err| err.to_string())?;
+    let parts = format!(
+        r#"[{{"part_number":1,"etag":{}}},{{"part_number":2,"etag":{}}}]"#,
+        etag1, etag2
+    );
+    let bucket_id: uuid::Uuid = sqlx::query_scalar("SELECT id FROM buckets WHERE name = 'photos'")
+        .fetch_one(&*app.service.db)
+        .await
+        .map_err(|err| err.to_string())?;
+    sqlx::query(
+        "INSERT INTO multipart_completions (
+            upload_id, bucket_id, key, parts, state, parts_total, parts_assembled,
+            bytes_total, bytes_assembled, started_at, updated_at
+         ) VALUES (?, ?, 'big.bin', ?, 'assembling', 2, 1, ?, ?, ?, ?)",
+    )
+    .bind(uuid::Uuid::parse_str(&upload_id).map_err(|err| err.to_string())?)
+    .bind(bucket_id)
+    .bind(&parts)
+    .bind(first.len() as i64 + 4)
+    .bind(first.len() as i64)
+    .bind(chrono::Utc::now())
+    .bind(chrono::Utc::now())
+    .execute(&*app.service.db)
+    .await
+    .map_err(|err| err.to_string())?;
+
+    let status_uri = format!("/photos/big.bin?uploadId={}&completion", upload_id);
+    let status = app.call(Method::GET, &status_uri, Body::empty()).await;
+    let report: serde_json::Value = serde_json::from_slice(&status.body).unwrap_or_default();
+    ensure!(
+        status.status == StatusCode::OK
+            && report["state"] == "assembling"
+            && report["parts_assembled"] == 1
+            && report["parts_total"] == 2,
+        "status before resuming {} {}",
+        status.status,
+        report
+    );
+
+    let resumed = app
+        .service
+        .resume_multipart_assemblies()
+        .await
+        .map_err(|err| err.to_string())?;
+    ensure!(resumed == 1, "resumed {}", resumed);
+    let mut report = serde_json::Value::Null;
+    for _ in 0..250 {
+        let status = app.call(Method::GET, &status_uri, Body::empty()).await;
+        report = serde_json::from_slice(&status.body).unwrap_or_default();
+        if report["state"] != "assembling" {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    ensure!(
+        report["state"] == "completed"
+            && report["bytes_assembled"] == first.len() + 4
+            && report["etag"]
+                .as_str()
+                .is_some_and(|etag| etag.ends_with("-2")),
+        "status after resuming {}",
+        report
+    );
+
+    let get = app
+        .call(Method::GET, "/photos/big.bin", Body::empty())
+        .await;
+    let mut expected = first;
+    expected.extend_from_slice(b"tail");
+    ensure!(
+        get.status == StatusCode::OK && get.body == expected,
+        "get {} ({} bytes)",
+        get.status,
+        get.body.len()
+    );
+
+    // A retry of the completion gets the object it produced.
+    let retry = app
+        .call(
+            Method::POST,
+            &format!("/photos/big.bin?uploadId={}", upload_id),
+            Body::from(complete_body(&[(1, &etag1), (2, &etag2)])),
+        )
+        .await;
+    ensure!(
+        retry.status == StatusCode::OK && retry.text().contains("-2&quot;"),
+        "retry {} {}",
+        retry.status,
+        retry.text()
+    );
+    Ok(())
+}
+
+async fn complete_multipart_upload_part_too_small(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    let upload_id = initiate_upload(app, "/photos/big.bin").await?;
+    let etag1 = put_part(app, "/photos/big.bin", &upload_id, 1, b"tiny".to_vec()).await?;
+    let etag2 = put_part(app, "/photos/big.bin", &upload_id, 2, b"tail".to_vec()).await?;
+    let resp = app
+        .call(
+            Method::POST,
+            &format!("/photos/big.bin?uploadId={}", upload_id),
+            Body::from(complete_body(&[(1, &etag1), (2, &etag2)])),
+        )
+        .await;
+    ensure!(
+        resp.status == StatusCode::BAD_REQUEST,
+        "status {}",
+        resp.status
+    );
+    Ok(())
+}
+
+async fn upload_part_bad_digest(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    let upload_id = initiate_upload(app, "/photos/big.bin").await?;
+    let resp = app
+        .send(
+            Request::builder()
+                .method(Method::PUT)
+                .uri(format!(
+                    "/photos/big.bin?partNumber=1&uploadId={}",
+                    upload_id
+                ))
+                .header("content-md5", "XrY7u+Ae7tCTyyK7j1rNww==")
+                .body(Body::from("part"))
+                .unwrap(),
+        )
+        .await;
+    ensure!(
+        resp.status == StatusCode::BAD_REQUEST,
+        "status {}",
+        resp.status
+    );
+    let parts = app
+        .call(
+            Method::GET,
+            &format!("/photos/big.bin?uploadId={}", upload_id),
+            Body::empty(),
+        )
+        .await;
+    ensure!(
+        !parts.text().contains("<Part>"),
+        "rejected part listed: {}",
+        parts.text()
+    );
+    Ok(())
+}
+
+async fn list_parts(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    let upload_id = initiate_upload(app, "/photos/big.bin").await?;
+    for part in [3, 1, 2] {
+        put_part(app, "/photos/big.bin", &upload_id, part, b"x".to_vec()).await?;
+    }
+    let resp = app
+        .call(
+            Method::GET,
+            &format!("/photos/big.bin?uploadId={}&max-parts=2", upload_id),
+            Body::empty(),
+        )
+        .await;
+    ensure!(resp.status == StatusCode::OK, "status {}", resp.status);
+    let text = resp.text();
+    ensure!(
+        extract_all(&text, "PartNumber") == ["1", "2"],
+        "body {}",
+        text
+    );
+    ensure!(
+        text.contains("<IsTruncated>true</IsTruncated>"),
+        "body {}",
+        text
+    );
+    Ok(())
+}
+
+async fn abort_multipart_upload(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    let upload_id = initiate_upload(app, "/photos/big.bin").await?;
+    put_part(app, "/photos/big.bin", &upload_id, 1, b"x".to_vec()).await?;
+    let uri = format!("/photos/big.bin?uploadId={}", upload_id);
+    let resp = app.call(Method::DELETE, &uri, Body::empty()).await;
+    ensure!(
+        resp.status == StatusCode::NO_CONTENT,
+        "abort {}",
+        resp.status
+    );
+    let resp = app.call(Method::GET, &uri, Body::empty()).await;
+    ensure!(
+        resp.status == StatusCode::NOT_FOUND,
+        "list after abort {}",
+        resp.status
+    );
+    Ok(())
+}
+
+async fn gc_multipart_uploads(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    let stale = initiate_upload(app, "/photos/stale.bin").await?;
+    put_part(app, "/photos/stale.bin", &stale, 1, b"abandoned".to_vec()).await?;
+    backdate(app, "UPDATE multipart_uploads SET initiated_at = ?", 2).await;
+    let fresh = initiate_upload(app, "/photos/fresh.bin").await?;
+    put_part(app, "/photos/fresh.bin", &fresh, 1, b"x".to_vec()).await?;
+
+    let summary = app
+        .service
+        .gc_multipart_uploads(std::time::Duration::from_secs(24 * 3600))
+        .await
+        .map_err(|err| err.to_string())?;
+    ensure!(
+        summary.aborted_uploads == 1 && summary.freed_bytes == 9,
+        "reaped {:?}",
+        summary
+    );
+    let parts_dir = app.service.base_path.join(".multipart/photos");
+    ensure!(
+        !parts_dir.join(&stale).exists() && parts_dir.join(&fresh).exists(),
+        "part directories after reaping"
+    );
+    let rows: i64 = sqlx::query_scalar("SELECT COUNT(*) FROM multipart_parts")
+        .fetch_one(&*app.service.db)
+        .await
+        .map_err(|err| err.to_string())?;
+    ensure!(rows == 1, "{} part rows left", rows);
+    for (key, upload_id, status) in [
+        ("stale.bin", &stale, StatusCode::NOT_FOUND),
+        ("fresh.bin", &fresh, StatusCode::OK),
+    ] {
+        let resp = app
+            .call(
+                Method::GET,
+                &format!("/photos/{}?uploadId={}", key, upload_id),
+                Body::empty(),
+            )
+            .await;
+        ensure!(resp.status == status, "{} is {}", key, resp.status);
+    }
+    Ok(())
+}
+
+fn attributes_request(uri: &str, attributes: &str) -> Request<Body> {
+    Request::builder()
+        .method(Method::GET)
+        .uri(format!("{}?attributes", uri))
+        .header("x-amz-object-attributes", attributes)
+        .body(Body::empty())
+        .unwrap()
+}
+
+async fn get_object_attributes(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    app.put_object("photos", "cat.jpg", b"meow").await;
+    let resp = app
+        .send(attributes_request("/photos/cat.jpg", "ETag,ObjectSize"))
+        .await;
+    ensure!(
+        resp.status == StatusCode::OK,
+        "attributes {} {}",
+        resp.status,
+        resp.text()
+    );
+    let xml = resp.text();
+    ensure!(
+        extract_all(&xml, "ETag") == [format!("{:x}", md5::compute(b"meow"))],
+        "etag in {}",
+        xml
+    );
+    ensure!(extract_all(&xml, "ObjectSize") == ["4"], "size in {}", xml);
+    ensure!(
+        extract_all(&xml, "StorageClass").is_empty() && !xml.contains("<ObjectParts>"),
+        "unrequested attributes in {}",
+        xml
+    );
+
+    let resp = app
+        .send(attributes_request("/photos/cat.jpg", "Colour"))
+        .await;
+    ensure!(
+        resp.status == StatusCode::BAD_REQUEST,
+        "unknown attribute {}",
+        resp.status
+    );
+    Ok(())
+}
+
+async fn get_object_attributes_parts(app: &TestApp) -> CaseResult {
+    app.create_bucket("photos").await;
+    let upload_id = initiate_upload(app, "/photos/big.bin").await?;
+    let etag1 = put_part(
+        app,
+        "/photos/big.bin",
+        &upload_id,
+        1,
+        vec![b'a'; 5 * 1024 * 1024],
+    )
+    .await?;
+    let etag3 = put_part(app, "/photos/big.bin", &upload_id, 3, b"tail".to_vec()).await?;
+    let resp = app
+        .call(
+            Method::POST,
+            &format!("/photos/big.bin?uploadId={}", upload_id),
+            Body::from(complete_body(&[(1, &etag1), (3, &etag3)])),
+        )
+        .await;
+    ensure!(resp.status == StatusCode::OK, "complete {}", resp.status);
+
+    let resp = app
+        .send(attributes_request(
+            "/photos/big.bin",
+            "ObjectParts,StorageClass",
+        ))
+        .await;
+    let xml = resp.text();
+    ensure!(
+        resp.status == StatusCode::OK,
+        "attributes {} {}",
+        resp.status,
+        xml
+    );
+    ensure!(
+        extract_all(&xml, "TotalPartsCount") == ["2"]
+            && extract_all(&xml, "PartNumber") == ["1", "3"]
+            && extract_all(&xml, "Size") == ["5242880", "4"],
+        "parts in {}",
+        xml
+    );
+    ensure!(
+        extract_all(&xml, "StorageClass") == ["STANDARD"],
+        "storage class in {}",
+        xml
+    );
+
+    // A plain upload over the key has no parts.
+    app.put_object("photos", "big.bin", b"small").await;
+    let resp = app
+        .send(attributes_request("/photos/big.bin", "ObjectParts"))
+        .await;
+    ensure!(
+        !resp.text().contains("<ObjectParts>"),
+        "parts after overwrite in {}",
+        resp.text()
+    );
+    Ok(())
+}
+
+async fn get_object_attributes_parts_paged(app: &TestApp) -> CaseResult {
+    use base64::{Engine as _, engine::general_purpose::STANDARD};
+
+    app.create_bucket("backups").await;
+    let upload_id = initiate_upload(app, "/backups/disk.img").await?;
+    let mut completed = Vec::new();
+    let mut crcs = Vec::new();
+    for (number, body) in [
+        (1, vec![b'a'; 5 * 1024 * 1024]),
+        (2, vec![b'b'; 5 * 1024 * 1024]),
+        (3, b"tail".to_vec()),
+    ] {
+        let crc = STANDARD.encode(crc32c::crc32c(&body).to_be_bytes());
+        let resp = app
+            .send(
+                Request::builder()
+                    .method(Method::PUT)
+                    .uri(format!(
+                        "/backups/disk.img?partNumber={}&uploadId={}",
+                        number, upload_id
+                    ))
+                    .header("x-amz-checksum-crc32c", &crc)
+                    .body(Body::from(body))
+                    .unwrap(),
+            )
+            .await;
+        ensure!(
+            resp.status == StatusCode::OK,
+            "part {} {}",
+            number,
+            resp.status
+        );
+        completed.push((number, resp.header("etag").unwrap_or_default().to_string()));
+        crcs.push(crc);
+    }
+    let parts: Vec<(u32, &str)> = completed
+        .iter()
+        .map(|(number, etag)| (*number, etag.as_str()))
+        .collect();
+    let resp = app
+        .call(
+            Method::POST,
+            &format!("/backups/disk.img?uploadId={}", upload_id),
+            Body::from(complete_body(&parts)),
+        )
+        .await;
+    ensure!(resp.status == StatusCode::OK, "complete {}", resp.status);
+
+    let page = |marker: Option<&str>| {
+        let mut builder = Request::builder()
+            .method(Method::GET)
+            .uri("/backups/disk.img?attributes")
+            .header("x-amz-object-attributes", "ObjectParts")
+            .header("x-amz-max-parts", "2");
+        if let Some(marker) = marker {
+            builder = builder.header("x-amz-part-number-marker", marker);
+        }
+        builder.body(Body::empty()).unwrap()
+    };
+    let first = app.send(page(None)).await.text();
+    ensure!(
+        extract_all(&first, "TotalPartsCount") == ["3"]
+            && extract_all(&first, "PartNumber") == ["1", "2"]
+            && extract_all(&first, "MaxParts") == ["2"]
+            && extract_all(&first, "IsTruncated") == ["true"]
+            && extract_all(&first, "NextPartNumberMarker") == ["2"],
+        "first page {}",
+        first
+    );
+    ensure!(
+        extract_all(&first, "ChecksumCRC32C") == crcs[..2],
+        "part checksums in {}",
+        first
+    );
+    let second = app.send(page(Some("2"))).await.text();
+    ensure!(
+        extract_all(&second, "TotalPartsCount") == ["3"]
+            && extract_all(&second, "PartNumberMarker") == ["2"]
+            && extract_all(&second, "PartNumber") == ["3"]
+            && extract_all(&second, "IsTruncated") == ["false"]
+            && extract_all(&second, "ChecksumCRC32C") == crcs[2..],
+        "second page {}",
+        second
+    );
+
+    let resp = app
+        .send(
+            Request::builder()
+                .method(Method::GET)
+                .uri("/backups/disk.img?attributes")
+                .header("x-amz-part-number-marker", "soon")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+    ensure!(
+        resp.status == StatusCode::BAD_REQUEST,
+        "bad marker {}",
+        resp.status
+    );
+    Ok(())
+}
+
+async fn put_lifecycle(app: &TestApp, bucket: &str, rules: &str) -> CaseResult {
+    let resp = app
+        .call(
+            Method::PUT,
+            &format!("/{}?lifecycle", bucket),
+            Body::from(format!(
+                "<LifecycleConfiguration>{}</LifecycleConfiguration>",
+                rules
+            )),
+        )
+        .await;
+    ensure!(
+        resp.status == StatusCode::OK,
+        "put lifecycle {} {}",
+        resp.status,
+        resp.text()
+    );
+    Ok(())
+}
+
+async fn bucket_config_if_match(app: &TestApp) -> CaseResult {
+    use object_store::middleware::config_etag::config_etags;
+
+    let router = app
+        .router
+        .clone()
+        .layer(axum::middleware::from_fn_with_state(
+            app.service.clone(),
+            config_etags,
+        ));
+    let request = |method: Method, uri: &str, condition: Option<(&str, &str)>, body: &str| {
+        let mut builder = Request::builder().method(method).uri(uri);
+        if let Some((name, value)) = condition {
+            builder = builder.header(name, value);
+        }
+        builder.body(Body::from(body.to_string())).unwrap()
+    };
+    let rules = |days: u32| {
+        format!(
+            concat!(
+                "<LifecycleConfiguration><Rule><ID>logs</ID><Filter><Prefix>logs/</Prefix>",
+                "</Filter><Status>Enabled</Status><Expiration><Days>{}</Days></Expiration>",
+                "</Rule></LifecycleConfiguration>"
+            ),
+            days
+        )
+    };
+
+    app.create_bucket("shared").await;
+    let first = app
+        .send_via(
+            router.clone(),
+            request(Method::PUT, "/shared?lifecycle", None, &rules(30)),
+        )
+        .await;
+    let etag = first.header("etag").unwrap_or_default().to_string();
+    ensure!(
+        first.status == StatusCode::OK && !etag.is_empty(),
+        "first put {} {:?}",
+        first.status,
+        etag
+    );
+    let get = app
+        .send_via(
+            router.clone(),
+            request(Method::GET, "/shared?lifecycle", None, ""),
+        )
+        .await;
+    ensure!(
+        get.header("etag") == Some(etag.as_str()),
+        "get etag {:?} after put {}",
+        get.header("etag"),
+        etag
+    );
+    let cached = app
+        .send_via(
+            router.clone(),
+            request(
+                Method::GET,
+                "/shared?lifecycle",
+                Some(("if-none-match", &etag)),
+                "",
+            ),
+        )
+        .await;
+    ensure!(
+        cached.status == StatusCode::NOT_MODIFIED,
+        "if-none-match {}",
+        cached.status
+    );
+
+    // Two admins update from the same read; the second one is refused.
+    let second = app
+        .send_via(
+            router.clone(),
+            request(
+                Method::PUT,
+                "/shared?lifecycle",
+                Some(("if-match", &etag)),
+                &rules(7),
+            ),
+        )
+        .await;
+    let newer = second.header("etag").unwrap_or_default().to_string();
+    ensure!(
+        second.status == StatusCode::OK && newer != etag,
+        "matching if-match {} {:?}",
+        second.status,
+        newer
+    );
+    let stale = app
+        .send_via(
+            router.clone(),
+            request(
+                Method::PUT,
+                "/shared?lifecycle",
+                Some(("if-match", &etag)),
+                &rules(90),
+            ),
+        )
+        .await;
+    ensure!(
+        stale.status == StatusCode::PRECONDITION_FAILED
+            && stale.header("etag") == Some(newer.as_str()),
+        "stale if-match {} {}",
+        stale.status,
+        stale.text()
+    );
+    let stored = app
+        .call(Method::GET, "/shared?lifecycle", Body::empty())
+        .await
+        .text();
+    ensure!(
+        extract_all(&stored, "Days") == ["7"],
+        "stale update applied: {}",
+        stored
+    );
+    let refused_delete = app
+        .send_via(
+            router.clone(),
+            request(
+                Method::DELETE,
+                "/shared?lifecycle",
+                Some(("if-match", &etag)),
+                "",
+            ),
+        )
+        .await;
+    ensure!(
+        refused_delete.status == StatusCode::PRECONDITION_FAILED,
+        "stale delete {}",
+        refused_delete.status
+    );
+
+    // Admin documents work the same way.
+    let quota = app
+        .send_via(
+            router.clone(),
+            request(Method::GET, "/admin/buckets/shared/quota", None, ""),
+        )
+        .await;
+    let quota_etag = quota.header("etag").unwrap_or_default().to_string();
+    ensure!(
+        !quota_etag.is_empty() && quota_etag != newer,
+        "quota etag {:?}",
+        quota_etag
+    );
+    let put_quota = |condition: &str| {
+        Request::builder()
+            .method(Method::PUT)
+            .uri("/admin/buckets/shared/quota")
+            .header("content-type", "application/json")
+            .header("if-match", condition)
+            .body(Body::from(r#"{"max_objects": 5}"#))
+            .unwrap()
+    };
+    let updated = app.send_via(router.clone(), put_quota(&quota_etag)).await;
+    ensure!(
+        updated.status == StatusCode::OK,
+        "quota update {}",
+        updated.status
+    );
+    let conflict = app.send_via(router.clone(), put_quota(&quota_etag)).await;
+    ensure!(
+        conflict.status == StatusCode::PRECONDITION_FAILED,
+        "stale quota update {}",
+        conflict.status
+    );
+    let any = app.send_via(router.clone(), put_quota("*")).await;
+    ensure!(any.status == StatusCode::OK, "if-match * {}", any.status);
+    Ok(())
+}
+
+/// Pretend `sql` rows (objects or uploads) were written `days` ago.
+async fn search_objects_across_buckets(app: &TestApp) -> CaseResult {
+    app.create_bucket("alpha").await;
+    app.create_bucket("beta").await;
+    app.put_object("alpha", "a.tmp", b"12345").await;
+    app.put_object("alpha", "keep.txt", b"12345").await;
+    app.put_object("alpha", "old.tmp", b"12345").await;
+    app.put_object("beta", "b.tmp", b"1").await;
+    app.put_object("beta", "c.tmp", b"12345").await;
+    app.put_object("beta", "gone.tmp", b"12345").await;
+    app.call(Method::DELETE, "/beta/gone.tmp", Body::empty())
+        .await;
+    backdate(
+        app,
+        "UPDATE objects SET last_modified = ? WHERE key = 'old.tmp'",
+        30,
+    )
+    .await;
+
+    let search = |query: String| async move {
+        let resp = app
+            .call(
+                Method::GET,
+                &format!("/admin/objects?{query}"),
+                Body::empty(),
+            )
+            .await;
+        let page: serde_json::Value = serde_json::from_slice(&resp.body).unwrap_or_default();
+        (resp.status, page)
+    };
+    let found = |page: &serde_json::Value| -> Vec<String> {
+        page["objects"]
+            .as_array()
+            .into_iter()
+            .flatten()
+            .map(|o| {
+                format!(
+                    "{}/{}",
+                    o["bucket"].as_str().unwrap_or_default(),
+                    o["key"].as_str().unwrap_or_default()
+                )
+            })
+            .collect()
+    };
+
+    let mut token = String::new();
+    let mut seen = Vec::new();
+    for _ in 0..4 {
+        let (status, page) = search(format!("pattern=*.tmp&min_size=2&max_keys=1{token}")).await;
+        ensure!(status == StatusCode::OK, "search {}", status);
+        seen.extend(found(&page));
+        match page["next_continuation_token"].as_str() {
+            Some(next) => token = format!("&continuation_token={next}"),
+            None => break,
+        }
+    }
+    ensure!(
+        seen == ["alpha/a.tmp", "alpha/old.tmp", "beta/c.tmp"],
+        "paged search found {:?}",
+        seen
+    );
+
+    let (_, page) = search("prefix=o&older_than_secs=86400".to_string()).await;
+    ensure!(
+        found(&page) == ["alpha/old.tmp"],
+        "old objects {:?}",
+        found(&page)
+    );
+    let (_, page) = search("pattern=*.tmp&newer_than_secs=86400&max_size=1".to_string()).await;
+    ensure!(
+        found(&page) == ["beta/b.tmp"],
+        "recent small objects {:?}",
+        found(&page)
+    );
+    ensure!(page["is_truncated"] == false, "single page {}", page);
+
+    let owner: uuid::Uuid = sqlx::query_scalar("SELECT owner_id FROM buckets WHERE name = 'beta'")
+        .fetch_one(&*app.service.db)
+        .await
+        .map_err(|err| err.to_string())?;
+    let (_, page) = search(format!("owner={owner}")).await;
+    ensure!(
+        found(&page) == ["beta/b.tmp", "beta/c.tmp"],
+        "owner's objects {:?}",
+        found(&page)
+    );
+
+    let (status, _) = search("continuation_token=%21%21".to_string()).await;
+    ensure!(status == StatusCode::BAD_REQUEST, "bad token {}", status);
+    Ok(())
+}
+
+async fn backdate(app: &TestApp, sql: &str, days: i64) {
+    sqlx::query(sql)
+        .bind(chrono::Utc::now() - chrono::Duration::days(days))
+        .execute(&*app.service.db)
+        .await
+        .expect("backdate rows");
+}
+
+async fn put_bucket_lifecycle(app: &TestApp) -> CaseResult {
+    app.create_bucket("logs").await;
+    let resp = app
+        .call(Method::GET, "/logs?lifecycle", Body::empty())
+        .await;
+    ensure!(
+        resp.status == StatusCode::NOT_FOUND,
+        "get before put {}",
+        resp.status
+    );
+    put_lifecycle(
+        app,
+        "logs",
+        concat!(
+            "<Rule><ID>old-logs</ID><Filter><Prefix>app/</Prefix></Filter>",
+            "<Status>Enabled</Status><Expiration><Days>30</Days></Expiration></Rule>",
+            "<Rule><ID>tmp</ID><Filter><And><Prefix>tmp/</Prefix>",
+            "<Tag><Key>scratch</Key><Value>yes</Value></Tag></And></Filter>",
+            "<Status>Disabled</Status><Expiration><Days>1</Days></Expiration></Rule>",
+        ),
+    )
+    .await?;
+    let resp = app
+        .call(Method::GET, "/logs?lifecycle", Body::empty())
+        .await;
+    let xml = resp.text();
+    ensure!(resp.status == StatusCode::OK, "get {} {}", resp.status, xml);
+    ensure!(
+        extract_all(&xml, "ID") == ["old-logs", "tmp"]
+            && extract_all(&xml, "Prefix") == ["app/", "tmp/"]
+            && extract_all(&xml, "Status") == ["Enabled", "Disabled"]
+            && extract_all(&xml, "Days") == ["30", "1"]
+            && extract_all(&xml, "Key") == ["scratch"],
+        "rules in {}",
+        xml
+    );
+
+    let resp = app
+        .call(Method::DELETE, "/logs?lifecycle", Body::empty())
+        .await;
+    ensure!(
+        resp.status == StatusCode::NO_CONTENT,
+        "delete {}",
+        resp.status
+    );
+    let resp = app
+        .call(Method::GET, "/logs?lifecycle", Body::empty())
+        .await;
+    ensure!(
+        resp.status == StatusCode::NOT_FOUND,
+        "get after delete {}",
+        resp.status
+    );
+    Ok(())
+}
+
+async fn put_bucket_lifecycle_unsupported(app: &TestApp) -> CaseResult {
+    app.create_bucket("logs").await;
+    for rule in [
+        concat!(
+            "<Rule><ID>cold</ID><Status>Enabled</Status>",
+            "<Transition><Days>30</Days><StorageClass>GLACIER</StorageClass></Transition></Rule>"
+        ),
+        "<Rule><ID>none</ID><Status>Enabled</Status></Rule>",
+        "<Rule><ID>zero</ID><Status>Enabled</Status><Expiration><Days>0</Days></Expiration></Rule>",
+    ] {
+        let resp = app
+            .call(
+                Method::PUT,
+                "/logs?lifecycle",
+                Body::from(format!(
+                    "<LifecycleConfiguration>{}</LifecycleConfiguration>",
+                    rule
+                )),
+            )
+            .await;
+        ensure!(
+            resp.status == StatusCode::BAD_REQUEST,
+            "{} accepted: {}",
+            rule,
+            resp.status
+        );
+    }
+    Ok(())
+}
+
+async fn lifecycle_expiration(app: &TestApp) -> CaseResult {
+    app.create_bucket("logs").await;
+    app.put_object("logs", "app/old.log", b"old").await;
+    app.put_object("logs", "db/old.log", b"old").await;
+    app.put_object("logs", "tmp/keep.log", b"old").await;
+    app.put_object("logs", "tmp/scratch.log", b"old").await;
+    let resp = app
+        .call(
+            Method::PUT,
+            "/logs/tmp/scratch.log?tagging",
+            Body::from(concat!(
+                "<Tagging><TagSet><Tag><Key>scratch</Key><Value>yes</Value></Tag>",
+                "</TagSet></Tagging>"
+            )),
+        )
+        .await;
+    ensure!(resp.status == StatusCode::OK, "tagging {}", resp.status);
+    backdate(app, "UPDATE objects SET last_modified = ?", 40).await;
+    app.put_object("logs", "app/new.log", b"new").await;
+    put_lifecycle(
+        app,
+        "logs",
+        concat!(
+            "<Rule><ID>app</ID><Filter><Prefix>app/</Prefix></Filter>",
+            "<Status>Enabled</Status><Expiration><Days>30</Days></Expiration></Rule>",
+            "<Rule><ID>scratch</ID><Filter><Tag><Key>scratch</Key><Value>yes</Value></Tag>",
+            "</Filter><Status>Enabled</Status><Expiration><Days>7</Days></Expiration></Rule>",
+        ),
+    )
+    .await?;
+
+    let summary = app
+        .service
+        .run_lifecycle()
+        .await
+        .map_err(|err| err.to_string())?;
+    ensure!(summary.expired == 2, "expired {}", summary.expired);
+    for (key, status) in [
+        ("app/old.log", StatusCode::NOT_FOUND),
+        ("app/new.log", StatusCode::OK),
+        ("db/old.log", StatusCode::OK),
+        ("tmp/keep.log", StatusCode::OK),
+        ("tmp/scratch.log", StatusCode::NOT_FOUND),
+    ] {
+        let resp = app
+            .call(Method::HEAD, &format!("/logs/{}", key), Body::empty())
+            .await;
+        ensure!(resp.status == status, "{} is {}", key, resp.status);
+    }
+    Ok(())
+}
+
+async fn lifecycle_dry_run(app: &TestApp) -> CaseResult {
+    app.create_bucket("logs").await;
+    app.put_object("logs", "app/a.log", b"aaaa").await;
+    app.put_object("logs", "app/b.log", b"bb").await;
+    app.put_object("logs", "db/old.log", b"old").await;
+    backdate(app, "UPDATE objects SET last_modified = ?", 40).await;
+    app.put_object("logs", "app/new.log", b"new").await;
+    initiate_upload(app, "/logs/app/stale.bin").await?;
+    backdate(app, "UPDATE multipart_uploads SET initiated_at = ?", 10).await;
+
+    let config = concat!(
+        "<LifecycleConfiguration>",
+        "<Rule><ID>app</ID><Filter><Prefix>app/</Prefix></Filter><Status>Enabled</Status>",
+        "<Expiration><Days>30</Days></Expiration>",
+        "<AbortIncompleteMultipartUpload><DaysAfterInitiation>7</DaysAfterInitiation>",
+        "</AbortIncompleteMultipartUpload></Rule>",
+        "<Rule><ID>off</ID><Status>Disabled</Status><Expiration><Days>1</Days></Expiration></Rule>",
+        "</LifecycleConfiguration>"
+    );
+    let resp = app
+        .call(Method::POST, "/logs?lifecycle-dry-run", Body::from(config))
+        .await;
+    ensure!(
+        resp.status == StatusCode::OK,
+        "dry run {} {}",
+        resp.status,
+        resp.text()
+    );
+    let report: serde_json::Value =
+        serde_json::from_slice(&resp.body).map_err(|e| e.to_string())?;
+    let app_rule = &report["rules"][0];
+    ensure!(
+        app_rule["expire"]["count"] == 2 && app_rule["expire"]["bytes"] == 6,
+        "expire {}",
+        app_rule
+    );
+    ensure!(
+        app_rule["expire"]["samples"] == serde_json::json!(["app/a.log", "app/b.log"]),
+        "samples {}",
+        app_rule
+    );
+    ensure!(
+        app_rule["abort_incomplete_uploads"]["samples"] == serde_json::json!(["app/stale.bin"]),
+        "uploads {}",
+        app_rule
+    );
+    ensure!(
+        report["rules"][1]["enabled"] == false && report["rules"][1]["expire"].is_null(),
+        "disabled rule {}",
+        report["rules"][1]
+    );
+
+    let resp = app
+        .call(Method::GET, "/logs?lifecycle", Body::empty())
+        .await;
+    ensure!(
+        resp.status == StatusCode::NOT_FOUND,
+        "stored rules {}",
+        resp.status
+    );
+    let resp = app
+        .call(Method::HEAD, "/logs/app/a.log", Body::empty())
+        .await;
+    ensure!(
+        resp.status == StatusCode::OK,
+        "expired by dry run {}",
+        resp.status
+    );
+    let resp = app
+        .call(
+            Method::POST,
+            "/logs?lifecycle-dry-run",
+            Body::from("<LifecycleConfiguration></LifecycleConfiguration>"),
+        )
+        .await;
+    ensure!(
+        resp.status == StatusCode::BAD_REQUEST,
+        "empty configuration {}",
+        resp.status
+    );
+    Ok(())
+}
+
+async fn lifecycle_abort_incomplete_uploads(app: &TestApp) -> CaseResult {
+    app.create_bucket("logs").await;
+    let stale = initiate_upload(app, "/logs/big/stale.bin").await?;
+    backdate(app, "UPDATE multipart_uploads SET initiated_at = ?", 10).await;
+    let fresh = initiate_upload(app, "/logs/big/fresh.bin").await?;
+    put_lifecycle(
+        app,
+        "logs",
+        concat!(
+            "<Rule><ID>uploads</ID><Filter></Filter><Status>Enabled</Status>",
+            "<AbortIncompleteMultipartUpload><DaysAfterInitiation>7</DaysAfterInitiation>",
+            "</AbortIncompleteMultipartUpload></Rule>",
+        ),
+    )
+    .await?;
+
+    let summary = app
+        .service
+        .run_lifecycle()
+        .await
+        .map_err(|err| err.to_string())?;
+    ensure!(
+        summary.aborted_uploads == 1,
+        "aborted {}",
+        summary.aborted_uploads
+    );
+    for (key, upload_id, status) in [
+        ("stale.bin", &stale, StatusCode::NOT_FOUND),
+        ("fresh.bin", &fresh, StatusCode::OK),
+    ] {
+        let resp = app
+            .call(
+                Method::GET,
+                &format!("/logs/big/{}?uploadId={}", key, upload_id),
+                Body::empty(),
+            )
+            .await;
+        ensure!(resp.status == status, "{} is {}", key, resp.status);
+    }
+    Ok(())
+}
+
+/// A remote tier kept in memory.
+#[derive(Debug, Default)]
+struct MemoryTier(std::sync::Mutex<BTreeMap<String, Vec<u8>>>);
+
+impl object_store::services::blob_store::BlobStore for MemoryTier {
+    fn tier(&self) -> &'static str {
+        "memory"
+    }
+
+    fn put<'a>(
+        &'a self,
+        key: &'a str,
+        path: &'a std::path::Path,
+    ) -> BoxFuture<'a, std::io::Result<()>> {
+        Box::pin(async move {
+            let data = tokio::fs::read(path).await?;
+            self.0.lock().unwrap().insert(key.to_string(), data);
+            Ok(())
+        })
+    }
+
+    fn get<'a>(
+        &'a self,
+        key: &'a str,
+    ) -> BoxFuture<'a, std::io::Result<object_store::services::content_encoding::ByteStream>> {
+        Box::pin(async move {
+            let data = self.0.lock().unwrap().get(key).cloned();
+            let data = data.ok_or_else(|| std::io::Error::from(std::io::ErrorKind::NotFound))?;
+            let stream: object_store::services::content_encoding::ByteStream =
+                Box::pin(futures::stream::once(async move {
+                    Ok(bytes::Bytes::from(data))
+                }));
+            Ok(stream)
+        })
+    }
+
+    fn delete<'a>(&'a self, key: &'a str) -> BoxFuture<'a, std::io::Result<()>> {
+        Box::pin(async move {
+            self.0.lock().unwrap().remove(key);
+            Ok(())
+        })
+    }
+}
+
+async fn lifecycle_transition(app: &TestApp) -> CaseResult {
+    let rule = concat!(
+        "<Rule><ID>cold</ID><Filter><Prefix>old/</Prefix></Filter><Status>Enabled</Status>",
+        "<Transition><Days>30</Days><StorageClass>GLACIER</StorageClass></Transition></Rule>",
+    );
+    // Without a remote tier the rule is refused.
+    app.create_bucket("logs").await;
+    let resp = app
+        .call(
+            Method::PUT,
+            "/logs?lifecycle",
+            Body::from(format!(
+                "<LifecycleConfiguration>{}</LifecycleConfiguration>",
+                rule
+            )),
+        )
+        .await;
+    ensure!(
+        resp.status == StatusCode::BAD_REQUEST,
+        "transition without tier {}",
+        resp.status
+    );
+
+    let tier = std::sync::Arc::new(MemoryTier::default());
+    let remote = tier.clone();
+    let app = TestApp::with_service(move |mut service| {
+        service.options.remote_tier = Some(remote);
+        service
+    })
+    .await;
+    app.create_bucket("logs").await;
+    app.put_object("logs", "old/a.log", b"cold data").await;
+    app.put_object("logs", "new/b.log", b"other").await;
+    backdate(&app, "UPDATE objects SET last_modified = ?", 40).await;
+    put_lifecycle(&app, "logs", rule).await?;
+    let resp = app
+        .call(Method::GET, "/logs?lifecycle", Body::empty())
+        .await;
+    ensure!(
+        resp.text().contains("<StorageClass>GLACIER</StorageClass>"),
+        "rules {}",
+        resp.text()
+    );
+
+    let summary = app
+        .service
+        .run_lifecycle()
+        .await
+        .map_err(|err| err.to_string())?;
+    ensure!(
+        summary.transitioned == 1,
+        "transitioned {}",
+        summary.transitioned
+    );
+    ensure!(tier.0.lock().unwrap().len() == 1, "remote blobs");
+    let local_files = count_files(&app.service.base_path.join("logs"));
+    ensure!(local_files == 1, "{} local payloads", local_files);
+
+    let head = app
+        .call(Method::HEAD, "/logs/old/a.log", Body::empty())
+        .await;
+    ensure!(
+        head.header("x-amz-storage-class") == Some("GLACIER"),
+        "head storage class {:?}",
+        head.header("x-amz-storage-class")
+    );
+    let get = app
+        .call(Method::GET, "/logs/old/a.log", Body::empty())
+        .await;
+    ensure!(
+        get.status == StatusCode::OK && get.body == b"cold data".as_slice(),
+        "get {} {}",
+        get.status,
+        get.text()
+    );
+    let range = Request::builder()
+        .uri("/logs/old/a.log")
+        .header("range", "bytes=5-8")
+        .body(Body::empty())
+        .unwrap();
+    let ranged = app.send(range).await;
+    ensure!(
+        ranged.status == StatusCode::PARTIAL_CONTENT && ranged.body == b"data".as_slice(),
+        "range {} {}",
+        ranged.status,
+        ranged.text()
+    );
+    let list = app
+        .call(Method::GET, "/logs?list-type=2", Body::empty())
+        .await;
+    ensure!(
+        list.text().contains("<StorageClass>GLACIER</StorageClass>"),
+        "list {}",
+        list.text()
+    );
+
+    // Overwriting leaves the remote blob orphaned until the next pass.
+    app.put_object("logs", "old/a.log", b"fresh").await;
+    let head = app
+        .call(Method::HEAD, "/logs/old/a.log", Body::empty())
+        .await;
+    ensure!(
+        head.header("x-amz-storage-class").is_none(),
+        "overwritten storage class {:?}",
+        head.header("x-amz-storage-class")
+    );
+    let summary = app
+        .service
+        .run_lifecycle()
+        .await
+        .map_err(|err| err.to_string())?;
+    ensure!(
+        summary.transitioned == 0 && summary.collected_blobs == 1,
+        "second pass {:?}",
+        summary
+    );
+    ensure!(tier.0.lock().unwrap().is_empty(), "orphan blob kept");
+    Ok(())
+}
+
+async fn naming_policy_enforced(app: &TestApp) -> CaseResult {
+    app.create_bucket("shared").await;
+    app.put_object("shared", "legacy/a/b/c/d.exe", b"old").await;
+    let put = |policy: &'static str| {
+        app.send(
+            Request::builder()
+                .method(Method::PUT)
+                .uri("/admin/buckets/shared/naming-policy")
+                .header("content-type", "application/json")
+                .body(Body::from(policy))
+                .unwrap(),
+        )
+    };
+    let resp = put(r#"{"allow": ["("]}"#).await;
+    ensure!(
+        resp.status == StatusCode::BAD_REQUEST,
+        "bad pattern {}",
+        resp.status
+    );
+    let resp = put(concat!(
+        r#"{"allow": ["[a-z0-9._/-]+"], "required_prefix": "(team-a|team-b)/","#,
+        r#" "max_depth": 3, "forbidden_extensions": [".EXE", "tar.gz"]}"#
+    ))
+    .await;
+    ensure!(
+        resp.status == StatusCode::OK && resp.text().contains(r#"["exe","tar.gz"]"#),
+        "put policy {} {}",
+        resp.status,
+        resp.text()
+    );
+
+    app.put_object("shared", "team-a/reports/q1.csv", b"ok")
+        .await;
+    for (key, rule) in [
+        ("team-c/x.csv", "does not start"),
+        ("team-a/Upper.csv", "allowed patterns"),
+        ("team-b/a/b/c.csv", "levels deep"),
+        ("team-b/setup.exe", "`.exe`"),
+        ("team-b/dump.tar.gz", "`.tar.gz`"),
+    ] {
+        let resp = app
+            .call(Method::PUT, &format!("/shared/{}", key), "data")
+            .await;
+        ensure!(
+            resp.status == StatusCode::BAD_REQUEST && resp.text().contains(rule),
+            "{}: {} {}",
+            key,
+            resp.status,
+            resp.text()
+        );
+    }
+    let copy = app
+        .send(
+            Request::builder()
+                .method(Method::PUT)
+                .uri("/shared/elsewhere.csv")
+                .header("x-amz-copy-source", "/shared/team-a/reports/q1.csv")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await;
+    ensure!(
+        copy.status == StatusCode::BAD_REQUEST,
+        "copy destination {}",
+        copy.status
+    );
+    let upload = app
+        .call(Method::POST, "/shared/setup.exe?uploads", Body::empty())
+        .await;
+    ensure!(
+        upload.status == StatusCode::BAD_REQUEST,
+        "multipart {}",
+        upload.status
+    );
+    let legacy = app
+        .call(Method::GET, "/shared/legacy/a/b/c/d.exe", Body::empty())
+        .await;
+    ensure!(
+        legacy.status == StatusCode::OK,
+        "legacy key {}",
+        legacy.status
+    );
+
+    let removed = app
+        .call(
+            Method::DELETE,
+            "/admin/buckets/shared/naming-policy",
+            Body::empty(),
+        )
+        .await;
+    ensure!(
+        removed.status == StatusCode::NO_CONTENT,
+        "delete policy {}",
+        removed.status
+    );
+    let resp = app
+        .call(
+            Method::GET,
+            "/admin/buckets/shared/naming-policy",
+            Body::empty(),
+        )
+        .await;
+    ensure!(resp.text() == "{}", "policy after delete {}", resp.text());
+    app.put_object("shared", "anything/goes.exe", b"ok").await;
+    Ok(())
+}
+
+async fn auto_tagging_on_upload(app: &TestApp) -> CaseResult {
+    app.create_bucket("lake").await;
+    app.put_object("lake", "ingest/old.txt", b"old").await;
+    let put = |rules: &'static str| {
+        app.send(
+            Request::builder()
+                .method(Method::PUT)
+                .uri("/admin/buckets/lake/auto-tagging")
+                .header("content-type", "application/json")
+                .body(Body::from(rules))
+                .unwrap(),
+        )
+    };
+    for bad in [
+        r#"{"rules": [{"prefix": "ingest/", "tags": {}}]}"#,
+        r#"{"rules": [{"key_pattern": "(", "tags": {"a": "b"}}]}"#,
+        r#"{"rules": [{"min_size": 10, "max_size": 1, "tags": {"a": "b"}}]}"#,
+    ] {
+        let resp = put(bad).await;
+        ensure!(
+            resp.status == StatusCode::BAD_REQUEST,
+            "{} {}",
+            bad,
+            resp.status
+        );
+    }
+    let resp = put(concat!(
+        r#"{"rules": ["#,
+        r#"{"prefix": "ingest/", "tags": {"tier": "raw"}},"#,
+        r#"{"content_type": "Image/*", "min_size": 4, "tags": {"media": "image", "tier": "media"}},"#,
+        r#"{"key_pattern": ".*\\.parquet", "uploader": "etl-writer", "tags": {"source": "etl"}}"#,
+        r#"]}"#
+    ))
+    .await;
+    ensure!(
+        resp.status == StatusCode::OK && resp.text().contains(r#""content_type":"image/*""#),
+        "put rules {} {}",
+        resp.status,
+        resp.text()
+    );
+
+    let upload = |key: &str, headers: &[(&str, &str)], body: &'static [u8]| {
+        let mut request = Request::builder()
+            .method(Method::PUT)
+            .uri(format!("/lake/{}", key));
+        for (name, value) in headers {
+            request = request.header(*name, *value);
+        }
+        app.send(request.body(Body::from(body)).unwrap())
+    };
+    let tags = |key: &'static str| async move {
+        let resp = app
+            .call(
+                Method::GET,
+                &format!("/lake/{}?tagging", key),
+                Body::empty(),
+            )
+            .await;
+        let keys = extract_all(&resp.text(), "Key");
+        let values = extract_all(&resp.text(), "Value");
+        keys.into_iter()
+            .zip(values)
+            .map(|(k, v)| format!("{}={}", k, v))
+            .collect::<Vec<_>>()
+    };
+
+    upload("ingest/a.txt", &[], b"data").await;
+    ensure!(tags("ingest/a.txt").await == ["tier=raw"], "prefix rule");
+    upload(
+        "ingest/p.png",
+        &[("content-type", "image/png; charset=binary")],
+        b"image",
+    )
+    .await;
+    let found = tags("ingest/p.png").await;
+    ensure!(
+        found == ["media=image", "tier=raw"],
+        "first rule wins {:?}",
+        found
+    );
+    upload("small.png", &[("content-type", "image/png")], b"img").await;
+    ensure!(tags("small.png").await.is_empty(), "below min_size");
+    upload("ingest/b.txt", &[("x-amz-tagging", "tier=gold")], b"data").await;
+    let found = tags("ingest/b.txt").await;
+    ensure!(found == ["tier=gold"], "client tag kept {:?}", found);
+
+    upload("t.parquet", &[], b"cols").await;
+    ensure!(tags("t.parquet").await.is_empty(), "anonymous uploader");
+    upload(
+        "t.parquet",
+        &[("authorization", "AWS etl-writer:c2lnbmF0dXJl")],
+        b"cols",
+    )
+    .await;
+    ensure!(
+        tags("t.parquet").await.is_empty(),
+        "unverified uploader {:?}",
+        tags("t.parquet").await
+    );
+
+    let copy = upload(
+        "ingest/copy.txt",
+        &[("x-amz-copy-source", "/lake/small.png")],
+        b"",
+    )
+    .await;
+    ensure!(copy.status == StatusCode::OK, "copy {}", copy.status);
+    let found = tags("ingest/copy.txt").await;
+    ensure!(found == ["tier=raw"], "copy {:?}", found);
+
+    let upload_id = initiate_upload(app, "/lake/ingest/big.bin").await?;
+    let etag = put_part(app, "/lake/ingest/big.bin", &upload_id, 1, b"part".to_vec()).await?;
+    let resp = app
+        .call(
+            Method::POST,
+            &format!("/lake/ingest/big.bin?uploadId={}", upload_id),
+            Body::from(complete_body(&[(1, &etag)])),
+        )
+        .await;
+    ensure!(resp.status == StatusCode::OK, "complete {}", resp.status);
+    ensure!(tags("ingest/big.bin").await == ["tier=raw"], "multipart");
+    ensure!(tags("ingest/old.txt").await.is_empty(), "existing object");
+
+    let crowded = upload(
+        "ingest/full.txt",
+        &[("x-amz-tagging", "a=1&b=2&c=3&d=4&e=5&f=6&g=7&h=8&i=9&j=10")],
+        b"data",
+    )
+    .await;
+    ensure!(
+        crowded.status == StatusCode::BAD_REQUEST,
+        "over the tag limit {}",
+        crowded.status
+    );
+
+    let removed = app
+        .call(
+            Method::DELETE,
+            "/admin/buckets/lake/auto-tagging",
+            Body::empty(),
+        )
+        .await;
+    ensure!(
+        removed.status == StatusCode::NO_CONTENT,
+        "delete rules {}",
+        removed.status
+    );
+    upload("ingest/c.txt", &[], b"data").await;
+    ensure!(tags("ingest/c.txt").await.is_empty(), "rules removed");
+    Ok(())
+}
+
+async fn auto_tagging_verified_uploader(_app: &TestApp) -> CaseResult {
+    use object_store::services::aws_chunked::AccessKeys;
+
+    let app = TestApp::with_service(|mut service| {
+        service.options.access_keys = AccessKeys::new(["etl-writer:etl-secret".parse().unwrap()]);
+        service
+    })
+    .await;
+    let app = &app;
+    app.create_bucket("lake").await;
+    let rules = app
+        .send(
+            Request::builder()
+                .method(Method::PUT)
+                .uri("/admin/buckets/lake/auto-tagging")
+                .header("content-type", "application/json")
+                .body(Body::from(
+                    r#"{"rules": [{"uploader": "etl-writer", "tags": {"source": "etl"}}]}"#,
+                ))
+                .unwrap(),
+        )
+        .await;
+    ensure!(rules.status == StatusCode::OK, "put rules {}", rules.status);
+
+    let upload = |key: &str| {
+        Request::builder()
+            .method(Method::PUT)
+            .uri(format!("/lake/{}", key))
+            .body(Body::from("cols"))
+            .unwrap()
+    };
+    let tags = |key: &'static str| async move {
+        let resp = app
+            .call(
+                Method::GET,
+                &format!("/lake/{}?tagging", key),
+                Body::empty(),
+            )
+            .await;
+        extract_all(&resp.text(), "Value")
+    };
+    let now = chrono::Utc::now();
+
+    let forged = app
+        .send(sigv4_signed(
+            upload("forged.parquet"),
+            "etl-writer",
+            "guessed-secret",
+            now,
+        ))
+        .await;
+    ensure!(forged.status == StatusCode::OK, "forged {}", forged.status);
+    ensure!(
+        tags("forged.parquet").await.is_empty(),
+        "forged credential tagged {:?}",
+        tags("forged.parquet").await
+    );
+    let initiate = app
+        .send(sigv4_signed(
+            Request::builder()
+                .method(Method::POST)
+                .uri("/lake/forged.bin?uploads")
+                .body(Body::empty())
+                .unwrap(),
+            "etl-writer",
+            "guessed-secret",
+            now,
+        ))
+        .await;
+    let upload_id = extract_all(&initiate.text(), "UploadId")
+        .pop()
+        .ok_or("initiate: no upload id")?;
+    let etag = put_part(app, "/lake/forged.bin", &upload_id, 1, b"part".to_vec()).await?;
+    let complete = app
+        .call(
+            Method::POST,
+            &format!("/lake/forged.bin?uploadId={}", upload_id),
+            Body::from(complete_body(&[(1, &etag)])),
+        )
+        .await;
+    ensure!(
+        complete.status == StatusCode::OK,
+        "complete {}",
+        complete.status
+    );
+    ensure!(
+        tags("forged.bin").await.is_empty(),
+        "forged initiator tagged {:?}",
+        tags("forged.bin").await
+    );
+
+    let signed = app
+        .send(sigv4_signed(
+            upload("signed.parquet"),
+            "etl-writer",
+            "etl-secret",
+            now,
+        ))
+        .await;
+    ensure!(signed.status == StatusCode::OK, "signed {}", signed.status);
+    ensure!(
+        tags("signed.parquet").await == ["etl"],
+        "verified uploader {:?}",
+        tags("signed.parquet").await
+    );
+    Ok(())
+}
+
+/// A webhook endpoint that records request bodies and answers `status`.
+async fn spawn_fake_webhook(
+    status: std::sync::Arc<std::sync::atomic::AtomicU16>,
+) -> (String, std::sync::Arc<std::sync::Mutex<Vec<String>>>) {
+    use std::sync::{Arc, Mutex, atomic::Ordering};
+
+    let received: Arc<Mutex<Vec<String>>> = Arc::default();
+    let sink = received.clone();
+    let router = axum::Router::new().route(
+        "/hook",
+        axum::routing::post(move |body: String| {
+            let sink = sink.clone();
+            let status = status.clone();
+            async move {
+                sink.lock().unwrap().push(body);
+                StatusCode::from_u16(status.load(Ordering::SeqCst)).unwrap()
+            }
+        }),
+    );
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, router).await });
+    (format!("http://{}/hook", addr), received)
+}
+
+async fn bucket_notification_webhooks(app: &TestApp) -> CaseResult {
+    use object_store::services::notifications::NotificationDispatcher;
+    use std::sync::{Arc, atomic::AtomicU16, atomic::Ordering};
+
+    let status = Arc::new(AtomicU16::new(200));
+    let (endpoint, received) = spawn_fake_webhook(status.clone()).await;
+    app.create_bucket("hooked").await;
+    let config = |event: &str| {
+        format!(
+            concat!(
+                "<NotificationConfiguration><WebhookConfiguration><Id>indexer</Id>",
+                "<Endpoint>{}</Endpoint><Event>{}</Event><Event>s3:ObjectRemoved:*</Event>",
+                "<Filter><S3Key><FilterRule><Name>prefix</Name><Value>images/</Value>",
+                "</FilterRule></S3Key></Filter></WebhookConfiguration></NotificationConfiguration>"
+            ),
+            endpoint, event
+        )
+    };
+
+    let unsupported = app
+        .call(
+            Method::PUT,
+            "/hooked?notification",
+            config("s3:ObjectRestore:Post"),
+        )
+        .await;
+    ensure!(
+        unsupported.status == StatusCode::BAD_REQUEST,
+        "unsupported event {}",
+        unsupported.status
+    );
+    let topic = app
+        .call(
+            Method::PUT,
+            "/hooked?notification",
+            "<NotificationConfiguration><TopicConfiguration><Topic>arn:aws:sns:x</Topic>\
+             <Event>s3:ObjectCreated:*</Event></TopicConfiguration></NotificationConfiguration>",
+        )
+        .await;
+    ensure!(
+        topic.status == StatusCode::BAD_REQUEST,
+        "sns target {}",
+        topic.status
+    );
+    let put = app
+        .call(
+            Method::PUT,
+            "/hooked?notification",
+            config("s3:ObjectCreated:*"),
+        )
+        .await;
+    ensure!(
+        put.status == StatusCode::OK,
+        "put {} {}",
+        put.status,
+        put.text()
+    );
+    let get = app
+        .call(Method::GET, "/hooked?notification", Body::empty())
+        .await;
+    ensure!(
+        get.text()
+            .contains(&format!("<Endpoint>{}</Endpoint>", endpoint))
+            && get.text().contains("<Value>images/</Value>"),
+        "get {}",
+        get.text()
+    );
+
+    app.put_object("hooked", "images/a%20b.jpg", b"hello").await;
+    app.put_object("hooked", "docs/skipped", b"hello").await;
+    let delete = app
+        .call(Method::DELETE, "/hooked/images/a%20b.jpg", Body::empty())
+        .await;
+    ensure!(delete.status.is_success(), "delete {}", delete.status);
+
+    let dispatcher = NotificationDispatcher::new(
+        app.service.clone(),
+        &object_store::services::outbound::OutboundHttp::default(),
+    )
+    .map_err(|e| e.to_string())?;
+    let summary = dispatcher.run_once().await.map_err(|e| e.to_string())?;
+    ensure!(
+        summary.queued == 2 && summary.delivered == 2,
+        "first pass {:?}",
+        summary
+    );
+    let events: Vec<serde_json::Value> = received
+        .lock()
+        .unwrap()
+        .iter()
+        .map(|body| serde_json::from_str(body).unwrap())
+        .collect();
+    let names: Vec<&str> = events
+        .iter()
+        .filter_map(|event| event["Records"][0]["eventName"].as_str())
+        .collect();
+    ensure!(
+        names.contains(&"ObjectCreated:Put") && names.contains(&"ObjectRemoved:Delete"),
+        "event names {:?}",
+        names
+    );
+    let created = events
+        .iter()
+        .find(|event| event["Records"][0]["eventName"] == "ObjectCreated:Put")
+        .map(|event| &event["Records"][0]["s3"])
+        .unwrap();
+    ensure!(
+        created["bucket"]["name"] == "hooked"
+            && created["object"]["key"] == "images/a%20b.jpg"
+            && created["object"]["size"] == 5
+            && created["configurationId"] == "indexer",
+        "created event {}",
+        created
+    );
+
+    status.store(503, Ordering::SeqCst);
+    app.put_object("hooked", "images/c.jpg", b"hello").await;
+    let summary = dispatcher.run_once().await.map_err(|e| e.to_string())?;
+    ensure!(
+        summary.queued == 1 && summary.retried == 1 && summary.delivered == 0,
+        "failing pass {:?}",
+        summary
+    );
+    let (attempts, error): (i64, Option<String>) = sqlx::query_as(
+        "SELECT attempts, last_error FROM notification_deliveries WHERE state = 'pending'",
+    )
+    .fetch_one(&*app.service.db)
+    .await
+    .map_err(|e| e.to_string())?;
+    ensure!(
+        attempts == 1 && error.is_some_and(|e| e.contains("503")),
+        "queued delivery {}",
+        attempts
+    );
+    let summary = dispatcher.run_once().await.map_err(|e| e.to_string())?;
+    ensure!(
+        summary.retried == 0 && summary.delivered == 0,
+        "backoff not honoured {:?}",
+        summary
+    );
+
+    status.store(200, Ordering::SeqCst);
+    sqlx::query("UPDATE notification_deliveries SET next_attempt_at = ?")
+        .bind(chrono::Utc::now() - chrono::Duration::seconds(1))
+        .execute(&*app.service.db)
+        .await
+        .map_err(|e| e.to_string())?;
+    let summary = dispatcher.run_once().await.map_err(|e| e.to_string())?;
+    ensure!(summary.delivered == 1, "retry {:?}", summary);
+    let left: (i64,) = sqlx::query_as("SELECT COUNT(*) FROM notification_deliveries")
+        .fetch_one(&*app.service.db)
+        .await
+        .map_err(|e| e.to_string())?;
+    ensure!(left.0 == 0, "{} deliveries left", left.0);
+
+    let cleared = app
+        .call(
+            Method::PUT,
+            "/hooked?notification",
+            "<NotificationConfiguration/>",
+        )
+        .await;
+    ensure!(cleared.status == StatusCode::OK, "clear {}", cleared.status);
+    let get = app
+        .call(Method::GET, "/hooked?notification", Body::empty())
+        .await;
+    ensure!(
+        !get.text().contains("WebhookConfiguration"),
+        "cleared config {}",
+        get.text()
+    );
+    Ok(())
+}
+
+async fn placement_by_prefix(_app: &TestApp) -> CaseResult {
+    use object_store::services::placement::VolumeSpec;
+
+    let app = TestApp::with_service(|mut service| {
+        service.options.volumes = vec![VolumeSpec {
+            name: "cold".into(),
+            path: service.base_path.with_file_name("cold"),
+        }];
+        service
+    })
+    .await;
+    let cold = app.service.base_path.with_file_name("cold");
+    app.create_bucket("media").await;
+    app.put_object("media", "raw/early.bin", b"before").await;
+    let put = |policy: &'static str| {
+        app.send(
+            Request::builder()
+                .method(Method::PUT)
+                .uri("/admin/buckets/media/placement")
+                .header("content-type", "application/json")
+                .body(Body::from(policy))
+                .unwrap(),
+        )
+    };
+    let resp = put(r#"{"rules": [{"prefix": "raw/", "volume": "tape"}]}"#).await;
+    ensure!(
+        resp.status == StatusCode::BAD_REQUEST,
+        "unknown volume {}",
+        resp.status
+    );
+    let resp = put(concat!(
+        r#"{"rules": [{"prefix": "raw/", "volume": "cold"},"#,
+        r#" {"prefix": "raw/hot/", "volume": "default"}]}"#
+    ))
+    .await;
+    ensure!(resp.status == StatusCode::OK, "put policy {}", resp.status);
+
+    app.put_object("media", "raw/scan.tif", b"cold bytes").await;
+    app.put_object("media", "raw/hot/live.tif", b"hot bytes")
+        .await;
+    app.put_object("media", "thumbs/a.jpg", b"thumb").await;
+    let on = |root: &std::path::Path, name: &str| find_files(root, name).len();
+    ensure!(
+        on(&cold, "scan.tif") == 1 && on(&app.service.base_path, "scan.tif") == 0,
+        "raw/scan.tif not on the cold volume"
+    );
+    ensure!(
+        on(&cold, "live.tif") == 0 && on(&cold, "a.jpg") == 0,
+        "unmatched keys placed on the cold volume"
+    );
+    // Placed before the rule: stays on the default volume.
+    ensure!(
+        on(&app.service.base_path, "early.bin") == 1,
+        "existing payload moved"
+    );
+    for (key, body) in [
+        ("raw/scan.tif", &b"cold bytes"[..]),
+        ("raw/early.bin", b"before"),
+    ] {
+        let get = app
+            .call(Method::GET, &format!("/media/{}", key), Body::empty())
+            .await;
+        ensure!(
+            get.status == StatusCode::OK && get.body == body,
+            "get {} {}",
+            key,
+            get.status
+        );
+    }
+
+    // Overwriting with the policy removed moves the key back.
+    let resp = app
+        .call(
+            Method::DELETE,
+            "/admin/buckets/media/placement",
+            Body::empty(),
+        )
+        .await;
+    ensure!(
+        resp.status == StatusCode::NO_CONTENT,
+        "delete policy {}",
+        resp.status
+    );
+    app.put_object("media", "raw/scan.tif", b"warm bytes").await;
+    ensure!(
+        on(&cold, "scan.tif") == 0 && on(&app.service.base_path, "scan.tif") == 1,
+        "overwrite left the cold payload behind"
+    );
+    put(r#"{"rules": [{"prefix": "raw/", "volume": "cold"}]}"#).await;
+    app.put_object("media", "raw/scan.tif", b"cold again").await;
+    let resp = app
+        .call(Method::DELETE, "/media/raw/scan.tif", Body::empty())
+        .await;
+    ensure!(
+        resp.status == StatusCode::NO_CONTENT,
+        "delete {}",
+        resp.status
+    );
+    ensure!(
+        on(&cold, "scan.tif") == 0,
+        "deleted payload left on the cold volume"
+    );
+
+    let report = app
+        .service
+        .fsck(std::time::Duration::ZERO)
+        .await
+        .map_err(|e| e.to_string())?;
+    ensure!(report.is_clean(), "fsck {:?}", report);
+    let volumes = app.call(Method::GET, "/admin/volumes", Body::empty()).await;
+    ensure!(
+        volumes.text().contains(r#""name":"cold""#),
+        "volumes {}",
+        volumes.text()
+    );
+    Ok(())
+}
+
+async fn event_publishers(app: &TestApp) -> CaseResult {
+    use object_store::services::publishers::spawn_event_publishers;
+
+    app.create_bucket("uploads").await;
+    let (url, received) = spawn_fake_nats().await;
+    let put = |config: String| {
+        app.send(
+            Request::builder()
+                .method(Method::PUT)
+                .uri("/admin/buckets/uploads/publishers")
+                .header("content-type", "application/json")
+                .body(Body::from(config))
+                .unwrap(),
+        )
+    };
+    let resp = put(format!(
+        r#"{{"publishers": [{{"id": "p", "kind": "nats", "url": "{}", "subject": "up", "events": ["bucket-created"]}}]}}"#,
+        url
+    ))
+    .await;
+    ensure!(
+        resp.status == StatusCode::BAD_REQUEST,
+        "unsupported event {}",
+        resp.status
+    );
+    let resp = put(format!(
+        r#"{{"publishers": [{{"id": "ingest", "kind": "nats", "url": "{}", "subject": "uploads.raw", "events": ["object-created"], "prefix": "raw/"}}]}}"#,
+        url
+    ))
+    .await;
+    if !cfg!(feature = "nats") {
+        ensure!(
+            resp.status == StatusCode::BAD_REQUEST && resp.text().contains("`nats` feature"),
+            "publisher without the feature {} {}",
+            resp.status,
+            resp.text()
+        );
+        return Ok(());
+    }
+    ensure!(
+        resp.status == StatusCode::OK,
+        "put {} {}",
+        resp.status,
+        resp.text()
+    );
+    let get = app
+        .call(
+            Method::GET,
+            "/admin/buckets/uploads/publishers",
+            Body::empty(),
+        )
+        .await;
+    ensure!(get.text().contains("uploads.raw"), "get {}", get.text());
+
+    let worker = spawn_event_publishers(app.service.clone());
+    app.put_object("uploads", "other/skip.txt", b"x").await;
+    app.put_object("uploads", "raw/a.txt", b"hello").await;
+    app.call(Method::DELETE, "/uploads/raw/a.txt", Body::empty())
+        .await;
+    for _ in 0..200 {
+        if !received.lock().unwrap().is_empty() {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    // Leave time for messages that should not have been sent.
+    tokio::time::sleep(std::time::Duration::from_millis(100)).await;
+    let messages = received.lock().unwrap().clone();
+    worker.abort();
+    ensure!(messages.len() == 1, "messages {:?}", messages);
+    let (subject, payload) = &messages[0];
+    ensure!(
+        subject == "uploads.raw"
+            && payload.contains(r#""kind":"object-created""#)
+            && payload.contains(r#""key":"raw/a.txt""#),
+        "message {} {}",
+        subject,
+        payload
+    );
+    Ok(())
+}
+
+/// A request received by `spawn_fake_s3`.
+#[derive(Debug, Clone)]
+struct FakeS3Request {
+    method: Method,
+    path: String,
+    headers: axum::http::HeaderMap,
+    body: Vec<u8>,
+}
+
+/// Objects held by `spawn_fake_s3`, by request path (`/bucket/key`).
+type FakeS3Objects = std::sync::Arc<std::sync::Mutex<std::collections::BTreeMap<String, Vec<u8>>>>;
+
+/// An S3 endpoint that records every request and answers `status`. While
+/// that is `200` it keeps PUT objects, forgets DELETEd ones and lists them
+/// for ListObjectsV2 (`?list-type=2&prefix=`, in one page).
+async fn spawn_fake_s3(
+    status: std::sync::Arc<std::sync::atomic::AtomicU16>,
+) -> (
+    String,
+    std::sync::Arc<std::sync::Mutex<Vec<FakeS3Request>>>,
+    FakeS3Objects,
+) {
+    use axum::response::IntoResponse;
+    use std::sync::{Arc, Mutex, atomic::Ordering};
+
+    let received: Arc<Mutex<Vec<FakeS3Request>>> = Arc::default();
+    let objects: FakeS3Objects = Arc::default();
+    let (sink, store) = (received.clone(), objects.clone());
+    let router = axum::Router::new().fallback(move |request: Request<Body>| {
+        let (sink, store) = (sink.clone(), store.clone());
+        let status = StatusCode::from_u16(status.load(Ordering::SeqCst)).unwrap();
+        async move {
+            let (parts, body) = request.into_parts();
+            let body = axum::body::to_bytes(body, usize::MAX).await.unwrap();
+            let path = parts.uri.path().to_string();
+            sink.lock().unwrap().push(FakeS3Request {
+                method: parts.method.clone(),
+                path: path.clone(),
+                headers: parts.headers,
+                body: body.to_vec(),
+            });
+            if status != StatusCode::OK {
+                return status.into_response();
+            }
+            let mut store = store.lock().unwrap();
+            match parts.method {
+                Method::PUT => {
+                    store.insert(path, body.to_vec());
+                }
+                Method::DELETE => {
+                    store.remove(&path);
+                }
+                Method::GET => {
+                    let url = reqwest::Url::parse(&format!("http://fake{}", parts.uri)).unwrap();
+                    let query: std::collections::HashMap<String, String> =
+                        url.query_pairs().into_owned().collect();
+                    let prefix = format!("{}/{}", path, query.get("prefix").cloned().unwrap_or_default());
+                    let contents: String = store
+                        .iter()
+                        .filter_map(|(stored, bytes)| {
+                            let key = percent_encoding::percent_decode_str(stored).decode_utf8().ok()?;
+                            key.starts_with(&prefix).then(|| {
+                                format!(
+                                    "<Contents><Key>{}</Key><ETag>&quot;{:x}&quot;</ETag><Size>{}</Size></Contents>",
+                                    &key[path.len() + 1..],
+                                    md5::compute(bytes),
+                                    bytes.len()
+                                )
+                            })
+                        })
+                        .collect();
+                    return format!(
+                        "<ListBucketResult><IsTruncated>false</IsTruncated>{}</ListBucketResult>",
+                        contents
+                    )
+                    .into_response();
+                }
+                _ => {}
+            }
+            StatusCode::OK.into_response()
+        }
+    });
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move { axum::serve(listener, router).await });
+    (format!("http://{}", addr), received, objects)
+}
+
+async fn shard_layout_migration(app: &TestApp) -> CaseResult {
+    use object_store::services::shard_layout::Layout;
+
+    app.create_bucket("layout").await;
+    app.put_object("layout", "a/one.txt", b"one").await;
+    app.put_object("layout", "two.txt", b"two").await;
+    let bucket_root = app.service.base_path.join("layout");
+    // Shard directories between the bucket and the key.
+    let depth_of = |name: &str, key_segments: usize| -> Vec<usize> {
+        find_files(&bucket_root, name)
+            .iter()
+            .map(|path| {
+                path.strip_prefix(&bucket_root)
+                    .unwrap()
+                    .components()
+                    .count()
+                    - key_segments
+            })
+            .collect()
+    };
+    ensure!(
+        depth_of("one.txt", 2) == [2],
+        "default depth {:?}",
+        depth_of("one.txt", 2)
+    );
+
+    let queued = app
+        .service
+        .adopt_shard_layout(Layout {
+            depth: 3,
+            portable: false,
+        })
+        .await
+        .map_err(|e| e.to_string())?;
+    ensure!(queued == 1, "queued {} migrations", queued);
+    ensure!(app.service.shard_layout.is_migrating(), "not migrating");
+    let get = |key: &'static str| async move {
+        app.call(Method::GET, &format!("/layout/{}", key), Body::empty())
+            .await
+    };
+    let resp = get("a/one.txt").await;
+    ensure!(
+        resp.status == StatusCode::OK && resp.body == b"one"[..],
+        "read from the previous layout {}",
+        resp.status
+    );
+    // Writes go to the new layout and drop the copy in the old one.
+    app.put_object("layout", "two.txt", b"two again").await;
+    app.put_object("layout", "three.txt", b"three").await;
+    ensure!(
+        depth_of("two.txt", 1) == [3],
+        "overwrite {:?}",
+        depth_of("two.txt", 1)
+    );
+    ensure!(
+        depth_of("three.txt", 1) == [3],
+        "new key {:?}",
+        depth_of("three.txt", 1)
+    );
+
+    while app
+        .service
+        .run_next_job()
+        .await
+        .map_err(|e| e.to_string())?
+    {}
+    let jobs = app.service.list_jobs().await.map_err(|e| e.to_string())?;
+    let job = jobs
+        .iter()
+        .find(|job| job.kind == "shard-migration")
+        .ok_or("no migration job")?;
+    ensure!(
+        job.status == "succeeded" && job.processed == 1,
+        "job {} moved {}",
+        job.status,
+        job.processed
+    );
+    ensure!(
+        depth_of("one.txt", 2) == [3],
+        "migrated {:?}",
+        depth_of("one.txt", 2)
+    );
+    ensure!(
+        !app.service.shard_layout.is_migrating(),
+        "layouts not retired"
+    );
+    for (key, body) in [
+        ("a/one.txt", &b"one"[..]),
+        ("two.txt", b"two again"),
+        ("three.txt", b"three"),
+    ] {
+        let resp = get(key).await;
+        ensure!(
+            resp.status == StatusCode::OK && resp.body == body,
+            "get {} {}",
+            key,
+            resp.status
+        );
+    }
+    let report = app
+        .service
+        .fsck(std::time::Duration::ZERO)
+        .await
+        .map_err(|e| e.to_string())?;
+    ensure!(report.is_clean(), "fsck after migration {:?}", report);
+
+    // Restarting with the same depth has nothing to do.
+    let queued = app
+        .service
+        .adopt_shard_layout(Layout {
+            depth: 3,
+            portable: false,
+        })
+        .await
+        .map_err(|e| e.to_string())?;
+    ensure!(
+        queued == 0 && !app.service.shard_layout.is_migrating(),
+        "re-adopted"
+    );
+    Ok(())
+}
+
+async fn shard_layout_portable_names(app: &TestApp) -> CaseResult {
+    use object_store::services::shard_layout::Layout;
+
+    app.create_bucket("win").await;
+    app.put_object("win", "notes%3Adraft%3F.txt", b"draft")
+        .await;
+    app.put_object("win", "con/aux.txt", b"device").await;
+    let queued = app
+        .service
+        .adopt_shard_layout(Layout {
+            depth: 2,
+            portable: true,
+        })
+        .await
+        .map_err(|e| e.to_string())?;
+    ensure!(queued == 1, "queued {} migrations", queued);
+    let long = "l".repeat(240);
+    let fresh: Vec<String> = vec![
+        "trailing.".into(),
+        "a%2Fb%2A%7Cc".into(),
+        "a//b".into(),
+        "a/b".into(),
+        "dir/".into(),
+        format!("deep/{}", long),
+    ];
+    for key in &fresh {
+        let resp = app
+            .call(Method::PUT, &format!("/win/{}", key), key.clone())
+            .await;
+        ensure!(resp.status == StatusCode::OK, "put {} {}", key, resp.status);
+    }
+    while app
+        .service
+        .run_next_job()
+        .await
+        .map_err(|e| e.to_string())?
+    {}
+    let jobs = app.service.list_jobs().await.map_err(|e| e.to_string())?;
+    ensure!(
+        jobs.iter()
+            .any(|job| job.status == "succeeded" && job.processed == 2),
+        "jobs {:?}",
+        jobs
+    );
+    ensure!(
+        !app.service.shard_layout.is_migrating(),
+        "layouts not retired"
+    );
+
+    fn all_files(dir: &std::path::Path, out: &mut Vec<String>) {
+        for entry in std::fs::read_dir(dir).into_iter().flatten().flatten() {
+            let name = entry.file_name().to_string_lossy().into_owned();
+            if entry.path().is_dir() {
+                all_files(&entry.path(), out);
+            }
+            out.push(name);
+        }
+    }
+    let mut names = Vec::new();
+    all_files(&app.service.base_path.join("win"), &mut names);
+    for name in &names {
+        ensure!(
+            !name.contains(['<', '>', ':', '"', '|', '?', '*'])
+                && !name.ends_with('.')
+                && !name.eq_ignore_ascii_case("con")
+                && !name.to_ascii_lowercase().starts_with("aux.")
+                && name.len() <= 200,
+            "name {:?} on disk",
+            name
+        );
+    }
+    for expected in [
+        "notes%3Adraft%3F.txt",
+        "%63on",
+        "%61ux.txt",
+        "trailing%2E",
+        "%",
+    ] {
+        ensure!(
+            names.iter().any(|name| name == expected),
+            "no {:?} in {:?}",
+            expected,
+            names
+        );
+    }
+
+    let mut reads: Vec<(String, Vec<u8>)> = vec![
+        ("notes%3Adraft%3F.txt".into(), b"draft".to_vec()),
+        ("con/aux.txt".into(), b"device".to_vec()),
+    ];
+    reads.extend(
+        fresh
+            .iter()
+            .map(|key| (key.clone(), key.as_bytes().to_vec())),
+    );
+    for (key, body) in reads {
+        let resp = app
+            .call(Method::GET, &format!("/win/{}", key), Body::empty())
+            .await;
+        ensure!(
+            resp.status == StatusCode::OK && resp.body == body,
+            "get {} {} {:?}",
+            key,
+            resp.status,
+            resp.text()
+        );
+    }
+    let report = app
+        .service
+        .fsck(std::time::Duration::ZERO)
+        .await
+        .map_err(|e| e.to_string())?;
+    ensure!(report.is_clean(), "fsck {:?}", report);
+    Ok(())
+}
+
+async fn bucket_replication(app: &TestApp) -> CaseResult {
+    use object_store::services::replication::Replicator;
+    use std::sync::{Arc, atomic::AtomicU16, atomic::Ordering};
+
+    let status = Arc::new(AtomicU16::new(200));
+    let (endpoint, received, objects) = spawn_fake_s3(status.clone()).await;
+    app.create_bucket("primary").await;
+    app.put_object("primary", "before.txt", b"old").await;
+    let put = |config: String| {
+        app.send(
+            Request::builder()
+                .method(Method::PUT)
+                .uri("/admin/buckets/primary/replication")
+                .header("content-type", "application/json")
+                .body(Body::from(config))
+                .unwrap(),
+        )
+    };
+    let bad = put(
+        r#"{"destination": "ftp://backup/replica", "access_key": "AK", "secret_key": "SK"}"#.into(),
+    )
+    .await;
+    ensure!(
+        bad.status == StatusCode::BAD_REQUEST,
+        "ftp destination {}",
+        bad.status
+    );
+    let missing = app
+        .call(
+            Method::GET,
+            "/admin/buckets/primary/replication",
+            Body::empty(),
+        )
+        .await;
+    ensure!(
+        missing.status == StatusCode::NOT_FOUND,
+        "unconfigured {}",
+        missing.status
+    );
+    let resp = put(format!(
+        r#"{{"destination": "{}/replica", "access_key": "AK", "secret_key": "SK"}}"#,
+        endpoint
+    ))
+    .await;
+    ensure!(
+        resp.status == StatusCode::OK,
+        "put {} {}",
+        resp.status,
+        resp.text()
+    );
+    // Re-putting what GET returns keeps the stored secret.
+    let get = app
+        .call(
+            Method::GET,
+            "/admin/buckets/primary/replication",
+            Body::empty(),
+        )
+        .await;
+    ensure!(
+        !get.text().contains("SK") && get.text().contains("/replica"),
+        "get {}",
+        get.text()
+    );
+    let resp = put(get.text()).await;
+    ensure!(resp.status == StatusCode::OK, "re-put {}", resp.text());
+
+    let upload = app
+        .send(
+            Request::builder()
+                .method(Method::PUT)
+                .uri("/primary/docs/a%20b.txt")
+                .header("content-type", "text/plain")
+                .header("x-amz-meta-owner", "ann")
+                .body(Body::from("hello"))
+                .unwrap(),
+        )
+        .await;
+    ensure!(upload.status == StatusCode::OK, "upload {}", upload.status);
+    app.put_object("primary", "gone.txt", b"bye").await;
+    app.call(Method::DELETE, "/primary/gone.txt", Body::empty())
+        .await;
+
+    let replicator = Replicator::new(
+        app.service.clone(),
+        &object_store::services::outbound::OutboundHttp::default(),
+    )
+    .map_err(|e| e.to_string())?;
+    let summary = replicator.run_once().await.map_err(|e| e.to_string())?;
+    ensure!(
+        summary.queued == 3 && summary.replicated == 2,
+        "first pass {:?}",
+        summary
+    );
+    let requests = received.lock().unwrap().clone();
+    ensure!(requests.len() == 2, "requests {:?}", requests);
+    let uploaded = requests
+        .iter()
+        .find(|request| request.method == Method::PUT)
+        .ok_or("no PUT")?;
+    let header = |name: &str| {
+        uploaded
+            .headers
+            .get(name)
+            .and_then(|value| value.to_str().ok())
+            .unwrap_or_default()
+            .to_string()
+    };
+    ensure!(
+        uploaded.path == "/replica/docs/a%20b.txt"
+            && uploaded.body == b"hello"
+            && header("content-type") == "text/plain"
+            && header("x-amz-meta-owner") == "ann"
+            && header("authorization").starts_with("AWS4-HMAC-SHA256 Credential=AK/"),
+        "uploaded {:?}",
+        uploaded
+    );
+    ensure!(
+        requests
+            .iter()
+            .any(|request| request.method == Method::DELETE && request.path == "/replica/gone.txt"),
+        "delete not replicated {:?}",
+        requests
+    );
+    let head = app
+        .call(Method::HEAD, "/primary/docs/a%20b.txt", Body::empty())
+        .await;
+    ensure!(
+        head.header("x-amz-replication-status") == Some("COMPLETED"),
+        "replicated object status {:?}",
+        head.header("x-amz-replication-status")
+    );
+    let head = app
+        .call(Method::HEAD, "/primary/before.txt", Body::empty())
+        .await;
+    ensure!(
+        head.header("x-amz-replication-status").is_none(),
+        "object from before replication {:?}",
+        head.header("x-amz-replication-status")
+    );
+    let stats = app
+        .call(Method::GET, "/admin/buckets/primary/stats", Body::empty())
+        .await;
+    let stats: serde_json::Value =
+        serde_json::from_slice(&stats.body).map_err(|e| e.to_string())?;
+    let replication = &stats["replication"];
+    ensure!(
+        replication["completed"] == 2
+            && replication["pending"] == 0
+            && replication["lag_secs"] == 0,
+        "stats after replication {}",
+        replication
+    );
+
+    status.store(503, Ordering::SeqCst);
+    app.put_object("primary", "c.txt", b"c").await;
+    let summary = replicator.run_once().await.map_err(|e| e.to_string())?;
+    ensure!(
+        summary.queued == 1 && summary.retried == 1 && summary.replicated == 0,
+        "failing pass {:?}",
+        summary
+    );
+    let stats = app
+        .call(Method::GET, "/admin/buckets/primary/stats", Body::empty())
+        .await;
+    let stats: serde_json::Value =
+        serde_json::from_slice(&stats.body).map_err(|e| e.to_string())?;
+    ensure!(
+        stats["replication"]["pending"] == 1
+            && stats["replication"]["oldest_unreplicated_at"].is_string(),
+        "stats while failing {}",
+        stats["replication"]
+    );
+    let get = app.call(Method::GET, "/primary/c.txt", Body::empty()).await;
+    ensure!(
+        get.header("x-amz-replication-status") == Some("PENDING"),
+        "queued object status {:?}",
+        get.header("x-amz-replication-status")
+    );
+    status.store(200, Ordering::SeqCst);
+    sqlx::query("UPDATE object_replication SET next_attempt_at = ?")
+        .bind(chrono::Utc::now() - chrono::Duration::seconds(1))
+        .execute(&*app.service.db)
+        .await
+        .map_err(|e| e.to_string())?;
+    let summary = replicator.run_once().await.map_err(|e| e.to_string())?;
+    ensure!(summary.replicated == 1, "retry pass {:?}", summary);
+
+    // The replica never got `before.txt`, loses one object and gains a
+    // stray one.
+    {
+        let mut objects = objects.lock().unwrap();
+        objects.remove("/replica/docs/a%20b.txt");
+        objects.insert("/replica/stray.txt".into(), b"stray".to_vec());
+    }
+    let reconcile = app
+        .call(
+            Method::POST,
+            "/admin/buckets/primary/replication/reconcile",
+            Body::empty(),
+        )
+        .await;
+    ensure!(
+        reconcile.status == StatusCode::ACCEPTED,
+        "reconcile {} {}",
+        reconcile.text(),
+        reconcile.status
+    );
+    let job: serde_json::Value =
+        serde_json::from_slice(&reconcile.body).map_err(|e| e.to_string())?;
+    ensure!(
+        app.service
+            .run_next_job()
+            .await
+            .map_err(|e| e.to_string())?,
+        "no job ran"
+    );
+    let job = app
+        .call(
+            Method::GET,
+            &format!("/admin/jobs/{}", job["id"].as_str().unwrap_or_default()),
+            Body::empty(),
+        )
+        .await;
+    let job: serde_json::Value = serde_json::from_slice(&job.body).map_err(|e| e.to_string())?;
+    let report = &job["report"];
+    ensure!(
+        job["status"] == "succeeded"
+            && report["checked"] == 3
+            && report["in_sync"] == 1
+            && report["missing"] == 2
+            && report["extra"] == 1
+            && report["requeued"] == 3,
+        "reconcile job {}",
+        job
+    );
+    let summary = replicator.run_once().await.map_err(|e| e.to_string())?;
+    ensure!(summary.replicated == 3, "repair pass {:?}", summary);
+    let keys: Vec<String> = objects.lock().unwrap().keys().cloned().collect();
+    ensure!(
+        keys == [
+            "/replica/before.txt",
+            "/replica/c.txt",
+            "/replica/docs/a%20b.txt"
+        ],
+        "replica after repair {:?}",
+        keys
+    );
+
+    let delete = app
+        .call(
+            Method::DELETE,
+            "/admin/buckets/primary/replication",
+            Body::empty(),
+        )
+        .await;
+    ensure!(
+        delete.status == StatusCode::NO_CONTENT,
+        "delete {}",
+        delete.status
+    );
+    let stats = app
+        .call(Method::GET, "/admin/buckets/primary/stats", Body::empty())
+        .await;
+    let stats: serde_json::Value =
+        serde_json::from_slice(&stats.body).map_err(|e| e.to_string())?;
+    ensure!(stats.get("replication").is_none(), "stats {}", stats);
+    Ok(())
+}
+
+/// A NATS server that accepts one client and records `(subject, payload)`
+/// of each `PUB`.
+async fn spawn_fake_nats() -> (
+    String,
+    std::sync::Arc<std::sync::Mutex<Vec<(String, String)>>>,
+) {
+    use tokio::io::{AsyncBufReadExt, AsyncReadExt, AsyncWriteExt, BufReader};
+
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    let received = std::sync::Arc::new(std::sync::Mutex::new(Vec::new()));
+    let sink = received.clone();
+    tokio::spawn(async move {
+        let Ok((socket, _)) = listener.accept().await else {
+            return;
+        };
+        let (read, mut write) = socket.into_split();
+        let info = r#"INFO {"server_id":"fake","server_name":"fake","version":"2.10.0","go":"go1.21","host":"127.0.0.1","port":4222,"headers":true,"max_payload":1048576,"proto":1}"#;
+        let _ = write.write_all(format!("{}\r\n", info).as_bytes()).await;
+        let mut read = BufReader::new(read);
+        let mut line = String::new();
+        while read.read_line(&mut line).await.unwrap_or(0) > 0 {
+            let command = line.trim_end().to_string();
+            line.clear();
+            if command == "PING" {
+                let _ = write.write_all(b"PONG\r\n").await;
+            } else if let Some(args) = command.strip_prefix("PUB ") {
+                let parts: Vec<&str> = args.split(' ').collect();
+                let len: usize = parts.last().unwrap().parse().unwrap();
+                let mut payload = vec![0; len + 2];
+                if read.read_exact(&mut payload).await.is_err() {
+                    return;
+                }
+                payload.truncate(len);
+                sink.lock().unwrap().push((
+                    parts[0].to_string(),
+                    String::from_utf8_lossy(&payload).into_owned(),
+                ));
+            }
+        }
+    });
+    (format!("nats://{}", addr), received)
+}
+
+async fn admin_events_stream(app: &TestApp) -> CaseResult {
+    use tower::ServiceExt;
+
+    app.create_bucket("photos").await;
+    let missing = app
+        .call(Method::GET, "/admin/events?bucket=nope", Body::empty())
+        .await;
+    ensure!(
+        missing.status == StatusCode::NOT_FOUND,
+        "unknown bucket filter {}",
+        missing.status
+    );
+
+    let response = app
+        .router
+        .clone()
+        .oneshot(
+            Request::builder()
+                .uri("/admin/events?bucket=photos")
+                .body(Body::empty())
+                .unwrap(),
+        )
+        .await
+        .unwrap();
+    ensure!(
+        response.status() == StatusCode::OK
+            && response
+                .headers()
+                .get("content-type")
+                .is_some_and(|v| v == "text/event-stream"),
+        "events response {} {:?}",
+        response.status(),
+        response.headers()
+    );
+    let mut frames = response.into_body().into_data_stream();
+
+    app.create_bucket("logs").await;
+    app.put_object("logs", "skipped", b"x").await;
+    app.put_object("photos", "cat.jpg", b"meow").await;
+    app.call(Method::DELETE, "/photos/cat.jpg", Body::empty())
+        .await;
+
+    // Frames may split or batch events; collect until both have arrived.
+    let mut text = String::new();
+    let deadline = std::time::Duration::from_secs(5);
+    while text.matches("\n\n").count() < 2 {
+        match tokio::time::timeout(deadline, frames.next()).await {
+            Ok(Some(Ok(chunk))) => text.push_str(&String::from_utf8_lossy(&chunk)),
+            other => return Err(format!("stream ended early {:?}: {}", other.is_ok(), text)),
+        }
+    }
+    let events: Vec<(&str, serde_json::Value)> = text
+        .split("\n\n")
+        .filter(|block| !block.trim().is_empty())
+        .map(|block| {
+            let field = |name: &str| {
+                block
+                    .lines()
+                    .find_map(|line| line.strip_prefix(name))
+                    .unwrap_or_default()
+                    .trim()
+            };
+            (
+                field("event:"),
+                serde_json::from_str(field("data:")).unwrap_or_default(),
+            )
+        })
+        .collect();
+    ensure!(
+        events.len() == 2
+            && events[0].0 == "object-created"
+            && events[0].1["key"] == "cat.jpg"
+            && events[0].1["size_bytes"] == 4
+            && events[1].0 == "object-deleted"
+            && events[1].1["bucket"] == "photos"
+            && events[1].1["seq"].as_u64() > events[0].1["seq"].as_u64(),
+        "events {}",
+        text
+    );
+    Ok(())
+}
+
+async fn change_feed(app: &TestApp) -> CaseResult {
+    app.create_bucket("feed").await;
+    app.put_object("feed", "a.txt", b"one").await;
+    app.put_object("feed", "a.txt", b"two").await;
+    app.put_object("feed", "b.txt", b"three").await;
+    app.call(Method::DELETE, "/feed/a.txt", Body::empty()).await;
+
+    let page = |uri: String| async move {
+        let resp = app.call(Method::GET, &uri, Body::empty()).await;
+        let body = serde_json::from_slice::<serde_json::Value>(&resp.body).unwrap_or_default();
+        (resp.status, body)
+    };
+    let (status, first) = page("/feed?changes&max-keys=2".into()).await;
+    ensure!(
+        status == StatusCode::OK
+            && first["changes"][0]["event"] == "created"
+            && first["changes"][0]["key"] == "a.txt"
+            && first["changes"][0]["size_bytes"] == 3
+            && first["changes"][1]["event"] == "updated"
+            && first["is_truncated"] == true,
+        "first page {} {}",
+        status,
+        first
+    );
+    let cursor = first["next_cursor"]
+        .as_str()
+        .unwrap_or_default()
+        .to_string();
+    let (status, second) = page(format!("/feed?changes&since={}", cursor)).await;
+    ensure!(
+        status == StatusCode::OK
+            && second["changes"][0]["event"] == "created"
+            && second["changes"][0]["key"] == "b.txt"
+            && second["changes"][1]["event"] == "deleted"
+            && second["changes"][1]["key"] == "a.txt"
+            && second["changes"].as_array().map(Vec::len) == Some(2)
+            && second["is_truncated"] == false,
+        "second page {} {}",
+        status,
+        second
+    );
+    let last = second["next_cursor"]
+        .as_str()
+        .unwrap_or_default()
+        .to_string();
+    let (_, caught_up) = page(format!("/feed?changes&since={}", last)).await;
+    ensure!(
+        caught_up["changes"].as_array().is_some_and(Vec::is_empty)
+            && caught_up["next_cursor"] == last.as_str(),
+        "caught up {}",
+        caught_up
+    );
+    let (status, _) = page("/feed?changes&since=abc".into()).await;
+    ensure!(
+        status == StatusCode::BAD_REQUEST,
+        "malformed cursor {}",
+        status
+    );
+
+    // Once the feed is pruned, older cursors cannot be resumed.
+    app.service
+        .prune_changes(std::time::Duration::ZERO)
+        .await
+        .map_err(|err| err.to_string())?;
+    let (status, _) = page(format!("/feed?changes&since={}", cursor)).await;
+    ensure!(status == StatusCode::GONE, "expired cursor {}", status);
+    let (status, _) = page(format!("/feed?changes&since={}", last)).await;
+    ensure!(status == StatusCode::OK, "latest cursor {}", status);
+    Ok(())
+}
+
+/// Marker that makes the fake clamd flag a payload.
+const FLAGGED: &[u8] = b"FAKE-CLAMD-TEST-SIGNATURE";
+
+/// Answer clamd `zINSTREAM` requests on a local port, flagging payloads
+/// that contain `FLAGGED`, and return the port's `clamd:` endpoint.
+async fn spawn_fake_clamd() -> String {
+    use tokio::io::{AsyncReadExt, AsyncWriteExt};
+    let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+    let addr = listener.local_addr().unwrap();
+    tokio::spawn(async move {
+        while let Ok((mut conn, _)) = listener.accept().await {
+            tokio::spawn(async move {
+                let mut command = [0u8; 10];
+                conn.read_exact(&mut command).await?;
+                let mut payload = Vec::new();
+                loop {
+                    let len = conn.read_u32().await? as usize;
+                    if len == 0 {
+                        break;
+                    }
+                    let mut chunk = vec![0u8; len];
+                    conn.read_exact(&mut chunk).await?;
+                    payload.extend_from_slice(&chunk);
+                }
+                let reply: &[u8] = if &command != b"zINSTREAM\0" {
+                    b"UNKNOWN COMMAND\0"
+                } else if payload.windows(FLAGGED.len()).any(|w| w == FLAGGED) {
+                    b"stream: Fake-Test-Signature FOUND\0"
+                } else {
+                    b"stream: OK\0"
+                };
+                conn.write_all(reply).await?;
+                conn.shutdown().await
+            });
+        }
+    });
+    format!("clamd:{}", addr)
+}
+
+async fn patch_bucket(app: &TestApp, bucket: &str, patch: &'static str) -> common::TestResponse {
+    app.send(
+        Request::builder()
+            .method(Method::PATCH)
+            .uri(format!("/admin/buckets/{}", bucket))
+            .header("content-type", "application/json")
+            .body(Body::from(patch))
+            .unwrap(),
+    )
+    .await
+}
+
+async fn upload_scanning(app: &TestApp) -> CaseResult {
+    app.create_bucket("inbox").await;
+    let resp = patch_bucket(app, "inbox", r#"{"scan_uploads": "inline"}"#).await;
+    ensure!(
+        resp.status == StatusCode::CONFLICT,
+        "scanning without scanner {}",
+        resp.status
+    );
+
+    let endpoint: object_store::services::scanner::ScannerEndpoint =
+        spawn_fake_clamd().await.parse()?;
+    let scanner = endpoint
+        .connect(
+            &object_store::services::outbound::OutboundHttp::default(),
+            std::time::Duration::from_secs(5),
+        )
+        .map_err(|err| err.to_string())?;
+    let app = TestApp::with_service(move |mut service| {
+        service.options.scanner = Some(scanner);
+        service
+    })
+    .await;
+    app.create_bucket("inbox").await;
+    let resp = patch_bucket(&app, "inbox", r#"{"scan_uploads": "sometimes"}"#).await;
+    ensure!(
+        resp.status == StatusCode::BAD_REQUEST,
+        "unknown mode {}",
+        resp.status
+    );
+    let resp = patch_bucket(&app, "inbox", r#"{"scan_uploads": "inline"}"#).await;
+    ensure!(
+        resp.status == StatusCode::OK && resp.text().contains(r#""scan_uploads":"inline""#),
+        "inline {} {}",
+        resp.status,
+        resp.text()
+    );
+
+    // Inline: positives never become visible.
+    app.put_object("inbox", "clean.txt", b"hello").await;
+    let infected = app.call(Method::PUT, "/inbox/bad.com", FLAGGED).await;
+    ensure!(
+        infected.status == StatusCode::FORBIDDEN && infected.text().contains("Fake-Test-Signature"),
+        "inline positive {} {}",
+        infected.status,
+        infected.text()
+    );
+    let head = app
+        .call(Method::HEAD, "/inbox/bad.com", Body::empty())
+        .await;
+    ensure!(
+        head.status == StatusCode::NOT_FOUND,
+        "rejected upload stored: {}",
+        head.status
+    );
+
+    // Async: the write succeeds and the scan worker quarantines it.
+    let resp = patch_bucket(&app, "inbox", r#"{"scan_uploads": "async"}"#).await;
+    ensure!(resp.status == StatusCode::OK, "async {}", resp.status);
+    app.put_object("inbox", "bad.com", FLAGGED).await;
+    app.put_object("inbox", "fine.txt", b"fine").await;
+    let get = app.call(Method::GET, "/inbox/bad.com", Body::empty()).await;
+    ensure!(get.status == StatusCode::OK, "queued get {}", get.status);
+    let summary = app
+        .service
+        .run_pending_scans()
+        .await
+        .map_err(|err| err.to_string())?;
+    ensure!(
+        summary.scanned == 2 && summary.quarantined == 1 && summary.failed == 0,
+        "summary {:?}",
+        summary
+    );
+    let get = app.call(Method::GET, "/inbox/bad.com", Body::empty()).await;
+    ensure!(
+        get.status == StatusCode::FORBIDDEN,
+        "quarantined get {}",
+        get.status
+    );
+    let head = app
+        .call(Method::HEAD, "/inbox/bad.com", Body::empty())
+        .await;
+    ensure!(
+        head.status == StatusCode::OK,
+        "quarantined head {}",
+        head.status
+    );
+    let get = app
+        .call(Method::GET, "/inbox/fine.txt", Body::empty())
+        .await;
+    ensure!(get.status == StatusCode::OK, "clean get {}", get.status);
+    let listed = app
+        .call(
+            Method::GET,
+            "/admin/buckets/inbox/quarantine",
+            Body::empty(),
+        )
+        .await;
+    ensure!(
+        listed.text().contains(r#""key":"bad.com""#)
+            && listed.text().contains("Fake-Test-Signature")
+            && !listed.text().contains("fine.txt"),
+        "quarantine {}",
+        listed.text()
+    );
+
+    // Rewriting the key replaces the quarantined payload.
+    app.put_object("inbox", "bad.com", b"now harmless").await;
+    let get = app.call(Method::GET, "/inbox/bad.com", Body::empty()).await;
+    ensure!(get.status == StatusCode::OK, "rewritten get {}", get.status);
+
+    // An administrator can release a false positive.
+    app.put_object("inbox", "sample.bin", FLAGGED).await;
+    app.service
+        .run_pending_scans()
+        .await
+        .map_err(|err| err.to_string())?;
+    let released = app
+        .call(
+            Method::DELETE,
+            "/admin/buckets/inbox/quarantine?key=sample.bin",
+            Body::empty(),
+        )
+        .await;
+    ensure!(
+        released.status == StatusCode::NO_CONTENT,
+        "release {} {}",
+        released.status,
+        released.text()
+    );
+    let get = app
+        .call(Method::GET, "/inbox/sample.bin", Body::empty())
+        .await;
+    ensure!(
+        get.status == StatusCode::OK && get.body == FLAGGED,
+        "released get {}",
+        get.status
+    );
+    Ok(())
+}
+
+/// Number of files below `dir`.
+fn count_files(dir: &std::path::Path) -> usize {
+    std::fs::read_dir(dir)
+        .map(|entries| {
+            entries
+                .flatten()
+                .map(|entry| match entry.file_type() {
+                    Ok(kind) if kind.is_dir() => count_files(&entry.path()),
+                    _ => 1,
+                })
+                .sum()
+        })
+        .unwrap_or(0)
+}
+
+/// Collect the text content of every `<tag>…</tag>` occurrence.
+fn extract_all(xml: &str, tag: &str) -> Vec<String> {
+    let open = format!("<{}>", tag);
+    let close = format!("</{}>", tag);
+    let mut out = Vec::new();
+    let mut rest = xml;
+    while let Some(start) = rest.find(&open) {
+        let after = &rest[start + open.len()..];
+        let Some(end) = after.find(&close) else { break };
+        out.push(after[..end].to_string());
+        rest = &after[end + close.len()..];
+    }
+    out
+}